@@ -0,0 +1,46 @@
+// SPDX-License-Identifier: MIT OR AGPL-3.0-or-later
+// SPDX-FileCopyrightText: 2024-2025 hyperpolymath
+
+//! Pipeline benchmarks on the fixtures of `testing::fixtures`.
+//!
+//! ```sh
+//! cargo bench --bench pipeline -- deep_nesting
+//! ```
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use obli_transpiler::emit::emit_rust;
+use obli_transpiler::testing::fixtures::standard;
+use obli_transpiler::{to_oblivious, transpile, Lexer, Parser};
+
+const SIZES: &[usize] = &[100, 1_000];
+
+fn stages(c: &mut Criterion) {
+    for size in SIZES {
+        for fixture in standard(*size) {
+            let mut group = c.benchmark_group(fixture.name);
+            group.throughput(Throughput::Bytes(fixture.source.len() as u64));
+            let source = fixture.source.as_str();
+            let (tokens, spans) = Lexer::new(source).tokenize().unwrap();
+            let ast = Parser::with_spans(&tokens, &spans).parse().unwrap();
+            let ir = to_oblivious(&ast);
+
+            group.bench_function(BenchmarkId::new("lex", size), |b| {
+                b.iter(|| Lexer::new(source).tokenize().unwrap())
+            });
+            group.bench_function(BenchmarkId::new("parse", size), |b| {
+                b.iter(|| Parser::with_spans(&tokens, &spans).parse().unwrap())
+            });
+            group.bench_function(BenchmarkId::new("transform", size), |b| {
+                b.iter(|| to_oblivious(&ast))
+            });
+            group.bench_function(BenchmarkId::new("emit", size), |b| b.iter(|| emit_rust(&ir)));
+            group.bench_function(BenchmarkId::new("transpile", size), |b| {
+                b.iter(|| transpile(source).unwrap())
+            });
+            group.finish();
+        }
+    }
+}
+
+criterion_group!(benches, stages);
+criterion_main!(benches);
@@ -0,0 +1,37 @@
+/* SPDX-License-Identifier: MIT OR AGPL-3.0-or-later */
+/* Generated by obli-ffi; do not edit. */
+
+#ifndef OBLI_H
+#define OBLI_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define OBLI_OK 0
+#define OBLI_ERR_NULL_ARGUMENT 1
+#define OBLI_ERR_INVALID_UTF8 2
+#define OBLI_ERR_INVALID_OPTIONS 3
+#define OBLI_ERR_PROGRAM 4
+#define OBLI_ERR_INTERNAL 5
+
+/* Bytes owned by the library, not NUL-terminated. */
+typedef struct ObliBuffer {
+    uint8_t *data;
+    size_t len;
+} ObliBuffer;
+
+/* Transpile `source` to Rust; `options_json` may be NULL. Free `*out`
+   with obli_buffer_free. */
+int32_t obli_transpile(const char *source, const char *options_json, ObliBuffer *out);
+
+void obli_buffer_free(ObliBuffer *buffer);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* OBLI_H */
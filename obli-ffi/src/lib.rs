@@ -0,0 +1,240 @@
+// SPDX-License-Identifier: MIT OR AGPL-3.0-or-later
+// SPDX-FileCopyrightText: 2024-2025 hyperpolymath
+
+//! C interface to the transpiler.
+//!
+//! Builds `libobli.a` and `libobli.so` for build systems that call the
+//! transpiler in-process. `include/obli.h` declares the interface; it is
+//! generated by [`header`] and checked against it by the tests, so after
+//! changing the interface regenerate it with
+//! `OBLI_HEADER=update cargo test -p obli-ffi`.
+//!
+//! ```c
+//! ObliBuffer out;
+//! int status = obli_transpile("secret(1) + 2", NULL, &out);
+//! fwrite(out.data, 1, out.len, status == OBLI_OK ? stdout : stderr);
+//! obli_buffer_free(&out);
+//! ```
+
+use obli_transpiler::check::check_with;
+use obli_transpiler::diagnostic::{has_errors, Renderer};
+use obli_transpiler::{transpile_with, Config};
+use std::ffi::{c_char, CStr};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::ptr;
+
+/// Result of an `obli_*` call.
+#[repr(i32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    Ok = 0,
+    /// A required pointer argument was null.
+    NullArgument = 1,
+    /// The source or options are not valid UTF-8.
+    InvalidUtf8 = 2,
+    /// The options are not a valid configuration.
+    InvalidOptions = 3,
+    /// The program has errors; the buffer holds the rendered diagnostics.
+    ProgramErrors = 4,
+    /// The transpiler panicked (a bug).
+    Internal = 5,
+}
+
+impl Status {
+    pub const ALL: [Status; 6] = [
+        Status::Ok,
+        Status::NullArgument,
+        Status::InvalidUtf8,
+        Status::InvalidOptions,
+        Status::ProgramErrors,
+        Status::Internal,
+    ];
+
+    /// Name of the constant in `obli.h`.
+    pub fn c_name(self) -> &'static str {
+        match self {
+            Status::Ok => "OBLI_OK",
+            Status::NullArgument => "OBLI_ERR_NULL_ARGUMENT",
+            Status::InvalidUtf8 => "OBLI_ERR_INVALID_UTF8",
+            Status::InvalidOptions => "OBLI_ERR_INVALID_OPTIONS",
+            Status::ProgramErrors => "OBLI_ERR_PROGRAM",
+            Status::Internal => "OBLI_ERR_INTERNAL",
+        }
+    }
+}
+
+/// Bytes owned by the library; release with [`obli_buffer_free`]. Not
+/// NUL-terminated.
+#[repr(C)]
+#[derive(Debug)]
+pub struct ObliBuffer {
+    pub data: *mut u8,
+    pub len: usize,
+}
+
+impl ObliBuffer {
+    fn new(text: String) -> Self {
+        let bytes = Box::into_raw(text.into_bytes().into_boxed_slice());
+        Self {
+            data: bytes as *mut u8,
+            len: bytes.len(),
+        }
+    }
+
+    fn empty() -> Self {
+        Self {
+            data: ptr::null_mut(),
+            len: 0,
+        }
+    }
+}
+
+/// Transpile the NUL-terminated `source` to Rust. `options_json` holds
+/// the settings of `obli.toml` as JSON, or is null for the defaults.
+///
+/// On `OBLI_OK` `*out` holds the generated code; on the other statuses
+/// except `OBLI_ERR_NULL_ARGUMENT` it holds an error message. Either way
+/// the caller frees it with [`obli_buffer_free`].
+///
+/// # Safety
+///
+/// `source` and a non-null `options_json` must be valid NUL-terminated
+/// strings, and `out` must be valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn obli_transpile(
+    source: *const c_char,
+    options_json: *const c_char,
+    out: *mut ObliBuffer,
+) -> i32 {
+    if source.is_null() || out.is_null() {
+        return Status::NullArgument as i32;
+    }
+    let source = CStr::from_ptr(source);
+    let options = (!options_json.is_null()).then(|| CStr::from_ptr(options_json));
+    let result = catch_unwind(AssertUnwindSafe(|| transpile(source, options)));
+    let (status, text) = result.unwrap_or_else(|_| {
+        (Status::Internal, "internal error: the transpiler panicked".to_string())
+    });
+    out.write(ObliBuffer::new(text));
+    status as i32
+}
+
+fn transpile(source: &CStr, options: Option<&CStr>) -> (Status, String) {
+    let Ok(source) = source.to_str() else {
+        return (Status::InvalidUtf8, "source is not valid UTF-8".to_string());
+    };
+    let config = match options.map(CStr::to_str) {
+        None => Ok(Config::default()),
+        Some(Ok(json)) => Config::parse_json(json),
+        Some(Err(_)) => return (Status::InvalidUtf8, "options are not valid UTF-8".to_string()),
+    };
+    let config = match config {
+        Ok(config) => config,
+        Err(e) => return (Status::InvalidOptions, e.to_string()),
+    };
+
+    let mut diagnostics = check_with(source, &config.lints);
+    if !has_errors(&diagnostics) {
+        match transpile_with(source, &config) {
+            Ok(code) => return (Status::Ok, code),
+            Err(e) => diagnostics.extend(e.into_diagnostics()),
+        }
+    }
+    let renderer = Renderer::plain();
+    let rendered: Vec<_> = diagnostics
+        .iter()
+        .filter(|d| d.is_error())
+        .map(|d| renderer.render(d, "<source>", source))
+        .collect();
+    (Status::ProgramErrors, rendered.join("\n"))
+}
+
+/// Release a buffer filled by the library and reset it to empty. Null
+/// pointers and empty buffers are ignored.
+///
+/// # Safety
+///
+/// `buffer` must be null or point to a buffer filled by the library and
+/// not freed since.
+#[no_mangle]
+pub unsafe extern "C" fn obli_buffer_free(buffer: *mut ObliBuffer) {
+    let Some(buffer) = buffer.as_mut() else {
+        return;
+    };
+    if !buffer.data.is_null() {
+        let bytes = ptr::slice_from_raw_parts_mut(buffer.data, buffer.len);
+        drop(Box::from_raw(bytes));
+    }
+    *buffer = ObliBuffer::empty();
+}
+
+/// The C header declaring this interface.
+pub fn header() -> String {
+    let mut out = String::from(
+        "/* SPDX-License-Identifier: MIT OR AGPL-3.0-or-later */\n\
+         /* Generated by obli-ffi; do not edit. */\n\n\
+         #ifndef OBLI_H\n#define OBLI_H\n\n\
+         #include <stddef.h>\n#include <stdint.h>\n\n\
+         #ifdef __cplusplus\nextern \"C\" {\n#endif\n\n",
+    );
+    for status in Status::ALL {
+        out.push_str(&format!("#define {} {}\n", status.c_name(), status as i32));
+    }
+    out.push_str(
+        "\n/* Bytes owned by the library, not NUL-terminated. */\n\
+         typedef struct ObliBuffer {\n    uint8_t *data;\n    size_t len;\n} ObliBuffer;\n\n\
+         /* Transpile `source` to Rust; `options_json` may be NULL. Free `*out`\n   \
+         with obli_buffer_free. */\n\
+         int32_t obli_transpile(const char *source, const char *options_json, ObliBuffer *out);\n\n\
+         void obli_buffer_free(ObliBuffer *buffer);\n\n\
+         #ifdef __cplusplus\n}\n#endif\n\n#endif /* OBLI_H */\n",
+    );
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ffi::CString;
+
+    fn call(source: &str, options: Option<&str>) -> (i32, String) {
+        let source = CString::new(source).unwrap();
+        let options = options.map(|o| CString::new(o).unwrap());
+        let options_ptr = options.as_ref().map_or(ptr::null(), |o| o.as_ptr());
+        let mut out = ObliBuffer::empty();
+        unsafe {
+            let status = obli_transpile(source.as_ptr(), options_ptr, &mut out);
+            let text = std::slice::from_raw_parts(out.data, out.len);
+            let text = String::from_utf8(text.to_vec()).unwrap();
+            obli_buffer_free(&mut out);
+            assert!(out.data.is_null());
+            (status, text)
+        }
+    }
+
+    #[test]
+    fn test_transpile_statuses() {
+        let (status, code) = call("secret(1) + 2", Some(r#"{"emit": {"prelude": false}}"#));
+        assert_eq!(status, Status::Ok as i32);
+        assert!(code.contains("ct_add") && !code.contains("struct Secret"));
+
+        let (status, message) = call("1 + true", None);
+        assert_eq!(status, Status::ProgramErrors as i32);
+        assert!(message.starts_with("error[OB0005]"), "{}", message);
+        let (status, message) = call("1", Some(r#"{"passes": {"pipeline": ["dce"]}}"#));
+        assert_eq!((status, message.as_str()), (3, "unknown pass `dce`"));
+
+        let status = unsafe { obli_transpile(ptr::null(), ptr::null(), ptr::null_mut()) };
+        assert_eq!(status, Status::NullArgument as i32);
+    }
+
+    #[test]
+    fn test_header_is_up_to_date() {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/include/obli.h");
+        if std::env::var("OBLI_HEADER").as_deref() == Ok("update") {
+            std::fs::write(path, header()).unwrap();
+        }
+        let checked_in = std::fs::read_to_string(path).unwrap();
+        assert_eq!(checked_in, header(), "regenerate with OBLI_HEADER=update");
+    }
+}
@@ -0,0 +1,122 @@
+// SPDX-License-Identifier: MIT OR AGPL-3.0-or-later
+// SPDX-FileCopyrightText: 2024-2025 hyperpolymath
+
+//! The `obli!` macro: MiniObli embedded in Rust.
+//!
+//! ```ignore
+//! use obli_macros::obli;
+//!
+//! let input: i64 = 7;
+//! let k: i64 = obli! { let x = secret(input) if x > 0 then x * 2 else 0 };
+//! ```
+//!
+//! The program is transpiled at compile time and the constant-time code
+//! inlined; see `obli_transpiler::embed` for how Rust variables are
+//! captured. Lexer, parser and type errors are reported as compile errors
+//! at the offending tokens of the macro input.
+
+use obli_transpiler::diagnostic::Diagnostic;
+use obli_transpiler::embed::expand;
+use obli_transpiler::Config;
+use proc_macro::{Delimiter, Group, Ident, Literal, Punct, Spacing, Span, TokenStream, TokenTree};
+use std::ops::Range;
+
+/// Transpile a MiniObli program into an `i64` or `bool` Rust expression.
+#[proc_macro]
+pub fn obli(input: TokenStream) -> TokenStream {
+    let mut source = SourceMap::default();
+    source.push_stream(input);
+    match expand(&source.text, &Config::default()) {
+        Ok(code) => code.parse().expect("emitted Rust is valid tokens"),
+        Err(diagnostics) => {
+            // One error per diagnostic, the last one the block's value
+            let mut errors = TokenStream::new();
+            for (i, diagnostic) in diagnostics.iter().enumerate() {
+                if i > 0 {
+                    errors.extend([TokenTree::from(Punct::new(';', Spacing::Alone))]);
+                }
+                errors.extend(compile_error(diagnostic, &source));
+            }
+            TokenTree::from(Group::new(Delimiter::Brace, errors)).into()
+        }
+    }
+}
+
+/// Macro input as MiniObli source, with the span each token came from.
+#[derive(Default)]
+struct SourceMap {
+    text: String,
+    tokens: Vec<(Range<usize>, Span)>,
+    /// Whether the last token was a punctuation joined to the next one.
+    joint: bool,
+}
+
+impl SourceMap {
+    fn push_stream(&mut self, stream: TokenStream) {
+        for tree in stream {
+            match tree {
+                TokenTree::Group(group) => {
+                    let (open, close) = match group.delimiter() {
+                        Delimiter::Parenthesis => ("(", ")"),
+                        Delimiter::Bracket => ("[", "]"),
+                        Delimiter::Brace => ("{", "}"),
+                        Delimiter::None => ("", ""),
+                    };
+                    self.push(open, group.span_open());
+                    self.push_stream(group.stream());
+                    self.push(close, group.span_close());
+                }
+                TokenTree::Punct(punct) => {
+                    self.push(&punct.to_string(), punct.span());
+                    self.joint = punct.spacing() == Spacing::Joint;
+                }
+                other => self.push(&other.to_string(), other.span()),
+            }
+        }
+    }
+
+    fn push(&mut self, token: &str, span: Span) {
+        if token.is_empty() {
+            return;
+        }
+        if !self.text.is_empty() && !self.joint {
+            self.text.push(' ');
+        }
+        self.joint = false;
+        let start = self.text.len();
+        self.text.push_str(token);
+        self.tokens.push((start..self.text.len(), span));
+    }
+
+    /// Span of the token at byte `offset` of the source.
+    fn span_at(&self, offset: usize) -> Span {
+        self.tokens
+            .iter()
+            .find(|(range, _)| offset < range.end)
+            .map_or_else(Span::call_site, |(_, span)| *span)
+    }
+}
+
+/// `compile_error!("...")` spanned at the diagnostic's first token.
+fn compile_error(diagnostic: &Diagnostic, source: &SourceMap) -> TokenStream {
+    let span = diagnostic
+        .span
+        .map_or_else(Span::call_site, |span| source.span_at(span.start));
+    let mut message = diagnostic.message.clone();
+    if let Some(code) = diagnostic.code {
+        message = format!("{} [{}]", message, code);
+    }
+    let mut literal = Literal::string(&message);
+    literal.set_span(span);
+    let mut args = Group::new(Delimiter::Parenthesis, TokenTree::from(literal).into());
+    args.set_span(span);
+    let mut bang = Punct::new('!', Spacing::Alone);
+    bang.set_span(span);
+    [
+        TokenTree::from(Ident::new("compile_error", span)),
+        bang.into(),
+        args.into(),
+    ]
+    .into_iter()
+    .collect()
+}
@@ -0,0 +1,25 @@
+// SPDX-License-Identifier: MIT OR AGPL-3.0-or-later
+// SPDX-FileCopyrightText: 2024-2025 hyperpolymath
+
+use obli_macros::obli;
+
+fn double_if_positive(input: i64) -> i64 {
+    obli! { let x = secret(input) if x > 0 then x * 2 else 0 }
+}
+
+#[test]
+fn test_captures_and_secret_branches() {
+    assert_eq!(double_if_positive(21), 42);
+    assert_eq!(double_if_positive(-3), 0);
+}
+
+#[test]
+fn test_bool_captures_and_results() {
+    let flag = true;
+    let n: i64 = 4;
+    let chosen: i64 = obli! { if secret(flag) then n else 0 - n };
+    assert_eq!(chosen, 4);
+    let below: bool = obli! { n < 10 && !flag };
+    assert!(!below);
+    assert_eq!(obli! { 6 * 7 }, 42);
+}
@@ -0,0 +1,14 @@
+// SPDX-License-Identifier: MIT OR AGPL-3.0-or-later
+// SPDX-FileCopyrightText: 2024-2025 hyperpolymath
+
+//! WebAssembly bindings of the transpiler, built with
+//! `wasm-pack build obli-wasm --target web`.
+
+use wasm_bindgen::prelude::wasm_bindgen;
+
+/// Transpile `source` with the options in `options_json`, returning the
+/// result as JSON; see `obli_transpiler::wasm::transpile_json`.
+#[wasm_bindgen]
+pub fn transpile(source: &str, options_json: &str) -> String {
+    obli_transpiler::wasm::transpile_json(source, options_json)
+}
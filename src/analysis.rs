@@ -0,0 +1,251 @@
+// SPDX-License-Identifier: MIT OR AGPL-3.0-or-later
+// SPDX-FileCopyrightText: 2024-2025 hyperpolymath
+
+//! Leakage analyses over oblivious IR.
+//!
+//! The transform removes secret-dependent control flow, but some operations
+//! remain variable-time on real hardware even when emitted as `ct_*` calls.
+//! These analyses point them out so users can restructure the program.
+//! [`leakage`] works from the IR's secrecy flags; [`dynamic_ct_check`] runs
+//! the program and watches the operands it actually sees, which also
+//! catches secrets that reach the IR unflagged, such as secret inputs.
+
+use crate::codes;
+use crate::interp::{eval_monitored, EvalError, Inputs, Labeled};
+use crate::ir::{ObliBinOp, ObliExpr};
+use crate::span::{NodeSpans, Span, SpanCursor};
+use crate::stack;
+use std::fmt;
+
+/// Kind of potential leak.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LeakKind {
+    /// Division with a secret operand: hardware dividers are variable-time,
+    /// and a secret zero divisor aborts the program.
+    SecretDivision,
+    /// Modulo with a secret operand (same hazards as division).
+    SecretModulo,
+    /// A `PubIf` that branched on a secret condition at run time.
+    SecretBranch,
+}
+
+impl LeakKind {
+    /// Catalog code of this leak.
+    pub fn code(&self) -> &'static str {
+        match self {
+            LeakKind::SecretDivision => codes::SECRET_DIVISION,
+            LeakKind::SecretModulo => codes::SECRET_MODULO,
+            LeakKind::SecretBranch => codes::SECRET_PUB_IF,
+        }
+    }
+}
+
+impl fmt::Display for LeakKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LeakKind::SecretDivision => write!(f, "division on secret data is not constant-time"),
+            LeakKind::SecretModulo => write!(f, "modulo on secret data is not constant-time"),
+            LeakKind::SecretBranch => write!(f, "branch on secret data"),
+        }
+    }
+}
+
+/// A potential leak found in the IR.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Leak {
+    pub kind: LeakKind,
+    pub span: Option<Span>,
+}
+
+/// Find potential leaks in an IR tree.
+pub fn leakage(expr: &ObliExpr) -> Vec<Leak> {
+    let mut leaks = Vec::new();
+    find_leaks(expr, &mut SpanCursor::none(), &mut leaks);
+    leaks
+}
+
+/// Find potential leaks, reporting them at the spans recorded by
+/// [`to_oblivious_with_spans`](crate::transform::to_oblivious_with_spans).
+pub fn leakage_with_spans(expr: &ObliExpr, spans: &NodeSpans) -> Vec<Leak> {
+    let mut leaks = Vec::new();
+    find_leaks(expr, &mut SpanCursor::new(Some(spans)), &mut leaks);
+    leaks
+}
+
+fn find_leaks(expr: &ObliExpr, spans: &mut SpanCursor, leaks: &mut Vec<Leak>) {
+    stack::guard(|| find_leaks_node(expr, spans, leaks))
+}
+
+fn find_leaks_node(expr: &ObliExpr, spans: &mut SpanCursor, leaks: &mut Vec<Leak>) {
+    match expr {
+        ObliExpr::PubInt(_)
+        | ObliExpr::PubBool(_)
+        | ObliExpr::SecretInt(_)
+        | ObliExpr::SecretBool(_)
+        | ObliExpr::Sized { .. }
+        | ObliExpr::Var { .. }
+        | ObliExpr::Input { .. } => {}
+        ObliExpr::BinOp { left, right, .. } => {
+            find_leaks(left, spans, leaks);
+            find_leaks(right, spans, leaks);
+        }
+        ObliExpr::UnaryOp { expr, .. } | ObliExpr::CtCast { expr, .. } => {
+            find_leaks(expr, spans, leaks)
+        }
+        ObliExpr::CtSelect {
+            cond,
+            then_val,
+            else_val,
+        } => {
+            find_leaks(cond, spans, leaks);
+            find_leaks(then_val, spans, leaks);
+            find_leaks(else_val, spans, leaks);
+        }
+        ObliExpr::PubIf {
+            cond,
+            then_branch,
+            else_branch,
+        } => {
+            find_leaks(cond, spans, leaks);
+            find_leaks(then_branch, spans, leaks);
+            find_leaks(else_branch, spans, leaks);
+        }
+        ObliExpr::Let { value, body, .. } => {
+            find_leaks(value, spans, leaks);
+            find_leaks(body, spans, leaks);
+        }
+        ObliExpr::Outputs(outputs) => {
+            for (_, value) in outputs {
+                find_leaks(value, spans, leaks);
+            }
+        }
+        ObliExpr::Table { index, .. } | ObliExpr::Declassify(index) => {
+            find_leaks(index, spans, leaks)
+        }
+        ObliExpr::While {
+            cond, init, body, ..
+        } => {
+            find_leaks(cond, spans, leaks);
+            find_leaks(init, spans, leaks);
+            find_leaks(body, spans, leaks);
+        }
+        ObliExpr::CtSwap { .. } | ObliExpr::Assert { .. } => {
+            for child in expr.children() {
+                find_leaks(child, spans, leaks);
+            }
+        }
+    }
+
+    let span = spans.next_span();
+    if let ObliExpr::BinOp {
+        op, is_secret: true, ..
+    } = expr
+    {
+        let kind = match op {
+            ObliBinOp::CtDiv => LeakKind::SecretDivision,
+            ObliBinOp::CtMod => LeakKind::SecretModulo,
+            _ => return,
+        };
+        leaks.push(Leak { kind, span });
+    }
+}
+
+/// Outcome of [`dynamic_ct_check`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct CtReport {
+    pub result: Labeled,
+    /// Number of branch decisions and divisions executed.
+    pub monitored: usize,
+    /// Operations that saw secret operands, in execution order. MiniObli
+    /// has no loops, so each node runs at most once.
+    pub leaks: Vec<Leak>,
+}
+
+/// Run `expr` on `inputs` with the interpreter's leakage monitor, reporting
+/// every branch decision and division that depended on a secret.
+pub fn dynamic_ct_check(expr: &ObliExpr, inputs: &Inputs) -> Result<CtReport, EvalError> {
+    dynamic_ct_check_with_spans(expr, &NodeSpans::new(), inputs)
+}
+
+/// Like [`dynamic_ct_check`], reporting leaks at the IR's post-order spans.
+pub fn dynamic_ct_check_with_spans(
+    expr: &ObliExpr,
+    spans: &NodeSpans,
+    inputs: &Inputs,
+) -> Result<CtReport, EvalError> {
+    let mut observations = Vec::new();
+    let result = eval_monitored(expr, inputs, &mut observations)?;
+
+    let nodes = expr.post_order();
+    let leaks = observations
+        .iter()
+        .filter(|o| o.secret)
+        .map(|observation| {
+            let kind = match observation.expr {
+                ObliExpr::BinOp {
+                    op: ObliBinOp::CtDiv,
+                    ..
+                } => LeakKind::SecretDivision,
+                ObliExpr::BinOp { .. } => LeakKind::SecretModulo,
+                _ => LeakKind::SecretBranch,
+            };
+            let span = nodes
+                .iter()
+                .position(|n| std::ptr::eq(*n, observation.expr))
+                .and_then(|i| spans.get(i))
+                .filter(|span| !span.is_empty());
+            Leak { kind, span }
+        })
+        .collect();
+    Ok(CtReport {
+        result,
+        monitored: observations.len(),
+        leaks,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+    use crate::transform::to_oblivious;
+
+    fn leaks(input: &str) -> Vec<LeakKind> {
+        let tokens: Vec<_> = Lexer::new(input).filter_map(Result::ok).collect();
+        let ir = to_oblivious(&Parser::new(&tokens).parse().unwrap());
+        leakage(&ir).into_iter().map(|l| l.kind).collect()
+    }
+
+    #[test]
+    fn test_dynamic_check_sees_secret_inputs() {
+        // `k` is a free variable, so the IR does not flag it secret
+        let ir = crate::transpile_to_ir("let a = k / 2 if k > 0 then a else 10 % a").unwrap();
+        assert!(leakage(&ir).is_empty());
+
+        let inputs = Inputs::new().secret("k", crate::interp::Value::Int(-4));
+        let report = dynamic_ct_check(&ir, &inputs).unwrap();
+        let kinds: Vec<_> = report.leaks.iter().map(|l| l.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![LeakKind::SecretDivision, LeakKind::SecretBranch, LeakKind::SecretModulo]
+        );
+        assert_eq!(report.monitored, 3);
+
+        let inputs = Inputs::new().public("k", crate::interp::Value::Int(4));
+        assert!(dynamic_ct_check(&ir, &inputs).unwrap().leaks.is_empty());
+    }
+
+    #[test]
+    fn test_public_division_is_fine() {
+        assert_eq!(leaks("10 / 2 + 10 % 3"), vec![]);
+    }
+
+    #[test]
+    fn test_secret_division_flagged() {
+        assert_eq!(
+            leaks("let k = secret(10) k / 2 + 7 % k"),
+            vec![LeakKind::SecretDivision, LeakKind::SecretModulo]
+        );
+    }
+}
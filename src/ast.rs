@@ -2,15 +2,42 @@
 // SPDX-FileCopyrightText: 2024-2025 hyperpolymath
 
 //! Abstract Syntax Tree for MiniObli.
+//!
+//! [`Expr`] displays as MiniObli source with every compound sub-expression
+//! parenthesised, e.g. `let x = secret(1) (x + 2) * 3`. The format is
+//! stable, so tests may compare against it; it changes only when the
+//! language does.
+
+use crate::symbol::Symbol;
+use crate::typeck::{Scalar, Width};
+use std::fmt;
 
 /// Binary operators.
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub enum BinOp {
     Add,
     Sub,
     Mul,
+    /// `+|`: addition clamped to the `i64` range instead of wrapping
+    SatAdd,
+    /// `-|`
+    SatSub,
+    /// `*|`
+    SatMul,
     Div,
     Mod,
+    /// `&`, on the bits of an `int`
+    BitAnd,
+    /// `|`
+    BitOr,
+    /// `^`
+    BitXor,
+    /// `<<`, by the shift amount modulo 64
+    Shl,
+    /// `>>`, arithmetic, by the shift amount modulo 64
+    Shr,
     Eq,
     Ne,
     Lt,
@@ -23,22 +50,65 @@ pub enum BinOp {
 
 /// Unary operators.
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub enum UnaryOp {
     Neg,
     Not,
+    /// `to_int(b)`: `1` if `b`, else `0`
+    ToInt,
+    /// `to_bool(i)`: whether `i != 0`
+    ToBool,
+}
+
+/// Declared type of a let binding, such as `secret int` or `bool`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct Annotation {
+    /// `Some(true)` for `secret`, `Some(false)` for `pub`, `None` to
+    /// leave secrecy to the transform
+    pub secret: Option<bool>,
+    pub ty: Scalar,
+}
+
+impl fmt::Display for Annotation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.secret {
+            Some(true) => write!(f, "secret ")?,
+            Some(false) => write!(f, "pub ")?,
+            None => {}
+        }
+        write!(f, "{}", crate::typeck::Type::from(self.ty))
+    }
 }
 
 /// Expression AST node.
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub enum Expr {
     /// Integer literal
     Int(i64),
+    /// Integer literal of a [`Width`], held as [`Width::value`] reads it
+    Sized(i64, Width),
     /// Boolean literal
     Bool(bool),
     /// Variable reference
-    Var(String),
+    Var(Symbol),
+    /// `input secret name: ty`, the value of the `let` it declares: the
+    /// program input `name`, read when the program runs, secret if
+    /// `secret`
+    Input {
+        name: Symbol,
+        secret: bool,
+        ty: Scalar,
+    },
     /// Secret value (marks data as sensitive)
     Secret(Box<Expr>),
+    /// `declassify(e)`: the value of scalar `e`, public even if `e` is
+    /// secret
+    Declassify(Box<Expr>),
     /// Binary operation
     BinOp {
         op: BinOp,
@@ -50,28 +120,490 @@ pub enum Expr {
         op: UnaryOp,
         expr: Box<Expr>,
     },
+    /// `expr as ty`: integer `expr` truncated or extended to the integer
+    /// type `ty`, as Rust's `as` does
+    Cast {
+        expr: Box<Expr>,
+        ty: Scalar,
+    },
     /// If-then-else expression
     If {
         cond: Box<Expr>,
         then_branch: Box<Expr>,
         else_branch: Box<Expr>,
     },
-    /// Let binding
+    /// `match scrutinee with p1 => e1, ..., _ => default`: the arm of the
+    /// first pattern equal to `scrutinee`, or `default` (never without
+    /// patterns). Patterns are literals or enum variants, and `default` is
+    /// `None` only for a match over every variant of an enum
+    Match {
+        scrutinee: Box<Expr>,
+        arms: Vec<(Expr, Expr)>,
+        default: Option<Box<Expr>>,
+    },
+    /// Let binding, `let name: ty = value body` if annotated, and
+    /// `let mut name = ...` if `body` may assign to it
     Let {
-        name: String,
+        name: Symbol,
+        ty: Option<Annotation>,
+        mutable: bool,
+        value: Box<Expr>,
+        body: Box<Expr>,
+    },
+    /// Compile-time constant `const name = value body`: `value` is a
+    /// public integer, see [`Expr::const_int_with`], and every `name` in
+    /// `body` is replaced by it before the transformation
+    Const {
+        name: Symbol,
+        value: Box<Expr>,
+        body: Box<Expr>,
+    },
+    /// C-like enum `enum name = v1 | ... | vn body`: each variant is a
+    /// constant of `body`, `v1` is `0` and `vn` is `n - 1`, see
+    /// [`Expr::Const`]
+    Enum {
+        name: Symbol,
+        variants: Vec<Symbol>,
+        body: Box<Expr>,
+    },
+    /// Assignment `name = value body` to a `let mut` variable, rebinding
+    /// it for `body`; `if cond then name = value body` keeps the old value
+    /// unless `cond` holds
+    Assign {
+        name: Symbol,
+        cond: Option<Box<Expr>>,
         value: Box<Expr>,
         body: Box<Expr>,
     },
+    /// `assert(cond) body`: `body`, failing unless `cond` holds; a secret
+    /// `cond` is only checked when the result is revealed
+    Assert {
+        cond: Box<Expr>,
+        body: Box<Expr>,
+    },
+    /// Array literal (never empty)
+    Array(Vec<Expr>),
+    /// Tuple `(e1, e2, ...)` of two or more scalars
+    Tuple(Vec<Expr>),
+    /// Destructuring `let (x, y, ...) = value body`, one name per
+    /// component of a tuple
+    LetTuple {
+        names: Vec<Symbol>,
+        value: Box<Expr>,
+        body: Box<Expr>,
+    },
+    /// Byte-string literal `b"..."` (never empty), an array of `int`s
+    Bytes(Vec<u8>),
+    /// Array element read
+    Index {
+        array: Box<Expr>,
+        index: Box<Expr>,
+    },
+    /// Array sorted in ascending order
+    Sort(Box<Expr>),
+    /// `swap_if(cond, left, right)`: the pair `(right, left)` if `cond`,
+    /// else `(left, right)`
+    SwapIf {
+        cond: Box<Expr>,
+        left: Box<Expr>,
+        right: Box<Expr>,
+    },
+    /// `clamp(value, lo, hi)`: `lo` if `value < lo`, else `hi` if
+    /// `value > hi`, else `value`
+    Clamp {
+        value: Box<Expr>,
+        lo: Box<Expr>,
+        hi: Box<Expr>,
+    },
+    /// `in_range(value, lo, hi)`: whether `lo <= value <= hi`
+    InRange {
+        value: Box<Expr>,
+        lo: Box<Expr>,
+        hi: Box<Expr>,
+    },
+    /// `pow(base, exp)`: `base` raised to the constant `exp`
+    Pow {
+        base: Box<Expr>,
+        exp: Box<Expr>,
+    },
+    /// `map(a, f)`: the array of `f` applied to each element of `a`
+    Map { array: Box<Expr>, f: Box<Expr> },
+    /// `fold(a, init, f)`: `f(acc, e)` for each element `e` of `a` in
+    /// turn, `acc` starting at `init` and then the previous result
+    Fold {
+        array: Box<Expr>,
+        init: Box<Expr>,
+        f: Box<Expr>,
+    },
+    /// `some(e)`: an option holding the scalar `e`
+    Some(Box<Expr>),
+    /// `none`: an option holding no value, of any value type
+    None,
+    /// `unwrap_or(option, default)`: the value `option` holds, or
+    /// `default` if it holds none
+    UnwrapOr {
+        option: Box<Expr>,
+        default: Box<Expr>,
+    },
+    /// Table lookup: the value of the first entry whose key equals `key`,
+    /// or `default` (never without entries)
+    Lookup {
+        key: Box<Expr>,
+        entries: Vec<(Expr, Expr)>,
+        default: Box<Expr>,
+    },
+    /// Named results `outputs(name = e, ...)`, each revealed by its own
+    /// label (never empty; only as the program's result)
+    Outputs(Vec<(Symbol, Expr)>),
+    /// Function definition `fn name(params) = body`, callable in `rest`
+    /// and the bodies of later functions. With `#[max_depth(n)]` it is
+    /// also callable in its own body, unrolled at most `n` calls deep
+    Fn {
+        name: Symbol,
+        max_depth: Option<u32>,
+        params: Vec<Symbol>,
+        body: Box<Expr>,
+        rest: Box<Expr>,
+    },
+    /// Call of a function defined by an enclosing [`Expr::Fn`], bound to
+    /// an [`Expr::Lambda`] by a `let`, or passed as a parameter
+    Call { name: Symbol, args: Vec<Expr> },
+    /// Anonymous function `fn(params) => body`, whose body also sees the
+    /// variables around it; only bound by a `let` or passed to a call
+    Lambda { params: Vec<Symbol>, body: Box<Expr> },
+    /// Bounded loop `for var in start..end with acc = init do body`: the
+    /// final `acc`, each iteration binding it to `body`. The bounds are
+    /// constant, see [`Expr::const_int`]
+    For {
+        var: Symbol,
+        start: Box<Expr>,
+        end: Box<Expr>,
+        acc: Symbol,
+        init: Box<Expr>,
+        body: Box<Expr>,
+    },
+    /// Loop `while cond with acc = init do body` on a public condition:
+    /// `acc` starts as `init` and is rebound to `body` while `cond` holds
+    While {
+        cond: Box<Expr>,
+        acc: Symbol,
+        init: Box<Expr>,
+        body: Box<Expr>,
+    },
+}
+
+/// A top-level statement of a [`Program`], in scope for every later one.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Statement {
+    /// `let name = value`, optionally annotated and `mut`
+    Let {
+        name: Symbol,
+        ty: Option<Annotation>,
+        mutable: bool,
+        value: Expr,
+    },
+    /// `const name = value`
+    Const { name: Symbol, value: Expr },
+    /// `enum name = v1 | ... | vn`
+    Enum { name: Symbol, variants: Vec<Symbol> },
+    /// `name = value`, or `if cond then name = value`
+    Assign {
+        name: Symbol,
+        cond: Option<Expr>,
+        value: Expr,
+    },
+    /// `let (x, y, ...) = value`
+    LetTuple { names: Vec<Symbol>, value: Expr },
+    /// `assert(cond)`
+    Assert { cond: Expr },
+    /// `fn name(params) = body`, after `#[max_depth(n)]` if recursive
+    Fn {
+        name: Symbol,
+        max_depth: Option<u32>,
+        params: Vec<Symbol>,
+        body: Expr,
+    },
+}
+
+/// A whole program: statements separated by `;` or newlines, then the
+/// expression whose value is the result. Frontends hand one over (see
+/// [`crate::json`]); it lowers to a single [`Expr`] in which each
+/// statement binds the rest.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Program {
+    pub statements: Vec<Statement>,
+    pub result: Expr,
+}
+
+impl Program {
+    /// Split the spine of `let`s, `const`s, `fn`s and `assert`s at the top
+    /// of `expr` into statements.
+    pub fn from_expr(mut expr: Expr) -> Self {
+        let mut statements = Vec::new();
+        loop {
+            let (statement, rest) = match expr {
+                Expr::Let {
+                    name,
+                    ty,
+                    mutable,
+                    value,
+                    body,
+                } => (
+                    Statement::Let {
+                        name,
+                        ty,
+                        mutable,
+                        value: *value,
+                    },
+                    body,
+                ),
+                Expr::Const { name, value, body } => (
+                    Statement::Const {
+                        name,
+                        value: *value,
+                    },
+                    body,
+                ),
+                Expr::Enum {
+                    name,
+                    variants,
+                    body,
+                } => (Statement::Enum { name, variants }, body),
+                Expr::Assign {
+                    name,
+                    cond,
+                    value,
+                    body,
+                } => (
+                    Statement::Assign {
+                        name,
+                        cond: cond.map(|cond| *cond),
+                        value: *value,
+                    },
+                    body,
+                ),
+                Expr::LetTuple { names, value, body } => (
+                    Statement::LetTuple {
+                        names,
+                        value: *value,
+                    },
+                    body,
+                ),
+                Expr::Assert { cond, body } => (Statement::Assert { cond: *cond }, body),
+                Expr::Fn {
+                    name,
+                    max_depth,
+                    params,
+                    body,
+                    rest,
+                } => (
+                    Statement::Fn {
+                        name,
+                        max_depth,
+                        params,
+                        body: *body,
+                    },
+                    rest,
+                ),
+                result => return Program { statements, result },
+            };
+            statements.push(statement);
+            expr = *rest;
+        }
+    }
+
+    /// The program as one expression, each statement binding the rest.
+    pub fn into_expr(self) -> Expr {
+        let mut expr = self.result;
+        for statement in self.statements.into_iter().rev() {
+            let rest = Box::new(expr);
+            expr = match statement {
+                Statement::Let {
+                    name,
+                    ty,
+                    mutable,
+                    value,
+                } => Expr::Let {
+                    name,
+                    ty,
+                    mutable,
+                    value: Box::new(value),
+                    body: rest,
+                },
+                Statement::Const { name, value } => Expr::Const {
+                    name,
+                    value: Box::new(value),
+                    body: rest,
+                },
+                Statement::Enum { name, variants } => Expr::Enum {
+                    name,
+                    variants,
+                    body: rest,
+                },
+                Statement::Assign { name, cond, value } => Expr::Assign {
+                    name,
+                    cond: cond.map(Box::new),
+                    value: Box::new(value),
+                    body: rest,
+                },
+                Statement::LetTuple { names, value } => Expr::LetTuple {
+                    names,
+                    value: Box::new(value),
+                    body: rest,
+                },
+                Statement::Assert { cond } => Expr::Assert {
+                    cond: Box::new(cond),
+                    body: rest,
+                },
+                Statement::Fn {
+                    name,
+                    max_depth,
+                    params,
+                    body,
+                } => Expr::Fn {
+                    name,
+                    max_depth,
+                    params,
+                    body: Box::new(body),
+                    rest,
+                },
+            };
+        }
+        expr
+    }
+
+    /// Typecheck, transform and emit the program as Rust.
+    pub fn transpile(&self, config: &crate::Config) -> Result<String, crate::TranspileError> {
+        let expr = self.clone().into_expr();
+        let transpiled = crate::transpile_ast(&expr, config);
+        crate::stack::drop_tree(expr);
+        transpiled
+    }
 }
 
 impl Expr {
+    /// Direct sub-expressions, in source order.
+    pub fn children(&self) -> Vec<&Expr> {
+        match self {
+            Expr::Int(_)
+            | Expr::Sized(..)
+            | Expr::Bool(_)
+            | Expr::Var(_)
+            | Expr::Input { .. }
+            | Expr::Bytes(_)
+            | Expr::None => vec![],
+            Expr::Secret(inner)
+            | Expr::Declassify(inner)
+            | Expr::Sort(inner)
+            | Expr::Some(inner) => vec![inner],
+            Expr::BinOp { left, right, .. } => vec![left, right],
+            Expr::SwapIf { cond, left, right } => vec![cond, left, right],
+            Expr::Clamp { value, lo, hi } | Expr::InRange { value, lo, hi } => vec![value, lo, hi],
+            Expr::UnwrapOr { option, default } => vec![option, default],
+            Expr::Pow { base, exp } => vec![base, exp],
+            Expr::Map { array, f } => vec![array, f],
+            Expr::Fold { array, init, f } => vec![array, init, f],
+            Expr::UnaryOp { expr, .. } | Expr::Cast { expr, .. } => vec![expr],
+            Expr::If {
+                cond,
+                then_branch,
+                else_branch,
+            } => vec![cond, then_branch, else_branch],
+            Expr::Let { value, body, .. } | Expr::Const { value, body, .. } => vec![value, body],
+            Expr::Enum { body, .. } => vec![body],
+            Expr::Assert { cond, body } => vec![cond, body],
+            Expr::Assign {
+                cond, value, body, ..
+            } => cond.iter().chain([value, body]).map(Box::as_ref).collect(),
+            Expr::Array(elements) | Expr::Tuple(elements) => elements.iter().collect(),
+            Expr::LetTuple { value, body, .. } => vec![value, body],
+            Expr::Index { array, index } => vec![array, index],
+            Expr::Lookup {
+                key: first,
+                entries: pairs,
+                default,
+            } => {
+                let mut children = vec![first.as_ref()];
+                children.extend(pairs.iter().flat_map(|(k, v)| [k, v]));
+                children.push(default);
+                children
+            }
+            Expr::Match {
+                scrutinee,
+                arms,
+                default,
+            } => {
+                let mut children = vec![scrutinee.as_ref()];
+                children.extend(arms.iter().flat_map(|(p, e)| [p, e]));
+                children.extend(default.as_deref());
+                children
+            }
+            Expr::Outputs(outputs) => outputs.iter().map(|(_, e)| e).collect(),
+            Expr::Fn { body, rest, .. } => vec![body, rest],
+            Expr::Call { args, .. } => args.iter().collect(),
+            Expr::Lambda { body, .. } => vec![body],
+            Expr::For {
+                start,
+                end,
+                init,
+                body,
+                ..
+            } => vec![start, end, init, body],
+            Expr::While {
+                cond, init, body, ..
+            } => vec![cond, init, body],
+        }
+    }
+
+    /// Value of an integer expression of literals, negation, `+`, `-` and
+    /// `*` alone, unless it overflows.
+    pub fn const_int(&self) -> Option<i64> {
+        self.const_int_with(&|_| None)
+    }
+
+    /// [`Expr::const_int`], also reading the variables `constant` gives a
+    /// value, such as those bound by [`Expr::Const`].
+    pub fn const_int_with(&self, constant: &dyn Fn(Symbol) -> Option<i64>) -> Option<i64> {
+        match self {
+            Expr::Int(n) => Some(*n),
+            Expr::Var(name) => constant(*name),
+            Expr::UnaryOp {
+                op: UnaryOp::Neg,
+                expr,
+            } => expr.const_int_with(constant)?.checked_neg(),
+            Expr::BinOp { op, left, right } => {
+                let (left, right) =
+                    (left.const_int_with(constant)?, right.const_int_with(constant)?);
+                match op {
+                    BinOp::Add => left.checked_add(right),
+                    BinOp::Sub => left.checked_sub(right),
+                    BinOp::Mul => left.checked_mul(right),
+                    _ => None,
+                }
+            }
+            _ => None,
+        }
+    }
+
     /// Check if expression contains any secret values.
     pub fn contains_secret(&self) -> bool {
         match self {
             Expr::Secret(_) => true,
-            Expr::Int(_) | Expr::Bool(_) | Expr::Var(_) => false,
+            Expr::Input { secret, .. } => *secret,
+            Expr::Sort(inner) | Expr::Declassify(inner) => inner.contains_secret(),
+            Expr::Int(_)
+            | Expr::Sized(..)
+            | Expr::Bool(_)
+            | Expr::Var(_)
+            | Expr::Bytes(_)
+            | Expr::None => false,
             Expr::BinOp { left, right, .. } => left.contains_secret() || right.contains_secret(),
-            Expr::UnaryOp { expr, .. } => expr.contains_secret(),
+            Expr::SwapIf { cond, left, right } => {
+                cond.contains_secret() || left.contains_secret() || right.contains_secret()
+            }
+            Expr::UnaryOp { expr, .. } | Expr::Cast { expr, .. } => expr.contains_secret(),
             Expr::If {
                 cond,
                 then_branch,
@@ -81,7 +613,441 @@ pub fn contains_secret(&self) -> bool {
                     || then_branch.contains_secret()
                     || else_branch.contains_secret()
             }
-            Expr::Let { value, body, .. } => value.contains_secret() || body.contains_secret(),
+            Expr::Let {
+                ty, value, body, ..
+            } => {
+                ty.is_some_and(|ty| ty.secret == Some(true))
+                    || value.contains_secret()
+                    || body.contains_secret()
+            }
+            Expr::Array(elements) => elements.iter().any(Expr::contains_secret),
+            Expr::Index { array, index } => array.contains_secret() || index.contains_secret(),
+            Expr::Tuple(_)
+            | Expr::LetTuple { .. }
+            | Expr::Clamp { .. }
+            | Expr::InRange { .. }
+            | Expr::Pow { .. }
+            | Expr::Map { .. }
+            | Expr::Fold { .. }
+            | Expr::Some(_)
+            | Expr::UnwrapOr { .. }
+            | Expr::Const { .. }
+            | Expr::Enum { .. }
+            | Expr::Assign { .. }
+            | Expr::Assert { .. }
+            | Expr::Lookup { .. }
+            | Expr::Match { .. }
+            | Expr::Outputs(_)
+            | Expr::Fn { .. }
+            | Expr::Call { .. }
+            | Expr::Lambda { .. }
+            | Expr::For { .. }
+            | Expr::While { .. } => self.children().into_iter().any(Expr::contains_secret),
+        }
+    }
+}
+
+impl fmt::Display for BinOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            BinOp::Add => "+",
+            BinOp::Sub => "-",
+            BinOp::Mul => "*",
+            BinOp::SatAdd => "+|",
+            BinOp::SatSub => "-|",
+            BinOp::SatMul => "*|",
+            BinOp::Div => "/",
+            BinOp::Mod => "%",
+            BinOp::BitAnd => "&",
+            BinOp::BitOr => "|",
+            BinOp::BitXor => "^",
+            BinOp::Shl => "<<",
+            BinOp::Shr => ">>",
+            BinOp::Eq => "==",
+            BinOp::Ne => "!=",
+            BinOp::Lt => "<",
+            BinOp::Le => "<=",
+            BinOp::Gt => ">",
+            BinOp::Ge => ">=",
+            BinOp::And => "&&",
+            BinOp::Or => "||",
+        })
+    }
+}
+
+impl fmt::Display for UnaryOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            UnaryOp::Neg => "-",
+            UnaryOp::Not => "!",
+            UnaryOp::ToInt => "to_int",
+            UnaryOp::ToBool => "to_bool",
+        })
+    }
+}
+
+/// Whether `let name: ty = value` is the `let` that `input ... name: ...`
+/// parses to, which displays as the declaration.
+pub(crate) fn declares_input(
+    name: Symbol,
+    ty: &Option<Annotation>,
+    mutable: bool,
+    value: &Expr,
+) -> bool {
+    matches!(value, Expr::Input { name: input, .. } if *input == name) && ty.is_none() && !mutable
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let nested = |e: &Expr| match e {
+            Expr::Int(n) if *n >= 0 => e.to_string(),
+            Expr::Sized(n, width) if width.value(*n) >= 0 => e.to_string(),
+            Expr::Bool(_)
+            | Expr::Var(_)
+            | Expr::Input { .. }
+            | Expr::Secret(_)
+            | Expr::Declassify(_)
+            | Expr::Bytes(_)
+            | Expr::Sort(_)
+            | Expr::SwapIf { .. }
+            | Expr::Clamp { .. }
+            | Expr::InRange { .. }
+            | Expr::Pow { .. }
+            | Expr::Map { .. }
+            | Expr::Fold { .. }
+            | Expr::Some(_)
+            | Expr::None
+            | Expr::UnwrapOr { .. }
+            | Expr::UnaryOp {
+                op: UnaryOp::ToInt | UnaryOp::ToBool,
+                ..
+            }
+            | Expr::Lookup { .. }
+            | Expr::Outputs(_)
+            | Expr::Call { .. }
+            | Expr::Tuple(_) => e.to_string(),
+            _ => format!("({})", e),
+        };
+        match self {
+            Expr::Int(n) => write!(f, "{}", n),
+            Expr::Sized(n, width) => write!(f, "{}{}", width.value(*n), width),
+            Expr::Bool(b) => write!(f, "{}", b),
+            Expr::Var(name) => f.write_str(name),
+            Expr::Input { name, secret, ty } => {
+                f.write_str(if *secret { "input secret " } else { "input " })?;
+                write!(f, "{}: {}", name, crate::typeck::Type::from(*ty))
+            }
+            Expr::Secret(inner) => write!(f, "secret({})", inner),
+            Expr::Declassify(inner) => write!(f, "declassify({})", inner),
+            Expr::BinOp { op, left, right } => {
+                write!(f, "{} {} {}", nested(left), op, nested(right))
+            }
+            Expr::UnaryOp {
+                op: op @ (UnaryOp::ToInt | UnaryOp::ToBool),
+                expr,
+            } => write!(f, "{}({})", op, expr),
+            Expr::UnaryOp { op, expr } => write!(f, "{}{}", op, nested(expr)),
+            Expr::Cast { expr, ty } => {
+                write!(f, "{} as {}", nested(expr), crate::typeck::Type::from(*ty))
+            }
+            Expr::If {
+                cond,
+                then_branch,
+                else_branch,
+            } => write!(
+                f,
+                "if {} then {} else {}",
+                nested(cond),
+                nested(then_branch),
+                nested(else_branch)
+            ),
+            Expr::Match {
+                scrutinee,
+                arms,
+                default,
+            } => {
+                let arms: Vec<_> =
+                    arms.iter().map(|(p, e)| format!("{} => {}", p, nested(e))).collect();
+                write!(f, "match {} with {}", nested(scrutinee), arms.join(", "))?;
+                match default {
+                    Some(default) => write!(f, ", _ => {}", nested(default)),
+                    None => Ok(()),
+                }
+            }
+            Expr::Let {
+                name,
+                ty,
+                mutable,
+                value,
+                body,
+            } => {
+                if declares_input(*name, ty, *mutable, value) {
+                    return write!(f, "{} {}", value, nested(body));
+                }
+                f.write_str(if *mutable { "let mut " } else { "let " })?;
+                write!(f, "{}", name)?;
+                if let Some(ty) = ty {
+                    write!(f, ": {}", ty)?;
+                }
+                write!(f, " = {} {}", nested(value), nested(body))
+            }
+            Expr::Const { name, value, body } => {
+                write!(f, "const {} = {} {}", name, nested(value), nested(body))
+            }
+            Expr::Enum {
+                name,
+                variants,
+                body,
+            } => write!(f, "{} {}", enum_header(*name, variants), nested(body)),
+            Expr::Assert { cond, body } => write!(f, "assert({}) {}", cond, nested(body)),
+            Expr::Assign {
+                name,
+                cond,
+                value,
+                body,
+            } => {
+                if let Some(cond) = cond {
+                    write!(f, "if {} then ", nested(cond))?;
+                }
+                write!(f, "{} = {} {}", name, nested(value), nested(body))
+            }
+            Expr::Array(elements) => {
+                let elements: Vec<_> = elements.iter().map(Expr::to_string).collect();
+                write!(f, "[{}]", elements.join(", "))
+            }
+            Expr::Tuple(elements) => {
+                let elements: Vec<_> = elements.iter().map(Expr::to_string).collect();
+                write!(f, "({})", elements.join(", "))
+            }
+            Expr::LetTuple { names, value, body } => {
+                let names: Vec<_> = names.iter().map(|name| name.as_str()).collect();
+                write!(f, "let ({}) = {} {}", names.join(", "), nested(value), nested(body))
+            }
+            Expr::Index { array, index } => {
+                let array = match array.as_ref() {
+                    Expr::Var(_) | Expr::Array(_) | Expr::Bytes(_) | Expr::Index { .. } => {
+                        array.to_string()
+                    }
+                    _ => format!("({})", array),
+                };
+                write!(f, "{}[{}]", array, index)
+            }
+            Expr::Bytes(bytes) => f.write_str(&byte_string(bytes)),
+            Expr::Sort(inner) => write!(f, "sort({})", inner),
+            Expr::SwapIf { cond, left, right } => {
+                write!(f, "swap_if({}, {}, {})", cond, left, right)
+            }
+            Expr::Clamp { value, lo, hi } => write!(f, "clamp({}, {}, {})", value, lo, hi),
+            Expr::InRange { value, lo, hi } => write!(f, "in_range({}, {}, {})", value, lo, hi),
+            Expr::Pow { base, exp } => write!(f, "pow({}, {})", base, exp),
+            Expr::Map { array, f: g } => write!(f, "map({}, {})", array, g),
+            Expr::Fold { array, init, f: g } => write!(f, "fold({}, {}, {})", array, init, g),
+            Expr::Some(inner) => write!(f, "some({})", inner),
+            Expr::None => f.write_str("none"),
+            Expr::UnwrapOr { option, default } => write!(f, "unwrap_or({}, {})", option, default),
+            Expr::Lookup {
+                key,
+                entries,
+                default,
+            } => {
+                let entries: Vec<_> =
+                    entries.iter().map(|(k, v)| format!("({}, {})", k, v)).collect();
+                write!(f, "lookup({}, [{}], {})", key, entries.join(", "), default)
+            }
+            Expr::Outputs(outputs) => {
+                let outputs: Vec<_> =
+                    outputs.iter().map(|(name, e)| format!("{} = {}", name, e)).collect();
+                write!(f, "outputs({})", outputs.join(", "))
+            }
+            Expr::Fn {
+                name,
+                max_depth,
+                params,
+                body,
+                rest,
+            } => {
+                if let Some(depth) = max_depth {
+                    write!(f, "#[max_depth({})] ", depth)?;
+                }
+                let params: Vec<_> = params.iter().map(|p| p.as_str()).collect();
+                let params = params.join(", ");
+                write!(f, "fn {}({}) = {} {}", name, params, nested(body), nested(rest))
+            }
+            Expr::Call { name, args } => {
+                let args: Vec<_> = args.iter().map(Expr::to_string).collect();
+                write!(f, "{}({})", name, args.join(", "))
+            }
+            Expr::Lambda { params, body } => {
+                let params: Vec<_> = params.iter().map(|p| p.as_str()).collect();
+                write!(f, "fn({}) => {}", params.join(", "), nested(body))
+            }
+            Expr::For {
+                var,
+                start,
+                end,
+                acc,
+                init,
+                body,
+            } => write!(
+                f,
+                "for {} in {}..{} with {} = {} do {}",
+                var,
+                nested(start),
+                nested(end),
+                acc,
+                nested(init),
+                nested(body)
+            ),
+            Expr::While {
+                cond,
+                acc,
+                init,
+                body,
+            } => write!(
+                f,
+                "while {} with {} = {} do {}",
+                nested(cond),
+                acc,
+                nested(init),
+                nested(body)
+            ),
         }
     }
 }
+
+impl fmt::Display for Statement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Statement::Let {
+                name,
+                ty,
+                mutable,
+                value,
+            } => {
+                if declares_input(*name, ty, *mutable, value) {
+                    return write!(f, "{}", value);
+                }
+                f.write_str(if *mutable { "let mut " } else { "let " })?;
+                write!(f, "{}", name)?;
+                if let Some(ty) = ty {
+                    write!(f, ": {}", ty)?;
+                }
+                write!(f, " = {}", value)
+            }
+            Statement::Const { name, value } => write!(f, "const {} = {}", name, value),
+            Statement::Enum { name, variants } => f.write_str(&enum_header(*name, variants)),
+            Statement::Assert { cond } => write!(f, "assert({})", cond),
+            Statement::Assign { name, cond, value } => {
+                if let Some(cond) = cond {
+                    write!(f, "if {} then ", cond)?;
+                }
+                write!(f, "{} = {}", name, value)
+            }
+            Statement::LetTuple { names, value } => {
+                let names: Vec<_> = names.iter().map(|name| name.as_str()).collect();
+                write!(f, "let ({}) = {}", names.join(", "), value)
+            }
+            Statement::Fn {
+                name,
+                max_depth,
+                params,
+                body,
+            } => {
+                if let Some(depth) = max_depth {
+                    write!(f, "#[max_depth({})] ", depth)?;
+                }
+                let params: Vec<_> = params.iter().map(|p| p.as_str()).collect();
+                write!(f, "fn {}({}) = {}", name, params.join(", "), body)
+            }
+        }
+    }
+}
+
+/// One statement per line, each ended by `;`, then the result.
+impl fmt::Display for Program {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for statement in &self.statements {
+            writeln!(f, "{};", statement)?;
+        }
+        write!(f, "{}", self.result)
+    }
+}
+
+/// `enum name = v1 | ... | vn`, without the body.
+pub(crate) fn enum_header(name: Symbol, variants: &[Symbol]) -> String {
+    let variants: Vec<_> = variants.iter().map(|v| v.as_str()).collect();
+    format!("enum {} = {}", name, variants.join(" | "))
+}
+
+/// `bytes` as a byte-string literal, escaping all but printable ASCII.
+pub(crate) fn byte_string(bytes: &[u8]) -> String {
+    let mut literal = String::from("b\"");
+    for &b in bytes {
+        match b {
+            b'\\' | b'"' => {
+                literal.push('\\');
+                literal.push(b as char);
+            }
+            _ if b == b' ' || b.is_ascii_graphic() => literal.push(b as char),
+            _ => literal.push_str(&format!("\\x{:02x}", b)),
+        }
+    }
+    literal.push('"');
+    literal
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_program_round_trips_through_expr() {
+        let expr = Expr::Let {
+            name: "x".into(),
+            ty: None,
+            mutable: false,
+            value: Box::new(Expr::Int(1)),
+            body: Box::new(Expr::Fn {
+                name: "f".into(),
+                max_depth: None,
+                params: vec![],
+                body: Box::new(Expr::Var("x".into())),
+                rest: Box::new(Expr::Call {
+                    name: "f".into(),
+                    args: vec![],
+                }),
+            }),
+        };
+        let program = Program::from_expr(expr.clone());
+        assert_eq!(program.statements.len(), 2);
+        let call = Expr::Call {
+            name: "f".into(),
+            args: vec![],
+        };
+        assert_eq!(program.result, call);
+        assert_eq!(program.into_expr(), expr);
+    }
+
+    #[test]
+    fn test_display_is_parenthesised_source() {
+        let expr = Expr::Let {
+            name: "x".into(),
+            ty: None,
+            mutable: false,
+            value: Box::new(Expr::Secret(Box::new(Expr::Int(1)))),
+            body: Box::new(Expr::BinOp {
+                op: BinOp::Mul,
+                left: Box::new(Expr::BinOp {
+                    op: BinOp::Add,
+                    left: Box::new(Expr::Var("x".into())),
+                    right: Box::new(Expr::Int(-2)),
+                }),
+                right: Box::new(Expr::UnaryOp {
+                    op: UnaryOp::Not,
+                    expr: Box::new(Expr::Bool(true)),
+                }),
+            }),
+        };
+        assert_eq!(expr.to_string(), "let x = secret(1) ((x + (-2)) * (!true))");
+    }
+}
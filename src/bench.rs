@@ -0,0 +1,213 @@
+// SPDX-License-Identifier: MIT OR AGPL-3.0-or-later
+// SPDX-FileCopyrightText: 2024-2025 hyperpolymath
+
+//! Timing-measurement harnesses.
+//!
+//! [`timing_harness`] generates a standalone Rust program that runs the
+//! emitted kernel of a MiniObli program on several classes of inputs,
+//! typically differing only in their secrets, and prints one CSV row
+//! `class,cycles` per run. Classes are interleaved in a pseudo-random
+//! order so drift affects them alike; the raw samples are meant for a
+//! statistical test such as Welch's t-test. Cycles come from the
+//! time-stamp counter on x86-64 and are nanoseconds elsewhere.
+//!
+//! ```sh
+//! rustc -O harness.rs && ./harness 1000000 > samples.csv
+//! ```
+
+use crate::config::Config;
+use crate::diagnostic::Diagnostic;
+use crate::emit::{emit_function, RUNTIME_PRELUDE};
+use crate::interp::{Inputs, Labeled, Value};
+use crate::ir::ObliExpr;
+use crate::run::{prepare_ast, RunError};
+use crate::transform::to_oblivious_checked;
+use crate::typeck::{typecheck, Type};
+
+/// Samples taken when the harness is run without an argument.
+pub const DEFAULT_SAMPLES: usize = 100_000;
+
+/// Generate a harness timing `source`, with the passes of `config`
+/// applied, on each of `classes`. Every class must bind the same inputs
+/// with the same types and secrecy; class `i` is reported as `i`.
+pub fn timing_harness(
+    source: &str,
+    config: &Config,
+    classes: &[Inputs],
+) -> Result<String, RunError> {
+    let first = classes
+        .first()
+        .ok_or_else(|| RunError::Input("at least one input class is required".to_string()))?;
+    let signature = |inputs: &Inputs| {
+        let mut inputs: Vec<_> = inputs
+            .iter()
+            .map(|(name, input)| (name, input.secret, rust_type(&input.value)))
+            .collect();
+        inputs.sort();
+        inputs
+    };
+    let params = signature(first);
+    for (i, class) in classes.iter().enumerate() {
+        if signature(class) != params {
+            let message = format!("class {} does not bind the same inputs as class 0", i);
+            return Err(RunError::Input(message));
+        }
+    }
+
+    // Inputs are bound by leading lets in name order: the kernel takes them
+    // as parameters instead
+    let (ast, spans) = prepare_ast(source, first)?;
+    let ty = match typecheck(&ast) {
+        Ok(Type::Bool) => "bool",
+        _ => "i64",
+    };
+    let (mut kernel, _, _) = to_oblivious_checked(&ast, &spans).map_err(|errors| {
+        RunError::Diagnostics(errors.into_iter().map(Diagnostic::from).collect())
+    })?;
+    for _ in 0..params.len() {
+        kernel = match kernel {
+            ObliExpr::Let { body, .. } => *body,
+            _ => unreachable!("prepare_ast binds every input with a let"),
+        };
+    }
+    let kernel = config.pipeline().run(kernel);
+    let wrapper = if kernel.is_secret() { "Secret" } else { "Pub" };
+    let ret = format!("{}<{}>", wrapper, ty);
+
+    let mut out = String::from("#![allow(dead_code)]\n\n");
+    out.push_str(RUNTIME_PRELUDE);
+    out.push_str("\n#[inline(never)]\n");
+    out.push_str(&emit_function("kernel", &params, &ret, &kernel));
+    out.push_str(CYCLES);
+
+    let names: Vec<_> = params.iter().map(|(name, _, _)| name.as_str()).collect();
+    out.push_str("\nfn main() {\n    let classes = [\n");
+    for class in classes {
+        let mut values: Vec<_> = class.iter().collect();
+        values.sort_by_key(|(name, _)| *name);
+        let values: Vec<_> = values.into_iter().map(|(_, input)| literal(input)).collect();
+        out.push_str(&format!("        ({}),\n", tuple(&values)));
+    }
+    out.push_str("    ];\n");
+    out.push_str(&format!(
+        "    let samples: usize = std::env::args()\n        .nth(1)\n        \
+         .and_then(|n| n.parse().ok())\n        .unwrap_or({});\n",
+        DEFAULT_SAMPLES
+    ));
+    out.push_str(&format!(
+        "    let mut measured = Vec::with_capacity(samples);\n    \
+         let mut state: u64 = 0x9e37_79b9_7f4a_7c15;\n    \
+         for _ in 0..samples {{\n        \
+         // xorshift64: interleave the classes\n        \
+         state ^= state << 13;\n        \
+         state ^= state >> 7;\n        \
+         state ^= state << 17;\n        \
+         let class = (state % classes.len() as u64) as usize;\n        \
+         let ({}) = std::hint::black_box(classes[class]);\n        \
+         let start = cycles();\n        \
+         let result = kernel({});\n        \
+         let end = cycles();\n        \
+         std::hint::black_box(result);\n        \
+         measured.push((class, end.wrapping_sub(start)));\n    }}\n",
+        tuple(&names),
+        names.join(", ")
+    ));
+    out.push_str(
+        "    use std::io::Write;\n    \
+         let mut out = std::io::BufWriter::new(std::io::stdout().lock());\n    \
+         writeln!(out, \"class,cycles\").unwrap();\n    \
+         for (class, cycles) in measured {\n        \
+         writeln!(out, \"{},{}\", class, cycles).unwrap();\n    }\n}\n",
+    );
+    Ok(out)
+}
+
+const CYCLES: &str = r#"
+#[cfg(target_arch = "x86_64")]
+fn cycles() -> u64 {
+    unsafe { std::arch::x86_64::_rdtsc() }
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+fn cycles() -> u64 {
+    static START: std::sync::OnceLock<std::time::Instant> = std::sync::OnceLock::new();
+    START.get_or_init(std::time::Instant::now).elapsed().as_nanos() as u64
+}
+"#;
+
+fn rust_type(value: &Value) -> &'static str {
+    match value {
+        Value::Int(_) => "i64",
+        Value::Sized(_, width) => width.name(),
+        Value::Bool(_) => "bool",
+    }
+}
+
+fn literal(input: &Labeled) -> String {
+    let wrapper = if input.secret { "Secret" } else { "Pub" };
+    match input.value {
+        Value::Int(n) => format!("{}::new({}i64)", wrapper, n),
+        Value::Sized(n, width) => format!("{}::new({}{})", wrapper, width.value(n), width),
+        Value::Bool(b) => format!("{}::new({})", wrapper, b),
+    }
+}
+
+/// A Rust tuple expression or pattern (with a trailing comma for one item).
+fn tuple<S: AsRef<str>>(items: &[S]) -> String {
+    let items: Vec<_> = items.iter().map(|item| item.as_ref()).collect();
+    match items.len() {
+        1 => format!("{},", items[0]),
+        _ => items.join(", "),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process::Command;
+
+    #[test]
+    fn test_harness_runs_and_samples_every_class() {
+        let classes = [
+            Inputs::new().secret("k", Value::Int(0)).public("n", Value::Int(3)),
+            Inputs::new().secret("k", Value::Int(-77)).public("n", Value::Int(3)),
+        ];
+        let code = timing_harness("if k > n then k * n else k - n", &Config::default(), &classes)
+            .unwrap();
+        assert!(code.contains("fn kernel(k: Secret<i64>, n: Pub<i64>) -> Secret<i64> {\n"));
+
+        let dir = std::env::temp_dir().join(format!("obli-bench-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let (main, binary) = (dir.join("main.rs"), dir.join("main"));
+        std::fs::write(&main, code).unwrap();
+        let compiled = Command::new("rustc")
+            .args(["--edition", "2021", "-O", "-o"])
+            .arg(&binary)
+            .arg(&main)
+            .output()
+            .unwrap();
+        assert!(compiled.status.success(), "{}", String::from_utf8_lossy(&compiled.stderr));
+        let ran = Command::new(&binary).arg("200").output().unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let csv = String::from_utf8(ran.stdout).unwrap();
+        let rows: Vec<_> = csv.lines().collect();
+        assert_eq!(rows[0], "class,cycles");
+        assert_eq!(rows.len(), 201);
+        assert!(rows.iter().any(|row| row.starts_with("0,")));
+        assert!(rows.iter().any(|row| row.starts_with("1,")));
+    }
+
+    #[test]
+    fn test_classes_must_agree() {
+        let classes = [
+            Inputs::new().secret("k", Value::Int(0)),
+            Inputs::new().public("k", Value::Int(0)),
+        ];
+        let err = timing_harness("k + 1", &Config::default(), &classes).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "invalid input: class 1 does not bind the same inputs as class 0"
+        );
+    }
+}
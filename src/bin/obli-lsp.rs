@@ -0,0 +1,256 @@
+// SPDX-License-Identifier: MIT OR AGPL-3.0-or-later
+// SPDX-FileCopyrightText: 2024-2025 hyperpolymath
+
+//! obli-lsp — language server for MiniObli
+//!
+//! Speaks LSP over stdio. Documents are fully re-analysed on every change;
+//! columns are counted in characters. Diagnostic suggestions are offered as
+//! quick fixes.
+
+use lsp_server::{Connection, ExtractError, Message, Notification, Request, RequestId, Response};
+use lsp_types::notification::{
+    DidChangeTextDocument, DidCloseTextDocument, DidOpenTextDocument,
+    Notification as _, PublishDiagnostics,
+};
+use lsp_types::request::{CodeActionRequest, GotoDefinition, HoverRequest};
+use lsp_types::{
+    CodeAction, CodeActionKind, CodeActionOrCommand, CodeActionProviderCapability,
+    DiagnosticSeverity, DidChangeTextDocumentParams, DidCloseTextDocumentParams,
+    DidOpenTextDocumentParams, GotoDefinitionResponse, Hover, HoverContents,
+    HoverProviderCapability, Location, MarkupContent, MarkupKind, NumberOrString, OneOf,
+    Position, PublishDiagnosticsParams, Range, ServerCapabilities, TextDocumentSyncCapability,
+    TextDocumentSyncKind, TextEdit, Url, WorkspaceEdit,
+};
+use obli_transpiler::diagnostic::{Diagnostic, Severity};
+use obli_transpiler::lsp::Document;
+use obli_transpiler::span::{line_col, offset_of, Span};
+use std::collections::HashMap;
+use std::error::Error;
+
+type BoxError = Box<dyn Error + Send + Sync>;
+
+fn main() -> Result<(), BoxError> {
+    let (connection, io_threads) = Connection::stdio();
+    let capabilities = ServerCapabilities {
+        text_document_sync: Some(TextDocumentSyncCapability::Kind(TextDocumentSyncKind::FULL)),
+        hover_provider: Some(HoverProviderCapability::Simple(true)),
+        definition_provider: Some(OneOf::Left(true)),
+        code_action_provider: Some(CodeActionProviderCapability::Simple(true)),
+        ..ServerCapabilities::default()
+    };
+    connection.initialize(serde_json::to_value(capabilities)?)?;
+    Server::default().run(&connection)?;
+    drop(connection);
+    io_threads.join()?;
+    Ok(())
+}
+
+#[derive(Default)]
+struct Server {
+    documents: HashMap<Url, (String, Document)>,
+}
+
+impl Server {
+    fn run(&mut self, connection: &Connection) -> Result<(), BoxError> {
+        for message in &connection.receiver {
+            match message {
+                Message::Request(request) => {
+                    if connection.handle_shutdown(&request)? {
+                        return Ok(());
+                    }
+                    let response = self.request(request);
+                    connection.sender.send(Message::Response(response))?;
+                }
+                Message::Notification(notification) => {
+                    if let Some(uri) = self.notification(notification) {
+                        connection.sender.send(self.publish_diagnostics(uri))?;
+                    }
+                }
+                Message::Response(_) => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn request(&self, request: Request) -> Response {
+        let request = match cast::<HoverRequest>(request) {
+            Ok((id, params)) => {
+                let params = params.text_document_position_params;
+                let hover = self
+                    .query(&params.text_document.uri, params.position, Document::hover)
+                    .map(|(source, hover)| Hover {
+                        contents: HoverContents::Markup(MarkupContent {
+                            kind: MarkupKind::PlainText,
+                            value: hover.to_string(),
+                        }),
+                        range: Some(range(source, hover.span)),
+                    });
+                return Response::new_ok(id, hover);
+            }
+            Err(request) => request,
+        };
+        let request = match cast::<GotoDefinition>(request) {
+            Ok((id, params)) => {
+                let params = params.text_document_position_params;
+                let uri = params.text_document.uri;
+                let location = self
+                    .query(&uri, params.position, Document::definition)
+                    .map(|(source, span)| {
+                        let location = Location::new(uri.clone(), range(source, span));
+                        GotoDefinitionResponse::Scalar(location)
+                    });
+                return Response::new_ok(id, location);
+            }
+            Err(request) => request,
+        };
+        let request = match cast::<CodeActionRequest>(request) {
+            Ok((id, params)) => {
+                let actions = self.code_actions(&params.text_document.uri, params.range);
+                return Response::new_ok(id, actions);
+            }
+            Err(request) => request,
+        };
+        Response::new_err(
+            request.id,
+            lsp_server::ErrorCode::MethodNotFound as i32,
+            format!("unsupported request `{}`", request.method),
+        )
+    }
+
+    /// Apply a document notification, returning the URI whose diagnostics changed.
+    fn notification(&mut self, notification: Notification) -> Option<Url> {
+        match notification.method.as_str() {
+            DidOpenTextDocument::METHOD => {
+                let params = notification
+                    .extract::<DidOpenTextDocumentParams>(DidOpenTextDocument::METHOD)
+                    .ok()?;
+                let doc = params.text_document;
+                self.open(doc.uri.clone(), doc.text);
+                Some(doc.uri)
+            }
+            DidChangeTextDocument::METHOD => {
+                let params = notification
+                    .extract::<DidChangeTextDocumentParams>(DidChangeTextDocument::METHOD)
+                    .ok()?;
+                let text = params.content_changes.into_iter().last()?.text;
+                let uri = params.text_document.uri;
+                self.open(uri.clone(), text);
+                Some(uri)
+            }
+            DidCloseTextDocument::METHOD => {
+                let params = notification
+                    .extract::<DidCloseTextDocumentParams>(DidCloseTextDocument::METHOD)
+                    .ok()?;
+                self.documents.remove(&params.text_document.uri);
+                None
+            }
+            _ => None,
+        }
+    }
+
+    fn open(&mut self, uri: Url, text: String) {
+        // Includes are resolved against the file, when the document is one
+        let document = match uri.to_file_path() {
+            Ok(path) => Document::from_file(path, &text),
+            Err(()) => Document::new(&text),
+        };
+        self.documents.insert(uri, (text, document));
+    }
+
+    fn query<T>(
+        &self,
+        uri: &Url,
+        position: Position,
+        f: impl Fn(&Document, usize) -> Option<T>,
+    ) -> Option<(&str, T)> {
+        let (source, document) = self.documents.get(uri)?;
+        let line = position.line as usize + 1;
+        let offset = offset_of(source, line, position.character as usize + 1)?;
+        f(document, offset).map(|result| (source.as_str(), result))
+    }
+
+    /// Quick fixes for the diagnostics overlapping `range`.
+    fn code_actions(&self, uri: &Url, selected: Range) -> Vec<CodeActionOrCommand> {
+        let Some((source, document)) = self.documents.get(uri) else {
+            return Vec::new();
+        };
+        let mut actions = Vec::new();
+        for diagnostic in document.diagnostics() {
+            let Some(span) = diagnostic.span else {
+                continue;
+            };
+            let at = range(source, span);
+            if at.end < selected.start || selected.end < at.start {
+                continue;
+            }
+            for suggestion in &diagnostic.suggestions {
+                let replacement = suggestion.replacement.clone();
+                let edit = TextEdit::new(range(source, suggestion.span), replacement);
+                let changes = HashMap::from([(uri.clone(), vec![edit])]);
+                actions.push(CodeActionOrCommand::CodeAction(CodeAction {
+                    title: suggestion.message.clone(),
+                    kind: Some(CodeActionKind::QUICKFIX),
+                    diagnostics: Some(vec![to_lsp_diagnostic(source, diagnostic)]),
+                    edit: Some(WorkspaceEdit::new(changes)),
+                    is_preferred: Some(suggestion.is_machine_applicable()),
+                    ..CodeAction::default()
+                }));
+            }
+        }
+        actions
+    }
+
+    fn publish_diagnostics(&self, uri: Url) -> Message {
+        let diagnostics = match self.documents.get(&uri) {
+            Some((source, document)) => document
+                .diagnostics()
+                .iter()
+                .map(|d| to_lsp_diagnostic(source, d))
+                .collect(),
+            None => Vec::new(),
+        };
+        let params = PublishDiagnosticsParams::new(uri, diagnostics, None);
+        Message::Notification(Notification::new(PublishDiagnostics::METHOD.to_string(), params))
+    }
+}
+
+fn cast<R: lsp_types::request::Request>(
+    request: Request,
+) -> Result<(RequestId, R::Params), Request> {
+    request.extract(R::METHOD).map_err(|e| match e {
+        ExtractError::MethodMismatch(request) => request,
+        ExtractError::JsonError { method, error } => {
+            panic!("malformed `{}` request: {}", method, error)
+        }
+    })
+}
+
+fn position(source: &str, offset: usize) -> Position {
+    let (line, col) = line_col(source, offset);
+    Position::new(line as u32 - 1, col as u32 - 1)
+}
+
+fn range(source: &str, span: Span) -> Range {
+    Range::new(position(source, span.start), position(source, span.end))
+}
+
+fn to_lsp_diagnostic(source: &str, diagnostic: &Diagnostic) -> lsp_types::Diagnostic {
+    let severity = match diagnostic.severity {
+        Severity::Error => DiagnosticSeverity::ERROR,
+        Severity::Warning => DiagnosticSeverity::WARNING,
+        Severity::Note => DiagnosticSeverity::INFORMATION,
+    };
+    let mut message = diagnostic.message.clone();
+    for note in &diagnostic.notes {
+        message.push_str("\nnote: ");
+        message.push_str(note);
+    }
+    lsp_types::Diagnostic {
+        range: range(source, diagnostic.span.unwrap_or_default()),
+        severity: Some(severity),
+        code: diagnostic.code.map(|code| NumberOrString::String(code.to_string())),
+        source: Some("obli".to_string()),
+        message,
+        ..lsp_types::Diagnostic::default()
+    }
+}
@@ -0,0 +1,1173 @@
+// SPDX-License-Identifier: MIT OR AGPL-3.0-or-later
+// SPDX-FileCopyrightText: 2024-2025 hyperpolymath
+
+//! obli - MiniObli to Rust (constant-time) transpiler CLI
+
+use clap::{Parser, Subcommand, ValueEnum};
+use obli_transpiler::analysis::dynamic_ct_check_with_spans;
+use obli_transpiler::bench::timing_harness;
+use obli_transpiler::debugger::{Debugger, Stop};
+use obli_transpiler::differential::{check_backends, DifferentialError};
+use obli_transpiler::diagnostic::{has_errors, Diagnostic, Renderer};
+use obli_transpiler::codes::explain;
+use obli_transpiler::config::Config;
+use obli_transpiler::cst::Cst;
+use obli_transpiler::emit::BACKENDS;
+use obli_transpiler::fix::apply;
+use obli_transpiler::formatter::{diff, format_source};
+use obli_transpiler::frontend::Registry;
+use obli_transpiler::graph;
+use obli_transpiler::include::Unit;
+use obli_transpiler::interp::{eval_ir, eval_ir_outputs, Inputs};
+use obli_transpiler::passes::{builtin, Pipeline};
+use obli_transpiler::lean::to_lean;
+use obli_transpiler::lint::Level;
+use obli_transpiler::policy::Policy;
+use obli_transpiler::provenance::explain_secrecy;
+use obli_transpiler::report::{transpile_with_report, REPORT_EXTENSION};
+use obli_transpiler::schema::{Schema, SCHEMA_EXTENSION};
+use obli_transpiler::repl::{Reply, Session};
+use obli_transpiler::run::{
+    parse_assignment, parse_witness, prepare, prepare_with_spans, run_native, RunError,
+};
+use obli_transpiler::span::{offset_of, Span};
+use obli_transpiler::stats::{stats, COST_MODELS};
+use obli_transpiler::watch::Watcher;
+use obli_transpiler::build::{build_dir, build_file, BuildReport};
+use obli_transpiler::check::{check_with, check_with_policy};
+use obli_transpiler::{transpile_to, transpile_to_ir, transpile_with, Timings, TranspileError};
+use std::fs;
+use tracing_subscriber::fmt::format::FmtSpan;
+use tracing_subscriber::EnvFilter;
+use std::io::{self, BufRead, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::Duration;
+
+#[derive(Parser)]
+#[command(name = "obli")]
+#[command(author, version, about = "Oblivious program transpiler: MiniObli → Rust", long_about = None)]
+struct Cli {
+    /// Project configuration (defaults to the nearest obli.toml)
+    #[arg(long, global = true, value_name = "PATH")]
+    config: Option<PathBuf>,
+
+    #[command(subcommand)]
+    command: Commands,
+}
+
+#[derive(Subcommand)]
+enum Commands {
+    /// Transpile a MiniObli file, or a directory of them, to Rust
+    #[command(alias = "transpile")]
+    Build {
+        /// Input .mobli file or directory (defaults to the configured sources)
+        file: Option<PathBuf>,
+
+        /// Output .rs file (defaults to stdout), or output directory when
+        /// building a directory (defaults to the configured out-dir)
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+
+        /// Rebuild whenever the input file changes
+        #[arg(short, long)]
+        watch: bool,
+
+        /// Report the time spent in each compilation stage
+        #[arg(long)]
+        timings: bool,
+
+        /// Write a JSON compilation report next to each output
+        #[arg(long)]
+        report: bool,
+    },
+    /// Check a MiniObli file for errors without emitting code
+    Check {
+        /// Input .mobli file
+        file: PathBuf,
+
+        /// Allow a lint
+        #[arg(short = 'A', long = "allow", value_name = "LINT")]
+        allow: Vec<String>,
+
+        /// Warn about a lint
+        #[arg(short = 'W', long = "warn", value_name = "LINT")]
+        warn: Vec<String>,
+
+        /// Report a lint as an error
+        #[arg(short = 'D', long = "deny", value_name = "LINT")]
+        deny: Vec<String>,
+
+        /// Declassification policy (TOML, or JSON by extension) that every
+        /// revealed result must be covered by; defaults to check.policy
+        #[arg(long, value_name = "FILE")]
+        policy: Option<PathBuf>,
+    },
+    /// Step through a MiniObli file with the reference interpreter
+    Debug {
+        /// Input .mobli file
+        file: PathBuf,
+
+        /// Bind a free variable to a secret input
+        #[arg(long = "secret", value_name = "NAME=VALUE")]
+        secrets: Vec<String>,
+
+        /// Bind a free variable to a public input
+        #[arg(long = "public", value_name = "NAME=VALUE")]
+        publics: Vec<String>,
+
+        /// JSON witness file: {"secret": {"k": 5}, "public": {"n": 3}}
+        #[arg(long, value_name = "FILE")]
+        witness: Option<PathBuf>,
+    },
+    /// Print the oblivious IR of a MiniObli file
+    EmitIr {
+        /// Input .mobli file
+        file: PathBuf,
+    },
+    /// Print Lean 4 definitions of a file's source and IR semantics
+    EmitLean {
+        /// Input .mobli file
+        file: PathBuf,
+
+        /// Make a free variable a secret parameter (VALUE fixes its type)
+        #[arg(long = "secret", value_name = "NAME=VALUE")]
+        secrets: Vec<String>,
+
+        /// Make a free variable a public parameter (VALUE fixes its type)
+        #[arg(long = "public", value_name = "NAME=VALUE")]
+        publics: Vec<String>,
+    },
+    /// Explain a diagnostic code such as OB0005
+    Explain {
+        /// Diagnostic code
+        code: String,
+    },
+    /// Explain why the expression at a position is secret
+    ExplainSecrecy {
+        /// Input .mobli file
+        file: PathBuf,
+
+        /// Position as LINE:COL (1-based)
+        position: String,
+    },
+    /// Write a Graphviz graph of a MiniObli file (DOT, or SVG via `dot`)
+    Graph {
+        /// Input .mobli file
+        file: PathBuf,
+
+        /// What to draw
+        #[arg(long, value_enum, default_value = "ir")]
+        view: GraphView,
+
+        /// Pass to run for `--view passes` (repeatable; defaults to all built-in passes)
+        #[arg(long = "pass", value_name = "PASS")]
+        passes: Vec<String>,
+
+        /// Output file; a `.svg` extension renders with Graphviz (defaults to DOT on stdout)
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Format MiniObli files in place
+    Fmt {
+        /// Input .mobli files
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
+
+        /// Report files that need formatting (with a diff) instead of rewriting them
+        #[arg(long)]
+        check: bool,
+    },
+    /// Apply the fixes `obli check` suggests, in place
+    Fix {
+        /// Input .mobli files
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
+
+        /// Report files with fixes to apply (with a diff) instead of rewriting them
+        #[arg(long)]
+        check: bool,
+    },
+    /// Start an interactive session backed by the reference interpreter
+    Repl,
+    /// Run a MiniObli file or expression and print its result
+    Run {
+        /// Input .mobli file
+        #[arg(required_unless_present = "expr", conflicts_with = "expr")]
+        file: Option<PathBuf>,
+
+        /// Expression to evaluate instead of a file
+        #[arg(short, long)]
+        expr: Option<String>,
+
+        /// Bind a free variable to a secret input
+        #[arg(long = "secret", value_name = "NAME=VALUE")]
+        secrets: Vec<String>,
+
+        /// Bind a free variable to a public input
+        #[arg(long = "public", value_name = "NAME=VALUE")]
+        publics: Vec<String>,
+
+        /// JSON witness file: {"secret": {"k": 5}, "public": {"n": 3}}
+        #[arg(long, value_name = "FILE")]
+        witness: Option<PathBuf>,
+
+        /// Compile the emitted Rust with rustc and run it instead of interpreting
+        #[arg(long)]
+        native: bool,
+
+        /// Warn about branches and divisions that depend on secrets at run time
+        #[arg(long, conflicts_with = "native")]
+        ct_check: bool,
+
+        /// Run on the interpreters and every back end and check they agree
+        #[arg(long, conflicts_with_all = ["native", "ct_check"])]
+        differential: bool,
+    },
+    /// Print IR statistics and projected costs per back end
+    Stats {
+        /// Input .mobli file
+        file: PathBuf,
+
+        /// Output format
+        #[arg(long, value_enum, default_value = "text")]
+        format: StatsFormat,
+    },
+    /// Print a Rust program timing a file's kernel on classes of inputs
+    TimingHarness {
+        /// Input .mobli file
+        file: PathBuf,
+
+        /// JSON witness file binding one input class (repeat per class)
+        #[arg(long = "class", value_name = "FILE", required = true)]
+        classes: Vec<PathBuf>,
+    },
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum GraphView {
+    /// Syntax tree
+    Ast,
+    /// Oblivious IR, secret nodes highlighted
+    Ir,
+    /// IR after the transform and after each pass
+    Passes,
+    /// Secrecy flow between variables
+    Flow,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum StatsFormat {
+    /// Human-readable report
+    Text,
+    /// Single JSON object, for tooling
+    Json,
+}
+
+fn main() {
+    let cli = Cli::parse();
+    if std::env::var_os("OBLI_LOG").is_some() {
+        tracing_subscriber::fmt()
+            .with_env_filter(EnvFilter::from_env("OBLI_LOG"))
+            .with_span_events(FmtSpan::CLOSE)
+            .with_writer(io::stderr)
+            .with_ansi(io::stderr().is_terminal())
+            .init();
+    }
+    let mut config = load_config(cli.config.as_deref());
+
+    match cli.command {
+        Commands::Build {
+            file,
+            output,
+            watch: true,
+            ..
+        } => {
+            let Some(file) = file.filter(|f| f.is_file()) else {
+                eprintln!("Error: --watch needs a single input file");
+                std::process::exit(2);
+            };
+            // Every file of the unit is watched, and any change rebuilds it
+            let mut watcher = Watcher::new([&file]);
+            eprintln!("Watching {} (Ctrl-C to stop)", file.display());
+            loop {
+                if !watcher.poll().is_empty() {
+                    match load_unit(&file) {
+                        Ok(unit) => {
+                            watcher.set_paths(unit.files.iter().map(|f| &f.path));
+                            rebuild(&file, &unit.source, output.as_deref(), &config);
+                        }
+                        Err(e) => eprintln!("Error: {}", e),
+                    }
+                }
+                thread::sleep(Duration::from_millis(200));
+            }
+        }
+        Commands::Build {
+            file: Some(file),
+            output,
+            timings,
+            report: write_report,
+            ..
+        } if !file.is_dir() => {
+            let source = read_source(&file);
+            config.report |= write_report;
+            if config.report && output.is_none() {
+                eprintln!("Error: a compilation report needs an output file (-o)");
+                std::process::exit(2);
+            }
+
+            let mut stage_timings = Timings::new();
+            if !config.report {
+                // Stream the code out instead of holding it all in memory
+                let result = match &output {
+                    Some(path) => stream_to_file(&source, &config, &mut stage_timings, path),
+                    None => {
+                        let mut stdout = io::BufWriter::new(io::stdout().lock());
+                        transpile_to(&source, &config, &mut stage_timings, &mut stdout)
+                            .and_then(|()| Ok(writeln!(stdout).and_then(|()| stdout.flush())?))
+                    }
+                };
+                if timings {
+                    eprint!("Timings:\n{}", stage_timings);
+                }
+                match result {
+                    Ok(()) => {
+                        if let Some(path) = output {
+                            eprintln!("Wrote {}", path.display());
+                            write_schema(&source, &path);
+                        }
+                    }
+                    Err(e) => {
+                        report(&file, &source, &e.into_diagnostics());
+                        std::process::exit(1);
+                    }
+                }
+                return;
+            }
+            let result = transpile_with_report(&source, &config, &mut stage_timings);
+            if timings {
+                eprint!("Timings:\n{}", stage_timings);
+            }
+            match result {
+                Ok((rust_code, compilation)) => {
+                    if let Some(output_path) = output {
+                        let report_path = output_path.with_extension(REPORT_EXTENSION);
+                        let schema_path = output_path.with_extension(SCHEMA_EXTENSION);
+                        let mut files = vec![(output_path, rust_code)];
+                        if config.report {
+                            files.push((report_path, compilation.to_json(&source)));
+                        }
+                        if let Ok(schema) = Schema::of(&source) {
+                            files.push((schema_path, schema.to_json()));
+                        }
+                        for (path, contents) in files {
+                            if let Err(e) = fs::write(&path, contents) {
+                                eprintln!("Error writing {}: {}", path.display(), e);
+                                std::process::exit(1);
+                            }
+                            eprintln!("Wrote {}", path.display());
+                        }
+                    } else {
+                        println!("{}", rust_code);
+                    }
+                }
+                Err(e) => {
+                    report(&file, &source, &e.into_diagnostics());
+                    std::process::exit(1);
+                }
+            }
+        }
+        Commands::Build {
+            file,
+            output,
+            timings,
+            report: write_report,
+            ..
+        } => {
+            config.report |= write_report;
+            let roots = match file {
+                Some(dir) => vec![dir],
+                None if !config.sources.is_empty() => config.sources.clone(),
+                None => {
+                    eprintln!("Error: no input: pass a file or directory, or set build.sources");
+                    std::process::exit(2);
+                }
+            };
+            let Some(out_dir) = output.or_else(|| config.out_dir.clone()) else {
+                eprintln!("Error: no output directory: pass -o or set build.out-dir");
+                std::process::exit(2);
+            };
+            build_roots(&roots, &out_dir, &config, timings);
+        }
+        Commands::Check {
+            file,
+            allow,
+            warn,
+            deny,
+            policy,
+        } => {
+            let mut lints = config.lints.clone();
+            let levels = [(allow, Level::Allow), (warn, Level::Warn), (deny, Level::Deny)];
+            for (names, level) in levels {
+                for name in names {
+                    if let Err(e) = lints.set(&name, level) {
+                        eprintln!("Error: {}", e);
+                        std::process::exit(2);
+                    }
+                }
+            }
+
+            let policy = policy.or(config.policy).map(|path| match Policy::from_path(&path) {
+                Ok(policy) => policy,
+                Err(e) => {
+                    eprintln!("Error in {}: {}", path.display(), e);
+                    std::process::exit(2);
+                }
+            });
+
+            let source = read_source(&file);
+            let diagnostics = check_with_policy(&source, &lints, policy.as_ref());
+            report(&file, &source, &diagnostics);
+
+            if has_errors(&diagnostics) {
+                std::process::exit(1);
+            }
+            println!("{}: OK", file.display());
+        }
+        Commands::Debug {
+            file,
+            secrets,
+            publics,
+            witness,
+        } => {
+            let source = read_source(&file);
+            let inputs = match run_inputs(&secrets, &publics, witness.as_deref()) {
+                Ok(inputs) => inputs,
+                Err(e) => {
+                    eprintln!("Error: {}", e);
+                    std::process::exit(2);
+                }
+            };
+            match Debugger::from_source(&source, &inputs) {
+                Ok(debugger) => debug_session(&source, debugger),
+                Err(RunError::Diagnostics(diagnostics)) => {
+                    report(&file, &source, &diagnostics);
+                    std::process::exit(1);
+                }
+                Err(e) => {
+                    eprintln!("Error: {}", e);
+                    std::process::exit(1);
+                }
+            }
+        }
+        Commands::EmitIr { file } => {
+            let source = read_source(&file);
+
+            match transpile_to_ir(&source) {
+                Ok(ir) => {
+                    println!("{:#?}", ir);
+                }
+                Err(e) => {
+                    report(&file, &source, &e.into_diagnostics());
+                    std::process::exit(1);
+                }
+            }
+        }
+        Commands::EmitLean {
+            file,
+            secrets,
+            publics,
+        } => {
+            let source = read_source(&file);
+            let inputs = match run_inputs(&secrets, &publics, None) {
+                Ok(inputs) => inputs,
+                Err(e) => {
+                    eprintln!("Error: {}", e);
+                    std::process::exit(2);
+                }
+            };
+            match to_lean(&source, &inputs) {
+                Ok(lean) => print!("{}", lean),
+                Err(RunError::Diagnostics(diagnostics)) => {
+                    report(&file, &source, &diagnostics);
+                    std::process::exit(1);
+                }
+                Err(e) => {
+                    eprintln!("Error: {}", e);
+                    std::process::exit(1);
+                }
+            }
+        }
+        Commands::Explain { code } => match explain(&code) {
+            Some(explanation) => {
+                println!("{}: {}\n\n{}", explanation.code, explanation.title, explanation.text);
+            }
+            None => {
+                eprintln!("Error: no such diagnostic code `{}`", code);
+                std::process::exit(1);
+            }
+        },
+        Commands::ExplainSecrecy { file, position } => {
+            let source = read_source(&file);
+            let offset = position
+                .split_once(':')
+                .and_then(|(line, col)| Some((line.parse().ok()?, col.parse().ok()?)))
+                .and_then(|(line, col)| offset_of(&source, line, col));
+            let Some(offset) = offset else {
+                eprintln!("Error: `{}` is not a LINE:COL position in {}", position, file.display());
+                std::process::exit(2);
+            };
+            let provenance = match explain_secrecy(&source, offset) {
+                Ok(Some(provenance)) => provenance,
+                Ok(None) => {
+                    eprintln!("Error: no expression at {}", position);
+                    std::process::exit(1);
+                }
+                Err(e) => {
+                    report(&file, &source, &e.into_diagnostics());
+                    std::process::exit(1);
+                }
+            };
+            let (line, col) = provenance.span.line_col(&source);
+            println!(
+                "{}:{}:{}: `{}` is {}",
+                file.display(),
+                line,
+                col,
+                excerpt(&source, provenance.span),
+                label(provenance.secret)
+            );
+            for step in &provenance.chain {
+                let (line, col) = step.span.line_col(&source);
+                println!("  {}:{} `{}`: {}", line, col, excerpt(&source, step.span), step.reason);
+            }
+        }
+        Commands::Graph {
+            file,
+            view,
+            passes,
+            output,
+        } => {
+            let source = read_source(&file);
+            let dot = match view {
+                GraphView::Ast => {
+                    let parsed = Cst::parse(&source).map(|cst| graph::ast_dot(cst.ast()));
+                    parsed.map_err(|e| vec![*e])
+                }
+                GraphView::Ir => transpile_to_ir(&source)
+                    .map(|ir| graph::ir_dot(&ir))
+                    .map_err(TranspileError::into_diagnostics),
+                GraphView::Passes => {
+                    let mut pipeline = if passes.is_empty() {
+                        Pipeline::standard()
+                    } else {
+                        Pipeline::new()
+                    };
+                    for name in &passes {
+                        match builtin(name) {
+                            Some(pass) => pipeline.add(pass),
+                            None => {
+                                eprintln!("Error: unknown pass `{}`", name);
+                                std::process::exit(2);
+                            }
+                        }
+                    }
+                    transpile_to_ir(&source)
+                        .map(|ir| graph::passes_dot(&ir, &pipeline))
+                        .map_err(TranspileError::into_diagnostics)
+                }
+                GraphView::Flow => transpile_to_ir(&source)
+                    .map(|ir| graph::flow_dot(&ir))
+                    .map_err(TranspileError::into_diagnostics),
+            };
+            let dot = match dot {
+                Ok(dot) => dot,
+                Err(diagnostics) => {
+                    report(&file, &source, &diagnostics);
+                    std::process::exit(1);
+                }
+            };
+
+            match output {
+                Some(path) if path.extension().is_some_and(|ext| ext == "svg") => {
+                    if let Err(e) = render_svg(&dot, &path) {
+                        eprintln!("Error rendering {}: {}", path.display(), e);
+                        std::process::exit(1);
+                    }
+                    eprintln!("Wrote {}", path.display());
+                }
+                Some(path) => {
+                    if let Err(e) = fs::write(&path, &dot) {
+                        eprintln!("Error writing {}: {}", path.display(), e);
+                        std::process::exit(1);
+                    }
+                    eprintln!("Wrote {}", path.display());
+                }
+                None => print!("{}", dot),
+            }
+        }
+        Commands::Fmt { files, check } => {
+            let mut unformatted = false;
+
+            for file in &files {
+                // Formatted as written, keeping its `include` lines
+                let source = read_text(file);
+                let formatted = match format_source(&source) {
+                    Ok(formatted) => formatted,
+                    Err(e) => {
+                        eprintln!("{}: {}", file.display(), e);
+                        std::process::exit(1);
+                    }
+                };
+
+                if formatted == source {
+                    continue;
+                }
+                if check {
+                    println!("Diff in {}:\n{}", file.display(), diff(&source, &formatted));
+                    unformatted = true;
+                } else if let Err(e) = fs::write(file, &formatted) {
+                    eprintln!("Error writing {}: {}", file.display(), e);
+                    std::process::exit(1);
+                }
+            }
+
+            if unformatted {
+                std::process::exit(1);
+            }
+        }
+        Commands::Fix { files, check } => {
+            let mut unfixed = false;
+
+            for file in &files {
+                let unit = match Unit::resolve(file) {
+                    Ok(unit) => unit,
+                    Err(e) => {
+                        eprintln!("Error: {}", e);
+                        std::process::exit(1);
+                    }
+                };
+                // Fixes are made in the file each diagnostic lies in
+                let mut by_file: Vec<(_, Vec<Diagnostic>)> = Vec::new();
+                for diagnostic in check_with(&unit.source, &config.lints) {
+                    let (source_file, diagnostic) = unit.relocate(&diagnostic);
+                    match by_file.iter_mut().find(|(f, _)| f == &source_file) {
+                        Some((_, diagnostics)) => diagnostics.push(diagnostic),
+                        None => by_file.push((source_file, vec![diagnostic])),
+                    }
+                }
+
+                for (source_file, diagnostics) in by_file {
+                    let fixed = apply(&source_file.text, &diagnostics);
+                    if fixed.applied == 0 {
+                        continue;
+                    }
+                    let path = source_file.path.display();
+                    if check {
+                        println!("Fixes for {}:\n{}", path, diff(&source_file.text, &fixed.source));
+                        unfixed = true;
+                    } else if let Err(e) = fs::write(&source_file.path, &fixed.source) {
+                        eprintln!("Error writing {}: {}", path, e);
+                        std::process::exit(1);
+                    } else {
+                        println!("{}: {} fix(es) applied", path, fixed.applied);
+                    }
+                }
+            }
+
+            if unfixed {
+                std::process::exit(1);
+            }
+        }
+        Commands::Repl => {
+            let mut session = Session::new();
+            println!("obli repl (type :help for commands)");
+            let stdin = io::stdin();
+            loop {
+                print!("obli> ");
+                io::stdout().flush().ok();
+                let mut line = String::new();
+                match stdin.lock().read_line(&mut line) {
+                    Ok(0) => break,
+                    Ok(_) => {}
+                    Err(e) => {
+                        eprintln!("Error reading input: {}", e);
+                        std::process::exit(1);
+                    }
+                }
+                match session.handle(&line) {
+                    Reply::Print(text) if text.is_empty() => {}
+                    Reply::Print(text) => println!("{}", text),
+                    Reply::Quit => break,
+                }
+            }
+        }
+        Commands::Run {
+            file,
+            expr,
+            secrets,
+            publics,
+            witness,
+            native,
+            ct_check,
+            differential,
+        } => {
+            let (name, source) = match (file, expr) {
+                (_, Some(expr)) => (PathBuf::from("<expr>"), expr),
+                (Some(file), None) => {
+                    let source = read_source(&file);
+                    (file, source)
+                }
+                (None, None) => unreachable!("clap requires a file or --expr"),
+            };
+
+            let inputs = match run_inputs(&secrets, &publics, witness.as_deref()) {
+                Ok(inputs) => inputs,
+                Err(e) => {
+                    eprintln!("Error: {}", e);
+                    std::process::exit(2);
+                }
+            };
+            let result = if native {
+                run_native(&source, &inputs, &config).map(|output| print!("{}", output))
+            } else if ct_check {
+                ct_check_run(&name, &source, &inputs)
+            } else if differential {
+                differential_run(&source, &inputs)
+            } else {
+                interpreted_run(&source, &inputs, &config)
+            };
+            match result {
+                Ok(()) => {}
+                Err(RunError::Diagnostics(diagnostics)) => {
+                    report(&name, &source, &diagnostics);
+                    std::process::exit(1);
+                }
+                Err(e) => {
+                    eprintln!("Error: {}", e);
+                    std::process::exit(1);
+                }
+            }
+        }
+        Commands::Stats { file, format } => {
+            let source = read_source(&file);
+            let stats = match transpile_to_ir(&source) {
+                Ok(ir) => stats(&ir),
+                Err(e) => {
+                    report(&file, &source, &e.into_diagnostics());
+                    std::process::exit(1);
+                }
+            };
+            match format {
+                StatsFormat::Text => print!("{}", stats.report(COST_MODELS)),
+                StatsFormat::Json => println!("{}", stats.to_json(COST_MODELS)),
+            }
+        }
+        Commands::TimingHarness { file, classes } => {
+            let source = read_source(&file);
+            let classes: Result<Vec<_>, _> = classes
+                .iter()
+                .map(|class| run_inputs(&[], &[], Some(class)))
+                .collect();
+            let classes = match classes {
+                Ok(classes) => classes,
+                Err(e) => {
+                    eprintln!("Error: {}", e);
+                    std::process::exit(2);
+                }
+            };
+            match timing_harness(&source, &config, &classes) {
+                Ok(code) => print!("{}", code),
+                Err(RunError::Diagnostics(diagnostics)) => {
+                    report(&file, &source, &diagnostics);
+                    std::process::exit(1);
+                }
+                Err(e) => {
+                    eprintln!("Error: {}", e);
+                    std::process::exit(1);
+                }
+            }
+        }
+    }
+}
+
+/// Render diagnostics to stderr, in colour when it is a terminal.
+fn report(file: &Path, source: &str, diagnostics: &[Diagnostic]) {
+    let renderer = if io::stderr().is_terminal() && std::env::var_os("NO_COLOR").is_none() {
+        Renderer::colored()
+    } else {
+        Renderer::plain()
+    };
+    // Diagnostics in included files are shown in those files
+    let unit = Unit::resolve(file).ok().filter(|unit| unit.has_includes() && unit.source == source);
+    let name = file.display().to_string();
+    for diagnostic in diagnostics {
+        match &unit {
+            Some(unit) => {
+                let (file, diagnostic) = unit.relocate(diagnostic);
+                let name = file.path.display().to_string();
+                eprintln!("{}", renderer.render(&diagnostic, &name, &file.text));
+            }
+            None => eprintln!("{}", renderer.render(diagnostic, &name, source)),
+        }
+    }
+    if let Some(code) = diagnostics.iter().find_map(|d| d.code) {
+        eprintln!("For more information about a diagnostic, try `obli explain {}`.", code);
+    }
+}
+
+/// One watch-mode build: report diagnostics, then emit unless there are errors.
+fn rebuild(file: &Path, source: &str, output: Option<&Path>, config: &Config) {
+    let diagnostics = check_with(source, &config.lints);
+    report(file, source, &diagnostics);
+    if has_errors(&diagnostics) {
+        eprintln!("{}: build failed", file.display());
+        return;
+    }
+
+    match transpile_with(source, config) {
+        Ok(rust_code) => match output {
+            Some(output_path) => match fs::write(output_path, &rust_code) {
+                Ok(()) => {
+                    eprintln!("Wrote {}", output_path.display());
+                    write_schema(source, output_path);
+                }
+                Err(e) => eprintln!("Error writing {}: {}", output_path.display(), e),
+            },
+            None => println!("{}", rust_code),
+        },
+        Err(e) => report(file, source, &e.into_diagnostics()),
+    }
+}
+
+/// `obli run --ct-check`: interpret with the leakage monitor, warning about
+/// each secret-dependent operation.
+fn ct_check_run(file: &Path, source: &str, inputs: &Inputs) -> Result<(), RunError> {
+    let (ir, spans) = prepare_with_spans(source, inputs)?;
+    let checked = dynamic_ct_check_with_spans(&ir, &spans, inputs)?;
+    let warnings: Vec<_> = checked
+        .leaks
+        .iter()
+        .map(|leak| {
+            let diagnostic = Diagnostic::warning(leak.kind.to_string()).with_code(leak.kind.code());
+            match leak.span {
+                Some(span) => diagnostic.with_span(span).with_label(span, "secret at run time"),
+                None => diagnostic,
+            }
+        })
+        .collect();
+    report(file, source, &warnings);
+    eprintln!(
+        "{} of {} monitored operations depended on secrets",
+        checked.leaks.len(),
+        checked.monitored
+    );
+    println!("Result: {}", checked.result.value);
+    Ok(())
+}
+
+/// Run `source` with the reference interpreter, printing its result as the
+/// emitted program would: `Result: v`, or `name: v` for each named output.
+fn interpreted_run(source: &str, inputs: &Inputs, config: &Config) -> Result<(), RunError> {
+    let ir = prepare(source, inputs, config)?;
+    if ir.outputs().is_some() {
+        for (name, output) in eval_ir_outputs(&ir, inputs)? {
+            println!("{}: {}", name, output.value);
+        }
+    } else {
+        println!("Result: {}", eval_ir(&ir, inputs)?.value);
+    }
+    Ok(())
+}
+
+/// Run `source` on the interpreters and every back end, exiting on the first
+/// disagreement.
+fn differential_run(source: &str, inputs: &Inputs) -> Result<(), RunError> {
+    let outcome = match check_backends(source, inputs, BACKENDS) {
+        Ok(outcome) => outcome,
+        Err(DifferentialError::Run(e)) => return Err(e),
+        Err(e) => {
+            eprintln!("Mismatch: {}", e);
+            std::process::exit(1);
+        }
+    };
+    eprintln!("interpreters and back ends agree ({})", BACKENDS.join(", "));
+    print!("{}", outcome?.printed());
+    Ok(())
+}
+
+/// Collect `obli run` inputs from a witness file and `--secret`/`--public`
+/// flags (flags win over the witness).
+fn run_inputs(
+    secrets: &[String],
+    publics: &[String],
+    witness: Option<&Path>,
+) -> Result<Inputs, RunError> {
+    let mut inputs = Inputs::new();
+    if let Some(path) = witness {
+        let json = fs::read_to_string(path).map_err(|source| RunError::Io {
+            context: format!("cannot read {}", path.display()),
+            source,
+        })?;
+        inputs = parse_witness(&json, inputs)?;
+    }
+    for assignment in secrets {
+        let (name, value) = parse_assignment(assignment)?;
+        inputs = inputs.secret(&name, value);
+    }
+    for assignment in publics {
+        let (name, value) = parse_assignment(assignment)?;
+        inputs = inputs.public(&name, value);
+    }
+    Ok(inputs)
+}
+
+const DEBUG_HELP: &str = "\
+Commands:
+  s, step          evaluate the next sub-expression
+  n, next          step over the current expression
+  c, continue      run to the next breakpoint
+  b LINE[:COL]     break on the expressions starting there (rest of the line)
+  p, env           print the variables in scope with their labels
+  w, where         show the current expression
+  q, quit          leave the debugger";
+
+/// Interactive `obli debug` loop on stdin.
+fn debug_session(source: &str, mut debugger: Debugger) {
+    println!("Type `help` for commands.");
+    show_stop(source, debugger.current());
+    let stdin = io::stdin();
+    loop {
+        print!("(debug) ");
+        let _ = io::stdout().flush();
+        let mut line = String::new();
+        if stdin.lock().read_line(&mut line).unwrap_or(0) == 0 {
+            break;
+        }
+        let mut words = line.split_whitespace();
+        match (words.next(), words.next()) {
+            (None, _) => {}
+            (Some("s" | "step"), _) => show_stop(source, debugger.step()),
+            (Some("n" | "next"), _) => show_stop(source, debugger.step_over()),
+            (Some("c" | "continue"), _) => show_stop(source, debugger.resume()),
+            (Some("w" | "where"), _) => show_stop(source, debugger.current()),
+            (Some("b" | "break"), Some(position)) => match line_breakpoint(source, position) {
+                Some(span) => {
+                    debugger.add_breakpoint(span);
+                    let (line, col) = span.line_col(source);
+                    println!("Breakpoint at {}:{}", line, col);
+                }
+                None => println!("`{}` is not a LINE[:COL] position", position),
+            },
+            (Some("p" | "env"), _) => match debugger.current() {
+                Some(stop) if stop.scope.is_empty() => println!("No variables in scope"),
+                Some(stop) => {
+                    for (name, value) in &stop.scope {
+                        println!("  {} = {} ({})", name, value.value, label(value.secret));
+                    }
+                }
+                None => println!("The program has finished"),
+            },
+            (Some("q" | "quit"), _) => break,
+            _ => println!("{}", DEBUG_HELP),
+        }
+    }
+}
+
+fn show_stop(source: &str, stop: Option<&Stop>) {
+    let Some(stop) = stop else {
+        println!("Program finished");
+        return;
+    };
+    let (line, col) = stop.span.line_col(source);
+    let indent = "  ".repeat(stop.depth);
+    match &stop.value {
+        None => println!("{}:{} {}> `{}`", line, col, indent, excerpt(source, stop.span)),
+        Some(value) => println!(
+            "{}:{} {}< `{}` = {} ({})",
+            line,
+            col,
+            indent,
+            excerpt(source, stop.span),
+            value.value,
+            label(value.secret)
+        ),
+    }
+}
+
+fn label(secret: bool) -> &'static str {
+    if secret {
+        "secret"
+    } else {
+        "public"
+    }
+}
+
+/// Breakpoint span from `LINE[:COL]` up to the end of that line.
+fn line_breakpoint(source: &str, position: &str) -> Option<Span> {
+    let (line, col) = match position.split_once(':') {
+        Some((line, col)) => (line.parse().ok()?, col.parse().ok()?),
+        None => (position.parse().ok()?, 1),
+    };
+    let start = offset_of(source, line, col)?;
+    let end = source[start..].find('\n').map_or(source.len(), |n| start + n);
+    Some(Span::new(start, end.max(start + 1)))
+}
+
+/// Build source roots (directories or single files) into `out_dir`.
+fn build_roots(roots: &[PathBuf], out_dir: &Path, config: &Config, timings: bool) {
+    let mut built = BuildReport::default();
+    for root in roots {
+        let result = if root.is_dir() {
+            build_dir(root, out_dir, config)
+        } else {
+            let name = root.file_name().map(PathBuf::from).unwrap_or_default();
+            let output = out_dir.join(name).with_extension("rs");
+            build_file(root, &output, config).map(|file| BuildReport { files: vec![file] })
+        };
+        match result {
+            Ok(report) => built.files.extend(report.files),
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    for file in &built.files {
+        report(&file.source_path, &file.source, &file.diagnostics);
+    }
+    let failed = built.failed();
+    eprintln!(
+        "Built {} of {} files into {}",
+        built.files.len() - failed,
+        built.files.len(),
+        out_dir.display()
+    );
+    if timings {
+        eprint!("Timings:\n{}", built.timings());
+    }
+    if failed > 0 {
+        std::process::exit(1);
+    }
+}
+
+/// Render DOT to an SVG file with Graphviz's `dot`.
+fn render_svg(dot: &str, path: &Path) -> io::Result<()> {
+    let mut child = Command::new("dot")
+        .arg("-Tsvg")
+        .arg("-o")
+        .arg(path)
+        .stdin(Stdio::piped())
+        .spawn()
+        .map_err(|e| io::Error::new(e.kind(), format!("cannot run Graphviz `dot`: {}", e)))?;
+    child
+        .stdin
+        .take()
+        .expect("stdin is piped")
+        .write_all(dot.as_bytes())?;
+    let status = child.wait()?;
+    if !status.success() {
+        return Err(io::Error::other(format!("`dot` exited with {}", status)));
+    }
+    Ok(())
+}
+
+/// Load the `--config` file, or the nearest obli.toml; defaults if there is none.
+fn load_config(path: Option<&Path>) -> Config {
+    let path = match path {
+        Some(path) => path.to_path_buf(),
+        None => match std::env::current_dir().ok().and_then(|dir| Config::discover(&dir)) {
+            Some(path) => path,
+            None => return Config::default(),
+        },
+    };
+    match Config::from_path(&path) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("Error in {}: {}", path.display(), e);
+            std::process::exit(2);
+        }
+    }
+}
+
+/// Source text of `span` on one line, shortened if long.
+fn excerpt(source: &str, span: Span) -> String {
+    let text = source.get(span.start..span.end).unwrap_or("");
+    let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    if text.chars().count() > 40 {
+        format!("{}…", text.chars().take(39).collect::<String>())
+    } else {
+        text
+    }
+}
+
+/// Read a source file, exiting with a diagnostic on failure.
+/// Transpile into a temporary file next to `path`, renamed over it once
+/// the code is complete, so errors leave an existing output in place.
+fn stream_to_file(
+    source: &str,
+    config: &Config,
+    timings: &mut Timings,
+    path: &Path,
+) -> Result<(), TranspileError> {
+    let partial = path.with_extension("rs.partial");
+    let written = fs::File::create(&partial).map_err(TranspileError::from).and_then(|file| {
+        let mut out = io::BufWriter::new(file);
+        transpile_to(source, config, timings, &mut out)?;
+        Ok(out.flush()?)
+    });
+    match written {
+        Ok(()) => Ok(fs::rename(&partial, path)?),
+        Err(e) => {
+            let _ = fs::remove_file(&partial);
+            Err(e)
+        }
+    }
+}
+
+/// Write the schema of `source` next to its generated code at `output`.
+fn write_schema(source: &str, output: &Path) {
+    let Ok(schema) = Schema::of(source) else { return };
+    let path = output.with_extension(SCHEMA_EXTENSION);
+    match fs::write(&path, schema.to_json()) {
+        Ok(()) => eprintln!("Wrote {}", path.display()),
+        Err(e) => eprintln!("Error writing {}: {}", path.display(), e),
+    }
+}
+
+/// Read a file as written, exiting on failure.
+fn read_text(path: &Path) -> String {
+    match fs::read_to_string(path) {
+        Ok(s) => s,
+        Err(e) => {
+            eprintln!("Error reading {}: {}", path.display(), e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Read a source file through the frontend for its extension, with its
+/// includes expanded, exiting on failure.
+fn read_source(path: &Path) -> String {
+    match load_unit(path) {
+        Ok(unit) => unit.source,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Read a source file through the frontend for its extension, with its
+/// includes expanded.
+fn load_unit(path: &Path) -> Result<Unit, String> {
+    let text =
+        fs::read_to_string(path).map_err(|e| format!("cannot read {}: {}", path.display(), e))?;
+    let source = Registry::standard()
+        .to_source(path, &text)
+        .map_err(|e| format!("{}: {}", path.display(), e))?;
+    Unit::from_source(path, source).map_err(|e| e.to_string())
+}
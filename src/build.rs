@@ -0,0 +1,588 @@
+// SPDX-License-Identifier: MIT OR AGPL-3.0-or-later
+// SPDX-FileCopyrightText: 2024-2025 hyperpolymath
+
+//! Multi-file build driver.
+//!
+//! [`build_dir`] transpiles every `.mobli` file under a source directory
+//! into a mirrored tree of `.rs` files, checking, transforming and emitting
+//! files in parallel on the rayon thread pool. Each file is compiled as its
+//! own [`Unit`], with the files it `include`s expanded in place, so files
+//! are independent of one another and need no dependency ordering; results
+//! are collected back in path order, so reports and output do not depend
+//! on scheduling.
+//!
+//! [`ObliBuild`] is the entry point for `build.rs` scripts:
+//!
+//! ```no_run
+//! // build.rs
+//! obli_transpiler::build::ObliBuild::new()
+//!     .file("src/kernel.mobli")
+//!     .library_mode()
+//!     .compile()
+//!     .unwrap();
+//! ```
+//!
+//! ```ignore
+//! // src/lib.rs
+//! mod kernel {
+//!     include!(concat!(env!("OUT_DIR"), "/kernel.rs"));
+//! }
+//! ```
+
+use crate::check::check_with;
+use crate::codes;
+use crate::config::Config;
+use crate::diagnostic::{has_errors, Diagnostic, Renderer};
+use crate::embed::embed;
+use crate::frontend::Registry;
+use crate::include::Unit;
+use crate::report::{transpile_with_report, REPORT_EXTENSION};
+use crate::schema::{Schema, SCHEMA_EXTENSION};
+use crate::timings::Timings;
+use crate::transpile_with;
+use rayon::prelude::*;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+/// Extension of MiniObli source files.
+pub const SOURCE_EXTENSION: &str = "mobli";
+
+#[derive(Error, Debug)]
+#[error("{}: {source}", path.display())]
+pub struct BuildError {
+    pub path: PathBuf,
+    #[source]
+    pub source: io::Error,
+}
+
+/// Outcome of building one file.
+#[derive(Debug)]
+pub struct FileReport {
+    pub source_path: PathBuf,
+    /// The source text, for rendering diagnostics.
+    pub source: String,
+    /// Written output, or `None` if the file had errors.
+    pub output: Option<PathBuf>,
+    pub diagnostics: Vec<Diagnostic>,
+    pub timings: Timings,
+}
+
+/// Outcome of a directory build, one entry per source file in path order.
+#[derive(Debug, Default)]
+pub struct BuildReport {
+    pub files: Vec<FileReport>,
+}
+
+impl BuildReport {
+    /// Number of files that failed to build.
+    pub fn failed(&self) -> usize {
+        self.files.iter().filter(|f| f.output.is_none()).count()
+    }
+
+    /// Stage timings summed over every file.
+    pub fn timings(&self) -> Timings {
+        let mut total = Timings::new();
+        for file in &self.files {
+            total.merge(&file.timings);
+        }
+        total
+    }
+}
+
+/// Every source file under `dir`, recursively, in path order.
+pub fn find_sources(dir: &Path) -> Result<Vec<PathBuf>, BuildError> {
+    let mut sources = Vec::new();
+    collect_sources(dir, &mut sources)?;
+    sources.sort();
+    Ok(sources)
+}
+
+fn collect_sources(dir: &Path, sources: &mut Vec<PathBuf>) -> Result<(), BuildError> {
+    let error = |source| BuildError {
+        path: dir.to_path_buf(),
+        source,
+    };
+    for entry in fs::read_dir(dir).map_err(error)? {
+        let path = entry.map_err(error)?.path();
+        if path.is_dir() {
+            collect_sources(&path, sources)?;
+        } else if path.extension().is_some_and(|ext| ext == SOURCE_EXTENSION) {
+            sources.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Transpile every source file under `src_dir` into `out_dir`, keeping the
+/// directory structure (`src/a/b.mobli` becomes `out/a/b.rs`).
+///
+/// Files with errors are reported but do not stop the build; I/O errors do.
+pub fn build_dir(
+    src_dir: &Path,
+    out_dir: &Path,
+    config: &Config,
+) -> Result<BuildReport, BuildError> {
+    let sources = find_sources(src_dir)?;
+    let results: Vec<Result<FileReport, BuildError>> = sources
+        .par_iter()
+        .map(|path| {
+            let relative = path.strip_prefix(src_dir).unwrap_or(path);
+            build_file(path, &out_dir.join(relative).with_extension("rs"), config)
+        })
+        .collect();
+    let files = results.into_iter().collect::<Result<_, _>>()?;
+    Ok(BuildReport { files })
+}
+
+/// Check and transpile one file, read by the [frontend](Registry::to_source)
+/// for its extension, writing `output` if it has no errors, and its
+/// compilation report alongside if `config.report` is set.
+pub fn build_file(path: &Path, output: &Path, config: &Config) -> Result<FileReport, BuildError> {
+    let io_error = |path: &Path| {
+        let path = path.to_path_buf();
+        move |source| BuildError { path, source }
+    };
+    let text = fs::read_to_string(path).map_err(io_error(path))?;
+    let text = match Registry::standard().to_source(path, &text) {
+        Ok(source) => source,
+        Err(e) => {
+            return Ok(FileReport {
+                source_path: path.to_path_buf(),
+                source: text,
+                output: None,
+                diagnostics: vec![Diagnostic::from(e)],
+                timings: Timings::new(),
+            })
+        }
+    };
+    let (source, mut diagnostics) = match Unit::from_source(path, text.clone()) {
+        Ok(unit) => (unit.source, Vec::new()),
+        Err(e) => (text, vec![Diagnostic::from(e)]),
+    };
+    let mut timings = Timings::new();
+    diagnostics.extend(timings.time("check", || check_with(&source, &config.lints)));
+    let mut written = None;
+    if !has_errors(&diagnostics) {
+        match transpile_with_report(&source, config, &mut timings) {
+            Ok((code, report)) => {
+                if let Some(parent) = output.parent() {
+                    fs::create_dir_all(parent).map_err(io_error(parent))?;
+                }
+                fs::write(output, code).map_err(io_error(output))?;
+                if config.report {
+                    let path = output.with_extension(REPORT_EXTENSION);
+                    fs::write(&path, report.to_json(&source)).map_err(io_error(&path))?;
+                }
+                if let Ok(schema) = Schema::of(&source) {
+                    let path = output.with_extension(SCHEMA_EXTENSION);
+                    fs::write(&path, schema.to_json()).map_err(io_error(&path))?;
+                }
+                written = Some(output.to_path_buf());
+            }
+            Err(e) => diagnostics.extend(e.into_diagnostics()),
+        }
+    }
+    Ok(FileReport {
+        source_path: path.to_path_buf(),
+        source,
+        output: written,
+        diagnostics,
+        timings,
+    })
+}
+
+#[derive(Error, Debug)]
+pub enum ObliBuildError {
+    #[error("OUT_DIR is not set: run from a build script or call `out_dir`")]
+    NoOutDir,
+    #[error(transparent)]
+    Io(#[from] BuildError),
+    #[error("{}", render_errors(path, text, diagnostics))]
+    Program {
+        path: PathBuf,
+        text: String,
+        diagnostics: Vec<Diagnostic>,
+    },
+}
+
+fn render_errors(path: &Path, text: &str, diagnostics: &[Diagnostic]) -> String {
+    let renderer = Renderer::plain();
+    let file = path.display().to_string();
+    let rendered: Vec<_> = diagnostics.iter().map(|d| renderer.render(d, &file, text)).collect();
+    rendered.join("\n")
+}
+
+/// Transpiles MiniObli files from a build script into `OUT_DIR`, one
+/// `<stem>.rs` per file, and tells Cargo to rerun when a file changes.
+///
+/// By default each output is a complete program with `main`. In library
+/// mode it is instead a private module plus `pub fn <stem>(...)`, whose
+/// parameters are the program's free variables (see [`crate::embed`]),
+/// for `include!` into a crate. Batch mode adds `pub fn <stem>_batch`,
+/// which takes a slice per parameter and returns every record's result.
+#[derive(Debug, Clone, Default)]
+pub struct ObliBuild {
+    files: Vec<PathBuf>,
+    library: bool,
+    batch: bool,
+    config: Config,
+    out_dir: Option<PathBuf>,
+}
+
+impl ObliBuild {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a source file.
+    pub fn file(&mut self, path: impl AsRef<Path>) -> &mut Self {
+        self.files.push(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// Generate a function per file instead of a program.
+    pub fn library_mode(&mut self) -> &mut Self {
+        self.library = true;
+        self
+    }
+
+    /// Library mode, plus a function over a batch of records per file:
+    /// `<stem>_batch(a: &[i64], b: &[bool]) -> Vec<i64>` for
+    /// `<stem>(a: i64, b: bool) -> i64`. Files without free variables
+    /// have nothing to batch and get none.
+    pub fn batch_mode(&mut self) -> &mut Self {
+        self.library = true;
+        self.batch = true;
+        self
+    }
+
+    /// Use the passes, lints and emitter options of `config`.
+    pub fn config(&mut self, config: Config) -> &mut Self {
+        self.config = config;
+        self
+    }
+
+    /// Write to `dir` instead of `OUT_DIR`.
+    pub fn out_dir(&mut self, dir: impl AsRef<Path>) -> &mut Self {
+        self.out_dir = Some(dir.as_ref().to_path_buf());
+        self
+    }
+
+    /// Transpile every file, returning the paths written. Files are
+    /// transpiled in parallel, then written in the order they were added:
+    /// warnings are passed on to Cargo and the first file with errors
+    /// fails the build.
+    pub fn compile(&self) -> Result<Vec<PathBuf>, ObliBuildError> {
+        let out_dir = match &self.out_dir {
+            Some(dir) => dir.clone(),
+            None => env::var_os("OUT_DIR").map(PathBuf::from).ok_or(ObliBuildError::NoOutDir)?,
+        };
+        let io_error = |path: &Path| {
+            let path = path.to_path_buf();
+            move |source| BuildError { path, source }
+        };
+        fs::create_dir_all(&out_dir).map_err(io_error(&out_dir))?;
+
+        for path in &self.files {
+            println!("cargo:rerun-if-changed={}", path.display());
+        }
+        let generated: Vec<_> = self.files.par_iter().map(|path| self.generate(path)).collect();
+        let mut written = Vec::new();
+        for (path, generated) in self.files.iter().zip(generated) {
+            let Generated {
+                text,
+                included,
+                code,
+                diagnostics,
+            } = generated?;
+            for path in included {
+                println!("cargo:rerun-if-changed={}", path.display());
+            }
+            let Some(code) = code else {
+                let diagnostics = diagnostics.into_iter().filter(Diagnostic::is_error).collect();
+                return Err(ObliBuildError::Program {
+                    path: path.clone(),
+                    text,
+                    diagnostics,
+                });
+            };
+            for warning in &diagnostics {
+                println!("cargo:warning={}: {}", path.display(), warning);
+            }
+            let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+            let output = out_dir.join(format!("{}.rs", stem));
+            fs::write(&output, code).map_err(io_error(&output))?;
+            if let Ok(schema) = Schema::of(&text) {
+                let path = output.with_extension(SCHEMA_EXTENSION);
+                fs::write(&path, schema.to_json()).map_err(io_error(&path))?;
+            }
+            written.push(output);
+        }
+        Ok(written)
+    }
+
+    /// Read and transpile one file.
+    fn generate(&self, path: &Path) -> Result<Generated, BuildError> {
+        let text = fs::read_to_string(path).map_err(|source| BuildError {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        let text = match Registry::standard().to_source(path, &text) {
+            Ok(source) => source,
+            Err(e) => {
+                return Ok(Generated {
+                    text,
+                    diagnostics: vec![Diagnostic::from(e)],
+                    code: None,
+                    included: Vec::new(),
+                })
+            }
+        };
+        let (text, mut diagnostics, included) = match Unit::from_source(path, text.clone()) {
+            Ok(unit) => {
+                let included = unit.files[1..].iter().map(|file| file.path.clone()).collect();
+                (unit.source, Vec::new(), included)
+            }
+            Err(e) => (text, vec![Diagnostic::from(e)], Vec::new()),
+        };
+        let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+        diagnostics.extend(check_with(&text, &self.config.lints));
+        if self.library {
+            // Free variables are the generated function's parameters
+            diagnostics.retain(|d| d.code != Some(codes::UNBOUND_VARIABLE));
+        }
+        let code = if has_errors(&diagnostics) {
+            None
+        } else if self.library {
+            match embed(&text, &self.config) {
+                Ok(embedded) => {
+                    let name = rust_ident(&stem);
+                    let mut code = format!(
+                        "{}\n/// Generated from `{}`.\npub fn {}({}) -> {} {{\n    \
+                         obli_embedded::run({})\n}}\n",
+                        embedded.module,
+                        path.display(),
+                        name,
+                        embedded.params(),
+                        embedded.ty,
+                        embedded.args()
+                    );
+                    if self.batch && !embedded.captures.is_empty() {
+                        code.push_str(&format!(
+                            "\n/// [`{}`] on every record of a batch.\n\
+                             pub fn {}_batch({}) -> Vec<{}> {{\n    \
+                             obli_embedded::run_batch({})\n}}\n",
+                            name,
+                            name,
+                            embedded.batch_params(),
+                            embedded.ty,
+                            embedded.args()
+                        ));
+                    }
+                    Some(code)
+                }
+                Err(errors) => {
+                    diagnostics.extend(errors);
+                    None
+                }
+            }
+        } else {
+            match transpile_with(&text, &self.config) {
+                Ok(code) => Some(code),
+                Err(e) => {
+                    diagnostics.extend(e.into_diagnostics());
+                    None
+                }
+            }
+        };
+        Ok(Generated {
+            text,
+            included,
+            code,
+            diagnostics,
+        })
+    }
+}
+
+/// One file transpiled by [`ObliBuild`].
+struct Generated {
+    text: String,
+    /// Files included by the source file.
+    included: Vec<PathBuf>,
+    /// The generated code, unless the file has errors.
+    code: Option<String>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+/// `stem` with every character that cannot appear in a Rust identifier
+/// replaced by `_`.
+fn rust_ident(stem: &str) -> String {
+    let ident: String = stem
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '_' { c } else { '_' })
+        .collect();
+    match ident.chars().next() {
+        Some(c) if !c.is_ascii_digit() => ident,
+        _ => format!("_{}", ident),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("obli-build-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("src/nested")).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_build_dir_mirrors_tree() {
+        let dir = temp_dir("mirror");
+        fs::write(dir.join("src/a.mobli"), "1 + 2").unwrap();
+        fs::write(dir.join("src/nested/b.mobli"), "secret(1) * 2").unwrap();
+        fs::write(dir.join("src/notes.txt"), "not a source").unwrap();
+
+        let config = Config {
+            report: true,
+            ..Config::default()
+        };
+        let report = build_dir(&dir.join("src"), &dir.join("out"), &config).unwrap();
+        assert_eq!(report.files.len(), 2);
+        assert_eq!(report.failed(), 0);
+        assert!(dir.join("out/a.rs").is_file());
+        assert!(dir.join("out/a.report.json").is_file());
+        assert!(fs::read_to_string(dir.join("out/nested/b.rs")).unwrap().contains("ct_mul"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_failed_file_not_written() {
+        let dir = temp_dir("failed");
+        fs::write(dir.join("src/good.mobli"), "1").unwrap();
+        fs::write(dir.join("src/bad.mobli"), "1 + true").unwrap();
+
+        let report = build_dir(&dir.join("src"), &dir.join("out"), &Config::default()).unwrap();
+        assert_eq!(report.failed(), 1);
+        assert!(report.files[0].source_path.ends_with("bad.mobli"));
+        assert!(!report.files[0].diagnostics.is_empty());
+        assert!(!dir.join("out/bad.rs").exists());
+        assert!(dir.join("out/good.rs").is_file());
+        assert!(!dir.join("out/good.report.json").exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_build_file_reads_other_frontends() {
+        let dir = temp_dir("frontend");
+        let json = r#"{"version": 1, "body": {"binop": "*", "left": {"secret": 6}, "right": 7}}"#;
+        fs::write(dir.join("src/kernel.json"), json).unwrap();
+        let config = Config::default();
+        let file =
+            build_file(&dir.join("src/kernel.json"), &dir.join("out/kernel.rs"), &config).unwrap();
+        assert!(file.diagnostics.is_empty(), "{:?}", file.diagnostics);
+        assert_eq!(file.source, "secret(6) * 7");
+        assert!(fs::read_to_string(dir.join("out/kernel.rs")).unwrap().contains("ct_mul"));
+
+        fs::write(dir.join("src/bad.json"), "{").unwrap();
+        let file = build_file(&dir.join("src/bad.json"), &dir.join("out/bad.rs"), &config).unwrap();
+        assert!(has_errors(&file.diagnostics));
+        assert!(file.output.is_none());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_obli_build_library_mode() {
+        let dir = temp_dir("library");
+        let source = dir.join("src/double-it.mobli");
+        fs::write(&source, "let x = secret(input) if x > 0 then x * 2 else 0").unwrap();
+        let written = ObliBuild::new()
+            .file(&source)
+            .library_mode()
+            .out_dir(dir.join("out"))
+            .compile()
+            .unwrap();
+        assert_eq!(written, vec![dir.join("out/double-it.rs")]);
+
+        let code = fs::read_to_string(&written[0]).unwrap();
+        assert!(code.contains("pub fn double_it(input: i64) -> i64 {\n"));
+        let main = format!(
+            "mod kernel {{\n{}}}\nfn main() {{\n    \
+             println!(\"{{}}\", kernel::double_it(21));\n}}\n",
+            code
+        );
+        assert_eq!(crate::run::run_emitted(&main).unwrap(), "42\n");
+
+        let schema = Schema::load(dir.join("out/double-it.schema.json")).unwrap();
+        assert_eq!(schema.input("input").unwrap().label, crate::schema::Label::Secret);
+        assert_eq!(schema.outputs[0].name, "result");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_obli_build_batch_mode() {
+        let dir = temp_dir("batch");
+        let source = dir.join("src/score.mobli");
+        fs::write(&source, "let x = secret(input) if x > limit then limit else x").unwrap();
+        let written = ObliBuild::new()
+            .file(&source)
+            .batch_mode()
+            .out_dir(dir.join("out"))
+            .compile()
+            .unwrap();
+
+        let code = fs::read_to_string(&written[0]).unwrap();
+        assert!(code.contains("pub fn score_batch(input: &[i64], limit: &[i64]) -> Vec<i64> {\n"));
+        let main = format!(
+            "mod kernel {{\n{}}}\nfn main() {{\n    \
+             println!(\"{{:?}}\", kernel::score_batch(&[3, 9, -4], &[5, 5, 0]));\n}}\n",
+            code
+        );
+        assert_eq!(crate::run::run_emitted(&main).unwrap(), "[3, 5, -4]\n");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_obli_build_keeps_file_order() {
+        let dir = temp_dir("order");
+        let sources: Vec<_> = (0..16).map(|i| dir.join(format!("src/k{}.mobli", i))).collect();
+        for (i, source) in sources.iter().enumerate() {
+            fs::write(source, format!("secret({}) * 2", i)).unwrap();
+        }
+        let mut build = ObliBuild::new();
+        build.out_dir(dir.join("out"));
+        for source in &sources {
+            build.file(source);
+        }
+        let written = build.compile().unwrap();
+        let expected: Vec<_> = (0..16).map(|i| dir.join(format!("out/k{}.rs", i))).collect();
+        assert_eq!(written, expected);
+
+        // The first failing file in order is reported, whichever finishes first
+        fs::write(&sources[3], "1 + true").unwrap();
+        fs::write(&sources[9], "let").unwrap();
+        let err = build.compile().unwrap_err();
+        assert!(matches!(&err, ObliBuildError::Program { path, .. } if *path == sources[3]));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_obli_build_reports_errors() {
+        let dir = temp_dir("errors");
+        let source = dir.join("src/bad.mobli");
+        fs::write(&source, "1 + true").unwrap();
+        let err = ObliBuild::new().file(&source).out_dir(dir.join("out")).compile().unwrap_err();
+        let ObliBuildError::Program { diagnostics, .. } = &err else {
+            panic!("expected program errors, got {}", err);
+        };
+        assert_eq!(diagnostics[0].code, Some(crate::codes::TYPE_MISMATCH));
+        assert!(err.to_string().contains("bad.mobli"));
+        assert!(!dir.join("out/bad.rs").exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,145 @@
+// SPDX-License-Identifier: MIT OR AGPL-3.0-or-later
+// SPDX-FileCopyrightText: 2024-2025 hyperpolymath
+
+//! Analysis-only pipeline.
+//!
+//! Runs every front- and middle-end stage (lex, parse, typecheck, transform,
+//! IR verification, leakage analysis, lints) without emitting code, collecting
+//! span-carrying diagnostics instead of stopping at the first failure.
+//! Given a [`Policy`], it also rejects reveals the policy does not cover.
+//! Diagnostics that can be fixed automatically carry
+//! [suggestions](crate::fix).
+
+use crate::analysis::leakage_with_spans;
+use crate::diagnostic::Diagnostic;
+use crate::emit::check_emittable;
+use crate::fix;
+use crate::lexer::Lexer;
+use crate::lint::{lint, LintConfig};
+use crate::parser::Parser;
+use crate::policy::Policy;
+use crate::stack;
+use crate::transform::to_oblivious_with_spans;
+use crate::typeck::typecheck_with_spans;
+use crate::verify::verify_with_spans;
+
+/// Check MiniObli source code, returning all diagnostics (empty if clean).
+pub fn check(source: &str) -> Vec<Diagnostic> {
+    check_with(source, &LintConfig::default())
+}
+
+/// Check MiniObli source code with the given lint levels.
+pub fn check_with(source: &str, lints: &LintConfig) -> Vec<Diagnostic> {
+    check_with_policy(source, lints, None)
+}
+
+/// Check MiniObli source code with the given lint levels and, if given, a
+/// declassification policy.
+pub fn check_with_policy(
+    source: &str,
+    lints: &LintConfig,
+    policy: Option<&Policy>,
+) -> Vec<Diagnostic> {
+    let mut diagnostics = diagnose(source, lints, policy);
+    fix::suggest(source, &mut diagnostics);
+    diagnostics
+}
+
+fn diagnose(source: &str, lints: &LintConfig, policy: Option<&Policy>) -> Vec<Diagnostic> {
+    let (tokens, token_spans) = match Lexer::new(source).tokenize() {
+        Ok(lexed) => lexed,
+        Err(e) => return vec![e.into()],
+    };
+
+    let mut parser = Parser::with_spans(&tokens, &token_spans);
+    let (ast, ast_spans) = match parser.parse_with_spans() {
+        Ok(parsed) => parsed,
+        Err(e) => return vec![e.into()],
+    };
+
+    if let Err(errors) = typecheck_with_spans(&ast, &ast_spans) {
+        return errors.into_iter().map(Diagnostic::from).collect();
+    }
+
+    let (ir, ir_spans) = to_oblivious_with_spans(&ast, &ast_spans);
+
+    let mut diagnostics: Vec<_> = verify_with_spans(&ir, &ir_spans)
+        .into_iter()
+        .map(Diagnostic::from)
+        .collect();
+    diagnostics.extend(check_emittable(&ir, &ir_spans).into_iter().map(Diagnostic::from));
+
+    diagnostics.extend(leakage_with_spans(&ir, &ir_spans).into_iter().map(|leak| {
+        let diagnostic = Diagnostic::warning(leak.kind.to_string()).with_code(leak.kind.code());
+        match leak.span {
+            Some(span) => diagnostic.with_span(span).with_label(span, "divisor is secret"),
+            None => diagnostic,
+        }
+    }));
+
+    diagnostics.extend(lint(&ir, &ir_spans, lints));
+    if let Some(policy) = policy {
+        let violations = policy.check(&ast, &ast_spans, &ir);
+        diagnostics.extend(violations.into_iter().map(Diagnostic::from));
+    }
+
+    stack::drop_tree((ast, ir));
+    // Every copy of an unrolled loop body reports the same problems
+    let mut unique = Vec::with_capacity(diagnostics.len());
+    for diagnostic in diagnostics {
+        if !unique.contains(&diagnostic) {
+            unique.push(diagnostic);
+        }
+    }
+    unique
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diagnostic::Severity;
+    use crate::span::Span;
+
+    #[test]
+    fn test_clean_program() {
+        assert_eq!(check("let x = secret(1) if x > 0 then secret(1) else secret(0)"), vec![]);
+    }
+
+    #[test]
+    fn test_lex_error_has_span() {
+        let diagnostics = check("1 + $");
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].span, Some(Span::new(4, 5)));
+    }
+
+    #[test]
+    fn test_type_error_has_span() {
+        let diagnostics = check("if 1 then 2 else 3");
+        assert_eq!(diagnostics.len(), 1);
+        assert!(diagnostics[0].is_error());
+        assert_eq!(diagnostics[0].span, Some(Span::new(3, 4)));
+    }
+
+    #[test]
+    fn test_policy_violation() {
+        let policy = Policy::parse("[declassify.total]").unwrap();
+        let source = "let k = secret(2) outputs(total = k, twice = k * 2)";
+        let diagnostics = check_with_policy(source, &LintConfig::default(), Some(&policy));
+        assert_eq!(diagnostics.len(), 1);
+        assert!(diagnostics[0].is_error());
+        assert_eq!(diagnostics[0].span, Some(Span::new(45, 50)));
+    }
+
+    #[test]
+    fn test_leak_warning_has_span() {
+        let source = "let k = secret(9) 100 / k";
+        let diagnostics = check(source);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].severity, Severity::Warning);
+        let span = diagnostics[0].span.unwrap();
+        assert_eq!(&source[span.start..span.end], "100 / k");
+
+        let source = "let k = secret(9) for i in 0..4 with _q = 0 do i + 100 / k";
+        assert_eq!(check(source).len(), 1);
+    }
+}
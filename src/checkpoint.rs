@@ -0,0 +1,151 @@
+// SPDX-License-Identifier: MIT OR AGPL-3.0-or-later
+// SPDX-FileCopyrightText: 2024-2025 hyperpolymath
+
+//! Checkpointing pipeline stages (feature `serde`).
+//!
+//! With the feature enabled, every artifact the pipeline passes between
+//! stages implements `serde::Serialize` and `serde::Deserialize`: tokens
+//! ([`Token`](crate::lexer::Token)) and their spans, the AST
+//! ([`Expr`](crate::ast::Expr), [`Program`](crate::ast::Program)) with its
+//! [`NodeSpans`](crate::span::NodeSpans), the typed AST
+//! ([`TypedAst`](crate::typeck::TypedAst)), the IR
+//! ([`ObliExpr`](crate::ir::ObliExpr)), diagnostics
+//! ([`Diagnostic`](crate::diagnostic::Diagnostic)) and cost statistics
+//! ([`Stats`](crate::stats::Stats)). A stage's output can be written with
+//! any serde format and read back by another process to resume from there.
+//!
+//! Diagnostic codes and operator names are stored as strings and read
+//! back as the crate's own constants, so unknown ones are rejected.
+
+use crate::codes::explain;
+use crate::diagnostic::{Diagnostic, Label, Severity, Suggestion};
+use crate::span::Span;
+use crate::stats::OP_NAMES;
+use serde::de::Error as _;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::BTreeMap;
+
+/// A [`Diagnostic`] as stored. The derive cannot read `Option<&'static
+/// str>` from a non-static input, so the code goes through a `String`.
+#[derive(Serialize, Deserialize)]
+struct StoredDiagnostic {
+    severity: Severity,
+    code: Option<String>,
+    message: String,
+    span: Option<Span>,
+    labels: Vec<Label>,
+    notes: Vec<String>,
+    #[serde(default)]
+    suggestions: Vec<Suggestion>,
+}
+
+impl Serialize for Diagnostic {
+    fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        StoredDiagnostic {
+            severity: self.severity,
+            code: self.code.map(str::to_string),
+            message: self.message.clone(),
+            span: self.span,
+            labels: self.labels.clone(),
+            notes: self.notes.clone(),
+            suggestions: self.suggestions.clone(),
+        }
+        .serialize(s)
+    }
+}
+
+impl<'de> Deserialize<'de> for Diagnostic {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+        let stored = StoredDiagnostic::deserialize(d)?;
+        let code = match stored.code {
+            None => None,
+            Some(code) => match explain(&code) {
+                Some(explanation) => Some(explanation.code),
+                None => {
+                    let message = format!("unknown diagnostic code `{}`", code);
+                    return Err(D::Error::custom(message));
+                }
+            },
+        };
+        Ok(Diagnostic {
+            severity: stored.severity,
+            code,
+            message: stored.message,
+            span: stored.span,
+            labels: stored.labels,
+            notes: stored.notes,
+            suggestions: stored.suggestions,
+        })
+    }
+}
+
+/// `Stats` operation counts, keyed by operator name.
+pub(crate) mod op_counts {
+    use super::*;
+
+    type Counts = BTreeMap<&'static str, usize>;
+
+    pub fn serialize<S: Serializer>(counts: &Counts, s: S) -> Result<S::Ok, S::Error> {
+        counts.serialize(s)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<Counts, D::Error> {
+        let counts = BTreeMap::<String, usize>::deserialize(d)?;
+        let op = |name: String| match OP_NAMES.iter().find(|op| **op == name) {
+            Some(op) => Ok(*op),
+            None => Err(D::Error::custom(format!("unknown operator `{}`", name))),
+        };
+        counts.into_iter().map(|(name, n)| Ok((op(name)?, n))).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::check::check;
+    use crate::diagnostic::Diagnostic;
+    use crate::lexer::{Lexer, Token};
+    use crate::span::{NodeSpans, Span};
+    use crate::stats::{stats, Stats};
+    use crate::testing::property::{to_source, Generator};
+    use crate::typeck::TypedAst;
+    use crate::{to_oblivious, ObliExpr, Parser};
+    use serde::de::DeserializeOwned;
+    use serde::Serialize;
+    use std::fmt::Debug;
+
+    fn round_trip<T: Serialize + DeserializeOwned + PartialEq + Debug>(value: &T) {
+        let json = serde_json::to_string(value).unwrap();
+        let back: T = serde_json::from_str(&json).unwrap();
+        assert_eq!(&back, value, "{}", json);
+    }
+
+    #[test]
+    fn test_every_stage_round_trips() {
+        let mut generator = Generator::new(1208);
+        for _ in 0..50 {
+            let source = to_source(&generator.program());
+            let (tokens, token_spans) = Lexer::new(&source).tokenize().unwrap();
+            round_trip::<(Vec<Token>, Vec<Span>)>(&(tokens.clone(), token_spans.clone()));
+            let (ast, spans) = Parser::with_spans(&tokens, &token_spans)
+                .parse_with_spans()
+                .unwrap();
+            round_trip::<NodeSpans>(&spans);
+            round_trip::<TypedAst>(&TypedAst::new(ast.clone()));
+            let ir = to_oblivious(&ast);
+            round_trip::<ObliExpr>(&ir);
+            round_trip::<Stats>(&stats(&ir));
+        }
+    }
+
+    #[test]
+    fn test_diagnostics_round_trip() {
+        let diagnostics = check("let x = secret(1) if x > 0 then 1 / x else (1 + true)");
+        assert!(diagnostics.iter().any(|d| d.code.is_some() && !d.labels.is_empty()));
+        round_trip::<Vec<Diagnostic>>(&diagnostics);
+
+        let json = r#"{"severity": "Error", "code": "OB9999", "message": "",
+            "span": null, "labels": [], "notes": []}"#;
+        let err = serde_json::from_str::<Diagnostic>(json).unwrap_err();
+        assert!(err.to_string().starts_with("unknown diagnostic code `OB9999`"));
+    }
+}
@@ -0,0 +1,951 @@
+// SPDX-License-Identifier: MIT OR AGPL-3.0-or-later
+// SPDX-FileCopyrightText: 2024-2025 hyperpolymath
+
+//! Catalog of diagnostic codes.
+//!
+//! Every diagnostic carries a stable code (`OB0001`…) so its message can
+//! stay short; [`explain`] returns the long-form description with examples
+//! (`obli explain OB0005`). Codes are never reused or renumbered.
+
+/// Long-form description of a diagnostic code.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Explanation {
+    pub code: &'static str,
+    /// One-line summary.
+    pub title: &'static str,
+    /// Description with examples.
+    pub text: &'static str,
+}
+
+pub const UNEXPECTED_CHAR: &str = "OB0001";
+pub const INVALID_NUMBER: &str = "OB0002";
+pub const UNEXPECTED_TOKEN: &str = "OB0003";
+pub const UNEXPECTED_EOF: &str = "OB0004";
+pub const TYPE_MISMATCH: &str = "OB0005";
+pub const BRANCH_MISMATCH: &str = "OB0006";
+pub const UNBOUND_VARIABLE: &str = "OB0007";
+pub const SECRET_PUB_IF: &str = "OB0008";
+pub const PUBLIC_CT_SELECT: &str = "OB0009";
+pub const SECRECY_DOWNGRADE: &str = "OB0010";
+pub const VAR_DOWNGRADE: &str = "OB0011";
+pub const SECRET_DIVISION: &str = "OB0012";
+pub const SECRET_MODULO: &str = "OB0013";
+pub const UNUSED_VARIABLE: &str = "OB0014";
+pub const SHADOWED_SECRET: &str = "OB0015";
+pub const SECRET_COMPARED_TO_CONSTANT: &str = "OB0016";
+pub const RESERVED_NAME: &str = "OB0017";
+pub const LIMIT_EXCEEDED: &str = "OB0018";
+pub const NOT_SCALAR: &str = "OB0019";
+pub const INDEX_OUT_OF_BOUNDS: &str = "OB0020";
+pub const NOT_AN_ARRAY: &str = "OB0021";
+pub const INVALID_BYTE_STRING: &str = "OB0022";
+pub const EARLY_EXIT_COMPARISON: &str = "OB0023";
+pub const DIVISOR_MAY_BE_ZERO: &str = "OB0024";
+pub const MISPLACED_OUTPUTS: &str = "OB0025";
+pub const DUPLICATE_OUTPUT: &str = "OB0026";
+pub const UNDECLARED_REVEAL: &str = "OB0027";
+pub const DISALLOWED_SECRET: &str = "OB0028";
+pub const SHADOWED_VARIABLE: &str = "OB0029";
+pub const UNUSED_SECRET: &str = "OB0030";
+pub const INCLUDE_CYCLE: &str = "OB0031";
+pub const UNDEFINED_FUNCTION: &str = "OB0032";
+pub const ARITY_MISMATCH: &str = "OB0033";
+pub const NON_CONSTANT_BOUND: &str = "OB0034";
+pub const TOO_MANY_ITERATIONS: &str = "OB0035";
+pub const SECRET_LOOP_CONDITION: &str = "OB0036";
+pub const NOT_A_TUPLE: &str = "OB0037";
+pub const DECLARED_PUBLIC: &str = "OB0038";
+pub const NOT_MUTABLE: &str = "OB0039";
+pub const FUNCTION_VALUE: &str = "OB0040";
+pub const NON_CONSTANT_VALUE: &str = "OB0041";
+pub const SECRET_CONSTANT: &str = "OB0042";
+pub const CONSTANT_REBOUND: &str = "OB0043";
+pub const UNBOUNDED_RECURSION: &str = "OB0044";
+pub const NO_BASE_CASE: &str = "OB0045";
+pub const MISPLACED_INPUT: &str = "OB0046";
+pub const DUPLICATE_INPUT: &str = "OB0047";
+pub const NARROWING_SECRET_CAST: &str = "OB0048";
+pub const NOT_A_VARIANT: &str = "OB0049";
+pub const NON_EXHAUSTIVE_MATCH: &str = "OB0050";
+pub const NON_CONSTANT_EXPONENT: &str = "OB0051";
+pub const NOT_A_FUNCTION: &str = "OB0052";
+pub const NARROWING: &str = "OB0053";
+pub const DEAD_DECLASSIFY: &str = "OB0054";
+pub const TRUNCATED_CALL: &str = "OB0055";
+pub const REDUNDANT_DECLASSIFY: &str = "OB0056";
+
+/// Every code, in numeric order.
+pub const CATALOG: &[Explanation] = &[
+    Explanation {
+        code: UNEXPECTED_CHAR,
+        title: "unexpected character",
+        text: "\
+The lexer found a character that cannot start any MiniObli token.
+
+Erroneous example:
+
+    let x = 1 $ 2
+
+MiniObli programs consist of integer, boolean and byte-string literals,
+identifiers, the keywords `let`, `if`, `then`, `else`, `secret`,
+`lookup`, `sort`, `saturating` and `outputs`, parentheses, brackets, commas, and
+the operators `+ - * +| -| *| / % == != < <= > >= && || !`. Comments start with `#` and run to
+the end of the line.",
+    },
+    Explanation {
+        code: INVALID_NUMBER,
+        title: "invalid number",
+        text: "\
+An integer literal does not fit in its type, has an unknown suffix, has
+a digit its base does not allow, or has no digits after its `0x` or `0b`
+prefix.
+
+Erroneous example:
+
+    secret(99999999999999999999)
+
+MiniObli integers are `i64`, so unsuffixed literals can be at most
+9223372036854775807. A suffixed literal such as `300u8` must fit its
+width, and the suffix must be one of `u8`, `u16`, `u32`, `u64`, `i32`
+or `i64`. Hexadecimal literals take the same limits, so a mask with the
+top bit set, such as `0xFFFF_FFFF_FFFF_FFFF`, needs the `u64` suffix.",
+    },
+    Explanation {
+        code: UNEXPECTED_TOKEN,
+        title: "unexpected token",
+        text: "\
+The parser found a token where the grammar does not allow it. The message
+says what was expected instead.
+
+Erroneous example:
+
+    let = 5 x
+
+`let` must be followed by the name being bound:
+
+    let x = 5 x",
+    },
+    Explanation {
+        code: UNEXPECTED_EOF,
+        title: "unexpected end of input",
+        text: "\
+The program ended in the middle of an expression.
+
+Erroneous example:
+
+    let x = secret(1)
+
+A `let` needs a body after its value; the body is the expression the
+whole `let` evaluates to:
+
+    let x = secret(1) x + 1",
+    },
+    Explanation {
+        code: TYPE_MISMATCH,
+        title: "mismatched types",
+        text: "\
+An operand or condition has the wrong type. Arithmetic and ordering
+comparisons take `int`s, `&&`, `||`, `!` and `if` conditions take `bool`s,
+and `==`/`!=` need both sides to have the same type.
+
+Erroneous example:
+
+    1 + true
+
+Secrecy does not affect typing: `secret(1) + 2` is a well-typed `int`.",
+    },
+    Explanation {
+        code: BRANCH_MISMATCH,
+        title: "if branches have different types",
+        text: "\
+Both branches of an `if` must have the same type, because either one may
+become the value of the whole expression (and for a secret condition,
+both are evaluated and blended by `ct_select`).
+
+Erroneous example:
+
+    if secret(1) > 0 then 1 else false",
+    },
+    Explanation {
+        code: UNBOUND_VARIABLE,
+        title: "unbound variable",
+        text: "\
+A variable is used outside the scope of any `let` binding it.
+
+Erroneous example:
+
+    let x = 1 y + x
+
+A `let` binding is visible only in its body, not in its own value:
+
+    let x = x + 1 x     # error: `x` is not bound yet",
+    },
+    Explanation {
+        code: SECRET_PUB_IF,
+        title: "PubIf branches on a secret condition",
+        text: "\
+Internal verifier error: the transform emitted a public `if` whose
+condition depends on secret data, which would leak the condition through
+timing. This indicates a transformer bug; please report it with the
+program that triggered it.",
+    },
+    Explanation {
+        code: PUBLIC_CT_SELECT,
+        title: "CtSelect used with a public condition",
+        text: "\
+Internal verifier error: the transform emitted a constant-time select for
+a public condition. This is not a leak, but it means secrecy inference
+and the IR disagree. This indicates a transformer bug; please report it.",
+    },
+    Explanation {
+        code: SECRECY_DOWNGRADE,
+        title: "node marked public but depends on secret data",
+        text: "\
+Internal verifier error: an IR node is labelled public although one of
+its operands is secret, so the emitter would treat secret data as public.
+This indicates a transformer or pass bug; please report it.",
+    },
+    Explanation {
+        code: VAR_DOWNGRADE,
+        title: "variable marked public but bound to a secret",
+        text: "\
+Internal verifier error: a variable reference is labelled public while
+its `let` binds a secret value. This indicates a transformer or pass bug;
+please report it.",
+    },
+    Explanation {
+        code: SECRET_DIVISION,
+        title: "division on secret data is not constant-time",
+        text: "\
+Hardware integer division takes a data-dependent number of cycles, and a
+secret zero divisor aborts the program, so `/` with a secret operand can
+leak the operands.
+
+Example:
+
+    let k = secret(9) 100 / k     # warning
+
+Where possible, divide by a public constant, or restructure the
+computation (e.g. compare `a < b * c` instead of `a / c < b`).",
+    },
+    Explanation {
+        code: SECRET_MODULO,
+        title: "modulo on secret data is not constant-time",
+        text: "\
+`%` has the same hazards as division (see OB0012): its timing depends on
+the operands and a secret zero divisor aborts the program.
+
+Example:
+
+    let k = secret(9) k % 4     # warning
+
+For a power-of-two modulus, masking with a public constant avoids the
+divider entirely.",
+    },
+    Explanation {
+        code: UNUSED_VARIABLE,
+        title: "unused variable (lint `unused_variable`)",
+        text: "\
+A `let` binding is never referenced in its body. For secrets this often
+means the intended computation silently ignores them.
+
+Example:
+
+    let key = secret(42) 1 + 2     # warning: unused variable `key`
+
+Prefix the name with `_` if the binding is intentionally unused. The
+level is configurable with `obli check -A/-W/-D unused_variable`.",
+    },
+    Explanation {
+        code: SHADOWED_SECRET,
+        title: "public binding shadows a secret (lint `shadowed_secret`)",
+        text: "\
+A public `let` reuses the name of a secret variable in scope. Readers (and
+reviewers) may assume the name still refers to secret data.
+
+Example:
+
+    let pin = secret(1234)
+    let pin = 0                    # warning
+    pin + 1
+
+Choose a different name for the public value.",
+    },
+    Explanation {
+        code: SECRET_COMPARED_TO_CONSTANT,
+        title: "secret compared to a constant (lint `secret_compared_to_constant`)",
+        text: "\
+A secret is compared with a literal. The comparison itself is constant
+time, but if its result is ever revealed it discloses whether the secret
+has (or exceeds) that exact value.
+
+Example:
+
+    let pin = secret(1234) pin == 1234
+
+This lint is allowed by default; enable it with
+`obli check -W secret_compared_to_constant`.",
+    },
+    Explanation {
+        code: RESERVED_NAME,
+        title: "name reserved in generated Rust",
+        text: "\
+An input (free variable) or a named output is named after a Rust keyword
+or an item of the generated runtime (`Pub`, `Secret`, `RevealToken`,
+`ct_select`). Code outside the program refers to these names, so the
+emitted Rust would not compile. Variables bound by `let` are renamed
+instead.
+
+Erroneous example:
+
+    let m = secret(1) outputs(match = m + 1)
+
+Rename the output:
+
+    let m = secret(1) outputs(matched = m + 1)",
+    },
+    Explanation {
+        code: LIMIT_EXCEEDED,
+        title: "resource limit exceeded",
+        text: "\
+The program nests more deeply, has more nodes, or generates more code than
+the configured limits allow. Limits are off unless set, typically by
+services compiling untrusted programs:
+
+    [limits]
+    max-depth = 1000
+    max-nodes = 100000
+    max-output-bytes = 10000000
+
+Split the program into smaller ones, or raise the limit in `obli.toml`.",
+    },
+    Explanation {
+        code: NOT_SCALAR,
+        title: "array used where a scalar is needed",
+        text: "\
+An array or tuple appears where only an `int` or `bool` is allowed: as an
+element of an array or tuple, in a `lookup`, or as the result of the
+whole program.
+
+Erroneous example:
+
+    let a = [1, 2] a
+
+Return an element, or compare the whole array:
+
+    let a = [1, 2] a == [1, 2]",
+    },
+    Explanation {
+        code: INDEX_OUT_OF_BOUNDS,
+        title: "constant index out of bounds",
+        text: "\
+An array is indexed with a literal outside `0..len`.
+
+Erroneous example:
+
+    let a = [1, 2, 3] a[3]
+
+Indices computed at run time are never out of bounds: one outside `0..len`
+reads the last element, without branching on it.",
+    },
+    Explanation {
+        code: NOT_AN_ARRAY,
+        title: "indexing a value that is not an array",
+        text: "\
+Only arrays can be indexed.
+
+Erroneous example:
+
+    let x = 5 x[0]",
+    },
+    Explanation {
+        code: INVALID_BYTE_STRING,
+        title: "invalid byte string",
+        text: "\
+A byte string is unterminated, empty, or contains a character other than
+printable ASCII or one of the escapes `\\\\`, `\\\"`, `\\n`, `\\t`, `\\0` and
+`\\xHH`.
+
+Erroneous example:
+
+    b\"café\" == b\"tea\"
+
+Write other bytes as hex escapes: `b\"caf\\xc3\\xa9\"`.",
+    },
+    Explanation {
+        code: EARLY_EXIT_COMPARISON,
+        title: "element-by-element comparison that stops early (lint `early_exit_comparison`)",
+        text: "\
+Secret values are compared one pair at a time in a chain of `if`s that
+gives up at the first mismatch. This is the shape of an early-exit
+`memcmp`: ported to a language with real branches, or with a public
+operand, its running time reveals how long the matching prefix is.
+
+Example:
+
+    let k = secret(b\"pin\")
+    if k[0] == 112 then (if k[1] == 105 then k[2] == 110 else false) else false
+
+Compare whole byte strings instead; `==` on arrays always compares every
+element:
+
+    let k = secret(b\"pin\") k == b\"pin\"",
+    },
+    Explanation {
+        code: DIVISOR_MAY_BE_ZERO,
+        title: "secret divisor may be zero",
+        text: "\
+With `division-by-zero = \"require-nonzero\"` in the `[arithmetic]`
+section of `obli.toml`, every `/` and `%` by a secret divisor must be
+provably nonzero, since dividing by zero would abort the program and
+reveal the divisor.
+
+Erroneous example:
+
+    let k = secret(4) 12 / k     # error: `k` may be zero
+
+A divisor is provably nonzero when it is a nonzero public literal, its
+negation, a variable bound to one, or an `if` whose arms all are:
+
+    let k = secret(4) let d = if k > 0 then k else 1 12 / d     # still an error
+    let k = secret(4) let d = if k > 0 then 3 else 1 12 / d     # ok
+
+Alternatively choose `\"zero\"` or `\"all-ones\"`, which make a division
+by zero yield 0 or -1 in constant time.",
+    },
+    Explanation {
+        code: MISPLACED_OUTPUTS,
+        title: "named outputs not allowed here",
+        text: "\
+`outputs(name = e, ...)` names the results of a whole program, so it can
+only be the program's result: the expression itself or the body of its
+`let`s. It is not a value that can be bound, computed with or chosen by
+an `if`.
+
+Erroneous example:
+
+    let k = secret(4) if k > 0 then outputs(a = k) else outputs(a = 0)
+
+Move the choice into the output instead:
+
+    let k = secret(4) outputs(a = if k > 0 then k else 0)
+
+Embedded snippets and the Lean export have a single result, so they do
+not accept `outputs` at all.",
+    },
+    Explanation {
+        code: DUPLICATE_OUTPUT,
+        title: "duplicate output name",
+        text: "\
+Two outputs of an `outputs(...)` have the same name. Each output becomes
+a field of the generated result struct and a line of the program's
+output, so names must be distinct.
+
+Erroneous example:
+
+    outputs(total = 1, total = 2)",
+    },
+    Explanation {
+        code: UNDECLARED_REVEAL,
+        title: "reveal not declassified by the policy",
+        text: "\
+A secret result is revealed, but the declassification policy passed to
+`obli check --policy` has no entry for it. Results are the named outputs
+of the program, or `result` for a program without `outputs`.
+
+Erroneous example, with an empty policy:
+
+    let k = secret(7)
+    outputs(total = k * 3)
+
+Add `[declassify.total]` to the policy if revealing it is intended.",
+    },
+    Explanation {
+        code: DISALLOWED_SECRET,
+        title: "revealed result depends on a disallowed secret",
+        text: "\
+A revealed result depends on a secret its policy entry does not list in
+`from`. Dependencies include secrets that only decide an `if`.
+
+Erroneous example, with `from = [\"salary\"]` for `total`:
+
+    let salary = secret(100)
+    let bonus = secret(10)
+    outputs(total = salary + bonus)
+
+A `secret(...)` used directly in the result has no name and is reported
+as `secret(...)`; bind it with `let` to list it.",
+    },
+    Explanation {
+        code: SHADOWED_VARIABLE,
+        title: "binding shadows a variable (lint `shadowed_variable`)",
+        text: "\
+A `let` reuses the name of a variable in scope. The new binding hides
+the old one until the end of its body, and takes the label of its own
+value: a secret name can become public and the other way round.
+
+Example, with the lint enabled:
+
+    let total = secret(5)
+    let total = total * 2          # warning
+    total
+
+The lint is allowed by default; enable it with
+`obli check -W shadowed_variable`. A public binding shadowing a secret
+one is reported by `shadowed_secret` (OB0015) instead.",
+    },
+    Explanation {
+        code: UNUSED_SECRET,
+        title: "secret never influences the result (lint `unused_secret`)",
+        text: "\
+A secret variable is used, but only by bindings that are themselves
+never used, so it cannot affect what the program reveals. This usually
+means the wrong variable is used somewhere.
+
+Example:
+
+    let pin = secret(1234)
+    let check = pin == 1111
+    let ok = secret(true)
+    ok                             # warning: `pin` is never consulted
+
+A variable that is not used at all is reported by `unused_variable`
+(OB0014) instead.",
+    },
+    Explanation {
+        code: INCLUDE_CYCLE,
+        title: "include cycle",
+        text: "\
+A file includes itself, directly or through other files, so expanding
+its includes would never end.
+
+Erroneous example, where `b.mobli` contains `include \"a.mobli\"`:
+
+    # a.mobli
+    include \"b.mobli\"
+    1
+
+Move what both files need into a third file that includes neither.",
+    },
+    Explanation {
+        code: UNDEFINED_FUNCTION,
+        title: "undefined function",
+        text: "\
+A call names no function defined before it. A function can be called
+after its definition and from the bodies of functions defined after it,
+but not from its own body.
+
+Erroneous example (as JSON, since the parser reads `twice(2)` as a
+variable followed by a parenthesised expression):
+
+    {\"call\": \"twice\", \"args\": [{\"int\": 2}]}
+
+Define the function with `fn twice(x) = x * 2` before the call.",
+    },
+    Explanation {
+        code: ARITY_MISMATCH,
+        title: "wrong number of arguments",
+        text: "\
+A call passes a different number of arguments than the function has
+parameters.
+
+Erroneous example:
+
+    fn add(x, y) = x + y
+    add(1)
+
+Pass one argument per parameter.",
+    },
+    Explanation {
+        code: NON_CONSTANT_BOUND,
+        title: "loop bound is not constant",
+        text: "\
+A `for` loop is unrolled when the program is compiled, so its bounds must
+be integer literals, `const` names or arithmetic on them.
+
+Erroneous example:
+
+    let n = 4
+    for i in 0..n with acc = 0 do acc + i
+
+Declare the bound as a constant, `const n = 4`, or write it out:
+`for i in 0..4 ...`.",
+    },
+    Explanation {
+        code: TOO_MANY_ITERATIONS,
+        title: "loop runs too many iterations",
+        text: "\
+A `for` loop, counting the loops around it, would be unrolled into more
+than 65536 copies of its body. So would a call of a recursive function
+whose body, calling itself `c` times, is unrolled `1 + c + ... +
+c^(n-1)` times for `#[max_depth(n)]`.
+
+Erroneous example:
+
+    for i in 0..1000 with a = 0 do
+      for j in 0..1000 with b = a do b + j
+
+Shorten the ranges, or split the work into several programs.",
+    },
+    Explanation {
+        code: SECRET_LOOP_CONDITION,
+        title: "while loop condition depends on secret data",
+        text: "\
+A `while` loop runs until its condition is false, so the number of
+iterations, and with it the running time, would reveal the secret. Only
+loops on public conditions can be made oblivious.
+
+Erroneous example:
+
+    let limit = secret(10)
+    while n < limit with n = 0 do n + 1
+
+Loop a public number of times instead, selecting whether each iteration
+takes effect: `for i in 0..16 with n = 0 do if n < limit then n + 1 else n`.",
+    },
+    Explanation {
+        code: NOT_A_TUPLE,
+        title: "destructuring a value that is not a tuple of that size",
+        text: "\
+`let (x, y, ...) = value` needs a tuple with exactly one component per
+name.
+
+Erroneous example:
+
+    let (q, r) = (1, 2, 3) q + r",
+    },
+    Explanation {
+        code: DECLARED_PUBLIC,
+        title: "variable declared `pub` but bound to a secret value",
+        text: "\
+A `let` annotated `pub` promises a public value, but the value bound
+depends on secret data.
+
+Erroneous example:
+
+    let key: pub int = secret(7) key + 1
+
+Declare the variable `secret`, or leave the secrecy out: `let key: int`.",
+    },
+    Explanation {
+        code: NOT_MUTABLE,
+        title: "assignment to a variable not declared `let mut`",
+        text: "\
+Only a variable bound by `let mut` can be assigned, and only in the block
+that declares it: function and loop bodies cannot assign to the variables
+around them.
+
+Erroneous example:
+
+    let total = 0; total = total + 1; total
+
+Declare the variable with `let mut total = 0`, or bind a new one with
+`let`.",
+    },
+    Explanation {
+        code: FUNCTION_VALUE,
+        title: "function used as a value",
+        text: "\
+A function, named or anonymous, was used where a value is needed.
+Functions are expanded at every call, so they are not values at run
+time: one can be called, passed as an argument to a call, and an
+anonymous one bound by a plain `let`.
+
+Erroneous example:
+
+    let inc = fn(x) => x + 1; if secret(1) > 0 then inc else inc
+
+Call the function in each branch, as in `inc(1)`, or pass it on to the
+function that calls it.",
+    },
+    Explanation {
+        code: NON_CONSTANT_VALUE,
+        title: "constant value is not constant",
+        text: "\
+The value of a `const` is computed when the program is compiled, so it
+must be an integer literal, another `const` or `+`, `-` and `*` on them.
+
+Erroneous example:
+
+    let n = 4
+    const size = n * 2
+
+Declare `n` with `const` too, or bind `size` with `let`.",
+    },
+    Explanation {
+        code: SECRET_CONSTANT,
+        title: "secret constant",
+        text: "\
+A `const` is folded into the program wherever it is used, including loop
+bounds that decide how the program is unrolled, so its value cannot be
+secret.
+
+Erroneous example:
+
+    const key = secret(42)
+
+Bind secret values with `let`.",
+    },
+    Explanation {
+        code: CONSTANT_REBOUND,
+        title: "constant rebound",
+        text: "\
+A name declared with `const` cannot be bound again while the constant is
+in scope, by `let`, a parameter or a loop variable, so every use of the
+name is the constant.
+
+Erroneous example:
+
+    const n = 4
+    let n = n + 1
+    n
+
+Choose another name for the variable.",
+    },
+    Explanation {
+        code: UNBOUNDED_RECURSION,
+        title: "recursion without a depth bound",
+        text: "\
+Every call is inlined when the program is compiled, so a function may
+call itself only if declared with `#[max_depth(n)]`, which unrolls it at
+most `n` calls deep.
+
+Erroneous example:
+
+    fn sum(n) = if n == 0 then 0 else n + sum(n - 1)
+
+Declare the deepest recursion the program needs:
+
+    #[max_depth(8)]
+    fn sum(n) = if n == 0 then 0 else n + sum(n - 1)",
+    },
+    Explanation {
+        code: NO_BASE_CASE,
+        title: "recursion without a base case",
+        text: "\
+The type of a recursive function is that of the branches of its body that
+do not call it again. A function whose every branch does has no type.
+
+Erroneous example:
+
+    #[max_depth(4)]
+    fn f(n) = f(n - 1)
+
+Return a value without recursing in some branch, as in
+`if n == 0 then 0 else f(n - 1)`.",
+    },
+    Explanation {
+        code: MISPLACED_INPUT,
+        title: "input declared inside an expression",
+        text: "\
+`input secret x: int` declares an input of the whole program, read once
+when it starts, so it can only be one of the program's statements. It
+cannot be declared in a function, a loop or a branch.
+
+Erroneous example:
+
+    fn f(n) = input k: int n + k
+    f(1)
+
+Declare the input as a statement and use it in the function instead:
+
+    input k: int
+    fn f(n) = n + k
+    f(1)
+
+Embedded snippets take their inputs as captured variables, so they do not
+accept `input` at all.",
+    },
+    Explanation {
+        code: DUPLICATE_INPUT,
+        title: "duplicate input name",
+        text: "\
+Two `input` declarations have the same name. Each input is one value the
+program reads when it runs, by name, so names must be distinct.
+
+Erroneous example:
+
+    input secret k: int
+    input k: int
+    k",
+    },
+    Explanation {
+        code: NARROWING_SECRET_CAST,
+        title: "secret cast to a narrower type (lint `narrowing_secret_cast`)",
+        text: "\
+A secret is cast to an integer type that cannot hold every value of its
+own type. The cast itself is constant-time, but values that do not fit
+are silently truncated, so the result may not be the secret at all.
+
+Example:
+
+    let k = secret(1000)
+    k as u8                        # warning: 1000 becomes 232
+
+Check the range first if the value should fit, for example with
+`clamp(k, 0, 255) as u8`, or cast to a type wide enough for every
+value. Widening casts, such as `u8` to `u32` or `u32` to `int`, are
+never reported.",
+    },
+    Explanation {
+        code: NOT_A_VARIANT,
+        title: "pattern is not a variant",
+        text: "\
+A name used as a `match` pattern must be a variant of an `enum` in
+scope, and every variant pattern of one `match` must belong to the same
+enum.
+
+Erroneous example:
+
+    enum Color = Red | Green
+    enum Size = Small | Large
+    let c = Red
+    match c with Red => 1, Small => 2
+
+Match on the variants of one enum, or compare the value with `==`.",
+    },
+    Explanation {
+        code: NON_EXHAUSTIVE_MATCH,
+        title: "match misses a variant",
+        text: "\
+A `match` over the variants of an enum without a `_` arm must have an arm
+for every variant, so the value always selects one. On a secret value
+every arm is evaluated and the result selected obliviously, so a missing
+arm cannot simply be skipped.
+
+Erroneous example:
+
+    enum Color = Red | Green | Blue
+    let c = secret(Green)
+    match c with Red => 1, Green => 2
+
+Add an arm for each remaining variant, here `Blue`, or a `_` arm.",
+    },
+    Explanation {
+        code: NON_CONSTANT_EXPONENT,
+        title: "exponent is not a constant",
+        text: "\
+`pow(base, exp)` is unrolled into a fixed chain of multiplications, one
+squaring per bit of `exp`, so the exponent must be a non-negative
+constant: an integer literal or a `const`. A secret exponent would decide
+how many multiplications run.
+
+Erroneous example:
+
+    let e = secret(3)
+    pow(2, e)
+
+Use a constant exponent, or compute the power with a loop over a public
+bound that selects each factor.",
+    },
+    Explanation {
+        code: NOT_A_FUNCTION,
+        title: "not a function of the right arity",
+        text: "\
+The last argument of `map` must be a function of one argument, the
+element, and that of `fold` a function of two, the accumulator and the
+element. It is a lambda or the name of a function, and it is called once
+per element when the array is unrolled.
+
+Erroneous example:
+
+    let double = 2
+    map([1, 2, 3], double)
+
+Pass a function instead, here `fn(x) => x * 2`.",
+    },
+    Explanation {
+        code: NARROWING,
+        title: "implicit narrowing conversion",
+        text: "\
+An integer is converted implicitly only where no value is lost: an
+unsuffixed literal to the type it fits, and a value to a type holding
+every value of its own, as `u8` to `u16` or `u32` to `int`. A value
+bound to an annotated `let` or assigned to a `let mut` variable of a
+type that cannot hold all of its values must be converted explicitly.
+
+Erroneous example:
+
+    let wide = 300u16
+    let narrow: u8 = wide
+
+Write the cast, here `let narrow: u8 = wide as u8`, which wraps a value
+that does not fit.",
+    },
+    Explanation {
+        code: DEAD_DECLASSIFY,
+        title: "declassified value never used publicly (lint `dead_declassify`)",
+        text: "\
+A `let` binds `declassify(...)` of a secret, but the variable is only used
+by secret computations or not towards the result at all. The secret is
+revealed, yet nothing public depends on it, so the declassification only
+leaks. This usually means the declassified value was meant to be used
+instead of the secret somewhere.
+
+Example:
+
+    let pin = secret(1234)
+    let shown = declassify(pin)
+    shown * pin                    # warning: `shown` is mixed back into a secret
+
+Use the value publicly, for instance in an output or a public `if`, or
+drop the `declassify`.",
+    },
+    Explanation {
+        code: TRUNCATED_CALL,
+        title: "recursive call deeper than `#[max_depth]`",
+        text: "\
+A `#[max_depth(n)]` function is inlined up to `n` calls deep, and a run
+that reaches a call nested deeper than that fails, like a failed
+assertion. Where the calls take public constants, the branches that run
+are known when the program is compiled, and a call that runs past the
+bound would fail every run.
+
+Erroneous example:
+
+    #[max_depth(2)]
+    fn sum(n) = if n == 0 then 0 else n + sum(n - 1)
+    sum(5)                         # sum(3) is nested 3 calls deep
+
+Raise the bound to cover the deepest call, here `#[max_depth(6)]`.",
+    },
+    Explanation {
+        code: REDUNDANT_DECLASSIFY,
+        title: "`declassify` of a public value (lint `redundant_declassify`)",
+        text: "\
+`declassify(...)` is applied to a value that is already public, so it
+reveals nothing. This usually means the value meant to be declassified
+is a different one, or the `declassify` outlived a change that made its
+argument public.
+
+Example:
+
+    let limit = 5
+    declassify(limit) + 1          # warning: `limit` is public
+
+Drop the `declassify`.",
+    },
+];
+
+/// Look up a code (case-insensitive, e.g. `OB0005` or `ob0005`).
+pub fn explain(code: &str) -> Option<&'static Explanation> {
+    CATALOG.iter().find(|e| e.code.eq_ignore_ascii_case(code))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_codes_are_sequential() {
+        for (i, explanation) in CATALOG.iter().enumerate() {
+            assert_eq!(explanation.code, format!("OB{:04}", i + 1));
+        }
+    }
+
+    #[test]
+    fn test_explain() {
+        assert_eq!(explain("ob0005").unwrap().title, "mismatched types");
+        assert!(explain("OB9999").is_none());
+    }
+}
@@ -0,0 +1,306 @@
+// SPDX-License-Identifier: MIT OR AGPL-3.0-or-later
+// SPDX-FileCopyrightText: 2024-2025 hyperpolymath
+
+//! Project configuration (`obli.toml`).
+//!
+//! ```toml
+//! [build]
+//! sources = ["src"]          # source roots, relative to obli.toml
+//! out-dir = "target/obli"
+//! backends = ["rust"]
+//! report = true              # write a JSON compilation report per file
+//!
+//! [emit]
+//! prelude = true
+//!
+//! [passes]
+//! pipeline = ["const-fold", "select-simplify", "const-tables"]
+//!
+//! [lints]
+//! unused_variable = "deny"
+//!
+//! [arithmetic]
+//! division-by-zero = "zero"  # or "trap" (default), "all-ones", "require-nonzero"
+//!
+//! [check]
+//! policy = "policy.toml"     # declassification policy; see `policy`
+//!
+//! [limits]                   # see `limits`; all off by default
+//! max-depth = 1000
+//! max-nodes = 100000
+//! max-output-bytes = 10000000
+//! ```
+//!
+//! Every section and key is optional. Names are validated on load, so a
+//! typo in a backend, pass or lint is an error rather than silently
+//! ignored.
+
+use crate::division::DivisionByZero;
+use crate::emit::{EmitOptions, BACKENDS};
+use crate::limits::Limits;
+use crate::lint::{LintConfig, LintError};
+use crate::passes::{builtin, Pipeline};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+/// Conventional file name of a project configuration.
+pub const CONFIG_FILE: &str = "obli.toml";
+
+#[derive(Error, Debug)]
+pub enum ConfigError {
+    #[error("cannot read {path}: {source}")]
+    Io {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+    #[error("invalid configuration: {0}")]
+    Toml(#[from] toml::de::Error),
+    #[error("invalid configuration: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("unknown backend `{0}` (available: {backends})", backends = BACKENDS.join(", "))]
+    UnknownBackend(String),
+    #[error("unknown pass `{0}`")]
+    UnknownPass(String),
+    #[error(transparent)]
+    Lint(#[from] LintError),
+}
+
+/// A loaded, validated project configuration.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Config {
+    /// Directories (or files) containing sources, resolved against the
+    /// directory of the configuration file.
+    pub sources: Vec<PathBuf>,
+    /// Where builds are written, resolved like `sources`.
+    pub out_dir: Option<PathBuf>,
+    /// Back ends to emit for.
+    pub backends: Vec<String>,
+    /// Write a [compilation report](crate::report) next to each output.
+    pub report: bool,
+    pub emit: EmitOptions,
+    /// Optimisation passes to run, in order.
+    pub passes: Vec<String>,
+    pub lints: LintConfig,
+    /// What a division by a secret zero does; see [`division`](crate::division).
+    pub division_by_zero: DivisionByZero,
+    /// Declassification policy for `obli check`, resolved like `sources`.
+    pub policy: Option<PathBuf>,
+    pub limits: Limits,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            sources: Vec::new(),
+            out_dir: None,
+            backends: vec![BACKENDS[0].to_string()],
+            report: false,
+            emit: EmitOptions::default(),
+            passes: Vec::new(),
+            lints: LintConfig::new(),
+            division_by_zero: DivisionByZero::default(),
+            policy: None,
+            limits: Limits::none(),
+        }
+    }
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct RawConfig {
+    build: RawBuild,
+    emit: RawEmit,
+    passes: RawPasses,
+    lints: BTreeMap<String, String>,
+    arithmetic: RawArithmetic,
+    check: RawCheck,
+    limits: Limits,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+struct RawBuild {
+    sources: Vec<PathBuf>,
+    out_dir: Option<PathBuf>,
+    backends: Option<Vec<String>>,
+    report: bool,
+}
+
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct RawEmit {
+    prelude: bool,
+}
+
+impl Default for RawEmit {
+    fn default() -> Self {
+        Self { prelude: true }
+    }
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+struct RawArithmetic {
+    division_by_zero: DivisionByZero,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct RawCheck {
+    policy: Option<PathBuf>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct RawPasses {
+    pipeline: Vec<String>,
+}
+
+impl Config {
+    /// Load a configuration file.
+    pub fn from_path(path: impl AsRef<Path>) -> Result<Self, ConfigError> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path).map_err(|source| ConfigError::Io {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        let base = path.parent().unwrap_or(Path::new(""));
+        Self::parse(&text, base)
+    }
+
+    /// Parse configuration text, resolving relative paths against `base`.
+    pub fn parse(text: &str, base: &Path) -> Result<Self, ConfigError> {
+        Self::from_raw(toml::from_str(text)?, base)
+    }
+
+    /// Parse the same settings given as JSON (`{"passes": {"pipeline":
+    /// ["const-fold"]}}`), with relative paths left as they are.
+    pub fn parse_json(text: &str) -> Result<Self, ConfigError> {
+        Self::from_raw(serde_json::from_str(text)?, Path::new(""))
+    }
+
+    fn from_raw(raw: RawConfig, base: &Path) -> Result<Self, ConfigError> {
+        let backends = raw.build.backends.unwrap_or_else(|| Config::default().backends);
+        if let Some(unknown) = backends.iter().find(|b| !BACKENDS.contains(&b.as_str())) {
+            return Err(ConfigError::UnknownBackend(unknown.clone()));
+        }
+        if let Some(unknown) = raw.passes.pipeline.iter().find(|p| builtin(p).is_none()) {
+            return Err(ConfigError::UnknownPass(unknown.clone()));
+        }
+        let mut lints = LintConfig::new();
+        for (name, level) in &raw.lints {
+            lints.set(name, level.parse()?)?;
+        }
+
+        Ok(Self {
+            sources: raw.build.sources.iter().map(|p| base.join(p)).collect(),
+            out_dir: raw.build.out_dir.map(|p| base.join(p)),
+            backends,
+            report: raw.build.report,
+            emit: EmitOptions {
+                prelude: raw.emit.prelude,
+            },
+            passes: raw.passes.pipeline,
+            lints,
+            division_by_zero: raw.arithmetic.division_by_zero,
+            policy: raw.check.policy.map(|p| base.join(p)),
+            limits: raw.limits,
+        })
+    }
+
+    /// Find `obli.toml` in `dir` or its nearest ancestor that has one.
+    pub fn discover(dir: &Path) -> Option<PathBuf> {
+        dir.ancestors()
+            .map(|d| d.join(CONFIG_FILE))
+            .find(|p| p.is_file())
+    }
+
+    /// The configured pass pipeline.
+    pub fn pipeline(&self) -> Pipeline {
+        let mut pipeline = Pipeline::new();
+        for name in &self.passes {
+            pipeline.add(builtin(name).expect("pass names are validated on load"));
+        }
+        pipeline
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lint::{Level, UNUSED_VARIABLE};
+
+    #[test]
+    fn test_parse_full() {
+        let text = r#"
+            [build]
+            sources = ["src"]
+            out-dir = "out"
+            backends = ["rust"]
+            report = true
+
+            [emit]
+            prelude = false
+
+            [passes]
+            pipeline = ["const-fold"]
+
+            [lints]
+            unused_variable = "deny"
+
+            [arithmetic]
+            division-by-zero = "all-ones"
+
+            [check]
+            policy = "policy.toml"
+
+            [limits]
+            max-depth = 100
+        "#;
+        let config = Config::parse(text, Path::new("proj")).unwrap();
+        assert_eq!(config.sources, vec![PathBuf::from("proj/src")]);
+        assert_eq!(config.out_dir, Some(PathBuf::from("proj/out")));
+        assert!(config.report);
+        assert!(!config.emit.prelude);
+        assert_eq!(config.pipeline().names(), vec!["const-fold"]);
+        assert_eq!(config.lints.level(&UNUSED_VARIABLE), Level::Deny);
+        assert_eq!(config.division_by_zero, DivisionByZero::AllOnes);
+        assert_eq!(config.policy, Some(PathBuf::from("proj/policy.toml")));
+        assert_eq!(config.limits.max_depth, Some(100));
+        assert_eq!(config.limits.max_nodes, None);
+    }
+
+    #[test]
+    fn test_empty_is_default() {
+        assert_eq!(Config::parse("", Path::new("")).unwrap(), Config::default());
+    }
+
+    #[test]
+    fn test_unknown_names_rejected() {
+        let base = Path::new("");
+        let err = |text| Config::parse(text, base).unwrap_err().to_string();
+        assert_eq!(
+            err("[build]\nbackends = [\"c\"]"),
+            "unknown backend `c` (available: rust)"
+        );
+        assert_eq!(err("[passes]\npipeline = [\"dce\"]"), "unknown pass `dce`");
+        assert_eq!(err("[lints]\nnope = \"warn\""), "unknown lint `nope`");
+        assert!(err("[emit]\nprelud = true").starts_with("invalid configuration"));
+    }
+
+    #[test]
+    fn test_parse_json() {
+        let config = Config::parse_json(r#"{"emit": {"prelude": false}, "passes":
+            {"pipeline": ["const-fold"]}}"#)
+        .unwrap();
+        assert!(!config.emit.prelude);
+        assert_eq!(config.passes, vec!["const-fold"]);
+        let err = Config::parse_json(r#"{"passes": {"pipeline": ["dce"]}}"#).unwrap_err();
+        assert_eq!(err.to_string(), "unknown pass `dce`");
+    }
+}
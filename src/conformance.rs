@@ -0,0 +1,330 @@
+// SPDX-License-Identifier: MIT OR AGPL-3.0-or-later
+// SPDX-FileCopyrightText: 2024-2025 hyperpolymath
+
+//! The IR conformance suite as a library.
+//!
+//! [`run_all`] checks an IR tree against the conformance properties of
+//! `docs/IR_SPEC.adoc`, so custom passes and external back ends can hold
+//! their IR to the same rules as the crate's own transform:
+//!
+//! - [`NO_SECRET_PUB_IF`]: no `PubIf` or `While` branches on a secret
+//!   (Property 1);
+//! - [`SECRECY_CONSISTENCY`]: no secrecy flag claims a value that depends
+//!   on a secret is public (Properties 3 and 5);
+//! - [`CT_SELECT_SEMANTICS`]: every `CtSelect` has a secret boolean
+//!   condition and arms of the same type, so both can be evaluated and
+//!   blended, and every `CtSwap` a boolean condition and operands of the
+//!   same type (Property 4).
+
+use crate::ir::{ObliBinOp, ObliExpr, ObliUnaryOp};
+use crate::span::{NodeSpans, Span, SpanCursor};
+use crate::symbol::Symbol;
+use crate::typeck::{Scalar, Width};
+use crate::verify::{verify_with_spans, VerifyError};
+use std::fmt;
+
+pub const NO_SECRET_PUB_IF: &str = "no-secret-pub-if";
+pub const SECRECY_CONSISTENCY: &str = "secrecy-consistency";
+pub const CT_SELECT_SEMANTICS: &str = "ct-select-semantics";
+
+/// Every check [`run_all`] runs.
+pub const CHECKS: &[&str] = &[NO_SECRET_PUB_IF, SECRECY_CONSISTENCY, CT_SELECT_SEMANTICS];
+
+/// A node that breaks a conformance property.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Violation {
+    /// Name of the failed check, one of [`CHECKS`].
+    pub check: &'static str,
+    pub message: String,
+    /// Span of the node (empty if spans were not supplied).
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ConformanceReport {
+    pub violations: Vec<Violation>,
+}
+
+impl ConformanceReport {
+    pub fn is_conformant(&self) -> bool {
+        self.violations.is_empty()
+    }
+
+    /// Violations of one check.
+    pub fn violations_of<'a>(&'a self, check: &'a str) -> impl Iterator<Item = &'a Violation> {
+        self.violations.iter().filter(move |v| v.check == check)
+    }
+}
+
+impl fmt::Display for ConformanceReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for check in CHECKS {
+            let count = self.violations_of(check).count();
+            let status = if count == 0 { "ok" } else { "FAILED" };
+            writeln!(f, "{:<22} {}", check, status)?;
+            for violation in self.violations_of(check) {
+                writeln!(f, "  {}", violation.message)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Run every conformance check on `expr`.
+pub fn run_all(expr: &ObliExpr) -> ConformanceReport {
+    run_all_with_spans(expr, &NodeSpans::new())
+}
+
+/// Like [`run_all`], reporting violations at the IR's post-order spans.
+pub fn run_all_with_spans(expr: &ObliExpr, spans: &NodeSpans) -> ConformanceReport {
+    let mut violations: Vec<Violation> = verify_with_spans(expr, spans)
+        .into_iter()
+        .map(|error| {
+            let check = match error {
+                VerifyError::SecretPubIf { .. } | VerifyError::SecretLoopCondition { .. } => {
+                    NO_SECRET_PUB_IF
+                }
+                VerifyError::PublicCtSelect { .. } => CT_SELECT_SEMANTICS,
+                VerifyError::SecrecyDowngrade { .. }
+                | VerifyError::VarDowngrade { .. }
+                | VerifyError::DeclaredPublic { .. } => SECRECY_CONSISTENCY,
+            };
+            Violation {
+                check,
+                message: error.to_string(),
+                span: error.span(),
+            }
+        })
+        .collect();
+
+    let mut types = Types {
+        scope: Vec::new(),
+        spans: SpanCursor::new(Some(spans)),
+        violations: Vec::new(),
+    };
+    types.infer(expr);
+    violations.extend(types.violations);
+    violations.sort_by_key(|v| CHECKS.iter().position(|c| *c == v.check));
+    ConformanceReport { violations }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Type {
+    Int,
+    Bool,
+    Sized(Width),
+}
+
+/// The type of integer arithmetic on operands of types `found`: sized
+/// if any operand is.
+fn int_type(found: &[Option<Type>]) -> Type {
+    let sized = found.iter().flatten().find(|ty| matches!(ty, Type::Sized(_)));
+    sized.copied().unwrap_or(Type::Int)
+}
+
+/// Infers value types to check `CtSelect` nodes; `None` is unknown (a free
+/// variable) or already ill-typed.
+struct Types<'a> {
+    scope: Vec<(Symbol, Option<Type>)>,
+    spans: SpanCursor<'a>,
+    violations: Vec<Violation>,
+}
+
+impl Types<'_> {
+    fn infer(&mut self, expr: &ObliExpr) -> Option<Type> {
+        // Children first, so spans are consumed in post-order
+        let ty = match expr {
+            ObliExpr::PubInt(_) | ObliExpr::SecretInt(_) => Some(Type::Int),
+            ObliExpr::PubBool(_) | ObliExpr::SecretBool(_) => Some(Type::Bool),
+            ObliExpr::Sized { width, .. } => Some(Type::Sized(*width)),
+            ObliExpr::Input { ty, .. } => Some(match ty {
+                Scalar::Int => Type::Int,
+                Scalar::Bool => Type::Bool,
+                Scalar::Sized(width) => Type::Sized(*width),
+            }),
+            ObliExpr::Var { name, .. } => {
+                self.scope.iter().rev().find(|(n, _)| n == name).and_then(|(_, ty)| *ty)
+            }
+            ObliExpr::BinOp { op, left, right, .. } => {
+                let found = [self.infer(left), self.infer(right)];
+                Some(match op {
+                    ObliBinOp::CtAdd
+                    | ObliBinOp::CtSub
+                    | ObliBinOp::CtMul
+                    | ObliBinOp::CtAddSat
+                    | ObliBinOp::CtSubSat
+                    | ObliBinOp::CtMulSat
+                    | ObliBinOp::CtDiv
+                    | ObliBinOp::CtMod
+                    | ObliBinOp::CtBitAnd
+                    | ObliBinOp::CtBitOr
+                    | ObliBinOp::CtBitXor
+                    | ObliBinOp::CtShl
+                    | ObliBinOp::CtShr => int_type(&found),
+                    _ => Type::Bool,
+                })
+            }
+            ObliExpr::UnaryOp { op, expr, .. } => {
+                let found = self.infer(expr);
+                Some(match op {
+                    ObliUnaryOp::CtNeg => int_type(&[found]),
+                    ObliUnaryOp::CtNot | ObliUnaryOp::CtToBool => Type::Bool,
+                    ObliUnaryOp::CtToInt => Type::Int,
+                })
+            }
+            ObliExpr::CtCast { expr, to, .. } => {
+                self.infer(expr);
+                Some(match to {
+                    Scalar::Sized(width) => Type::Sized(*width),
+                    _ => Type::Int,
+                })
+            }
+            ObliExpr::CtSelect {
+                cond,
+                then_val,
+                else_val,
+            } => {
+                let c = self.infer(cond);
+                let t = self.infer(then_val);
+                let e = self.infer(else_val);
+                let mut problems = Vec::new();
+                if matches!(c, Some(Type::Int | Type::Sized(_))) {
+                    problems.push("CtSelect condition is not a boolean".to_string());
+                }
+                if let (Some(t), Some(e)) = (t, e) {
+                    if t != e {
+                        let problem = format!("CtSelect arms differ in type ({:?} and {:?})", t, e);
+                        problems.push(problem);
+                    }
+                }
+                self.report(problems);
+                return t.or(e);
+            }
+            ObliExpr::PubIf {
+                cond,
+                then_branch,
+                else_branch,
+            } => {
+                self.infer(cond);
+                let t = self.infer(then_branch);
+                let e = self.infer(else_branch);
+                t.or(e)
+            }
+            ObliExpr::Let {
+                name, value, body, ..
+            } => {
+                let v = self.infer(value);
+                self.scope.push((*name, v));
+                let ty = self.infer(body);
+                self.scope.pop();
+                ty
+            }
+            ObliExpr::CtSwap {
+                cond,
+                left,
+                right,
+                names,
+                body,
+                ..
+            } => {
+                let c = self.infer(cond);
+                let l = self.infer(left);
+                let r = self.infer(right);
+                self.scope.extend(names.map(|name| (name, l.or(r))));
+                let ty = self.infer(body);
+                self.scope.truncate(self.scope.len() - 2);
+                let mut problems = Vec::new();
+                if matches!(c, Some(Type::Int | Type::Sized(_))) {
+                    problems.push("CtSwap condition is not a boolean".to_string());
+                }
+                if let (Some(l), Some(r)) = (l, r) {
+                    if l != r {
+                        let problem = format!("CtSwap operands differ in type ({:?} and {:?})", l, r);
+                        problems.push(problem);
+                    }
+                }
+                self.report(problems);
+                return ty;
+            }
+            ObliExpr::While {
+                cond,
+                acc,
+                init,
+                body,
+                ..
+            } => {
+                // The condition comes first, before the type of `acc` is known
+                self.scope.push((*acc, None));
+                self.infer(cond);
+                self.scope.pop();
+                let ty = self.infer(init);
+                self.scope.push((*acc, ty));
+                self.infer(body);
+                self.scope.pop();
+                ty
+            }
+            ObliExpr::Outputs(outputs) => {
+                for (_, value) in outputs {
+                    self.infer(value);
+                }
+                None
+            }
+            ObliExpr::Table { index, .. } => {
+                self.infer(index);
+                Some(Type::Int)
+            }
+            ObliExpr::Declassify(expr) => self.infer(expr),
+            ObliExpr::Assert { cond, body } => {
+                self.infer(cond);
+                self.infer(body)
+            }
+        };
+        self.spans.next_span();
+        ty
+    }
+
+    fn report(&mut self, problems: Vec<String>) {
+        let span = self.spans.next_span().unwrap_or_default();
+        for message in problems {
+            self.violations.push(Violation {
+                check: CT_SELECT_SEMANTICS,
+                message,
+                span,
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transpile_to_ir;
+
+    #[test]
+    fn test_transform_output_conforms() {
+        let ir = transpile_to_ir("let k = secret(5) if k > 3 then k * 2 else -k").unwrap();
+        let report = run_all(&ir);
+        assert!(report.is_conformant(), "{}", report);
+    }
+
+    #[test]
+    fn test_violations_are_grouped_by_check() {
+        let select = |cond, then_val| ObliExpr::CtSelect {
+            cond: Box::new(cond),
+            then_val: Box::new(then_val),
+            else_val: Box::new(ObliExpr::SecretInt(0)),
+        };
+        let ir = ObliExpr::PubIf {
+            cond: Box::new(ObliExpr::SecretBool(true)),
+            then_branch: Box::new(select(ObliExpr::SecretInt(1), ObliExpr::PubBool(true))),
+            else_branch: Box::new(select(ObliExpr::PubBool(false), ObliExpr::PubInt(1))),
+        };
+        let report = run_all(&ir);
+        let checks: Vec<_> = report.violations.iter().map(|v| v.check).collect();
+        assert_eq!(
+            checks,
+            vec![NO_SECRET_PUB_IF, CT_SELECT_SEMANTICS, CT_SELECT_SEMANTICS, CT_SELECT_SEMANTICS]
+        );
+        assert!(report.to_string().starts_with("no-secret-pub-if       FAILED\n"));
+    }
+}
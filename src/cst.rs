@@ -0,0 +1,199 @@
+// SPDX-License-Identifier: MIT OR AGPL-3.0-or-later
+// SPDX-FileCopyrightText: 2024-2025 hyperpolymath
+
+//! Lossless concrete syntax tree.
+//!
+//! The CST keeps every byte of the source: significant tokens plus the
+//! whitespace and comment trivia between them, alongside the AST and the
+//! span of every AST node. Concatenating the token texts reproduces the
+//! input exactly, which is what source-preserving tools (the formatter,
+//! editor integrations) build on.
+
+use crate::ast::Expr;
+use crate::diagnostic::Diagnostic;
+use crate::lexer::{Lexer, Token};
+use crate::parser::Parser;
+use crate::span::{NodeSpans, Span};
+use crate::stack;
+use std::collections::HashMap;
+use std::fmt;
+
+/// Kind of a lossless token.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SyntaxKind {
+    /// A significant token
+    Token(Token),
+    /// Run of whitespace
+    Whitespace,
+    /// `#` comment up to (not including) the end of the line
+    Comment,
+}
+
+impl SyntaxKind {
+    pub fn is_trivia(&self) -> bool {
+        matches!(self, SyntaxKind::Whitespace | SyntaxKind::Comment)
+    }
+}
+
+/// A token together with its location in the source.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SyntaxToken {
+    pub kind: SyntaxKind,
+    pub span: Span,
+}
+
+/// A parsed source file that remembers everything about its text.
+#[derive(Debug, Clone)]
+pub struct Cst {
+    source: String,
+    tokens: Vec<SyntaxToken>,
+    ast: Expr,
+    node_spans: NodeSpans,
+}
+
+impl Cst {
+    /// Parse source text into a CST.
+    pub fn parse(source: &str) -> Result<Cst, Box<Diagnostic>> {
+        let (tokens, spans) = Lexer::new(source)
+            .tokenize()
+            .map_err(Diagnostic::from)?;
+
+        let (ast, node_spans) = Parser::with_spans(&tokens, &spans)
+            .parse_with_spans()
+            .map_err(Diagnostic::from)?;
+
+        let mut syntax_tokens = Vec::with_capacity(tokens.len() * 2);
+        let mut pos = 0;
+        for (token, span) in tokens.into_iter().zip(spans) {
+            push_trivia(source, pos, span.start, &mut syntax_tokens);
+            syntax_tokens.push(SyntaxToken {
+                kind: SyntaxKind::Token(token),
+                span,
+            });
+            pos = span.end;
+        }
+        push_trivia(source, pos, source.len(), &mut syntax_tokens);
+
+        Ok(Cst {
+            source: source.to_string(),
+            tokens: syntax_tokens,
+            ast,
+            node_spans,
+        })
+    }
+
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// All tokens, trivia included, in source order.
+    pub fn tokens(&self) -> &[SyntaxToken] {
+        &self.tokens
+    }
+
+    pub fn ast(&self) -> &Expr {
+        &self.ast
+    }
+
+    /// Spans of the AST nodes, in post-order.
+    pub fn node_spans(&self) -> &NodeSpans {
+        &self.node_spans
+    }
+
+    /// Source text covered by a token or span.
+    pub fn text(&self, span: Span) -> &str {
+        &self.source[span.start..span.end]
+    }
+
+    /// Comment trivia, in source order.
+    pub fn comments(&self) -> impl Iterator<Item = &SyntaxToken> {
+        self.tokens
+            .iter()
+            .filter(|t| t.kind == SyntaxKind::Comment)
+    }
+
+    /// Map from each AST node (by address) to its source span.
+    pub fn span_map(&self) -> HashMap<*const Expr, Span> {
+        let mut map = HashMap::new();
+        let mut index = 0;
+        index_spans(&self.ast, &self.node_spans, &mut index, &mut map);
+        map
+    }
+}
+
+impl Drop for Cst {
+    fn drop(&mut self) {
+        stack::drop_tree(std::mem::replace(&mut self.ast, Expr::Int(0)));
+    }
+}
+
+impl fmt::Display for Cst {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for token in &self.tokens {
+            f.write_str(self.text(token.span))?;
+        }
+        Ok(())
+    }
+}
+
+fn index_spans(
+    expr: &Expr,
+    spans: &NodeSpans,
+    index: &mut usize,
+    map: &mut HashMap<*const Expr, Span>,
+) {
+    for child in expr.children() {
+        stack::guard(|| index_spans(child, spans, index, map));
+    }
+    if let Some(span) = spans.get(*index) {
+        map.insert(expr as *const Expr, span);
+    }
+    *index += 1;
+}
+
+/// Split the gap `start..end` between significant tokens into trivia.
+fn push_trivia(source: &str, start: usize, end: usize, out: &mut Vec<SyntaxToken>) {
+    let gap = &source[start..end];
+    let mut offset = 0;
+    while offset < gap.len() {
+        let rest = &gap[offset..];
+        let (kind, len) = if rest.starts_with('#') {
+            (SyntaxKind::Comment, rest.find('\n').unwrap_or(rest.len()))
+        } else {
+            let len = rest.find('#').unwrap_or(rest.len());
+            (SyntaxKind::Whitespace, len)
+        };
+        out.push(SyntaxToken {
+            kind,
+            span: Span::new(start + offset, start + offset + len),
+        });
+        offset += len;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lossless_roundtrip() {
+        let src = "# header\nlet x = secret(1)   # trailing\n\n  x +  2 # end";
+        let cst = Cst::parse(src).unwrap();
+        assert_eq!(cst.to_string(), src);
+    }
+
+    #[test]
+    fn test_comments() {
+        let cst = Cst::parse("# a\n1 # b").unwrap();
+        let comments: Vec<_> = cst.comments().map(|t| cst.text(t.span)).collect();
+        assert_eq!(comments, vec!["# a", "# b"]);
+    }
+
+    #[test]
+    fn test_span_map() {
+        let cst = Cst::parse("let x = 1 x").unwrap();
+        let map = cst.span_map();
+        let root = cst.ast() as *const Expr;
+        assert_eq!(map[&root], Span::new(0, 11));
+    }
+}
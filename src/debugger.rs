@@ -0,0 +1,160 @@
+// SPDX-License-Identifier: MIT OR AGPL-3.0-or-later
+// SPDX-FileCopyrightText: 2024-2025 hyperpolymath
+
+//! Step debugger on the reference interpreter.
+//!
+//! MiniObli programs are pure, so the debugger evaluates the program once
+//! with [`eval_traced`] and then moves over the recorded trace: every node
+//! is a stop when its evaluation starts and again when it produces a value.
+//! Breakpoints are source spans; a breakpoint is hit when evaluation enters
+//! a node whose span starts inside it.
+
+use crate::interp::{eval_traced, EvalError, Inputs, Labeled, TraceEvent};
+use crate::ir::ObliExpr;
+use crate::run::{prepare_with_spans, RunError};
+use crate::span::{NodeSpans, Span};
+use crate::symbol::Symbol;
+
+/// A point where the debugger can stop.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Stop {
+    pub span: Span,
+    pub depth: usize,
+    /// `None` on entry to the node, its value on exit.
+    pub value: Option<Labeled>,
+    /// Variables in scope, innermost last, with their secrecy labels.
+    pub scope: Vec<(Symbol, Labeled)>,
+}
+
+/// Steps through one evaluation of a program.
+#[derive(Debug)]
+pub struct Debugger {
+    stops: Vec<Stop>,
+    result: Result<Labeled, EvalError>,
+    position: usize,
+    breakpoints: Vec<Span>,
+}
+
+impl Debugger {
+    /// Debug `source` with its free variables bound to `inputs`.
+    pub fn from_source(source: &str, inputs: &Inputs) -> Result<Self, RunError> {
+        let (ir, spans) = prepare_with_spans(source, inputs)?;
+        Ok(Self::new(&ir, &spans, inputs))
+    }
+
+    /// Debug an IR tree with its post-order spans.
+    pub fn new(expr: &ObliExpr, spans: &NodeSpans, inputs: &Inputs) -> Self {
+        let order = expr.post_order();
+        let span_of = |node: &ObliExpr| {
+            let index = order.iter().position(|n| std::ptr::eq(*n, node));
+            index.and_then(|i| spans.get(i)).unwrap_or_default()
+        };
+
+        let mut trace = Vec::new();
+        let result = eval_traced(expr, inputs, &mut trace);
+        let stops = trace
+            .into_iter()
+            .map(|TraceEvent { expr, depth, value, scope }| Stop {
+                span: span_of(expr),
+                depth,
+                value,
+                scope,
+            })
+            .collect();
+        Self {
+            stops,
+            result,
+            position: 0,
+            breakpoints: Vec::new(),
+        }
+    }
+
+    pub fn add_breakpoint(&mut self, span: Span) {
+        self.breakpoints.push(span);
+    }
+
+    pub fn clear_breakpoints(&mut self) {
+        self.breakpoints.clear();
+    }
+
+    /// Where the debugger is stopped, or `None` once evaluation has finished.
+    pub fn current(&self) -> Option<&Stop> {
+        self.stops.get(self.position)
+    }
+
+    /// Result of the whole program.
+    pub fn result(&self) -> &Result<Labeled, EvalError> {
+        &self.result
+    }
+
+    /// Go to the next stop, entering sub-expressions.
+    pub fn step(&mut self) -> Option<&Stop> {
+        self.position = (self.position + 1).min(self.stops.len());
+        self.current()
+    }
+
+    /// Go to the next stop at the same or an outer level, skipping over the
+    /// evaluation of the current node's sub-expressions.
+    pub fn step_over(&mut self) -> Option<&Stop> {
+        let depth = self.current()?.depth;
+        let from = self.position + 1;
+        self.position = self.stops[from..]
+            .iter()
+            .position(|stop| stop.depth <= depth)
+            .map_or(self.stops.len(), |i| from + i);
+        self.current()
+    }
+
+    /// Run to the next breakpoint, or to the end.
+    pub fn resume(&mut self) -> Option<&Stop> {
+        let from = self.position + 1;
+        self.position = (from..self.stops.len())
+            .find(|&i| self.hits_breakpoint(i))
+            .unwrap_or(self.stops.len());
+        self.current()
+    }
+
+    /// Whether stop `i` enters a breakpoint: it starts a node inside the
+    /// breakpoint, and the previous stop was outside it. Nodes nested in an
+    /// expression that already hit do not hit again.
+    fn hits_breakpoint(&self, i: usize) -> bool {
+        let stop = &self.stops[i];
+        let inside = |bp: &Span, span: Span| !span.is_empty() && bp.contains(span.start);
+        stop.value.is_none()
+            && self.breakpoints.iter().any(|bp| {
+                inside(bp, stop.span) && (i == 0 || !inside(bp, self.stops[i - 1].span))
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interp::Value;
+
+    const SRC: &str = "let k = secret(5)\nlet y = k * 2\ny + 1";
+
+    #[test]
+    fn test_step_and_step_over() {
+        let mut debugger = Debugger::from_source(SRC, &Inputs::new()).unwrap();
+        assert_eq!(debugger.current().unwrap().span, Span::new(0, SRC.len()));
+        let stop = debugger.step().unwrap();
+        assert_eq!(&SRC[stop.span.start..stop.span.end], "secret(5)");
+        let stop = debugger.step_over().unwrap();
+        assert_eq!(stop.value, Some(Labeled::secret(Value::Int(5))));
+        while debugger.step().is_some() {}
+        assert_eq!(debugger.result(), &Ok(Labeled::secret(Value::Int(11))));
+    }
+
+    #[test]
+    fn test_breakpoint_and_scope() {
+        let mut debugger = Debugger::from_source(SRC, &Inputs::new()).unwrap();
+        let line3 = SRC.rfind('\n').unwrap() + 1;
+        debugger.add_breakpoint(Span::new(line3, SRC.len()));
+        let stop = debugger.resume().unwrap();
+        assert_eq!(&SRC[stop.span.start..stop.span.end], "y + 1");
+        let names: Vec<_> = stop.scope.iter().map(|(n, v)| (n.as_str(), v.secret)).collect();
+        assert_eq!(names, vec![("k", true), ("y", true)]);
+        assert!(debugger.resume().is_none());
+    }
+}
@@ -0,0 +1,2161 @@
+// SPDX-License-Identifier: MIT OR AGPL-3.0-or-later
+// SPDX-FileCopyrightText: 2024-2025 hyperpolymath
+
+//! Lowering of surface constructs the IR has no nodes for: functions,
+//! `for` loops, `map` and `fold`, fixed-size arrays and byte strings,
+//! tuples, options, `sort`, `lookup` tables and `match`.
+//!
+//! Functions and loops go first: [`expand`] replaces every call with the
+//! body of the function, its arguments bound to the parameters by `let`s,
+//! and drops the definitions. Each call site is a copy of its own, so the
+//! transform infers the secrecy of every call from the arguments it gets:
+//!
+//! ```text
+//! fn add(x, y) = x + y  add(secret(a), 1)  =>  let x = secret(a) let y = 1 x + y
+//! ```
+//!
+//! A `#[max_depth(n)]` function's calls inside its own body are inlined
+//! the same way, up to `n` calls deep; a call deeper than that is the zero
+//! of the function's result type, as found by the type checker, under an
+//! `assert(false)`, so the program fails if it runs. The type checker
+//! rejects one that runs whatever the inputs.
+//!
+//! A loop is unrolled into one copy of its body per iteration, each
+//! rebinding the accumulator, with the induction variable a constant:
+//!
+//! ```text
+//! for i in 0..2 with acc = 0 do acc + a[i]
+//!   =>  let acc = 0 let acc = (let i = 0 acc + a[i]) let acc = (let i = 1 acc + a[i]) acc
+//! ```
+//!
+//! `map` and `fold` are unrolled too, with one call per element of the
+//! array, whose length the type checker found, read at a constant index:
+//!
+//! ```text
+//! fold(a, 0, f)  =>  let t0 = a let t1 = 0 let t1 = f(t1, t0[0]) let t1 = f(t1, t0[1]) t1
+//! ```
+//!
+//! [`expand`] also turns each assignment to a `let mut` variable into a
+//! `let` rebinding it, with the annotation of its declaration. Under a
+//! condition the old value is kept unless the condition holds, so a secret
+//! condition becomes a `CtSelect`:
+//!
+//! ```text
+//! let mut c = 0; if k > 3 then c = c + 1; c  =>  let c = 0 let c = (if k > 3 then c + 1 else c) c
+//! ```
+//!
+//! It folds constants too: each use of a `const` becomes its value, each
+//! variant of an `enum` its index, and the declaration is dropped.
+//!
+//! [`promote`] then makes the integer conversions the type checker allows
+//! explicit: an unsuffixed literal converted to a sized type becomes a
+//! literal of that type, and any other converted value a cast, which the
+//! transform lowers to a `CtCast`:
+//!
+//! ```text
+//! let x: u16 = 1u8  x + 2  =>  let x: u16 = 1u8 as u16  x + 2u16
+//! ```
+//!
+//! The IR has no arrays and no memory accesses. Before the oblivious
+//! transform, [`desugar`] binds every element of an array to a fresh
+//! `let` and rewrites each read `a[i]` into a scan over all of them:
+//!
+//! ```text
+//! if i == 0 then a0 else if i == 1 then a1 else ... a{n-1}
+//! ```
+//!
+//! The transform then treats the scan like any other `if` chain: for a
+//! secret index every comparison is secret, so the chain becomes
+//! `CtSelect(ct_eq(i, k), a_k, acc)` folded over all `k`, and the access
+//! pattern is the same whichever element is read; the emitter turns the
+//! chain back into a masked select loop over the elements. A public index
+//! keeps public `if`s. An index outside the array reads the last element.
+//!
+//! Tuples are lowered like arrays, one `let` per component, and
+//! `let (x, y) = t` reads `x` and `y` from the components' temporaries.
+//! Each component is a variable of its own in the IR, so a tuple of a
+//! public and a secret value keeps the public one public:
+//!
+//! ```text
+//! let (q, r) = (n / 2, secret(k)) q + 1  =>  let t0 = n / 2 let t1 = secret(k) t0 + 1
+//! ```
+//!
+//! An option is a pair too, of a flag whether it holds a value and the
+//! value, and `unwrap_or` reads it with an `if`:
+//!
+//! ```text
+//! unwrap_or(some(v), d)  =>  let t0 = true let t1 = v if t0 then t1 else d
+//! ```
+//!
+//! A flag chosen under a secret condition is secret, so the read becomes a
+//! `CtSelect`. The value of a `none` is never read, and is not selected.
+//!
+//! Arrays compared with `==` become the conjunction of the comparisons of
+//! every pair of elements, and with `!=` the disjunction. `&&` and `||` are
+//! constant-time in the IR, so the comparison always runs the full length,
+//! wherever the first difference is:
+//!
+//! ```text
+//! a == b  =>  a0 == b0 && a1 == b1 && ... && a{n-1} == b{n-1}
+//! ```
+//!
+//! `sort(a)` becomes Batcher's merge-exchange sorting network over the
+//! elements (see [`sorting_network`]). Each compare-exchange is a
+//! `swap_if` of two wires:
+//!
+//! ```text
+//! let (lo, hi) = swap_if(x > y, x, y)
+//! ```
+//!
+//! The network depends only on the length, so a secret array is sorted
+//! with the same `O(n log² n)` comparisons and swaps whatever it holds.
+//! A `swap_if` elsewhere is bound to two temporaries the same way; the
+//! `let` of its pair is the one tuple left for the transform.
+//!
+//! `lookup(k, [(k1, v1), (k2, v2)], d)` becomes the same kind of scan,
+//! comparing with each entry key in turn:
+//!
+//! ```text
+//! if k == k1 then v1 else if k == k2 then v2 else d
+//! ```
+//!
+//! so the first matching entry wins. The key, every entry and the default
+//! are bound to temporaries first, so all of them are evaluated whichever
+//! entry matches.
+//!
+//! `clamp(x, lo, hi)` and `in_range(x, lo, hi)` bind their operands to
+//! temporaries too, and compare them:
+//!
+//! ```text
+//! clamp(x, lo, hi)     =>  if x < lo then lo else if x > hi then hi else x
+//! in_range(x, lo, hi)  =>  lo <= x and x <= hi
+//! ```
+//!
+//! so a secret operand makes the comparisons secret, and the `if`s become
+//! `CtSelect`s.
+//!
+//! `pow(x, n)` becomes square-and-multiply over the bits of the constant
+//! `n`, most significant first, each step a temporary:
+//!
+//! ```text
+//! pow(x, 5)  =>  let t0 = x let t1 = t0 * t0 let t2 = t1 * t1 let t3 = t2 * t0 t3
+//! ```
+//!
+//! The type checker only accepts a constant exponent, so the chain, and
+//! the `CtMul`s a secret base turns it into, is the same for every base.
+//!
+//! `match k with p1 => e1, p2 => e2, _ => d` is the same chain with the
+//! patterns as keys (a boolean pattern tests the scrutinee itself), but
+//! only the scrutinee is bound to a temporary: the arms stay in the
+//! branches of the `if`s. A secret scrutinee makes every
+//! condition secret, so the transform evaluates every arm and folds them
+//! with `CtSelect`; a public one keeps public `if`s that run only the arm
+//! taken.
+//!
+//! Temporaries come from a [`NameSupply`]: `__obli_t0`, `__obli_t1`, …
+//! skipping any name the program already uses. Every node of the rewritten program carries the
+//! span of the source node it came from.
+
+use crate::ast::{Annotation, BinOp, Expr, UnaryOp};
+use crate::interp::{apply_binop, apply_cast, apply_unaryop, Value};
+use crate::ir::{ObliBinOp, ObliUnaryOp};
+use crate::node::{Boxed, Build, Node};
+use crate::span::{NodeSpans, Span, SpanCursor};
+use crate::stack;
+use crate::symbol::{NameSupply, Scope, Symbol};
+use crate::typeck::{mapped_lengths, node_types, promotions, recursive_results, Scalar, Type};
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+
+/// Whether `expr` has any function, loop, array, tuple or byte-string
+/// literal, option, destructuring `let` other than of a `swap_if`, `sort`,
+/// index, `lookup`, `match`, `clamp`, `in_range` or `pow`.
+pub fn needs_desugaring(expr: &Expr) -> bool {
+    let swap = |expr: &Expr| matches!(expr, Expr::SwapIf { .. });
+    any_node(expr, |expr| match expr {
+        // The pair of a `swap_if` is the one tuple the IR binds
+        Expr::LetTuple { value, .. } => !swap(value),
+        _ => {
+            expr.children().into_iter().any(swap)
+                || matches!(
+                    expr,
+                    Expr::Array(_)
+                        | Expr::Tuple(_)
+                        | Expr::Bytes(_)
+                        | Expr::Sort(_)
+                        | Expr::Index { .. }
+                        | Expr::Lookup { .. }
+                        | Expr::Match { .. }
+                        | Expr::Clamp { .. }
+                        | Expr::InRange { .. }
+                        | Expr::Pow { .. }
+                        | Expr::Map { .. }
+                        | Expr::Fold { .. }
+                        | Expr::Some(_)
+                        | Expr::None
+                        | Expr::UnwrapOr { .. }
+                        | Expr::Fn { .. }
+                        | Expr::Call { .. }
+                        | Expr::Lambda { .. }
+                        | Expr::Const { .. }
+                        | Expr::Enum { .. }
+                        | Expr::For { .. }
+                        | Expr::Let { mutable: true, .. }
+                        | Expr::Assign { .. }
+                )
+        }
+    })
+}
+
+/// Whether `expr` defines or calls a function, has a loop, a `map` or
+/// `fold`, mutable variables, constants or enums.
+pub(crate) fn needs_expanding(expr: &Expr) -> bool {
+    any_node(expr, |expr| {
+        matches!(
+            expr,
+            Expr::Fn { .. }
+                | Expr::Call { .. }
+                | Expr::Lambda { .. }
+                | Expr::Map { .. }
+                | Expr::Fold { .. }
+                | Expr::Const { .. }
+                | Expr::Enum { .. }
+                | Expr::For { .. }
+                | Expr::Let { mutable: true, .. }
+                | Expr::Assign { .. }
+        )
+    })
+}
+
+pub(crate) fn any_node(expr: &Expr, predicate: impl Fn(&Expr) -> bool) -> bool {
+    let mut pending = vec![expr];
+    while let Some(expr) = pending.pop() {
+        if predicate(expr) {
+            return true;
+        }
+        pending.extend(expr.children());
+    }
+    false
+}
+
+/// Rewrite a typechecked program without functions, loops, arrays, tuples,
+/// `sort`s or `lookup`s.
+pub fn desugar(expr: &Expr) -> Expr {
+    if needs_expanding(expr) {
+        return desugar(&expand(expr));
+    }
+    if needs_promoting(expr) {
+        return desugar(&promote(expr));
+    }
+    Desugarer::new(expr, None).run(expr).0
+}
+
+/// [`desugar`], mapping the AST node spans produced by
+/// [`Parser::parse_with_spans`](crate::parser::Parser::parse_with_spans)
+/// onto the rewritten program (again in post-order).
+pub fn desugar_with_spans(expr: &Expr, spans: &NodeSpans) -> (Expr, NodeSpans) {
+    if needs_expanding(expr) {
+        let (expr, spans) = expand_with_spans(expr, spans);
+        return desugar_with_spans(&expr, &spans);
+    }
+    if needs_promoting(expr) {
+        let (expr, spans) = promote_with_spans(expr, spans);
+        return desugar_with_spans(&expr, &spans);
+    }
+    Desugarer::new(expr, Some(spans)).run(expr)
+}
+
+/// Rewrite a typechecked program without functions, loops or
+/// assignments, replacing every call by the body of the function it calls,
+/// unrolling every loop and rebinding every assigned variable.
+pub fn expand(expr: &Expr) -> Expr {
+    Expander::new(expr, None).run(expr).0
+}
+
+/// [`expand`], mapping the spans of `expr` onto the rewritten
+/// program like [`desugar_with_spans`]. The nodes of an inlined body keep
+/// their spans in the definition; the `let`s binding the arguments get the
+/// span of the call. Unrolled loops keep the spans of their bodies, and
+/// the `let`s they introduce get the span of the loop.
+pub fn expand_with_spans(expr: &Expr, spans: &NodeSpans) -> (Expr, NodeSpans) {
+    Expander::new(expr, Some(spans)).run(expr)
+}
+
+/// Whether the expanded program `expr` converts an integer implicitly.
+/// Only sized integers convert, so a program without any is not checked.
+pub(crate) fn needs_promoting(expr: &Expr) -> bool {
+    let sized = |ty: &Scalar| matches!(ty, Scalar::Sized(_));
+    any_node(expr, |expr| match expr {
+        Expr::Sized(..) => true,
+        Expr::Input { ty, .. } | Expr::Cast { ty, .. } => sized(ty),
+        Expr::Let { ty: Some(annotation), .. } => sized(&annotation.ty),
+        _ => false,
+    }) && !promotions(expr).is_empty()
+}
+
+/// Rewrite an expanded, typechecked program with every implicit integer
+/// conversion explicit: a converted unsuffixed literal becomes a literal
+/// of the sized type, and any other converted value a cast.
+pub fn promote(expr: &Expr) -> Expr {
+    Promoter::new(expr, None).run(expr).0
+}
+
+/// [`promote`], mapping the spans of `expr` onto the rewritten program
+/// like [`desugar_with_spans`]; a cast gets the span of the value it
+/// converts.
+pub fn promote_with_spans(expr: &Expr, spans: &NodeSpans) -> (Expr, NodeSpans) {
+    Promoter::new(expr, Some(spans)).run(expr)
+}
+
+/// Each call of a `#[max_depth]` function nested deeper than its bound
+/// that runs whenever the program does: one under no `if` whose condition
+/// is secret or not a constant once the calls around it are inlined. Such
+/// a call fails every run of the program. Gives the function's name and
+/// bound and the call's span, once per call site.
+pub(crate) fn truncated_calls(
+    expr: &Expr,
+    spans: Option<&NodeSpans>,
+) -> Vec<(Symbol, u32, Span)> {
+    let mut expander = Expander::new(expr, spans);
+    expander.truncated = Some(HashMap::new());
+    let expanded = expander.expand(expr).expr;
+    let mut reach = Reach {
+        truncated: expander.truncated.take().unwrap_or_default(),
+        known: Scope::new(),
+        found: Vec::new(),
+    };
+    reach.visit(&expanded, true);
+    stack::drop_tree(expanded);
+    reach.found
+}
+
+/// A rewritten expression and the spans of its nodes, in a tree of the
+/// same shape.
+struct Lowered {
+    expr: Expr,
+    spans: SpanTree,
+}
+
+struct SpanTree {
+    span: Span,
+    children: Vec<SpanTree>,
+}
+
+/// Build a node from rewritten children.
+fn build(span: Span, node: Node<Lowered>) -> Lowered {
+    let mut children = Vec::new();
+    let node = node.map(|child| {
+        children.push(child.spans);
+        child.expr
+    });
+    Lowered {
+        expr: Boxed.build(node),
+        spans: SpanTree { span, children },
+    }
+}
+
+fn var(span: Span, name: Symbol) -> Lowered {
+    build(span, Node::Var(name))
+}
+
+/// A copy of a literal or variable.
+fn leaf(span: Span, expr: &Expr) -> Lowered {
+    Lowered {
+        expr: expr.clone(),
+        spans: SpanTree {
+            span,
+            children: Vec::new(),
+        },
+    }
+}
+
+/// What a source variable stands for in the rewritten program.
+#[derive(Debug, Clone)]
+enum Binding {
+    Scalar(Symbol),
+    /// The temporaries holding the elements.
+    Array(Vec<Symbol>),
+}
+
+/// A `let` to wrap around the rewritten expression.
+struct Temp {
+    name: Symbol,
+    ty: Option<Annotation>,
+    value: Lowered,
+    span: Span,
+    /// For a `swap_if` value, the name of its second component, `name`
+    /// being that of the first.
+    pair: Option<Symbol>,
+}
+
+/// How an array is indexed.
+enum Key {
+    Const(i64),
+    Var(Symbol),
+}
+
+struct Desugarer {
+    /// Span and type of every source node, by address.
+    nodes: HashMap<*const Expr, (Span, Option<Type>)>,
+    scope: Scope<Binding>,
+    names: NameSupply,
+    /// The conditions of the `if`s whose arms are hoisted while binding
+    /// their elements, each with whether the arm is the `then` one.
+    arms: Vec<(Symbol, bool)>,
+}
+
+impl Desugarer {
+    fn new(expr: &Expr, spans: Option<&NodeSpans>) -> Self {
+        let mut post_order = Vec::new();
+        let mut used = HashSet::new();
+        collect(expr, &mut post_order, &mut used);
+        let mut cursor = SpanCursor::new(spans);
+        let nodes = post_order
+            .into_iter()
+            .zip(node_types(expr))
+            .map(|(node, ty)| (node, (cursor.next_span().unwrap_or_default(), ty)))
+            .collect();
+        Self {
+            nodes,
+            scope: Scope::new(),
+            names: NameSupply::new(used),
+            arms: Vec::new(),
+        }
+    }
+
+    fn run(mut self, expr: &Expr) -> (Expr, NodeSpans) {
+        let lowered = self.lower(expr);
+        let mut spans = NodeSpans::new();
+        flatten(lowered.spans, &mut spans);
+        (lowered.expr, spans)
+    }
+
+    fn span(&self, expr: &Expr) -> Span {
+        self.nodes.get(&(expr as *const Expr)).map(|n| n.0).unwrap_or_default()
+    }
+
+    fn ty(&self, expr: &Expr) -> Option<&Type> {
+        self.nodes.get(&(expr as *const Expr)).and_then(|n| n.1.as_ref())
+    }
+
+    /// Whether `expr` is an array, or a tuple or option, which is lowered
+    /// like one. A `swap_if` is a pair even where its operands have no
+    /// known type.
+    fn is_array(&self, expr: &Expr) -> bool {
+        let ty = self.ty(expr);
+        matches!(ty, Some(Type::Array(..) | Type::Tuple(_) | Type::Option(_)))
+            || matches!(expr, Expr::SwapIf { .. })
+    }
+
+    /// Whether `expr` is an option only `none`s build, which never holds
+    /// a value.
+    fn is_none(&self, expr: &Expr) -> bool {
+        self.ty(expr) == Some(&Type::Option(None))
+    }
+
+    fn fresh(&mut self) -> Symbol {
+        self.names.fresh()
+    }
+
+    /// Rewrite a scalar expression.
+    fn lower(&mut self, expr: &Expr) -> Lowered {
+        stack::guard(|| self.lower_node(expr))
+    }
+
+    fn lower_node(&mut self, expr: &Expr) -> Lowered {
+        let span = self.span(expr);
+        let node = match expr {
+            Expr::Int(n) => Node::Int(*n),
+            Expr::Sized(n, width) => Node::Sized(*n, *width),
+            Expr::Bool(b) => Node::Bool(*b),
+            Expr::Var(name) => match self.scope.get(*name) {
+                Some(Binding::Scalar(renamed)) => Node::Var(*renamed),
+                _ => Node::Var(*name),
+            },
+            &Expr::Input { name, secret, ty } => Node::Input { name, secret, ty },
+            Expr::Secret(inner) => Node::Secret(self.lower(inner)),
+            Expr::Declassify(inner) => Node::Declassify(self.lower(inner)),
+            Expr::BinOp {
+                op: op @ (BinOp::Eq | BinOp::Ne),
+                left,
+                right,
+            } if self.is_array(left) => {
+                let (mut temps, left) = self.elements(left);
+                let (right_temps, right) = self.elements(right);
+                temps.extend(right_temps);
+                return wrap(temps, compare(span, op, &left, &right));
+            }
+            Expr::BinOp { op, left, right } => Node::BinOp {
+                op: op.clone(),
+                left: self.lower(left),
+                right: self.lower(right),
+            },
+            Expr::UnaryOp { op, expr } => Node::UnaryOp {
+                op: op.clone(),
+                expr: self.lower(expr),
+            },
+            Expr::Cast { expr, ty } => Node::Cast {
+                expr: self.lower(expr),
+                ty: *ty,
+            },
+            Expr::If {
+                cond,
+                then_branch,
+                else_branch,
+            } => Node::If {
+                cond: self.lower(cond),
+                then_branch: self.lower(then_branch),
+                else_branch: self.lower(else_branch),
+            },
+            Expr::Let {
+                name, value, body, ..
+            } if self.is_array(value) => {
+                let (temps, elements) = self.elements(value);
+                self.scope.push(*name, Binding::Array(elements));
+                let body = self.lower(body);
+                self.scope.pop();
+                return wrap(temps, body);
+            }
+            Expr::LetTuple { names, value, body } => {
+                let (temps, components) = self.elements(value);
+                for (name, component) in names.iter().zip(components) {
+                    self.scope.push(*name, Binding::Scalar(component));
+                }
+                let body = self.lower(body);
+                for _ in names {
+                    self.scope.pop();
+                }
+                return wrap(temps, body);
+            }
+            Expr::Let {
+                name,
+                ty,
+                value,
+                body,
+                ..
+            } => {
+                let value = self.lower(value);
+                self.scope.push(*name, Binding::Scalar(*name));
+                let body = self.lower(body);
+                self.scope.pop();
+                Node::Let {
+                    name: *name,
+                    ty: *ty,
+                    mutable: false,
+                    value,
+                    body,
+                }
+            }
+            Expr::Index { array, index } => {
+                let (mut temps, elements) = self.elements(array);
+                let key = match index.as_ref() {
+                    Expr::Int(k) => Key::Const(*k),
+                    _ => {
+                        let name = self.fresh();
+                        let value = self.lower(index);
+                        temps.push(Temp { name, ty: None, value, span, pair: None });
+                        Key::Var(name)
+                    }
+                };
+                return wrap(temps, scan(span, &elements, key));
+            }
+            Expr::Lookup {
+                key,
+                entries,
+                default,
+            } => {
+                let mut temps = Vec::new();
+                let key = self.operand(key, &mut temps);
+                let entries: Vec<_> = entries
+                    .iter()
+                    .map(|(k, v)| (self.operand(k, &mut temps), self.operand(v, &mut temps)))
+                    .collect();
+                let default = self.operand(default, &mut temps);
+                let mut acc = leaf(span, &default);
+                for (k, v) in entries.into_iter().rev() {
+                    let cond = Node::BinOp {
+                        op: BinOp::Eq,
+                        left: leaf(span, &key),
+                        right: leaf(span, &k),
+                    };
+                    let node = Node::If {
+                        cond: build(span, cond),
+                        then_branch: leaf(span, &v),
+                        else_branch: acc,
+                    };
+                    acc = build(span, node);
+                }
+                return wrap(temps, acc);
+            }
+            Expr::Match {
+                scrutinee,
+                arms,
+                default,
+            } => {
+                let mut temps = Vec::new();
+                let scrutinee = self.operand(scrutinee, &mut temps);
+                let mut arms: Vec<_> = arms.iter().map(|(p, e)| (p, self.lower(e))).collect();
+                // Without a wildcard the patterns cover every variant, so
+                // the last arm is taken when no other is
+                let mut acc = match default {
+                    Some(default) => self.lower(default),
+                    None => arms.pop().expect("a match has an arm").1,
+                };
+                for (pattern, arm) in arms.into_iter().rev() {
+                    // A boolean is its own test
+                    let cond = match pattern {
+                        Expr::Bool(true) => leaf(span, &scrutinee),
+                        Expr::Bool(false) => {
+                            let op = UnaryOp::Not;
+                            build(span, Node::UnaryOp { op, expr: leaf(span, &scrutinee) })
+                        }
+                        _ => {
+                            let cond = Node::BinOp {
+                                op: BinOp::Eq,
+                                left: leaf(span, &scrutinee),
+                                right: leaf(self.span(pattern), pattern),
+                            };
+                            build(span, cond)
+                        }
+                    };
+                    let node = Node::If {
+                        cond,
+                        then_branch: arm,
+                        else_branch: acc,
+                    };
+                    acc = build(span, node);
+                }
+                return wrap(temps, acc);
+            }
+            Expr::UnwrapOr { option, default } => {
+                let (temps, elements) = self.elements(option);
+                let node = match elements[..] {
+                    [present, value] if !self.is_none(option) => {
+                        let node = Node::If {
+                            cond: var(span, present),
+                            then_branch: var(span, value),
+                            else_branch: self.lower(default),
+                        };
+                        build(span, node)
+                    }
+                    _ => self.lower(default),
+                };
+                return wrap(temps, node);
+            }
+            Expr::Clamp { value, lo, hi } | Expr::InRange { value, lo, hi } => {
+                let mut temps = Vec::new();
+                let [value, lo, hi] = [value, lo, hi].map(|e| self.operand(e, &mut temps));
+                let compare = |op, left: &Expr, right: &Expr| {
+                    let (left, right) = (leaf(span, left), leaf(span, right));
+                    build(span, Node::BinOp { op, left, right })
+                };
+                let node = match expr {
+                    Expr::Clamp { .. } => {
+                        let above = Node::If {
+                            cond: compare(BinOp::Gt, &value, &hi),
+                            then_branch: leaf(span, &hi),
+                            else_branch: leaf(span, &value),
+                        };
+                        Node::If {
+                            cond: compare(BinOp::Lt, &value, &lo),
+                            then_branch: leaf(span, &lo),
+                            else_branch: build(span, above),
+                        }
+                    }
+                    _ => Node::BinOp {
+                        op: BinOp::And,
+                        left: compare(BinOp::Le, &lo, &value),
+                        right: compare(BinOp::Le, &value, &hi),
+                    },
+                };
+                return wrap(temps, build(span, node));
+            }
+            Expr::Pow { base, exp } => {
+                let n = exp.const_int().unwrap_or(0);
+                if n <= 0 {
+                    let one = match self.ty(expr) {
+                        Some(&Type::Sized(width)) => Expr::Sized(1, width),
+                        _ => Expr::Int(1),
+                    };
+                    return leaf(span, &one);
+                }
+                let mut temps = Vec::new();
+                let base = self.operand(base, &mut temps);
+                let mut acc = base.clone();
+                for bit in (0..n.ilog2()).rev() {
+                    let mut factors = vec![acc.clone()];
+                    if n >> bit & 1 == 1 {
+                        factors.push(base.clone());
+                    }
+                    for factor in factors {
+                        let node = Node::BinOp {
+                            op: BinOp::Mul,
+                            left: leaf(span, &acc),
+                            right: leaf(span, &factor),
+                        };
+                        let name = self.fresh();
+                        let value = build(span, node);
+                        temps.push(Temp { name, ty: None, value, span, pair: None });
+                        acc = Expr::Var(name);
+                    }
+                }
+                return wrap(temps, leaf(span, &acc));
+            }
+            Expr::While {
+                cond,
+                acc,
+                init,
+                body,
+            } => {
+                self.scope.push(*acc, Binding::Scalar(*acc));
+                let cond = self.lower(cond);
+                self.scope.pop();
+                let init = self.lower(init);
+                self.scope.push(*acc, Binding::Scalar(*acc));
+                let body = self.lower(body);
+                self.scope.pop();
+                Node::While {
+                    cond,
+                    acc: *acc,
+                    init,
+                    body,
+                }
+            }
+            Expr::Assert { cond, body } => {
+                let cond = self.lower(cond);
+                Node::Assert {
+                    cond: self.guarded(span, cond),
+                    body: self.lower(body),
+                }
+            }
+            Expr::Outputs(outputs) => Node::Outputs(
+                outputs.iter().map(|(name, value)| (*name, self.lower(value))).collect(),
+            ),
+            // Only reached for ill-typed programs
+            Expr::Array(_)
+            | Expr::Tuple(_)
+            | Expr::Bytes(_)
+            | Expr::Sort(_)
+            | Expr::SwapIf { .. }
+            | Expr::Some(_)
+            | Expr::None => return self.lower_array(expr),
+            Expr::Fn { .. }
+            | Expr::Call { .. }
+            | Expr::Lambda { .. }
+            | Expr::Map { .. }
+            | Expr::Fold { .. }
+            | Expr::Const { .. }
+            | Expr::Enum { .. }
+            | Expr::For { .. }
+            | Expr::Assign { .. } => unreachable!("expanded before lowering"),
+        };
+        build(span, node)
+    }
+
+    /// An ill-typed array in scalar position, as its last element.
+    fn lower_array(&mut self, expr: &Expr) -> Lowered {
+        let (temps, elements) = self.elements(expr);
+        let span = self.span(expr);
+        wrap(temps, scan(span, &elements, Key::Const(-1)))
+    }
+
+    /// Bind the elements of an array expression to temporaries: the
+    /// `let`s to evaluate first and the temporary of each element.
+    fn elements(&mut self, expr: &Expr) -> (Vec<Temp>, Vec<Symbol>) {
+        stack::guard(|| self.elements_node(expr))
+    }
+
+    fn elements_node(&mut self, expr: &Expr) -> (Vec<Temp>, Vec<Symbol>) {
+        let span = self.span(expr);
+        match expr {
+            Expr::Array(values) | Expr::Tuple(values) => {
+                let mut temps = Vec::new();
+                for value in values {
+                    let name = self.fresh();
+                    let span = self.span(value);
+                    let value = self.lower(value);
+                    temps.push(Temp { name, ty: None, value, span, pair: None });
+                }
+                let elements = temps.iter().map(|t| t.name).collect();
+                (temps, elements)
+            }
+            Expr::Some(_) | Expr::None => {
+                let (present, value) = match expr {
+                    Expr::Some(inner) => (true, self.lower(inner)),
+                    // Never read, as the option is absent
+                    _ => (false, build(span, Node::Int(0))),
+                };
+                let present = build(span, Node::Bool(present));
+                let temps: Vec<_> = [present, value]
+                    .into_iter()
+                    .map(|value| Temp { name: self.fresh(), ty: None, value, span, pair: None })
+                    .collect();
+                let elements = temps.iter().map(|t| t.name).collect();
+                (temps, elements)
+            }
+            Expr::Bytes(bytes) => {
+                let mut temps = Vec::new();
+                for &byte in bytes {
+                    let name = self.fresh();
+                    let value = build(span, Node::Int(byte.into()));
+                    temps.push(Temp { name, ty: None, value, span, pair: None });
+                }
+                let elements = temps.iter().map(|t| t.name).collect();
+                (temps, elements)
+            }
+            Expr::Sort(inner) => {
+                let (mut temps, mut wires) = self.elements(inner);
+                for (i, j) in sorting_network(wires.len()) {
+                    let (lo, hi) = self.cswap(span, wires[i], wires[j], &mut temps);
+                    wires[i] = lo;
+                    wires[j] = hi;
+                }
+                (temps, wires)
+            }
+            Expr::SwapIf { cond, left, right } => {
+                let node = Node::SwapIf {
+                    cond: self.lower(cond),
+                    left: self.lower(left),
+                    right: self.lower(right),
+                };
+                let (first, second) = (self.fresh(), self.fresh());
+                let temp = Temp {
+                    name: first,
+                    ty: None,
+                    value: build(span, node),
+                    span,
+                    pair: Some(second),
+                };
+                (vec![temp], vec![first, second])
+            }
+            Expr::Var(name) => match self.scope.get(*name) {
+                Some(Binding::Array(elements)) => (Vec::new(), elements.clone()),
+                _ => self.single(expr),
+            },
+            Expr::Secret(inner) => {
+                let (mut temps, inner) = self.elements(inner);
+                let mut elements = Vec::new();
+                for element in inner {
+                    let name = self.fresh();
+                    let value = build(span, Node::Secret(var(span, element)));
+                    temps.push(Temp { name, ty: None, value, span, pair: None });
+                    elements.push(name);
+                }
+                (temps, elements)
+            }
+            Expr::If {
+                cond,
+                then_branch,
+                else_branch,
+            } => {
+                let cond_name = self.fresh();
+                let cond_span = self.span(cond);
+                let cond = self.lower(cond);
+                let mut temps = vec![Temp {
+                    name: cond_name,
+                    ty: None,
+                    value: cond,
+                    span: cond_span,
+                    pair: None,
+                }];
+                // Both arms are evaluated, but their assertions only
+                // count in the arm taken
+                self.arms.push((cond_name, true));
+                let (then_temps, then_elements) = self.elements(then_branch);
+                self.arms.last_mut().expect("pushed above").1 = false;
+                let (else_temps, else_elements) = self.elements(else_branch);
+                self.arms.pop();
+                temps.extend(then_temps);
+                temps.extend(else_temps);
+                // The value of a `none` is never read, so where a branch is
+                // one the option takes the other's without a select
+                let nones = [then_branch, else_branch].map(|branch| self.is_none(branch));
+                let mut elements = Vec::new();
+                let pairs = then_elements.into_iter().zip(else_elements);
+                for (i, (then_element, else_element)) in pairs.enumerate() {
+                    if i == 1 && nones.contains(&true) {
+                        elements.push(if nones[1] { then_element } else { else_element });
+                        continue;
+                    }
+                    let name = self.fresh();
+                    let node = Node::If {
+                        cond: var(span, cond_name),
+                        then_branch: var(span, then_element),
+                        else_branch: var(span, else_element),
+                    };
+                    temps.push(Temp {
+                        name,
+                        ty: None,
+                        value: build(span, node),
+                        span,
+                        pair: None,
+                    });
+                    elements.push(name);
+                }
+                (temps, elements)
+            }
+            Expr::Let {
+                name, value, body, ..
+            } if self.is_array(value) => {
+                let (mut temps, elements) = self.elements(value);
+                self.scope.push(*name, Binding::Array(elements));
+                let (body_temps, elements) = self.elements(body);
+                self.scope.pop();
+                temps.extend(body_temps);
+                (temps, elements)
+            }
+            Expr::LetTuple { names, value, body } => {
+                let (mut temps, components) = self.elements(value);
+                for (name, component) in names.iter().zip(components) {
+                    self.scope.push(*name, Binding::Scalar(component));
+                }
+                let (body_temps, elements) = self.elements(body);
+                for _ in names {
+                    self.scope.pop();
+                }
+                temps.extend(body_temps);
+                (temps, elements)
+            }
+            Expr::Let {
+                name,
+                ty,
+                value,
+                body,
+                ..
+            } => {
+                // Hoisted out of its scope, so renamed
+                let renamed = self.fresh();
+                let value = self.lower(value);
+                let mut temps = vec![Temp {
+                    name: renamed,
+                    ty: *ty,
+                    value,
+                    span,
+                    pair: None,
+                }];
+                self.scope.push(*name, Binding::Scalar(renamed));
+                let (body_temps, elements) = self.elements(body);
+                self.scope.pop();
+                temps.extend(body_temps);
+                (temps, elements)
+            }
+            Expr::Assert { cond, body } => {
+                // The first element carries the assertion
+                let cond = self.lower(cond);
+                let cond = self.guarded(span, cond);
+                let (mut temps, mut elements) = self.elements(body);
+                let name = self.fresh();
+                let node = Node::Assert {
+                    cond,
+                    body: var(span, elements[0]),
+                };
+                temps.push(Temp { name, ty: None, value: build(span, node), span, pair: None });
+                elements[0] = name;
+                (temps, elements)
+            }
+            // Only reached for ill-typed programs
+            _ => self.single(expr),
+        }
+    }
+
+    /// An assertion's condition, holding too wherever an arm it was hoisted
+    /// out of is not taken.
+    fn guarded(&self, span: Span, cond: Lowered) -> Lowered {
+        self.arms.iter().rev().fold(cond, |cond, &(arm, then)| {
+            let mut skipped = var(span, arm);
+            if then {
+                skipped = build(span, Node::UnaryOp { op: UnaryOp::Not, expr: skipped });
+            }
+            build(span, Node::BinOp { op: BinOp::Or, left: skipped, right: cond })
+        })
+    }
+
+    /// Order `x` and `y` through one `swap_if`, binding the smaller and
+    /// the larger to new temporaries.
+    fn cswap(
+        &mut self,
+        span: Span,
+        x: Symbol,
+        y: Symbol,
+        temps: &mut Vec<Temp>,
+    ) -> (Symbol, Symbol) {
+        let cond = Node::BinOp {
+            op: BinOp::Gt,
+            left: var(span, x),
+            right: var(span, y),
+        };
+        let node = Node::SwapIf {
+            cond: build(span, cond),
+            left: var(span, x),
+            right: var(span, y),
+        };
+        let (lo, hi) = (self.fresh(), self.fresh());
+        temps.push(Temp {
+            name: lo,
+            ty: None,
+            value: build(span, node),
+            span,
+            pair: Some(hi),
+        });
+        (lo, hi)
+    }
+
+    /// Evaluate a scalar expression ahead of its use: a literal stays as
+    /// is, anything else is bound to a temporary and read from it.
+    fn operand(&mut self, expr: &Expr, temps: &mut Vec<Temp>) -> Expr {
+        if let Expr::Int(_) | Expr::Sized(..) | Expr::Bool(_) = expr {
+            return expr.clone();
+        }
+        let name = self.fresh();
+        let span = self.span(expr);
+        let value = self.lower(expr);
+        temps.push(Temp { name, ty: None, value, span, pair: None });
+        Expr::Var(name)
+    }
+
+    /// A scalar expression as a one-element array.
+    fn single(&mut self, expr: &Expr) -> (Vec<Temp>, Vec<Symbol>) {
+        let name = self.fresh();
+        let span = self.span(expr);
+        let value = self.lower(expr);
+        (vec![Temp { name, ty: None, value, span, pair: None }], vec![name])
+    }
+}
+
+/// A function the [`Expander`] can expand.
+struct Definition<'e> {
+    /// For a `#[max_depth]` function, the name its body calls it by and
+    /// the bound.
+    recursion: Option<(Symbol, u32)>,
+    params: &'e [Symbol],
+    body: &'e Expr,
+    /// Functions the body can call.
+    functions: Scope<Rc<Definition<'e>>>,
+    /// Constants the body sees.
+    consts: Scope<i64>,
+    /// Each variable a lambda's body reads from around it, and the
+    /// temporary holding its value where the lambda was written.
+    captures: Vec<(Symbol, Symbol)>,
+}
+
+/// An argument of a call: a value, or a function.
+enum Arg<'e> {
+    Value(Lowered),
+    Function(Rc<Definition<'e>>),
+}
+
+struct Expander<'e> {
+    /// Span of every source node, by address.
+    spans: HashMap<*const Expr, Span>,
+    functions: Scope<Rc<Definition<'e>>>,
+    /// Value of every constant in scope.
+    consts: Scope<i64>,
+    /// Annotation of every `let mut` variable in scope.
+    mutables: Scope<Option<Annotation>>,
+    names: NameSupply,
+    /// Result type of every recursive function called, by body.
+    results: HashMap<*const Expr, Type>,
+    /// Bodies of the calls being expanded, innermost last.
+    active: Vec<*const Expr>,
+    /// Length of the array of every `map` and `fold`, by the node.
+    lengths: HashMap<*const Expr, usize>,
+    /// For [`truncated_calls`], the fresh variable each call nested too
+    /// deep stands for instead of a zero, with the function's name and
+    /// bound and the call's span.
+    truncated: Option<HashMap<Symbol, (Symbol, u32, Span)>>,
+}
+
+impl<'e> Expander<'e> {
+    fn new(expr: &Expr, spans: Option<&NodeSpans>) -> Self {
+        let mut post_order = Vec::new();
+        let mut used = HashSet::new();
+        collect(expr, &mut post_order, &mut used);
+        let mut cursor = SpanCursor::new(spans);
+        let spans = post_order
+            .into_iter()
+            .map(|node| (node, cursor.next_span().unwrap_or_default()))
+            .collect();
+        let recursive = any_node(expr, |expr| {
+            matches!(expr, Expr::Fn { max_depth: Some(_), .. })
+        });
+        let mapped = any_node(expr, |expr| matches!(expr, Expr::Map { .. } | Expr::Fold { .. }));
+        Self {
+            spans,
+            functions: Scope::new(),
+            consts: Scope::new(),
+            mutables: Scope::new(),
+            names: NameSupply::new(used),
+            results: if recursive { recursive_results(expr) } else { HashMap::new() },
+            active: Vec::new(),
+            lengths: if mapped { mapped_lengths(expr) } else { HashMap::new() },
+            truncated: None,
+        }
+    }
+
+    fn run(mut self, expr: &'e Expr) -> (Expr, NodeSpans) {
+        let lowered = self.expand(expr);
+        let mut spans = NodeSpans::new();
+        flatten(lowered.spans, &mut spans);
+        (lowered.expr, spans)
+    }
+
+    fn expand(&mut self, expr: &'e Expr) -> Lowered {
+        stack::guard(|| self.expand_node(expr))
+    }
+
+    fn expand_node(&mut self, expr: &'e Expr) -> Lowered {
+        let span = self.spans.get(&(expr as *const Expr)).copied().unwrap_or_default();
+        match expr {
+            Expr::Fn {
+                name,
+                max_depth,
+                params,
+                body,
+                rest,
+            } => {
+                let definition = Definition {
+                    recursion: max_depth.map(|depth| (*name, depth)),
+                    params,
+                    body,
+                    functions: self.functions.clone(),
+                    consts: self.consts.clone(),
+                    captures: Vec::new(),
+                };
+                self.functions.push(*name, Rc::new(definition));
+                let rest = self.expand(rest);
+                self.functions.pop();
+                rest
+            }
+            Expr::Const { name, value, body } => {
+                let consts = &self.consts;
+                let value = value.const_int_with(&|name| consts.get(name).copied());
+                // Only `None` for ill-typed programs
+                self.consts.push(*name, value.unwrap_or(0));
+                let body = self.expand(body);
+                self.consts.pop();
+                body
+            }
+            Expr::Enum { variants, body, .. } => {
+                for (index, variant) in variants.iter().enumerate() {
+                    self.consts.push(*variant, index as i64);
+                }
+                let body = self.expand(body);
+                variants.iter().for_each(|_| self.consts.pop());
+                body
+            }
+            Expr::Var(name) if self.consts.get(*name).is_some() => {
+                build(span, Node::Int(self.consts.get(*name).copied().unwrap()))
+            }
+            Expr::Let {
+                name,
+                ty: None,
+                mutable: false,
+                value,
+                body,
+            } if matches!(**value, Expr::Lambda { .. }) => {
+                let mut temps = Vec::new();
+                let definition = self.lambda(span, value, &mut temps);
+                self.functions.push(*name, definition);
+                let body = self.expand(body);
+                self.functions.pop();
+                wrap(temps, body)
+            }
+            Expr::Call { name, args } => {
+                if let Some(definition) = self.functions.get(*name) {
+                    if self.too_deep(definition) {
+                        return self.truncate(span, &definition.clone());
+                    }
+                }
+                let mut temps = Vec::new();
+                let args: Vec<_> = args
+                    .iter()
+                    .map(|arg| match arg {
+                        Expr::Lambda { .. } => Arg::Function(self.lambda(span, arg, &mut temps)),
+                        Expr::Var(name) if self.functions.get(*name).is_some() => {
+                            Arg::Function(self.functions.get(*name).cloned().unwrap())
+                        }
+                        _ => Arg::Value(self.expand(arg)),
+                    })
+                    .collect();
+                match self.functions.get(*name).cloned() {
+                    Some(definition) if definition.params.len() == args.len() => {
+                        let call = self.call(span, &definition, args);
+                        wrap(temps, call)
+                    }
+                    // Only reached for ill-typed programs
+                    _ => build(span, Node::Int(0)),
+                }
+            }
+            Expr::For {
+                var: name,
+                start,
+                end,
+                acc,
+                init,
+                body,
+            } => {
+                let consts = &self.consts;
+                let constant = |name| consts.get(name).copied();
+                let const_int = |bound: &Expr| bound.const_int_with(&constant);
+                let range = const_int(start).unwrap_or(0)..const_int(end).unwrap_or(0);
+                let mut temps = vec![Temp {
+                    name: *acc,
+                    ty: None,
+                    value: self.expand(init),
+                    span,
+                    pair: None,
+                }];
+                for k in range {
+                    let index = Temp {
+                        name: *name,
+                        ty: None,
+                        value: build(span, Node::Int(k)),
+                        span,
+                        pair: None,
+                    };
+                    temps.push(Temp {
+                        name: *acc,
+                        ty: None,
+                        value: wrap(vec![index], self.expand(body)),
+                        span,
+                        pair: None,
+                    });
+                }
+                wrap(temps, var(span, *acc))
+            }
+            Expr::Let {
+                name,
+                ty,
+                mutable: true,
+                value,
+                body,
+            } => {
+                let value = self.expand(value);
+                self.mutables.push(*name, *ty);
+                let body = self.expand(body);
+                self.mutables.pop();
+                let node = Node::Let {
+                    name: *name,
+                    ty: *ty,
+                    mutable: false,
+                    value,
+                    body,
+                };
+                build(span, node)
+            }
+            Expr::Assign {
+                name,
+                cond,
+                value,
+                body,
+            } => {
+                let cond = cond.as_ref().map(|cond| self.expand(cond));
+                let mut value = self.expand(value);
+                if let Some(cond) = cond {
+                    let node = Node::If {
+                        cond,
+                        then_branch: value,
+                        else_branch: var(span, *name),
+                    };
+                    value = build(span, node);
+                }
+                let node = Node::Let {
+                    name: *name,
+                    ty: self.mutables.get(*name).copied().flatten(),
+                    mutable: false,
+                    value,
+                    body: self.expand(body),
+                };
+                build(span, node)
+            }
+            Expr::Map { array, f } | Expr::Fold { array, f, .. } => {
+                let len = self.lengths.get(&(expr as *const Expr)).copied();
+                let name = self.names.fresh();
+                let mut temps = vec![Temp {
+                    name,
+                    ty: None,
+                    value: self.expand(array),
+                    span,
+                    pair: None,
+                }];
+                let definition = match &**f {
+                    Expr::Lambda { .. } => Some(self.lambda(span, f, &mut temps)),
+                    Expr::Var(name) => self.functions.get(*name).cloned(),
+                    _ => None,
+                };
+                // Only `None` for ill-typed programs
+                let (Some(len), Some(definition)) = (len, definition) else {
+                    return build(span, Node::Int(0));
+                };
+                let element = |k| {
+                    let index = build(span, Node::Int(k as i64));
+                    build(span, Node::Index { array: var(span, name), index })
+                };
+                let apply = |this: &mut Self, args| {
+                    if this.too_deep(&definition) {
+                        return this.truncate(span, &definition);
+                    }
+                    this.call(span, &definition, args)
+                };
+                let Expr::Fold { init, .. } = expr else {
+                    let elements = (0..len).map(|k| apply(self, vec![Arg::Value(element(k))]));
+                    let node = Node::Array(elements.collect());
+                    return wrap(temps, build(span, node));
+                };
+                // Each step rebinds the accumulator, as a loop does
+                let acc = self.names.fresh();
+                let value = self.expand(init);
+                temps.push(Temp { name: acc, ty: None, value, span, pair: None });
+                for k in 0..len {
+                    let args = vec![Arg::Value(var(span, acc)), Arg::Value(element(k))];
+                    let value = apply(self, args);
+                    temps.push(Temp { name: acc, ty: None, value, span, pair: None });
+                }
+                wrap(temps, var(span, acc))
+            }
+            // Only reached for ill-typed programs
+            Expr::Lambda { .. } => build(span, Node::Int(0)),
+            _ => build(span, Node::of(expr).map(|child| self.expand(child))),
+        }
+    }
+
+    /// A lambda as a function. Each variable its body reads from around
+    /// it is saved in a temporary of `temps` here, so a call sees the
+    /// value it had where the lambda was written, whatever shadows it
+    /// where the call is; the temporary takes its label, so a lambda
+    /// capturing a secret gives secret results.
+    fn lambda(&mut self, span: Span, expr: &'e Expr, temps: &mut Vec<Temp>) -> Rc<Definition<'e>> {
+        let Expr::Lambda { params, body } = expr else {
+            unreachable!("only called on lambdas")
+        };
+        let mut free = Vec::new();
+        free_vars(body, &mut params.clone(), &mut free);
+        let mut captures = Vec::new();
+        for name in free {
+            if self.functions.get(name).is_none() && self.consts.get(name).is_none() {
+                let temp = self.names.fresh();
+                temps.push(Temp {
+                    name: temp,
+                    ty: None,
+                    value: var(span, name),
+                    span,
+                    pair: None,
+                });
+                captures.push((name, temp));
+            }
+        }
+        Rc::new(Definition {
+            recursion: None,
+            params,
+            body,
+            functions: self.functions.clone(),
+            consts: self.consts.clone(),
+            captures,
+        })
+    }
+
+    /// Whether a call of `definition` would be nested deeper than its
+    /// `#[max_depth]` allows.
+    fn too_deep(&self, definition: &Definition<'e>) -> bool {
+        let Some((_, max_depth)) = definition.recursion else {
+            return false;
+        };
+        let body = definition.body as *const Expr;
+        let depth = self.active.iter().filter(|&&active| active == body).count();
+        depth >= max_depth as usize
+    }
+
+    /// What a call of `definition` nested too deep stands for: the zero
+    /// of its result type, under an assertion that fails should it run.
+    fn truncate(&mut self, span: Span, definition: &Definition<'e>) -> Lowered {
+        let Some(truncated) = &mut self.truncated else {
+            let body = definition.body as *const Expr;
+            let cond = build(span, Node::Bool(false));
+            let body = zero(span, self.results.get(&body));
+            return build(span, Node::Assert { cond, body });
+        };
+        let Some((name, max_depth)) = definition.recursion else {
+            unreachable!("only recursive calls nest too deep")
+        };
+        let marker = self.names.fresh();
+        truncated.insert(marker, (name, max_depth, span));
+        var(span, marker)
+    }
+
+    /// The body of `definition` with `args` bound to its parameters.
+    fn call(&mut self, span: Span, definition: &Rc<Definition<'e>>, args: Vec<Arg<'e>>) -> Lowered {
+        let params = definition.params;
+        let mut temps = Vec::new();
+        let mut functions = definition.functions.clone();
+        if let Some((name, _)) = definition.recursion {
+            functions.push(name, definition.clone());
+        }
+        let mut values = Vec::new();
+        for (param, arg) in params.iter().zip(args) {
+            match arg {
+                Arg::Value(value) => values.push((*param, value)),
+                Arg::Function(function) => functions.push(*param, function),
+            }
+        }
+        // Binding `x` first would capture an `x` of the caller in a later
+        // argument, so then every argument goes through a temporary
+        let bound: Vec<_> = values.iter().map(|(param, _)| *param).collect();
+        let captures =
+            values.iter().enumerate().any(|(i, (_, arg))| mentions(&arg.expr, &bound[..i]));
+        if captures {
+            for (_, value) in &mut values {
+                let name = self.names.fresh();
+                let value = std::mem::replace(value, var(span, name));
+                temps.push(Temp { name, ty: None, value, span, pair: None });
+            }
+        }
+        for (name, value) in values {
+            temps.push(Temp {
+                name,
+                ty: None,
+                value,
+                span,
+                pair: None,
+            });
+        }
+        for (name, temp) in &definition.captures {
+            temps.push(Temp {
+                name: *name,
+                ty: None,
+                value: var(span, *temp),
+                span,
+                pair: None,
+            });
+        }
+        let outer = std::mem::replace(&mut self.functions, functions);
+        let outer_consts = std::mem::replace(&mut self.consts, definition.consts.clone());
+        self.active.push(definition.body);
+        let body = self.expand(definition.body);
+        self.active.pop();
+        self.functions = outer;
+        self.consts = outer_consts;
+        wrap(temps, body)
+    }
+}
+
+/// The zero of `ty`, or `0` if unknown, which a call nested deeper than
+/// its function's `#[max_depth]` evaluates to.
+fn zero(span: Span, ty: Option<&Type>) -> Lowered {
+    let scalar = |scalar| match scalar {
+        Scalar::Int => build(span, Node::Int(0)),
+        Scalar::Bool => build(span, Node::Bool(false)),
+        Scalar::Sized(width) => build(span, Node::Sized(0, width)),
+    };
+    match ty {
+        Some(Type::Array(element, len)) => {
+            build(span, Node::Array((0..*len).map(|_| scalar(*element)).collect()))
+        }
+        Some(Type::Tuple(components)) => {
+            build(span, Node::Tuple(components.iter().map(|c| scalar(*c)).collect()))
+        }
+        Some(Type::Option(_)) => build(span, Node::None),
+        _ => scalar(ty.and_then(Type::scalar).unwrap_or(Scalar::Int)),
+    }
+}
+
+/// A walk of an expanded program for [`truncated_calls`], folding the
+/// public constants it can to tell which branch of an `if` runs.
+struct Reach {
+    truncated: HashMap<Symbol, (Symbol, u32, Span)>,
+    /// Value of every variable in scope, where a public constant.
+    known: Scope<Option<Value>>,
+    found: Vec<(Symbol, u32, Span)>,
+}
+
+impl Reach {
+    /// The value of `expr` where a public constant. `runs` is whether it
+    /// runs whenever the program does.
+    fn visit(&mut self, expr: &Expr, runs: bool) -> Option<Value> {
+        stack::guard(|| self.visit_node(expr, runs))
+    }
+
+    fn visit_node(&mut self, expr: &Expr, runs: bool) -> Option<Value> {
+        match expr {
+            Expr::Int(n) => Some(Value::Int(*n)),
+            Expr::Sized(n, width) => Some(Value::Sized(*n, *width)),
+            Expr::Bool(b) => Some(Value::Bool(*b)),
+            Expr::Var(name) => {
+                if let Some(&call) = self.truncated.get(name) {
+                    if runs && !self.found.iter().any(|found| found.2 == call.2) {
+                        self.found.push(call);
+                    }
+                }
+                self.known.get(*name).copied().flatten()
+            }
+            Expr::Let {
+                name, ty, value, body, ..
+            } => {
+                let value = self.visit(value, runs);
+                let secret = ty.is_some_and(|ty| ty.secret == Some(true));
+                self.known.push(*name, value.filter(|_| !secret));
+                let body = self.visit(body, runs);
+                self.known.pop();
+                body
+            }
+            Expr::BinOp { op, left, right } => {
+                let left = self.visit(left, runs);
+                let right = self.visit(right, runs);
+                apply_binop(&ObliBinOp::from(op), left?, right?).ok()
+            }
+            Expr::UnaryOp { op, expr } => {
+                apply_unaryop(&ObliUnaryOp::from(op), self.visit(expr, runs)?).ok()
+            }
+            Expr::Cast { expr, ty } => apply_cast(*ty, self.visit(expr, runs)?).ok(),
+            Expr::If {
+                cond,
+                then_branch,
+                else_branch,
+            } => match self.visit(cond, runs) {
+                Some(Value::Bool(taken)) => {
+                    let then_value = self.visit(then_branch, runs && taken);
+                    let else_value = self.visit(else_branch, runs && !taken);
+                    if taken {
+                        then_value
+                    } else {
+                        else_value
+                    }
+                }
+                _ => {
+                    self.visit(then_branch, false);
+                    self.visit(else_branch, false);
+                    None
+                }
+            },
+            Expr::LetTuple { names, value, body } => {
+                self.visit(value, runs);
+                names.iter().for_each(|name| self.known.push(*name, None));
+                self.visit(body, runs);
+                names.iter().for_each(|_| self.known.pop());
+                None
+            }
+            Expr::While {
+                cond,
+                acc,
+                init,
+                body,
+            } => {
+                self.visit(init, runs);
+                self.known.push(*acc, None);
+                self.visit(cond, runs);
+                self.visit(body, false);
+                self.known.pop();
+                None
+            }
+            _ => {
+                for child in expr.children() {
+                    self.visit(child, runs);
+                }
+                None
+            }
+        }
+    }
+}
+
+/// Whether `expr` reads any of `names`.
+fn mentions(expr: &Expr, names: &[Symbol]) -> bool {
+    any_node(expr, |expr| matches!(expr, Expr::Var(name) if names.contains(name)))
+}
+
+/// Add each variable `expr` reads but does not bind to `free`, once, in
+/// the order of the first read.
+fn free_vars(expr: &Expr, bound: &mut Vec<Symbol>, free: &mut Vec<Symbol>) {
+    match expr {
+        Expr::Var(name) | Expr::Assign { name, .. }
+            if !bound.contains(name) && !free.contains(name) =>
+        {
+            free.push(*name);
+        }
+        _ => {}
+    }
+    let mut within = |names: &[Symbol], expr: &Expr, free: &mut Vec<Symbol>| {
+        bound.extend_from_slice(names);
+        stack::guard(|| free_vars(expr, bound, free));
+        bound.truncate(bound.len() - names.len());
+    };
+    match expr {
+        Expr::Let {
+            name, value, body, ..
+        }
+        | Expr::Const { name, value, body } => {
+            within(&[], value, free);
+            within(&[*name], body, free);
+        }
+        Expr::LetTuple { names, value, body } => {
+            within(&[], value, free);
+            within(names, body, free);
+        }
+        Expr::Enum { variants, body, .. } => within(variants, body, free),
+        Expr::Fn {
+            params, body, rest, ..
+        } => {
+            within(params, body, free);
+            within(&[], rest, free);
+        }
+        Expr::Lambda { params, body } => within(params, body, free),
+        Expr::For {
+            var,
+            start,
+            end,
+            acc,
+            init,
+            body,
+        } => {
+            for bound_expr in [start, end, init] {
+                within(&[], bound_expr, free);
+            }
+            within(&[*var, *acc], body, free);
+        }
+        Expr::While {
+            cond,
+            acc,
+            init,
+            body,
+        } => {
+            within(&[], init, free);
+            within(&[*acc], cond, free);
+            within(&[*acc], body, free);
+        }
+        _ => {
+            for child in expr.children() {
+                within(&[], child, free);
+            }
+        }
+    }
+}
+
+struct Promoter {
+    /// Span of every source node, by address.
+    spans: HashMap<*const Expr, Span>,
+    /// Type every converted node is converted to, by address.
+    promotions: HashMap<*const Expr, Scalar>,
+}
+
+impl Promoter {
+    fn new(expr: &Expr, spans: Option<&NodeSpans>) -> Self {
+        let mut post_order = Vec::new();
+        collect(expr, &mut post_order, &mut HashSet::new());
+        let mut cursor = SpanCursor::new(spans);
+        let spans = post_order
+            .into_iter()
+            .map(|node| (node, cursor.next_span().unwrap_or_default()))
+            .collect();
+        Self {
+            spans,
+            promotions: promotions(expr),
+        }
+    }
+
+    fn run(self, expr: &Expr) -> (Expr, NodeSpans) {
+        let lowered = self.promote(expr);
+        let mut spans = NodeSpans::new();
+        flatten(lowered.spans, &mut spans);
+        (lowered.expr, spans)
+    }
+
+    fn promote(&self, expr: &Expr) -> Lowered {
+        stack::guard(|| self.promote_node(expr))
+    }
+
+    fn promote_node(&self, expr: &Expr) -> Lowered {
+        let span = self.spans.get(&(expr as *const Expr)).copied().unwrap_or_default();
+        match (expr, self.promotions.get(&(expr as *const Expr))) {
+            (Expr::Int(n), Some(&Scalar::Sized(width))) => build(span, Node::Sized(*n, width)),
+            (_, promoted) => {
+                let node = build(span, Node::of(expr).map(|child| self.promote(child)));
+                match promoted {
+                    Some(&ty) => build(span, Node::Cast { expr: node, ty }),
+                    None => node,
+                }
+            }
+        }
+    }
+}
+
+/// Source nodes in post-order, and every name the program uses.
+fn collect(expr: &Expr, post_order: &mut Vec<*const Expr>, used: &mut HashSet<Symbol>) {
+    for child in expr.children() {
+        stack::guard(|| collect(child, post_order, used));
+    }
+    match expr {
+        Expr::Var(name) | Expr::Let { name, .. } => {
+            used.insert(*name);
+        }
+        Expr::Fn { params, .. }
+        | Expr::Lambda { params, .. }
+        | Expr::LetTuple { names: params, .. } => used.extend(params.iter().copied()),
+        Expr::For { var, acc, .. } => used.extend([*var, *acc]),
+        Expr::While { acc, .. } => {
+            used.insert(*acc);
+        }
+        _ => {}
+    }
+    post_order.push(expr);
+}
+
+fn flatten(tree: SpanTree, spans: &mut NodeSpans) {
+    for child in tree.children {
+        stack::guard(|| flatten(child, spans));
+    }
+    spans.push(tree.span);
+}
+
+/// Read `elements[key]` by comparing the key with every position.
+fn scan(span: Span, elements: &[Symbol], key: Key) -> Lowered {
+    let last = elements.len().saturating_sub(1);
+    let name = match key {
+        Key::Const(k) => {
+            let k = usize::try_from(k).map_or(last, |k| k.min(last));
+            return var(span, elements[k]);
+        }
+        Key::Var(name) => name,
+    };
+    let mut acc = var(span, elements[last]);
+    for (k, element) in elements[..last].iter().enumerate().rev() {
+        let cond = Node::BinOp {
+            op: BinOp::Eq,
+            left: var(span, name),
+            right: build(span, Node::Int(k as i64)),
+        };
+        let node = Node::If {
+            cond: build(span, cond),
+            then_branch: var(span, *element),
+            else_branch: acc,
+        };
+        acc = build(span, node);
+    }
+    acc
+}
+
+/// The compare-exchanges of Batcher's merge-exchange sort of `n` wires
+/// (Knuth, TAOCP vol. 3, algorithm 5.2.2M), in order; after all of them
+/// the wires hold their values in ascending order. Works for any `n`.
+pub fn sorting_network(n: usize) -> Vec<(usize, usize)> {
+    let mut pairs = Vec::new();
+    if n < 2 {
+        return pairs;
+    }
+    let t = usize::BITS - (n - 1).leading_zeros();
+    let mut p = 1 << (t - 1);
+    while p > 0 {
+        let (mut q, mut r, mut d) = (1 << (t - 1), 0, p);
+        loop {
+            pairs.extend((0..n - d).filter(|i| i & p == r).map(|i| (i, i + d)));
+            if q == p {
+                break;
+            }
+            d = q - p;
+            q >>= 1;
+            r = p;
+        }
+        p >>= 1;
+    }
+    pairs
+}
+
+/// Compare two arrays of the same length element by element, combining
+/// every comparison: with `&&` for `==` and with `||` for `!=`.
+fn compare(span: Span, op: &BinOp, left: &[Symbol], right: &[Symbol]) -> Lowered {
+    let combine = match op {
+        BinOp::Eq => BinOp::And,
+        _ => BinOp::Or,
+    };
+    let mut pairs = left.iter().zip(right).map(|(l, r)| {
+        let node = Node::BinOp {
+            op: op.clone(),
+            left: var(span, *l),
+            right: var(span, *r),
+        };
+        build(span, node)
+    });
+    let first = pairs.next().expect("arrays are never empty");
+    pairs.fold(first, |acc, pair| {
+        let node = Node::BinOp {
+            op: combine.clone(),
+            left: acc,
+            right: pair,
+        };
+        build(span, node)
+    })
+}
+
+/// Wrap `body` in the `let`s of `temps`, the first outermost.
+fn wrap(temps: Vec<Temp>, body: Lowered) -> Lowered {
+    temps.into_iter().rev().fold(body, |body, temp| {
+        if let Some(second) = temp.pair {
+            let node = Node::LetTuple {
+                names: vec![temp.name, second],
+                value: temp.value,
+                body,
+            };
+            return build(temp.span, node);
+        }
+        let node = Node::Let {
+            name: temp.name,
+            ty: temp.ty,
+            mutable: false,
+            value: temp.value,
+            body,
+        };
+        build(temp.span, node)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interp::{eval_ast, eval_ir, EvalError, Inputs, Value};
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+    use crate::transform::to_oblivious_with_spans;
+
+    fn parse(source: &str) -> (Expr, NodeSpans) {
+        let (tokens, spans) = Lexer::new(source).tokenize().unwrap();
+        Parser::with_spans(&tokens, &spans).parse_with_spans().unwrap()
+    }
+
+    #[test]
+    fn test_secret_index_becomes_select_chain() {
+        let (ast, _) = parse("let a = [10, 20, 30] a[secret(1)]");
+        let desugared = desugar(&ast);
+        assert!(!needs_desugaring(&desugared));
+        assert_eq!(
+            desugared.to_string(),
+            "let __obli_t0 = 10 (let __obli_t1 = 20 (let __obli_t2 = 30 \
+             (let __obli_t3 = secret(1) (if (__obli_t3 == 0) then __obli_t0 else \
+             (if (__obli_t3 == 1) then __obli_t1 else __obli_t2)))))"
+        );
+        let ir = crate::to_oblivious(&ast).to_string();
+        assert_eq!(ir.matches("ct_select").count(), 2, "{}", ir);
+        assert!(!ir.contains("if "), "{}", ir);
+    }
+
+    #[test]
+    fn test_tuple_components_keep_their_own_secrecy() {
+        let (ast, _) = parse("let (p, s) = (1 + 2, secret(5)) p * 2");
+        assert_eq!(
+            desugar(&ast).to_string(),
+            "let __obli_t0 = (1 + 2) (let __obli_t1 = secret(5) (__obli_t0 * 2))"
+        );
+        assert!(!crate::to_oblivious(&ast).is_secret());
+    }
+
+    #[test]
+    fn test_match_becomes_select_chain_on_secrets() {
+        let (ast, _) = parse("match secret(2) with 1 => 10, 2 => 20, _ => 0");
+        assert_eq!(
+            desugar(&ast).to_string(),
+            "let __obli_t0 = secret(2) (if (__obli_t0 == 1) then 10 else \
+             (if (__obli_t0 == 2) then 20 else 0))"
+        );
+        let ir = crate::to_oblivious(&ast).to_string();
+        assert_eq!(ir.matches("ct_select").count(), 2, "{}", ir);
+        let (ast, _) = parse("let b = 4 > 3 match b with false => 1, _ => 2");
+        let ir = crate::to_oblivious(&ast).to_string();
+        assert!(!ir.contains("ct_select") && ir.contains("if "), "{}", ir);
+    }
+
+    #[test]
+    fn test_enum_match_selects_over_every_variant() {
+        let (ast, _) = parse("enum C = A | B | D match secret(B) with A => 10, B => 20, D => 30");
+        assert_eq!(
+            desugar(&ast).to_string(),
+            "let __obli_t0 = secret(1) (if (__obli_t0 == 0) then 10 else \
+             (if (__obli_t0 == 1) then 20 else 30))"
+        );
+        let ir = crate::to_oblivious(&ast).to_string();
+        assert_eq!(ir.matches("ct_select").count(), 2, "{}", ir);
+        assert!(!ir.contains("if "), "{}", ir);
+    }
+
+    #[test]
+    fn test_options_become_flag_and_value() {
+        let (ast, _) = parse("unwrap_or(if secret(true) then some(4) else none, 0)");
+        assert_eq!(
+            desugar(&ast).to_string(),
+            "let __obli_t0 = secret(true) (let __obli_t1 = true (let __obli_t2 = 4 \
+             (let __obli_t3 = false (let __obli_t4 = 0 (let __obli_t5 = \
+             (if __obli_t0 then __obli_t1 else __obli_t3) \
+             (if __obli_t5 then __obli_t2 else 0))))))"
+        );
+        let ir = crate::to_oblivious(&ast).to_string();
+        assert_eq!(ir.matches("ct_select").count(), 2, "{}", ir);
+        assert!(!ir.contains("if "), "{}", ir);
+        let (ast, _) = parse("let o = none unwrap_or(o, true)");
+        assert!(desugar(&ast).to_string().ends_with("true)"));
+    }
+
+    #[test]
+    fn test_clamp_becomes_select_chain_on_secrets() {
+        let (ast, _) = parse("clamp(secret(12), 0, 9)");
+        assert_eq!(
+            desugar(&ast).to_string(),
+            "let __obli_t0 = secret(12) (if (__obli_t0 < 0) then 0 else \
+             (if (__obli_t0 > 9) then 9 else __obli_t0))"
+        );
+        let ir = crate::to_oblivious(&ast).to_string();
+        assert_eq!(ir.matches("ct_select").count(), 2, "{}", ir);
+        let (ast, _) = parse("in_range(secret(3), 1, 2)");
+        assert_eq!(
+            desugar(&ast).to_string(),
+            "let __obli_t0 = secret(3) ((1 <= __obli_t0) && (__obli_t0 <= 2))"
+        );
+    }
+
+    #[test]
+    fn test_pow_becomes_square_and_multiply() {
+        let (ast, _) = parse("pow(secret(3), 5)");
+        assert_eq!(
+            desugar(&ast).to_string(),
+            "let __obli_t0 = secret(3) (let __obli_t1 = (__obli_t0 * __obli_t0) \
+             (let __obli_t2 = (__obli_t1 * __obli_t1) \
+             (let __obli_t3 = (__obli_t2 * __obli_t0) __obli_t3)))"
+        );
+        let ir = crate::to_oblivious(&ast).to_string();
+        assert_eq!(ir.matches("ct_mul").count(), 3, "{}", ir);
+        assert!(!ir.contains("ct_select") && !ir.contains("if "), "{}", ir);
+        let (ast, _) = parse("const N = 0 pow(secret(3u8), N)");
+        assert_eq!(desugar(&ast).to_string(), "1u8");
+    }
+
+    #[test]
+    fn test_map_and_fold_are_unrolled() {
+        let (ast, _) = parse("fold(map(secret([1, 2, 3]), fn(x) => x * x), 0, fn(s, x) => s + x)");
+        let expanded = expand(&ast).to_string();
+        assert!(!expanded.contains("map") && !expanded.contains("fold"), "{}", expanded);
+        let ir = crate::to_oblivious(&ast).to_string();
+        assert_eq!(ir.matches("ct_mul").count(), 3, "{}", ir);
+        assert_eq!(ir.matches("ct_add").count(), 3, "{}", ir);
+        assert!(!ir.contains("if "), "{}", ir);
+    }
+
+    #[test]
+    fn test_desugared_programs_agree() {
+        let inputs = Inputs::new().secret("i", Value::Int(2)).public("j", Value::Int(7));
+        for (source, expected) in [
+            ("let a = [1, 2, 3] a[i] + a[0]", 4),
+            ("let a = [1, 2, 3] a[j]", 3),
+            ("let a = if i > 0 then [1, 2, 3] else secret([4, 5, 6]) a[1]", 2),
+            ("let a = (let i = 5 ([i, i + 1, i + 2])) a[i] * i", 14),
+            ("let __obli_t0 = 3 ([__obli_t0, 4][0] + __obli_t0)", 6),
+            ("lookup(i, [(1, 10), (i, 20), (2, 30)], 0)", 20),
+            ("lookup(j + 1, [(1, 10), (i, 20)], i * 5)", 10),
+            ("let t = [7, 8] lookup(t[1], [(8, t[0])], -1)", 7),
+            ("let a = sort([5, i, j, -1, 2]) a[0] * 100 + a[2] * 10 + a[4]", -73),
+            ("let (x, y) = swap_if(i > j, i, j) x * 10 + y", 27),
+            ("let t = swap_if(j > i, i, j) let (x, y) = t x * 10 + y", 72),
+            ("let (q, r) = (j / i, j % i) let t = (r, q) let (x, y) = t x * 10 + y", 13),
+            ("match i with 1 => 10, 2 => j, _ => 0", 7),
+            ("match j > i with true => match j with -7 => 1, _ => 2, _ => 3", 2),
+            ("clamp(j, 0, i) * 10 + clamp(i - j, 0, 9)", 20),
+            ("if in_range(i, 0, j) and not in_range(j, 0, i) then clamp(j, i, 9) else 0", 7),
+            ("const N = 6 pow(i, N) + pow(j - i, 3) - pow(i, 0)", 188),
+            ("fold(map([i, j, 3], fn(x) => x * i), 0, fn(s, x) => s + x)", 24),
+            ("let m = 5 fold([i, j, 0], m, fn(m, x) => if x > m then x else m)", 7),
+            ("fn sq(x) = x * x let x = 1 map([i, j, 0], sq)[1] + x", 50),
+        ] {
+            let (ast, _) = parse(source);
+            let source_value = eval_ast(&ast, &inputs).unwrap();
+            let desugared_value = eval_ast(&desugar(&ast), &inputs).unwrap();
+            assert_eq!(source_value.value, Value::Int(expected), "{}", source);
+            assert_eq!(desugared_value.value, source_value.value, "{}", source);
+        }
+    }
+
+    #[test]
+    fn test_hoisted_assertions_keep_their_arm() {
+        for k in ["1", "secret(1)"] {
+            let untaken = [
+                ("(if k > 0 then [1, 2] else [(assert(k < 0) 3), 4])[0]", 1),
+                ("unwrap_or(if k > 0 then none else some(assert(k < 0) 5), 7)", 7),
+                ("(if k < 0 then [(assert(k < 0) 3), 4] else [1, 2])[1]", 2),
+            ];
+            for (source, expected) in untaken {
+                let source = format!("let k = {}; {}", k, source);
+                let (ast, _) = parse(&source);
+                let value = eval_ir(&crate::to_oblivious(&ast), &Inputs::new());
+                assert_eq!(value.unwrap().value, Value::Int(expected), "{}", source);
+            }
+            let taken = "(if k > 0 then [(assert(k < 0) 3), 4] else [1, 2])[0]";
+            let source = format!("let k = {}; {}", k, taken);
+            let (ast, _) = parse(&source);
+            let value = eval_ir(&crate::to_oblivious(&ast), &Inputs::new());
+            assert_eq!(value, Err(EvalError::AssertionFailed), "{}", source);
+        }
+    }
+
+    #[test]
+    fn test_lookup_becomes_scan() {
+        let (ast, _) = parse("lookup(secret(3), [(1, 100), (3, 300)], 0)");
+        assert_eq!(
+            desugar(&ast).to_string(),
+            "let __obli_t0 = secret(3) (if (__obli_t0 == 1) then 100 else \
+             (if (__obli_t0 == 3) then 300 else 0))"
+        );
+        let ir = crate::to_oblivious(&ast).to_string();
+        assert_eq!(ir.matches("ct_select").count(), 2, "{}", ir);
+    }
+
+    #[test]
+    fn test_byte_strings_compare_every_element() {
+        let (ast, _) = parse(r#"secret(b"ab") != b"ac""#);
+        let desugared = desugar(&ast).to_string();
+        assert!(
+            desugared.ends_with("((__obli_t2 != __obli_t4) || (__obli_t3 != __obli_t5)))))))"),
+            "{}",
+            desugared
+        );
+        let ir = crate::to_oblivious(&ast).to_string();
+        assert_eq!(ir.matches("ct_ne").count(), 2, "{}", ir);
+        assert_eq!(eval_ast(&ast, &Inputs::new()).unwrap().value, Value::Bool(true));
+    }
+
+    #[test]
+    fn test_sorting_network_sorts() {
+        assert_eq!(sorting_network(8).len(), 19);
+        assert!(sorting_network(1).is_empty());
+        // By the 0-1 principle, sorting every sequence of 0s and 1s suffices
+        for n in 2..=10 {
+            let network = sorting_network(n);
+            for bits in 0..1u32 << n {
+                let mut wires: Vec<_> = (0..n).map(|i| bits >> i & 1).collect();
+                for &(i, j) in &network {
+                    if wires[i] > wires[j] {
+                        wires.swap(i, j);
+                    }
+                }
+                assert!(wires.windows(2).all(|w| w[0] <= w[1]), "n={} bits={:b}", n, bits);
+            }
+        }
+    }
+
+    #[test]
+    fn test_spans_follow_the_rewrite() {
+        let source = "let a = [1, secret(2)] a[secret(0)]";
+        let (ast, spans) = parse(source);
+        let (desugared, desugared_spans) = desugar_with_spans(&ast, &spans);
+        let (_, ir_spans) = to_oblivious_with_spans(&desugared, &desugared_spans);
+        let text = |span: Span| &source[span.start..span.end];
+        let desugared_texts: Vec<_> = desugared_spans.iter().map(|s| text(*s)).collect();
+        assert_eq!(desugared_texts.first(), Some(&"1"));
+        assert_eq!(desugared_texts.last(), Some(&"1"));
+        assert!(desugared_texts.contains(&"a[secret(0)]"));
+        assert!(ir_spans.iter().all(|s| !s.is_empty()));
+    }
+
+    #[test]
+    fn test_calls_inlined() {
+        let (ast, spans) = parse("fn inc(x) = x + 1 inc(2)");
+        let (inlined, spans) = expand_with_spans(&ast, &spans);
+        assert_eq!(inlined.to_string(), "let x = 2 (x + 1)");
+        let source = "fn inc(x) = x + 1 inc(2)";
+        let texts: Vec<_> = spans.iter().map(|s| &source[s.start..s.end]).collect();
+        assert_eq!(texts, ["2", "x", "1", "x + 1", "inc(2)"]);
+
+        // `sub(y, x)` cannot bind `x` before reading the caller's `x`
+        let source = "fn sub(x, y) = x - y let x = 10 let y = 3 sub(y, x) * 100 + sub(x, y)";
+        let (ast, _) = parse(source);
+        let first = "let __obli_t0 = y (let __obli_t1 = x (let x = __obli_t0 (let y = __obli_t1";
+        let second = "(let x = x (let y = y (x - y)))";
+        let inlined = expand(&ast).to_string();
+        assert!(inlined.contains(first) && inlined.contains(second), "{}", inlined);
+        let value = eval_ir(&crate::to_oblivious(&ast), &Inputs::new()).unwrap();
+        assert_eq!(value.value, Value::Int(-693));
+
+        let (ast, _) = parse("fn first(a) = a[0] first([secret(4), 5]) + first(b\"ab\")");
+        let value = eval_ir(&crate::to_oblivious(&ast), &Inputs::new()).unwrap();
+        assert_eq!(value.value, Value::Int(101));
+        assert!(value.secret);
+    }
+
+    #[test]
+    fn test_lambda_captures_are_lexical() {
+        let source = "let k = 1 let f = fn(x) => x + k let k = 2 f(0) * 10 + k";
+        let (ast, _) = parse(source);
+        let value = eval_ir(&crate::to_oblivious(&ast), &Inputs::new()).unwrap();
+        assert_eq!(value.value, Value::Int(12));
+        // The capture is saved where the lambda is written
+        let (ast, _) = parse("fn apply(f) = f(1) let x = 5 apply(fn(y) => x + y)");
+        let inlined = expand(&ast).to_string();
+        assert!(inlined.contains("let __obli_t0 = x (let y = 1 (let x = __obli_t0"), "{}", inlined);
+    }
+
+    #[test]
+    fn test_constants_folded() {
+        let source = "const n = 2 const m = n * 3 fn f(x) = x + m for i in 0..n with s = 0 do f(s)";
+        let (ast, _) = parse(source);
+        let first = "let s = (let i = 0 (let x = s (x + 6)))";
+        let second = "let s = (let i = 1 (let x = s (x + 6)))";
+        assert_eq!(expand(&ast).to_string(), format!("let s = 0 ({} ({} s))", first, second));
+    }
+
+    #[test]
+    fn test_recursion_unrolled() {
+        let (ast, _) = parse("#[max_depth(2)] fn f(n) = if n == 0 then true else f(n - 1) f(5)");
+        let inner = "let n = (n - 1) (if (n == 0) then true else (assert(false) false))";
+        let expected = format!("let n = 5 (if (n == 0) then true else ({}))", inner);
+        assert_eq!(expand(&ast).to_string(), expected);
+
+        // Running past the bound on a secret path fails once revealed
+        let source = "#[max_depth(2)] fn f(n) = if n == 0 then 0 else f(n - 1) + 1 f(secret(2))";
+        let (ast, _) = parse(source);
+        let value = eval_ir(&crate::to_oblivious(&ast), &Inputs::new());
+        assert_eq!(value, Err(EvalError::AssertionFailed));
+        let (ast, _) = parse(&source.replace("secret(2)", "secret(1)"));
+        let value = eval_ir(&crate::to_oblivious(&ast), &Inputs::new()).unwrap();
+        assert_eq!(value.value, Value::Int(1));
+
+        // Binary search over a fixed array, unrolled as deep as it goes
+        let source = "#[max_depth(4)] fn search(a, key, lo, hi) = \
+                      if hi - lo == 1 then lo else let mid = (lo + hi) / 2 \
+                      if a[mid] <= key then search(a, key, mid, hi) else search(a, key, lo, mid) \
+                      search([1, 3, 5, 7, 9, 11, 13, 15], secret(9), 0, 8)";
+        let (ast, _) = parse(source);
+        let value = eval_ir(&crate::to_oblivious(&ast), &Inputs::new()).unwrap();
+        assert_eq!(value.value, Value::Int(4));
+    }
+
+    #[test]
+    fn test_loops_unrolled() {
+        let source = "for i in 1..3 with s = secret(0) do s + i";
+        let (ast, spans) = parse(source);
+        let (unrolled, spans) = expand_with_spans(&ast, &spans);
+        assert_eq!(
+            unrolled.to_string(),
+            "let s = secret(0) (let s = (let i = 1 (s + i)) (let s = (let i = 2 (s + i)) s))"
+        );
+        let texts: Vec<_> = spans.iter().map(|s| &source[s.start..s.end]).collect();
+        assert_eq!(texts[..6], ["0", "secret(0)", source, "s", "i", "s + i"]);
+        assert_eq!(texts.iter().filter(|text| **text == "s + i").count(), 2);
+
+        // A linear scan reads every element with a constant index
+        let (ast, _) = parse("let a = [3, secret(9), 4] for i in 0..3 with m = 0 do \
+                              if a[i] > m then a[i] else m");
+        let value = eval_ir(&crate::to_oblivious(&ast), &Inputs::new()).unwrap();
+        assert_eq!(value.value, Value::Int(9));
+        assert!(value.secret);
+        let (empty, _) = parse("for i in 2..0 with s = 7 do s + i");
+        assert_eq!(expand(&empty).to_string(), "let s = 7 s");
+    }
+
+    #[test]
+    fn test_assignments_rebind() {
+        let source = "let mut c: int = 0; let k = secret(5); if k > 3 then c = c + 1; c = c * 2; c";
+        let (ast, _) = parse(source);
+        assert_eq!(
+            expand(&ast).to_string(),
+            "let c: int = 0 (let k = secret(5) (let c: int = (if (k > 3) then (c + 1) else c) \
+             (let c: int = (c * 2) c)))"
+        );
+        let ir = crate::to_oblivious(&ast);
+        assert!(ir.to_string().contains("ct_select"), "{}", ir);
+        let value = eval_ir(&ir, &Inputs::new()).unwrap();
+        assert_eq!(value.value, Value::Int(2));
+        assert!(value.secret);
+    }
+
+}
@@ -0,0 +1,545 @@
+// SPDX-License-Identifier: MIT OR AGPL-3.0-or-later
+// SPDX-FileCopyrightText: 2024-2025 hyperpolymath
+
+//! Structured diagnostics shared by the analysis passes.
+//!
+//! Every stage error converts into a [`Diagnostic`], which [`Renderer`]
+//! prints with the offending source lines, underlines, labels and notes.
+//! A diagnostic may carry [`Suggestion`]s: source edits that fix it, which
+//! `obli fix` and the language server apply.
+
+use crate::division::DivisionError;
+use crate::emit::EmitError;
+use crate::frontend::FrontendError;
+use crate::lexer::LexError;
+use crate::limits::LimitError;
+use crate::parser::ParseError;
+use crate::policy::PolicyViolation;
+use crate::span::{line_col, Span};
+use crate::typeck::TypeError;
+use crate::transform::SecrecyError;
+use crate::verify::VerifyError;
+use std::fmt;
+use std::fmt::Write as _;
+
+/// Diagnostic severity.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Severity {
+    Note,
+    Warning,
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Note => write!(f, "note"),
+            Severity::Warning => write!(f, "warning"),
+            Severity::Error => write!(f, "error"),
+        }
+    }
+}
+
+/// A message attached to a span of source.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Label {
+    pub span: Span,
+    pub message: String,
+}
+
+/// Whether a [`Suggestion`] can be applied without review.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Applicability {
+    /// The edit fixes the diagnostic and keeps the program's meaning.
+    MachineApplicable,
+    /// The edit is a guess, such as a similarly named variable.
+    MaybeIncorrect,
+}
+
+/// An edit replacing a span of source.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Suggestion {
+    /// What the edit does, e.g. ``prefix `key` with `_` ``.
+    pub message: String,
+    pub span: Span,
+    pub replacement: String,
+    pub applicability: Applicability,
+}
+
+impl Suggestion {
+    /// A machine-applicable edit.
+    pub fn new(message: impl Into<String>, span: Span, replacement: impl Into<String>) -> Self {
+        Self {
+            message: message.into(),
+            span,
+            replacement: replacement.into(),
+            applicability: Applicability::MachineApplicable,
+        }
+    }
+
+    /// Mark the edit as needing review.
+    pub fn maybe_incorrect(mut self) -> Self {
+        self.applicability = Applicability::MaybeIncorrect;
+        self
+    }
+
+    pub fn is_machine_applicable(&self) -> bool {
+        self.applicability == Applicability::MachineApplicable
+    }
+}
+
+/// A single error, warning, or note, optionally pointing into the source.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    /// Stable code from the [catalog](crate::codes), e.g. `OB0005`.
+    pub code: Option<&'static str>,
+    pub message: String,
+    pub span: Option<Span>,
+    /// Labels on the primary span (rendered with `^`) or related spans (`-`).
+    pub labels: Vec<Label>,
+    pub notes: Vec<String>,
+    pub suggestions: Vec<Suggestion>,
+}
+
+impl Diagnostic {
+    pub fn new(severity: Severity, message: impl Into<String>) -> Self {
+        Self {
+            severity,
+            code: None,
+            message: message.into(),
+            span: None,
+            labels: Vec::new(),
+            notes: Vec::new(),
+            suggestions: Vec::new(),
+        }
+    }
+
+    pub fn error(message: impl Into<String>) -> Self {
+        Self::new(Severity::Error, message)
+    }
+
+    pub fn warning(message: impl Into<String>) -> Self {
+        Self::new(Severity::Warning, message)
+    }
+
+    /// Attach a catalog code.
+    pub fn with_code(mut self, code: &'static str) -> Self {
+        self.code = Some(code);
+        self
+    }
+
+    /// Attach a source span.
+    pub fn with_span(mut self, span: Span) -> Self {
+        self.span = Some(span);
+        self
+    }
+
+    /// Label a span of source.
+    pub fn with_label(mut self, span: Span, message: impl Into<String>) -> Self {
+        self.labels.push(Label {
+            span,
+            message: message.into(),
+        });
+        self
+    }
+
+    /// Attach an explanatory note.
+    pub fn with_note(mut self, note: impl Into<String>) -> Self {
+        self.notes.push(note.into());
+        self
+    }
+
+    /// Attach an edit that fixes the diagnostic.
+    pub fn with_suggestion(mut self, suggestion: Suggestion) -> Self {
+        self.suggestions.push(suggestion);
+        self
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+}
+
+impl Diagnostic {
+    /// Severity and code, e.g. `error[OB0005]`.
+    fn heading(&self) -> String {
+        match self.code {
+            Some(code) => format!("{}[{}]", self.severity, code),
+            None => self.severity.to_string(),
+        }
+    }
+}
+
+/// The heading and message on one line, e.g.
+/// `error[OB0005]: cannot add int and bool`; stable, so tests may compare
+/// against it. [`Renderer`] adds the source context.
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.heading(), self.message)
+    }
+}
+
+impl std::error::Error for Diagnostic {}
+
+impl From<LexError> for Diagnostic {
+    fn from(e: LexError) -> Self {
+        let label = match e {
+            LexError::UnexpectedChar(..) => "not valid in MiniObli",
+            LexError::InvalidNumber(_) => "does not fit its type",
+            LexError::MissingDigits(..) => "no digits",
+            LexError::InvalidSuffix(..) => "not an integer type",
+            LexError::InvalidByteString(_) => "byte string starts here",
+        };
+        Diagnostic::error(e.to_string())
+            .with_code(e.code())
+            .with_span(e.span())
+            .with_label(e.span(), label)
+    }
+}
+
+impl From<ParseError> for Diagnostic {
+    fn from(e: ParseError) -> Self {
+        let label = match &e {
+            ParseError::Limit(e) => return e.clone().into(),
+            ParseError::UnexpectedToken(_, expected, _) => format!("expected {}", expected),
+            ParseError::UnexpectedEof(_) => "input ends here".to_string(),
+            ParseError::UnboundedRecursion(..) => "called in its own body".to_string(),
+        };
+        Diagnostic::error(e.to_string())
+            .with_code(e.code())
+            .with_span(e.span())
+            .with_label(e.span(), label)
+    }
+}
+
+impl From<TypeError> for Diagnostic {
+    fn from(e: TypeError) -> Self {
+        let label = match &e {
+            TypeError::Mismatch { expected, .. } => format!("expected {}", expected),
+            TypeError::BranchMismatch { .. } => "branches must have the same type".to_string(),
+            TypeError::Unbound { .. } => "not found in this scope".to_string(),
+            TypeError::NotScalar { .. } => "expected int or bool".to_string(),
+            TypeError::IndexOutOfBounds { len, .. } => format!("must be below {}", len),
+            TypeError::NotAnArray { .. } => "not an array".to_string(),
+            TypeError::NotATuple { len, .. } => format!("expected {} components", len),
+            TypeError::MisplacedOutputs { .. } => "not the program's result".to_string(),
+            TypeError::DuplicateOutput { .. } => "names must be distinct".to_string(),
+            TypeError::UndefinedFunction { .. } => "not defined before this call".to_string(),
+            TypeError::ArityMismatch { expected, .. } => {
+                format!("expected {} arguments", expected)
+            }
+            TypeError::NonConstantBound { .. } => "not a constant".to_string(),
+            TypeError::TooManyIterations { .. } => "unrolled too often".to_string(),
+            TypeError::NotMutable { .. } => "assigned here".to_string(),
+            TypeError::FunctionValue { .. } => "not called here".to_string(),
+            TypeError::NonConstantValue { .. } => "not a constant".to_string(),
+            TypeError::SecretConstant { .. } => "secret".to_string(),
+            TypeError::ConstantRebound { .. } => "rebound in its scope".to_string(),
+            TypeError::NoBaseCase { .. } => "never returns".to_string(),
+            TypeError::MisplacedInput { .. } => "not a statement of the program".to_string(),
+            TypeError::DuplicateInput { .. } => "names must be distinct".to_string(),
+            TypeError::NotAVariant { .. } => "not a variant".to_string(),
+            TypeError::NonExhaustiveMatch { .. } => "missing an arm".to_string(),
+            TypeError::NonConstantExponent { .. } => "not a constant".to_string(),
+            TypeError::NotAFunction { arity, .. } => {
+                format!("expected a function of {} arguments", arity)
+            }
+            TypeError::Narrowing { to, .. } => format!("may not fit in {}", to),
+            TypeError::TruncatedCall { .. } => "always reached, and replaced with zero".to_string(),
+        };
+        Diagnostic::error(e.to_string())
+            .with_code(e.code())
+            .with_span(e.span())
+            .with_label(e.span(), label)
+    }
+}
+
+impl From<FrontendError> for Diagnostic {
+    fn from(e: FrontendError) -> Self {
+        Diagnostic::error(e.to_string()).with_span(e.span)
+    }
+}
+
+impl From<EmitError> for Diagnostic {
+    fn from(e: EmitError) -> Self {
+        let label = match &e {
+            EmitError::ReservedName { .. } => "reserved in generated Rust",
+        };
+        Diagnostic::error(e.to_string())
+            .with_code(e.code())
+            .with_span(e.span())
+            .with_label(e.span(), label)
+    }
+}
+
+impl From<DivisionError> for Diagnostic {
+    fn from(e: DivisionError) -> Self {
+        Diagnostic::error(e.to_string())
+            .with_code(e.code())
+            .with_span(e.span())
+            .with_label(e.span(), "divisor not proven nonzero")
+    }
+}
+
+impl From<PolicyViolation> for Diagnostic {
+    fn from(e: PolicyViolation) -> Self {
+        let label = match e {
+            PolicyViolation::Undeclared { .. } => "revealed here",
+            PolicyViolation::Disallowed { .. } => "not allowed by the policy",
+        };
+        Diagnostic::error(e.to_string())
+            .with_code(e.code())
+            .with_span(e.span())
+            .with_label(e.span(), label)
+    }
+}
+
+impl From<LimitError> for Diagnostic {
+    fn from(e: LimitError) -> Self {
+        let diagnostic = Diagnostic::error(e.to_string()).with_code(e.code());
+        match e {
+            LimitError::OutputTooLarge { .. } => diagnostic,
+            _ => diagnostic.with_span(e.span()),
+        }
+    }
+}
+
+impl From<SecrecyError> for Diagnostic {
+    fn from(e: SecrecyError) -> Self {
+        let diagnostic = Diagnostic::error(e.to_string())
+            .with_code(e.code())
+            .with_span(e.span());
+        match e {
+            SecrecyError::SecretLoopCondition { .. } => {
+                diagnostic.with_note("the number of iterations would reveal the secret")
+            }
+            SecrecyError::DeclaredPublic { .. } => diagnostic,
+        }
+    }
+}
+
+impl From<VerifyError> for Diagnostic {
+    fn from(e: VerifyError) -> Self {
+        let diagnostic = Diagnostic::error(e.to_string())
+            .with_code(e.code())
+            .with_span(e.span());
+        match e {
+            VerifyError::SecretLoopCondition { .. } => {
+                diagnostic.with_note("the number of iterations would reveal the secret")
+            }
+            VerifyError::DeclaredPublic { .. } => diagnostic,
+            _ => diagnostic.with_note("this is a transformer bug; please report it"),
+        }
+    }
+}
+
+/// Whether any diagnostic in the list is an error.
+pub fn has_errors(diagnostics: &[Diagnostic]) -> bool {
+    diagnostics.iter().any(Diagnostic::is_error)
+}
+
+/// Renders diagnostics with source excerpts for the terminal.
+///
+/// ```text
+/// error: mismatched types: expected int, found bool
+///  --> main.mobli:1:5
+///   |
+/// 1 | 1 + true
+///   |     ^^^^ expected int
+///   = note: ...
+///   = help: ...
+/// ```
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Renderer {
+    color: bool,
+}
+
+const RESET: &str = "\x1b[0m";
+const BOLD: &str = "\x1b[1m";
+const BLUE: &str = "\x1b[1;34m";
+
+impl Renderer {
+    /// A renderer emitting plain text.
+    pub fn plain() -> Self {
+        Self { color: false }
+    }
+
+    /// A renderer emitting ANSI colour codes.
+    pub fn colored() -> Self {
+        Self { color: true }
+    }
+
+    fn paint(&self, style: &str, text: &str) -> String {
+        if self.color {
+            format!("{}{}{}", style, text, RESET)
+        } else {
+            text.to_string()
+        }
+    }
+
+    fn severity_style(severity: Severity) -> &'static str {
+        match severity {
+            Severity::Error => "\x1b[1;31m",
+            Severity::Warning => "\x1b[1;33m",
+            Severity::Note => "\x1b[1;36m",
+        }
+    }
+
+    /// Render one diagnostic against the source of `file`.
+    pub fn render(&self, diagnostic: &Diagnostic, file: &str, source: &str) -> String {
+        let style = Self::severity_style(diagnostic.severity);
+        let mut out = format!(
+            "{}{}\n",
+            self.paint(style, &diagnostic.heading()),
+            self.paint(BOLD, &format!(": {}", diagnostic.message)),
+        );
+
+        // (span, marker, style, message), primary first
+        let mut labels = Vec::new();
+        if let Some(span) = diagnostic.span {
+            let message = diagnostic
+                .labels
+                .iter()
+                .find(|l| l.span == span)
+                .map_or("", |l| l.message.as_str());
+            labels.push((span, '^', style, message));
+        }
+        for label in &diagnostic.labels {
+            if Some(label.span) != diagnostic.span {
+                labels.push((label.span, '-', BLUE, label.message.as_str()));
+            }
+        }
+
+        let lines: Vec<&str> = source.split('\n').collect();
+        let last_line = labels
+            .iter()
+            .map(|(span, ..)| line_col(source, span.start).0)
+            .max()
+            .unwrap_or(1);
+        let width = last_line.to_string().len();
+        let gutter = self.paint(BLUE, &format!("{} |", " ".repeat(width)));
+
+        match diagnostic.span {
+            Some(span) => {
+                let (line, col) = line_col(source, span.start);
+                let arrow = self.paint(BLUE, &format!("{}-->", " ".repeat(width)));
+                let _ = writeln!(out, "{} {}:{}:{}", arrow, file, line, col);
+            }
+            None => {
+                let arrow = self.paint(BLUE, &format!("{}-->", " ".repeat(width)));
+                let _ = writeln!(out, "{} {}", arrow, file);
+            }
+        }
+
+        if !labels.is_empty() {
+            let _ = writeln!(out, "{}", gutter);
+            let mut line_numbers: Vec<usize> = labels
+                .iter()
+                .map(|(span, ..)| line_col(source, span.start).0)
+                .collect();
+            line_numbers.sort_unstable();
+            line_numbers.dedup();
+
+            let mut previous = None;
+            for line in line_numbers {
+                if previous.is_some_and(|p| line > p + 1) {
+                    let _ = writeln!(out, "{}", self.paint(BLUE, "..."));
+                }
+                previous = Some(line);
+
+                let text = lines.get(line - 1).copied().unwrap_or("");
+                let number = self.paint(BLUE, &format!("{:>width$} |", line, width = width));
+                let _ = writeln!(out, "{} {}", number, text);
+
+                for (span, marker, style, message) in &labels {
+                    let (start_line, start_col) = line_col(source, span.start);
+                    if start_line != line {
+                        continue;
+                    }
+                    // Multi-line spans are underlined to the end of their first line
+                    let (end_line, end_col) = line_col(source, span.end);
+                    let end_col = if end_line == line {
+                        end_col
+                    } else {
+                        text.chars().count() + 1
+                    };
+                    let len = end_col.saturating_sub(start_col).max(1);
+                    let underline = marker.to_string().repeat(len);
+                    let mut row = format!("{}{}", " ".repeat(start_col - 1), underline);
+                    if !message.is_empty() {
+                        row.push(' ');
+                        row.push_str(message);
+                    }
+                    let _ = writeln!(out, "{} {}", gutter, self.paint(style, &row));
+                }
+            }
+        }
+
+        let eq = self.paint(BLUE, &format!("{} =", " ".repeat(width)));
+        for note in &diagnostic.notes {
+            let _ = writeln!(out, "{} {}: {}", eq, self.paint(BOLD, "note"), note);
+        }
+        for suggestion in &diagnostic.suggestions {
+            let _ = writeln!(out, "{} {}: {}", eq, self.paint(BOLD, "help"), suggestion.message);
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_primary_label_and_note() {
+        let diagnostic = Diagnostic::error("mismatched types: expected int, found bool")
+            .with_span(Span::new(14, 18))
+            .with_label(Span::new(14, 18), "expected int")
+            .with_note("operands of `+` are ints");
+        let rendered = Renderer::plain().render(&diagnostic, "main.mobli", "let x = 1\n1 + true");
+        assert_eq!(
+            rendered,
+            "error: mismatched types: expected int, found bool\n \
+             --> main.mobli:2:5\n  \
+             |\n\
+             2 | 1 + true\n  \
+             |     ^^^^ expected int\n  \
+             = note: operands of `+` are ints\n"
+        );
+    }
+
+    #[test]
+    fn test_render_secondary_labels_across_lines() {
+        let source = "let k = secret(1)\n\n\n100 / k";
+        let diagnostic = Diagnostic::warning("division on secret data is not constant-time")
+            .with_span(Span::new(20, 27))
+            .with_label(Span::new(4, 5), "secret bound here");
+        let rendered = Renderer::plain().render(&diagnostic, "f", source);
+        assert!(rendered.contains("1 | let k = secret(1)\n  |     - secret bound here\n...\n"));
+        assert!(rendered.contains("4 | 100 / k\n  | ^^^^^^^\n"));
+    }
+
+    #[test]
+    fn test_stage_errors_carry_labels() {
+        let diagnostic = Diagnostic::from(LexError::UnexpectedChar('$', 4));
+        assert_eq!(diagnostic.span, Some(Span::new(4, 5)));
+        assert_eq!(diagnostic.labels[0].message, "not valid in MiniObli");
+        assert_eq!(
+            diagnostic.to_string(),
+            "error[OB0001]: unexpected character: '$' at position 4"
+        );
+        assert!(Renderer::colored().render(&diagnostic, "f", "1 + $").contains("\x1b["));
+        let diagnostic = Diagnostic::from(LexError::MissingDigits("0x", 4));
+        assert_eq!(diagnostic.span, Some(Span::new(4, 6)));
+        assert_eq!(diagnostic.labels[0].message, "no digits");
+        assert_eq!(
+            diagnostic.to_string(),
+            "error[OB0002]: expected digits after `0x` at position 4"
+        );
+    }
+}
@@ -0,0 +1,316 @@
+// SPDX-License-Identifier: MIT OR AGPL-3.0-or-later
+// SPDX-FileCopyrightText: 2024-2025 hyperpolymath
+
+//! Differential testing of the compiler.
+//!
+//! [`check`] evaluates a program on the source AST ([`eval_ast`]), on the
+//! oblivious IR, and on the IR after every built-in pass ([`eval_ir`]);
+//! [`check_backends`] also compiles the program for each back end and runs
+//! it ([`check_native`] for Rust alone). All of them must compute the same
+//! value, or the same named outputs, or fail the same way. The IR may
+//! label a result secret where the AST does not (a public `if` with one
+//! secret branch is secret whichever branch runs), but never the reverse:
+//! that would mean the transform lost track of a secret.
+
+use crate::ast::Expr;
+use crate::config::Config;
+use crate::diagnostic::Diagnostic;
+use crate::emit::BACKENDS;
+use crate::interp::{
+    eval_ast, eval_ast_outputs, eval_ir, eval_ir_outputs, EvalError, Inputs, Labeled,
+};
+use crate::ir::ObliExpr;
+use crate::passes::BUILTIN_PASSES;
+use crate::run::{prepare_ast, run_native, RunError};
+use crate::symbol::Symbol;
+use crate::transform::{to_oblivious, to_oblivious_checked};
+use std::fmt;
+use thiserror::Error;
+
+/// What a program computes: its result, or its named outputs in order.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Results {
+    Value(Labeled),
+    Outputs(Vec<(Symbol, Labeled)>),
+}
+
+impl Results {
+    /// The lines the emitted program prints: `Result: v`, or `name: v`
+    /// for each output.
+    pub fn printed(&self) -> String {
+        match self {
+            Results::Value(result) => format!("Result: {}\n", result.value),
+            Results::Outputs(outputs) => outputs
+                .iter()
+                .map(|(name, output)| format!("{}: {}\n", name, output.value))
+                .collect(),
+        }
+    }
+}
+
+impl fmt::Display for Results {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Results::Value(result) => write!(f, "{}", result),
+            Results::Outputs(outputs) => {
+                for (i, (name, output)) in outputs.iter().enumerate() {
+                    let sep = if i == 0 { "" } else { ", " };
+                    write!(f, "{}{} = {}", sep, name, output)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Result of evaluating a program.
+pub type Outcome = Result<Results, EvalError>;
+
+#[derive(Error, Debug)]
+pub enum DifferentialError {
+    /// The program does not compile, or the emitted code could not be run.
+    #[error(transparent)]
+    Run(#[from] RunError),
+    #[error("{stage} gave {}, but the AST interpreter gave {}", show(.actual), show(.expected))]
+    Mismatch {
+        stage: &'static str,
+        expected: Outcome,
+        actual: Outcome,
+    },
+    #[error(
+        "the {backend} back end printed `{actual}`, but the AST interpreter gave {}",
+        show(.expected)
+    )]
+    Native {
+        backend: &'static str,
+        expected: Outcome,
+        actual: String,
+    },
+    #[error("unknown backend `{0}` (available: {backends})", backends = BACKENDS.join(", "))]
+    UnknownBackend(String),
+}
+
+fn show(outcome: &Outcome) -> String {
+    match outcome {
+        Ok(value) => format!("`{}`", value),
+        Err(e) => format!("error `{}`", e),
+    }
+}
+
+/// Check that the interpreters agree on `source` with its free variables
+/// bound to `inputs`, returning their common outcome.
+pub fn check(source: &str, inputs: &Inputs) -> Result<Outcome, DifferentialError> {
+    let (ast, spans) = prepare_ast(source, inputs)?;
+    // Rejected before evaluation: a loop on a secret condition may not stop
+    if let Err(errors) = to_oblivious_checked(&ast, &spans) {
+        let diagnostics = errors.into_iter().map(Diagnostic::from).collect();
+        return Err(RunError::Diagnostics(diagnostics).into());
+    }
+    check_ast_with_inputs(&ast, inputs)
+}
+
+/// Like [`check`], on a well-typed AST without free variables.
+pub fn check_ast(ast: &Expr) -> Result<Outcome, DifferentialError> {
+    check_ast_with_inputs(ast, &Inputs::new())
+}
+
+/// Like [`check_ast`], reading the program's declared `input`s from `inputs`.
+pub fn check_ast_with_inputs(ast: &Expr, inputs: &Inputs) -> Result<Outcome, DifferentialError> {
+    let ir = to_oblivious(ast);
+    let expected = if ir.outputs().is_some() {
+        eval_ast_outputs(ast, inputs).map(Results::Outputs)
+    } else {
+        eval_ast(ast, inputs).map(Results::Value)
+    };
+
+    compare("IR evaluation", &expected, eval_ir_results(&ir, inputs))?;
+    let optimised = all_passes().pipeline().run(ir);
+    compare("IR evaluation after passes", &expected, eval_ir_results(&optimised, inputs))?;
+    Ok(expected)
+}
+
+fn eval_ir_results(ir: &ObliExpr, inputs: &Inputs) -> Outcome {
+    if ir.outputs().is_some() {
+        eval_ir_outputs(ir, inputs).map(Results::Outputs)
+    } else {
+        eval_ir(ir, inputs).map(Results::Value)
+    }
+}
+
+/// Like [`check`], also compiling the emitted Rust with `rustc` and
+/// checking what it prints (a failed evaluation must make it panic).
+pub fn check_native(source: &str, inputs: &Inputs) -> Result<Outcome, DifferentialError> {
+    check_backends(source, inputs, &["rust"])
+}
+
+/// Like [`check`], also running the program as compiled for each of
+/// `backends` (names from [`BACKENDS`]) and checking what it prints.
+pub fn check_backends(
+    source: &str,
+    inputs: &Inputs,
+    backends: &[&str],
+) -> Result<Outcome, DifferentialError> {
+    let backends = backends
+        .iter()
+        .map(|name| {
+            BACKENDS
+                .iter()
+                .find(|b| *b == name)
+                .ok_or_else(|| DifferentialError::UnknownBackend(name.to_string()))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let expected = check(source, inputs)?;
+    for backend in backends {
+        let output = run_backend(backend, source, inputs);
+        let agrees = match (&expected, &output) {
+            (Ok(results), Ok(output)) => output.trim_end() == results.printed().trim_end(),
+            (Err(_), Err(RunError::Exit(_))) => true,
+            _ => false,
+        };
+        let actual = match output {
+            _ if agrees => continue,
+            Ok(actual) => actual,
+            Err(RunError::Exit(status)) => format!("nothing (exited with {})", status),
+            Err(e) => return Err(e.into()),
+        };
+        return Err(DifferentialError::Native {
+            backend,
+            expected,
+            actual,
+        });
+    }
+    Ok(expected)
+}
+
+/// Compile `source` for `backend`, run it, and return what it printed.
+fn run_backend(backend: &str, source: &str, inputs: &Inputs) -> Result<String, RunError> {
+    match backend {
+        "rust" => run_native(source, inputs, &all_passes()),
+        _ => unreachable!("every name in BACKENDS has a runner"),
+    }
+}
+
+fn all_passes() -> Config {
+    Config {
+        passes: BUILTIN_PASSES.iter().map(|p| p.to_string()).collect(),
+        ..Config::default()
+    }
+}
+
+fn compare(
+    stage: &'static str,
+    expected: &Outcome,
+    actual: Outcome,
+) -> Result<(), DifferentialError> {
+    // Equal values, and no label the IR lost
+    let same = |e: &Labeled, a: &Labeled| e.value == a.value && (a.secret || !e.secret);
+    let agrees = match (expected, &actual) {
+        (Ok(Results::Value(e)), Ok(Results::Value(a))) => same(e, a),
+        (Ok(Results::Outputs(e)), Ok(Results::Outputs(a))) => {
+            e.len() == a.len()
+                && e.iter().zip(a).all(|((e_name, e), (a_name, a))| e_name == a_name && same(e, a))
+        }
+        (Err(e), Err(a)) => e == a,
+        _ => false,
+    };
+    if agrees {
+        return Ok(());
+    }
+    Err(DifferentialError::Mismatch {
+        stage,
+        expected: expected.clone(),
+        actual,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interp::Value;
+
+    #[test]
+    fn test_interpreters_agree() {
+        let inputs = Inputs::new()
+            .secret("k", Value::Int(7))
+            .public("n", Value::Int(3));
+        let programs = [
+            "if k > n then k * n else k - n",
+            "let x = secret(2) if n == 3 then x else 1",
+            "-(k % n) + (if true then 1 else 2)",
+            "k / (n - 3)",
+        ];
+        for program in programs {
+            if let Err(e) = check(program, &inputs) {
+                panic!("{}: {}", program, e);
+            }
+        }
+        let outcome = check("if k > n then k * n else k - n", &inputs).unwrap();
+        assert_eq!(outcome, Ok(Results::Value(Labeled::secret(Value::Int(21)))));
+    }
+
+    #[test]
+    fn test_promoted_integers_agree() {
+        use crate::typeck::Width;
+        let source = "let a = secret(200u8) let b: u32 = a * 2 + 70000u32 let c = b - 1 c";
+        let outcome = check(source, &Inputs::new()).unwrap();
+        let sum = Value::Sized(70143, Width::U32);
+        assert_eq!(outcome, Ok(Results::Value(Labeled::secret(sum))));
+    }
+
+    #[test]
+    fn test_declared_inputs_are_read() {
+        let inputs = Inputs::new()
+            .secret("pin", Value::Int(1234))
+            .public("attempts", Value::Int(2));
+        let source = "input secret pin: int input attempts: int pin == 1234 and attempts < 3";
+        let outcome = check_native(source, &inputs).unwrap();
+        assert_eq!(outcome, Ok(Results::Value(Labeled::secret(Value::Bool(true)))));
+    }
+
+    #[test]
+    fn test_secret_loop_is_rejected_before_evaluation() {
+        let err = check("while secret(true) with x = 0 do x + 1", &Inputs::new()).unwrap_err();
+        assert!(matches!(err, DifferentialError::Run(RunError::Diagnostics(_))));
+    }
+
+    #[test]
+    fn test_outputs_agree() {
+        let inputs = Inputs::new().secret("k", Value::Int(4));
+        let source = "let n = 2 assert(n > 0) outputs(a = k + n, b = n * 3)";
+        let outcome = check_native(source, &inputs).unwrap().unwrap();
+        assert_eq!(
+            outcome,
+            Results::Outputs(vec![
+                ("a".into(), Labeled::secret(Value::Int(6))),
+                ("b".into(), Labeled::public(Value::Int(6))),
+            ])
+        );
+        assert_eq!(outcome.printed(), "a: 6\nb: 6\n");
+    }
+
+    #[test]
+    fn test_mismatch_is_reported() {
+        let expected = Ok(Results::Value(Labeled::secret(Value::Int(1))));
+        let actual = Ok(Results::Value(Labeled::public(Value::Int(1))));
+        let err = compare("IR evaluation", &expected, actual);
+        assert_eq!(
+            err.unwrap_err().to_string(),
+            "IR evaluation gave `1 (public)`, but the AST interpreter gave `1 (secret)`"
+        );
+    }
+
+    #[test]
+    fn test_native_agrees() {
+        let inputs = Inputs::new().secret("k", Value::Int(4));
+        let outcome = check_native("if k < 5 then k + 1 else 0", &inputs).unwrap();
+        assert_eq!(outcome, Ok(Results::Value(Labeled::secret(Value::Int(5)))));
+    }
+
+    #[test]
+    fn test_backends_are_checked_by_name() {
+        let outcome = check_backends("secret(6) / 4", &Inputs::new(), BACKENDS).unwrap();
+        assert_eq!(outcome, Ok(Results::Value(Labeled::secret(Value::Int(1)))));
+        let err = check_backends("1", &Inputs::new(), &["circuit"]).unwrap_err();
+        assert_eq!(err.to_string(), "unknown backend `circuit` (available: rust)");
+    }
+}
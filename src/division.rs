@@ -0,0 +1,545 @@
+// SPDX-License-Identifier: MIT OR AGPL-3.0-or-later
+// SPDX-FileCopyrightText: 2024-2025 hyperpolymath
+
+//! What `x / y` and `x % y` do when a secret divisor `y` is zero.
+//!
+//! By default the generated program panics, which reveals that the secret
+//! was zero ([`DivisionByZero::Trap`]). The other settings, chosen with
+//! `[arithmetic] division-by-zero` in `obli.toml`, remove the trap:
+//!
+//! * `"zero"` and `"all-ones"` make the result `0` or `-1` (every bit
+//!   set). Each secret division is rewritten after the transform into
+//!
+//!   ```text
+//!   let d = y  let z = d == 0  ct_select(z, 0, x / ct_select(z, 1, d))
+//!   ```
+//!
+//!   so the runtime never divides by zero and takes the same path either
+//!   way. The literals take the width of a sized division;
+//! * `"require-nonzero"` rejects every secret division whose divisor is
+//!   not provably nonzero: a nonzero public literal, its negation, a
+//!   variable bound to one, or an `if` whose arms all are. Secret literals
+//!   stand for inputs, so they prove nothing.
+//!
+//! A public divisor is left alone: a trap on it reveals nothing secret.
+
+use crate::codes;
+use crate::ir::{ObliBinOp, ObliExpr, ObliUnaryOp};
+use crate::span::{NodeSpans, Span, SpanCursor};
+use crate::stack;
+use crate::symbol::{NameSupply, Scope, Symbol};
+use crate::typeck::{Scalar, Width};
+use serde::Deserialize;
+use std::collections::HashSet;
+use thiserror::Error;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum DivisionByZero {
+    /// Panic at run time.
+    #[default]
+    Trap,
+    /// The result is `0`.
+    Zero,
+    /// The result is `-1`.
+    AllOnes,
+    /// Reject divisors that may be zero.
+    RequireNonzero,
+}
+
+#[derive(Error, Debug, Clone, PartialEq)]
+pub enum DivisionError {
+    #[error("secret divisor may be zero")]
+    MaybeZero { span: Span },
+}
+
+impl DivisionError {
+    /// Catalog code of this error.
+    pub fn code(&self) -> &'static str {
+        codes::DIVISOR_MAY_BE_ZERO
+    }
+
+    /// Source span of the division (empty if spans were not supplied).
+    pub fn span(&self) -> Span {
+        match self {
+            DivisionError::MaybeZero { span } => *span,
+        }
+    }
+}
+
+impl DivisionByZero {
+    /// Apply the setting to IR straight from the transform, whose post-order
+    /// spans are `spans`.
+    pub fn apply(
+        self,
+        expr: ObliExpr,
+        spans: &NodeSpans,
+    ) -> Result<ObliExpr, Vec<DivisionError>> {
+        match self {
+            DivisionByZero::Trap => Ok(expr),
+            DivisionByZero::Zero => Ok(guard_divisions(expr, 0)),
+            DivisionByZero::AllOnes => Ok(guard_divisions(expr, -1)),
+            DivisionByZero::RequireNonzero => {
+                let mut checker = Checker {
+                    spans: SpanCursor::new(Some(spans)),
+                    scope: Scope::new(),
+                    errors: Vec::new(),
+                };
+                checker.visit(&expr);
+                if checker.errors.is_empty() {
+                    Ok(expr)
+                } else {
+                    Err(checker.errors)
+                }
+            }
+        }
+    }
+}
+
+/// Rewrite every division and modulo by a secret divisor to yield
+/// `default` instead of dividing by zero.
+pub fn guard_divisions(expr: ObliExpr, default: i64) -> ObliExpr {
+    let mut used = HashSet::new();
+    collect_names(&expr, &mut used);
+    Guard {
+        default,
+        names: NameSupply::new(used),
+        widths: Scope::new(),
+    }
+    .rewrite(expr)
+}
+
+fn collect_names(expr: &ObliExpr, used: &mut HashSet<Symbol>) {
+    if let ObliExpr::Var { name, .. }
+    | ObliExpr::Let { name, .. }
+    | ObliExpr::While { acc: name, .. } = expr
+    {
+        used.insert(*name);
+    }
+    if let ObliExpr::CtSwap { names, .. } = expr {
+        used.extend(names);
+    }
+    for child in expr.children() {
+        stack::guard(|| collect_names(child, used));
+    }
+}
+
+struct Guard {
+    default: i64,
+    names: NameSupply,
+    /// The width of each bound variable, `None` for `int` or `bool`.
+    widths: Scope<Option<Width>>,
+}
+
+impl Guard {
+    /// The width of a sized integer expression; `None` for anything else.
+    fn width(&mut self, expr: &ObliExpr) -> Option<Width> {
+        match expr {
+            ObliExpr::Sized { width, .. }
+            | ObliExpr::CtCast {
+                to: Scalar::Sized(width),
+                ..
+            } => Some(*width),
+            ObliExpr::Var { name, .. } => self.widths.get(*name).copied().flatten(),
+            ObliExpr::BinOp {
+                op:
+                    ObliBinOp::CtEq
+                    | ObliBinOp::CtNe
+                    | ObliBinOp::CtLt
+                    | ObliBinOp::CtLe
+                    | ObliBinOp::CtGt
+                    | ObliBinOp::CtGe
+                    | ObliBinOp::CtAnd
+                    | ObliBinOp::CtOr,
+                ..
+            } => None,
+            ObliExpr::BinOp { left, right, .. } => {
+                stack::guard(|| self.width(left).or_else(|| self.width(right)))
+            }
+            ObliExpr::CtSelect {
+                then_val: first,
+                else_val: second,
+                ..
+            }
+            | ObliExpr::PubIf {
+                then_branch: first,
+                else_branch: second,
+                ..
+            } => stack::guard(|| self.width(first).or_else(|| self.width(second))),
+            ObliExpr::UnaryOp { expr, .. }
+            | ObliExpr::Declassify(expr)
+            | ObliExpr::While { init: expr, .. } => stack::guard(|| self.width(expr)),
+            ObliExpr::Let {
+                name, value, body, ..
+            } => {
+                let width = stack::guard(|| self.width(value));
+                self.widths.push(*name, width);
+                let body = stack::guard(|| self.width(body));
+                self.widths.pop();
+                body
+            }
+            ObliExpr::CtSwap {
+                left,
+                right,
+                names,
+                body,
+                ..
+            } => {
+                let width = stack::guard(|| self.width(left).or_else(|| self.width(right)));
+                names.iter().for_each(|name| self.widths.push(*name, width));
+                let body = stack::guard(|| self.width(body));
+                names.iter().for_each(|_| self.widths.pop());
+                body
+            }
+            _ => None,
+        }
+    }
+
+
+    fn rewrite(&mut self, expr: ObliExpr) -> ObliExpr {
+        stack::guard(|| self.rewrite_node(expr))
+    }
+
+    fn rewrite_node(&mut self, expr: ObliExpr) -> ObliExpr {
+        let boxed = |this: &mut Self, expr: Box<ObliExpr>| Box::new(this.rewrite(*expr));
+        match expr {
+            ObliExpr::BinOp {
+                op: op @ (ObliBinOp::CtDiv | ObliBinOp::CtMod),
+                left,
+                right,
+                ..
+            } if right.is_secret() => {
+                let left = boxed(self, left);
+                let right = boxed(self, right);
+                let width = self.width(&left).or_else(|| self.width(&right));
+                let literal = |n: i64| match width {
+                    Some(width) => ObliExpr::Sized {
+                        value: width.wrap(n.into()),
+                        width,
+                        is_secret: false,
+                    },
+                    None => ObliExpr::PubInt(n),
+                };
+                let (divisor, zero) = (self.names.fresh(), self.names.fresh());
+                let var = |name| {
+                    Box::new(ObliExpr::Var {
+                        name,
+                        is_secret: true,
+                    })
+                };
+                let safe = ObliExpr::CtSelect {
+                    cond: var(zero),
+                    then_val: Box::new(literal(1)),
+                    else_val: var(divisor),
+                };
+                let quotient = ObliExpr::BinOp {
+                    op,
+                    left,
+                    right: Box::new(safe),
+                    is_secret: true,
+                };
+                let select = ObliExpr::CtSelect {
+                    cond: var(zero),
+                    then_val: Box::new(literal(self.default)),
+                    else_val: Box::new(quotient),
+                };
+                let is_zero = ObliExpr::BinOp {
+                    op: ObliBinOp::CtEq,
+                    left: var(divisor),
+                    right: Box::new(literal(0)),
+                    is_secret: true,
+                };
+                ObliExpr::Let {
+                    name: divisor,
+                    value: right,
+                    body: Box::new(ObliExpr::Let {
+                        name: zero,
+                        value: Box::new(is_zero),
+                        body: Box::new(select),
+                        is_secret: true,
+                    }),
+                    is_secret: true,
+                }
+            }
+            ObliExpr::BinOp {
+                op,
+                left,
+                right,
+                is_secret,
+            } => ObliExpr::BinOp {
+                op,
+                left: boxed(self, left),
+                right: boxed(self, right),
+                is_secret,
+            },
+            ObliExpr::UnaryOp {
+                op,
+                expr,
+                is_secret,
+            } => ObliExpr::UnaryOp {
+                op,
+                expr: boxed(self, expr),
+                is_secret,
+            },
+            ObliExpr::CtCast {
+                expr,
+                from,
+                to,
+                is_secret,
+            } => ObliExpr::CtCast {
+                expr: boxed(self, expr),
+                from,
+                to,
+                is_secret,
+            },
+            ObliExpr::CtSelect {
+                cond,
+                then_val,
+                else_val,
+            } => ObliExpr::CtSelect {
+                cond: boxed(self, cond),
+                then_val: boxed(self, then_val),
+                else_val: boxed(self, else_val),
+            },
+            ObliExpr::PubIf {
+                cond,
+                then_branch,
+                else_branch,
+            } => ObliExpr::PubIf {
+                cond: boxed(self, cond),
+                then_branch: boxed(self, then_branch),
+                else_branch: boxed(self, else_branch),
+            },
+            ObliExpr::Let {
+                name,
+                value,
+                body,
+                is_secret,
+            } => {
+                let value = boxed(self, value);
+                let width = self.width(&value);
+                self.widths.push(name, width);
+                let body = boxed(self, body);
+                self.widths.pop();
+                ObliExpr::Let {
+                    name,
+                    value,
+                    body,
+                    is_secret,
+                }
+            }
+            ObliExpr::Outputs(outputs) => ObliExpr::Outputs(
+                outputs.into_iter().map(|(name, value)| (name, self.rewrite(value))).collect(),
+            ),
+            ObliExpr::Table { values, index } => ObliExpr::Table {
+                values,
+                index: boxed(self, index),
+            },
+            ObliExpr::While {
+                cond,
+                acc,
+                init,
+                body,
+                is_secret,
+            } => {
+                let width = self.width(&init);
+                self.widths.push(acc, width);
+                let cond = boxed(self, cond);
+                self.widths.pop();
+                let init = boxed(self, init);
+                self.widths.push(acc, width);
+                let body = boxed(self, body);
+                self.widths.pop();
+                ObliExpr::While {
+                    cond,
+                    acc,
+                    init,
+                    body,
+                    is_secret,
+                }
+            }
+            ObliExpr::CtSwap {
+                cond,
+                left,
+                right,
+                names,
+                body,
+                is_secret,
+            } => {
+                let cond = boxed(self, cond);
+                let (left, right) = (boxed(self, left), boxed(self, right));
+                let width = self.width(&left).or_else(|| self.width(&right));
+                names.iter().for_each(|name| self.widths.push(*name, width));
+                let body = boxed(self, body);
+                names.iter().for_each(|_| self.widths.pop());
+                ObliExpr::CtSwap {
+                    cond,
+                    left,
+                    right,
+                    names,
+                    body,
+                    is_secret,
+                }
+            }
+            ObliExpr::Declassify(inner) => ObliExpr::Declassify(boxed(self, inner)),
+            leaf => leaf,
+        }
+    }
+}
+
+struct Checker<'a> {
+    spans: SpanCursor<'a>,
+    /// Whether each bound variable is provably nonzero.
+    scope: Scope<bool>,
+    errors: Vec<DivisionError>,
+}
+
+impl Checker<'_> {
+    fn visit(&mut self, expr: &ObliExpr) {
+        stack::guard(|| self.visit_node(expr))
+    }
+
+    fn visit_node(&mut self, expr: &ObliExpr) {
+        // Children first, so spans are consumed in post-order
+        if let ObliExpr::Let { name, value, body, .. } = expr {
+            self.visit(value);
+            let nonzero = self.nonzero(value);
+            self.scope.push(*name, nonzero);
+            self.visit(body);
+            self.scope.pop();
+        } else if let ObliExpr::While {
+            cond,
+            acc,
+            init,
+            body,
+            ..
+        } = expr
+        {
+            // Whatever the accumulator starts as, an iteration may zero it
+            self.scope.push(*acc, false);
+            self.visit(cond);
+            self.scope.pop();
+            self.visit(init);
+            self.scope.push(*acc, false);
+            self.visit(body);
+            self.scope.pop();
+        } else if let ObliExpr::CtSwap {
+            cond,
+            left,
+            right,
+            names,
+            body,
+            ..
+        } = expr
+        {
+            self.visit(cond);
+            self.visit(left);
+            self.visit(right);
+            // Either name may hold either operand
+            let nonzero = self.nonzero(left) && self.nonzero(right);
+            names.iter().for_each(|name| self.scope.push(*name, nonzero));
+            self.visit(body);
+            names.iter().for_each(|_| self.scope.pop());
+        } else {
+            for child in expr.children() {
+                self.visit(child);
+            }
+        }
+        let span = self.spans.next_span().unwrap_or_default();
+        if let ObliExpr::BinOp {
+            op: ObliBinOp::CtDiv | ObliBinOp::CtMod,
+            right,
+            ..
+        } = expr
+        {
+            if right.is_secret() && !self.nonzero(right) {
+                self.errors.push(DivisionError::MaybeZero { span });
+            }
+        }
+    }
+
+    fn nonzero(&self, expr: &ObliExpr) -> bool {
+        match expr {
+            ObliExpr::PubInt(n) => *n != 0,
+            ObliExpr::Var { name, .. } => self.scope.get(*name).is_some_and(|nonzero| *nonzero),
+            ObliExpr::UnaryOp {
+                op: ObliUnaryOp::CtNeg,
+                expr,
+                ..
+            } => self.nonzero(expr),
+            ObliExpr::CtSelect {
+                then_val: then_branch,
+                else_val: else_branch,
+                ..
+            }
+            | ObliExpr::PubIf {
+                then_branch,
+                else_branch,
+                ..
+            } => stack::guard(|| self.nonzero(then_branch) && self.nonzero(else_branch)),
+            // Variables bound inside are not in scope here
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interp::{Inputs, Value};
+    use crate::run::{run_interpreted, run_native};
+    use crate::{transpile_with, Config, TranspileError};
+
+    fn config(division_by_zero: DivisionByZero) -> Config {
+        Config {
+            division_by_zero,
+            ..Config::default()
+        }
+    }
+
+    #[test]
+    fn test_zero_divisor_yields_default() {
+        let source = "(100 / k) + (7 % k)";
+        for (setting, at_zero) in [(DivisionByZero::Zero, 0), (DivisionByZero::AllOnes, -2)] {
+            let config = config(setting);
+            for (k, expected) in [(0, at_zero), (3, 34), (-5, -18)] {
+                let inputs = Inputs::new().secret("k", Value::Int(k));
+                let result = run_interpreted(source, &inputs, &config).unwrap();
+                assert_eq!(result.value, Value::Int(expected));
+                assert!(result.secret);
+            }
+        }
+        let inputs = Inputs::new().secret("k", Value::Int(0));
+        let native = run_native(source, &inputs, &config(DivisionByZero::Zero)).unwrap();
+        assert_eq!(native, "Result: 0\n");
+        assert!(run_native(source, &inputs, &Config::default()).is_err());
+    }
+
+    #[test]
+    fn test_sized_divisions_keep_their_width() {
+        let source = "let n = 200u8 n / k";
+        let inputs = Inputs::new().secret("k", Value::Sized(0, Width::U8));
+        let config = config(DivisionByZero::AllOnes);
+        let result = run_interpreted(source, &inputs, &config).unwrap();
+        assert_eq!(result.value, Value::Sized(255, Width::U8));
+        assert_eq!(run_native(source, &inputs, &config).unwrap(), "Result: 255\n");
+    }
+
+    #[test]
+    fn test_require_nonzero() {
+        let config = config(DivisionByZero::RequireNonzero);
+        let Err(TranspileError::Division(errors)) =
+            transpile_with("let k = secret(4) 12 / k + 12 / 2", &config)
+        else {
+            panic!("expected a division error");
+        };
+        assert_eq!(errors, [DivisionError::MaybeZero { span: Span::new(18, 24) }]);
+        assert_eq!(errors[0].code(), codes::DIVISOR_MAY_BE_ZERO);
+
+        let proven = "let k = secret(4) let d = if k > 2 then 5 else -3 12 / d + k / 2";
+        assert_eq!(
+            transpile_with(proven, &config).unwrap(),
+            transpile_with(proven, &Config::default()).unwrap()
+        );
+    }
+}
@@ -0,0 +1,274 @@
+// SPDX-License-Identifier: MIT OR AGPL-3.0-or-later
+// SPDX-FileCopyrightText: 2024-2025 hyperpolymath
+
+//! Embedding MiniObli in Rust expressions.
+//!
+//! [`expand`] turns a program into a Rust block expression, which is what
+//! the `obli!` macro of the `obli-macros` crate inlines:
+//!
+//! ```ignore
+//! let input: i64 = 7;
+//! let k: i64 = obli! { let x = secret(input) if x > 0 then x * 2 else 0 };
+//! ```
+//!
+//! Free variables capture Rust variables of the same name, which must be
+//! `i64` or `bool`: `i64` unless the program only typechecks with the
+//! variable as `bool`. Captured values are public; wrap them in `secret`
+//! as above to make them secret. The block evaluates the constant-time
+//! code in a private module and reveals its result, so the expression has
+//! type `i64` or `bool`; named `outputs` and `input` declarations are
+//! rejected.
+
+use crate::config::Config;
+use crate::diagnostic::Diagnostic;
+use crate::emit::{
+    check_emittable, emit_function, EmitError, RESERVED_NAMES, RUNTIME_PRELUDE,
+};
+use crate::hygiene::mangle_reserved;
+use crate::interp::{Inputs, Value};
+use crate::ir::ObliExpr;
+use crate::lexer::Lexer;
+use crate::parser::Parser;
+use crate::run::{prepare_ast, RunError};
+use crate::symbol::Symbol;
+use crate::transform::to_oblivious_checked;
+use crate::typeck::{typecheck, Type, TypeError};
+
+/// Free variables beyond this many are all taken as `i64`.
+const MAX_INFERRED: usize = 8;
+
+/// The Rust block expression computing `source` with the passes of
+/// `config`, or the program's diagnostics, spanned in `source`.
+pub fn expand(source: &str, config: &Config) -> Result<String, Vec<Diagnostic>> {
+    let embedded = embed(source, config)?;
+    Ok(format!("{{\n{}obli_embedded::run({})\n}}\n", embedded.module, embedded.args()))
+}
+
+/// A program compiled into a private module `obli_embedded` holding the
+/// runtime prelude and `pub(super) fn run`, which takes the captured
+/// variables and returns the revealed result. With captures, it also has
+/// `pub(super) fn run_batch`, which takes a slice per variable and runs
+/// every record.
+pub(crate) struct Embedded {
+    pub module: String,
+    /// Captured variables and their Rust types, in name order.
+    pub captures: Vec<(String, &'static str)>,
+    /// Rust type of the result.
+    pub ty: &'static str,
+}
+
+impl Embedded {
+    /// `run`'s parameter list.
+    pub fn params(&self) -> String {
+        let params: Vec<_> =
+            self.captures.iter().map(|(name, ty)| format!("{}: {}", name, ty)).collect();
+        params.join(", ")
+    }
+
+    /// The captured variables as arguments to `run`.
+    pub fn args(&self) -> String {
+        let args: Vec<_> = self.captures.iter().map(|(name, _)| name.as_str()).collect();
+        args.join(", ")
+    }
+
+    /// `run_batch`'s parameter list: one slice per captured variable.
+    pub fn batch_params(&self) -> String {
+        let params: Vec<_> =
+            self.captures.iter().map(|(name, ty)| format!("{}: &[{}]", name, ty)).collect();
+        params.join(", ")
+    }
+
+    /// `fn run_batch`, running `run` on the `i`th element of every slice
+    /// for each `i`. The loop has no branches on the data, so the compiler
+    /// is free to vectorize the inlined kernel.
+    fn run_batch(&self) -> Option<String> {
+        let ((first, _), rest) = self.captures.split_first()?;
+        let mut body = format!("    let len = {}.len();
+", first);
+        for (name, _) in rest {
+            body.push_str(&format!(
+                "    assert_eq!({}.len(), len, \"batch inputs differ in length\");\n",
+                name
+            ));
+        }
+        let args: Vec<_> = self.captures.iter().map(|(name, _)| format!("{}[i]", name)).collect();
+        Some(format!(
+            "\npub(super) fn run_batch({}) -> Vec<{}> {{\n{}    \
+             (0..len).map(|i| run({})).collect()\n}}\n",
+            self.batch_params(),
+            self.ty,
+            body,
+            args.join(", ")
+        ))
+    }
+}
+
+pub(crate) fn embed(source: &str, config: &Config) -> Result<Embedded, Vec<Diagnostic>> {
+    let names = free_variables(source);
+    let (ast, spans, types) = infer_captures(source, &names)?;
+    let ty = match typecheck(&ast) {
+        Ok(Type::Bool) => "bool",
+        Ok(Type::Outputs) => {
+            // The program's root comes before the lets binding captures
+            let span = spans.get(spans.len() - 1 - names.len()).unwrap_or_default();
+            return Err(vec![TypeError::MisplacedOutputs { span }.into()]);
+        }
+        _ => "i64",
+    };
+
+    let (ir, ir_spans, _) = to_oblivious_checked(&ast, &spans)
+        .map_err(|errors| errors.into_iter().map(Diagnostic::from).collect::<Vec<_>>())?;
+    // The kernel has no `main` to read inputs: captures stand for them
+    let nodes = ir.post_order();
+    if let Some(index) = nodes.iter().position(|node| matches!(node, ObliExpr::Input { .. })) {
+        let span = ir_spans.get(index).unwrap_or_default();
+        return Err(vec![TypeError::MisplacedInput { span }.into()]);
+    }
+    let mut errors = check_emittable(&ir, &ir_spans);
+    // Captures are bound below, but name the parameters of the kernel
+    errors.extend(names.iter().filter(|name| RESERVED_NAMES.contains(&name.as_str())).map(
+        |name| EmitError::ReservedName {
+            name: name.clone(),
+            span: Default::default(),
+        },
+    ));
+    if !errors.is_empty() {
+        return Err(errors.into_iter().map(Diagnostic::from).collect());
+    }
+    // Captures are bound by leading lets in name order, like `obli run`
+    // inputs: the kernel takes them as parameters instead
+    let mut kernel = ir;
+    for _ in 0..names.len() {
+        kernel = match kernel {
+            ObliExpr::Let { body, .. } => *body,
+            _ => unreachable!("prepare_ast binds every input with a let"),
+        };
+    }
+    let kernel = mangle_reserved(config.pipeline().run(kernel));
+
+    let embedded = Embedded {
+        module: String::new(),
+        captures: names.into_iter().zip(types).collect(),
+        ty,
+    };
+    let params: Vec<_> = embedded
+        .captures
+        .iter()
+        .map(|(name, ty)| (Symbol::from(name), false, *ty))
+        .collect();
+    let args: Vec<_> = embedded
+        .captures
+        .iter()
+        .map(|(name, _)| format!("Pub::new({})", name))
+        .collect();
+    let (ret, reveal) = if kernel.is_secret() {
+        ("Secret", "reveal(&RevealToken::output_section())")
+    } else {
+        ("Pub", "reveal()")
+    };
+
+    let mut module = String::from("#[allow(dead_code, clippy::all)]\nmod obli_embedded {\n");
+    module.push_str(RUNTIME_PRELUDE);
+    module.push('\n');
+    module.push_str(&emit_function("kernel", &params, &format!("{}<{}>", ret, ty), &kernel));
+    module.push_str(&format!(
+        "\n#[inline]\npub(super) fn run({}) -> {} {{\n    *kernel({}).{}\n}}\n",
+        embedded.params(),
+        ty,
+        args.join(", "),
+        reveal
+    ));
+    module.extend(embedded.run_batch());
+    module.push_str("}\n");
+    Ok(Embedded { module, ..embedded })
+}
+
+/// Names `source` uses without binding them, sorted. Empty if it does not
+/// parse; [`prepare_ast`] reports why.
+pub(crate) fn free_variables(source: &str) -> Vec<String> {
+    let Ok((tokens, _)) = Lexer::new(source).tokenize() else {
+        return Vec::new();
+    };
+    let Ok(ast) = Parser::new(&tokens).parse() else {
+        return Vec::new();
+    };
+    let mut names: Vec<_> = match typecheck(&ast) {
+        Ok(_) => Vec::new(),
+        Err(errors) => errors
+            .into_iter()
+            .filter_map(|e| match e {
+                TypeError::Unbound { name, .. } => Some(name),
+                _ => None,
+            })
+            .collect(),
+    };
+    names.sort();
+    names.dedup();
+    names
+}
+
+pub(crate) type Prepared = (crate::ast::Expr, crate::span::NodeSpans, Vec<&'static str>);
+
+/// Bind `captures` to the first types, fewest `bool`s first, that make
+/// `source` typecheck. Reports the all-`i64` attempt's errors otherwise.
+pub(crate) fn infer_captures(
+    source: &str,
+    captures: &[String],
+) -> Result<Prepared, Vec<Diagnostic>> {
+    let inferred = captures.len().min(MAX_INFERRED);
+    let mut masks: Vec<u32> = (0..1u32 << inferred).collect();
+    masks.sort_by_key(|mask| mask.count_ones());
+
+    let mut first_error = None;
+    for mask in masks {
+        let types: Vec<_> = (0..captures.len())
+            .map(|i| if i < inferred && mask & (1 << i) != 0 { "bool" } else { "i64" })
+            .collect();
+        let inputs = captures.iter().zip(&types).fold(Inputs::new(), |inputs, (name, ty)| {
+            let value = if *ty == "bool" { Value::Bool(false) } else { Value::Int(0) };
+            inputs.public(name, value)
+        });
+        match prepare_ast(source, &inputs) {
+            Ok((ast, spans)) => return Ok((ast, spans, types)),
+            Err(e) => {
+                first_error.get_or_insert(e);
+            }
+        }
+    }
+    match first_error.expect("at least one attempt") {
+        RunError::Diagnostics(diagnostics) => Err(diagnostics),
+        other => Err(vec![Diagnostic::error(other.to_string())]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::run::run_emitted;
+
+    fn run_embedded(captures: &str, source: &str) -> String {
+        let block = expand(source, &Config::default()).unwrap();
+        let main = format!(
+            "fn main() {{\n{}\nlet k = {};\nprintln!(\"{{}}\", k);\n}}\n",
+            captures, block
+        );
+        run_emitted(&main).unwrap()
+    }
+
+    #[test]
+    fn test_captures_rust_variables() {
+        let source = "let x = secret(input) if x > 0 then x * 2 else 0";
+        assert_eq!(run_embedded("let input: i64 = 21;", source), "42\n");
+        assert_eq!(run_embedded("let input: i64 = -3;", source), "0\n");
+        let flags = "let a = true; let b: i64 = 4;";
+        assert_eq!(run_embedded(flags, "if secret(a) then b else 0 - b"), "4\n");
+    }
+
+    #[test]
+    fn test_diagnostics_are_spanned_in_source() {
+        let errors = expand("1 + (true < 2)", &Config::default()).unwrap_err();
+        assert_eq!(errors[0].span, Some(crate::span::Span::new(5, 9)));
+        let errors = expand("secret(ct_select)", &Config::default()).unwrap_err();
+        assert_eq!(errors[0].code, Some(crate::codes::RESERVED_NAME));
+    }
+}
@@ -3,105 +3,636 @@
 
 //! Rust code emitter for oblivious IR.
 //!
-//! Emits Rust code using constant-time primitives.
+//! Emits Rust code using constant-time primitives. Code is written into
+//! any [`fmt::Write`] or [`io::Write`] sink as it is generated
+//! ([`emit_rust_to`], [`write_rust`]), so large programs can stream
+//! straight to a file.
 
+use crate::codes;
 use crate::ir::{ObliBinOp, ObliExpr, ObliUnaryOp};
+use crate::span::{NodeSpans, Span, SpanCursor};
+use crate::stack;
+use crate::symbol::{Scope, Symbol};
+use crate::typeck::Scalar;
+use std::fmt;
+use std::io;
+use thiserror::Error;
+
+/// Back ends that code can be emitted for.
+pub const BACKENDS: &[&str] = &["rust"];
+
+/// Names that MiniObli allows but generated Rust cannot use as variables:
+/// Rust keywords, the variants of the standard prelude, and the items of
+/// the runtime prelude. Bound variables
+/// with these names are renamed by [`mangle_reserved`](crate::hygiene::mangle_reserved).
+pub const RESERVED_NAMES: &[&str] = &[
+    "_", "abstract", "as", "async", "await", "become", "box", "break", "const", "continue",
+    "crate", "do", "dyn", "enum", "extern", "final", "fn", "for", "gen", "impl", "in", "loop",
+    "macro", "match", "mod", "move", "mut", "override", "priv", "pub", "ref", "return", "self",
+    "Self", "static", "struct", "super", "trait", "try", "type", "typeof", "unsafe", "unsized",
+    "use", "virtual", "where", "while", "yield", "Some", "None", "Ok", "Err", "Pub", "Secret",
+    "RevealToken", "ct_select",
+    "ct_table", "ct_scan", "ct_swap", "read_inputs", "parse_input", "__obli_inputs",
+    "__obli_held", "__obli_cond", "__obli_outer", "__obli_then", "__obli_then_held",
+    "__obli_else",
+];
+
+/// Message of the check, when the result is revealed, of the assertions
+/// on secret data.
+const DEFERRED_FAILURE: &str = "an assertion on secret data failed";
+
+#[derive(Error, Debug, Clone, PartialEq)]
+pub enum EmitError {
+    #[error("`{name}` cannot be used as an input or output name in generated Rust")]
+    ReservedName { name: String, span: Span },
+}
+
+impl EmitError {
+    /// Catalog code of this error.
+    pub fn code(&self) -> &'static str {
+        match self {
+            EmitError::ReservedName { .. } => codes::RESERVED_NAME,
+        }
+    }
+
+    /// Source span of the offending binding (empty if spans were not supplied).
+    pub fn span(&self) -> Span {
+        match self {
+            EmitError::ReservedName { span, .. } => *span,
+        }
+    }
+}
+
+/// Check that an IR tree can be emitted, reporting errors at the spans
+/// recorded by [`to_oblivious_with_spans`](crate::transform::to_oblivious_with_spans).
+pub fn check_emittable(expr: &ObliExpr, spans: &NodeSpans) -> Vec<EmitError> {
+    let mut errors = Vec::new();
+    let mut scope = Scope::new();
+    check_names(expr, &mut SpanCursor::new(Some(spans)), &mut scope, &mut errors);
+    errors
+}
+
+fn check_names(
+    expr: &ObliExpr,
+    spans: &mut SpanCursor,
+    scope: &mut Scope<()>,
+    errors: &mut Vec<EmitError>,
+) {
+    if let ObliExpr::Let { name, value, body, .. } = expr {
+        stack::guard(|| check_names(value, spans, scope, errors));
+        scope.push(*name, ());
+        stack::guard(|| check_names(body, spans, scope, errors));
+        scope.pop();
+    } else if let ObliExpr::While {
+        cond,
+        acc,
+        init,
+        body,
+        ..
+    } = expr
+    {
+        scope.push(*acc, ());
+        stack::guard(|| check_names(cond, spans, scope, errors));
+        scope.pop();
+        stack::guard(|| check_names(init, spans, scope, errors));
+        scope.push(*acc, ());
+        stack::guard(|| check_names(body, spans, scope, errors));
+        scope.pop();
+    } else if let ObliExpr::CtSwap { names, body, .. } = expr {
+        for child in &expr.children()[..3] {
+            stack::guard(|| check_names(child, spans, scope, errors));
+        }
+        names.iter().for_each(|name| scope.push(*name, ()));
+        stack::guard(|| check_names(body, spans, scope, errors));
+        names.iter().for_each(|_| scope.pop());
+    } else {
+        for child in expr.children() {
+            stack::guard(|| check_names(child, spans, scope, errors));
+        }
+    }
+    let span = spans.next_span().unwrap_or_default();
+    // Bound variables are renamed; free variables and output names are
+    // parameters and fields that code outside the program refers to
+    let names = match expr {
+        ObliExpr::Var { name, .. } if scope.get(*name).is_none() => vec![*name],
+        ObliExpr::Outputs(outputs) => outputs.iter().map(|(name, _)| *name).collect(),
+        _ => return,
+    };
+    for name in names {
+        if RESERVED_NAMES.contains(&name.as_str()) {
+            errors.push(EmitError::ReservedName {
+                name: name.to_string(),
+                span,
+            });
+        }
+    }
+}
+
+/// Emitter settings.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EmitOptions {
+    /// Include the runtime prelude (`Secret`, `Pub`, `ct_select`, …). Turn
+    /// off when the generated code is included into a crate that already
+    /// defines it.
+    pub prelude: bool,
+}
+
+impl Default for EmitOptions {
+    fn default() -> Self {
+        Self { prelude: true }
+    }
+}
 
 /// Emit Rust code from oblivious IR.
 pub fn emit_rust(expr: &ObliExpr) -> String {
-    let mut emitter = RustEmitter::new();
-    emitter.emit_program(expr)
+    emit_rust_with(expr, &EmitOptions::default())
+}
+
+/// Emit Rust code from oblivious IR with the given options.
+pub fn emit_rust_with(expr: &ObliExpr, options: &EmitOptions) -> String {
+    let mut out = String::new();
+    emit_rust_to(expr, options, &mut out).expect("writing to a String cannot fail");
+    out
+}
+
+/// Emit Rust code from oblivious IR into `out` as it is generated, without
+/// building the whole program in memory first.
+pub fn emit_rust_to<W: fmt::Write>(
+    expr: &ObliExpr,
+    options: &EmitOptions,
+    out: &mut W,
+) -> fmt::Result {
+    let _span = tracing::debug_span!("emit").entered();
+    RustEmitter::new(out).emit_program(expr, options)
 }
 
-struct RustEmitter {
+/// Like [`emit_rust_to`], writing to an [`io::Write`] such as a file.
+/// Writes are as small as the generated tokens, so wrap unbuffered
+/// writers in an [`io::BufWriter`].
+pub fn write_rust<W: io::Write>(expr: &ObliExpr, options: &EmitOptions, out: W) -> io::Result<()> {
+    let mut adapter = IoAdapter { out, error: None };
+    match emit_rust_to(expr, options, &mut adapter) {
+        Ok(()) => Ok(()),
+        Err(fmt::Error) => Err(adapter
+            .error
+            .unwrap_or_else(|| io::Error::other("formatter error"))),
+    }
+}
+
+/// A [`fmt::Write`] over an [`io::Write`], keeping the I/O error that
+/// `fmt::Error` cannot carry.
+struct IoAdapter<W> {
+    out: W,
+    error: Option<io::Error>,
+}
+
+impl<W: io::Write> fmt::Write for IoAdapter<W> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.out.write_all(s.as_bytes()).map_err(|e| {
+            self.error = Some(e);
+            fmt::Error
+        })
+    }
+}
+
+/// Emit `expr` as `fn name(params) -> ret` without the prelude, its free
+/// variables bound by the parameters, given as `(name, secret, type)`.
+pub(crate) fn emit_function(
+    name: &str,
+    params: &[(Symbol, bool, &str)],
+    ret: &str,
+    expr: &ObliExpr,
+) -> String {
+    let mut out = String::new();
+    let mut emitter = RustEmitter::new(&mut out);
+    let mut param_list = Vec::new();
+    for (param, secret, ty) in params {
+        let wrapper = if *secret { "Secret" } else { "Pub" };
+        param_list.push(format!("{}: {}<{}>", param, wrapper, ty));
+        emitter.scope.push(*param, *secret);
+    }
+    emitter
+        .emit_function(name, &param_list.join(", "), ret, expr)
+        .expect("writing to a String cannot fail");
+    out
+}
+
+struct RustEmitter<'w, W> {
+    out: &'w mut W,
     indent: usize,
+    /// Variables in scope and whether each holds a `Secret`.
+    scope: Scope<bool>,
+    /// Constant tables defined as `static`s, by index in the name.
+    tables: Vec<Vec<i64>>,
+    /// Program inputs, by index in the words `main` reads.
+    inputs: Vec<Symbol>,
+    /// How many `CtSelect` arms the node being emitted is in.
+    secret_arms: usize,
 }
 
-impl RustEmitter {
-    fn new() -> Self {
-        Self { indent: 0 }
+impl<'w, W: fmt::Write> RustEmitter<'w, W> {
+    fn new(out: &'w mut W) -> Self {
+        Self {
+            out,
+            indent: 0,
+            scope: Scope::new(),
+            tables: Vec::new(),
+            inputs: Vec::new(),
+            secret_arms: 0,
+        }
     }
 
-    fn emit_program(&mut self, expr: &ObliExpr) -> String {
-        let mut output = String::new();
+    /// Whether `name` is bound to a `Pub` value (free variables are not).
+    fn is_public(&self, name: Symbol) -> bool {
+        self.scope.get(name).is_some_and(|secret| !secret)
+    }
 
+    fn emit_program(&mut self, expr: &ObliExpr, options: &EmitOptions) -> fmt::Result {
         // Header with runtime support
-        output.push_str(RUNTIME_PRELUDE);
-        output.push_str("\n");
+        if options.prelude {
+            self.out.write_str(RUNTIME_PRELUDE)?;
+            self.out.write_char('\n')?;
+        }
+
+        // Named outputs are fields of a struct whose types are inferred
+        let outputs = expr.outputs();
+        if let Some(outputs) = outputs {
+            let params: Vec<_> = (0..outputs.len()).map(|i| format!("T{}", i)).collect();
+            writeln!(self.out, "struct Outputs<{}> {{", params.join(", "))?;
+            for ((name, _), param) in outputs.iter().zip(&params) {
+                writeln!(self.out, "    {}: {},", name, param)?;
+            }
+            self.out.write_str("}\n\n")?;
+        }
+
+        // Constant tables, once each however often they are read
+        for node in expr.post_order() {
+            if let ObliExpr::Table { values, .. } = node {
+                if !self.tables.contains(values) {
+                    let i = self.tables.len();
+                    write!(self.out, "static __OBLI_TABLE_{}: [i64; {}] = ", i, values.len())?;
+                    writeln!(self.out, "{};", table_literal(values))?;
+                    self.tables.push(values.clone());
+                }
+            }
+        }
+        if !self.tables.is_empty() {
+            self.out.write_char('\n')?;
+        }
 
         // Main function
-        output.push_str("fn main() {\n");
+        self.out.write_str("fn main() {\n")?;
         self.indent += 1;
 
-        let result = self.emit_expr(expr);
-        output.push_str(&self.indented(&format!("let result = {};\n", result)));
-        output.push_str(&self.indented("println!(\"Result: {:?}\", result);\n"));
+        // Inputs, read once whatever reads them
+        let inputs = expr.inputs();
+        if !inputs.is_empty() {
+            let names: Vec<_> = inputs
+                .iter()
+                .map(|(name, ..)| format!("{:?}", name.as_str()))
+                .collect();
+            let read = format!("let __obli_inputs = read_inputs(&[{}]);\n", names.join(", "));
+            self.indented(&read)?;
+            self.inputs = inputs.into_iter().map(|(name, ..)| name).collect();
+        }
+
+        let deferred = expr.defers_assertions();
+        if deferred {
+            self.indented("let mut __obli_held = Secret::new(true);\n")?;
+        }
+        self.indented("let result = ")?;
+        self.emit_expr(expr)?;
+        self.out.write_str(";\n")?;
+
+        // Output section: the only RevealToken not from a `declassify(...)`
+        if expr.is_secret() || deferred {
+            self.indented("let token = RevealToken::output_section();\n")?;
+        }
+        if deferred {
+            self.indented(&format!(
+                "assert!(*__obli_held.reveal(&token), {:?});\n",
+                DEFERRED_FAILURE
+            ))?;
+        }
+        match outputs {
+            Some(outputs) => {
+                for (name, value) in outputs {
+                    let reveal = if value.is_secret() { "reveal(&token)" } else { "reveal()" };
+                    self.indented(&format!(
+                        "println!(\"{}: {{:?}}\", result.{}.{});\n",
+                        name, name, reveal
+                    ))?;
+                }
+            }
+            None if expr.is_secret() => {
+                self.indented("println!(\"Result: {:?}\", result.reveal(&token));\n")?;
+            }
+            None => self.indented("println!(\"Result: {:?}\", result.reveal());\n")?,
+        }
 
         self.indent -= 1;
-        output.push_str("}\n");
+        self.out.write_str("}\n")
+    }
+
+    fn emit_function(
+        &mut self,
+        name: &str,
+        params: &str,
+        ret: &str,
+        expr: &ObliExpr,
+    ) -> fmt::Result {
+        write!(self.out, "fn {}({}) -> {} {{\n    ", name, params, ret)?;
+        if !expr.defers_assertions() {
+            self.emit_expr(expr)?;
+            return self.out.write_str("\n}\n");
+        }
+        // Checked before the caller can reveal the result
+        self.out.write_str("let mut __obli_held = Secret::new(true);\n    let result = ")?;
+        self.emit_expr(expr)?;
+        self.out.write_str(";\n    let token = RevealToken::output_section();\n")?;
+        writeln!(self.out, "    assert!(*__obli_held.reveal(&token), {:?});", DEFERRED_FAILURE)?;
+        self.out.write_str("    result\n}\n")
+    }
 
-        output
+    fn emit_expr(&mut self, expr: &ObliExpr) -> fmt::Result {
+        stack::guard(|| self.emit_node(expr))
     }
 
-    fn emit_expr(&mut self, expr: &ObliExpr) -> String {
+    fn emit_node(&mut self, expr: &ObliExpr) -> fmt::Result {
         match expr {
-            ObliExpr::PubInt(n) => format!("Pub::new({}i64)", n),
-            ObliExpr::PubBool(b) => format!("Pub::new({})", b),
-            ObliExpr::SecretInt(n) => format!("Secret::new({}i64)", n),
-            ObliExpr::SecretBool(b) => format!("Secret::new({})", b),
-            ObliExpr::Var { name, .. } => name.clone(),
+            ObliExpr::PubInt(n) => write!(self.out, "Pub::new({}i64)", n),
+            ObliExpr::PubBool(b) => write!(self.out, "Pub::new({})", b),
+            ObliExpr::SecretInt(n) => write!(self.out, "Secret::new({}i64)", n),
+            ObliExpr::SecretBool(b) => write!(self.out, "Secret::new({})", b),
+            ObliExpr::Sized {
+                value,
+                width,
+                is_secret,
+            } => {
+                let wrapper = if *is_secret { "Secret" } else { "Pub" };
+                write!(self.out, "{}::new({}{})", wrapper, width.value(*value), width)
+            }
+            // `secret(x)` of a public `x` marks the variable itself secret
+            ObliExpr::Var { name, is_secret } if *is_secret && self.is_public(*name) => {
+                write!(self.out, "{}.classify()", name)
+            }
+            ObliExpr::Var { name, .. } => self.out.write_str(name),
+            ObliExpr::Input {
+                name,
+                ty,
+                is_secret,
+            } => {
+                let index = self.inputs.iter().position(|input| input == name);
+                let index = index.expect("inputs are read at the start of main");
+                let wrapper = if *is_secret { "Secret" } else { "Pub" };
+                let ty = match ty {
+                    Scalar::Int => "i64",
+                    Scalar::Bool => "bool",
+                    Scalar::Sized(width) => width.name(),
+                };
+                write!(self.out, "{}::new(parse_input::<{}>({:?}, ", wrapper, ty, name.as_str())?;
+                write!(self.out, "&__obli_inputs[{}]))", index)
+            }
             ObliExpr::BinOp {
-                op, left, right, ..
+                op,
+                left,
+                right,
+                is_secret,
+            } => {
+                self.emit_operand(left, *is_secret)?;
+                write!(self.out, ".{}(&", binop_method(op))?;
+                self.emit_operand(right, *is_secret)?;
+                self.out.write_char(')')
+            }
+            // `secret(-x)` of a public `x` marks only the operation secret
+            ObliExpr::UnaryOp {
+                op,
+                expr,
+                is_secret,
             } => {
-                let left_code = self.emit_expr(left);
-                let right_code = self.emit_expr(right);
-                let method = binop_method(op);
-                format!("{}.{}(&{})", left_code, method, right_code)
+                self.emit_operand(expr, *is_secret)?;
+                write!(self.out, ".{}()", unaryop_method(op))
+            }
+            ObliExpr::CtCast {
+                expr, to, is_secret, ..
+            } => {
+                self.emit_operand(expr, *is_secret)?;
+                let to = match to {
+                    Scalar::Sized(width) => width.name(),
+                    Scalar::Int | Scalar::Bool => "i64",
+                };
+                write!(self.out, ".ct_cast::<{}>()", to)
+            }
+            // Each arm's assertions are gathered apart, and only the
+            // chosen arm's kept
+            ObliExpr::CtSelect {
+                cond,
+                then_val,
+                else_val,
+            } if then_val.has_assertions() || else_val.has_assertions() => {
+                self.out.write_str("{ let __obli_cond = ")?;
+                self.emit_expr(cond)?;
+                self.out.write_str("; let __obli_outer = __obli_held; ")?;
+                self.out.write_str("__obli_held = Secret::new(true); let __obli_then = ")?;
+                self.secret_arms += 1;
+                let emitted = self.emit_arms(then_val, else_val);
+                self.secret_arms -= 1;
+                emitted?;
+                self.out.write_str("; __obli_held = __obli_outer.ct_and(&ct_select(")?;
+                self.out.write_str("&__obli_cond, &__obli_then_held, &__obli_held)); ")?;
+                self.out.write_str("ct_select(&__obli_cond, &__obli_then, &__obli_else) }")
             }
-            ObliExpr::UnaryOp { op, expr, .. } => {
-                let expr_code = self.emit_expr(expr);
-                let method = unaryop_method(op);
-                format!("{}.{}()", expr_code, method)
+            ObliExpr::CtSelect { .. } if expr.scan().is_some() => {
+                let (elements, index) = expr.scan().expect("matched a scan");
+                self.out.write_str("ct_scan(&[")?;
+                for (i, element) in elements.into_iter().enumerate() {
+                    if i > 0 {
+                        self.out.write_str(", ")?;
+                    }
+                    self.emit_operand(element, true)?;
+                }
+                self.out.write_str("], &")?;
+                self.emit_expr(index)?;
+                self.out.write_char(')')
             }
             ObliExpr::CtSelect {
                 cond,
                 then_val,
                 else_val,
             } => {
-                let cond_code = self.emit_expr(cond);
-                let then_code = self.emit_expr(then_val);
-                let else_code = self.emit_expr(else_val);
-                format!(
-                    "ct_select(&{}, &{}, &{})",
-                    cond_code, then_code, else_code
-                )
+                self.out.write_str("ct_select(&")?;
+                self.emit_expr(cond)?;
+                self.out.write_str(", &")?;
+                self.emit_operand(then_val, true)?;
+                self.out.write_str(", &")?;
+                self.emit_operand(else_val, true)?;
+                self.out.write_char(')')
             }
             ObliExpr::PubIf {
                 cond,
                 then_branch,
                 else_branch,
             } => {
-                let cond_code = self.emit_expr(cond);
-                let then_code = self.emit_expr(then_branch);
-                let else_code = self.emit_expr(else_branch);
-                format!(
-                    "if {}.reveal() {{ {} }} else {{ {} }}",
-                    cond_code, then_code, else_code
-                )
+                // Both branches must have the type of the whole `if`
+                let secret = expr.is_secret();
+                self.out.write_str("if *")?;
+                self.emit_expr(cond)?;
+                self.out.write_str(".reveal() { ")?;
+                self.emit_operand(then_branch, secret)?;
+                self.out.write_str(" } else { ")?;
+                self.emit_operand(else_branch, secret)?;
+                self.out.write_str(" }")
             }
             ObliExpr::Let {
                 name, value, body, ..
             } => {
-                let value_code = self.emit_expr(value);
-                let body_code = self.emit_expr(body);
-                format!("{{ let {} = {}; {} }}", name, value_code, body_code)
+                write!(self.out, "{{ let {} = ", name)?;
+                self.emit_expr(value)?;
+                self.out.write_str("; ")?;
+                self.scope.push(*name, value.is_secret());
+                let body = self.emit_expr(body);
+                self.scope.pop();
+                body?;
+                self.out.write_str(" }")
+            }
+            ObliExpr::While {
+                cond,
+                acc,
+                init,
+                body,
+                is_secret,
+            } => {
+                write!(self.out, "{{ let mut {} = ", acc)?;
+                self.emit_operand(init, *is_secret)?;
+                self.out.write_str("; while *")?;
+                self.scope.push(*acc, *is_secret);
+                let emitted = self.emit_loop(cond, *acc, body, *is_secret);
+                self.scope.pop();
+                emitted?;
+                write!(self.out, " }} {} }}", acc)
+            }
+            ObliExpr::CtSwap {
+                cond,
+                left,
+                right,
+                names: [first, second],
+                body,
+                is_secret,
+            } => {
+                write!(self.out, "{{ let ({}, {}) = ", first, second)?;
+                if cond.is_secret() {
+                    self.out.write_str("ct_swap(&")?;
+                    self.emit_expr(cond)?;
+                    self.out.write_str(", &")?;
+                    self.emit_operand(left, true)?;
+                    self.out.write_str(", &")?;
+                    self.emit_operand(right, true)?;
+                    self.out.write_str("); ")?;
+                } else {
+                    self.out.write_str("if *")?;
+                    self.emit_expr(cond)?;
+                    self.out.write_str(".reveal() { ")?;
+                    self.emit_pair(right, left, *is_secret)?;
+                    self.out.write_str(" } else { ")?;
+                    self.emit_pair(left, right, *is_secret)?;
+                    self.out.write_str(" }; ")?;
+                }
+                self.scope.push(*first, *is_secret);
+                self.scope.push(*second, *is_secret);
+                let body = self.emit_expr(body);
+                self.scope.pop();
+                self.scope.pop();
+                body?;
+                self.out.write_str(" }")
+            }
+            ObliExpr::Outputs(outputs) => {
+                self.out.write_str("Outputs {")?;
+                for (i, (name, value)) in outputs.iter().enumerate() {
+                    let sep = if i == 0 { " " } else { ", " };
+                    write!(self.out, "{}{}: ", sep, name)?;
+                    self.emit_expr(value)?;
+                }
+                self.out.write_str(" }")
+            }
+            ObliExpr::Table { values, index } => {
+                match self.tables.iter().position(|t| t == values) {
+                    Some(i) => write!(self.out, "ct_table(&__OBLI_TABLE_{}, &", i)?,
+                    None => write!(self.out, "ct_table(&{}, &", table_literal(values))?,
+                }
+                self.emit_operand(index, true)?;
+                self.out.write_char(')')
+            }
+            ObliExpr::Assert { cond, body } if cond.is_secret() || self.secret_arms > 0 => {
+                self.out.write_str("{ __obli_held = __obli_held.ct_and(&")?;
+                self.emit_operand(cond, true)?;
+                self.out.write_str("); ")?;
+                self.emit_expr(body)?;
+                self.out.write_str(" }")
+            }
+            ObliExpr::Assert { cond, body } => {
+                self.out.write_str("{ assert!(*")?;
+                self.emit_expr(cond)?;
+                self.out.write_str(".reveal(), \"assertion failed\"); ")?;
+                self.emit_expr(body)?;
+                self.out.write_str(" }")
+            }
+            ObliExpr::Declassify(inner) if inner.is_secret() => {
+                self.out.write_str("Pub::new(*")?;
+                self.emit_expr(inner)?;
+                self.out.write_str(".reveal(&RevealToken::declassify()))")
             }
+            ObliExpr::Declassify(inner) => self.emit_expr(inner),
         }
     }
 
-    fn indented(&self, s: &str) -> String {
-        let indent = "    ".repeat(self.indent);
-        format!("{}{}", indent, s)
+    /// The condition and body of a `while` loop, with `acc` in scope.
+    fn emit_loop(
+        &mut self,
+        cond: &ObliExpr,
+        acc: Symbol,
+        body: &ObliExpr,
+        secret: bool,
+    ) -> fmt::Result {
+        self.emit_expr(cond)?;
+        write!(self.out, ".reveal() {{ {} = ", acc)?;
+        self.emit_operand(body, secret)?;
+        self.out.write_char(';')
+    }
+
+    /// The arms of a `CtSelect` with assertions, from the `then` value
+    /// to the `else` value, each binding the assertions held in it.
+    fn emit_arms(&mut self, then_val: &ObliExpr, else_val: &ObliExpr) -> fmt::Result {
+        self.emit_operand(then_val, true)?;
+        self.out.write_str("; let __obli_then_held = __obli_held; ")?;
+        self.out.write_str("__obli_held = Secret::new(true); let __obli_else = ")?;
+        self.emit_operand(else_val, true)
+    }
+
+    /// `(first, second)`, both operands at the same secrecy.
+    fn emit_pair(&mut self, first: &ObliExpr, second: &ObliExpr, secret: bool) -> fmt::Result {
+        self.out.write_char('(')?;
+        self.emit_operand(first, secret)?;
+        self.out.write_str(", ")?;
+        self.emit_operand(second, secret)?;
+        self.out.write_char(')')
+    }
+
+    /// Emit an operand of a secret (`secret == true`) or public operation,
+    /// lifting public values into `Secret` where a secret one is expected.
+    fn emit_operand(&mut self, expr: &ObliExpr, secret: bool) -> fmt::Result {
+        self.emit_expr(expr)?;
+        if secret && !expr.is_secret() {
+            self.out.write_str(".classify()")?;
+        }
+        Ok(())
+    }
+
+    /// Write `s` at the current indentation.
+    fn indented(&mut self, s: &str) -> fmt::Result {
+        for _ in 0..self.indent {
+            self.out.write_str("    ")?;
+        }
+        self.out.write_str(s)
     }
 }
 
@@ -110,8 +641,16 @@ fn binop_method(op: &ObliBinOp) -> &'static str {
         ObliBinOp::CtAdd => "ct_add",
         ObliBinOp::CtSub => "ct_sub",
         ObliBinOp::CtMul => "ct_mul",
+        ObliBinOp::CtAddSat => "ct_add_sat",
+        ObliBinOp::CtSubSat => "ct_sub_sat",
+        ObliBinOp::CtMulSat => "ct_mul_sat",
         ObliBinOp::CtDiv => "ct_div",
         ObliBinOp::CtMod => "ct_mod",
+        ObliBinOp::CtBitAnd => "ct_bit_and",
+        ObliBinOp::CtBitOr => "ct_bit_or",
+        ObliBinOp::CtBitXor => "ct_bit_xor",
+        ObliBinOp::CtShl => "ct_shl",
+        ObliBinOp::CtShr => "ct_shr",
         ObliBinOp::CtEq => "ct_eq",
         ObliBinOp::CtNe => "ct_ne",
         ObliBinOp::CtLt => "ct_lt",
@@ -123,16 +662,24 @@ fn binop_method(op: &ObliBinOp) -> &'static str {
     }
 }
 
+fn table_literal(values: &[i64]) -> String {
+    let values: Vec<_> = values.iter().map(|v| v.to_string()).collect();
+    format!("[{}]", values.join(", "))
+}
+
 fn unaryop_method(op: &ObliUnaryOp) -> &'static str {
     match op {
         ObliUnaryOp::CtNeg => "ct_neg",
         ObliUnaryOp::CtNot => "ct_not",
+        ObliUnaryOp::CtToInt => "ct_to_int",
+        ObliUnaryOp::CtToBool => "ct_to_bool",
     }
 }
 
 /// Runtime prelude - defines Secret, Pub, and ct_select
-const RUNTIME_PRELUDE: &str = r#"// SPDX-License-Identifier: MIT OR AGPL-3.0-or-later
+pub(crate) const RUNTIME_PRELUDE: &str = r#"// SPDX-License-Identifier: MIT OR AGPL-3.0-or-later
 // Generated by obli-transpiler
+// obli-runtime-version: 2
 
 /// Public value wrapper
 #[derive(Debug, Clone, Copy)]
@@ -142,70 +689,220 @@ fn unaryop_method(op: &ObliUnaryOp) -> &'static str {
 #[derive(Debug, Clone, Copy)]
 struct Secret<T>(T);
 
-impl<T> Pub<T> {
+impl<T: Copy> Pub<T> {
     fn new(v: T) -> Self { Pub(v) }
     fn reveal(&self) -> &T { &self.0 }
+    /// Treat a public value as secret (always allowed: `Public ⊑ Secret`).
+    #[allow(dead_code)]
+    fn classify(&self) -> Secret<T> { Secret(self.0) }
+}
+
+/// Capability required to declassify a `Secret`.
+/// Only the generated output section of `main` and each `declassify(...)`
+/// of the source construct one, so intermediate code cannot casually
+/// reveal secrets.
+struct RevealToken {
+    _sealed: (),
+}
+
+impl RevealToken {
+    #[allow(dead_code)]
+    fn output_section() -> Self { RevealToken { _sealed: () } }
+    /// One per `declassify(...)`, each listed in the compilation report.
+    #[allow(dead_code)]
+    fn declassify() -> Self { RevealToken { _sealed: () } }
 }
 
 impl<T> Secret<T> {
     fn new(v: T) -> Self { Secret(v) }
     #[allow(dead_code)]
-    fn reveal(&self) -> &T { &self.0 } // Use cautiously!
-}
-
-// Constant-time operations for i64
-impl Pub<i64> {
-    fn ct_add(&self, other: &Self) -> Pub<i64> { Pub(self.0.wrapping_add(other.0)) }
-    fn ct_sub(&self, other: &Self) -> Pub<i64> { Pub(self.0.wrapping_sub(other.0)) }
-    fn ct_mul(&self, other: &Self) -> Pub<i64> { Pub(self.0.wrapping_mul(other.0)) }
-    fn ct_div(&self, other: &Self) -> Pub<i64> { Pub(self.0.wrapping_div(other.0)) }
-    fn ct_mod(&self, other: &Self) -> Pub<i64> { Pub(self.0.wrapping_rem(other.0)) }
-    fn ct_eq(&self, other: &Self) -> Pub<bool> { Pub(self.0 == other.0) }
-    fn ct_ne(&self, other: &Self) -> Pub<bool> { Pub(self.0 != other.0) }
-    fn ct_lt(&self, other: &Self) -> Pub<bool> { Pub(self.0 < other.0) }
-    fn ct_le(&self, other: &Self) -> Pub<bool> { Pub(self.0 <= other.0) }
-    fn ct_gt(&self, other: &Self) -> Pub<bool> { Pub(self.0 > other.0) }
-    fn ct_ge(&self, other: &Self) -> Pub<bool> { Pub(self.0 >= other.0) }
-    fn ct_neg(&self) -> Pub<i64> { Pub(-self.0) }
-}
-
-impl Secret<i64> {
-    fn ct_add(&self, other: &Self) -> Secret<i64> { Secret(self.0.wrapping_add(other.0)) }
-    fn ct_sub(&self, other: &Self) -> Secret<i64> { Secret(self.0.wrapping_sub(other.0)) }
-    fn ct_mul(&self, other: &Self) -> Secret<i64> { Secret(self.0.wrapping_mul(other.0)) }
-    fn ct_div(&self, other: &Self) -> Secret<i64> { Secret(self.0.wrapping_div(other.0)) }
-    fn ct_mod(&self, other: &Self) -> Secret<i64> { Secret(self.0.wrapping_rem(other.0)) }
-    fn ct_eq(&self, other: &Pub<i64>) -> Secret<bool> { Secret(self.0 == other.0) }
-    fn ct_ne(&self, other: &Pub<i64>) -> Secret<bool> { Secret(self.0 != other.0) }
-    fn ct_lt(&self, other: &Pub<i64>) -> Secret<bool> { Secret(self.0 < other.0) }
-    fn ct_le(&self, other: &Pub<i64>) -> Secret<bool> { Secret(self.0 <= other.0) }
-    fn ct_gt(&self, other: &Pub<i64>) -> Secret<bool> { Secret(self.0 > other.0) }
-    fn ct_ge(&self, other: &Pub<i64>) -> Secret<bool> { Secret(self.0 >= other.0) }
-    fn ct_neg(&self) -> Secret<i64> { Secret(-self.0) }
+    fn reveal(&self, _token: &RevealToken) -> &T { &self.0 }
+}
+
+/// Replace an overflowed result with `max` (`high`) or `min` through
+/// masks, so saturating is as constant-time as wrapping.
+#[allow(dead_code)]
+fn ct_clamp<T: Blend>((value, overflow): (T, bool), high: bool, min: T, max: T) -> T {
+    let bound = max.blend(min, -(high as i64));
+    bound.blend(value, -(overflow as i64))
+}
+
+// Constant-time operations for `$wrapper<$t>`. An overflowing saturated
+// result clamps up when the exact result is positive, which the operand
+// signs tell: widened to `i128`, so unsigned operands are never negative.
+macro_rules! ct_int_ops {
+    ($wrapper:ident, $t:ty) => {
+        impl $wrapper<$t> {
+            fn ct_add(&self, other: &Self) -> Self { $wrapper(self.0.wrapping_add(other.0)) }
+            fn ct_sub(&self, other: &Self) -> Self { $wrapper(self.0.wrapping_sub(other.0)) }
+            fn ct_mul(&self, other: &Self) -> Self { $wrapper(self.0.wrapping_mul(other.0)) }
+            fn ct_add_sat(&self, other: &Self) -> Self {
+                let high = (self.0 as i128) >= 0;
+                $wrapper(ct_clamp(self.0.overflowing_add(other.0), high, <$t>::MIN, <$t>::MAX))
+            }
+            fn ct_sub_sat(&self, other: &Self) -> Self {
+                let high = (other.0 as i128) < 0;
+                $wrapper(ct_clamp(self.0.overflowing_sub(other.0), high, <$t>::MIN, <$t>::MAX))
+            }
+            fn ct_mul_sat(&self, other: &Self) -> Self {
+                let high = ((self.0 ^ other.0) as i128) >= 0;
+                $wrapper(ct_clamp(self.0.overflowing_mul(other.0), high, <$t>::MIN, <$t>::MAX))
+            }
+            fn ct_div(&self, other: &Self) -> Self { $wrapper(self.0.wrapping_div(other.0)) }
+            fn ct_mod(&self, other: &Self) -> Self { $wrapper(self.0.wrapping_rem(other.0)) }
+            fn ct_bit_and(&self, other: &Self) -> Self { $wrapper(self.0 & other.0) }
+            fn ct_bit_or(&self, other: &Self) -> Self { $wrapper(self.0 | other.0) }
+            fn ct_bit_xor(&self, other: &Self) -> Self { $wrapper(self.0 ^ other.0) }
+            // Shifts mask the amount to the width and compile to one
+            // shift instruction, which takes the same time for every amount
+            fn ct_shl(&self, other: &Self) -> Self { $wrapper(self.0.wrapping_shl(other.0 as u32)) }
+            fn ct_shr(&self, other: &Self) -> Self { $wrapper(self.0.wrapping_shr(other.0 as u32)) }
+            fn ct_eq(&self, other: &Self) -> $wrapper<bool> { $wrapper(self.0 == other.0) }
+            fn ct_ne(&self, other: &Self) -> $wrapper<bool> { $wrapper(self.0 != other.0) }
+            fn ct_lt(&self, other: &Self) -> $wrapper<bool> { $wrapper(self.0 < other.0) }
+            fn ct_le(&self, other: &Self) -> $wrapper<bool> { $wrapper(self.0 <= other.0) }
+            fn ct_gt(&self, other: &Self) -> $wrapper<bool> { $wrapper(self.0 > other.0) }
+            fn ct_ge(&self, other: &Self) -> $wrapper<bool> { $wrapper(self.0 >= other.0) }
+            fn ct_neg(&self) -> Self { $wrapper(self.0.wrapping_neg()) }
+            fn ct_to_bool(&self) -> $wrapper<bool> { self.ct_ne(&$wrapper(0)) }
+            fn ct_cast<U: CastFrom>(&self) -> $wrapper<U> {
+                $wrapper(U::cast_from(self.0 as i128))
+            }
+        }
+    };
 }
 
+// Integers, their blends through a mask, and their operations
+macro_rules! ct_ints {
+    ($($t:ty),*) => {$(
+        impl Blend for $t {
+            fn blend(self, other: Self, mask: i64) -> Self {
+                let mask = mask as $t;
+                (self & mask) | (other & !mask)
+            }
+            fn swap(self, other: Self, mask: i64) -> (Self, Self) {
+                let t = (self ^ other) & mask as $t;
+                (self ^ t, other ^ t)
+            }
+        }
+        impl CastFrom for $t {
+            fn cast_from(n: i128) -> Self { n as $t }
+        }
+        ct_int_ops!(Pub, $t);
+        ct_int_ops!(Secret, $t);
+    )*};
+}
+
+/// Integers a cast converts to. Widened to `i128` and cut back with `as`,
+/// a value is extended or truncated by one instruction whatever it is.
+#[allow(dead_code)]
+trait CastFrom: Copy {
+    fn cast_from(n: i128) -> Self;
+}
+
+ct_ints!(i64, u8, u16, u32, u64, i32);
+
 impl Pub<bool> {
     fn ct_and(&self, other: &Self) -> Pub<bool> { Pub(self.0 & other.0) }
     fn ct_or(&self, other: &Self) -> Pub<bool> { Pub(self.0 | other.0) }
     fn ct_not(&self) -> Pub<bool> { Pub(!self.0) }
+    fn ct_to_int(&self) -> Pub<i64> { Pub(1i64.blend(0, -(self.0 as i64))) }
 }
 
 impl Secret<bool> {
     fn ct_and(&self, other: &Self) -> Secret<bool> { Secret(self.0 & other.0) }
     fn ct_or(&self, other: &Self) -> Secret<bool> { Secret(self.0 | other.0) }
     fn ct_not(&self) -> Secret<bool> { Secret(!self.0) }
+    // `1` or `0` through the mask of the condition, as `ct_select` blends
+    fn ct_to_int(&self) -> Secret<i64> { Secret(1i64.blend(0, -(self.0 as i64))) }
+}
+
+/// Read `table[index]` by blending every entry through a mask, so the
+/// index is never used as an address. An index outside `0..N - 1` reads
+/// the last entry.
+#[allow(dead_code)]
+fn ct_table<const N: usize>(table: &[i64; N], index: &Secret<i64>) -> Secret<i64> {
+    let mut acc = table[N - 1];
+    for (k, &value) in table[..N - 1].iter().enumerate() {
+        let mask = -((index.0 == k as i64) as i64);
+        acc = (value & mask) | (acc & !mask);
+    }
+    Secret(acc)
+}
+
+/// Values `ct_select` and `ct_scan` blend, and `ct_swap` swaps, through
+/// an all-ones or all-zeros mask.
+#[allow(dead_code)]
+trait Blend: Copy {
+    fn blend(self, other: Self, mask: i64) -> Self;
+    fn swap(self, other: Self, mask: i64) -> (Self, Self);
+}
+
+impl Blend for bool {
+    fn blend(self, other: Self, mask: i64) -> Self {
+        ((self as i64 & mask) | (other as i64 & !mask)) != 0
+    }
+    fn swap(self, other: Self, mask: i64) -> (Self, Self) {
+        let t = (self ^ other) & (mask != 0);
+        (self ^ t, other ^ t)
+    }
+}
+
+/// Read `entries[index]` like `ct_table`, for entries computed at run
+/// time: one pass over every entry, each blended in through a mask.
+#[allow(dead_code)]
+fn ct_scan<T: Blend, const N: usize>(entries: &[Secret<T>; N], index: &Secret<i64>) -> Secret<T> {
+    let mut acc = entries[N - 1].0;
+    for (k, entry) in entries[..N - 1].iter().enumerate() {
+        let mask = -((index.0 == k as i64) as i64);
+        acc = entry.0.blend(acc, mask);
+    }
+    Secret(acc)
 }
 
 /// Constant-time select: returns `then_val` if `cond` is true, else `else_val`.
 /// CRITICAL: Both branches are always evaluated to prevent timing leaks.
-fn ct_select<T: Copy>(cond: &Secret<bool>, then_val: &Secret<T>, else_val: &Secret<T>) -> Secret<T> {
-    // This is a simplified implementation. Production code should use
-    // bitwise operations to ensure constant-time execution.
-    let mask = if cond.0 { !0u64 } else { 0u64 };
-    let then_bits = unsafe { std::mem::transmute_copy::<T, u64>(&then_val.0) };
-    let else_bits = unsafe { std::mem::transmute_copy::<T, u64>(&else_val.0) };
-    let result_bits = (then_bits & mask) | (else_bits & !mask);
-    Secret(unsafe { std::mem::transmute_copy::<u64, T>(&result_bits) })
+fn ct_select<T: Blend>(cond: &Secret<bool>, then_val: &Secret<T>, else_val: &Secret<T>) -> Secret<T> {
+    Secret(then_val.0.blend(else_val.0, -(cond.0 as i64)))
+}
+
+/// Constant-time swap: returns `(b, a)` if `cond` is true, else `(a, b)`,
+/// exchanging the bits that differ under a mask.
+#[allow(dead_code)]
+fn ct_swap<T: Blend>(
+    cond: &Secret<bool>,
+    a: &Secret<T>,
+    b: &Secret<T>,
+) -> (Secret<T>, Secret<T>) {
+    let (a, b) = a.0.swap(b.0, -(cond.0 as i64));
+    (Secret(a), Secret(b))
+}
+
+/// The words of the program's inputs, in declaration order: the
+/// command-line arguments, or the whitespace-separated words of stdin if
+/// there are none.
+#[allow(dead_code)]
+fn read_inputs(names: &[&str]) -> Vec<String> {
+    let mut words: Vec<String> = std::env::args().skip(1).collect();
+    if words.is_empty() {
+        let mut text = String::new();
+        std::io::Read::read_to_string(&mut std::io::stdin(), &mut text).expect("cannot read stdin");
+        words = text.split_whitespace().map(String::from).collect();
+    }
+    if words.len() != names.len() {
+        eprintln!("expected {} inputs ({}), found {}", names.len(), names.join(", "), words.len());
+        std::process::exit(2);
+    }
+    words
+}
+
+/// Input `name` read from `word`, exiting if it is not a `T`.
+#[allow(dead_code)]
+fn parse_input<T: std::str::FromStr>(name: &str, word: &str) -> T {
+    word.parse().unwrap_or_else(|_| {
+        eprintln!("invalid value for input `{}`: `{}`", name, word);
+        std::process::exit(2)
+    })
 }
 "#;
 
@@ -237,9 +934,378 @@ fn test_secret_emit() {
         assert!(code.contains("Secret::new"));
     }
 
+    #[test]
+    fn test_secret_of_public_variable_classifies() {
+        let code = transpile("let a = 1 secret(a) + a");
+        assert!(code.contains("{ let a = Pub::new(1i64); a.classify().ct_add(&a.classify()) }"));
+        let code = transpile("let a = secret(1) secret(a) + 1");
+        assert!(code.contains("a.ct_add("));
+    }
+
+    #[test]
+    fn test_secret_result_revealed_with_token() {
+        let code = transpile("secret(42) + 1");
+        assert!(code.contains("let token = RevealToken::output_section();"));
+        assert!(code.contains("result.reveal(&token)"));
+    }
+
+    #[test]
+    fn test_reserved_names_rejected() {
+        let source = "let loop = 1 outputs(Pub = loop + ct_select)";
+        let (tokens, spans) = Lexer::new(source).tokenize().unwrap();
+        let (ast, ast_spans) = Parser::with_spans(&tokens, &spans).parse_with_spans().unwrap();
+        let (ir, ir_spans) = crate::transform::to_oblivious_with_spans(&ast, &ast_spans);
+        let errors = check_emittable(&ir, &ir_spans);
+        let names: Vec<_> = errors
+            .iter()
+            .map(|EmitError::ReservedName { name, .. }| name.as_str())
+            .collect();
+        assert_eq!(names, ["ct_select", "Pub"]);
+        assert_eq!(errors[0].span(), Span::new(34, 43));
+    }
+
+    #[test]
+    fn test_public_operand_of_secret_op_classified() {
+        let code = transpile("secret(3) * 2");
+        assert!(code.contains("Secret::new(3i64).ct_mul(&Pub::new(2i64).classify())"));
+    }
+
+    #[test]
+    fn test_saturating_arithmetic_clamps() {
+        use crate::interp::{Inputs, Value};
+        use crate::run::{run_interpreted, run_native};
+        let code = transpile("let k = secret(1) saturating(k * 3 - 1)");
+        assert!(code.contains("k.ct_mul_sat(&Pub::new(3i64).classify()).ct_sub_sat("));
+
+        let big = 1 << 32;
+        let cases = [
+            ("saturating(k * k + n)", 3, 1, 10),
+            ("saturating(k * k + n)", big, 1, i64::MAX),
+            ("saturating(n - k * k)", big, -2, i64::MIN),
+            ("k *| -k", big, 0, i64::MIN),
+            ("k * k + n", big, 1, 1),
+        ];
+        let config = crate::Config::default();
+        for (source, k, n, expected) in cases {
+            let inputs = Inputs::new()
+                .secret("k", Value::Int(k))
+                .public("n", Value::Int(n));
+            let interpreted = run_interpreted(source, &inputs, &config).unwrap();
+            assert_eq!(interpreted.value, Value::Int(expected), "{}", source);
+            let native = run_native(source, &inputs, &config).unwrap();
+            assert_eq!(native, format!("Result: {}\n", expected), "{}", source);
+        }
+    }
+
+    #[test]
+    fn test_sized_integers_wrap_natively() {
+        use crate::interp::{Inputs, Value};
+        use crate::run::{run_interpreted, run_native};
+        use crate::typeck::Width;
+        let code = transpile("secret(200u8) + 100u8");
+        assert!(code.contains("Secret::new(200u8).ct_add(&Pub::new(100u8).classify())"));
+
+        let cases = [
+            ("k + 100u8", Value::Sized(200, Width::U8), "44"),
+            ("k +| 100u8", Value::Sized(200, Width::U8), "255"),
+            ("k * k", Value::Sized(65536, Width::U32), "0"),
+            ("2i32 *| k", Value::Sized(-2147483647, Width::I32), "-2147483648"),
+            ("k >> 60u64", Value::Sized(-1, Width::U64), "15"),
+            ("if k < 3u16 then k else 0u16 - k", Value::Sized(7, Width::U16), "65529"),
+        ];
+        let config = crate::Config::default();
+        for (source, k, expected) in cases {
+            let inputs = Inputs::new().secret("k", k);
+            let interpreted = run_interpreted(source, &inputs, &config).unwrap();
+            assert_eq!(interpreted.value.to_string(), expected, "{}", source);
+            let native = run_native(source, &inputs, &config).unwrap();
+            assert_eq!(native, format!("Result: {}\n", expected), "{}", source);
+        }
+    }
+
+    #[test]
+    fn test_outputs_revealed_per_label() {
+        use crate::interp::{Inputs, Value};
+        let source = "let n = 3 outputs(total = k * n, count = n + 1)";
+        let code = transpile(&format!("let k = secret(7) {}", source));
+        assert!(code.contains("struct Outputs<T0, T1> {"));
+        assert!(code.contains("result.total.reveal(&token)"));
+        assert!(code.contains("result.count.reveal()"));
+        let inputs = Inputs::new().secret("k", Value::Int(7));
+        let native = crate::run::run_native(source, &inputs, &crate::Config::default());
+        assert_eq!(native.unwrap(), "total: 21\ncount: 4\n");
+    }
+
+    #[test]
+    fn test_inputs_read_from_arguments() {
+        use crate::interp::{Inputs, Value};
+        let source = "input secret k: int input n: u8 let total = k * 3 output total output n";
+        let code = transpile(source);
+        assert!(code.contains("let __obli_inputs = read_inputs(&[\"k\", \"n\"]);"));
+        assert!(code.contains("Secret::new(parse_input::<i64>(\"k\", &__obli_inputs[0]))"));
+        let inputs = Inputs::new()
+            .public("k", Value::Int(7))
+            .public("n", Value::Int(200));
+        let native = crate::run::run_native(source, &inputs, &crate::Config::default());
+        assert_eq!(native.unwrap(), "total: 21\nn: 200\n");
+    }
+
+    #[test]
+    fn test_const_table_static() {
+        use crate::interp::{Inputs, Value};
+        use crate::passes::{ConstTables, Pass};
+        let source = "let t = secret([5, 7, 9]) t[k] * t[k - 1]";
+        let ir = ConstTables.run(crate::transpile_to_ir(source).unwrap());
+        let code = emit_rust(&ir);
+        assert_eq!(code.matches("static __OBLI_TABLE_0: [i64; 3] = [5, 7, 9];").count(), 1);
+        assert_eq!(code.matches("ct_table(&__OBLI_TABLE_0, &").count(), 2);
+        let config = crate::Config {
+            passes: vec!["const-tables".to_string()],
+            ..crate::Config::default()
+        };
+        for (k, expected) in [(0, 45), (1, 35), (2, 63), (7, 81)] {
+            let inputs = Inputs::new().secret("k", Value::Int(k));
+            let native = crate::run::run_native(source, &inputs, &config).unwrap();
+            assert_eq!(native, format!("Result: {}\n", expected));
+        }
+    }
+
     #[test]
     fn test_ct_select_emit() {
         let code = transpile("let x = secret(1) if x > 0 then secret(1) else secret(0)");
         assert!(code.contains("ct_select"));
     }
+
+    #[test]
+    fn test_swaps_run() {
+        let run = |source| crate::run::run_emitted(&crate::transpile(source).unwrap()).unwrap();
+        let source = "let k = secret(9) let (lo, hi) = swap_if(k > 4, k, 4) lo * 10 + hi";
+        assert!(transpile(source).contains("ct_swap(&"));
+        assert_eq!(run(source), "Result: 49\n");
+        assert_eq!(run("let (x, y) = swap_if(secret(false), true, false) x"), "Result: true\n");
+        // A public condition swaps with a branch
+        let source = "let (x, y) = swap_if(2 > 1, secret(1), 2) x - y";
+        assert!(!transpile(source).contains("ct_swap(&"));
+        assert_eq!(run(source), "Result: 1\n");
+    }
+
+    #[test]
+    fn test_conversions_run() {
+        let run = |source| crate::run::run_emitted(&crate::transpile(source).unwrap()).unwrap();
+        let source = "let k = secret(5) to_int(k > 3) * 10 + to_int(to_bool(k - 5))";
+        let code = transpile(source);
+        assert!(code.contains(".ct_to_int()") && code.contains(".ct_to_bool()"), "{}", code);
+        assert_eq!(run(source), "Result: 10\n");
+        assert_eq!(run("to_bool(255u8 + 1u8)"), "Result: false\n");
+    }
+
+    #[test]
+    fn test_secret_public_operations_run() {
+        use crate::interp::Inputs;
+        use crate::run::{run_interpreted, run_native};
+        let config = crate::Config::default();
+        for (source, expected) in [
+            ("secret(-1)", "-1"),
+            ("secret(not true)", "false"),
+            ("secret(5 as u8)", "5"),
+            ("let x = 5; secret(-x)", "-5"),
+            ("secret(to_int(true))", "1"),
+            ("secret(to_bool(3))", "true"),
+        ] {
+            let interpreted = run_interpreted(source, &Inputs::new(), &config).unwrap();
+            assert_eq!(interpreted.value.to_string(), expected, "{}", source);
+            assert!(interpreted.secret, "{}", source);
+            let native = run_native(source, &Inputs::new(), &config).unwrap();
+            assert_eq!(native, format!("Result: {}\n", expected), "{}", source);
+        }
+    }
+
+    #[test]
+    fn test_casts_run() {
+        use crate::interp::{Inputs, Value};
+        use crate::run::{run_interpreted, run_native};
+        use crate::typeck::Width;
+        assert!(transpile("secret(300) as u8").contains(".ct_cast::<u8>()"));
+        let cases = [
+            ("k as u8", Value::Int(300), "44"),
+            ("k as u8", Value::Int(-1), "255"),
+            ("(k as u8) +| 250u8", Value::Int(2), "252"),
+            ("k as i32 as int", Value::Sized(3_000_000_000, Width::U32), "-1294967296"),
+            ("k as u64", Value::Sized(-1, Width::I32), "18446744073709551615"),
+            ("k as u16 as int * 2", Value::Sized(65535, Width::U16), "131070"),
+        ];
+        let config = crate::Config::default();
+        for (source, k, expected) in cases {
+            let inputs = Inputs::new().secret("k", k);
+            let interpreted = run_interpreted(source, &inputs, &config).unwrap();
+            assert_eq!(interpreted.value.to_string(), expected, "{}", source);
+            let native = run_native(source, &inputs, &config).unwrap();
+            assert_eq!(native, format!("Result: {}\n", expected), "{}", source);
+        }
+    }
+
+    #[test]
+    fn test_enums_run() {
+        use crate::interp::{Inputs, Value};
+        use crate::run::{run_interpreted, run_native};
+        let source = "enum C = A | B | D match k with A => 10, B => 20, D => 30";
+        let config = crate::Config::default();
+        for (k, expected) in [(0, "10"), (1, "20"), (2, "30"), (7, "30")] {
+            let inputs = Inputs::new().secret("k", Value::Int(k));
+            let interpreted = run_interpreted(source, &inputs, &config).unwrap();
+            assert_eq!(interpreted.value.to_string(), expected, "{}", k);
+            let native = run_native(source, &inputs, &config).unwrap();
+            assert_eq!(native, format!("Result: {}\n", expected), "{}", k);
+        }
+    }
+
+    #[test]
+    fn test_options_run() {
+        use crate::interp::{Inputs, Value};
+        use crate::run::{run_interpreted, run_native};
+        let source = "let o = if k > 5 then some(k * 2) else none \
+                      let b = if k > 9 then some(true) else none \
+                      unwrap_or(o, 1) + to_int(unwrap_or(b, false)) + unwrap_or(none, 3)";
+        let config = crate::Config::default();
+        for (k, expected) in [(7, "17"), (2, "4"), (10, "24")] {
+            let inputs = Inputs::new().secret("k", Value::Int(k));
+            let interpreted = run_interpreted(source, &inputs, &config).unwrap();
+            assert_eq!(interpreted.value.to_string(), expected, "{}", k);
+            assert!(interpreted.secret);
+            let native = run_native(source, &inputs, &config).unwrap();
+            assert_eq!(native, format!("Result: {}\n", expected), "{}", k);
+        }
+    }
+
+    #[test]
+    fn test_pow_run() {
+        use crate::interp::{Inputs, Value};
+        use crate::run::{run_interpreted, run_native};
+        let source = "const E = 10 pow(k, E) - pow(k, 3) + to_int(pow(k as u8, 7) == 128u8)";
+        let config = crate::Config::default();
+        for (k, expected) in [(2, "1017"), (-3, "59076"), (0, "0")] {
+            let inputs = Inputs::new().secret("k", Value::Int(k));
+            let interpreted = run_interpreted(source, &inputs, &config).unwrap();
+            assert_eq!(interpreted.value.to_string(), expected, "{}", k);
+            assert!(interpreted.secret);
+            let native = run_native(source, &inputs, &config).unwrap();
+            assert_eq!(native, format!("Result: {}\n", expected), "{}", k);
+        }
+        assert!(transpile("pow(k, 3)").contains("ct_mul"));
+    }
+
+    #[test]
+    fn test_map_and_fold_run() {
+        use crate::interp::{Inputs, Value};
+        use crate::run::{run_interpreted, run_native};
+        let source = "let a = map([k, k + 1, k * 2], fn(x) => clamp(x, 0, 9)) \
+                      fold(a, 0, fn(s, x) => s * 10 + x)";
+        let config = crate::Config::default();
+        for (k, expected) in [(3, "346"), (5, "569"), (-1, "0")] {
+            let inputs = Inputs::new().secret("k", Value::Int(k));
+            let interpreted = run_interpreted(source, &inputs, &config).unwrap();
+            assert_eq!(interpreted.value.to_string(), expected, "{}", k);
+            assert!(interpreted.secret);
+            let native = run_native(source, &inputs, &config).unwrap();
+            assert_eq!(native, format!("Result: {}\n", expected), "{}", k);
+        }
+    }
+
+    #[test]
+    fn test_assertions_run() {
+        let run = |source| crate::run::run_emitted(&crate::transpile(source).unwrap());
+        let source = "let k = secret(9) assert(k > 4) if k < 5 then (assert(k < 0) 0) else k";
+        let code = transpile(source);
+        assert!(code.contains("__obli_held = __obli_held.ct_and(&"), "{}", code);
+        assert!(code.contains(DEFERRED_FAILURE));
+        assert_eq!(run(source).unwrap(), "Result: 9\n");
+        assert!(run("let k = secret(9) assert(k < 4) k").is_err());
+        // A public condition is checked where it stands
+        let code = transpile("assert(1 < 2) 3");
+        assert!(code.contains("assert!(*") && !code.contains("__obli_held"));
+        // Recursion past its bound on a secret path fails at the end too
+        let source = "#[max_depth(2)] fn f(n) = if n == 0 then 0 else f(n - 1) + 1 f(secret(1))";
+        assert_eq!(run(source).unwrap(), "Result: 1\n");
+        assert!(run(&source.replace("secret(1)", "secret(2)")).is_err());
+    }
+
+    #[test]
+    fn test_secret_index_scans_in_a_loop() {
+        let source = "let a = [10, 20, 30] let i = secret(1) a[i] + a[0]";
+        let code = crate::transpile(source).unwrap();
+        assert_eq!(code.matches("ct_scan(&[").count(), 1, "{}", code);
+        assert!(!code.contains("ct_select(&"), "{}", code);
+        let run = |source| crate::run::run_emitted(&crate::transpile(source).unwrap()).unwrap();
+        assert_eq!(run(source), "Result: 30\n");
+        assert_eq!(run("let b = [true, secret(false)] b[secret(5)]"), "Result: false\n");
+        // A public index reads the element directly
+        assert!(!transpile("let a = [1, secret(2)] a[1]").contains("ct_scan(&["));
+    }
+
+    #[test]
+    fn test_while_loops_run() {
+        let run = |source| crate::run::run_emitted(&crate::transpile(source).unwrap()).unwrap();
+        assert_eq!(run("while n < 100 with n = 1 do n * 2"), "Result: 128\n");
+        assert_eq!(run("let k = secret(3) while i < 4 with i = 0 do i + 1"), "Result: 4\n");
+        assert_eq!(run("secret(while n < 10 with n = 0 do n + 3)"), "Result: 12\n");
+    }
+
+    #[test]
+    fn test_declassified_condition_branches_publicly() {
+        let source = "let k = secret(5) if declassify(k > 3) then 1 else 0";
+        let code = crate::transpile(source).unwrap();
+        assert!(code.contains(".reveal(&RevealToken::declassify())"), "{}", code);
+        assert!(!code.contains("ct_select("), "{}", code);
+        assert_eq!(crate::run::run_emitted(&code).unwrap(), "Result: 1\n");
+    }
+
+    #[test]
+    fn test_bitwise_operators_run() {
+        // Shifts take the amount modulo 64, so `>> 68` undoes `<< 4`
+        let code = crate::transpile("let k = secret(90) (k ^ 255) & 15 | k << 4 >> 68").unwrap();
+        assert!(code.contains(".ct_bit_xor(") && code.contains(".ct_shr("), "{}", code);
+        assert_eq!(crate::run::run_emitted(&code).unwrap(), "Result: 95\n");
+    }
+
+    /// Accepts `limit` bytes, then fails.
+    struct Full {
+        written: Vec<u8>,
+        limit: usize,
+    }
+
+    impl io::Write for Full {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            let n = buf.len().min(self.limit - self.written.len());
+            if n == 0 {
+                return Err(io::Error::new(io::ErrorKind::StorageFull, "disk full"));
+            }
+            self.written.extend_from_slice(&buf[..n]);
+            Ok(n)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_streamed_output_matches() {
+        let source = "let k = secret(5) if k > 2 then k * 3 else -k";
+        let (tokens, _) = Lexer::new(source).tokenize().unwrap();
+        let ir = to_oblivious(&Parser::new(&tokens).parse().unwrap());
+        let options = EmitOptions::default();
+        let mut sink = Full {
+            written: Vec::new(),
+            limit: usize::MAX,
+        };
+        write_rust(&ir, &options, &mut sink).unwrap();
+        assert_eq!(String::from_utf8(sink.written).unwrap(), emit_rust_with(&ir, &options));
+
+        let mut full = Full {
+            written: Vec::new(),
+            limit: 100,
+        };
+        let err = write_rust(&ir, &options, &mut full).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::StorageFull);
+    }
 }
@@ -0,0 +1,121 @@
+// SPDX-License-Identifier: MIT OR AGPL-3.0-or-later
+// SPDX-FileCopyrightText: 2024-2025 hyperpolymath
+
+//! Errors of the transpilation pipeline.
+//!
+//! [`TranspileError`] names the stage that failed and keeps that stage's
+//! own error values, so library users can match on causes and read spans
+//! and payloads directly; [`TranspileError::into_diagnostics`] converts to
+//! renderable diagnostics.
+
+use crate::diagnostic::Diagnostic;
+use crate::division::DivisionError;
+use crate::emit::EmitError;
+use crate::lexer::LexError;
+use crate::limits::LimitError;
+use crate::parser::ParseError;
+use crate::span::Span;
+use crate::transform::SecrecyError;
+use crate::typeck::TypeError;
+use crate::verify::VerifyError;
+use std::fmt::Display;
+use std::io;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum TranspileError {
+    #[error(transparent)]
+    Lex(#[from] LexError),
+    #[error(transparent)]
+    Parse(#[from] ParseError),
+    /// Every type error in the program.
+    #[error("{}", join(.0))]
+    Type(Vec<TypeError>),
+    /// A `while` loop on a secret condition, or a secret value declared
+    /// `pub`: the program cannot be made oblivious.
+    #[error("{}", join(.0))]
+    Secrecy(Vec<SecrecyError>),
+    /// The transformed IR failed verification, a transformer bug.
+    #[error("{}", join(.0))]
+    Transform(Vec<VerifyError>),
+    #[error("{}", join(.0))]
+    Emit(Vec<EmitError>),
+    /// Secret divisors that may be zero, with
+    /// [`DivisionByZero::RequireNonzero`](crate::division::DivisionByZero).
+    #[error("{}", join(.0))]
+    Division(Vec<DivisionError>),
+    /// The IR or the generated code outgrew the configured
+    /// [`Limits`](crate::limits::Limits); the parser reports AST limits as
+    /// [`ParseError::Limit`].
+    #[error(transparent)]
+    Limit(#[from] LimitError),
+    /// Writing the generated code failed.
+    #[error("cannot write output: {0}")]
+    Io(#[from] io::Error),
+}
+
+fn join<E: Display>(errors: &[E]) -> String {
+    let messages: Vec<_> = errors.iter().map(|e| e.to_string()).collect();
+    messages.join("; ")
+}
+
+impl TranspileError {
+    /// Spans of every error, in report order.
+    pub fn spans(&self) -> Vec<Span> {
+        match self {
+            TranspileError::Lex(e) => vec![e.span()],
+            TranspileError::Parse(e) => vec![e.span()],
+            TranspileError::Type(errors) => errors.iter().map(TypeError::span).collect(),
+            TranspileError::Secrecy(errors) => errors.iter().map(SecrecyError::span).collect(),
+            TranspileError::Transform(errors) => errors.iter().map(VerifyError::span).collect(),
+            TranspileError::Emit(errors) => errors.iter().map(EmitError::span).collect(),
+            TranspileError::Division(errors) => errors.iter().map(DivisionError::span).collect(),
+            TranspileError::Limit(e) => vec![e.span()],
+            TranspileError::Io(_) => Vec::new(),
+        }
+    }
+
+    /// One diagnostic per error.
+    pub fn into_diagnostics(self) -> Vec<Diagnostic> {
+        match self {
+            TranspileError::Lex(e) => vec![e.into()],
+            TranspileError::Parse(e) => vec![e.into()],
+            TranspileError::Type(errors) => errors.into_iter().map(Diagnostic::from).collect(),
+            TranspileError::Secrecy(errors) => errors.into_iter().map(Diagnostic::from).collect(),
+            TranspileError::Transform(errors) => errors.into_iter().map(Diagnostic::from).collect(),
+            TranspileError::Emit(errors) => errors.into_iter().map(Diagnostic::from).collect(),
+            TranspileError::Division(errors) => {
+                errors.into_iter().map(Diagnostic::from).collect()
+            }
+            TranspileError::Limit(e) => vec![e.into()],
+            TranspileError::Io(e) => vec![Diagnostic::error(e.to_string())],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transpile;
+
+    #[test]
+    fn test_stage_is_matchable() {
+        assert!(matches!(transpile("1 $ 2"), Err(TranspileError::Lex(_))));
+        assert!(matches!(transpile("let = 1"), Err(TranspileError::Parse(_))));
+        assert!(matches!(transpile("outputs(loop = 1)"), Err(TranspileError::Emit(_))));
+        let Err(TranspileError::Type(errors)) = transpile("(1 + true) * (2 + false)") else {
+            panic!("expected type errors");
+        };
+        assert_eq!(errors.len(), 2);
+        assert!(matches!(errors[0], TypeError::Mismatch { .. }));
+    }
+
+    #[test]
+    fn test_spans_and_diagnostics() {
+        let err = transpile("x + 1").unwrap_err();
+        assert_eq!(err.to_string(), "unbound variable `x`");
+        assert_eq!(err.spans(), vec![Span::new(0, 1)]);
+        let diagnostics = err.into_diagnostics();
+        assert_eq!(diagnostics[0].code, Some(crate::codes::UNBOUND_VARIABLE));
+    }
+}
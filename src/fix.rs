@@ -0,0 +1,270 @@
+// SPDX-License-Identifier: MIT OR AGPL-3.0-or-later
+// SPDX-FileCopyrightText: 2024-2025 hyperpolymath
+
+//! Fixing diagnostics automatically.
+//!
+//! [`check`](crate::check::check) attaches a [`Suggestion`] to every
+//! diagnostic it knows how to fix: an unused binding can be renamed with a
+//! leading `_`, and an unbound variable replaced by a similarly named one
+//! bound before it. A secret bound to a `let` declared `pub` can be
+//! wrapped in `declassify(...)`, and a secret divided by a power of two
+//! shifted instead, which rounds negative values down rather than towards
+//! zero. [`apply`] makes the machine-applicable edits, as `obli fix` does;
+//! the language server also offers the guesses, as quick fixes the user
+//! picks.
+
+use crate::ast::Expr;
+use crate::codes;
+use crate::diagnostic::{Diagnostic, Suggestion};
+use crate::lexer::{Lexer, Token};
+use crate::parser::Parser;
+use crate::span::Span;
+use crate::stack;
+
+/// Attach suggestions to the `diagnostics` of `source`.
+pub(crate) fn suggest(source: &str, diagnostics: &mut [Diagnostic]) {
+    let Ok((tokens, spans)) = Lexer::new(source).tokenize() else {
+        return;
+    };
+    // Every `let`-bound name, in source order
+    let bound: Vec<(&str, Span)> = tokens
+        .windows(2)
+        .zip(spans.get(1..).unwrap_or_default())
+        .filter_map(|(pair, span)| match pair {
+            [Token::Let, Token::Ident(name)] => Some((name.as_str(), *span)),
+            _ => None,
+        })
+        .collect();
+    let used = |name: &str| {
+        tokens
+            .iter()
+            .any(|token| matches!(token, Token::Ident(n) if n.as_str() == name))
+    };
+
+    for diagnostic in diagnostics {
+        let Some(span) = diagnostic.span else {
+            continue;
+        };
+        let suggestion = match diagnostic.code {
+            Some(codes::UNUSED_VARIABLE) => {
+                // The binding's span starts at its `let`; a parameter bound
+                // by an inlined call spans the call instead
+                let Some((name, name_span)) = bound
+                    .iter()
+                    .find(|(_, s)| s.start > span.start)
+                    .filter(|(_, s)| s.end <= span.end && source[span.start..].starts_with("let"))
+                else {
+                    continue;
+                };
+                let renamed = format!("_{}", name);
+                if used(&renamed) {
+                    continue;
+                }
+                let message = format!("prefix `{}` with `_` to mark it unused", name);
+                Suggestion::new(message, *name_span, renamed)
+            }
+            Some(codes::UNBOUND_VARIABLE) => {
+                let name = &source[span.start..span.end];
+                let Some(similar) = similar_name(name, &bound, span.start) else {
+                    continue;
+                };
+                let message = format!("use `{}`, bound earlier with a similar name", similar);
+                Suggestion::new(message, span, similar).maybe_incorrect()
+            }
+            Some(codes::DECLARED_PUBLIC) => {
+                let Some(value) = let_value(source, span) else {
+                    continue;
+                };
+                let declassified = format!("declassify({})", &source[value.start..value.end]);
+                let message = "reveal the value explicitly with `declassify`";
+                Suggestion::new(message, value, declassified).maybe_incorrect()
+            }
+            Some(codes::SECRET_DIVISION) => {
+                let within: Vec<_> = tokens
+                    .iter()
+                    .zip(&spans)
+                    .filter(|(_, s)| s.start >= span.start && s.end <= span.end)
+                    .collect();
+                let [.., (Token::Slash, slash), (Token::Int(divisor), last)] = within[..] else {
+                    continue;
+                };
+                if *divisor < 2 || divisor.count_ones() != 1 || last.end != span.end {
+                    continue;
+                }
+                let dividend = source[span.start..slash.start].trim_end();
+                let shifted = format!("({} >> {})", dividend, divisor.trailing_zeros());
+                let message = "shift instead of dividing by a power of two";
+                Suggestion::new(message, span, shifted).maybe_incorrect()
+            }
+            _ => continue,
+        };
+        diagnostic.suggestions.push(suggestion);
+    }
+}
+
+/// Span of the value of the `let` of `source` spanning `span`.
+fn let_value(source: &str, span: Span) -> Option<Span> {
+    let (tokens, token_spans) = Lexer::new(source).tokenize().ok()?;
+    let (ast, spans) = Parser::with_spans(&tokens, &token_spans).parse_with_spans().ok()?;
+    let mut nodes = Vec::new();
+    post_order(&ast, &mut nodes);
+    let span_of = |node: &Expr| {
+        let index = nodes.iter().position(|&other| std::ptr::eq(other, node))?;
+        spans.get(index)
+    };
+    let value = nodes.iter().find_map(|&node| match node {
+        Expr::Let { value, .. } if span_of(node) == Some(span) => span_of(value),
+        _ => None,
+    });
+    stack::drop_tree(ast);
+    value
+}
+
+/// The nodes of `expr`, in the post-order of its spans.
+fn post_order<'e>(expr: &'e Expr, nodes: &mut Vec<&'e Expr>) {
+    for child in expr.children() {
+        stack::guard(|| post_order(child, nodes));
+    }
+    nodes.push(expr);
+}
+
+/// The name in `bound` before `before` closest to `name`, if close enough
+/// to be a typo; the latest binding wins ties.
+fn similar_name<'a>(name: &str, bound: &[(&'a str, Span)], before: usize) -> Option<&'a str> {
+    let limit = (name.chars().count() / 3).max(1);
+    bound
+        .iter()
+        .rev()
+        .filter(|(candidate, span)| span.start < before && *candidate != name)
+        .map(|(candidate, _)| (edit_distance(name, candidate), *candidate))
+        .filter(|(distance, _)| *distance <= limit)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| candidate)
+}
+
+/// Levenshtein distance between `a` and `b`, in characters.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substituted = diagonal + usize::from(ca != *cb);
+            diagonal = row[j + 1];
+            row[j + 1] = substituted.min(row[j] + 1).min(diagonal + 1);
+        }
+    }
+    row[b.len()]
+}
+
+/// Source with suggestions applied.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Fixed {
+    pub source: String,
+    /// Number of edits made.
+    pub applied: usize,
+}
+
+/// Apply the machine-applicable suggestions of `diagnostics` to `source`.
+/// An edit overlapping one earlier in the source is skipped; running
+/// [`check`](crate::check::check) again finds whatever it would have fixed.
+pub fn apply(source: &str, diagnostics: &[Diagnostic]) -> Fixed {
+    let mut suggestions: Vec<&Suggestion> = diagnostics
+        .iter()
+        .flat_map(|diagnostic| &diagnostic.suggestions)
+        .filter(|suggestion| suggestion.is_machine_applicable())
+        .collect();
+    suggestions.sort_by_key(|suggestion| (suggestion.span.start, suggestion.span.end));
+
+    let mut fixed = String::with_capacity(source.len());
+    let mut copied = 0;
+    let mut applied = 0;
+    for suggestion in suggestions {
+        let span = suggestion.span;
+        if span.start < copied || span.end > source.len() {
+            continue;
+        }
+        fixed.push_str(&source[copied..span.start]);
+        fixed.push_str(&suggestion.replacement);
+        copied = span.end;
+        applied += 1;
+    }
+    fixed.push_str(&source[copied..]);
+    Fixed {
+        source: fixed,
+        applied,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::check::check;
+    use crate::diagnostic::Renderer;
+
+    #[test]
+    fn test_unused_bindings_fixed() {
+        let source = "let key = secret(42)\nlet n = 2\nlet _n = 3\n_n + 1";
+        let diagnostics = check(source);
+        assert_eq!(diagnostics.len(), 2);
+        // `n` cannot become `_n` without capturing its use
+        assert!(diagnostics[0].suggestions.is_empty());
+        assert_eq!(diagnostics[1].suggestions[0].span, Span::new(4, 7));
+        let rendered = Renderer::plain().render(&diagnostics[1], "f", source);
+        assert!(rendered.ends_with("= help: prefix `key` with `_` to mark it unused\n"));
+
+        let fixed = apply(source, &diagnostics);
+        assert_eq!(fixed.applied, 1);
+        assert_eq!(fixed.source, "let _key = secret(42)\nlet n = 2\nlet _n = 3\n_n + 1");
+    }
+
+    #[test]
+    fn test_typos_suggested_but_not_applied() {
+        let source = "let total = secret(1) let count = 2 totl + count";
+        let diagnostics = check(source);
+        let suggestion = &diagnostics[0].suggestions[0];
+        assert_eq!(suggestion.span, Span::new(36, 40));
+        assert_eq!(suggestion.replacement, "total");
+        assert!(!suggestion.is_machine_applicable());
+        assert_eq!(apply(source, &diagnostics).applied, 0);
+
+        assert!(check("let total = 1 x").iter().all(|d| d.suggestions.is_empty()));
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+    }
+
+    #[test]
+    fn test_empty_sources() {
+        for source in ["", "# only a comment\n"] {
+            let mut diagnostics = check(source);
+            suggest(source, &mut diagnostics);
+        }
+    }
+
+    #[test]
+    fn test_public_secrets_declassified() {
+        let source = "let key: pub int = secret(7) + 1\nkey * 2";
+        let diagnostics = check(source);
+        assert_eq!(diagnostics[0].code, Some(codes::DECLARED_PUBLIC));
+        let suggestion = &diagnostics[0].suggestions[0];
+        assert_eq!(suggestion.span, Span::new(19, 32));
+        assert_eq!(suggestion.replacement, "declassify(secret(7) + 1)");
+        assert!(!suggestion.is_machine_applicable());
+    }
+
+    #[test]
+    fn test_divisions_by_powers_of_two_shifted() {
+        let source = "let k = secret(9)\n1 + (k + 1) / 8";
+        let diagnostics = check(source);
+        assert_eq!(diagnostics[0].code, Some(codes::SECRET_DIVISION));
+        let suggestion = &diagnostics[0].suggestions[0];
+        assert_eq!(suggestion.span, Span::new(22, 33));
+        assert_eq!(suggestion.replacement, "((k + 1) >> 3)");
+        let fixed = source.replace("(k + 1) / 8", &suggestion.replacement);
+        assert!(check(&fixed).is_empty());
+
+        for source in ["let k = secret(9) k / 6", "let k = secret(9) 8 / k"] {
+            assert!(check(source).iter().all(|d| d.suggestions.is_empty()));
+        }
+    }
+}
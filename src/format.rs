@@ -0,0 +1,130 @@
+// SPDX-License-Identifier: MIT OR AGPL-3.0-or-later
+// SPDX-FileCopyrightText: 2024-2025 hyperpolymath
+
+//! Versions of stored artifacts.
+//!
+//! Everything the crate writes for other tools to read carries
+//! [`FORMAT_VERSION`]: checkpointed pipeline stages saved with
+//! [`to_versioned_json`], [schemas](crate::schema), compilation
+//! [reports](crate::report), and the runtime prelude of generated code,
+//! whose header names the version of the runtime API it defines. The
+//! version is bumped whenever one of these changes incompatibly, and
+//! readers accept versions from [`OLDEST_READABLE`] to [`FORMAT_VERSION`],
+//! so a toolchain can mix artifacts of crate versions within that range.
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+/// Version of the artifacts this crate writes.
+pub const FORMAT_VERSION: u32 = 2;
+
+/// Oldest version this crate still reads.
+pub const OLDEST_READABLE: u32 = 2;
+
+/// Header line of the runtime prelude, followed by its version.
+pub const RUNTIME_HEADER: &str = "// obli-runtime-version: ";
+
+#[derive(Error, Debug)]
+pub enum FormatError {
+    #[error("invalid artifact: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error(
+        "format version {0} is not supported (expected {OLDEST_READABLE} to {FORMAT_VERSION})"
+    )]
+    Unsupported(u32),
+    #[error("generated code does not name its runtime version")]
+    Unversioned,
+}
+
+/// Whether this crate reads artifacts of format `version`.
+pub fn is_compatible(version: u32) -> bool {
+    (OLDEST_READABLE..=FORMAT_VERSION).contains(&version)
+}
+
+/// Fail unless this crate reads artifacts of format `version`.
+pub fn check_version(version: u32) -> Result<(), FormatError> {
+    if is_compatible(version) {
+        Ok(())
+    } else {
+        Err(FormatError::Unsupported(version))
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct Versioned<T> {
+    format_version: u32,
+    value: T,
+}
+
+#[derive(Deserialize)]
+struct Header {
+    format_version: u32,
+}
+
+/// `value` as JSON, tagged with [`FORMAT_VERSION`]. With the `serde`
+/// feature this stores any pipeline stage, such as the IR.
+pub fn to_versioned_json<T: Serialize>(value: &T) -> String {
+    let versioned = Versioned {
+        format_version: FORMAT_VERSION,
+        value,
+    };
+    serde_json::to_string(&versioned).expect("artifacts always serialize")
+}
+
+/// Read a value written by [`to_versioned_json`], checking its version
+/// before its contents.
+pub fn from_versioned_json<T: DeserializeOwned>(json: &str) -> Result<T, FormatError> {
+    let header: Header = serde_json::from_str(json)?;
+    check_version(header.format_version)?;
+    let versioned: Versioned<T> = serde_json::from_str(json)?;
+    Ok(versioned.value)
+}
+
+/// Runtime API version of generated code, read from its prelude header.
+pub fn runtime_version(code: &str) -> Option<u32> {
+    code.lines()
+        .find_map(|line| line.strip_prefix(RUNTIME_HEADER))
+        .and_then(|version| version.trim().parse().ok())
+}
+
+/// Fail unless generated `code` uses a runtime API this crate supports.
+pub fn check_runtime(code: &str) -> Result<(), FormatError> {
+    check_version(runtime_version(code).ok_or(FormatError::Unversioned)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transpile;
+
+    #[test]
+    fn test_versioned_json() {
+        let json = to_versioned_json(&vec![1, 2]);
+        assert_eq!(json, format!("{{\"format_version\":{},\"value\":[1,2]}}", FORMAT_VERSION));
+        assert_eq!(from_versioned_json::<Vec<i64>>(&json).unwrap(), [1, 2]);
+
+        let newer = format!("{{\"format_version\":{},\"value\":\"x\"}}", FORMAT_VERSION + 1);
+        let err = from_versioned_json::<Vec<i64>>(&newer).unwrap_err();
+        assert!(matches!(err, FormatError::Unsupported(v) if v == FORMAT_VERSION + 1));
+        let older = format!("{{\"format_version\":{},\"value\":[]}}", OLDEST_READABLE - 1);
+        assert!(from_versioned_json::<Vec<i64>>(&older).is_err());
+        assert!(from_versioned_json::<Vec<i64>>("{\"value\":[]}").is_err());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_versioned_ir() {
+        let ir = crate::to_oblivious(&crate::Expr::Secret(Box::new(crate::Expr::Int(3))));
+        let json = to_versioned_json(&ir);
+        assert_eq!(from_versioned_json::<crate::ObliExpr>(&json).unwrap(), ir);
+    }
+
+    #[test]
+    fn test_generated_code_names_runtime_version() {
+        let code = transpile("secret(1) + 2").unwrap();
+        assert_eq!(runtime_version(&code), Some(FORMAT_VERSION));
+        assert!(check_runtime(&code).is_ok());
+        assert!(matches!(check_runtime("fn main() {}"), Err(FormatError::Unversioned)));
+    }
+}
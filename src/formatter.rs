@@ -0,0 +1,1182 @@
+// SPDX-License-Identifier: MIT OR AGPL-3.0-or-later
+// SPDX-FileCopyrightText: 2024-2025 hyperpolymath
+
+//! Source formatter for MiniObli.
+//!
+//! Works on the lossless [`Cst`] so comments survive formatting. Layout
+//! rules:
+//!
+//! * each `let` starts a new line, with its body on the following line at
+//!   the same indentation;
+//! * an `if` that does not fit on one line is split into
+//!   `if cond` / `then ...` / `else ...`, branches indented by one level;
+//! * operator chains that do not fit are broken before each operator;
+//! * parentheses are normalized to the minimum the grammar requires;
+//! * an `if` in an else branch stays an `elif` if it was written as one;
+//! * `saturating(e)` is written out as the saturating operators it
+//!   stands for;
+//! * integer literals keep their spelling, such as `0xFF` or `1_000`;
+//! * comments stay in order and are placed on their own line before the
+//!   code that followed them; single blank lines are preserved;
+//! * `include` lines are kept as written, like comments, rather than
+//!   expanded.
+
+use crate::ast::{byte_string, declares_input, enum_header, Annotation, BinOp, Expr, UnaryOp};
+use crate::cst::{Cst, SyntaxKind};
+use crate::diagnostic::Diagnostic;
+use crate::include::directive;
+use crate::lexer::{Lexer, Token};
+use crate::span::Span;
+use crate::stack;
+use crate::symbol::Symbol;
+use crate::typeck::Type;
+use std::collections::HashMap;
+
+/// Formatter settings.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FormatOptions {
+    /// Preferred maximum line width.
+    pub max_width: usize,
+    /// Spaces per indentation level.
+    pub indent_width: usize,
+}
+
+impl Default for FormatOptions {
+    fn default() -> Self {
+        Self {
+            max_width: 80,
+            indent_width: 2,
+        }
+    }
+}
+
+/// Format source text with default options.
+pub fn format_source(source: &str) -> Result<String, Box<Diagnostic>> {
+    format_source_with(source, &FormatOptions::default())
+}
+
+/// Format source text.
+pub fn format_source_with(
+    source: &str,
+    options: &FormatOptions,
+) -> Result<String, Box<Diagnostic>> {
+    // `include` lines are formatted as the comments they are masked as
+    let cst = Cst::parse(&mask_includes(source))?;
+    Ok(unmask_includes(&format_cst(&cst, options)))
+}
+
+/// Spelling of `include` that makes a directive a comment of the same length.
+const MASKED_INCLUDE: &str = "#nclude";
+
+fn mask_includes(source: &str) -> String {
+    source
+        .split_inclusive('\n')
+        .map(|line| match directive(line) {
+            Some((start, ..)) => {
+                format!("{}{}{}", &line[..start], MASKED_INCLUDE, &line[start + 7..])
+            }
+            None => line.to_string(),
+        })
+        .collect()
+}
+
+fn unmask_includes(formatted: &str) -> String {
+    formatted
+        .split_inclusive('\n')
+        .map(|line| {
+            let start = line.len() - line.trim_start().len();
+            let restored = line[start..]
+                .strip_prefix(MASKED_INCLUDE)
+                .map(|rest| format!("{}include{}", &line[..start], rest));
+            match restored {
+                Some(restored) if directive(&restored).is_some() => restored,
+                _ => line.to_string(),
+            }
+        })
+        .collect()
+}
+
+/// Format an already-parsed CST.
+pub fn format_cst(cst: &Cst, options: &FormatOptions) -> String {
+    let mut formatter = Formatter {
+        cst,
+        options,
+        spans: cst.span_map(),
+        comments: cst.comments().map(|t| t.span).collect(),
+        next_comment: 0,
+        out: String::new(),
+    };
+    formatter.block(cst.ast(), 0);
+    formatter.remaining_comments();
+    formatter.out
+}
+
+/// Line diff between `original` and `formatted`, empty if they are equal.
+pub fn diff(original: &str, formatted: &str) -> String {
+    diff_labeled(original, formatted, ("original", "formatted"))
+}
+
+/// Like [`diff`], naming the two sides in the header.
+pub fn diff_labeled(original: &str, formatted: &str, labels: (&str, &str)) -> String {
+    if original == formatted {
+        return String::new();
+    }
+    let old: Vec<&str> = original.lines().collect();
+    let new: Vec<&str> = formatted.lines().collect();
+
+    // Longest common subsequence table, filled from the end
+    let mut lcs = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lcs[i][j] = if old[i] == new[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let mut out = format!("--- {}\n+++ {}\n", labels.0, labels.1);
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            out.push_str(&format!(" {}\n", old[i]));
+            i += 1;
+            j += 1;
+        } else if i < old.len() && (j == new.len() || lcs[i + 1][j] >= lcs[i][j + 1]) {
+            out.push_str(&format!("-{}\n", old[i]));
+            i += 1;
+        } else {
+            out.push_str(&format!("+{}\n", new[j]));
+            j += 1;
+        }
+    }
+    if original.ends_with('\n') != formatted.ends_with('\n') {
+        out.push_str("\\ trailing newline differs\n");
+    }
+    out
+}
+
+/// The condition, then branch and else branch of an `if`.
+type IfParts<'e> = (&'e Expr, &'e Expr, &'e Expr);
+
+struct Formatter<'a> {
+    cst: &'a Cst,
+    options: &'a FormatOptions,
+    spans: HashMap<*const Expr, Span>,
+    comments: Vec<Span>,
+    next_comment: usize,
+    out: String,
+}
+
+impl<'a> Formatter<'a> {
+    fn span(&self, expr: &Expr) -> Span {
+        self.spans
+            .get(&(expr as *const Expr))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Start of the parentheses, if any, around the expression at `pos`,
+    /// so a blank line before them counts as one before it.
+    fn paren_start(&self, pos: usize) -> usize {
+        self.cst
+            .tokens()
+            .iter()
+            .rev()
+            .skip_while(|t| t.span.start >= pos)
+            .filter(|t| !t.kind.is_trivia())
+            .take_while(|t| t.kind == SyntaxKind::Token(Token::LParen))
+            .last()
+            .map_or(pos, |t| t.span.start)
+    }
+
+    fn fits(&self, indent: usize, text: &str) -> bool {
+        indent + text.len() <= self.options.max_width
+    }
+
+    /// Write one line at `indent`, preserving a blank line that preceded
+    /// `source_pos` in the original text.
+    fn line(&mut self, indent: usize, source_pos: usize, text: &str) {
+        if !self.out.is_empty()
+            && !self.out.ends_with("\n\n")
+            && blank_line_before(self.cst.source(), source_pos)
+        {
+            self.out.push('\n');
+        }
+        self.out.push_str(&" ".repeat(indent));
+        self.out.push_str(text);
+        self.out.push('\n');
+    }
+
+    fn comments_before(&mut self, pos: usize, indent: usize) {
+        while let Some(&span) = self.comments.get(self.next_comment) {
+            if span.start >= pos {
+                break;
+            }
+            self.next_comment += 1;
+            let text = self.cst.text(span).trim_end().to_string();
+            self.line(indent, span.start, &text);
+        }
+    }
+
+    fn remaining_comments(&mut self) {
+        self.comments_before(usize::MAX, 0);
+    }
+
+    /// Emit `expr` starting on a fresh line at `indent`.
+    fn block(&mut self, expr: &Expr, indent: usize) {
+        stack::guard(|| self.block_node(expr, indent))
+    }
+
+    fn block_node(&mut self, expr: &Expr, indent: usize) {
+        let start = self.span(expr).start;
+        self.comments_before(start, indent);
+        let step = self.options.indent_width;
+
+        match expr {
+            Expr::Let {
+                name,
+                ty,
+                mutable,
+                value,
+                body,
+            } if declares_input(*name, ty, *mutable, value) => {
+                self.line(indent, start, &self.flat(value));
+                self.block(body, indent);
+            }
+            Expr::Outputs(outputs) if self.declares_outputs(expr) => {
+                for (name, value) in outputs {
+                    // The declaration starts at its `output`, before the name
+                    let name_start = self.span(value).start;
+                    let source = &self.cst.source()[..name_start];
+                    let output_start = source.rfind("output").unwrap_or(name_start);
+                    self.comments_before(output_start, indent);
+                    self.line(indent, output_start, &format!("output {}", name));
+                }
+            }
+            Expr::Let {
+                name,
+                ty,
+                mutable,
+                value,
+                body,
+            } => {
+                let header = let_header(*name, ty, *mutable);
+                self.binding(&header, value, body, start, indent);
+            }
+            Expr::Assign {
+                name,
+                cond,
+                value,
+                body,
+            } => {
+                self.binding(&self.assign_header(*name, cond), value, body, start, indent);
+            }
+            Expr::LetTuple { names, value, body } => {
+                let header = format!("let ({}) = ", param_list(names));
+                self.binding(&header, value, body, start, indent);
+            }
+            Expr::Const { name, value, body } => {
+                self.binding(&format!("const {} = ", name), value, body, start, indent);
+            }
+            Expr::Enum {
+                name,
+                variants,
+                body,
+            } => {
+                self.line(indent, start, &enum_header(*name, variants));
+                self.block(body, indent);
+            }
+            Expr::Assert { cond, body } => {
+                self.line(indent, start, &format!("assert({})", self.flat(cond)));
+                self.block(body, indent);
+            }
+            Expr::Fn {
+                name,
+                max_depth,
+                params,
+                body,
+                rest,
+            } => {
+                let mut start = start;
+                if let Some(depth) = max_depth {
+                    self.line(indent, start, &format!("#[max_depth({})]", depth));
+                    // The definition's own line follows the attribute
+                    let source = &self.cst.source()[start..];
+                    start += source.find(']').map_or(0, |end| end + 1);
+                }
+                let header = format!("fn {}({}) = ", name, param_list(params));
+                self.binding(&header, body, rest, start, indent);
+            }
+            Expr::If {
+                cond,
+                then_branch,
+                else_branch,
+            } => {
+                let whole = self.flat(expr);
+                if self.fits(indent, &whole) {
+                    self.line(indent, start, &whole);
+                    return;
+                }
+                self.if_lines("if", (cond, then_branch, else_branch), start, indent);
+            }
+            Expr::Match {
+                scrutinee,
+                arms,
+                default,
+            } => {
+                let whole = self.flat(expr);
+                if self.fits(indent, &whole) {
+                    self.line(indent, start, &whole);
+                    return;
+                }
+                self.line(indent, start, &format!("match {} with", self.flat(scrutinee)));
+                for (i, (pattern, arm)) in arms.iter().enumerate() {
+                    let arm_start = self.span(pattern).start;
+                    self.comments_before(arm_start, indent + step);
+                    let comma = if default.is_some() || i + 1 < arms.len() { "," } else { "" };
+                    let line = format!("{} => {}{}", self.flat(pattern), self.flat(arm), comma);
+                    self.line(indent + step, arm_start, &line);
+                }
+                if let Some(default) = default {
+                    self.branch("_ =>", default, indent + step);
+                }
+            }
+            Expr::For { body, .. } | Expr::While { body, .. } => {
+                let whole = self.flat(expr);
+                if !is_binding(body) && self.fits(indent, &whole) {
+                    self.line(indent, start, &whole);
+                    return;
+                }
+                self.line(indent, start, &format!("{} do", self.loop_header(expr)));
+                self.block(body, indent + step);
+            }
+            Expr::Lambda { params, body } => {
+                let whole = self.flat(expr);
+                if !is_binding(body) && self.fits(indent, &whole) {
+                    self.line(indent, start, &whole);
+                    return;
+                }
+                self.line(indent, start, &format!("fn({}) =>", param_list(params)));
+                self.block(body, indent + step);
+            }
+            _ => {
+                let lines = self.wrap(expr, indent);
+                for (i, line) in lines.into_iter().enumerate() {
+                    if i == 0 {
+                        self.line(0, start, &line);
+                    } else {
+                        self.out.push_str(&line);
+                        self.out.push('\n');
+                    }
+                }
+            }
+        }
+    }
+
+    /// Emit `header value` from `start` and then `body`, as for a `let`.
+    fn binding(&mut self, header: &str, value: &Expr, body: &Expr, start: usize, indent: usize) {
+        let one_line = format!("{}{}", header, self.flat(value));
+        let lines = match value {
+            Expr::Lambda { body, .. } => is_binding(body),
+            _ => is_binding(value),
+        };
+        if !lines && self.fits(indent, &one_line) {
+            self.line(indent, start, &one_line);
+        } else {
+            self.line(indent, start, header.trim_end());
+            self.block(value, indent + self.options.indent_width);
+        }
+        if continues_value(body) {
+            // Otherwise the value would index or subtract it
+            let body_start = self.paren_start(self.span(body).start);
+            self.line(indent, body_start, &format!("({})", self.flat(body)));
+        } else {
+            self.block(body, indent);
+        }
+    }
+
+    /// Emit `keyword cond` and the branches of an `if` on lines of their
+    /// own, continuing with `elif` where the source wrote one.
+    fn if_lines(&mut self, keyword: &str, parts: IfParts, start: usize, indent: usize) {
+        let (cond, then_branch, else_branch) = parts;
+        let step = self.options.indent_width;
+        let cond_lines = self.wrap(cond, indent + keyword.len() + 1);
+        for (i, line) in cond_lines.into_iter().enumerate() {
+            if i == 0 {
+                self.line(indent, start, &format!("{} {}", keyword, line.trim_start()));
+            } else {
+                self.out.push_str(&line);
+                self.out.push('\n');
+            }
+        }
+        self.branch("then", then_branch, indent + step);
+        match self.elif(else_branch) {
+            Some(parts) => {
+                let elif_start = self.span(else_branch).start;
+                self.comments_before(elif_start, indent);
+                self.if_lines("elif", parts, elif_start, indent);
+            }
+            None => self.branch("else", else_branch, indent + step),
+        }
+    }
+
+    /// The condition and branches of `expr` if it is an `if` the source
+    /// wrote as an `elif`.
+    fn elif<'e>(&self, expr: &'e Expr) -> Option<IfParts<'e>> {
+        match expr {
+            Expr::If {
+                cond,
+                then_branch,
+                else_branch,
+            } if self.cst.text(self.span(expr)).starts_with("elif") => {
+                Some((cond, then_branch, else_branch))
+            }
+            _ => None,
+        }
+    }
+
+    /// Emit `keyword branch`, moving the branch to its own block if needed.
+    fn branch(&mut self, keyword: &str, branch: &Expr, indent: usize) {
+        let start = self.span(branch).start;
+        self.comments_before(start, indent);
+        let branch_flat = self.flat(branch);
+        let line = format!("{} {}", keyword, branch_flat);
+        if !is_binding(branch) && self.fits(indent, &line) {
+            self.line(indent, start, &line);
+        } else {
+            self.line(indent, start, keyword);
+            self.block(branch, indent + self.options.indent_width);
+        }
+    }
+
+    /// Render `expr` as indented lines, breaking operator chains before
+    /// each operator when the flat form is too wide.
+    fn wrap(&self, expr: &Expr, indent: usize) -> Vec<String> {
+        let pad = " ".repeat(indent);
+        let whole = self.flat(expr);
+        if self.fits(indent, &whole) {
+            return vec![format!("{}{}", pad, whole)];
+        }
+        let Expr::BinOp { op, .. } = expr else {
+            return vec![format!("{}{}", pad, whole)];
+        };
+
+        let level = precedence(op);
+        let mut operands = Vec::new();
+        collect_chain(expr, level, &mut operands);
+
+        let cont = " ".repeat(indent + self.options.indent_width);
+        let mut lines = vec![format!("{}{}", pad, self.operand(operands[0].1, level, false))];
+        for (op, operand_expr) in &operands[1..] {
+            let op = op.expect("chain operands after the first have an operator");
+            lines.push(format!(
+                "{}{} {}",
+                cont,
+                binop_symbol(op),
+                self.operand(operand_expr, level, true)
+            ));
+        }
+        lines
+    }
+
+    /// An integer literal as the source wrote it, so `0xFF` and `1_000`
+    /// keep their spelling, or in decimal if it was not written as one.
+    fn literal(&self, expr: &Expr) -> String {
+        let text = self.cst.text(self.span(expr));
+        let token = match Lexer::new(text).tokenize() {
+            Ok((tokens, _)) if tokens.len() == 1 => tokens.into_iter().next(),
+            _ => None,
+        };
+        match (token, expr) {
+            (Some(Token::Int(n)), Expr::Int(value)) if n == *value => text.to_string(),
+            (Some(Token::Sized(n, w)), Expr::Sized(value, width)) if (n, w) == (*value, *width) => {
+                text.to_string()
+            }
+            _ => expr.to_string(),
+        }
+    }
+
+    /// Render an operand of an operator at `level`, parenthesized if needed.
+    fn operand(&self, expr: &Expr, level: u8, is_right: bool) -> String {
+        let prec = expr_precedence(expr);
+        let needs_parens = if is_right || level == CMP {
+            prec <= level
+        } else {
+            prec < level
+        };
+        if needs_parens {
+            format!("({})", self.flat(expr))
+        } else {
+            self.flat(expr)
+        }
+    }
+
+    /// Single-line rendering with minimal parentheses.
+    fn flat(&self, expr: &Expr) -> String {
+        stack::guard(|| self.flat_node(expr))
+    }
+
+    fn flat_node(&self, expr: &Expr) -> String {
+        match expr {
+            Expr::Int(_) | Expr::Sized(..) => self.literal(expr),
+            Expr::Bool(b) => b.to_string(),
+            Expr::Var(name) => name.to_string(),
+            Expr::Input { .. } => expr.to_string(),
+            Expr::Secret(inner) => format!("secret({})", self.flat(inner)),
+            Expr::BinOp { op, left, right } => {
+                let level = precedence(op);
+                format!(
+                    "{} {} {}",
+                    self.operand(left, level, false),
+                    binop_symbol(op),
+                    self.operand(right, level, true)
+                )
+            }
+            Expr::UnaryOp { op, expr: inner } => {
+                let inner_text = if expr_precedence(inner) < UNARY {
+                    format!("({})", self.flat(inner))
+                } else {
+                    self.flat(inner)
+                };
+                match op {
+                    UnaryOp::Neg => format!("-{}", inner_text),
+                    UnaryOp::Not => format!("not {}", inner_text),
+                    UnaryOp::ToInt | UnaryOp::ToBool => format!("{}({})", op, self.flat(inner)),
+                }
+            }
+            Expr::Cast { expr: inner, ty } => {
+                let inner_text = if expr_precedence(inner) < CAST {
+                    format!("({})", self.flat(inner))
+                } else {
+                    self.flat(inner)
+                };
+                format!("{} as {}", inner_text, Type::from(*ty))
+            }
+            Expr::If {
+                cond,
+                then_branch,
+                else_branch,
+            } => {
+                let mut text = format!("if {} then {}", self.flat(cond), self.flat(then_branch));
+                let mut rest: &Expr = else_branch;
+                while let Some((cond, then_branch, else_branch)) = self.elif(rest) {
+                    let (cond, then_branch) = (self.flat(cond), self.flat(then_branch));
+                    text.push_str(&format!(" elif {} then {}", cond, then_branch));
+                    rest = else_branch;
+                }
+                format!("{} else {}", text, self.flat(rest))
+            }
+            Expr::Match {
+                scrutinee,
+                arms,
+                default,
+            } => {
+                let mut arms: Vec<_> = arms
+                    .iter()
+                    .map(|(p, e)| format!("{} => {}", self.flat(p), self.flat(e)))
+                    .collect();
+                arms.extend(default.iter().map(|default| format!("_ => {}", self.flat(default))));
+                format!("match {} with {}", self.flat(scrutinee), arms.join(", "))
+            }
+            Expr::Let {
+                name,
+                ty,
+                mutable,
+                value,
+                body,
+            } if declares_input(*name, ty, *mutable, value) => {
+                format!("{} {}", self.flat(value), self.flat(body))
+            }
+            Expr::Let {
+                name,
+                ty,
+                mutable,
+                value,
+                body,
+            } => {
+                let header = format!("{}{}", let_header(*name, ty, *mutable), self.flat(value));
+                if continues_value(body) {
+                    format!("{} ({})", header, self.flat(body))
+                } else {
+                    format!("{} {}", header, self.flat(body))
+                }
+            }
+            Expr::Assign {
+                name,
+                cond,
+                value,
+                body,
+            } => {
+                let header = format!("{}{}", self.assign_header(*name, cond), self.flat(value));
+                if continues_value(body) {
+                    format!("{} ({})", header, self.flat(body))
+                } else {
+                    format!("{} {}", header, self.flat(body))
+                }
+            }
+            Expr::Const { name, value, body } => {
+                let header = format!("const {} = {}", name, self.flat(value));
+                if continues_value(body) {
+                    format!("{} ({})", header, self.flat(body))
+                } else {
+                    format!("{} {}", header, self.flat(body))
+                }
+            }
+            Expr::Enum {
+                name,
+                variants,
+                body,
+            } => format!("{} {}", enum_header(*name, variants), self.flat(body)),
+            Expr::Assert { cond, body } => {
+                format!("assert({}) {}", self.flat(cond), self.flat(body))
+            }
+            Expr::LetTuple { names, value, body } => {
+                let header = format!("let ({}) = {}", param_list(names), self.flat(value));
+                if continues_value(body) {
+                    format!("{} ({})", header, self.flat(body))
+                } else {
+                    format!("{} {}", header, self.flat(body))
+                }
+            }
+            Expr::Fn {
+                name,
+                max_depth,
+                params,
+                body,
+                rest,
+            } => {
+                let attribute = match max_depth {
+                    Some(depth) => format!("#[max_depth({})] ", depth),
+                    None => String::new(),
+                };
+                let params = param_list(params);
+                let header = format!("{}fn {}({}) = {}", attribute, name, params, self.flat(body));
+                if continues_value(rest) {
+                    format!("{} ({})", header, self.flat(rest))
+                } else {
+                    format!("{} {}", header, self.flat(rest))
+                }
+            }
+            Expr::Call { name, args } => {
+                let args: Vec<_> = args.iter().map(|e| self.flat(e)).collect();
+                format!("{}({})", name, args.join(", "))
+            }
+            Expr::Lambda { params, body } => {
+                format!("fn({}) => {}", param_list(params), self.flat(body))
+            }
+            Expr::For { body, .. } | Expr::While { body, .. } => {
+                format!("{} do {}", self.loop_header(expr), self.flat(body))
+            }
+            Expr::Array(elements) => {
+                let elements: Vec<_> = elements.iter().map(|e| self.flat(e)).collect();
+                format!("[{}]", elements.join(", "))
+            }
+            Expr::Tuple(elements) => {
+                let elements: Vec<_> = elements.iter().map(|e| self.flat(e)).collect();
+                format!("({})", elements.join(", "))
+            }
+            Expr::Bytes(bytes) => byte_string(bytes),
+            Expr::Sort(inner) => format!("sort({})", self.flat(inner)),
+            Expr::SwapIf { cond, left, right } => {
+                let (left, right) = (self.flat(left), self.flat(right));
+                format!("swap_if({}, {}, {})", self.flat(cond), left, right)
+            }
+            Expr::Clamp { value, lo, hi } | Expr::InRange { value, lo, hi } => {
+                let name = if let Expr::Clamp { .. } = expr { "clamp" } else { "in_range" };
+                let (lo, hi) = (self.flat(lo), self.flat(hi));
+                format!("{}({}, {}, {})", name, self.flat(value), lo, hi)
+            }
+            Expr::Pow { base, exp } => format!("pow({}, {})", self.flat(base), self.flat(exp)),
+            Expr::Map { array, f } => format!("map({}, {})", self.flat(array), self.flat(f)),
+            Expr::Fold { array, init, f } => {
+                let (init, f) = (self.flat(init), self.flat(f));
+                format!("fold({}, {}, {})", self.flat(array), init, f)
+            }
+            Expr::Some(inner) => format!("some({})", self.flat(inner)),
+            Expr::None => "none".to_string(),
+            Expr::UnwrapOr { option, default } => {
+                format!("unwrap_or({}, {})", self.flat(option), self.flat(default))
+            }
+            Expr::Declassify(inner) => format!("declassify({})", self.flat(inner)),
+            Expr::Index { array, index } if expr_precedence(array) <= UNARY => {
+                format!("({})[{}]", self.flat(array), self.flat(index))
+            }
+            Expr::Index { array, index } => format!("{}[{}]", self.flat(array), self.flat(index)),
+            Expr::Lookup {
+                key,
+                entries,
+                default,
+            } => {
+                let entries: Vec<_> = entries
+                    .iter()
+                    .map(|(k, v)| format!("({}, {})", self.flat(k), self.flat(v)))
+                    .collect();
+                let (key, default) = (self.flat(key), self.flat(default));
+                format!("lookup({}, [{}], {})", key, entries.join(", "), default)
+            }
+            Expr::Outputs(outputs) if self.declares_outputs(expr) => {
+                let outputs: Vec<_> =
+                    outputs.iter().map(|(name, _)| format!("output {}", name)).collect();
+                outputs.join(" ")
+            }
+            Expr::Outputs(outputs) => {
+                let outputs: Vec<_> = outputs
+                    .iter()
+                    .map(|(name, e)| format!("{} = {}", name, self.flat(e)))
+                    .collect();
+                format!("outputs({})", outputs.join(", "))
+            }
+        }
+    }
+
+    /// Whether `expr` is `outputs` the source wrote as `output`
+    /// declarations.
+    fn declares_outputs(&self, expr: &Expr) -> bool {
+        let source = &self.cst.source()[self.span(expr).start..];
+        let declared = source.starts_with("output") && !source.starts_with("outputs");
+        declared && matches!(expr, Expr::Outputs(outputs)
+            if outputs.iter().all(|(name, e)| matches!(e, Expr::Var(var) if var == name)))
+    }
+
+    /// `for i in start..end with acc = init` or `while cond with acc = init`,
+    /// the line before a loop body.
+    fn loop_header(&self, expr: &Expr) -> String {
+        let (var, start, end, acc, init) = match expr {
+            Expr::For {
+                var,
+                start,
+                end,
+                acc,
+                init,
+                ..
+            } => (var, start, end, acc, init),
+            Expr::While {
+                cond, acc, init, ..
+            } => return format!("while {} with {} = {}", self.flat(cond), acc, self.flat(init)),
+            _ => unreachable!("only called on loops"),
+        };
+        // Bounds are `or` expressions
+        let bound = |bound: &Expr| match expr_precedence(bound) {
+            0 => format!("({})", self.flat(bound)),
+            _ => self.flat(bound),
+        };
+        let init = self.flat(init);
+        format!("for {} in {}..{} with {} = {}", var, bound(start), bound(end), acc, init)
+    }
+
+    /// `name = ` or `if cond then name = `.
+    fn assign_header(&self, name: Symbol, cond: &Option<Box<Expr>>) -> String {
+        match cond {
+            Some(cond) => format!("if {} then {} = ", self.flat(cond), name),
+            None => format!("{} = ", name),
+        }
+    }
+}
+
+/// Flatten a left-associative chain of operators at the same precedence.
+fn collect_chain<'e>(expr: &'e Expr, level: u8, out: &mut Vec<(Option<&'e BinOp>, &'e Expr)>) {
+    match expr {
+        Expr::BinOp { op, left, right } if precedence(op) == level && level != CMP => {
+            stack::guard(|| collect_chain(left, level, out));
+            out.push((Some(op), right));
+        }
+        Expr::BinOp { op, left, right } if precedence(op) == level && out.is_empty() => {
+            out.push((None, left));
+            out.push((Some(op), right));
+        }
+        _ => out.push((None, expr)),
+    }
+}
+
+const CMP: u8 = 3;
+const CAST: u8 = 10;
+const UNARY: u8 = 11;
+
+fn precedence(op: &BinOp) -> u8 {
+    match op {
+        BinOp::Or => 1,
+        BinOp::And => 2,
+        BinOp::Eq | BinOp::Ne | BinOp::Lt | BinOp::Le | BinOp::Gt | BinOp::Ge => CMP,
+        BinOp::BitOr => 4,
+        BinOp::BitXor => 5,
+        BinOp::BitAnd => 6,
+        BinOp::Shl | BinOp::Shr => 7,
+        BinOp::Add | BinOp::Sub | BinOp::SatAdd | BinOp::SatSub => 8,
+        BinOp::Mul | BinOp::SatMul | BinOp::Div | BinOp::Mod => 9,
+    }
+}
+
+fn expr_precedence(expr: &Expr) -> u8 {
+    match expr {
+        Expr::Let { .. }
+        | Expr::LetTuple { .. }
+        | Expr::Const { .. }
+        | Expr::Enum { .. }
+        | Expr::Assert { .. }
+        | Expr::Assign { .. }
+        | Expr::Fn { .. }
+        | Expr::Lambda { .. }
+        | Expr::If { .. }
+        | Expr::Match { .. }
+        | Expr::For { .. }
+        | Expr::While { .. } => 0,
+        Expr::BinOp { op, .. } => precedence(op),
+        Expr::UnaryOp {
+            op: UnaryOp::Neg | UnaryOp::Not,
+            ..
+        } => UNARY,
+        Expr::Cast { .. } => CAST,
+        _ => UNARY + 1,
+    }
+}
+
+pub(crate) fn binop_symbol(op: &BinOp) -> &'static str {
+    match op {
+        BinOp::Add => "+",
+        BinOp::Sub => "-",
+        BinOp::Mul => "*",
+        BinOp::SatAdd => "+|",
+        BinOp::SatSub => "-|",
+        BinOp::SatMul => "*|",
+        BinOp::Div => "/",
+        BinOp::Mod => "%",
+        BinOp::BitAnd => "&",
+        BinOp::BitOr => "|",
+        BinOp::BitXor => "^",
+        BinOp::Shl => "<<",
+        BinOp::Shr => ">>",
+        BinOp::Eq => "==",
+        BinOp::Ne => "!=",
+        BinOp::Lt => "<",
+        BinOp::Le => "<=",
+        BinOp::Gt => ">",
+        BinOp::Ge => ">=",
+        BinOp::And => "and",
+        BinOp::Or => "or",
+    }
+}
+
+/// Whether `expr` starts a line of its own, as a `let`, `const`, `enum`,
+/// `fn`, `assert` or assignment does.
+fn is_binding(expr: &Expr) -> bool {
+    matches!(
+        expr,
+        Expr::Let { .. }
+            | Expr::LetTuple { .. }
+            | Expr::Const { .. }
+            | Expr::Enum { .. }
+            | Expr::Assert { .. }
+            | Expr::Assign { .. }
+            | Expr::Fn { .. }
+    )
+}
+
+/// `let name = `, with the annotation if there is one.
+fn let_header(name: Symbol, ty: &Option<Annotation>, mutable: bool) -> String {
+    let keyword = if mutable { "let mut" } else { "let" };
+    match ty {
+        Some(ty) => format!("{} {}: {} = ", keyword, name, ty),
+        None => format!("{} {} = ", keyword, name),
+    }
+}
+
+fn param_list(params: &[Symbol]) -> String {
+    let params: Vec<_> = params.iter().map(|p| p.as_str()).collect();
+    params.join(", ")
+}
+
+/// Whether `expr` is written starting with `[` or `-`, which would
+/// continue a bound value written before it.
+fn continues_value(expr: &Expr) -> bool {
+    match expr {
+        Expr::Array(_) | Expr::UnaryOp { op: UnaryOp::Neg, .. } => true,
+        Expr::Int(n) => *n < 0,
+        Expr::Sized(n, width) => width.value(*n) < 0,
+        Expr::Index { array: first, .. }
+        | Expr::BinOp { left: first, .. }
+        | Expr::Cast { expr: first, .. } => stack::guard(|| continues_value(first)),
+        _ => false,
+    }
+}
+
+/// Whether the whitespace immediately before `pos` contains a blank line.
+fn blank_line_before(source: &str, pos: usize) -> bool {
+    let before = &source[..pos.min(source.len())];
+    let gap_start = before
+        .rfind(|c: char| !c.is_whitespace())
+        .map(|i| i + 1)
+        .unwrap_or(0);
+    gap_start > 0 && before[gap_start..].matches('\n').count() >= 2
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalizes_whitespace_and_parens() {
+        let formatted = format_source("let   x=secret( 1 )   ((x+2))*3").unwrap();
+        assert_eq!(formatted, "let x = secret(1)\n(x + 2) * 3\n");
+    }
+
+    #[test]
+    fn test_idempotent() {
+        for source in [
+            "let x = 1;\nlet y = x * 2;\n\n-y",
+            "let x = 1\n\n((-x))",
+            "let k = secret(3)\n# note\nlet a = [k, 2]\n\n(a)[0]",
+        ] {
+            let once = format_source(source).unwrap();
+            assert_eq!(format_source(&once).unwrap(), once, "{:?}", source);
+        }
+        let formatted = format_source("let x = 1;\nlet y = x * 2;\n\n-y").unwrap();
+        assert_eq!(formatted, "let x = 1\nlet y = x * 2\n\n(-y)\n");
+    }
+
+    #[test]
+    fn test_keeps_includes() {
+        let source =
+            "include \"limits.mobli\"  # shared\ninclude \"lib/x.mobli\"\nlet y = limit+x\ny";
+        let formatted = format_source(source).unwrap();
+        assert_eq!(
+            formatted,
+            "include \"limits.mobli\"  # shared\ninclude \"lib/x.mobli\"\nlet y = limit + x\ny\n"
+        );
+        assert_eq!(format_source(&formatted).unwrap(), formatted);
+    }
+
+    #[test]
+    fn test_formats_annotations() {
+        let formatted = format_source("let k :secret   int=1 let b:bool=true k").unwrap();
+        assert_eq!(formatted, "let k: secret int = 1\nlet b: bool = true\nk\n");
+    }
+
+    #[test]
+    fn test_formats_functions() {
+        let formatted = format_source("fn add(x,y)=x+y fn zero( )=0 add(zero(),[2][0])").unwrap();
+        assert_eq!(formatted, "fn add(x, y) = x + y\nfn zero() = 0\nadd(zero(), [2][0])\n");
+        assert_eq!(format_source(&formatted).unwrap(), formatted);
+    }
+
+    #[test]
+    fn test_formats_lambdas() {
+        let formatted = format_source("fn apply(f,x)=f(x) apply(fn( y )=>y*2,3)").unwrap();
+        assert_eq!(formatted, "fn apply(f, x) = f(x)\napply(fn(y) => y * 2, 3)\n");
+        let formatted = format_source("let f=fn(x)=>let y=x*2 y+1 f(1)").unwrap();
+        assert_eq!(formatted, "let f =\n  fn(x) =>\n    let y = x * 2\n    y + 1\nf(1)\n");
+        assert_eq!(format_source(&formatted).unwrap(), formatted);
+    }
+
+    #[test]
+    fn test_formats_constants() {
+        let formatted = format_source("const n=4;const m=n*2 m-n").unwrap();
+        assert_eq!(formatted, "const n = 4\nconst m = n * 2\nm - n\n");
+        assert_eq!(format_source(&formatted).unwrap(), formatted);
+    }
+
+    #[test]
+    fn test_formats_recursive_functions() {
+        let formatted = format_source("#[max_depth(4)]fn f(n)=if n==0 then 0 else f(n-1);f(3)");
+        let formatted = formatted.unwrap();
+        let expected = "#[max_depth(4)]\nfn f(n) = if n == 0 then 0 else f(n - 1)\nf(3)\n";
+        assert_eq!(formatted, expected);
+        assert_eq!(format_source(&formatted).unwrap(), formatted);
+    }
+
+    #[test]
+    fn test_formats_pow() {
+        let formatted = format_source("pow( k+1,3 )*2").unwrap();
+        assert_eq!(formatted, "pow(k + 1, 3) * 2\n");
+        assert_eq!(format_source(&formatted).unwrap(), formatted);
+    }
+
+    #[test]
+    fn test_formats_map_and_fold() {
+        let formatted = format_source("fold(map( a,fn(x)=>x*2 ),0,add)").unwrap();
+        assert_eq!(formatted, "fold(map(a, fn(x) => x * 2), 0, add)\n");
+        assert_eq!(format_source(&formatted).unwrap(), formatted);
+    }
+
+    #[test]
+    fn test_formats_clamp_and_in_range() {
+        let formatted = format_source("if in_range( k,0,9 ) then clamp(k*2,0 ,9) else 0").unwrap();
+        assert_eq!(formatted, "if in_range(k, 0, 9) then clamp(k * 2, 0, 9) else 0\n");
+        assert_eq!(format_source(&formatted).unwrap(), formatted);
+    }
+
+    #[test]
+    fn test_formats_casts() {
+        let formatted = format_source("(-k)as u8*2+( k+1 ) as int").unwrap();
+        assert_eq!(formatted, "-k as u8 * 2 + (k + 1) as int\n");
+        assert_eq!(format_source(&formatted).unwrap(), formatted);
+    }
+
+    #[test]
+    fn test_formats_conversions() {
+        let formatted = format_source("-to_int( k>0 )+to_int(not to_bool(k))").unwrap();
+        assert_eq!(formatted, "-to_int(k > 0) + to_int(not to_bool(k))\n");
+        assert_eq!(format_source(&formatted).unwrap(), formatted);
+    }
+
+    #[test]
+    fn test_formats_options() {
+        let formatted = format_source("unwrap_or( if k>0 then some(k*2) else none,0 )").unwrap();
+        assert_eq!(formatted, "unwrap_or(if k > 0 then some(k * 2) else none, 0)\n");
+        assert_eq!(format_source(&formatted).unwrap(), formatted);
+    }
+
+    #[test]
+    fn test_formats_assertions() {
+        let formatted = format_source("let k=secret(1) assert( k>0 ) ;k*2").unwrap();
+        assert_eq!(formatted, "let k = secret(1)\nassert(k > 0)\nk * 2\n");
+        assert_eq!(format_source(&formatted).unwrap(), formatted);
+    }
+
+    #[test]
+    fn test_formats_for_loops() {
+        let formatted = format_source("for i in 0..2*2 with s=0 do s+i").unwrap();
+        assert_eq!(formatted, "for i in 0..2 * 2 with s = 0 do s + i\n");
+        let formatted = format_source("for i in 0..4 with s=0 do let t=s+i t*t").unwrap();
+        assert_eq!(formatted, "for i in 0..4 with s = 0 do\n  let t = s + i\n  t * t\n");
+        assert_eq!(format_source(&formatted).unwrap(), formatted);
+        let formatted = format_source("while n<100 with n=1 do n*2").unwrap();
+        assert_eq!(formatted, "while n < 100 with n = 1 do n * 2\n");
+    }
+
+    #[test]
+    fn test_formats_arrays() {
+        let formatted = format_source("let i=1 ( [i,2 ] [ i ] )").unwrap();
+        assert_eq!(formatted, "let i = 1\n([i, 2][i])\n");
+        assert_eq!(format_source(&formatted).unwrap(), formatted);
+        let formatted = format_source(r#"b"a\x22\t"==[lookup(1,[(1,2)],3)]"#).unwrap();
+        assert_eq!(formatted, r#"b"a\"\x09" == [lookup(1, [(1, 2)], 3)]"#.to_string() + "\n");
+    }
+
+    #[test]
+    fn test_keeps_statements_apart() {
+        let formatted = format_source("let x = 1; -x").unwrap();
+        assert_eq!(formatted, "let x = 1\n(-x)\n");
+        assert_eq!(format_source(&formatted).unwrap(), formatted);
+    }
+
+    #[test]
+    fn test_assignments_on_lines_of_their_own() {
+        let formatted = format_source("let mut c = 0; if secret(1) > 0 then c = c + 1; c").unwrap();
+        assert_eq!(formatted, "let mut c = 0\nif secret(1) > 0 then c = c + 1\nc\n");
+        assert_eq!(format_source(&formatted).unwrap(), formatted);
+    }
+
+    #[test]
+    fn test_formats_match() {
+        let formatted = format_source("match x with -1=>0,2=>x*x,_=>1").unwrap();
+        assert_eq!(formatted, "match x with -1 => 0, 2 => x * x, _ => 1\n");
+        let arms: Vec<_> = (1..9).map(|k| format!("{} => {}000000", k, k)).collect();
+        let formatted = format_source(&format!("match x with {}, _ => 0", arms.join(", ")));
+        let formatted = formatted.unwrap();
+        assert!(formatted.starts_with("match x with\n  1 => 1000000,\n"), "{}", formatted);
+        assert!(formatted.ends_with(",\n  _ => 0\n"), "{}", formatted);
+        assert_eq!(format_source(&formatted).unwrap(), formatted);
+    }
+
+    #[test]
+    fn test_keeps_literal_spelling() {
+        let formatted = format_source("let m=0xFF_u8 m&0b1010u8").unwrap();
+        assert_eq!(formatted, "let m = 0xFF_u8\nm & 0b1010u8\n");
+        let formatted = format_source("let m=0xFF (m&0b1010)+1_000*saturating(-m)").unwrap();
+        assert_eq!(formatted, "let m = 0xFF\n(m & 0b1010) + 1_000 * (0 -| m)\n");
+    }
+
+    #[test]
+    fn test_preserves_comments_and_blank_lines() {
+        let src = "# Header\n\nlet pin = secret(1234)\n# Double it\npin * 2\n";
+        assert_eq!(format_source(src).unwrap(), src);
+    }
+
+    #[test]
+    fn test_long_if_is_split_and_nested() {
+        let src = "let s = secret(5) if s > 0 then if s > 100000000000 then secret(111111111111111) else secret(222222222222222) else secret(0)";
+        let formatted = format_source(src).unwrap();
+        assert_eq!(
+            formatted,
+            "let s = secret(5)\n\
+             if s > 0\n  \
+               then\n    \
+                 if s > 100000000000\n      \
+                   then secret(111111111111111)\n      \
+                   else secret(222222222222222)\n  \
+               else secret(0)\n"
+        );
+    }
+
+    #[test]
+    fn test_keeps_elif_chains() {
+        let formatted = format_source("if k<0 then 0 elif k<10 then 1 else if k<100 then 2 else 3");
+        assert_eq!(
+            formatted.unwrap(),
+            "if k < 0 then 0 elif k < 10 then 1 else if k < 100 then 2 else 3\n"
+        );
+        let options = FormatOptions {
+            max_width: 20,
+            ..FormatOptions::default()
+        };
+        let src = "if k < 0 then 0 elif k < 10 then 1 # small\nelif k < 100 then 2 else 3";
+        let formatted = format_source_with(src, &options).unwrap();
+        assert_eq!(
+            formatted,
+            "if k < 0\n  then 0\nelif k < 10\n  then 1\n# small\nelif k < 100\n  then 2\n  else 3\n"
+        );
+        assert_eq!(format_source_with(&formatted, &options).unwrap(), formatted);
+    }
+
+    #[test]
+    fn test_long_chain_wraps_before_operators() {
+        let options = FormatOptions {
+            max_width: 20,
+            ..FormatOptions::default()
+        };
+        let formatted = format_source_with("aaaaa + bbbbb * 2 - ccccc + ddddd", &options).unwrap();
+        assert_eq!(formatted, "aaaaa\n  + bbbbb * 2\n  - ccccc\n  + ddddd\n");
+    }
+
+    #[test]
+    fn test_declarations_on_lines_of_their_own() {
+        let formatted = format_source("input secret k:int;input n : u8 output k   output n").unwrap();
+        assert_eq!(formatted, "input secret k: int\ninput n: u8\noutput k\noutput n\n");
+        assert_eq!(format_source(&formatted).unwrap(), formatted);
+    }
+
+    #[test]
+    fn test_enums() {
+        let formatted = format_source("enum C=A|B match c with A=>1,B=>2").unwrap();
+        assert_eq!(formatted, "enum C = A | B\nmatch c with A => 1, B => 2\n");
+        let options = FormatOptions {
+            max_width: 20,
+            ..FormatOptions::default()
+        };
+        let src = "enum C = A | B match c with A => 100, B => 200";
+        let formatted = format_source_with(src, &options).unwrap();
+        assert_eq!(formatted, "enum C = A | B\nmatch c with\n  A => 100,\n  B => 200\n");
+        assert_eq!(format_source_with(&formatted, &options).unwrap(), formatted);
+    }
+
+    #[test]
+    fn test_idempotent_on_examples() {
+        for src in [
+            include_str!("../examples/complex.mobli"),
+            include_str!("../examples/conditional.mobli"),
+            include_str!("../examples/parameterized.mobli"),
+            include_str!("../examples/simple.mobli"),
+        ] {
+            let once = format_source(src).unwrap();
+            assert_eq!(format_source(&once).unwrap(), once);
+        }
+    }
+
+    #[test]
+    fn test_diff() {
+        assert_eq!(diff("a\n", "a\n"), "");
+        assert_eq!(diff("a\nb\n", "a\nc\n"), "--- original\n+++ formatted\n a\n-b\n+c\n");
+    }
+}
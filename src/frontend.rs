@@ -0,0 +1,395 @@
+// SPDX-License-Identifier: MIT OR AGPL-3.0-or-later
+// SPDX-FileCopyrightText: 2024-2025 hyperpolymath
+
+//! Frontends: source languages read into the shared AST.
+//!
+//! A [`Frontend`] reads a whole program into a [`Program`]; MiniObli
+//! itself and the [JSON AST](crate::json) are built in, and a [`Registry`]
+//! picks one by name or file extension, as [`Pipeline`](crate::passes::Pipeline)
+//! does for passes. The build driver and the command line read every file
+//! through [`Registry::to_source`], so a program in another language is
+//! printed back as MiniObli and checked and compiled like one.
+//!
+//! Kernel frontends ([`c`], and `rust` with the `rust-frontend` feature)
+//! lower one function of their language into a [`Kernel`] instead: a
+//! MiniObli body whose free variables are the function's parameters, each
+//! declared secret or public. [`Kernel::check`] validates the body against
+//! the signature and [`Kernel::emit`] turns it into a constant-time Rust
+//! function, through the same transform and passes as MiniObli source.
+
+use crate::ast::{Expr, Program};
+use crate::build::SOURCE_EXTENSION;
+use crate::config::Config;
+use crate::emit::{emit_function, RUNTIME_PRELUDE};
+use crate::ir::ObliExpr;
+use crate::lexer::Lexer;
+use crate::parser::Parser;
+use crate::span::Span;
+use crate::symbol::Symbol;
+use crate::transform::{mark_as_secret, to_oblivious};
+use crate::typeck::{typecheck, Type};
+use std::fmt;
+use std::path::Path;
+use thiserror::Error;
+
+pub mod c;
+#[cfg(feature = "rust-frontend")]
+pub mod rust;
+
+/// A rejected construct, spanned in the frontend's source.
+#[derive(Error, Debug, Clone, PartialEq)]
+#[error("{message}")]
+pub struct FrontendError {
+    pub message: String,
+    pub span: Span,
+}
+
+impl FrontendError {
+    pub fn new(message: impl Into<String>, span: Span) -> Self {
+        Self {
+            message: message.into(),
+            span,
+        }
+    }
+}
+
+/// A source language read into a [`Program`].
+pub trait Frontend: Send + Sync {
+    /// Stable name used to select the frontend.
+    fn name(&self) -> &'static str;
+
+    /// File extensions the frontend reads, without the dot.
+    fn extensions(&self) -> &'static [&'static str];
+
+    fn parse(&self, input: &str) -> Result<Program, FrontendError>;
+}
+
+/// Names of the built-in frontends; the first is the default.
+pub const BUILTIN_FRONTENDS: &[&str] = &["miniobli", "json"];
+
+/// Look up a built-in frontend by name.
+pub fn builtin(name: &str) -> Option<Box<dyn Frontend>> {
+    match name {
+        "miniobli" => Some(Box::new(MiniObli)),
+        "json" => Some(Box::new(Json)),
+        _ => None,
+    }
+}
+
+/// MiniObli source, through [`Lexer`] and [`Parser`].
+pub struct MiniObli;
+
+impl Frontend for MiniObli {
+    fn name(&self) -> &'static str {
+        "miniobli"
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &[SOURCE_EXTENSION]
+    }
+
+    fn parse(&self, input: &str) -> Result<Program, FrontendError> {
+        let (tokens, spans) = Lexer::new(input)
+            .tokenize()
+            .map_err(|e| FrontendError::new(e.to_string(), e.span()))?;
+        Parser::with_spans(&tokens, &spans)
+            .parse_program()
+            .map_err(|e| FrontendError::new(e.to_string(), e.span()))
+    }
+}
+
+/// Programs in the [JSON schema](crate::json).
+pub struct Json;
+
+impl Frontend for Json {
+    fn name(&self) -> &'static str {
+        "json"
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &["json"]
+    }
+
+    fn parse(&self, input: &str) -> Result<Program, FrontendError> {
+        Program::from_json(input).map_err(|e| FrontendError::new(e.to_string(), Span::default()))
+    }
+}
+
+/// The frontends available to a tool, looked up by name or extension.
+#[derive(Default)]
+pub struct Registry {
+    frontends: Vec<Box<dyn Frontend>>,
+}
+
+impl Registry {
+    /// An empty registry.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// All built-in frontends.
+    pub fn standard() -> Self {
+        let mut registry = Self::new();
+        for name in BUILTIN_FRONTENDS {
+            registry.frontends.extend(builtin(name));
+        }
+        registry
+    }
+
+    /// Add a frontend; it replaces an earlier one of the same name.
+    pub fn add(&mut self, frontend: Box<dyn Frontend>) {
+        self.frontends.retain(|f| f.name() != frontend.name());
+        self.frontends.push(frontend);
+    }
+
+    /// Add a frontend, builder style.
+    pub fn with(mut self, frontend: impl Frontend + 'static) -> Self {
+        self.add(Box::new(frontend));
+        self
+    }
+
+    pub fn get(&self, name: &str) -> Option<&dyn Frontend> {
+        self.frontends.iter().find(|f| f.name() == name).map(|f| &**f)
+    }
+
+    /// The frontend reading files with `path`'s extension; the most
+    /// recently added wins when several claim it.
+    pub fn for_path(&self, path: &Path) -> Option<&dyn Frontend> {
+        let extension = path.extension()?.to_str()?;
+        let mut frontends = self.frontends.iter().rev();
+        frontends
+            .find(|f| f.extensions().contains(&extension))
+            .map(|f| &**f)
+    }
+
+    pub fn names(&self) -> Vec<&'static str> {
+        self.frontends.iter().map(|f| f.name()).collect()
+    }
+
+    /// `text`, the contents of `path`, as MiniObli source: unchanged unless
+    /// another frontend claims the file's extension, which then reads it
+    /// into a program that is printed back.
+    pub fn to_source(&self, path: &Path, text: &str) -> Result<String, FrontendError> {
+        match self.for_path(path) {
+            Some(frontend) if frontend.name() != MiniObli.name() => {
+                Ok(frontend.parse(text)?.to_string())
+            }
+            _ => Ok(text.to_string()),
+        }
+    }
+}
+
+impl fmt::Debug for Registry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.names()).finish()
+    }
+}
+
+/// A parameter or result type: a base type and its secrecy.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KernelType {
+    pub ty: Type,
+    pub secret: bool,
+}
+
+impl KernelType {
+    /// The type in the emitted Rust, e.g. `Secret<i64>`.
+    fn rust(&self) -> String {
+        format!("{}<{}>", self.wrapper(), self.base())
+    }
+
+    fn wrapper(&self) -> &'static str {
+        if self.secret {
+            "Secret"
+        } else {
+            "Pub"
+        }
+    }
+
+    fn base(&self) -> &'static str {
+        match self.ty {
+            Type::Int => "i64",
+            Type::Bool => "bool",
+            Type::Sized(width) => width.name(),
+            Type::Array(..) | Type::Tuple(_) | Type::Option(_) | Type::Outputs => {
+                unreachable!("kernel types are scalars")
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Kernel {
+    pub name: String,
+    pub params: Vec<(String, KernelType)>,
+    pub ret: KernelType,
+    pub body: Expr,
+    /// Span of the function's signature.
+    pub span: Span,
+}
+
+impl Kernel {
+    /// The body with each parameter bound by a `let` to a placeholder of
+    /// its type and secrecy, in parameter order.
+    fn bound_body(&self) -> Expr {
+        self.params.iter().rev().fold(self.body.clone(), |body, (name, ty)| {
+            let value = match ty.ty {
+                Type::Int => Expr::Int(0),
+                Type::Bool => Expr::Bool(false),
+                Type::Sized(width) => Expr::Sized(0, width),
+                Type::Array(..) | Type::Tuple(_) | Type::Option(_) | Type::Outputs => {
+                    unreachable!("kernel types are scalars")
+                }
+            };
+            let value = if ty.secret {
+                Expr::Secret(Box::new(value))
+            } else {
+                value
+            };
+            Expr::Let {
+                name: Symbol::from(name),
+                ty: None,
+                mutable: false,
+                value: Box::new(value),
+                body: Box::new(body),
+            }
+        })
+    }
+
+    /// Check that the body typechecks with the parameters bound, has the
+    /// declared result type, and is not secret where a public result is
+    /// declared.
+    pub fn check(&self) -> Result<(), FrontendError> {
+        let bound = self.bound_body();
+        let ty = typecheck(&bound).map_err(|errors| {
+            let messages: Vec<_> = errors.iter().map(|e| e.to_string()).collect();
+            FrontendError::new(messages.join("; "), self.span)
+        })?;
+        if ty != self.ret.ty {
+            let message = format!("`{}` returns {} but is declared {}", self.name, ty, self.ret.ty);
+            return Err(FrontendError::new(message, self.span));
+        }
+        if !self.ret.secret && to_oblivious(&bound).is_secret() {
+            let message = format!("`{}` returns a secret value but is declared public", self.name);
+            return Err(FrontendError::new(message, self.span));
+        }
+        Ok(())
+    }
+
+    /// The kernel as a constant-time Rust function with the passes of
+    /// `config` applied, preceded by the runtime prelude if the options ask
+    /// for it.
+    pub fn emit(&self, config: &Config) -> Result<String, FrontendError> {
+        self.check()?;
+        let mut body = to_oblivious(&self.bound_body());
+        for _ in &self.params {
+            body = match body {
+                ObliExpr::Let { body, .. } => *body,
+                _ => unreachable!("every parameter is bound with a let"),
+            };
+        }
+        let mut body = config.pipeline().run(body);
+        if self.ret.secret && !body.is_secret() {
+            body = mark_as_secret(body);
+        }
+        let params: Vec<_> = self
+            .params
+            .iter()
+            .map(|(name, ty)| (Symbol::from(name), ty.secret, ty.base()))
+            .collect();
+        let mut out = String::new();
+        if config.emit.prelude {
+            out.push_str(RUNTIME_PRELUDE);
+            out.push('\n');
+        }
+        out.push_str(&emit_function(&self.name, &params, &self.ret.rust(), &body));
+        Ok(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::run::run_emitted;
+
+    #[test]
+    fn test_emitted_kernel_runs() {
+        // fn pick(flag: Secret<bool>, n: i64) -> Secret<i64> { if flag { n } else { 1 } }
+        let int = |secret| KernelType { ty: Type::Int, secret };
+        let kernel = Kernel {
+            name: "pick".to_string(),
+            params: vec![
+                ("flag".to_string(), KernelType { ty: Type::Bool, secret: true }),
+                ("n".to_string(), int(false)),
+            ],
+            ret: int(true),
+            body: Expr::If {
+                cond: Box::new(Expr::Var("flag".into())),
+                then_branch: Box::new(Expr::Var("n".into())),
+                else_branch: Box::new(Expr::Int(1)),
+            },
+            span: Span::default(),
+        };
+        let code = kernel.emit(&Config::default()).unwrap() +
+            "fn main() {\n    let r = pick(Secret::new(true), Pub::new(7));\n    \
+             println!(\"{:?}\", r.reveal(&RevealToken::output_section()));\n}\n";
+        assert_eq!(run_emitted(&code).unwrap(), "7\n");
+
+        let public = Kernel { ret: int(false), ..kernel };
+        let err = public.check().unwrap_err();
+        assert_eq!(err.message, "`pick` returns a secret value but is declared public");
+    }
+
+    struct Upper;
+
+    /// MiniObli written in capitals.
+    impl Frontend for Upper {
+        fn name(&self) -> &'static str {
+            "upper"
+        }
+
+        fn extensions(&self) -> &'static [&'static str] {
+            &["OBLI"]
+        }
+
+        fn parse(&self, input: &str) -> Result<Program, FrontendError> {
+            MiniObli.parse(&input.to_lowercase())
+        }
+    }
+
+    #[test]
+    fn test_registry_selects_frontends() {
+        let registry = Registry::standard().with(Upper);
+        assert_eq!(registry.names(), ["miniobli", "json", "upper"]);
+        let from_source = registry.get("miniobli").unwrap().parse("secret(6) * 7").unwrap();
+        let json = r#"{"version": 1, "body": {"binop": "*", "left": {"secret": 6}, "right": 7}}"#;
+        let frontend = registry.for_path(Path::new("kernel.json")).unwrap();
+        assert_eq!(frontend.parse(json).unwrap(), from_source);
+        let frontend = registry.for_path(Path::new("KERNEL.OBLI")).unwrap();
+        assert_eq!(frontend.parse("SECRET(6) * 7").unwrap(), from_source);
+        assert!(registry.for_path(Path::new("kernel.c")).is_none());
+        let standard = Registry::standard();
+        let frontend = standard.for_path(Path::new("examples/simple.mobli"));
+        assert_eq!(frontend.map(|f| f.name()), Some("miniobli"));
+    }
+
+    #[test]
+    fn test_to_source_prints_other_languages_back() {
+        let registry = Registry::standard().with(Upper);
+        let source = "secret(6) * 7";
+        assert_eq!(registry.to_source(Path::new("a.mobli"), source).unwrap(), source);
+        assert_eq!(registry.to_source(Path::new("a.txt"), source).unwrap(), source);
+        let json = r#"{"version": 1, "body": {"binop": "*", "left": {"secret": 6}, "right": 7}}"#;
+        assert_eq!(registry.to_source(Path::new("a.json"), json).unwrap(), source);
+        let upper = registry.to_source(Path::new("a.OBLI"), "LET X = 1 X").unwrap();
+        assert_eq!(upper, "let x = 1;\nx");
+        assert!(registry.to_source(Path::new("a.json"), "{").is_err());
+    }
+
+    #[test]
+    fn test_miniobli_errors_keep_spans() {
+        let err = MiniObli.parse("let x = 1 +").unwrap_err();
+        assert_eq!(err.span.start, 11);
+        assert!(builtin("c").is_none());
+    }
+}
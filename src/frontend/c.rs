@@ -0,0 +1,939 @@
+// SPDX-License-Identifier: MIT OR AGPL-3.0-or-later
+// SPDX-FileCopyrightText: 2024-2025 hyperpolymath
+
+//! A constant-time C subset, for porting reference implementations.
+//!
+//! ```c
+//! __secret int mul_clamp(__secret int k, int limit) {
+//!     int acc = 0;
+//!     for (int i = 0; i < 4; i++) {
+//!         acc += k;
+//!     }
+//!     if (acc > limit) {
+//!         acc = limit;
+//!     }
+//!     return acc;
+//! }
+//! ```
+//!
+//! One function whose parameters and result are `int` or `bool` (also
+//! `_Bool`), made secret by a leading `__secret`. `int` is read as
+//! MiniObli's 64-bit integer. Statements are declarations with an
+//! initializer, assignments (`=`, `+=` and the like, `++`, `--`),
+//! `if`/`else` with braced bodies, `for` loops over literal bounds, which
+//! are unrolled, and a final `return`. Expressions are literals,
+//! variables, parentheses, `?:`, the arithmetic, bitwise, shift,
+//! comparison and logical operators, and unary `-` and `!`. `int` shifts
+//! take the amount modulo 64, where C leaves larger ones undefined.
+//!
+//! Each assignment becomes a fresh `let`, and a variable assigned in an
+//! `if` is merged after it by a conditional on the branch condition, so
+//! the body lowers to plain MiniObli.
+
+use super::{FrontendError, Kernel, KernelType};
+use crate::ast::{BinOp, Expr, UnaryOp};
+use crate::span::Span;
+use crate::symbol::Symbol;
+use crate::typeck::Type;
+use std::collections::{BTreeMap, HashSet};
+use std::ops::Range;
+
+/// Iterations a single `for` loop may unroll to.
+pub const MAX_UNROLL: i64 = 4096;
+
+/// Keywords of the subset.
+const KEYWORDS: &[&str] = &[
+    "__secret", "int", "bool", "_Bool", "if", "else", "for", "return", "true", "false", "void",
+];
+
+/// C keywords outside the subset.
+const UNSUPPORTED_KEYWORDS: &[&str] = &[
+    "while", "do", "switch", "case", "default", "goto", "break", "continue", "char", "short",
+    "long", "unsigned", "signed", "float", "double", "const", "volatile", "static", "extern",
+    "struct", "union", "enum", "typedef", "sizeof",
+];
+
+/// Names the emitted Rust or MiniObli cannot bind.
+const RESERVED: &[&str] = &[
+    "_", "as", "async", "await", "box", "break", "const", "continue", "crate", "dyn", "else",
+    "enum", "extern", "false", "fn", "for", "gen", "if", "impl", "in", "let", "loop", "match",
+    "mod", "move", "mut", "pub", "ref", "return", "self", "Self", "static", "struct", "super",
+    "trait", "true", "try", "type", "unsafe", "use", "where", "while", "yield", "then", "elif",
+    "secret", "lookup", "sort", "saturating", "outputs", "and", "or", "not", "assert", "clamp",
+    "in_range", "to_int", "to_bool", "some", "none", "unwrap_or", "pow", "map", "fold",
+];
+
+const PUNCTS: &[&str] = &[
+    "<<=", ">>=", "==", "!=", "<=", ">=", "&&", "||", "++", "--", "+=", "-=", "*=", "/=", "%=",
+    "&=", "|=", "^=", "<<", ">>", "+", "-", "*", "/", "%", "<", ">", "=", "!", "?", ":", ";", ",",
+    "(", ")", "{", "}", "[", "]", "&", "|", "^", "~",
+];
+
+/// Parse the single function in `source`.
+pub fn parse_kernel(source: &str) -> Result<Kernel, FrontendError> {
+    let tokens = tokenize(source)?;
+    let mut parser = Parser {
+        tokens: &tokens,
+        pos: 0,
+        scopes: vec![Vec::new()],
+    };
+    let kernel = lower(parser.function()?);
+    kernel.check()?;
+    Ok(kernel)
+}
+
+fn unsupported(span: Span, what: &str) -> FrontendError {
+    FrontendError::new(format!("unsupported C syntax: {}", what), span)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Ident(String),
+    Int(i64),
+    Punct(&'static str),
+    Eof,
+}
+
+impl Token {
+    fn describe(&self) -> String {
+        match self {
+            Token::Ident(name) => format!("`{}`", name),
+            Token::Int(n) => format!("`{}`", n),
+            Token::Punct(p) => format!("`{}`", p),
+            Token::Eof => "end of input".to_string(),
+        }
+    }
+}
+
+fn tokenize(source: &str) -> Result<Vec<(Token, Span)>, FrontendError> {
+    let mut tokens = Vec::new();
+    let mut pos = 0;
+    while let Some(c) = source[pos..].chars().next() {
+        let rest = &source[pos..];
+        let line_len = rest.find('\n').unwrap_or(rest.len());
+        if c.is_ascii_whitespace() {
+            pos += 1;
+            continue;
+        }
+        if rest.starts_with("//") {
+            pos += line_len;
+            continue;
+        }
+        if let Some(comment) = rest.strip_prefix("/*") {
+            let len = comment.find("*/").ok_or_else(|| {
+                FrontendError::new("unterminated comment", Span::new(pos, source.len()))
+            })?;
+            pos += len + 4;
+            continue;
+        }
+        let start = pos;
+        let token = if c.is_ascii_alphabetic() || c == '_' {
+            let is_word = |c: char| c.is_ascii_alphanumeric() || c == '_';
+            pos += rest.find(|c| !is_word(c)).unwrap_or(rest.len());
+            Token::Ident(source[start..pos].to_string())
+        } else if c.is_ascii_digit() {
+            pos += rest.find(|c: char| !c.is_ascii_alphanumeric()).unwrap_or(rest.len());
+            let text = &source[start..pos];
+            let value = match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+                Some(hex) => i64::from_str_radix(hex, 16).ok(),
+                None if text.len() > 1 && text.starts_with('0') => None,
+                None => text.parse().ok(),
+            };
+            let invalid = || {
+                let message = format!("invalid integer literal `{}`", text);
+                FrontendError::new(message, Span::new(start, pos))
+            };
+            Token::Int(value.ok_or_else(invalid)?)
+        } else if c == '#' {
+            let span = Span::new(pos, pos + line_len);
+            return Err(unsupported(span, "preprocessor directives"));
+        } else {
+            let punct = PUNCTS.iter().find(|p| rest.starts_with(**p)).ok_or_else(|| {
+                let span = Span::new(pos, pos + c.len_utf8());
+                FrontendError::new(format!("unexpected character `{}`", c), span)
+            })?;
+            pos += punct.len();
+            Token::Punct(punct)
+        };
+        tokens.push((token, Span::new(start, pos)));
+    }
+    tokens.push((Token::Eof, Span::new(pos, pos)));
+    Ok(tokens)
+}
+
+/// A parsed function, its names still those of the C source.
+struct Function {
+    name: String,
+    params: Vec<(String, KernelType)>,
+    ret: KernelType,
+    body: Vec<Stmt>,
+    result: Expr,
+    span: Span,
+}
+
+enum Stmt {
+    Decl {
+        name: String,
+        secret: bool,
+        value: Expr,
+    },
+    Assign {
+        name: String,
+        value: Expr,
+    },
+    If {
+        cond: Expr,
+        then_body: Vec<Stmt>,
+        else_body: Vec<Stmt>,
+    },
+    For {
+        counter: String,
+        range: Range<i64>,
+        body: Vec<Stmt>,
+    },
+}
+
+/// A variable in scope while parsing.
+struct Local {
+    name: String,
+    /// Whether it is a `for` loop counter, which the body may not assign.
+    counter: bool,
+}
+
+struct Parser<'t> {
+    tokens: &'t [(Token, Span)],
+    pos: usize,
+    scopes: Vec<Vec<Local>>,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> &Token {
+        &self.tokens[self.pos].0
+    }
+
+    fn span(&self) -> Span {
+        self.tokens[self.pos].1
+    }
+
+    fn bump(&mut self) -> Span {
+        let span = self.span();
+        if self.pos + 1 < self.tokens.len() {
+            self.pos += 1;
+        }
+        span
+    }
+
+    fn at(&self, punct: &str) -> bool {
+        matches!(self.peek(), Token::Punct(p) if *p == punct)
+    }
+
+    fn at_keyword(&self, keyword: &str) -> bool {
+        matches!(self.peek(), Token::Ident(name) if name == keyword)
+    }
+
+    fn eat(&mut self, punct: &str) -> bool {
+        let found = self.at(punct);
+        if found {
+            self.bump();
+        }
+        found
+    }
+
+    fn eat_keyword(&mut self, keyword: &str) -> bool {
+        let found = self.at_keyword(keyword);
+        if found {
+            self.bump();
+        }
+        found
+    }
+
+    fn expect(&mut self, punct: &str) -> Result<Span, FrontendError> {
+        if self.at(punct) {
+            Ok(self.bump())
+        } else {
+            Err(self.expected(&format!("`{}`", punct)))
+        }
+    }
+
+    fn expected(&self, what: &str) -> FrontendError {
+        let message = format!("expected {}, found {}", what, self.peek().describe());
+        FrontendError::new(message, self.span())
+    }
+
+    fn name(&mut self) -> Result<(String, Span), FrontendError> {
+        match self.peek() {
+            Token::Ident(name)
+                if !KEYWORDS.contains(&name.as_str())
+                    && !UNSUPPORTED_KEYWORDS.contains(&name.as_str()) =>
+            {
+                let name = name.clone();
+                Ok((name, self.bump()))
+            }
+            _ => Err(self.expected("a name")),
+        }
+    }
+
+    fn declare(&mut self, name: &str, span: Span, counter: bool) -> Result<(), FrontendError> {
+        let scope = self.scopes.last_mut().expect("parsing inside a scope");
+        if scope.iter().any(|local| local.name == name) {
+            let message = format!("`{}` is already declared in this scope", name);
+            return Err(FrontendError::new(message, span));
+        }
+        scope.push(Local {
+            name: name.to_string(),
+            counter,
+        });
+        Ok(())
+    }
+
+    fn lookup(&self, name: &str, span: Span) -> Result<&Local, FrontendError> {
+        let mut locals = self.scopes.iter().rev().flatten();
+        locals
+            .find(|local| local.name == name)
+            .ok_or_else(|| FrontendError::new(format!("undeclared variable `{}`", name), span))
+    }
+
+    /// `[__secret] int`, `bool` or `_Bool`, if a type starts here.
+    fn ty(&mut self) -> Result<Option<KernelType>, FrontendError> {
+        let secret = self.eat_keyword("__secret");
+        let ty = match self.peek() {
+            Token::Ident(name) if name == "int" => Type::Int,
+            Token::Ident(name) if name == "bool" || name == "_Bool" => Type::Bool,
+            _ if secret => return Err(self.expected("a type")),
+            _ => return Ok(None),
+        };
+        self.bump();
+        Ok(Some(KernelType { ty, secret }))
+    }
+
+    fn function(&mut self) -> Result<Function, FrontendError> {
+        let start = self.span();
+        let ret = self.ty()?.ok_or_else(|| self.expected("a result type"))?;
+        let (name, _) = self.name()?;
+        self.expect("(")?;
+        let mut params = Vec::new();
+        if !self.eat_keyword("void") && !self.at(")") {
+            loop {
+                let ty = self.ty()?.ok_or_else(|| self.expected("a parameter type"))?;
+                let (param, span) = self.name()?;
+                if RESERVED.contains(&param.as_str()) {
+                    let message = format!("`{}` is reserved and cannot name a parameter", param);
+                    return Err(FrontendError::new(message, span));
+                }
+                self.declare(&param, span, false)?;
+                params.push((param, ty));
+                if !self.eat(",") {
+                    break;
+                }
+            }
+        }
+        let end = self.expect(")")?;
+        self.expect("{")?;
+        let mut body = Vec::new();
+        while !self.eat_keyword("return") {
+            if self.at("}") {
+                return Err(self.expected("a final `return`"));
+            }
+            body.push(self.stmt()?);
+        }
+        let result = self.expr()?;
+        self.expect(";")?;
+        self.expect("}")?;
+        if *self.peek() != Token::Eof {
+            let span = self.span();
+            return Err(FrontendError::new("only a single function is supported", span));
+        }
+        Ok(Function {
+            name,
+            params,
+            ret,
+            body,
+            result,
+            span: start.merge(end),
+        })
+    }
+
+    fn block(&mut self) -> Result<Vec<Stmt>, FrontendError> {
+        self.expect("{")?;
+        self.scopes.push(Vec::new());
+        let mut stmts = Vec::new();
+        while !self.eat("}") {
+            stmts.push(self.stmt()?);
+        }
+        self.scopes.pop();
+        Ok(stmts)
+    }
+
+    fn stmt(&mut self) -> Result<Stmt, FrontendError> {
+        let span = self.span();
+        if let Some(ty) = self.ty()? {
+            let (name, name_span) = self.name()?;
+            if !self.eat("=") {
+                return Err(self.expected("an initializer"));
+            }
+            let value = self.expr()?;
+            self.expect(";")?;
+            self.declare(&name, name_span, false)?;
+            return Ok(Stmt::Decl {
+                name,
+                secret: ty.secret,
+                value,
+            });
+        }
+        match self.peek() {
+            Token::Ident(keyword) if keyword == "if" => self.if_stmt(),
+            Token::Ident(keyword) if keyword == "for" => self.for_stmt(),
+            Token::Ident(keyword) if keyword == "return" => Err(FrontendError::new(
+                "`return` is only supported as the last statement of the function",
+                span,
+            )),
+            Token::Ident(keyword) if UNSUPPORTED_KEYWORDS.contains(&keyword.as_str()) => {
+                Err(unsupported(span, &format!("`{}`", keyword)))
+            }
+            Token::Ident(_) => self.assignment(),
+            _ => Err(self.expected("a statement")),
+        }
+    }
+
+    fn assignment(&mut self) -> Result<Stmt, FrontendError> {
+        let (name, span) = self.name()?;
+        if self.lookup(&name, span)?.counter {
+            let message = format!("the loop counter `{}` cannot be assigned", name);
+            return Err(FrontendError::new(message, span));
+        }
+        let op = match self.peek() {
+            Token::Punct("=") => None,
+            Token::Punct("+=" | "++") => Some(BinOp::Add),
+            Token::Punct("-=" | "--") => Some(BinOp::Sub),
+            Token::Punct("*=") => Some(BinOp::Mul),
+            Token::Punct("/=") => Some(BinOp::Div),
+            Token::Punct("%=") => Some(BinOp::Mod),
+            Token::Punct("&=") => Some(BinOp::BitAnd),
+            Token::Punct("|=") => Some(BinOp::BitOr),
+            Token::Punct("^=") => Some(BinOp::BitXor),
+            Token::Punct("<<=") => Some(BinOp::Shl),
+            Token::Punct(">>=") => Some(BinOp::Shr),
+            _ => return Err(self.expected("an assignment")),
+        };
+        let step = self.at("++") || self.at("--");
+        self.bump();
+        let rhs = if step { Expr::Int(1) } else { self.expr()? };
+        self.expect(";")?;
+        let value = match op {
+            None => rhs,
+            Some(op) => Expr::BinOp {
+                op,
+                left: Box::new(Expr::Var(Symbol::from(&name))),
+                right: Box::new(rhs),
+            },
+        };
+        Ok(Stmt::Assign { name, value })
+    }
+
+    fn if_stmt(&mut self) -> Result<Stmt, FrontendError> {
+        self.bump();
+        self.expect("(")?;
+        let cond = self.expr()?;
+        self.expect(")")?;
+        let then_body = self.block()?;
+        let else_body = if !self.eat_keyword("else") {
+            Vec::new()
+        } else if self.at_keyword("if") {
+            vec![self.if_stmt()?]
+        } else {
+            self.block()?
+        };
+        Ok(Stmt::If {
+            cond,
+            then_body,
+            else_body,
+        })
+    }
+
+    /// `for (int i = a; i < b; i++) { ... }`, for integer literals `a` and
+    /// `b`; `<=`, `++i` and `i += 1` are accepted too.
+    fn for_stmt(&mut self) -> Result<Stmt, FrontendError> {
+        let start = self.bump();
+        let shape = |span| {
+            let message = "expected a loop `for (int i = a; i < b; i++)` with literal bounds";
+            FrontendError::new(message, span)
+        };
+        self.expect("(")?;
+        let span = self.span();
+        if self.ty()? != Some(KernelType { ty: Type::Int, secret: false }) {
+            return Err(shape(span));
+        }
+        let (counter, counter_span) = self.name()?;
+        self.expect("=")?;
+        let from = self.literal()?;
+        self.expect(";")?;
+        let span = self.span();
+        let inclusive = match (self.name()?.0 == counter, self.eat("<"), self.eat("<=")) {
+            (true, true, _) => false,
+            (true, _, true) => true,
+            _ => return Err(shape(span)),
+        };
+        let to = self.literal()?;
+        self.expect(";")?;
+        let span = self.span();
+        let step = if self.eat("++") {
+            self.name()?.0 == counter
+        } else {
+            self.name()?.0 == counter
+                && (self.eat("++") || (self.eat("+=") && self.literal()? == 1))
+        };
+        if !step {
+            return Err(shape(span));
+        }
+        let end = self.expect(")")?;
+        let to = if inclusive { to.saturating_add(1) } else { to };
+        let count = (i128::from(to) - i128::from(from)).max(0);
+        if count > i128::from(MAX_UNROLL) {
+            let message = format!(
+                "the loop runs {} times; at most {} iterations are unrolled",
+                count, MAX_UNROLL
+            );
+            return Err(FrontendError::new(message, start.merge(end)));
+        }
+        self.scopes.push(Vec::new());
+        self.declare(&counter, counter_span, true)?;
+        let body = self.block()?;
+        self.scopes.pop();
+        Ok(Stmt::For {
+            counter,
+            range: from..to,
+            body,
+        })
+    }
+
+    /// An integer literal, possibly negated.
+    fn literal(&mut self) -> Result<i64, FrontendError> {
+        let negative = self.eat("-");
+        match *self.peek() {
+            Token::Int(n) => {
+                self.bump();
+                Ok(if negative { -n } else { n })
+            }
+            _ => Err(self.expected("an integer literal")),
+        }
+    }
+
+    fn expr(&mut self) -> Result<Expr, FrontendError> {
+        let cond = self.binary(0)?;
+        if !self.eat("?") {
+            return Ok(cond);
+        }
+        let then_branch = self.expr()?;
+        self.expect(":")?;
+        let else_branch = self.expr()?;
+        Ok(Expr::If {
+            cond: Box::new(cond),
+            then_branch: Box::new(then_branch),
+            else_branch: Box::new(else_branch),
+        })
+    }
+
+    /// Operators binding at least as tightly as `min`, by C precedence.
+    fn binary(&mut self, min: u8) -> Result<Expr, FrontendError> {
+        let mut left = self.unary()?;
+        loop {
+            let (op, precedence) = match self.peek() {
+                Token::Punct("||") => (BinOp::Or, 0),
+                Token::Punct("&&") => (BinOp::And, 1),
+                Token::Punct("|") => (BinOp::BitOr, 2),
+                Token::Punct("^") => (BinOp::BitXor, 3),
+                Token::Punct("&") => (BinOp::BitAnd, 4),
+                Token::Punct("==") => (BinOp::Eq, 5),
+                Token::Punct("!=") => (BinOp::Ne, 5),
+                Token::Punct("<") => (BinOp::Lt, 6),
+                Token::Punct("<=") => (BinOp::Le, 6),
+                Token::Punct(">") => (BinOp::Gt, 6),
+                Token::Punct(">=") => (BinOp::Ge, 6),
+                Token::Punct("<<") => (BinOp::Shl, 7),
+                Token::Punct(">>") => (BinOp::Shr, 7),
+                Token::Punct("+") => (BinOp::Add, 8),
+                Token::Punct("-") => (BinOp::Sub, 8),
+                Token::Punct("*") => (BinOp::Mul, 9),
+                Token::Punct("/") => (BinOp::Div, 9),
+                Token::Punct("%") => (BinOp::Mod, 9),
+                _ => break,
+            };
+            if precedence < min {
+                break;
+            }
+            self.bump();
+            let right = self.binary(precedence + 1)?;
+            left = Expr::BinOp {
+                op,
+                left: Box::new(left),
+                right: Box::new(right),
+            };
+        }
+        Ok(left)
+    }
+
+    fn unary(&mut self) -> Result<Expr, FrontendError> {
+        let op = match self.peek() {
+            Token::Punct("-") => UnaryOp::Neg,
+            Token::Punct("!") => UnaryOp::Not,
+            Token::Punct(p @ ("~" | "++" | "--" | "&" | "*")) => {
+                return Err(unsupported(self.span(), &format!("operator `{}`", p)));
+            }
+            _ => return self.primary(),
+        };
+        self.bump();
+        Ok(Expr::UnaryOp {
+            op,
+            expr: Box::new(self.unary()?),
+        })
+    }
+
+    fn primary(&mut self) -> Result<Expr, FrontendError> {
+        match self.peek().clone() {
+            Token::Int(n) => {
+                self.bump();
+                Ok(Expr::Int(n))
+            }
+            Token::Ident(name) if name == "true" || name == "false" => {
+                self.bump();
+                Ok(Expr::Bool(name == "true"))
+            }
+            Token::Punct("(") => {
+                self.bump();
+                let expr = self.expr()?;
+                self.expect(")")?;
+                Ok(expr)
+            }
+            Token::Ident(_) => {
+                let (name, span) = self.name()?;
+                if self.at("(") {
+                    return Err(unsupported(span, "function calls"));
+                }
+                self.lookup(&name, span)?;
+                Ok(Expr::Var(name.into()))
+            }
+            _ => Err(self.expected("an expression")),
+        }
+    }
+}
+
+/// What a C variable holds at a point of the body: the MiniObli variable
+/// bound to its latest value, or a loop counter's constant.
+#[derive(Debug, Clone, PartialEq)]
+struct Slot {
+    value: Expr,
+    secret: bool,
+}
+
+struct Lowering {
+    scopes: Vec<BTreeMap<String, Slot>>,
+    used: HashSet<String>,
+}
+
+fn lower(function: Function) -> Kernel {
+    let mut lowering = Lowering {
+        scopes: vec![BTreeMap::new()],
+        used: HashSet::new(),
+    };
+    for (name, ty) in &function.params {
+        lowering.used.insert(name.clone());
+        let slot = Slot {
+            value: Expr::Var(name.into()),
+            secret: ty.secret,
+        };
+        lowering.scopes[0].insert(name.clone(), slot);
+    }
+    let mut bindings = Vec::new();
+    lowering.stmts(&function.body, &mut bindings);
+    let result = lowering.rename(&function.result);
+    Kernel {
+        name: function.name,
+        params: function.params,
+        ret: function.ret,
+        body: wrap(&bindings, result),
+        span: function.span,
+    }
+}
+
+/// `bindings` as nested `let`s around `body`.
+fn wrap(bindings: &[(Symbol, Expr)], body: Expr) -> Expr {
+    bindings.iter().rev().fold(body, |body, (name, value)| Expr::Let {
+        name: *name,
+        ty: None,
+        mutable: false,
+        value: Box::new(value.clone()),
+        body: Box::new(body),
+    })
+}
+
+fn secret_if(secret: bool, value: Expr) -> Expr {
+    if secret {
+        Expr::Secret(Box::new(value))
+    } else {
+        value
+    }
+}
+
+impl Lowering {
+    fn slot(&mut self, name: &str) -> &mut Slot {
+        let mut scopes = self.scopes.iter_mut().rev();
+        scopes
+            .find_map(|scope| scope.get_mut(name))
+            .expect("the parser resolved every name")
+    }
+
+    /// `base`, or `base_1`, `base_2`, ... if that is taken.
+    fn fresh(&mut self, base: &str) -> String {
+        let mut name = base.to_string();
+        let mut n = 0;
+        while self.used.contains(&name) || RESERVED.contains(&name.as_str()) {
+            n += 1;
+            name = format!("{}_{}", base, n);
+        }
+        self.used.insert(name.clone());
+        name
+    }
+
+    fn bind(&mut self, base: &str, value: Expr, bindings: &mut Vec<(Symbol, Expr)>) -> Expr {
+        let name = Symbol::from(self.fresh(base));
+        bindings.push((name, value));
+        Expr::Var(name)
+    }
+
+    /// `expr` with each C variable replaced by what it holds.
+    fn rename(&mut self, expr: &Expr) -> Expr {
+        let mut rename = |expr| Box::new(self.rename(expr));
+        match expr {
+            Expr::Int(_) | Expr::Sized(..) | Expr::Bool(_) | Expr::Input { .. } => expr.clone(),
+            Expr::Var(name) => self.slot(name).value.clone(),
+            Expr::Secret(inner) => Expr::Secret(rename(inner)),
+            Expr::Declassify(inner) => Expr::Declassify(rename(inner)),
+            Expr::BinOp { op, left, right } => Expr::BinOp {
+                op: op.clone(),
+                left: rename(left),
+                right: rename(right),
+            },
+            Expr::UnaryOp { op, expr } => Expr::UnaryOp {
+                op: op.clone(),
+                expr: rename(expr),
+            },
+            Expr::Cast { expr, ty } => Expr::Cast {
+                expr: rename(expr),
+                ty: *ty,
+            },
+            Expr::If {
+                cond,
+                then_branch,
+                else_branch,
+            } => Expr::If {
+                cond: rename(cond),
+                then_branch: rename(then_branch),
+                else_branch: rename(else_branch),
+            },
+            Expr::Let { .. }
+            | Expr::Assign { .. }
+            | Expr::Array(_)
+            | Expr::Tuple(_)
+            | Expr::LetTuple { .. }
+            | Expr::Bytes(_)
+            | Expr::Sort(_)
+            | Expr::SwapIf { .. }
+            | Expr::Clamp { .. }
+            | Expr::InRange { .. }
+            | Expr::Pow { .. }
+            | Expr::Map { .. }
+            | Expr::Fold { .. }
+            | Expr::Some(_)
+            | Expr::None
+            | Expr::UnwrapOr { .. }
+            | Expr::Index { .. }
+            | Expr::Lookup { .. }
+            | Expr::Match { .. }
+            | Expr::Outputs(_)
+            | Expr::Fn { .. }
+            | Expr::Call { .. }
+            | Expr::Lambda { .. }
+            | Expr::Const { .. }
+            | Expr::Enum { .. }
+            | Expr::Assert { .. }
+            | Expr::For { .. }
+            | Expr::While { .. } => {
+                unreachable!("C expressions have no `let`, arrays, `lookup`, `outputs` or calls")
+            }
+        }
+    }
+
+    fn block(&mut self, body: &[Stmt], bindings: &mut Vec<(Symbol, Expr)>) {
+        self.scopes.push(BTreeMap::new());
+        self.stmts(body, bindings);
+        self.scopes.pop();
+    }
+
+    fn stmts(&mut self, stmts: &[Stmt], bindings: &mut Vec<(Symbol, Expr)>) {
+        for stmt in stmts {
+            match stmt {
+                Stmt::Decl {
+                    name,
+                    secret,
+                    value,
+                } => {
+                    let value = secret_if(*secret, self.rename(value));
+                    let value = self.bind(name, value, bindings);
+                    let slot = Slot {
+                        value,
+                        secret: *secret,
+                    };
+                    let scope = self.scopes.last_mut().expect("lowering inside a scope");
+                    scope.insert(name.clone(), slot);
+                }
+                Stmt::Assign { name, value } => {
+                    let secret = self.slot(name).secret;
+                    let value = secret_if(secret, self.rename(value));
+                    self.slot(name).value = self.bind(name, value, bindings);
+                }
+                Stmt::If {
+                    cond,
+                    then_body,
+                    else_body,
+                } => {
+                    let cond = self.rename(cond);
+                    let cond = self.bind("cond", cond, bindings);
+                    self.if_stmt(cond, then_body, else_body, bindings);
+                }
+                Stmt::For {
+                    counter,
+                    range,
+                    body,
+                } => {
+                    for i in range.clone() {
+                        let slot = Slot {
+                            value: Expr::Int(i),
+                            secret: false,
+                        };
+                        self.scopes.push(BTreeMap::from([(counter.clone(), slot)]));
+                        self.block(body, bindings);
+                        self.scopes.pop();
+                    }
+                }
+            }
+        }
+    }
+
+    /// Lower both branches and rebind each variable either assigns to a
+    /// conditional on `cond`, each side repeating its branch's `let`s.
+    fn if_stmt(
+        &mut self,
+        cond: Expr,
+        then_body: &[Stmt],
+        else_body: &[Stmt],
+        bindings: &mut Vec<(Symbol, Expr)>,
+    ) {
+        let before = self.scopes.clone();
+        let mut then_bindings = Vec::new();
+        self.block(then_body, &mut then_bindings);
+        let after_then = std::mem::replace(&mut self.scopes, before.clone());
+        let mut else_bindings = Vec::new();
+        self.block(else_body, &mut else_bindings);
+        let after_else = std::mem::replace(&mut self.scopes, before);
+        for (depth, scope) in after_then.iter().enumerate() {
+            for (name, then_slot) in scope {
+                let else_slot = &after_else[depth][name];
+                let old = &self.scopes[depth][name];
+                if *then_slot == *old && *else_slot == *old {
+                    continue;
+                }
+                let merged = Expr::If {
+                    cond: Box::new(cond.clone()),
+                    then_branch: Box::new(wrap(&then_bindings, then_slot.value.clone())),
+                    else_branch: Box::new(wrap(&else_bindings, else_slot.value.clone())),
+                };
+                let value = self.bind(name, merged, bindings);
+                self.scopes[depth].get_mut(name).expect("declared before the `if`").value = value;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interp::{eval_ast, Inputs, Value};
+
+    const MUL_CLAMP: &str = "__secret int mul_clamp(__secret int k, int limit) {\n\
+                             \x20   int acc = 0; /* running sum */\n\
+                             \x20   for (int i = 0; i < 4; i++) {\n\
+                             \x20       acc += k;\n\
+                             \x20   }\n\
+                             \x20   if (acc > limit) {\n\
+                             \x20       acc = limit;\n\
+                             \x20   }\n\
+                             \x20   return acc;\n\
+                             }\n";
+
+    #[test]
+    fn test_lowers_assignments_and_loops() {
+        let kernel = parse_kernel(MUL_CLAMP).unwrap();
+        assert_eq!(kernel.name, "mul_clamp");
+        assert_eq!(kernel.ret, KernelType { ty: Type::Int, secret: true });
+        let run = |k, limit| {
+            let inputs = Inputs::new()
+                .secret("k", Value::Int(k))
+                .public("limit", Value::Int(limit));
+            eval_ast(&kernel.body, &inputs).unwrap()
+        };
+        assert_eq!(run(2, 100).value, Value::Int(8));
+        assert_eq!(run(30, 100).value, Value::Int(100));
+        assert!(run(30, 100).secret);
+
+        let code = kernel.emit(&crate::Config::default()).unwrap();
+        assert!(code.contains("fn mul_clamp(k: Secret<i64>, limit: Pub<i64>) -> Secret<i64>"));
+        assert!(code.contains("ct_select("));
+    }
+
+    #[test]
+    fn test_branches_merge_per_variable() {
+        let source = "int f(int x) {\n    int a = 1;\n    int b = 2;\n    \
+                      if (x > 0) { a = x; } else if (x < -5) { b = x; }\n    \
+                      return a * b;\n}";
+        let kernel = parse_kernel(source).unwrap();
+        let run = |x| {
+            let inputs = Inputs::new().public("x", Value::Int(x));
+            eval_ast(&kernel.body, &inputs).unwrap().value
+        };
+        assert_eq!([run(3), run(-1), run(-7)], [6, 2, -7].map(Value::Int));
+    }
+
+    #[test]
+    fn test_bitwise_operators_follow_c_precedence() {
+        let source = "int f(int x) {\n    int h = x ^ x >> 3 & 7 | 1 << 4;\n    \
+                      h <<= 1;\n    h ^= 5;\n    return h;\n}";
+        let kernel = parse_kernel(source).unwrap();
+        let inputs = Inputs::new().public("x", Value::Int(13));
+        // (13 ^ ((13 >> 3) & 7)) | (1 << 4) is 28, then 56, then 61
+        assert_eq!(eval_ast(&kernel.body, &inputs).unwrap().value, Value::Int(61));
+    }
+
+    #[test]
+    fn test_rejects_outside_the_subset() {
+        let err = |source: &str| {
+            let err = parse_kernel(source).unwrap_err();
+            (err.message, source[err.span.start..err.span.end].to_string())
+        };
+        assert_eq!(
+            err("int f(int x) { while (x) { x--; } return x; }"),
+            ("unsupported C syntax: `while`".to_string(), "while".to_string())
+        );
+        assert_eq!(
+            err("int f(int x) { return ~x; }"),
+            ("unsupported C syntax: operator `~`".to_string(), "~".to_string())
+        );
+        assert_eq!(
+            err("int f(int x) { return y; }"),
+            ("undeclared variable `y`".to_string(), "y".to_string())
+        );
+        let (message, _) = err("int f(int n) { for (int i = 0; i < n; i++) { } return n; }");
+        assert_eq!(message, "expected an integer literal, found `n`");
+        let (message, _) = err("int f(__secret int k) { return k; }");
+        assert_eq!(message, "`f` returns a secret value but is declared public");
+    }
+}
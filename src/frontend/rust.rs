@@ -0,0 +1,293 @@
+// SPDX-License-Identifier: MIT OR AGPL-3.0-or-later
+// SPDX-FileCopyrightText: 2024-2025 hyperpolymath
+
+//! A Rust-subset frontend, parsed with `syn` (feature `rust-frontend`).
+//!
+//! ```ignore
+//! fn clamp(k: Secret<i64>, limit: Pub<i64>) -> Secret<i64> {
+//!     let doubled = k * 2;
+//!     if doubled > limit { limit } else { doubled }
+//! }
+//! ```
+//!
+//! Parameters and results are `Secret<T>`, `Pub<T>` or a bare `T`
+//! (public), for `T` one of `i64` and `bool`. Bodies may use `let` with
+//! a plain name (an optional `Secret<T>` annotation makes the value
+//! secret), `if`/`else` with both branches, blocks, parentheses, literals,
+//! the arithmetic, comparison and logical operators, unary `-` and `!`,
+//! and `Secret::new(e)` / `Pub::new(e)`. Anything else is rejected with
+//! its span.
+
+use super::{FrontendError, Kernel, KernelType};
+use crate::ast::{BinOp, Expr, UnaryOp};
+use crate::span::Span;
+use crate::typeck::Type;
+use syn::spanned::Spanned;
+
+/// Parse the single function in `source`.
+pub fn parse_kernel(source: &str) -> Result<Kernel, FrontendError> {
+    let file = syn::parse_file(source).map_err(|e| error(e.to_string(), e.span()))?;
+    let mut functions = file.items.iter().filter_map(|item| match item {
+        syn::Item::Fn(function) => Some(function),
+        _ => None,
+    });
+    let function = functions
+        .next()
+        .ok_or_else(|| FrontendError::new("expected a function", Span::default()))?;
+    if let Some(item) = file.items.iter().find(|item| !matches!(item, syn::Item::Fn(_))) {
+        return Err(error("only a single function is supported", item.span()));
+    }
+    if let Some(extra) = functions.next() {
+        return Err(error("only a single function is supported", extra.sig.span()));
+    }
+    lower_function(function)
+}
+
+fn error(message: impl Into<String>, span: proc_macro2::Span) -> FrontendError {
+    let range = span.byte_range();
+    FrontendError::new(message, Span::new(range.start, range.end))
+}
+
+fn unsupported(node: &impl Spanned, what: &str) -> FrontendError {
+    error(format!("unsupported Rust syntax: {}", what), node.span())
+}
+
+fn lower_function(function: &syn::ItemFn) -> Result<Kernel, FrontendError> {
+    let sig = &function.sig;
+    if !sig.generics.params.is_empty() {
+        return Err(unsupported(&sig.generics, "generic parameters"));
+    }
+    let mut params = Vec::new();
+    for input in &sig.inputs {
+        let syn::FnArg::Typed(arg) = input else {
+            return Err(unsupported(input, "`self` parameters"));
+        };
+        params.push((ident(&arg.pat)?, kernel_type(&arg.ty)?));
+    }
+    let ret = match &sig.output {
+        syn::ReturnType::Type(_, ty) => kernel_type(ty)?,
+        syn::ReturnType::Default => return Err(error("expected a return type", sig.span())),
+    };
+    let kernel = Kernel {
+        name: sig.ident.to_string(),
+        params,
+        ret,
+        body: block(&function.block)?,
+        span: error("", sig.span()).span,
+    };
+    kernel.check()?;
+    Ok(kernel)
+}
+
+fn ident(pat: &syn::Pat) -> Result<String, FrontendError> {
+    match pat {
+        syn::Pat::Ident(p) if p.by_ref.is_none() && p.subpat.is_none() => {
+            if p.mutability.is_some() {
+                return Err(unsupported(pat, "`mut` bindings"));
+            }
+            Ok(p.ident.to_string())
+        }
+        syn::Pat::Type(p) => ident(&p.pat),
+        _ => Err(unsupported(pat, "patterns other than a name")),
+    }
+}
+
+/// `Secret<T>`, `Pub<T>` or `T`.
+fn kernel_type(ty: &syn::Type) -> Result<KernelType, FrontendError> {
+    let invalid = || error("expected `Secret<T>`, `Pub<T>` or `T`, for `i64` or `bool`", ty.span());
+    let syn::Type::Path(path) = ty else {
+        return Err(invalid());
+    };
+    let segment = path.path.segments.last().ok_or_else(invalid)?;
+    let base = |name: &str| match name {
+        "i64" => Some(Type::Int),
+        "bool" => Some(Type::Bool),
+        _ => None,
+    };
+    let name = segment.ident.to_string();
+    if let Some(ty) = base(&name) {
+        return Ok(KernelType { ty, secret: false });
+    }
+    let secret = match name.as_str() {
+        "Secret" => true,
+        "Pub" => false,
+        _ => return Err(invalid()),
+    };
+    let syn::PathArguments::AngleBracketed(args) = &segment.arguments else {
+        return Err(invalid());
+    };
+    match args.args.first() {
+        Some(syn::GenericArgument::Type(syn::Type::Path(inner))) if args.args.len() == 1 => {
+            let ty = inner.path.get_ident().and_then(|i| base(&i.to_string()));
+            ty.map(|ty| KernelType { ty, secret }).ok_or_else(invalid)
+        }
+        _ => Err(invalid()),
+    }
+}
+
+/// A block as nested `let`s ending in its final expression.
+fn block(block: &syn::Block) -> Result<Expr, FrontendError> {
+    let Some((last, lets)) = block.stmts.split_last() else {
+        return Err(error("expected a final expression", block.span()));
+    };
+    let mut body = match last {
+        syn::Stmt::Expr(expr, None) => lower(expr)?,
+        _ => return Err(error("expected a final expression without `;`", last.span())),
+    };
+    for stmt in lets.iter().rev() {
+        let syn::Stmt::Local(local) = stmt else {
+            return Err(unsupported(stmt, "statements other than `let`"));
+        };
+        let init = local
+            .init
+            .as_ref()
+            .ok_or_else(|| error("expected an initializer", local.span()))?;
+        if let Some((_, diverge)) = &init.diverge {
+            return Err(unsupported(diverge, "`let ... else`"));
+        }
+        let mut value = lower(&init.expr)?;
+        if let syn::Pat::Type(annotated) = &local.pat {
+            if kernel_type(&annotated.ty)?.secret {
+                value = Expr::Secret(Box::new(value));
+            }
+        }
+        body = Expr::Let {
+            name: ident(&local.pat)?.into(),
+            ty: None,
+            mutable: false,
+            value: Box::new(value),
+            body: Box::new(body),
+        };
+    }
+    Ok(body)
+}
+
+fn lower(expr: &syn::Expr) -> Result<Expr, FrontendError> {
+    match expr {
+        syn::Expr::Lit(lit) => match &lit.lit {
+            syn::Lit::Int(int) if matches!(int.suffix(), "" | "i64") => int
+                .base10_parse()
+                .map(Expr::Int)
+                .map_err(|e| error(e.to_string(), int.span())),
+            syn::Lit::Bool(b) => Ok(Expr::Bool(b.value)),
+            _ => Err(unsupported(lit, "literals other than `i64` and `bool`")),
+        },
+        syn::Expr::Path(path) if path.qself.is_none() => match path.path.get_ident() {
+            Some(ident) => Ok(Expr::Var(ident.to_string().into())),
+            None => Err(unsupported(path, "paths")),
+        },
+        syn::Expr::Paren(paren) => lower(&paren.expr),
+        syn::Expr::Block(b) if b.label.is_none() => block(&b.block),
+        syn::Expr::Binary(binary) => {
+            let op = match binary.op {
+                syn::BinOp::Add(_) => BinOp::Add,
+                syn::BinOp::Sub(_) => BinOp::Sub,
+                syn::BinOp::Mul(_) => BinOp::Mul,
+                syn::BinOp::Div(_) => BinOp::Div,
+                syn::BinOp::Rem(_) => BinOp::Mod,
+                syn::BinOp::BitAnd(_) => BinOp::BitAnd,
+                syn::BinOp::BitOr(_) => BinOp::BitOr,
+                syn::BinOp::BitXor(_) => BinOp::BitXor,
+                syn::BinOp::Shl(_) => BinOp::Shl,
+                syn::BinOp::Shr(_) => BinOp::Shr,
+                syn::BinOp::Eq(_) => BinOp::Eq,
+                syn::BinOp::Ne(_) => BinOp::Ne,
+                syn::BinOp::Lt(_) => BinOp::Lt,
+                syn::BinOp::Le(_) => BinOp::Le,
+                syn::BinOp::Gt(_) => BinOp::Gt,
+                syn::BinOp::Ge(_) => BinOp::Ge,
+                syn::BinOp::And(_) => BinOp::And,
+                syn::BinOp::Or(_) => BinOp::Or,
+                _ => return Err(unsupported(&binary.op, "this operator")),
+            };
+            Ok(Expr::BinOp {
+                op,
+                left: Box::new(lower(&binary.left)?),
+                right: Box::new(lower(&binary.right)?),
+            })
+        }
+        syn::Expr::Unary(unary) => {
+            let op = match unary.op {
+                syn::UnOp::Neg(_) => UnaryOp::Neg,
+                syn::UnOp::Not(_) => UnaryOp::Not,
+                _ => return Err(unsupported(&unary.op, "this operator")),
+            };
+            Ok(Expr::UnaryOp {
+                op,
+                expr: Box::new(lower(&unary.expr)?),
+            })
+        }
+        syn::Expr::If(if_expr) => {
+            let (_, else_branch) = if_expr
+                .else_branch
+                .as_ref()
+                .ok_or_else(|| error("`if` needs an `else` branch", if_expr.span()))?;
+            Ok(Expr::If {
+                cond: Box::new(lower(&if_expr.cond)?),
+                then_branch: Box::new(block(&if_expr.then_branch)?),
+                else_branch: Box::new(lower(else_branch)?),
+            })
+        }
+        syn::Expr::Call(call) => {
+            let wrapper = match &*call.func {
+                syn::Expr::Path(path) => {
+                    let names: Vec<_> = path.path.segments.iter().map(|s| &s.ident).collect();
+                    match names[..] {
+                        [wrapper, new] if new == "new" && wrapper == "Secret" => Some(true),
+                        [wrapper, new] if new == "new" && wrapper == "Pub" => Some(false),
+                        _ => None,
+                    }
+                }
+                _ => None,
+            };
+            match (wrapper, call.args.len()) {
+                (Some(secret), 1) => {
+                    let value = lower(&call.args[0])?;
+                    Ok(if secret {
+                        Expr::Secret(Box::new(value))
+                    } else {
+                        value
+                    })
+                }
+                _ => Err(unsupported(call, "calls other than `Secret::new` and `Pub::new`")),
+            }
+        }
+        _ => Err(unsupported(expr, "this expression")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    #[test]
+    fn test_lowers_kernel() {
+        let source = "fn clamp(k: Secret<i64>, limit: i64) -> Secret<i64> {\n    \
+                      let doubled = k * 2;\n    \
+                      if doubled > limit { limit } else { doubled }\n}\n";
+        let kernel = parse_kernel(source).unwrap();
+        assert_eq!(kernel.name, "clamp");
+        assert_eq!(kernel.params[0].1, KernelType { ty: Type::Int, secret: true });
+        assert_eq!(
+            kernel.body.to_string(),
+            "let doubled = (k * 2) (if (doubled > limit) then limit else doubled)"
+        );
+        let code = kernel.emit(&Config::default()).unwrap();
+        assert!(code.contains("fn clamp(k: Secret<i64>, limit: Pub<i64>) -> Secret<i64> {\n"));
+        assert!(code.contains("ct_select("));
+    }
+
+    #[test]
+    fn test_rejects_outside_the_subset() {
+        let source = "fn f(k: Secret<i64>) -> i64 {\n    k.pow(2)\n}";
+        let err = parse_kernel(source).unwrap_err();
+        assert_eq!(err.message, "unsupported Rust syntax: this expression");
+        assert_eq!(&source[err.span.start..err.span.end], "k.pow(2)");
+
+        let leak = parse_kernel("fn f(k: Secret<i64>) -> Pub<i64> { k + 1 }").unwrap_err();
+        assert_eq!(leak.message, "`f` returns a secret value but is declared public");
+        let loops = parse_kernel("fn f(n: i64) -> i64 { while true {} n }").unwrap_err();
+        assert_eq!(loops.message, "unsupported Rust syntax: statements other than `let`");
+    }
+}
@@ -0,0 +1,139 @@
+// SPDX-License-Identifier: MIT OR AGPL-3.0-or-later
+// SPDX-FileCopyrightText: 2024-2025 hyperpolymath
+
+//! Entry points for fuzzing.
+//!
+//! Each function accepts any input, never panics, and sorts failures into
+//! rejected input ([`FuzzError::Rejected`], expected for most inputs) and
+//! compiler bugs ([`FuzzError::is_bug`]). A `cargo fuzz` target only needs
+//! to panic on bugs:
+//!
+//! ```no_run
+//! # let data: &[u8] = b"";
+//! if let Err(e) = obli_transpiler::fuzz::try_transpile_bytes(data) {
+//!     assert!(!e.is_bug(), "{}", e);
+//! }
+//! ```
+//!
+//! With the `arbitrary` feature, [`Token`] and [`Expr`] implement
+//! `arbitrary::Arbitrary`, so token streams and ASTs can be fuzzed directly.
+
+use crate::ast::Expr;
+use crate::error::TranspileError;
+use crate::ir::ObliExpr;
+use crate::lexer::Token;
+use crate::parser::Parser;
+use crate::span::NodeSpans;
+use crate::transform::to_oblivious_checked;
+use crate::typeck::typecheck;
+use crate::verify::verify;
+use std::any::Any;
+use std::panic::{self, UnwindSafe};
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum FuzzError {
+    #[error("input is not UTF-8")]
+    Utf8(#[from] std::str::Utf8Error),
+    /// The input is not a valid program.
+    #[error(transparent)]
+    Rejected(#[from] TranspileError),
+    #[error("compiler panicked: {0}")]
+    Panic(String),
+}
+
+impl FuzzError {
+    /// Whether this is a compiler bug rather than bad input: a panic, or
+    /// transformed IR that fails verification.
+    pub fn is_bug(&self) -> bool {
+        matches!(
+            self,
+            FuzzError::Panic(_) | FuzzError::Rejected(TranspileError::Transform(_))
+        )
+    }
+}
+
+/// Transpile arbitrary bytes as MiniObli source.
+pub fn try_transpile_bytes(data: &[u8]) -> Result<String, FuzzError> {
+    let source = std::str::from_utf8(data)?;
+    guard(|| crate::transpile(source))?.map_err(FuzzError::from)
+}
+
+/// Parse an arbitrary token stream (it need not end in [`Token::Eof`]).
+pub fn try_parse_tokens(tokens: &[Token]) -> Result<Expr, FuzzError> {
+    let parsed = guard(|| Parser::new(tokens).parse())?;
+    parsed.map_err(|e| TranspileError::from(e).into())
+}
+
+/// Typecheck, transform and verify an arbitrary AST.
+pub fn try_transform_arbitrary_ast(ast: &Expr) -> Result<ObliExpr, FuzzError> {
+    guard(|| {
+        typecheck(ast).map_err(TranspileError::Type)?;
+        let (ir, ..) =
+            to_oblivious_checked(ast, &NodeSpans::new()).map_err(TranspileError::Secrecy)?;
+        let errors = verify(&ir);
+        if !errors.is_empty() {
+            return Err(TranspileError::Transform(errors).into());
+        }
+        Ok(ir)
+    })?
+}
+
+/// Run `f`, turning a panic into [`FuzzError::Panic`].
+fn guard<T>(f: impl FnOnce() -> T + UnwindSafe) -> Result<T, FuzzError> {
+    panic::catch_unwind(f).map_err(|payload| FuzzError::Panic(panic_message(payload)))
+}
+
+fn panic_message(payload: Box<dyn Any + Send>) -> String {
+    match payload.downcast::<String>() {
+        Ok(message) => *message,
+        Err(payload) => match payload.downcast::<&str>() {
+            Ok(message) => message.to_string(),
+            Err(_) => "non-string panic payload".to_string(),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::BinOp;
+
+    #[test]
+    fn test_bad_input_is_rejected_not_a_bug() {
+        let err = try_transpile_bytes(&[0xff, 0xfe]).unwrap_err();
+        assert!(matches!(err, FuzzError::Utf8(_)));
+        assert!(!err.is_bug());
+        assert!(!try_transpile_bytes(b"let = (").unwrap_err().is_bug());
+        assert!(try_transpile_bytes(b"secret(1) + 2").is_ok());
+        let secret_loop = b"while x < 5 with x = pow(secret(3), 5)-0 do x + 1";
+        let err = try_transpile_bytes(secret_loop).unwrap_err();
+        assert!(matches!(err, FuzzError::Rejected(TranspileError::Secrecy(_))));
+        assert!(!err.is_bug());
+        let declared_pub = b"let shown: pub int = secret(1); shown";
+        assert!(!try_transpile_bytes(declared_pub).unwrap_err().is_bug());
+
+        let tokens = [Token::Let, Token::Ident("x".into()), Token::Assign];
+        assert!(!try_parse_tokens(&tokens).unwrap_err().is_bug());
+    }
+
+    #[test]
+    fn test_arbitrary_ast() {
+        let ill_typed = Expr::BinOp {
+            op: BinOp::Add,
+            left: Box::new(Expr::Int(1)),
+            right: Box::new(Expr::Bool(true)),
+        };
+        let err = try_transform_arbitrary_ast(&ill_typed).unwrap_err();
+        assert!(matches!(err, FuzzError::Rejected(TranspileError::Type(_))));
+        let secret = Expr::Secret(Box::new(Expr::Int(1)));
+        assert!(try_transform_arbitrary_ast(&secret).unwrap().is_secret());
+    }
+
+    #[test]
+    fn test_panics_are_caught() {
+        let err = guard(|| panic!("boom")).unwrap_err();
+        assert!(err.is_bug());
+        assert_eq!(err.to_string(), "compiler panicked: boom");
+    }
+}
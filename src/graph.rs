@@ -0,0 +1,417 @@
+// SPDX-License-Identifier: MIT OR AGPL-3.0-or-later
+// SPDX-FileCopyrightText: 2024-2025 hyperpolymath
+
+//! Graphviz (DOT) renderings of programs.
+//!
+//! - [`ast_dot`]: the syntax tree.
+//! - [`ir_dot`]: the oblivious IR, with secret nodes filled red and
+//!   `CtSelect` nodes drawn as diamonds.
+//! - [`passes_dot`]: the IR after the transform and after each pass of a
+//!   pipeline, one cluster per stage, to see what each pass changed.
+//! - [`flow_dot`]: secrecy flow between `let`-bound variables and the
+//!   program result.
+
+use crate::ast::{byte_string, enum_header, Expr, UnaryOp};
+use crate::formatter::binop_symbol;
+use crate::ir::ObliExpr;
+use crate::passes::Pipeline;
+use crate::symbol::Symbol;
+use crate::typeck::Type;
+use std::fmt::Write as _;
+
+const SECRET_STYLE: &str = "style=filled, fillcolor=\"#f4cccc\"";
+const PUBLIC_STYLE: &str = "style=filled, fillcolor=\"#d9ead3\"";
+const NODE_DEFAULTS: &str = "  node [shape=box, fontname=\"monospace\"];\n";
+
+/// Accumulates DOT statements, handing out unique node ids.
+struct Dot {
+    body: String,
+    next_id: usize,
+    prefix: String,
+}
+
+impl Dot {
+    fn new(prefix: &str) -> Self {
+        Self {
+            body: String::new(),
+            next_id: 0,
+            prefix: prefix.to_string(),
+        }
+    }
+
+    fn node(&mut self, label: &str, attrs: &str) -> String {
+        let id = format!("{}{}", self.prefix, self.next_id);
+        self.next_id += 1;
+        let _ = write!(self.body, "  {} [label=\"{}\"", id, escape(label));
+        if !attrs.is_empty() {
+            let _ = write!(self.body, ", {}", attrs);
+        }
+        self.body.push_str("];\n");
+        id
+    }
+
+    fn edge(&mut self, from: &str, to: &str, label: Option<&str>) {
+        let _ = write!(self.body, "  {} -> {}", from, to);
+        if let Some(label) = label {
+            let _ = write!(self.body, " [label=\"{}\"]", escape(label));
+        }
+        self.body.push_str(";\n");
+    }
+
+    fn finish(self, name: &str) -> String {
+        format!("digraph {} {{\n{}{}}}\n", name, NODE_DEFAULTS, self.body)
+    }
+}
+
+fn escape(label: &str) -> String {
+    label.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// DOT graph of a syntax tree.
+pub fn ast_dot(expr: &Expr) -> String {
+    let mut dot = Dot::new("n");
+    ast_node(expr, &mut dot);
+    dot.finish("ast")
+}
+
+fn ast_node(expr: &Expr, dot: &mut Dot) -> String {
+    let label = match expr {
+        Expr::Int(n) => n.to_string(),
+        Expr::Sized(..) => expr.to_string(),
+        Expr::Bool(b) => b.to_string(),
+        Expr::Var(name) => name.to_string(),
+        Expr::Input { .. } => expr.to_string(),
+        Expr::Secret(_) => "secret".to_string(),
+        Expr::BinOp { op, .. } => binop_symbol(op).to_string(),
+        Expr::UnaryOp { op, .. } => match op {
+            UnaryOp::Neg => "-".to_string(),
+            UnaryOp::Not => "not".to_string(),
+            UnaryOp::ToInt | UnaryOp::ToBool => op.to_string(),
+        },
+        Expr::Cast { ty, .. } => format!("as {}", Type::from(*ty)),
+        Expr::If { .. } => "if".to_string(),
+        Expr::Match { .. } => "match".to_string(),
+        Expr::Let {
+            name, ty, mutable, ..
+        } => {
+            let keyword = if *mutable { "let mut" } else { "let" };
+            match ty {
+                Some(ty) => format!("{} {}: {}", keyword, name, ty),
+                None => format!("{} {}", keyword, name),
+            }
+        }
+        Expr::Assign { name, .. } => format!("{} =", name),
+        Expr::Array(_) => "[]".to_string(),
+        Expr::Tuple(_) => "()".to_string(),
+        Expr::LetTuple { names, .. } => {
+            let names: Vec<_> = names.iter().map(|n| n.as_str()).collect();
+            format!("let ({})", names.join(", "))
+        }
+        Expr::Const { name, .. } => format!("const {}", name),
+        Expr::Enum { name, variants, .. } => enum_header(*name, variants),
+        Expr::Assert { .. } => "assert".to_string(),
+        Expr::Bytes(bytes) => byte_string(bytes),
+        Expr::Sort(_) => "sort".to_string(),
+        Expr::SwapIf { .. } => "swap_if".to_string(),
+        Expr::Clamp { .. } => "clamp".to_string(),
+        Expr::InRange { .. } => "in_range".to_string(),
+        Expr::Pow { .. } => "pow".to_string(),
+        Expr::Map { .. } => "map".to_string(),
+        Expr::Fold { .. } => "fold".to_string(),
+        Expr::Some(_) => "some".to_string(),
+        Expr::None => "none".to_string(),
+        Expr::UnwrapOr { .. } => "unwrap_or".to_string(),
+        Expr::Declassify(_) => "declassify".to_string(),
+        Expr::Index { .. } => "index".to_string(),
+        Expr::Lookup { .. } => "lookup".to_string(),
+        Expr::Outputs(_) => "outputs".to_string(),
+        Expr::Fn { name, .. } => format!("fn {}", name),
+        Expr::Call { name, .. } => format!("call {}", name),
+        Expr::Lambda { params, .. } => {
+            let params: Vec<_> = params.iter().map(|p| p.as_str()).collect();
+            format!("fn({})", params.join(", "))
+        }
+        Expr::For { var, acc, .. } => format!("for {} with {}", var, acc),
+        Expr::While { acc, .. } => format!("while with {}", acc),
+    };
+    let id = dot.node(&label, "");
+    let edge_labels: Vec<&str> = match expr {
+        Expr::If { .. } => vec!["cond", "then", "else"],
+        Expr::SwapIf { .. } => vec!["cond", "left", "right"],
+        Expr::Clamp { .. } | Expr::InRange { .. } => vec!["value", "lo", "hi"],
+        Expr::Pow { .. } => vec!["base", "exp"],
+        Expr::Map { .. } => vec!["array", "f"],
+        Expr::Fold { .. } => vec!["array", "init", "f"],
+        Expr::UnwrapOr { .. } => vec!["option", "default"],
+        Expr::Let { .. } | Expr::LetTuple { .. } | Expr::Const { .. } => vec!["value", "body"],
+        Expr::Assign { cond: Some(_), .. } => vec!["if", "value", "body"],
+        Expr::Assign { cond: None, .. } => vec!["value", "body"],
+        Expr::Fn { .. } => vec!["body", "rest"],
+        Expr::Lambda { .. } | Expr::Enum { .. } => vec!["body"],
+        Expr::For { .. } => vec!["from", "to", "init", "do"],
+        Expr::While { .. } => vec!["cond", "init", "do"],
+        Expr::Index { .. } => vec!["array", "index"],
+        Expr::Outputs(outputs) => outputs.iter().map(|(name, _)| name.as_str()).collect(),
+        _ => vec![],
+    };
+    for (i, child) in expr.children().into_iter().enumerate() {
+        let child_id = ast_node(child, dot);
+        dot.edge(&id, &child_id, edge_labels.get(i).copied());
+    }
+    id
+}
+
+/// DOT graph of an IR tree.
+pub fn ir_dot(expr: &ObliExpr) -> String {
+    let mut dot = Dot::new("n");
+    ir_node(expr, &mut dot);
+    dot.finish("ir")
+}
+
+/// DOT graph of the IR before and after each pass in `pipeline`.
+pub fn passes_dot(expr: &ObliExpr, pipeline: &Pipeline) -> String {
+    let mut out = format!("digraph passes {{\n{}", NODE_DEFAULTS);
+    let mut stage = |index: usize, title: &str, expr: &ObliExpr| {
+        let mut dot = Dot::new(&format!("s{}_", index));
+        ir_node(expr, &mut dot);
+        let _ = writeln!(out, "  subgraph cluster_{} {{", index);
+        let _ = writeln!(out, "    label=\"{}\";", escape(title));
+        for line in dot.body.lines() {
+            let _ = writeln!(out, "  {}", line);
+        }
+        out.push_str("  }\n");
+    };
+
+    stage(0, "transform", expr);
+    let mut current = expr.clone();
+    for (i, pass) in pipeline.passes().enumerate() {
+        current = pass.run(current);
+        stage(i + 1, &format!("after {}", pass.name()), &current);
+    }
+    out.push_str("}\n");
+    out
+}
+
+fn ir_node(expr: &ObliExpr, dot: &mut Dot) -> String {
+    let label = match expr {
+        ObliExpr::PubInt(n) => n.to_string(),
+        ObliExpr::PubBool(b) => b.to_string(),
+        ObliExpr::SecretInt(n) => format!("secret {}", n),
+        ObliExpr::SecretBool(b) => format!("secret {}", b),
+        ObliExpr::Sized {
+            value,
+            width,
+            is_secret,
+        } => {
+            let secret = if *is_secret { "secret " } else { "" };
+            format!("{}{}{}", secret, width.value(*value), width)
+        }
+        ObliExpr::Var { name, .. } => name.to_string(),
+        ObliExpr::Input { .. } => expr.to_string(),
+        ObliExpr::BinOp { op, .. } => format!("{:?}", op),
+        ObliExpr::UnaryOp { op, .. } => format!("{:?}", op),
+        ObliExpr::CtCast { from, to, .. } => {
+            format!("CtCast {} -> {}", Type::from(*from), Type::from(*to))
+        }
+        ObliExpr::CtSelect { .. } => "CtSelect".to_string(),
+        ObliExpr::PubIf { .. } => "PubIf".to_string(),
+        ObliExpr::Let { name, .. } => format!("let {}", name),
+        ObliExpr::Outputs(_) => "outputs".to_string(),
+        ObliExpr::Table { values, .. } => format!("table[{}]", values.len()),
+        ObliExpr::While { acc, .. } => format!("while with {}", acc),
+        ObliExpr::CtSwap {
+            names: [first, second],
+            ..
+        } => format!("CtSwap {}, {}", first, second),
+        ObliExpr::Assert { .. } => "assert".to_string(),
+        ObliExpr::Declassify(_) => "declassify".to_string(),
+    };
+    let style = if expr.is_secret() {
+        SECRET_STYLE
+    } else {
+        PUBLIC_STYLE
+    };
+    let attrs = match expr {
+        ObliExpr::CtSelect { .. } => format!("shape=diamond, {}", style),
+        _ => style.to_string(),
+    };
+    let id = dot.node(&label, &attrs);
+    let edge_labels: Vec<&str> = match expr {
+        ObliExpr::CtSelect { .. } | ObliExpr::PubIf { .. } => vec!["cond", "then", "else"],
+        ObliExpr::Let { .. } => vec!["value", "body"],
+        ObliExpr::Outputs(outputs) => outputs.iter().map(|(name, _)| name.as_str()).collect(),
+        ObliExpr::Table { .. } => vec!["index"],
+        ObliExpr::While { .. } => vec!["cond", "init", "do"],
+        ObliExpr::CtSwap { .. } => vec!["cond", "left", "right", "body"],
+        ObliExpr::Assert { .. } => vec!["cond", "body"],
+        _ => vec![],
+    };
+    for (i, child) in expr.children().into_iter().enumerate() {
+        let child_id = ir_node(child, dot);
+        dot.edge(&id, &child_id, edge_labels.get(i).copied());
+    }
+    id
+}
+
+/// DOT graph of how secrecy flows between variables and into the result.
+///
+/// Each `let` binding is a node (red if secret); an edge `a -> b` means the
+/// value bound to `b` reads `a`. Free variables appear as inputs.
+pub fn flow_dot(expr: &ObliExpr) -> String {
+    let mut dot = Dot::new("v");
+    let mut scope = Vec::new();
+    let mut inputs = Vec::new();
+    let result = flow(expr, &mut dot, &mut scope, &mut inputs);
+    let style = if expr.is_secret() {
+        SECRET_STYLE
+    } else {
+        PUBLIC_STYLE
+    };
+    let sink = dot.node("result", &format!("shape=doubleoctagon, {}", style));
+    for source in result {
+        dot.edge(&source, &sink, None);
+    }
+    dot.finish("secrecy_flow")
+}
+
+/// Walk `expr`, returning the ids of the variable nodes it reads.
+fn flow(
+    expr: &ObliExpr,
+    dot: &mut Dot,
+    scope: &mut Vec<(Symbol, String)>,
+    inputs: &mut Vec<(Symbol, String)>,
+) -> Vec<String> {
+    match expr {
+        ObliExpr::Var { name, is_secret } => {
+            if let Some((_, id)) = scope.iter().rev().find(|(n, _)| n == name) {
+                return vec![id.clone()];
+            }
+            if let Some((_, id)) = inputs.iter().find(|(n, _)| n == name) {
+                return vec![id.clone()];
+            }
+            let style = if *is_secret { SECRET_STYLE } else { PUBLIC_STYLE };
+            let id = dot.node(&format!("input {}", name), &format!("shape=ellipse, {}", style));
+            inputs.push((*name, id.clone()));
+            vec![id]
+        }
+        ObliExpr::Let {
+            name, value, body, ..
+        } => {
+            let reads = flow(value, dot, scope, inputs);
+            let style = if value.is_secret() {
+                SECRET_STYLE
+            } else {
+                PUBLIC_STYLE
+            };
+            let id = dot.node(name, &format!("shape=ellipse, {}", style));
+            for source in dedup(reads) {
+                dot.edge(&source, &id, None);
+            }
+            scope.push((*name, id));
+            let result = flow(body, dot, scope, inputs);
+            scope.pop();
+            result
+        }
+        // `acc` is bound once, reading its initial value and every
+        // iteration's; the condition only decides when to stop
+        ObliExpr::While {
+            cond,
+            acc,
+            init,
+            body,
+            is_secret,
+        } => {
+            let style = if *is_secret { SECRET_STYLE } else { PUBLIC_STYLE };
+            let mut reads = flow(init, dot, scope, inputs);
+            let id = dot.node(acc, &format!("shape=ellipse, {}", style));
+            scope.push((*acc, id.clone()));
+            flow(cond, dot, scope, inputs);
+            reads.extend(flow(body, dot, scope, inputs));
+            scope.pop();
+            for source in dedup(reads).into_iter().filter(|source| *source != id) {
+                dot.edge(&source, &id, None);
+            }
+            vec![id]
+        }
+        // Each name reads the condition and both operands
+        ObliExpr::CtSwap {
+            names,
+            body,
+            is_secret,
+            ..
+        } => {
+            let reads = expr.children()[..3]
+                .iter()
+                .flat_map(|child| flow(child, dot, scope, inputs))
+                .collect();
+            let reads = dedup(reads);
+            let style = if *is_secret { SECRET_STYLE } else { PUBLIC_STYLE };
+            for name in names {
+                let id = dot.node(name, &format!("shape=ellipse, {}", style));
+                for source in &reads {
+                    dot.edge(source, &id, None);
+                }
+                scope.push((*name, id));
+            }
+            let result = flow(body, dot, scope, inputs);
+            scope.truncate(scope.len() - 2);
+            result
+        }
+        _ => {
+            let reads = expr
+                .children()
+                .into_iter()
+                .flat_map(|child| flow(child, dot, scope, inputs))
+                .collect();
+            dedup(reads)
+        }
+    }
+}
+
+fn dedup(mut ids: Vec<String>) -> Vec<String> {
+    let mut seen = Vec::new();
+    ids.retain(|id| {
+        let new = !seen.contains(id);
+        if new {
+            seen.push(id.clone());
+        }
+        new
+    });
+    ids
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transpile_to_ir;
+
+    #[test]
+    fn test_ir_dot_marks_secrets_and_selects() {
+        let ir = transpile_to_ir("let x = secret(1) if x > 0 then 1 else 2").unwrap();
+        let dot = ir_dot(&ir);
+        assert!(dot.starts_with("digraph ir {"));
+        assert!(dot.contains(&format!("[label=\"CtSelect\", shape=diamond, {}]", SECRET_STYLE)));
+        assert!(dot.contains("[label=\"cond\"]"));
+    }
+
+    #[test]
+    fn test_passes_dot_has_cluster_per_stage() {
+        let ir = transpile_to_ir("1 + 2").unwrap();
+        let dot = passes_dot(&ir, &Pipeline::standard());
+        assert!(dot.contains("label=\"transform\""));
+        assert!(dot.contains("label=\"after const-fold\""));
+        assert!(dot.contains("label=\"after select-simplify\""));
+        assert!(dot.contains("s1_0 [label=\"3\""));
+    }
+
+    #[test]
+    fn test_flow_dot() {
+        let ir = transpile_to_ir("let a = secret(1) let b = a + k let c = 2 b").unwrap();
+        let dot = flow_dot(&ir);
+        assert!(dot.contains(&format!("v0 [label=\"a\", shape=ellipse, {}]", SECRET_STYLE)));
+        assert!(dot.contains("v1 [label=\"input k\""));
+        assert!(dot.contains("v0 -> v2;\n  v1 -> v2;"));
+        assert!(dot.contains("v2 -> v4;"));
+        assert!(!dot.contains("v3 -> v4"));
+    }
+}
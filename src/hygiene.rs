@@ -0,0 +1,211 @@
+// SPDX-License-Identifier: MIT OR AGPL-3.0-or-later
+// SPDX-FileCopyrightText: 2024-2025 hyperpolymath
+
+//! Renaming variables that generated Rust cannot use.
+//!
+//! MiniObli allows `let match = 1` or `let ct_select = 2`, but as Rust the
+//! first does not compile and the second shadows the runtime function.
+//! [`mangle_reserved`] renames every `let` whose name is in
+//! [`RESERVED_NAMES`] to `__obli_<name>`, adding a number if the program
+//! already uses that name, and every use of it along with it. Free
+//! variables and output names are visible outside the program, so they
+//! cannot be renamed; [`check_emittable`](crate::emit::check_emittable)
+//! rejects reserved ones.
+
+use crate::emit::RESERVED_NAMES;
+use crate::ir::ObliExpr;
+use crate::stack;
+use crate::symbol::{Scope, Symbol};
+use std::collections::{HashMap, HashSet};
+
+/// Rename the `let`-bound variables of `expr` that are reserved in Rust.
+pub fn mangle_reserved(mut expr: ObliExpr) -> ObliExpr {
+    let mut used = HashSet::new();
+    for node in expr.post_order() {
+        if let ObliExpr::Var { name, .. }
+        | ObliExpr::Let { name, .. }
+        | ObliExpr::While { acc: name, .. } = node
+        {
+            used.insert(*name);
+        }
+    }
+    if used.iter().any(|name| RESERVED_NAMES.contains(&name.as_str())) {
+        let mut mangler = Mangler {
+            used,
+            mangled: HashMap::new(),
+            scope: Scope::new(),
+        };
+        mangler.rename(&mut expr);
+    }
+    expr
+}
+
+struct Mangler {
+    used: HashSet<Symbol>,
+    /// The new name of every reserved name renamed so far.
+    mangled: HashMap<Symbol, Symbol>,
+    /// The name each bound variable is emitted as.
+    scope: Scope<Symbol>,
+}
+
+impl Mangler {
+    /// The name `name` is bound as, chosen once per reserved name.
+    fn mangle(&mut self, name: Symbol) -> Symbol {
+        if !RESERVED_NAMES.contains(&name.as_str()) {
+            return name;
+        }
+        if let Some(mangled) = self.mangled.get(&name) {
+            return *mangled;
+        }
+        let base = format!("__obli_{}", name);
+        let mut candidate = Symbol::from(base.as_str());
+        let mut n = 0;
+        while !self.used.insert(candidate) {
+            candidate = Symbol::from(format!("{}{}", base, n));
+            n += 1;
+        }
+        self.mangled.insert(name, candidate);
+        candidate
+    }
+
+    fn rename(&mut self, expr: &mut ObliExpr) {
+        stack::guard(|| self.rename_node(expr))
+    }
+
+    fn rename_node(&mut self, expr: &mut ObliExpr) {
+        match expr {
+            ObliExpr::Var { name, .. } => {
+                if let Some(bound) = self.scope.get(*name) {
+                    *name = *bound;
+                }
+            }
+            ObliExpr::Let {
+                name, value, body, ..
+            } => {
+                self.rename(value);
+                let mangled = self.mangle(*name);
+                self.scope.push(*name, mangled);
+                self.rename(body);
+                self.scope.pop();
+                *name = mangled;
+            }
+            ObliExpr::BinOp { left, right, .. } => {
+                self.rename(left);
+                self.rename(right);
+            }
+            ObliExpr::UnaryOp { expr, .. } | ObliExpr::CtCast { expr, .. } => self.rename(expr),
+            ObliExpr::Assert { cond, body } => {
+                self.rename(cond);
+                self.rename(body);
+            }
+            ObliExpr::CtSelect {
+                cond,
+                then_val,
+                else_val,
+            } => {
+                self.rename(cond);
+                self.rename(then_val);
+                self.rename(else_val);
+            }
+            ObliExpr::PubIf {
+                cond,
+                then_branch,
+                else_branch,
+            } => {
+                self.rename(cond);
+                self.rename(then_branch);
+                self.rename(else_branch);
+            }
+            ObliExpr::Outputs(outputs) => {
+                for (_, value) in outputs {
+                    self.rename(value);
+                }
+            }
+            ObliExpr::Table { index, .. } => self.rename(index),
+            ObliExpr::Declassify(inner) => self.rename(inner),
+            ObliExpr::While {
+                cond,
+                acc,
+                init,
+                body,
+                ..
+            } => {
+                let mangled = self.mangle(*acc);
+                self.scope.push(*acc, mangled);
+                self.rename(cond);
+                self.scope.pop();
+                self.rename(init);
+                self.scope.push(*acc, mangled);
+                self.rename(body);
+                self.scope.pop();
+                *acc = mangled;
+            }
+            ObliExpr::CtSwap {
+                cond,
+                left,
+                right,
+                names,
+                body,
+                ..
+            } => {
+                self.rename(cond);
+                self.rename(left);
+                self.rename(right);
+                let mangled = names.map(|name| self.mangle(name));
+                for (name, mangled) in names.iter().zip(mangled) {
+                    self.scope.push(*name, mangled);
+                }
+                self.rename(body);
+                self.scope.pop();
+                self.scope.pop();
+                *names = mangled;
+            }
+            ObliExpr::PubInt(_)
+            | ObliExpr::PubBool(_)
+            | ObliExpr::SecretInt(_)
+            | ObliExpr::SecretBool(_)
+            | ObliExpr::Sized { .. }
+            | ObliExpr::Input { .. } => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::run::run_emitted;
+    use crate::{to_oblivious, transpile, Lexer, Parser};
+
+    fn ir(source: &str) -> ObliExpr {
+        let (tokens, _) = Lexer::new(source).tokenize().unwrap();
+        to_oblivious(&Parser::new(&tokens).parse().unwrap())
+    }
+
+    #[test]
+    fn test_reserved_lets_renamed() {
+        let source = "let loop = 1 let __obli_loop = 2 let loop = loop + __obli_loop loop";
+        let mangled = mangle_reserved(ir(source));
+        assert_eq!(
+            mangled.to_string(),
+            ir("let __obli_loop0 = 1 let __obli_loop = 2 \
+                let __obli_loop0 = __obli_loop0 + __obli_loop __obli_loop0")
+            .to_string()
+        );
+        let untouched = ir("let x = 1 x + 2");
+        assert_eq!(mangle_reserved(untouched.clone()), untouched);
+    }
+
+    #[test]
+    fn test_runtime_names_compile() {
+        let code = transpile("let ct_select = secret(2) let Secret = 3 ct_select * Secret");
+        let code = code.unwrap();
+        assert_eq!(run_emitted(&code).unwrap(), "Result: 6\n");
+    }
+
+    #[test]
+    fn test_prelude_variants_compile() {
+        let source = "let Some = secret(3) let None = 1 let Ok = 2 let Err = Ok Some + Err";
+        let code = transpile(source);
+        assert_eq!(run_emitted(&code.unwrap()).unwrap(), "Result: 5\n");
+    }
+}
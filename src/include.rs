@@ -0,0 +1,458 @@
+// SPDX-License-Identifier: MIT OR AGPL-3.0-or-later
+// SPDX-FileCopyrightText: 2024-2025 hyperpolymath
+
+//! Including other files.
+//!
+//! A line of the form `include "common.mobli"` is replaced by the contents
+//! of that file, resolved against the directory of the including file, so
+//! shared bindings can live in one place:
+//!
+//! ```text
+//! include "limits.mobli"          # let limit = 100
+//! if secret(x) > limit then limit else secret(x)
+//! ```
+//!
+//! Includes nest. A file included more than once, directly or through
+//! other files, is only expanded the first time; including a file from
+//! itself, directly or not, is an error. A [`Unit`] holds the expanded
+//! text the pipeline compiles, with the file and offset every byte of it
+//! came from, so diagnostics can point into the file that caused them.
+//!
+//! Files are read through a [`Loader`]: [`FileSystem`] by default, or
+//! [`MemoryFiles`] for programs that never touch the disk, such as those
+//! of an editor or a test.
+
+use crate::codes;
+use crate::diagnostic::Diagnostic;
+use crate::span::Span;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum IncludeError {
+    #[error("cannot read {path}: {source}")]
+    Io {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+    #[error("include cycle: {}", display_chain(.0))]
+    Cycle(Vec<PathBuf>),
+}
+
+impl IncludeError {
+    /// Catalog code of this error, if it has one.
+    pub fn code(&self) -> Option<&'static str> {
+        match self {
+            IncludeError::Io { .. } => None,
+            IncludeError::Cycle(_) => Some(codes::INCLUDE_CYCLE),
+        }
+    }
+}
+
+impl From<IncludeError> for Diagnostic {
+    fn from(e: IncludeError) -> Self {
+        let code = e.code();
+        let diagnostic = Diagnostic::error(e.to_string());
+        match code {
+            Some(code) => diagnostic.with_code(code),
+            None => diagnostic,
+        }
+    }
+}
+
+fn display_chain(chain: &[PathBuf]) -> String {
+    let names: Vec<_> = chain.iter().map(|path| path.display().to_string()).collect();
+    names.join(" -> ")
+}
+
+/// Where included files come from.
+pub trait Loader {
+    /// The contents of the file at `path`.
+    fn load(&self, path: &Path) -> io::Result<String>;
+
+    /// The same path for every way of naming the file at `path`, to find
+    /// files included twice and cycles.
+    fn canonical(&self, path: &Path) -> PathBuf {
+        normalize(path)
+    }
+}
+
+/// Files on disk.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FileSystem;
+
+impl Loader for FileSystem {
+    fn load(&self, path: &Path) -> io::Result<String> {
+        fs::read_to_string(path)
+    }
+
+    fn canonical(&self, path: &Path) -> PathBuf {
+        fs::canonicalize(path).unwrap_or_else(|_| normalize(path))
+    }
+}
+
+/// Files held in memory, by path.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryFiles {
+    files: BTreeMap<PathBuf, String>,
+}
+
+impl MemoryFiles {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add the file `path` with contents `text`, replacing any before.
+    pub fn insert(&mut self, path: impl AsRef<Path>, text: impl Into<String>) {
+        self.files.insert(normalize(path.as_ref()), text.into());
+    }
+}
+
+impl Loader for MemoryFiles {
+    fn load(&self, path: &Path) -> io::Result<String> {
+        self.files.get(&normalize(path)).cloned().ok_or_else(|| io::ErrorKind::NotFound.into())
+    }
+}
+
+/// `path` without `.` components, and with each `..` removing the
+/// component before it where there is one.
+fn normalize(path: &Path) -> PathBuf {
+    let mut normal = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir
+                if matches!(normal.components().next_back(), Some(Component::Normal(_))) =>
+            {
+                normal.pop();
+            }
+            component => normal.push(component),
+        }
+    }
+    normal
+}
+
+/// A source file that is part of a [`Unit`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceFile {
+    pub path: PathBuf,
+    pub text: String,
+}
+
+/// A run of the expanded text copied from one file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Segment {
+    /// Byte range in the expanded text.
+    start: usize,
+    end: usize,
+    /// Index into [`Unit::files`].
+    file: usize,
+    /// Byte offset of `start` in that file.
+    offset: usize,
+}
+
+/// A file with its includes expanded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Unit {
+    /// The expanded text.
+    pub source: String,
+    /// The root file, then every included file in the order first included.
+    pub files: Vec<SourceFile>,
+    segments: Vec<Segment>,
+}
+
+impl Unit {
+    /// Read `path` and expand its includes.
+    pub fn resolve(path: impl AsRef<Path>) -> Result<Self, IncludeError> {
+        Self::resolve_with(path, &FileSystem)
+    }
+
+    /// Read `path` and expand its includes, with files from `loader`.
+    pub fn resolve_with(path: impl AsRef<Path>, loader: &dyn Loader) -> Result<Self, IncludeError> {
+        let path = path.as_ref();
+        let text = read(loader, path)?;
+        Self::from_source_with(path, text, loader)
+    }
+
+    /// Expand the includes of `text`, the contents of `path`.
+    pub fn from_source(
+        path: impl AsRef<Path>,
+        text: impl Into<String>,
+    ) -> Result<Self, IncludeError> {
+        Self::from_source_with(path, text, &FileSystem)
+    }
+
+    /// Expand the includes of `text`, the contents of `path`, with
+    /// included files from `loader`.
+    pub fn from_source_with(
+        path: impl AsRef<Path>,
+        text: impl Into<String>,
+        loader: &dyn Loader,
+    ) -> Result<Self, IncludeError> {
+        let mut unit = Unit {
+            source: String::new(),
+            files: Vec::new(),
+            segments: Vec::new(),
+        };
+        let mut resolver = Resolver {
+            unit: &mut unit,
+            loader,
+            seen: Vec::new(),
+            stack: Vec::new(),
+        };
+        resolver.expand(path.as_ref(), Some(text.into()))?;
+        Ok(unit)
+    }
+
+    /// Whether the text came from more than one file.
+    pub fn has_includes(&self) -> bool {
+        self.files.len() > 1
+    }
+
+    /// The file a span of the expanded text lies in, and the span in that
+    /// file. A span crossing into another file is cut short.
+    pub fn locate(&self, span: Span) -> (&SourceFile, Span) {
+        let segment = self
+            .segments
+            .iter()
+            .find(|s| s.start <= span.start && span.start < s.end)
+            .or_else(|| self.segments.iter().rfind(|s| s.start <= span.start))
+            .copied()
+            .unwrap_or(Segment {
+                start: 0,
+                end: 0,
+                file: 0,
+                offset: 0,
+            });
+        let shift =
+            |at: usize| at.clamp(segment.start, segment.end) - segment.start + segment.offset;
+        let file = &self.files[segment.file];
+        (file, Span::new(shift(span.start), shift(span.end.max(span.start))))
+    }
+
+    /// `diagnostic` moved into the file its span lies in, with that file.
+    /// Labels and suggestions in other files are dropped.
+    pub fn relocate(&self, diagnostic: &Diagnostic) -> (&SourceFile, Diagnostic) {
+        let Some(span) = diagnostic.span else {
+            return (&self.files[0], diagnostic.clone());
+        };
+        let (file, span) = self.locate(span);
+        let mut relocated = diagnostic.clone();
+        relocated.span = Some(span);
+        relocated.labels.retain_mut(|label| {
+            let (label_file, label_span) = self.locate(label.span);
+            label.span = label_span;
+            label_file.path == file.path
+        });
+        relocated.suggestions.retain_mut(|suggestion| {
+            let (suggestion_file, suggestion_span) = self.locate(suggestion.span);
+            suggestion.span = suggestion_span;
+            suggestion_file.path == file.path
+        });
+        (file, relocated)
+    }
+
+    /// Offset in the expanded text of byte `offset` of the root file,
+    /// unless it lies in an `include` directive.
+    pub fn expanded_offset(&self, offset: usize) -> Option<usize> {
+        self.segments
+            .iter()
+            .find(|s| s.file == 0 && s.offset <= offset && offset < s.offset + s.end - s.start)
+            .map(|s| s.start + offset - s.offset)
+    }
+
+    /// The span of the root file `span` of the expanded text comes from:
+    /// its own, or that of the `include` directive that brought in the
+    /// file it lies in.
+    pub fn locate_in_root(&self, span: Span) -> Span {
+        let (file, located) = self.locate(span);
+        if std::ptr::eq(file, &self.files[0]) {
+            return located;
+        }
+        // The directive starts where the root text before it ends
+        let start = self
+            .segments
+            .iter()
+            .rev()
+            .find(|s| s.file == 0 && s.end <= span.start)
+            .map_or(0, |s| s.offset + s.end - s.start);
+        let root = &self.files[0].text;
+        match directive(&root[start..]) {
+            Some((from, to, _)) => Span::new(start + from, start + to),
+            None => Span::new(start, start),
+        }
+    }
+
+    /// `diagnostic` moved into the root file: one in an included file is
+    /// reported at the `include` directive, naming that file.
+    pub fn relocate_to_root(&self, diagnostic: &Diagnostic) -> Diagnostic {
+        let (file, relocated) = self.relocate(diagnostic);
+        if std::ptr::eq(file, &self.files[0]) {
+            return relocated;
+        }
+        let span = self.locate_in_root(diagnostic.span.unwrap_or_default());
+        let mut moved = diagnostic.clone();
+        moved.message = format!("{}: {}", file.path.display(), diagnostic.message);
+        moved.span = Some(span);
+        moved.labels.clear();
+        moved.suggestions.clear();
+        moved
+    }
+}
+
+struct Resolver<'a> {
+    unit: &'a mut Unit,
+    loader: &'a dyn Loader,
+    /// Canonical paths of the files expanded so far.
+    seen: Vec<PathBuf>,
+    /// Canonical paths of the files being expanded, outermost first.
+    stack: Vec<PathBuf>,
+}
+
+impl Resolver<'_> {
+    fn expand(&mut self, path: &Path, text: Option<String>) -> Result<(), IncludeError> {
+        let canonical = self.loader.canonical(path);
+        if let Some(at) = self.stack.iter().position(|p| *p == canonical) {
+            let mut chain = self.stack[at..].to_vec();
+            chain.push(canonical);
+            return Err(IncludeError::Cycle(chain));
+        }
+        if self.seen.contains(&canonical) {
+            return Ok(());
+        }
+        let text = match text {
+            Some(text) => text,
+            None => read(self.loader, path)?,
+        };
+        let file = self.unit.files.len();
+        self.unit.files.push(SourceFile {
+            path: path.to_path_buf(),
+            text: text.clone(),
+        });
+        self.seen.push(canonical.clone());
+        self.stack.push(canonical);
+
+        let dir = path.parent().unwrap_or(Path::new(""));
+        let mut copied = 0;
+        let mut line_start = 0;
+        for line in text.split_inclusive('\n') {
+            if let Some((start, end, target)) = directive(line) {
+                self.copy(file, &text, copied, line_start + start);
+                self.expand(&dir.join(target), None)?;
+                copied = line_start + end;
+            }
+            line_start += line.len();
+        }
+        self.copy(file, &text, copied, text.len());
+        self.stack.pop();
+        Ok(())
+    }
+
+    /// Append `text[from..to]` of file `file` to the expanded text.
+    fn copy(&mut self, file: usize, text: &str, from: usize, to: usize) {
+        if from == to {
+            return;
+        }
+        let start = self.unit.source.len();
+        self.unit.source.push_str(&text[from..to]);
+        self.unit.segments.push(Segment {
+            start,
+            end: self.unit.source.len(),
+            file,
+            offset: from,
+        });
+    }
+}
+
+/// The byte range of an `include "path"` directive on `line`, and its path.
+pub(crate) fn directive(line: &str) -> Option<(usize, usize, &str)> {
+    let start = line.len() - line.trim_start().len();
+    let rest = line[start..].strip_prefix("include")?.trim_start();
+    let rest = rest.strip_prefix('"')?;
+    let close = rest.find('"')?;
+    let end = line.len() - rest.len() + close + 1;
+    Some((start, end, &rest[..close]))
+}
+
+fn read(loader: &dyn Loader, path: &Path) -> Result<String, IncludeError> {
+    loader.load(path).map_err(|source| IncludeError::Io {
+        path: path.to_path_buf(),
+        source,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::check::check;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir()
+            .join(format!("obli-include-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("lib")).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_includes_expand_once() {
+        let dir = temp_dir("expand");
+        fs::write(dir.join("lib/limit.mobli"), "let limit = 100\n").unwrap();
+        fs::write(dir.join("lib/both.mobli"), "include \"limit.mobli\"\nlet k = secret(3)\n")
+            .unwrap();
+        let main = "include \"lib/limit.mobli\"\ninclude \"lib/both.mobli\"\nk + limit\n";
+        let unit = Unit::from_source(dir.join("main.mobli"), main).unwrap();
+        assert_eq!(unit.source, "let limit = 100\n\n\nlet k = secret(3)\n\nk + limit\n");
+        assert_eq!(unit.files.len(), 3);
+        assert!(check(&unit.source).is_empty());
+
+        // `1 + true` in the included file is reported there
+        fs::write(dir.join("lib/limit.mobli"), "let limit = 1 + true\n").unwrap();
+        let unit = Unit::from_source(dir.join("main.mobli"), main).unwrap();
+        let (file, diagnostic) = unit.relocate(&check(&unit.source)[0]);
+        assert_eq!(file.path, dir.join("lib/limit.mobli"));
+        assert_eq!(diagnostic.span, Some(Span::new(16, 20)));
+        // ... or at the directive including it, from the root file
+        let moved = unit.relocate_to_root(&check(&unit.source)[0]);
+        assert_eq!(moved.span, Some(Span::new(0, 25)));
+        let message = "limit.mobli: mismatched types: expected int, found bool";
+        assert!(moved.message.ends_with(message), "{}", moved.message);
+        let k = main.find("k +").unwrap();
+        let expanded = unit.expanded_offset(k).unwrap();
+        assert_eq!(&unit.source[expanded..expanded + 3], "k +");
+        assert_eq!(unit.locate_in_root(Span::new(expanded, expanded + 1)), Span::new(k, k + 1));
+        assert_eq!(unit.expanded_offset(3), None);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_cycles_are_errors() {
+        let dir = temp_dir("cycle");
+        fs::write(dir.join("a.mobli"), "include \"b.mobli\"\n1").unwrap();
+        fs::write(dir.join("b.mobli"), "  include \"a.mobli\"  # back again\n").unwrap();
+        let err = Unit::resolve(dir.join("a.mobli")).unwrap_err();
+        let IncludeError::Cycle(chain) = &err else {
+            panic!("{}", err)
+        };
+        let names: Vec<_> = chain.iter().map(|p| p.file_name().unwrap()).collect();
+        assert_eq!(names, ["a.mobli", "b.mobli", "a.mobli"]);
+        assert_eq!(Diagnostic::from(err).code, Some(codes::INCLUDE_CYCLE));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_memory_files() {
+        let mut files = MemoryFiles::new();
+        files.insert("lib/limit.mobli", "let limit = 100\n");
+        files.insert("lib/both.mobli", "include \"../lib/./limit.mobli\"\nlet k = secret(3)\n");
+        files.insert("main.mobli", "include \"lib/limit.mobli\"\ninclude \"lib/both.mobli\"\nk\n");
+        let unit = Unit::resolve_with("main.mobli", &files).unwrap();
+        assert_eq!(unit.source, "let limit = 100\n\n\nlet k = secret(3)\n\nk\n");
+        let err = Unit::resolve_with("missing.mobli", &files).unwrap_err();
+        assert!(matches!(err, IncludeError::Io { .. }), "{}", err);
+    }
+}
@@ -0,0 +1,1203 @@
+// SPDX-License-Identifier: MIT OR AGPL-3.0-or-later
+// SPDX-FileCopyrightText: 2024-2025 hyperpolymath
+
+//! Reference interpreters for oblivious IR and for the source AST.
+//!
+//! [`eval_ir`] evaluates IR with the same semantics as the emitted Rust
+//! runtime (arithmetic wrapping at each integer's width, `CtSelect`
+//! evaluating both arms)
+//! while tracking a secrecy label on every value. [`eval_ast`] evaluates
+//! the program as written, before the oblivious transform, so the two can
+//! be compared (see [`differential`](crate::differential)).
+
+use crate::ast::{Annotation, BinOp, Expr};
+use crate::desugar;
+use crate::ir::{ObliBinOp, ObliExpr, ObliUnaryOp};
+use crate::stack;
+use crate::symbol::Symbol;
+use crate::typeck::{Scalar, Width};
+use std::collections::HashMap;
+use std::fmt;
+use thiserror::Error;
+
+/// A runtime value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Value {
+    Int(i64),
+    /// An integer of a sized type, held as [`Width::value`] reads it
+    Sized(i64, Width),
+    Bool(bool),
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Int(n) => write!(f, "{}", n),
+            Value::Sized(n, width) => write!(f, "{}", width.value(*n)),
+            Value::Bool(b) => write!(f, "{}", b),
+        }
+    }
+}
+
+/// A value together with its secrecy label.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Labeled {
+    pub value: Value,
+    pub secret: bool,
+}
+
+impl Labeled {
+    pub fn public(value: Value) -> Self {
+        Self {
+            value,
+            secret: false,
+        }
+    }
+
+    pub fn secret(value: Value) -> Self {
+        Self {
+            value,
+            secret: true,
+        }
+    }
+}
+
+impl fmt::Display for Labeled {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let label = if self.secret { "secret" } else { "public" };
+        write!(f, "{} ({})", self.value, label)
+    }
+}
+
+/// Values of free variables supplied to a program.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Inputs {
+    values: HashMap<Symbol, Labeled>,
+}
+
+impl Inputs {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a public input.
+    pub fn public(mut self, name: &str, value: Value) -> Self {
+        self.values.insert(Symbol::from(name), Labeled::public(value));
+        self
+    }
+
+    /// Add a secret input.
+    pub fn secret(mut self, name: &str, value: Value) -> Self {
+        self.values.insert(Symbol::from(name), Labeled::secret(value));
+        self
+    }
+
+    pub fn get(&self, name: impl Into<Symbol>) -> Option<Labeled> {
+        self.values.get(&name.into()).copied()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (Symbol, &Labeled)> {
+        self.values.iter().map(|(name, value)| (*name, value))
+    }
+
+    /// The value of the input `name` a program declares of type `ty`; an
+    /// integer in range is a value of a sized type.
+    pub fn read(&self, name: Symbol, ty: Scalar) -> Result<Value, EvalError> {
+        let input = self
+            .get(name)
+            .ok_or_else(|| EvalError::MissingInput(name.to_string()))?;
+        match (input.value, ty) {
+            (Value::Int(_), Scalar::Int) | (Value::Bool(_), Scalar::Bool) => Ok(input.value),
+            (Value::Sized(_, width), Scalar::Sized(expected)) if width == expected => {
+                Ok(input.value)
+            }
+            (Value::Int(n), Scalar::Sized(width)) => {
+                let (min, max) = width.range();
+                if !(min..=max).contains(&i128::from(n)) {
+                    return Err(EvalError::TypeMismatch(width.name()));
+                }
+                Ok(Value::Sized(width.wrap(i128::from(n)), width))
+            }
+            (_, Scalar::Int) => Err(EvalError::TypeMismatch("int")),
+            (_, Scalar::Bool) => Err(EvalError::TypeMismatch("bool")),
+            (_, Scalar::Sized(width)) => Err(EvalError::TypeMismatch(width.name())),
+        }
+    }
+}
+
+#[derive(Error, Debug, Clone, PartialEq)]
+pub enum EvalError {
+    #[error("unbound variable `{0}`")]
+    UnboundVariable(String),
+    #[error("type mismatch: expected {0}")]
+    TypeMismatch(&'static str),
+    #[error("division by zero")]
+    DivisionByZero,
+    #[error("no value for input `{0}`")]
+    MissingInput(String),
+    #[error("assertion failed")]
+    AssertionFailed,
+    /// Only from [symbolic execution](crate::symbolic), which cannot tell
+    /// when a loop on a symbol stops.
+    #[error("a loop branched on symbols more than {0} times")]
+    UnboundedLoop(usize),
+}
+
+/// Evaluate an IR expression.
+pub fn eval_ir(expr: &ObliExpr, inputs: &Inputs) -> Result<Labeled, EvalError> {
+    let mut env = Env {
+        inputs,
+        scope: Vec::new(),
+        trace: None,
+        monitor: None,
+        depth: 0,
+        held: true,
+        secret_arms: 0,
+    };
+    let result = env.eval(expr);
+    env.checked(result)
+}
+
+/// Evaluate a program ending in `outputs(...)`: each named result, in
+/// order, with its own label.
+pub fn eval_ir_outputs(
+    expr: &ObliExpr,
+    inputs: &Inputs,
+) -> Result<Vec<(Symbol, Labeled)>, EvalError> {
+    let mut env = Env {
+        inputs,
+        scope: Vec::new(),
+        trace: None,
+        monitor: None,
+        depth: 0,
+        held: true,
+        secret_arms: 0,
+    };
+    let result = env.eval_outputs(expr);
+    env.checked(result)
+}
+
+/// A branch decision or division executed by [`eval_monitored`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Observation<'e> {
+    /// The `PubIf`, or the division or modulo `BinOp`.
+    pub expr: &'e ObliExpr,
+    /// Whether the condition, or an operand, was secret at run time.
+    pub secret: bool,
+}
+
+/// Evaluate an IR expression, recording every operation whose timing
+/// depends on its operands: `PubIf` branch decisions, and divisions and
+/// modulos. The IR has no memory accesses: array reads are desugared
+/// into selects before it is built.
+pub fn eval_monitored<'e>(
+    expr: &'e ObliExpr,
+    inputs: &Inputs,
+    observations: &mut Vec<Observation<'e>>,
+) -> Result<Labeled, EvalError> {
+    let mut env = Env {
+        inputs,
+        scope: Vec::new(),
+        trace: None,
+        monitor: Some(observations),
+        depth: 0,
+        held: true,
+        secret_arms: 0,
+    };
+    let result = env.eval(expr);
+    env.checked(result)
+}
+
+/// Evaluate a source AST directly: `if` runs only the taken branch, and a
+/// value is secret when it was computed from a secret.
+///
+/// Arrays follow the desugared program: every element of an array is
+/// evaluated, as are both arms of an `if` that yields an array. An index
+/// outside the array reads its last element, and the element read is
+/// secret when the index is secret and there was more than one to choose
+/// from. Arrays compared with `==` or `!=` are equal when every element
+/// is, and the result is secret when any element is. `sort` yields
+/// elements that are all secret when any input element is. Tuples are
+/// evaluated like arrays, each component keeping its own label.
+///
+/// A `lookup` evaluates its key and every entry and default, and its
+/// result is secret when the value chosen, the key, or any entry key
+/// compared before the match is.
+///
+/// A call evaluates the body of its function with the arguments bound to
+/// the parameters, as [inlined](crate::desugar::expand), and a `for` loop
+/// its body once per iteration. An integer converted implicitly is
+/// converted by the cast [`promote`](crate::desugar::promote) writes.
+pub fn eval_ast(expr: &Expr, inputs: &Inputs) -> Result<Labeled, EvalError> {
+    if desugar::needs_expanding(expr) {
+        return eval_ast(&desugar::expand(expr), inputs);
+    }
+    if desugar::needs_promoting(expr) {
+        return eval_ast(&desugar::promote(expr), inputs);
+    }
+    eval_ast_in(expr, inputs, &mut Vec::new())
+}
+
+/// Like [`eval_ast`], for a program ending in `outputs(...)`: each named
+/// result, in order, with its own label.
+pub fn eval_ast_outputs(
+    expr: &Expr,
+    inputs: &Inputs,
+) -> Result<Vec<(Symbol, Labeled)>, EvalError> {
+    if desugar::needs_expanding(expr) {
+        return eval_ast_outputs(&desugar::expand(expr), inputs);
+    }
+    if desugar::needs_promoting(expr) {
+        return eval_ast_outputs(&desugar::promote(expr), inputs);
+    }
+    match eval_bound(expr, inputs, &mut Vec::new())? {
+        Bound::Outputs(outputs) => Ok(outputs),
+        Bound::Scalar(_) | Bound::Array(_) => Err(EvalError::TypeMismatch("outputs")),
+    }
+}
+
+/// A value bound by a source `let`, or the named results of a program.
+#[derive(Debug, Clone)]
+enum Bound {
+    Scalar(Labeled),
+    Array(Vec<Labeled>),
+    Outputs(Vec<(Symbol, Labeled)>),
+}
+
+fn eval_ast_in(
+    expr: &Expr,
+    inputs: &Inputs,
+    scope: &mut Vec<(Symbol, Bound)>,
+) -> Result<Labeled, EvalError> {
+    match eval_bound(expr, inputs, scope)? {
+        Bound::Scalar(v) => Ok(v),
+        Bound::Array(_) | Bound::Outputs(_) => Err(EvalError::TypeMismatch("scalar")),
+    }
+}
+
+fn eval_array(
+    expr: &Expr,
+    inputs: &Inputs,
+    scope: &mut Vec<(Symbol, Bound)>,
+) -> Result<Vec<Labeled>, EvalError> {
+    match eval_bound(expr, inputs, scope)? {
+        Bound::Array(elements) => Ok(elements),
+        Bound::Scalar(_) | Bound::Outputs(_) => Err(EvalError::TypeMismatch("array")),
+    }
+}
+
+fn eval_bound(
+    expr: &Expr,
+    inputs: &Inputs,
+    scope: &mut Vec<(Symbol, Bound)>,
+) -> Result<Bound, EvalError> {
+    stack::guard(|| eval_bound_node(expr, inputs, scope))
+}
+
+fn eval_bound_node(
+    expr: &Expr,
+    inputs: &Inputs,
+    scope: &mut Vec<(Symbol, Bound)>,
+) -> Result<Bound, EvalError> {
+    let scalar = match expr {
+        Expr::Int(n) => Labeled::public(Value::Int(*n)),
+        Expr::Sized(n, width) => Labeled::public(Value::Sized(*n, *width)),
+        Expr::Bool(b) => Labeled::public(Value::Bool(*b)),
+        &Expr::Input { name, secret, ty } => Labeled {
+            value: inputs.read(name, ty)?,
+            secret,
+        },
+        Expr::Var(name) => {
+            return scope
+                .iter()
+                .rev()
+                .find(|(n, _)| n == name)
+                .map(|(_, v)| v.clone())
+                .or_else(|| inputs.get(*name).map(Bound::Scalar))
+                .ok_or_else(|| EvalError::UnboundVariable(name.to_string()))
+        }
+        Expr::Secret(inner) => {
+            return Ok(match eval_bound(inner, inputs, scope)? {
+                Bound::Scalar(v) => Bound::Scalar(Labeled::secret(v.value)),
+                Bound::Array(elements) => {
+                    Bound::Array(elements.iter().map(|e| Labeled::secret(e.value)).collect())
+                }
+                Bound::Outputs(_) => return Err(EvalError::TypeMismatch("scalar")),
+            })
+        }
+        Expr::BinOp { op, left, right } => {
+            let l = eval_bound(left, inputs, scope)?;
+            let (l, r) = match (l, eval_bound(right, inputs, scope)?) {
+                (Bound::Scalar(l), Bound::Scalar(r)) => (l, r),
+                (Bound::Array(l), Bound::Array(r)) if l.len() == r.len() => {
+                    let equal = l.iter().zip(&r).all(|(l, r)| l.value == r.value);
+                    let value = match op {
+                        BinOp::Eq => equal,
+                        BinOp::Ne => !equal,
+                        _ => return Err(EvalError::TypeMismatch("scalar")),
+                    };
+                    return Ok(Bound::Scalar(Labeled {
+                        value: Value::Bool(value),
+                        secret: l.iter().chain(&r).any(|v| v.secret),
+                    }));
+                }
+                _ => return Err(EvalError::TypeMismatch("scalar")),
+            };
+            Labeled {
+                value: apply_binop(&ObliBinOp::from(op), l.value, r.value)?,
+                secret: l.secret || r.secret,
+            }
+        }
+        Expr::Declassify(inner) => Labeled::public(eval_ast_in(inner, inputs, scope)?.value),
+        Expr::UnaryOp { op, expr } => {
+            let v = eval_ast_in(expr, inputs, scope)?;
+            Labeled {
+                value: apply_unaryop(&ObliUnaryOp::from(op), v.value)?,
+                secret: v.secret,
+            }
+        }
+        Expr::Cast { expr, ty } => {
+            let v = eval_ast_in(expr, inputs, scope)?;
+            Labeled {
+                value: apply_cast(*ty, v.value)?,
+                secret: v.secret,
+            }
+        }
+        Expr::If {
+            cond,
+            then_branch,
+            else_branch,
+        } => {
+            let c = eval_ast_in(cond, inputs, scope)?;
+            let (taken, other) = if as_bool(c.value)? {
+                (then_branch, else_branch)
+            } else {
+                (else_branch, then_branch)
+            };
+            let label = |v: Labeled| Labeled {
+                value: v.value,
+                secret: v.secret || c.secret,
+            };
+            return Ok(match eval_bound(taken, inputs, scope)? {
+                Bound::Scalar(v) => Bound::Scalar(label(v)),
+                Bound::Array(elements) => {
+                    // The assertions of the other arm do not count
+                    match eval_array(other, inputs, scope) {
+                        Ok(_) | Err(EvalError::AssertionFailed) => {}
+                        Err(error) => return Err(error),
+                    }
+                    Bound::Array(elements.into_iter().map(label).collect())
+                }
+                Bound::Outputs(_) => return Err(EvalError::TypeMismatch("scalar")),
+            });
+        }
+        Expr::Let {
+            name,
+            ty,
+            value,
+            body,
+            ..
+        } => {
+            let mut v = eval_bound(value, inputs, scope)?;
+            // A `secret` annotation labels the value as `secret(...)` does
+            if let (Some(Annotation { secret: Some(true), .. }), Bound::Scalar(s)) = (ty, &v) {
+                v = Bound::Scalar(Labeled::secret(s.value));
+            }
+            scope.push((*name, v));
+            let result = eval_bound(body, inputs, scope);
+            scope.pop();
+            return result;
+        }
+        Expr::Assert { cond, body } => {
+            if !as_bool(eval_ast_in(cond, inputs, scope)?.value)? {
+                return Err(EvalError::AssertionFailed);
+            }
+            return eval_bound(body, inputs, scope);
+        }
+        Expr::Array(elements) | Expr::Tuple(elements) => {
+            let elements = elements.iter().map(|e| eval_ast_in(e, inputs, scope));
+            return Ok(Bound::Array(elements.collect::<Result<_, _>>()?));
+        }
+        Expr::LetTuple { names, value, body } => {
+            let components = eval_array(value, inputs, scope)?;
+            if components.len() != names.len() {
+                return Err(EvalError::TypeMismatch("tuple"));
+            }
+            let outer = scope.len();
+            scope.extend(names.iter().zip(components).map(|(n, c)| (*n, Bound::Scalar(c))));
+            let result = eval_bound(body, inputs, scope);
+            scope.truncate(outer);
+            return result;
+        }
+        Expr::SwapIf { cond, left, right } => {
+            let c = eval_ast_in(cond, inputs, scope)?;
+            let mut pair = [left, right].map(|e| eval_ast_in(e, inputs, scope));
+            if as_bool(c.value)? {
+                pair.swap(0, 1);
+            }
+            // Both components are selected under the condition
+            let secret = c.secret || pair.iter().flatten().any(|e| e.secret);
+            let pair = pair.into_iter().map(|e| e.map(|e| Labeled { secret, ..e }));
+            return Ok(Bound::Array(pair.collect::<Result<_, _>>()?));
+        }
+        Expr::Clamp { value, lo, hi } | Expr::InRange { value, lo, hi } => {
+            let [v, lo, hi] = [value, lo, hi].map(|e| eval_ast_in(e, inputs, scope));
+            let (v, lo, hi) = (v?, lo?, hi?);
+            let compare = |op, l: Labeled, r: Labeled| as_bool(apply_binop(&op, l.value, r.value)?);
+            let value = match expr {
+                Expr::Clamp { .. } if compare(ObliBinOp::CtLt, v, lo)? => lo.value,
+                Expr::Clamp { .. } if compare(ObliBinOp::CtGt, v, hi)? => hi.value,
+                Expr::Clamp { .. } => v.value,
+                _ => {
+                    let above = compare(ObliBinOp::CtLe, lo, v)?;
+                    Value::Bool(compare(ObliBinOp::CtLe, v, hi)? && above)
+                }
+            };
+            // Every comparison runs, and selects among all three
+            Labeled {
+                value,
+                secret: v.secret || lo.secret || hi.secret,
+            }
+        }
+        Expr::Pow { base, exp } => {
+            let base = eval_ast_in(base, inputs, scope)?;
+            let n = as_int(eval_ast_in(exp, inputs, scope)?.value)?;
+            let mut value = match base.value {
+                Value::Sized(_, width) => Value::Sized(1, width),
+                _ => Value::Int(1),
+            };
+            // Square-and-multiply, as the chain it lowers to
+            for bit in (0..i64::BITS - n.max(0).leading_zeros()).rev() {
+                value = apply_binop(&ObliBinOp::CtMul, value, value)?;
+                if n >> bit & 1 == 1 {
+                    value = apply_binop(&ObliBinOp::CtMul, value, base.value)?;
+                }
+            }
+            Labeled {
+                value,
+                secret: base.secret && n > 0,
+            }
+        }
+        Expr::Some(inner) => {
+            let value = eval_ast_in(inner, inputs, scope)?;
+            return Ok(Bound::Array(vec![Labeled::public(Value::Bool(true)), value]));
+        }
+        Expr::None => {
+            // The value of an absent option is never read
+            let absent = [Value::Bool(false), Value::Int(0)].map(Labeled::public);
+            return Ok(Bound::Array(absent.to_vec()));
+        }
+        Expr::UnwrapOr { option, default } => {
+            let &[present, value] = &eval_array(option, inputs, scope)?[..] else {
+                return Err(EvalError::TypeMismatch("option"));
+            };
+            // As the `if` it lowers to: only the default of an absent one runs
+            let taken = if as_bool(present.value)? {
+                value
+            } else {
+                eval_ast_in(default, inputs, scope)?
+            };
+            Labeled {
+                value: taken.value,
+                secret: taken.secret || present.secret,
+            }
+        }
+        Expr::Bytes(bytes) => {
+            let elements = bytes.iter().map(|&b| Labeled::public(Value::Int(b.into())));
+            return Ok(Bound::Array(elements.collect()));
+        }
+        Expr::Sort(inner) => {
+            let elements = eval_array(inner, inputs, scope)?;
+            // Every output of a sorting network depends on every input
+            let secret = elements.iter().any(|e| e.secret);
+            let keys = elements.iter().map(|e| Ok((as_wide(e.value)?, e.value)));
+            let mut keys = keys.collect::<Result<Vec<_>, _>>()?;
+            keys.sort_unstable_by_key(|(k, _)| *k);
+            let sorted = keys.into_iter().map(|(_, value)| Labeled { value, secret });
+            return Ok(Bound::Array(sorted.collect()));
+        }
+        Expr::Index { array, index } => {
+            let elements = eval_array(array, inputs, scope)?;
+            let i = eval_ast_in(index, inputs, scope)?;
+            let Value::Int(k) = i.value else {
+                return Err(EvalError::TypeMismatch("int"));
+            };
+            let last = elements.len().checked_sub(1).ok_or(EvalError::TypeMismatch("array"))?;
+            let element = usize::try_from(k).ok().and_then(|k| elements.get(k));
+            let element = element.unwrap_or(&elements[last]);
+            Labeled {
+                value: element.value,
+                secret: element.secret || (i.secret && last > 0),
+            }
+        }
+        Expr::Match {
+            scrutinee,
+            arms,
+            default,
+        } => {
+            let scrutinee = eval_ast_in(scrutinee, inputs, scope)?;
+            // A match over every variant takes its last arm if no other
+            let (mut taken, arms) = match default {
+                Some(default) => (default.as_ref(), &arms[..]),
+                None => {
+                    let ((_, last), arms) = arms.split_last().expect("a match has an arm");
+                    (last, arms)
+                }
+            };
+            for (pattern, arm) in arms {
+                let pattern = eval_ast_in(pattern, inputs, scope)?;
+                if as_bool(apply_binop(&ObliBinOp::CtEq, scrutinee.value, pattern.value)?)? {
+                    taken = arm;
+                    break;
+                }
+            }
+            let v = eval_ast_in(taken, inputs, scope)?;
+            Labeled {
+                value: v.value,
+                secret: v.secret || scrutinee.secret,
+            }
+        }
+        Expr::Lookup {
+            key,
+            entries,
+            default,
+        } => {
+            let key = eval_ast_in(key, inputs, scope)?;
+            let mut table = Vec::with_capacity(entries.len());
+            for (k, v) in entries {
+                let k = eval_ast_in(k, inputs, scope)?;
+                table.push((k, eval_ast_in(v, inputs, scope)?));
+            }
+            let mut chosen = eval_ast_in(default, inputs, scope)?;
+            let mut secret = key.secret;
+            for (k, v) in table {
+                secret |= k.secret;
+                if as_bool(apply_binop(&ObliBinOp::CtEq, key.value, k.value)?)? {
+                    chosen = v;
+                    break;
+                }
+            }
+            Labeled {
+                value: chosen.value,
+                secret: chosen.secret || secret,
+            }
+        }
+        Expr::While {
+            cond,
+            acc,
+            init,
+            body,
+        } => {
+            // As for `if`, the result depends on every condition checked
+            let mut v = eval_ast_in(init, inputs, scope)?;
+            let mut secret = false;
+            loop {
+                scope.push((*acc, Bound::Scalar(v)));
+                let next = iterate_ast(cond, body, inputs, scope, &mut secret);
+                scope.pop();
+                match next? {
+                    Some(next) => v = next,
+                    None => break,
+                }
+            }
+            Labeled {
+                value: v.value,
+                secret: v.secret || secret,
+            }
+        }
+        Expr::Outputs(outputs) => {
+            let outputs = outputs
+                .iter()
+                .map(|(name, value)| Ok((*name, eval_ast_in(value, inputs, scope)?)))
+                .collect::<Result<_, EvalError>>()?;
+            return Ok(Bound::Outputs(outputs));
+        }
+        Expr::Fn { .. }
+        | Expr::Call { .. }
+        | Expr::Lambda { .. }
+        | Expr::Map { .. }
+        | Expr::Fold { .. }
+        | Expr::Const { .. }
+        | Expr::Enum { .. }
+        | Expr::For { .. }
+        | Expr::Assign { .. } => unreachable!("expanded by `eval_ast`"),
+    };
+    Ok(Bound::Scalar(scalar))
+}
+
+/// One iteration of a `while` loop: the next value of its accumulator, or
+/// `None` once the condition is false. Sets `secret` if the condition is.
+fn iterate_ast(
+    cond: &Expr,
+    body: &Expr,
+    inputs: &Inputs,
+    scope: &mut Vec<(Symbol, Bound)>,
+    secret: &mut bool,
+) -> Result<Option<Labeled>, EvalError> {
+    let c = eval_ast_in(cond, inputs, scope)?;
+    *secret |= c.secret;
+    if !as_bool(c.value)? {
+        return Ok(None);
+    }
+    eval_ast_in(body, inputs, scope).map(Some)
+}
+
+/// Something the interpreter did, recorded by [`eval_traced`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct TraceEvent<'e> {
+    pub expr: &'e ObliExpr,
+    /// Nesting depth of `expr` in the evaluation.
+    pub depth: usize,
+    /// `None` when evaluation of `expr` starts, its value when it ends.
+    pub value: Option<Labeled>,
+    /// Variables in scope (innermost last).
+    pub scope: Vec<(Symbol, Labeled)>,
+}
+
+/// Evaluate an IR expression, recording entry to and exit from every node
+/// evaluated (untaken `PubIf` branches do not appear).
+pub fn eval_traced<'e>(
+    expr: &'e ObliExpr,
+    inputs: &Inputs,
+    trace: &mut Vec<TraceEvent<'e>>,
+) -> Result<Labeled, EvalError> {
+    let mut env = Env {
+        inputs,
+        scope: Vec::new(),
+        trace: Some(trace),
+        monitor: None,
+        depth: 0,
+        held: true,
+        secret_arms: 0,
+    };
+    let result = env.eval(expr);
+    env.checked(result)
+}
+
+struct Env<'a, 'e> {
+    inputs: &'a Inputs,
+    scope: Vec<(Symbol, Labeled)>,
+    trace: Option<&'a mut Vec<TraceEvent<'e>>>,
+    monitor: Option<&'a mut Vec<Observation<'e>>>,
+    depth: usize,
+    /// Whether every assertion checked only at the end has held so far.
+    held: bool,
+    /// How many `CtSelect` arms the node being evaluated is in.
+    secret_arms: usize,
+}
+
+impl<'a, 'e> Env<'a, 'e> {
+    fn lookup(&self, name: Symbol) -> Result<Labeled, EvalError> {
+        self.scope
+            .iter()
+            .rev()
+            .find(|(n, _)| *n == name)
+            .map(|(_, v)| *v)
+            .or_else(|| self.inputs.get(name))
+            .ok_or_else(|| EvalError::UnboundVariable(name.to_string()))
+    }
+
+    /// `result`, unless an assertion checked only at the end failed.
+    fn checked<T>(&self, result: Result<T, EvalError>) -> Result<T, EvalError> {
+        let result = result?;
+        if !self.held {
+            return Err(EvalError::AssertionFailed);
+        }
+        Ok(result)
+    }
+
+    fn observe(&mut self, expr: &'e ObliExpr, secret: bool) {
+        if let Some(monitor) = self.monitor.as_deref_mut() {
+            monitor.push(Observation { expr, secret });
+        }
+    }
+
+    fn eval(&mut self, expr: &'e ObliExpr) -> Result<Labeled, EvalError> {
+        let Some(trace) = self.trace.as_deref_mut() else {
+            return stack::guard(|| self.eval_node(expr));
+        };
+        let depth = self.depth;
+        trace.push(TraceEvent {
+            expr,
+            depth,
+            value: None,
+            scope: self.scope.clone(),
+        });
+        self.depth += 1;
+        let result = stack::guard(|| self.eval_node(expr));
+        self.depth -= 1;
+        let result = result?;
+        if let Some(trace) = self.trace.as_deref_mut() {
+            trace.push(TraceEvent {
+                expr,
+                depth,
+                value: Some(result),
+                scope: self.scope.clone(),
+            });
+        }
+        Ok(result)
+    }
+
+    fn eval_node(&mut self, expr: &'e ObliExpr) -> Result<Labeled, EvalError> {
+        match expr {
+            ObliExpr::PubInt(n) => Ok(Labeled::public(Value::Int(*n))),
+            ObliExpr::PubBool(b) => Ok(Labeled::public(Value::Bool(*b))),
+            ObliExpr::SecretInt(n) => Ok(Labeled::secret(Value::Int(*n))),
+            ObliExpr::SecretBool(b) => Ok(Labeled::secret(Value::Bool(*b))),
+            ObliExpr::Sized {
+                value,
+                width,
+                is_secret,
+            } => Ok(Labeled {
+                value: Value::Sized(*value, *width),
+                secret: *is_secret,
+            }),
+            &ObliExpr::Input {
+                name,
+                ty,
+                is_secret,
+            } => Ok(Labeled {
+                value: self.inputs.read(name, ty)?,
+                secret: is_secret,
+            }),
+            ObliExpr::Var { name, is_secret } => {
+                let v = self.lookup(*name)?;
+                Ok(Labeled {
+                    value: v.value,
+                    secret: v.secret || *is_secret,
+                })
+            }
+            ObliExpr::BinOp {
+                op,
+                left,
+                right,
+                is_secret,
+            } => {
+                let l = self.eval(left)?;
+                let r = self.eval(right)?;
+                if matches!(op, ObliBinOp::CtDiv | ObliBinOp::CtMod) {
+                    self.observe(expr, l.secret || r.secret);
+                }
+                Ok(Labeled {
+                    value: apply_binop(op, l.value, r.value)?,
+                    secret: l.secret || r.secret || *is_secret,
+                })
+            }
+            ObliExpr::UnaryOp {
+                op,
+                expr,
+                is_secret,
+            } => {
+                let v = self.eval(expr)?;
+                Ok(Labeled {
+                    value: apply_unaryop(op, v.value)?,
+                    secret: v.secret || *is_secret,
+                })
+            }
+            ObliExpr::CtCast {
+                expr, to, is_secret, ..
+            } => {
+                let v = self.eval(expr)?;
+                Ok(Labeled {
+                    value: apply_cast(*to, v.value)?,
+                    secret: v.secret || *is_secret,
+                })
+            }
+            ObliExpr::CtSelect {
+                cond,
+                then_val,
+                else_val,
+            } => {
+                // Both arms are always evaluated, as in the emitted code;
+                // only the assertions of the arm chosen count
+                let c = as_bool(self.eval(cond)?.value)?;
+                let outer = std::mem::replace(&mut self.held, true);
+                self.secret_arms += 1;
+                let t = self.eval(then_val);
+                let then_held = std::mem::replace(&mut self.held, true);
+                let e = self.eval(else_val);
+                self.secret_arms -= 1;
+                self.held = outer && if c { then_held } else { self.held };
+                let (t, e) = (t?, e?);
+                let chosen = if c { t } else { e };
+                Ok(Labeled::secret(chosen.value))
+            }
+            ObliExpr::PubIf {
+                cond,
+                then_branch,
+                else_branch,
+            } => {
+                let c = self.eval(cond)?;
+                self.observe(expr, c.secret);
+                let taken = if as_bool(c.value)? {
+                    self.eval(then_branch)?
+                } else {
+                    self.eval(else_branch)?
+                };
+                Ok(Labeled {
+                    value: taken.value,
+                    secret: taken.secret || expr.is_secret(),
+                })
+            }
+            ObliExpr::Let {
+                name, value, body, ..
+            } => {
+                let v = self.eval(value)?;
+                self.scope.push((*name, v));
+                let result = self.eval(body);
+                self.scope.pop();
+                result
+            }
+            ObliExpr::Assert { cond, body } => {
+                let c = self.eval(cond)?;
+                if cond.is_secret() || self.secret_arms > 0 {
+                    // Checked when the result is revealed
+                    self.held &= as_bool(c.value)?;
+                } else if !as_bool(c.value)? {
+                    return Err(EvalError::AssertionFailed);
+                }
+                self.eval(body)
+            }
+            ObliExpr::Declassify(inner) => Ok(Labeled::public(self.eval(inner)?.value)),
+            // Only a program's result, read by `eval_ir_outputs`
+            ObliExpr::Outputs(_) => Err(EvalError::TypeMismatch("scalar")),
+            ObliExpr::Table { values, index } => {
+                let k = as_int(self.eval(index)?.value)?;
+                let last = values.len() - 1;
+                let k = usize::try_from(k).ok().filter(|&k| k < last).unwrap_or(last);
+                Ok(Labeled::secret(Value::Int(values[k])))
+            }
+            ObliExpr::While {
+                cond,
+                acc,
+                init,
+                body,
+                is_secret,
+            } => {
+                let mut v = self.eval(init)?;
+                loop {
+                    self.scope.push((*acc, v));
+                    let next = self.iterate(expr, cond, body);
+                    self.scope.pop();
+                    match next? {
+                        Some(next) => v = next,
+                        None => break,
+                    }
+                }
+                Ok(Labeled {
+                    value: v.value,
+                    secret: v.secret || *is_secret,
+                })
+            }
+            ObliExpr::CtSwap { body, .. } => {
+                self.swap(expr)?;
+                let result = self.eval(body);
+                self.scope.truncate(self.scope.len() - 2);
+                result
+            }
+        }
+    }
+
+    /// Bind the names of the swap `expr`; a swap on a public condition
+    /// is emitted as a branch, so it is observed like one.
+    fn swap(&mut self, expr: &'e ObliExpr) -> Result<(), EvalError> {
+        let ObliExpr::CtSwap {
+            cond,
+            left,
+            right,
+            names,
+            is_secret,
+            ..
+        } = expr
+        else {
+            unreachable!("called on swaps")
+        };
+        let c = self.eval(cond)?;
+        if !cond.is_secret() {
+            self.observe(expr, c.secret);
+        }
+        let mut pair = [self.eval(left)?, self.eval(right)?];
+        if as_bool(c.value)? {
+            pair.swap(0, 1);
+        }
+        let secret = c.secret || pair.iter().any(|v| v.secret) || *is_secret;
+        for (name, v) in names.iter().zip(pair) {
+            self.scope.push((*name, Labeled { secret, ..v }));
+        }
+        Ok(())
+    }
+
+    /// One iteration of the `while` loop `expr`: the next value of its
+    /// accumulator, or `None` once the condition is false.
+    fn iterate(
+        &mut self,
+        expr: &'e ObliExpr,
+        cond: &'e ObliExpr,
+        body: &'e ObliExpr,
+    ) -> Result<Option<Labeled>, EvalError> {
+        let c = self.eval(cond)?;
+        self.observe(expr, c.secret);
+        if !as_bool(c.value)? {
+            return Ok(None);
+        }
+        self.eval(body).map(Some)
+    }
+
+    fn eval_outputs(&mut self, expr: &'e ObliExpr) -> Result<Vec<(Symbol, Labeled)>, EvalError> {
+        match expr {
+            ObliExpr::Let {
+                name, value, body, ..
+            } => {
+                let v = self.eval(value)?;
+                self.scope.push((*name, v));
+                let result = stack::guard(|| self.eval_outputs(body));
+                self.scope.pop();
+                result
+            }
+            ObliExpr::CtSwap { body, .. } => {
+                self.swap(expr)?;
+                let result = self.eval_outputs(body);
+                self.scope.truncate(self.scope.len() - 2);
+                result
+            }
+            ObliExpr::Assert { cond, body } => {
+                let c = self.eval(cond)?;
+                if cond.is_secret() {
+                    // Checked when the results are revealed
+                    self.held &= as_bool(c.value)?;
+                } else if !as_bool(c.value)? {
+                    return Err(EvalError::AssertionFailed);
+                }
+                self.eval_outputs(body)
+            }
+            ObliExpr::Outputs(outputs) => outputs
+                .iter()
+                .map(|(name, value)| Ok((*name, self.eval(value)?)))
+                .collect(),
+            _ => Err(EvalError::TypeMismatch("outputs")),
+        }
+    }
+}
+
+fn as_int(v: Value) -> Result<i64, EvalError> {
+    match v {
+        Value::Int(n) => Ok(n),
+        Value::Sized(..) | Value::Bool(_) => Err(EvalError::TypeMismatch("int")),
+    }
+}
+
+/// An integer of any type, widened to hold every one.
+fn as_wide(v: Value) -> Result<i128, EvalError> {
+    match v {
+        Value::Int(n) => Ok(n.into()),
+        Value::Sized(n, width) => Ok(width.value(n)),
+        Value::Bool(_) => Err(EvalError::TypeMismatch("int")),
+    }
+}
+
+fn as_bool(v: Value) -> Result<bool, EvalError> {
+    match v {
+        Value::Bool(b) => Ok(b),
+        Value::Int(_) | Value::Sized(..) => Err(EvalError::TypeMismatch("bool")),
+    }
+}
+
+/// Apply a binary operator with runtime semantics.
+pub fn apply_binop(op: &ObliBinOp, l: Value, r: Value) -> Result<Value, EvalError> {
+    if let (Value::Sized(_, width), _) | (_, Value::Sized(_, width)) = (l, r) {
+        return apply_sized(op, width, as_wide(l)?, as_wide(r)?);
+    }
+    Ok(match op {
+        ObliBinOp::CtAdd => Value::Int(as_int(l)?.wrapping_add(as_int(r)?)),
+        ObliBinOp::CtSub => Value::Int(as_int(l)?.wrapping_sub(as_int(r)?)),
+        ObliBinOp::CtMul => Value::Int(as_int(l)?.wrapping_mul(as_int(r)?)),
+        ObliBinOp::CtAddSat => Value::Int(as_int(l)?.saturating_add(as_int(r)?)),
+        ObliBinOp::CtSubSat => Value::Int(as_int(l)?.saturating_sub(as_int(r)?)),
+        ObliBinOp::CtMulSat => Value::Int(as_int(l)?.saturating_mul(as_int(r)?)),
+        ObliBinOp::CtDiv | ObliBinOp::CtMod => {
+            let (l, r) = (as_int(l)?, as_int(r)?);
+            if r == 0 {
+                return Err(EvalError::DivisionByZero);
+            }
+            if *op == ObliBinOp::CtDiv {
+                Value::Int(l.wrapping_div(r))
+            } else {
+                Value::Int(l.wrapping_rem(r))
+            }
+        }
+        ObliBinOp::CtBitAnd => Value::Int(as_int(l)? & as_int(r)?),
+        ObliBinOp::CtBitOr => Value::Int(as_int(l)? | as_int(r)?),
+        ObliBinOp::CtBitXor => Value::Int(as_int(l)? ^ as_int(r)?),
+        ObliBinOp::CtShl => Value::Int(as_int(l)?.wrapping_shl(as_int(r)? as u32)),
+        ObliBinOp::CtShr => Value::Int(as_int(l)?.wrapping_shr(as_int(r)? as u32)),
+        ObliBinOp::CtEq => Value::Bool(l == r),
+        ObliBinOp::CtNe => Value::Bool(l != r),
+        ObliBinOp::CtLt => Value::Bool(as_int(l)? < as_int(r)?),
+        ObliBinOp::CtLe => Value::Bool(as_int(l)? <= as_int(r)?),
+        ObliBinOp::CtGt => Value::Bool(as_int(l)? > as_int(r)?),
+        ObliBinOp::CtGe => Value::Bool(as_int(l)? >= as_int(r)?),
+        ObliBinOp::CtAnd => Value::Bool(as_bool(l)? & as_bool(r)?),
+        ObliBinOp::CtOr => Value::Bool(as_bool(l)? | as_bool(r)?),
+    })
+}
+
+/// As [`apply_binop`], for widened operands of the sized type `width`:
+/// exact in `i128`, then wrapped or clamped to the width.
+fn apply_sized(op: &ObliBinOp, width: Width, l: i128, r: i128) -> Result<Value, EvalError> {
+    let wrap = |n| Value::Sized(width.wrap(n), width);
+    let saturate = |n| Value::Sized(width.saturate(n), width);
+    // Amounts are masked to the width, as Rust's `wrapping_shl` does
+    let amount = r as u32 & (width.bits() - 1);
+    Ok(match op {
+        ObliBinOp::CtAdd => wrap(l + r),
+        ObliBinOp::CtSub => wrap(l - r),
+        // Two `u64`s can overflow `i128`, but only in bits above the width
+        ObliBinOp::CtMul => wrap(l.wrapping_mul(r)),
+        ObliBinOp::CtAddSat => saturate(l + r),
+        ObliBinOp::CtSubSat => saturate(l - r),
+        ObliBinOp::CtMulSat => saturate(l.saturating_mul(r)),
+        ObliBinOp::CtDiv | ObliBinOp::CtMod if r == 0 => return Err(EvalError::DivisionByZero),
+        ObliBinOp::CtDiv => wrap(l / r),
+        ObliBinOp::CtMod => wrap(l % r),
+        ObliBinOp::CtBitAnd => wrap(l & r),
+        ObliBinOp::CtBitOr => wrap(l | r),
+        ObliBinOp::CtBitXor => wrap(l ^ r),
+        ObliBinOp::CtShl => wrap(l << amount),
+        ObliBinOp::CtShr => wrap(l >> amount),
+        ObliBinOp::CtEq => Value::Bool(l == r),
+        ObliBinOp::CtNe => Value::Bool(l != r),
+        ObliBinOp::CtLt => Value::Bool(l < r),
+        ObliBinOp::CtLe => Value::Bool(l <= r),
+        ObliBinOp::CtGt => Value::Bool(l > r),
+        ObliBinOp::CtGe => Value::Bool(l >= r),
+        ObliBinOp::CtAnd | ObliBinOp::CtOr => return Err(EvalError::TypeMismatch("bool")),
+    })
+}
+
+/// Apply a unary operator with runtime semantics.
+pub fn apply_unaryop(op: &ObliUnaryOp, v: Value) -> Result<Value, EvalError> {
+    Ok(match op {
+        ObliUnaryOp::CtNeg => match v {
+            Value::Sized(n, width) => Value::Sized(width.wrap(-width.value(n)), width),
+            _ => Value::Int(as_int(v)?.wrapping_neg()),
+        },
+        ObliUnaryOp::CtNot => Value::Bool(!as_bool(v)?),
+        ObliUnaryOp::CtToInt => Value::Int(as_bool(v)?.into()),
+        ObliUnaryOp::CtToBool => match v {
+            Value::Sized(n, _) => Value::Bool(n != 0),
+            _ => Value::Bool(as_int(v)? != 0),
+        },
+    })
+}
+
+/// Convert an integer to the integer type `to` as Rust's `as` does,
+/// truncating or extending it.
+pub fn apply_cast(to: Scalar, v: Value) -> Result<Value, EvalError> {
+    let n = as_wide(v)?;
+    Ok(match to {
+        Scalar::Int => Value::Int(n as i64),
+        Scalar::Sized(width) => Value::Sized(width.wrap(n), width),
+        Scalar::Bool => return Err(EvalError::TypeMismatch("int")),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transpile_to_ir;
+
+    fn eval(src: &str) -> Labeled {
+        eval_ir(&transpile_to_ir(src).unwrap(), &Inputs::new()).unwrap()
+    }
+
+    #[test]
+    fn test_public_arithmetic() {
+        assert_eq!(eval("1 + 2 * 3"), Labeled::public(Value::Int(7)));
+    }
+
+    #[test]
+    fn test_secret_select() {
+        assert_eq!(
+            eval("let x = secret(5) if x > 3 then secret(1) else secret(0)"),
+            Labeled::secret(Value::Int(1))
+        );
+    }
+
+    #[test]
+    fn test_sized_arithmetic_wraps_at_width() {
+        assert_eq!(eval("200u8 + 100u8"), Labeled::public(Value::Sized(44, Width::U8)));
+        assert_eq!(eval("200u8 +| 100u8"), Labeled::public(Value::Sized(255, Width::U8)));
+        assert_eq!(eval("1u16 - 2u16"), Labeled::public(Value::Sized(65535, Width::U16)));
+        assert_eq!(eval("-(1u64)").value.to_string(), u64::MAX.to_string());
+        assert_eq!(eval("3u8 > 250u8"), Labeled::public(Value::Bool(false)));
+    }
+
+    #[test]
+    fn test_casts() {
+        assert_eq!(eval("secret(300) as u8"), Labeled::secret(Value::Sized(44, Width::U8)));
+        assert_eq!(eval("-1 as u8"), Labeled::public(Value::Sized(255, Width::U8)));
+        assert_eq!(eval("255u8 as i32"), Labeled::public(Value::Sized(255, Width::I32)));
+        let max = Width::U64.wrap(u64::MAX.into());
+        assert_eq!(eval("-1i32 as u64"), Labeled::public(Value::Sized(max, Width::U64)));
+        assert_eq!(eval("-1i32 as u64 as int"), Labeled::public(Value::Int(-1)));
+    }
+
+    #[test]
+    fn test_conversions() {
+        assert_eq!(eval("to_int(secret(2) > 1) + to_int(false)"), Labeled::secret(Value::Int(1)));
+        assert_eq!(eval("to_bool(0u8)"), Labeled::public(Value::Bool(false)));
+        assert_eq!(eval("to_bool(-3)"), Labeled::public(Value::Bool(true)));
+    }
+
+    #[test]
+    fn test_inputs_and_errors() {
+        let ir = transpile_to_ir("k * 2").unwrap();
+        let inputs = Inputs::new().secret("k", Value::Int(21));
+        assert_eq!(eval_ir(&ir, &inputs), Ok(Labeled::secret(Value::Int(42))));
+        assert_eq!(
+            eval_ir(&ir, &Inputs::new()),
+            Err(EvalError::UnboundVariable("k".to_string()))
+        );
+        let ir = transpile_to_ir("1 / 0").unwrap();
+        assert_eq!(eval_ir(&ir, &Inputs::new()), Err(EvalError::DivisionByZero));
+    }
+
+    #[test]
+    fn test_outputs() {
+        let ir = transpile_to_ir("let n = 3 outputs(total = k * n, count = n + 1)").unwrap();
+        let inputs = Inputs::new().secret("k", Value::Int(7));
+        assert_eq!(
+            eval_ir_outputs(&ir, &inputs),
+            Ok(vec![
+                (Symbol::from("total"), Labeled::secret(Value::Int(21))),
+                (Symbol::from("count"), Labeled::public(Value::Int(4))),
+            ])
+        );
+        assert_eq!(eval_ir(&ir, &inputs), Err(EvalError::TypeMismatch("scalar")));
+    }
+
+    #[test]
+    fn test_eval_ast() {
+        let tokens = crate::Lexer::new("if k > 3 then 1 / 0 else -k").tokenize().unwrap().0;
+        let ast = crate::Parser::new(&tokens).parse().unwrap();
+        // Only the taken branch runs, and the result depends on a secret
+        let inputs = Inputs::new().secret("k", Value::Int(2));
+        assert_eq!(eval_ast(&ast, &inputs), Ok(Labeled::secret(Value::Int(-2))));
+        let inputs = Inputs::new().public("k", Value::Int(5));
+        assert_eq!(eval_ast(&ast, &inputs), Err(EvalError::DivisionByZero));
+    }
+
+    #[test]
+    fn test_assertions() {
+        let failed = Err(EvalError::AssertionFailed);
+        let eval = |src| eval_ir(&transpile_to_ir(src).unwrap(), &Inputs::new());
+        assert_eq!(eval("assert(2 > 1) 3"), Ok(Labeled::public(Value::Int(3))));
+        assert_eq!(eval("assert(1 > 2) 3"), failed);
+        assert_eq!(eval("let k = secret(1) assert(k > 1) 3"), failed);
+        // Only the assertions of the arm chosen count
+        let source = "let k = secret(5) if k > 3 then 1 else (assert(k < 3) 0)";
+        assert_eq!(eval(source), Ok(Labeled::secret(Value::Int(1))));
+        let source = "let k = secret(1) if k > 3 then 1 else (assert(k > 3) 0)";
+        assert_eq!(eval(source), failed);
+    }
+}
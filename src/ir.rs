@@ -5,11 +5,21 @@
 //!
 //! This IR represents programs where all operations are constant-time.
 //! Branching on secrets is replaced with constant-time selection.
+//!
+//! [`ObliExpr`] displays as a compact s-expression in which `:s` marks a
+//! secret node, e.g. `(let k:s 5:s (ct_select (ct_gt:s k:s 3) k:s 0))`.
+//! The format is stable, so tests may compare against it; it changes only
+//! when the IR does.
 
-use crate::ast::BinOp;
+use crate::ast::{BinOp, UnaryOp};
+use crate::stack;
+use crate::symbol::Symbol;
+use crate::typeck::{Scalar, Type, Width};
+use std::fmt;
 
 /// Oblivious binary operators (constant-time).
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum ObliBinOp {
     /// Constant-time addition
     CtAdd,
@@ -17,10 +27,28 @@ pub enum ObliBinOp {
     CtSub,
     /// Constant-time multiplication
     CtMul,
+    /// Constant-time saturating addition: overflow clamps to the type's
+    /// minimum or maximum through a mask, without a branch
+    CtAddSat,
+    /// Constant-time saturating subtraction
+    CtSubSat,
+    /// Constant-time saturating multiplication
+    CtMulSat,
     /// Constant-time division (careful: timing may leak via hardware)
     CtDiv,
     /// Constant-time modulo
     CtMod,
+    /// Constant-time bitwise AND
+    CtBitAnd,
+    /// Constant-time bitwise OR
+    CtBitOr,
+    /// Constant-time bitwise XOR
+    CtBitXor,
+    /// Constant-time left shift by the amount modulo 64, which never
+    /// branches on the amount
+    CtShl,
+    /// Constant-time arithmetic right shift by the amount modulo 64
+    CtShr,
     /// Constant-time equality
     CtEq,
     /// Constant-time not-equal
@@ -45,8 +73,16 @@ fn from(op: &BinOp) -> Self {
             BinOp::Add => ObliBinOp::CtAdd,
             BinOp::Sub => ObliBinOp::CtSub,
             BinOp::Mul => ObliBinOp::CtMul,
+            BinOp::SatAdd => ObliBinOp::CtAddSat,
+            BinOp::SatSub => ObliBinOp::CtSubSat,
+            BinOp::SatMul => ObliBinOp::CtMulSat,
             BinOp::Div => ObliBinOp::CtDiv,
             BinOp::Mod => ObliBinOp::CtMod,
+            BinOp::BitAnd => ObliBinOp::CtBitAnd,
+            BinOp::BitOr => ObliBinOp::CtBitOr,
+            BinOp::BitXor => ObliBinOp::CtBitXor,
+            BinOp::Shl => ObliBinOp::CtShl,
+            BinOp::Shr => ObliBinOp::CtShr,
             BinOp::Eq => ObliBinOp::CtEq,
             BinOp::Ne => ObliBinOp::CtNe,
             BinOp::Lt => ObliBinOp::CtLt,
@@ -61,15 +97,34 @@ fn from(op: &BinOp) -> Self {
 
 /// Oblivious unary operators.
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum ObliUnaryOp {
     /// Constant-time negation
     CtNeg,
     /// Constant-time logical NOT
     CtNot,
+    /// Constant-time conversion of a boolean to `1` or `0`, through its
+    /// all-ones or all-zeros mask
+    CtToInt,
+    /// Constant-time conversion of an integer to whether it is nonzero,
+    /// as a `CtNe` with zero
+    CtToBool,
+}
+
+impl From<&UnaryOp> for ObliUnaryOp {
+    fn from(op: &UnaryOp) -> Self {
+        match op {
+            UnaryOp::Neg => ObliUnaryOp::CtNeg,
+            UnaryOp::Not => ObliUnaryOp::CtNot,
+            UnaryOp::ToInt => ObliUnaryOp::CtToInt,
+            UnaryOp::ToBool => ObliUnaryOp::CtToBool,
+        }
+    }
 }
 
 /// Oblivious expression - all operations are constant-time.
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum ObliExpr {
     /// Public integer literal
     PubInt(i64),
@@ -79,9 +134,22 @@ pub enum ObliExpr {
     SecretInt(i64),
     /// Secret boolean (runtime value)
     SecretBool(bool),
+    /// Integer literal of a [`Width`], public or secret; a plain `int`
+    /// is a [`PubInt`](ObliExpr::PubInt) or [`SecretInt`](ObliExpr::SecretInt)
+    Sized {
+        value: i64,
+        width: Width,
+        is_secret: bool,
+    },
     /// Variable reference (with secrecy flag)
     Var {
-        name: String,
+        name: Symbol,
+        is_secret: bool,
+    },
+    /// The program input `name` of type `ty`, read when the program runs
+    Input {
+        name: Symbol,
+        ty: Scalar,
         is_secret: bool,
     },
     /// Constant-time binary operation
@@ -98,6 +166,15 @@ pub enum ObliExpr {
         expr: Box<ObliExpr>,
         is_secret: bool,
     },
+    /// Integer `expr` of type `from` truncated or extended to `to`, as
+    /// Rust's `as` does, which takes the same time for every value.
+    /// `from` is `int` where the transform could not tell
+    CtCast {
+        expr: Box<ObliExpr>,
+        from: Scalar,
+        to: Scalar,
+        is_secret: bool,
+    },
     /// Constant-time selection (replaces if-then-else on secrets)
     /// `ct_select(cond, then_val, else_val)` - always evaluates both branches
     CtSelect {
@@ -113,27 +190,391 @@ pub enum ObliExpr {
     },
     /// Let binding
     Let {
-        name: String,
+        name: Symbol,
         value: Box<ObliExpr>,
         body: Box<ObliExpr>,
         is_secret: bool,
     },
+    /// `body`, failing unless `cond` holds: at once if `cond` is public
+    /// and every branch around it is taken, else when the result is
+    /// revealed, so the failure leaks no more than one bit at the end
+    Assert {
+        cond: Box<ObliExpr>,
+        body: Box<ObliExpr>,
+    },
+    /// Named program results; each is revealed by its own label
+    Outputs(Vec<(Symbol, ObliExpr)>),
+    /// Read of a constant integer table, scanning every entry in constant
+    /// time; an index outside `0..len - 1` reads the last entry. Always
+    /// secret, and only made by the `const-tables` pass.
+    Table {
+        values: Vec<i64>,
+        index: Box<ObliExpr>,
+    },
+    /// Deliberate reveal of a value, public whatever the secrecy of its
+    /// operand; see [`to_oblivious_with_audit`](crate::transform::to_oblivious_with_audit)
+    Declassify(Box<ObliExpr>),
+    /// Public loop: `acc` starts as `init` and is rebound to `body` while
+    /// `cond`, which must be public, holds. Secret if `acc` is
+    While {
+        cond: Box<ObliExpr>,
+        acc: Symbol,
+        init: Box<ObliExpr>,
+        body: Box<ObliExpr>,
+        is_secret: bool,
+    },
+    /// Constant-time swap: `names` are bound to `(right, left)` if `cond`,
+    /// else to `(left, right)`, for `body`; both operands are read either
+    /// way. Both names are secret if any operand is
+    CtSwap {
+        cond: Box<ObliExpr>,
+        left: Box<ObliExpr>,
+        right: Box<ObliExpr>,
+        names: [Symbol; 2],
+        body: Box<ObliExpr>,
+        is_secret: bool,
+    },
 }
 
 impl ObliExpr {
     /// Check if this expression is secret (depends on secret data).
     pub fn is_secret(&self) -> bool {
+        // Iterative along `let` bodies and `else` branches, which is how
+        // generated programs nest
+        let mut expr = self;
+        loop {
+            expr = match expr {
+                ObliExpr::PubInt(_) | ObliExpr::PubBool(_) | ObliExpr::Declassify(_) => {
+                    return false
+                }
+                ObliExpr::SecretInt(_) | ObliExpr::SecretBool(_) => return true,
+                ObliExpr::Var { is_secret, .. } | ObliExpr::Input { is_secret, .. } => {
+                    return *is_secret
+                }
+                ObliExpr::Sized { is_secret, .. } => return *is_secret,
+                ObliExpr::BinOp { is_secret, .. } => return *is_secret,
+                ObliExpr::UnaryOp { is_secret, .. } => return *is_secret,
+                ObliExpr::CtCast { is_secret, .. } => return *is_secret,
+                ObliExpr::While { is_secret, .. } => return *is_secret,
+                ObliExpr::CtSelect { .. } => return true, // ct_select always produces secret
+                ObliExpr::Table { .. } => return true,
+                ObliExpr::PubIf { then_branch, else_branch, .. } => {
+                    if stack::guard(|| then_branch.is_secret()) {
+                        return true;
+                    }
+                    else_branch
+                }
+                ObliExpr::Let { body, .. }
+                | ObliExpr::CtSwap { body, .. }
+                | ObliExpr::Assert { body, .. } => body,
+                ObliExpr::Outputs(outputs) => {
+                    return outputs.iter().any(|(_, e)| stack::guard(|| e.is_secret()))
+                }
+            };
+        }
+    }
+
+    /// The named results of a program ending in [`ObliExpr::Outputs`],
+    /// past its `let`s, swaps and assertions.
+    pub fn outputs(&self) -> Option<&[(Symbol, ObliExpr)]> {
+        let mut expr = self;
+        loop {
+            expr = match expr {
+                ObliExpr::Let { body, .. }
+                | ObliExpr::CtSwap { body, .. }
+                | ObliExpr::Assert { body, .. } => body,
+                ObliExpr::Outputs(outputs) => return Some(outputs),
+                _ => return None,
+            };
+        }
+    }
+
+    /// The program inputs read by `self`, each once, in the order they
+    /// are first read.
+    pub fn inputs(&self) -> Vec<(Symbol, Scalar, bool)> {
+        let mut inputs: Vec<(Symbol, Scalar, bool)> = Vec::new();
+        for node in self.post_order() {
+            if let &ObliExpr::Input { name, ty, is_secret } = node {
+                if inputs.iter().all(|(seen, ..)| *seen != name) {
+                    inputs.push((name, ty, is_secret));
+                }
+            }
+        }
+        inputs
+    }
+
+    /// Whether an assertion of `self` is only checked when the result is
+    /// revealed: one on a secret condition, or in an arm of a `CtSelect`,
+    /// which holds only if the arm is chosen.
+    pub fn defers_assertions(&self) -> bool {
+        self.post_order().iter().any(|node| match node {
+            ObliExpr::Assert { cond, .. } => cond.is_secret(),
+            ObliExpr::CtSelect {
+                then_val, else_val, ..
+            } => then_val.has_assertions() || else_val.has_assertions(),
+            _ => false,
+        })
+    }
+
+    /// Whether `self` has an assertion.
+    pub fn has_assertions(&self) -> bool {
+        self.post_order().iter().any(|node| matches!(node, ObliExpr::Assert { .. }))
+    }
+
+    /// Direct subexpressions, in evaluation order; a loop's in source
+    /// order, condition first.
+    pub fn children(&self) -> Vec<&ObliExpr> {
         match self {
-            ObliExpr::PubInt(_) | ObliExpr::PubBool(_) => false,
-            ObliExpr::SecretInt(_) | ObliExpr::SecretBool(_) => true,
-            ObliExpr::Var { is_secret, .. } => *is_secret,
-            ObliExpr::BinOp { is_secret, .. } => *is_secret,
-            ObliExpr::UnaryOp { is_secret, .. } => *is_secret,
-            ObliExpr::CtSelect { .. } => true, // ct_select always produces secret
-            ObliExpr::PubIf { then_branch, else_branch, .. } => {
-                then_branch.is_secret() || else_branch.is_secret()
+            ObliExpr::PubInt(_)
+            | ObliExpr::PubBool(_)
+            | ObliExpr::SecretInt(_)
+            | ObliExpr::SecretBool(_)
+            | ObliExpr::Sized { .. }
+            | ObliExpr::Var { .. }
+            | ObliExpr::Input { .. } => vec![],
+            ObliExpr::BinOp { left, right, .. } => vec![left, right],
+            ObliExpr::UnaryOp { expr, .. } | ObliExpr::CtCast { expr, .. } => vec![expr],
+            ObliExpr::CtSelect {
+                cond,
+                then_val,
+                else_val,
+            } => vec![cond, then_val, else_val],
+            ObliExpr::PubIf {
+                cond,
+                then_branch,
+                else_branch,
+            } => vec![cond, then_branch, else_branch],
+            ObliExpr::Let { value, body, .. } => vec![value, body],
+            ObliExpr::Assert { cond, body } => vec![cond, body],
+            ObliExpr::Outputs(outputs) => outputs.iter().map(|(_, e)| e).collect(),
+            ObliExpr::Table { index, .. } => vec![index],
+            ObliExpr::Declassify(expr) => vec![expr],
+            ObliExpr::While {
+                cond, init, body, ..
+            } => vec![cond, init, body],
+            ObliExpr::CtSwap {
+                cond,
+                left,
+                right,
+                body,
+                ..
+            } => vec![cond, left, right, body],
+        }
+    }
+
+    /// The `CtSelect` scan a [`Table`](ObliExpr::Table) read stands for:
+    /// `ct_select(index == 0, v0, ct_select(index == 1, v1, ... v{n-1}))`.
+    pub fn table_scan(values: &[i64], index: &ObliExpr) -> ObliExpr {
+        let (&last, rest) = values.split_last().expect("tables are never empty");
+        let mut acc = ObliExpr::SecretInt(last);
+        for (k, &value) in rest.iter().enumerate().rev() {
+            acc = ObliExpr::CtSelect {
+                cond: Box::new(ObliExpr::BinOp {
+                    op: ObliBinOp::CtEq,
+                    left: Box::new(index.clone()),
+                    right: Box::new(ObliExpr::PubInt(k as i64)),
+                    is_secret: true,
+                }),
+                then_val: Box::new(ObliExpr::SecretInt(value)),
+                else_val: Box::new(acc),
+            };
+        }
+        acc
+    }
+
+    /// The elements and index of a secret array read: a `CtSelect` chain
+    /// comparing one variable with `0, 1, ...` in turn, like
+    /// [`table_scan`](Self::table_scan) builds but with any elements.
+    pub fn scan(&self) -> Option<(Vec<&ObliExpr>, &ObliExpr)> {
+        let mut elements = Vec::new();
+        let mut index: Option<&ObliExpr> = None;
+        let mut node = self;
+        while let ObliExpr::CtSelect {
+            cond,
+            then_val,
+            else_val,
+        } = node
+        {
+            let ObliExpr::BinOp {
+                op: ObliBinOp::CtEq,
+                left,
+                right,
+                is_secret: true,
+            } = &**cond
+            else {
+                break;
+            };
+            let is_next = **right == ObliExpr::PubInt(elements.len() as i64);
+            let same_index = index.is_none_or(|index| index == &**left);
+            if !matches!(**left, ObliExpr::Var { .. }) || !is_next || !same_index {
+                break;
             }
-            ObliExpr::Let { body, .. } => body.is_secret(),
+            index = Some(left);
+            elements.push(&**then_val);
+            node = else_val;
         }
+        elements.push(node);
+        Some((elements, index?))
+    }
+
+    /// Every node of the tree in post-order, the order of its span table.
+    pub fn post_order(&self) -> Vec<&ObliExpr> {
+        let mut nodes = Vec::new();
+        self.collect_post_order(&mut nodes);
+        nodes
+    }
+
+    fn collect_post_order<'a>(&'a self, nodes: &mut Vec<&'a ObliExpr>) {
+        for child in self.children() {
+            stack::guard(|| child.collect_post_order(nodes));
+        }
+        nodes.push(self);
+    }
+}
+
+impl fmt::Display for ObliBinOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ObliBinOp::CtAdd => "ct_add",
+            ObliBinOp::CtSub => "ct_sub",
+            ObliBinOp::CtMul => "ct_mul",
+            ObliBinOp::CtAddSat => "ct_add_sat",
+            ObliBinOp::CtSubSat => "ct_sub_sat",
+            ObliBinOp::CtMulSat => "ct_mul_sat",
+            ObliBinOp::CtDiv => "ct_div",
+            ObliBinOp::CtMod => "ct_mod",
+            ObliBinOp::CtBitAnd => "ct_bit_and",
+            ObliBinOp::CtBitOr => "ct_bit_or",
+            ObliBinOp::CtBitXor => "ct_bit_xor",
+            ObliBinOp::CtShl => "ct_shl",
+            ObliBinOp::CtShr => "ct_shr",
+            ObliBinOp::CtEq => "ct_eq",
+            ObliBinOp::CtNe => "ct_ne",
+            ObliBinOp::CtLt => "ct_lt",
+            ObliBinOp::CtLe => "ct_le",
+            ObliBinOp::CtGt => "ct_gt",
+            ObliBinOp::CtGe => "ct_ge",
+            ObliBinOp::CtAnd => "ct_and",
+            ObliBinOp::CtOr => "ct_or",
+        })
+    }
+}
+
+impl fmt::Display for ObliUnaryOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ObliUnaryOp::CtNeg => "ct_neg",
+            ObliUnaryOp::CtNot => "ct_not",
+            ObliUnaryOp::CtToInt => "ct_to_int",
+            ObliUnaryOp::CtToBool => "ct_to_bool",
+        })
+    }
+}
+
+impl fmt::Display for ObliExpr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mark = |secret: bool| if secret { ":s" } else { "" };
+        match self {
+            ObliExpr::PubInt(n) => write!(f, "{}", n),
+            ObliExpr::PubBool(b) => write!(f, "{}", b),
+            ObliExpr::SecretInt(n) => write!(f, "{}:s", n),
+            ObliExpr::SecretBool(b) => write!(f, "{}:s", b),
+            ObliExpr::Sized {
+                value,
+                width,
+                is_secret,
+            } => write!(f, "{}{}{}", width.value(*value), width, mark(*is_secret)),
+            ObliExpr::Var { name, is_secret } => write!(f, "{}{}", name, mark(*is_secret)),
+            ObliExpr::Input {
+                name,
+                ty,
+                is_secret,
+            } => write!(f, "(input {}{} {})", name, mark(*is_secret), Type::from(*ty)),
+            ObliExpr::BinOp {
+                op,
+                left,
+                right,
+                is_secret,
+            } => write!(f, "({}{} {} {})", op, mark(*is_secret), left, right),
+            ObliExpr::UnaryOp {
+                op,
+                expr,
+                is_secret,
+            } => write!(f, "({}{} {})", op, mark(*is_secret), expr),
+            ObliExpr::CtCast {
+                expr,
+                from,
+                to,
+                is_secret,
+            } => {
+                let (from, to) = (Type::from(*from), Type::from(*to));
+                write!(f, "(ct_cast{} {} {} {})", mark(*is_secret), from, to, expr)
+            }
+            ObliExpr::CtSelect {
+                cond,
+                then_val,
+                else_val,
+            } => write!(f, "(ct_select {} {} {})", cond, then_val, else_val),
+            ObliExpr::PubIf {
+                cond,
+                then_branch,
+                else_branch,
+            } => write!(f, "(pub_if {} {} {})", cond, then_branch, else_branch),
+            ObliExpr::Let {
+                name,
+                value,
+                body,
+                is_secret,
+            } => write!(f, "(let {}{} {} {})", name, mark(*is_secret), value, body),
+            ObliExpr::Assert { cond, body } => write!(f, "(assert {} {})", cond, body),
+            ObliExpr::Outputs(outputs) => {
+                f.write_str("(outputs")?;
+                for (name, e) in outputs {
+                    write!(f, " ({} {})", name, e)?;
+                }
+                f.write_str(")")
+            }
+            ObliExpr::Table { values, index } => {
+                f.write_str("(table [")?;
+                for (i, value) in values.iter().enumerate() {
+                    let sep = if i == 0 { "" } else { " " };
+                    write!(f, "{}{}", sep, value)?;
+                }
+                write!(f, "] {})", index)
+            }
+            ObliExpr::Declassify(expr) => write!(f, "(declassify {})", expr),
+            ObliExpr::While {
+                cond,
+                acc,
+                init,
+                body,
+                is_secret,
+            } => write!(f, "(while {} {}{} {} {})", cond, acc, mark(*is_secret), init, body),
+            ObliExpr::CtSwap {
+                cond,
+                left,
+                right,
+                names: [first, second],
+                body,
+                is_secret,
+            } => {
+                let s = mark(*is_secret);
+                write!(f, "(ct_swap {}{} {}{} {} {} {} ", first, s, second, s, cond, left, right)?;
+                write!(f, "{})", body)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::transpile_to_ir;
+
+    #[test]
+    fn test_display_is_compact_s_expression() {
+        let ir = transpile_to_ir("let k = secret(5) if k > 3 then k else 0").unwrap();
+        assert_eq!(ir.to_string(), "(let k:s 5:s (ct_select (ct_gt:s k:s 3) k:s 0))");
+        let ir = transpile_to_ir("if true then -1 else 2 - 1").unwrap();
+        assert_eq!(ir.to_string(), "(pub_if true (ct_neg 1) (ct_sub 2 1))");
     }
 }
@@ -0,0 +1,816 @@
+// SPDX-License-Identifier: MIT OR AGPL-3.0-or-later
+// SPDX-FileCopyrightText: 2024-2025 hyperpolymath
+
+//! JSON ASTs for frontends written in other languages.
+//!
+//! A frontend can build an AST as JSON and hand it to [`Program::from_json`]
+//! instead of printing MiniObli source. Expressions are objects keyed by
+//! their kind:
+//!
+//! | Expression | JSON |
+//! |---|---|
+//! | integer, boolean | `{"int": 5}`, `{"bool": true}`, or the bare `5`, `true` |
+//! | `200u8` | `{"int": 200, "type": "u8"}` |
+//! | variable | `{"var": "x"}` |
+//! | `secret(e)` | `{"secret": e}` |
+//! | `declassify(e)` | `{"declassify": e}` |
+//! | `l op r` | `{"binop": "+", "left": l, "right": r}` |
+//! | `op e` | `{"unary": "-", "expr": e}` |
+//! | `e as u8` | `{"cast": e, "type": "u8"}` |
+//! | `if c then t else e` | `{"if": c, "then": t, "else": e}` |
+//! | `let x = v b` | `{"let": "x", "value": v, "body": b}` |
+//! | `let x: secret int = v b` | `{"let": "x", "type": "secret int", "value": v, "body": b}` |
+//! | `let mut x = v b` | `{"let": "x", "mut": true, "value": v, "body": b}` |
+//! | `let (x, y) = v b` | `{"let": ["x", "y"], "value": v, "body": b}` |
+//! | `input secret x: int b` | `{"input": "x", "type": "secret int", "body": b}` |
+//! | `const n = v b` | `{"const": "n", "value": v, "body": b}` |
+//! | `enum C = A \| B b` | `{"enum": "C", "variants": ["A", "B"], "body": b}` |
+//! | `assert(c) b` | `{"assert": c, "body": b}` |
+//! | `x = v b` | `{"assign": "x", "value": v, "body": b}` |
+//! | `if c then x = v b` | `{"assign": "x", "if": c, "value": v, "body": b}` |
+//! | `[e1, e2]` | `{"array": [e1, e2]}` |
+//! | `(e1, e2)` | `{"tuple": [e1, e2]}` |
+//! | `b"hi"` | `{"bytes": [104, 105]}` |
+//! | `sort(a)` | `{"sort": a}` |
+//! | `swap_if(c, l, r)` | `{"swap_if": c, "left": l, "right": r}` |
+//! | `clamp(x, lo, hi)` | `{"clamp": x, "lo": lo, "hi": hi}` |
+//! | `in_range(x, lo, hi)` | `{"in_range": x, "lo": lo, "hi": hi}` |
+//! | `pow(b, e)` | `{"pow": b, "exp": e}` |
+//! | `map(a, f)` | `{"map": a, "with": f}` |
+//! | `fold(a, v, f)` | `{"fold": a, "init": v, "with": f}` |
+//! | `some(e)`, `none` | `{"some": e}`, `{"none": null}` |
+//! | `unwrap_or(o, d)` | `{"unwrap_or": o, "default": d}` |
+//! | `a[i]` | `{"index": a, "at": i}` |
+//! | `lookup(k, [(k1, v1)], d)` | `{"lookup": k, "entries": [[k1, v1]], "default": d}` |
+//! | `match k with p1 => v1, _ => d` | `{"match": k, "arms": [[p1, v1]], "default": d}` |
+//! | `match c with A => v1, B => v2` | `{"match": c, "arms": [[{"var": "A"}, v1], ...]}` |
+//! | `outputs(a = e1, b = e2)` | `{"outputs": [["a", e1], ["b", e2]]}` |
+//! | `fn f(x, y) = b r` | `{"fn": "f", "params": ["x", "y"], "body": b, "rest": r}` |
+//! | `#[max_depth(8)] fn f(x) = b r` | as `fn`, adding `"max_depth": 8` |
+//! | `f(e1, e2)` | `{"call": "f", "args": [e1, e2]}` |
+//! | `fn(x, y) => b` | `{"lambda": ["x", "y"], "body": b}` |
+//! | `for` loop | `{"for": "i", "from": s, "to": e, "with": "a", "init": v, "do": b}` |
+//! | `while` loop | `{"while": c, "with": "a", "init": v, "do": b}` |
+//!
+//! Operators are written as in source
+//! (`+ - * +| -| *| / % & | ^ << >> == != < <= > >= && ||`, unary `-`
+//! and `!`); `to_int(e)` and `to_bool(e)` are the unary operators
+//! `"to_int"` and `"to_bool"`. A program wraps its body with the schema
+//! version: `{"version": 1, "body": e}`. Unknown keys are rejected, and errors name
+//! the offending location as a path such as `$.body.left`.
+
+use crate::ast::{Annotation, BinOp, Expr, Program, UnaryOp};
+use crate::lexer::KEYWORDS;
+use crate::typeck::{Scalar, Width};
+use serde_json::{Map, Value};
+use thiserror::Error;
+
+/// Schema version [`Program::from_json`] reads.
+pub const SCHEMA_VERSION: u64 = 1;
+
+#[derive(Error, Debug)]
+pub enum JsonError {
+    #[error("invalid JSON: {0}")]
+    Syntax(#[from] serde_json::Error),
+    #[error("at {path}: {message}")]
+    Schema { path: String, message: String },
+    #[error("unsupported schema version {0} (expected {SCHEMA_VERSION})")]
+    Version(u64),
+}
+
+fn schema_error(path: &str, message: impl Into<String>) -> JsonError {
+    JsonError::Schema {
+        path: path.to_string(),
+        message: message.into(),
+    }
+}
+
+impl Expr {
+    /// Read an expression in the [JSON schema](crate::json).
+    pub fn from_json(json: &str) -> Result<Expr, JsonError> {
+        Expr::from_json_value(&serde_json::from_str(json)?)
+    }
+
+    /// Like [`Expr::from_json`], from parsed JSON.
+    pub fn from_json_value(value: &Value) -> Result<Expr, JsonError> {
+        expr(value, "$")
+    }
+}
+
+impl Program {
+    /// Read a program in the [JSON schema](crate::json).
+    pub fn from_json(json: &str) -> Result<Program, JsonError> {
+        let value: Value = serde_json::from_str(json)?;
+        let object = value
+            .as_object()
+            .ok_or_else(|| schema_error("$", "expected a program object"))?;
+        only_keys(object, &["version", "body"], "$")?;
+        let version = field(object, "version", "$")?;
+        let version = version
+            .as_u64()
+            .ok_or_else(|| schema_error("$.version", "expected a version number"))?;
+        if version != SCHEMA_VERSION {
+            return Err(JsonError::Version(version));
+        }
+        let body = expr(field(object, "body", "$")?, "$.body")?;
+        Ok(Program::from_expr(body))
+    }
+}
+
+fn expr(value: &Value, path: &str) -> Result<Expr, JsonError> {
+    let object = match value {
+        Value::Bool(b) => return Ok(Expr::Bool(*b)),
+        Value::Number(_) => return int(value, path).map(Expr::Int),
+        Value::Object(object) => object,
+        _ => return Err(schema_error(path, "expected an expression")),
+    };
+    let sub = |key: &str| {
+        let value = field(object, key, path)?;
+        expr(value, &format!("{}.{}", path, key)).map(Box::new)
+    };
+    // `assign` first: a conditional assignment has an `if` key too
+    let kinds = [
+        "assign", "int", "bool", "var", "secret", "declassify", "binop", "unary", "cast", "if",
+        "let", "const", "enum", "array", "tuple", "bytes", "sort", "swap_if", "clamp", "in_range",
+        "pow", "map", "fold", "some", "none", "unwrap_or", "index", "match", "lookup", "outputs",
+        "fn", "call", "lambda", "for", "while", "input", "assert",
+    ];
+    let kind = kinds
+        .into_iter()
+        .find(|kind| object.contains_key(*kind))
+        .ok_or_else(|| schema_error(path, "expected an expression"))?;
+    match kind {
+        "int" => {
+            only_keys(object, &["int", "type"], path)?;
+            let int_path = format!("{}.int", path);
+            let Some(ty) = object.get("type") else {
+                return Ok(Expr::Int(int(&object["int"], &int_path)?));
+            };
+            let width = ty.as_str().and_then(Width::from_name).ok_or_else(|| {
+                schema_error(&format!("{}.type", path), "expected a type such as \"u8\"")
+            })?;
+            let n = object["int"].as_i64().map(i128::from);
+            let n = n.or_else(|| object["int"].as_u64().map(i128::from));
+            let (min, max) = width.range();
+            match n {
+                Some(n) if (min..=max).contains(&n) => Ok(Expr::Sized(width.wrap(n), width)),
+                _ => {
+                    let message = format!("expected an integer that fits in a {}", width);
+                    Err(schema_error(&int_path, message))
+                }
+            }
+        }
+        "bool" => {
+            only_keys(object, &["bool"], path)?;
+            let b = object["bool"].as_bool();
+            let error = || schema_error(&format!("{}.bool", path), "expected a boolean");
+            b.map(Expr::Bool).ok_or_else(error)
+        }
+        "var" => {
+            only_keys(object, &["var"], path)?;
+            Ok(Expr::Var(name(&object["var"], &format!("{}.var", path))?.into()))
+        }
+        "secret" => {
+            only_keys(object, &["secret"], path)?;
+            Ok(Expr::Secret(sub("secret")?))
+        }
+        "declassify" => {
+            only_keys(object, &["declassify"], path)?;
+            Ok(Expr::Declassify(sub("declassify")?))
+        }
+        "sort" => {
+            only_keys(object, &["sort"], path)?;
+            Ok(Expr::Sort(sub("sort")?))
+        }
+        "swap_if" => {
+            only_keys(object, &["swap_if", "left", "right"], path)?;
+            Ok(Expr::SwapIf {
+                cond: sub("swap_if")?,
+                left: sub("left")?,
+                right: sub("right")?,
+            })
+        }
+        "clamp" | "in_range" => {
+            only_keys(object, &[kind, "lo", "hi"], path)?;
+            let (value, lo, hi) = (sub(kind)?, sub("lo")?, sub("hi")?);
+            Ok(match kind {
+                "clamp" => Expr::Clamp { value, lo, hi },
+                _ => Expr::InRange { value, lo, hi },
+            })
+        }
+        "pow" => {
+            only_keys(object, &["pow", "exp"], path)?;
+            Ok(Expr::Pow {
+                base: sub("pow")?,
+                exp: sub("exp")?,
+            })
+        }
+        "map" => {
+            only_keys(object, &["map", "with"], path)?;
+            Ok(Expr::Map {
+                array: sub("map")?,
+                f: sub("with")?,
+            })
+        }
+        "fold" => {
+            only_keys(object, &["fold", "init", "with"], path)?;
+            Ok(Expr::Fold {
+                array: sub("fold")?,
+                init: sub("init")?,
+                f: sub("with")?,
+            })
+        }
+        "some" => {
+            only_keys(object, &["some"], path)?;
+            Ok(Expr::Some(sub("some")?))
+        }
+        "none" => {
+            only_keys(object, &["none"], path)?;
+            match object["none"] {
+                Value::Null => Ok(Expr::None),
+                _ => Err(schema_error(&format!("{}.none", path), "expected null")),
+            }
+        }
+        "unwrap_or" => {
+            only_keys(object, &["unwrap_or", "default"], path)?;
+            Ok(Expr::UnwrapOr {
+                option: sub("unwrap_or")?,
+                default: sub("default")?,
+            })
+        }
+        "assert" => {
+            only_keys(object, &["assert", "body"], path)?;
+            Ok(Expr::Assert {
+                cond: sub("assert")?,
+                body: sub("body")?,
+            })
+        }
+        "binop" => {
+            only_keys(object, &["binop", "left", "right"], path)?;
+            let op_path = format!("{}.binop", path);
+            let op = match object["binop"].as_str() {
+                Some("+") => BinOp::Add,
+                Some("-") => BinOp::Sub,
+                Some("*") => BinOp::Mul,
+                Some("+|") => BinOp::SatAdd,
+                Some("-|") => BinOp::SatSub,
+                Some("*|") => BinOp::SatMul,
+                Some("/") => BinOp::Div,
+                Some("%") => BinOp::Mod,
+                Some("&") => BinOp::BitAnd,
+                Some("|") => BinOp::BitOr,
+                Some("^") => BinOp::BitXor,
+                Some("<<") => BinOp::Shl,
+                Some(">>") => BinOp::Shr,
+                Some("==") => BinOp::Eq,
+                Some("!=") => BinOp::Ne,
+                Some("<") => BinOp::Lt,
+                Some("<=") => BinOp::Le,
+                Some(">") => BinOp::Gt,
+                Some(">=") => BinOp::Ge,
+                Some("&&") => BinOp::And,
+                Some("||") => BinOp::Or,
+                _ => return Err(schema_error(&op_path, "expected a binary operator")),
+            };
+            Ok(Expr::BinOp {
+                op,
+                left: sub("left")?,
+                right: sub("right")?,
+            })
+        }
+        "unary" => {
+            only_keys(object, &["unary", "expr"], path)?;
+            let op = match object["unary"].as_str() {
+                Some("-") => UnaryOp::Neg,
+                Some("!") => UnaryOp::Not,
+                Some("to_int") => UnaryOp::ToInt,
+                Some("to_bool") => UnaryOp::ToBool,
+                _ => {
+                    let op_path = format!("{}.unary", path);
+                    return Err(schema_error(&op_path, "expected a unary operator"));
+                }
+            };
+            Ok(Expr::UnaryOp {
+                op,
+                expr: sub("expr")?,
+            })
+        }
+        "cast" => {
+            only_keys(object, &["cast", "type"], path)?;
+            let ty = match field(object, "type", path)?.as_str() {
+                Some("int" | "i64") => Some(Scalar::Int),
+                ty => ty.and_then(Width::from_name).map(Scalar::Sized),
+            };
+            let ty = ty.ok_or_else(|| {
+                schema_error(&format!("{}.type", path), "expected an integer type such as \"u8\"")
+            })?;
+            Ok(Expr::Cast {
+                expr: sub("cast")?,
+                ty,
+            })
+        }
+        "const" => {
+            only_keys(object, &["const", "value", "body"], path)?;
+            Ok(Expr::Const {
+                name: name(&object["const"], &format!("{}.const", path))?.into(),
+                value: sub("value")?,
+                body: sub("body")?,
+            })
+        }
+        "if" => {
+            only_keys(object, &["if", "then", "else"], path)?;
+            Ok(Expr::If {
+                cond: sub("if")?,
+                then_branch: sub("then")?,
+                else_branch: sub("else")?,
+            })
+        }
+        "array" => {
+            only_keys(object, &["array"], path)?;
+            let elements_path = format!("{}.array", path);
+            let elements = match object["array"].as_array() {
+                Some(elements) if !elements.is_empty() => elements,
+                _ => return Err(schema_error(&elements_path, "expected a non-empty array")),
+            };
+            let elements = elements
+                .iter()
+                .enumerate()
+                .map(|(i, e)| expr(e, &format!("{}[{}]", elements_path, i)));
+            Ok(Expr::Array(elements.collect::<Result<_, _>>()?))
+        }
+        "tuple" => {
+            only_keys(object, &["tuple"], path)?;
+            let elements_path = format!("{}.tuple", path);
+            let elements = match object["tuple"].as_array() {
+                Some(elements) if elements.len() >= 2 => elements,
+                _ => return Err(schema_error(&elements_path, "expected two or more elements")),
+            };
+            let elements = elements
+                .iter()
+                .enumerate()
+                .map(|(i, e)| expr(e, &format!("{}[{}]", elements_path, i)));
+            Ok(Expr::Tuple(elements.collect::<Result<_, _>>()?))
+        }
+        "bytes" => {
+            only_keys(object, &["bytes"], path)?;
+            let bytes_path = format!("{}.bytes", path);
+            let error = || schema_error(&bytes_path, "expected a non-empty array of bytes");
+            let bytes = object["bytes"].as_array().filter(|b| !b.is_empty()).ok_or_else(error)?;
+            let bytes = bytes.iter().map(|b| {
+                b.as_u64().and_then(|b| u8::try_from(b).ok()).ok_or_else(error)
+            });
+            Ok(Expr::Bytes(bytes.collect::<Result<_, _>>()?))
+        }
+        "index" => {
+            only_keys(object, &["index", "at"], path)?;
+            Ok(Expr::Index {
+                array: sub("index")?,
+                index: sub("at")?,
+            })
+        }
+        "lookup" => {
+            only_keys(object, &["lookup", "entries", "default"], path)?;
+            Ok(Expr::Lookup {
+                key: sub("lookup")?,
+                entries: pairs(object, "entries", path, "a [key, value] pair")?,
+                default: sub("default")?,
+            })
+        }
+        "match" => {
+            only_keys(object, &["match", "arms", "default"], path)?;
+            let arms = pairs(object, "arms", path, "a [pattern, value] pair")?;
+            for (i, (pattern, _)) in arms.iter().enumerate() {
+                let literal = matches!(pattern, Expr::Int(_) | Expr::Sized(..) | Expr::Bool(_));
+                if !literal && !matches!(pattern, Expr::Var(_)) {
+                    let pattern_path = format!("{}.arms[{}][0]", path, i);
+                    let message = "expected an integer, boolean or variant";
+                    return Err(schema_error(&pattern_path, message));
+                }
+            }
+            // Only a match over enum variants may leave out the default
+            let variants = arms.iter().all(|(pattern, _)| matches!(pattern, Expr::Var(_)));
+            let default = match object.get("default") {
+                None if variants => None,
+                _ => Some(sub("default")?),
+            };
+            Ok(Expr::Match {
+                scrutinee: sub("match")?,
+                arms,
+                default,
+            })
+        }
+        "outputs" => {
+            only_keys(object, &["outputs"], path)?;
+            let outputs_path = format!("{}.outputs", path);
+            let outputs = match object["outputs"].as_array() {
+                Some(outputs) if !outputs.is_empty() => outputs,
+                _ => return Err(schema_error(&outputs_path, "expected a non-empty array")),
+            };
+            let outputs = outputs.iter().enumerate().map(|(i, output)| {
+                let output_path = format!("{}[{}]", outputs_path, i);
+                match output.as_array().map(Vec::as_slice) {
+                    Some([n, e]) => Ok((
+                        name(n, &format!("{}[0]", output_path))?.into(),
+                        expr(e, &format!("{}[1]", output_path))?,
+                    )),
+                    _ => Err(schema_error(&output_path, "expected a [name, value] pair")),
+                }
+            });
+            Ok(Expr::Outputs(outputs.collect::<Result<_, _>>()?))
+        }
+        "enum" => {
+            only_keys(object, &["enum", "variants", "body"], path)?;
+            let variants_path = format!("{}.variants", path);
+            let variants = match field(object, "variants", path)?.as_array() {
+                Some(variants) if !variants.is_empty() => variants,
+                _ => return Err(schema_error(&variants_path, "expected a non-empty array")),
+            };
+            let variants = variants
+                .iter()
+                .enumerate()
+                .map(|(i, v)| name(v, &format!("{}[{}]", variants_path, i)).map(Into::into));
+            Ok(Expr::Enum {
+                name: name(&object["enum"], &format!("{}.enum", path))?.into(),
+                variants: variants.collect::<Result<_, _>>()?,
+                body: sub("body")?,
+            })
+        }
+        "fn" => {
+            only_keys(object, &["fn", "max_depth", "params", "body", "rest"], path)?;
+            let max_depth = match object.get("max_depth") {
+                Some(depth) => Some(
+                    depth
+                        .as_u64()
+                        .and_then(|depth| u32::try_from(depth).ok())
+                        .filter(|&depth| depth > 0)
+                        .ok_or_else(|| {
+                            let path = format!("{}.max_depth", path);
+                            schema_error(&path, "expected a positive integer")
+                        })?,
+                ),
+                None => None,
+            };
+            let params_path = format!("{}.params", path);
+            let params = field(object, "params", path)?
+                .as_array()
+                .ok_or_else(|| schema_error(&params_path, "expected an array"))?
+                .iter()
+                .enumerate()
+                .map(|(i, p)| name(p, &format!("{}[{}]", params_path, i)).map(Into::into));
+            Ok(Expr::Fn {
+                name: name(&object["fn"], &format!("{}.fn", path))?.into(),
+                max_depth,
+                params: params.collect::<Result<_, _>>()?,
+                body: sub("body")?,
+                rest: sub("rest")?,
+            })
+        }
+        "call" => {
+            only_keys(object, &["call", "args"], path)?;
+            let args_path = format!("{}.args", path);
+            let args = field(object, "args", path)?
+                .as_array()
+                .ok_or_else(|| schema_error(&args_path, "expected an array"))?
+                .iter()
+                .enumerate()
+                .map(|(i, arg)| expr(arg, &format!("{}[{}]", args_path, i)));
+            Ok(Expr::Call {
+                name: name(&object["call"], &format!("{}.call", path))?.into(),
+                args: args.collect::<Result<_, _>>()?,
+            })
+        }
+        "lambda" => {
+            only_keys(object, &["lambda", "body"], path)?;
+            let params_path = format!("{}.lambda", path);
+            let params = object["lambda"]
+                .as_array()
+                .ok_or_else(|| schema_error(&params_path, "expected an array"))?
+                .iter()
+                .enumerate()
+                .map(|(i, p)| name(p, &format!("{}[{}]", params_path, i)).map(Into::into));
+            Ok(Expr::Lambda {
+                params: params.collect::<Result<_, _>>()?,
+                body: sub("body")?,
+            })
+        }
+        "for" => {
+            only_keys(object, &["for", "from", "to", "with", "init", "do"], path)?;
+            Ok(Expr::For {
+                var: name(&object["for"], &format!("{}.for", path))?.into(),
+                start: sub("from")?,
+                end: sub("to")?,
+                acc: name(field(object, "with", path)?, &format!("{}.with", path))?.into(),
+                init: sub("init")?,
+                body: sub("do")?,
+            })
+        }
+        "assign" => {
+            only_keys(object, &["assign", "if", "value", "body"], path)?;
+            let cond = match object.get("if") {
+                Some(cond) => Some(Box::new(expr(cond, &format!("{}.if", path))?)),
+                None => None,
+            };
+            Ok(Expr::Assign {
+                name: name(&object["assign"], &format!("{}.assign", path))?.into(),
+                cond,
+                value: sub("value")?,
+                body: sub("body")?,
+            })
+        }
+        "while" => {
+            only_keys(object, &["while", "with", "init", "do"], path)?;
+            Ok(Expr::While {
+                cond: sub("while")?,
+                acc: name(field(object, "with", path)?, &format!("{}.with", path))?.into(),
+                init: sub("init")?,
+                body: sub("do")?,
+            })
+        }
+        "input" => {
+            only_keys(object, &["input", "type", "body"], path)?;
+            let name = name(&object["input"], &format!("{}.input", path))?.into();
+            let ty = annotation(field(object, "type", path)?, &format!("{}.type", path))?;
+            let input = Expr::Input {
+                name,
+                secret: ty.secret == Some(true),
+                ty: ty.ty,
+            };
+            Ok(Expr::Let {
+                name,
+                ty: None,
+                mutable: false,
+                value: Box::new(input),
+                body: sub("body")?,
+            })
+        }
+        _ => {
+            only_keys(object, &["let", "type", "mut", "value", "body"], path)?;
+            if let Some(names) = object["let"].as_array() {
+                only_keys(object, &["let", "value", "body"], path)?;
+                let names_path = format!("{}.let", path);
+                if names.len() < 2 {
+                    return Err(schema_error(&names_path, "expected two or more names"));
+                }
+                let names = names
+                    .iter()
+                    .enumerate()
+                    .map(|(i, n)| name(n, &format!("{}[{}]", names_path, i)).map(Into::into));
+                return Ok(Expr::LetTuple {
+                    names: names.collect::<Result<_, _>>()?,
+                    value: sub("value")?,
+                    body: sub("body")?,
+                });
+            }
+            let ty = match object.get("type") {
+                Some(ty) => Some(annotation(ty, &format!("{}.type", path))?),
+                None => None,
+            };
+            let mutable = match object.get("mut") {
+                Some(Value::Bool(b)) => *b,
+                Some(_) => {
+                    return Err(schema_error(&format!("{}.mut", path), "expected a boolean"))
+                }
+                None => false,
+            };
+            Ok(Expr::Let {
+                name: name(&object["let"], &format!("{}.let", path))?.into(),
+                ty,
+                mutable,
+                value: sub("value")?,
+                body: sub("body")?,
+            })
+        }
+    }
+}
+
+/// The non-empty array of two-element arrays under `key`.
+fn pairs(
+    object: &Map<String, Value>,
+    key: &str,
+    path: &str,
+    pair: &str,
+) -> Result<Vec<(Expr, Expr)>, JsonError> {
+    let pairs_path = format!("{}.{}", path, key);
+    let pairs = match field(object, key, path)?.as_array() {
+        Some(pairs) if !pairs.is_empty() => pairs,
+        _ => return Err(schema_error(&pairs_path, "expected a non-empty array")),
+    };
+    let pairs = pairs.iter().enumerate().map(|(i, entry)| {
+        let entry_path = format!("{}[{}]", pairs_path, i);
+        match entry.as_array().map(Vec::as_slice) {
+            Some([k, v]) => Ok((
+                expr(k, &format!("{}[0]", entry_path))?,
+                expr(v, &format!("{}[1]", entry_path))?,
+            )),
+            _ => Err(schema_error(&entry_path, format!("expected {}", pair))),
+        }
+    });
+    pairs.collect()
+}
+
+/// A let annotation such as `"secret int"` or `"bool"`.
+fn annotation(value: &Value, path: &str) -> Result<Annotation, JsonError> {
+    let words: Vec<_> = value.as_str().unwrap_or_default().split_whitespace().collect();
+    let (secret, ty) = match words.as_slice() {
+        ["secret", ty] => (Some(true), *ty),
+        ["pub", ty] => (Some(false), *ty),
+        [ty] => (None, *ty),
+        _ => (None, ""),
+    };
+    let ty = match ty {
+        "int" | "i64" => Scalar::Int,
+        "bool" => Scalar::Bool,
+        _ => match Width::from_name(ty) {
+            Some(width) => Scalar::Sized(width),
+            None => return Err(schema_error(path, "expected a type such as \"secret int\"")),
+        },
+    };
+    Ok(Annotation { secret, ty })
+}
+
+fn field<'v>(
+    object: &'v Map<String, Value>,
+    key: &str,
+    path: &str,
+) -> Result<&'v Value, JsonError> {
+    object
+        .get(key)
+        .ok_or_else(|| schema_error(path, format!("missing `{}`", key)))
+}
+
+fn only_keys(object: &Map<String, Value>, keys: &[&str], path: &str) -> Result<(), JsonError> {
+    match object.keys().find(|key| !keys.contains(&key.as_str())) {
+        Some(key) => Err(schema_error(path, format!("unexpected key `{}`", key))),
+        None => Ok(()),
+    }
+}
+
+fn int(value: &Value, path: &str) -> Result<i64, JsonError> {
+    value
+        .as_i64()
+        .ok_or_else(|| schema_error(path, "expected an integer that fits in an i64"))
+}
+
+fn name(value: &Value, path: &str) -> Result<String, JsonError> {
+    match value.as_str() {
+        Some(name) if is_identifier(name) => Ok(name.to_string()),
+        _ => Err(schema_error(path, "expected an identifier")),
+    }
+}
+
+/// Whether the lexer would read `name` as one identifier.
+fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    let starts_well = chars.next().is_some_and(|c| c.is_alphabetic() || c == '_');
+    starts_well && chars.all(|c| c.is_alphanumeric() || c == '_') && !KEYWORDS.contains(&name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reads_every_expression_kind() {
+        let json = r#"{"let": "x", "value": {"secret": 5}, "body":
+            {"if": {"binop": ">", "left": {"var": "x"}, "right": {"int": 3}},
+             "then": {"unary": "-", "expr": {"var": "x"}},
+             "else": {"bool": false}}}"#;
+        let expr = Expr::from_json(json).unwrap();
+        assert_eq!(
+            expr.to_string(),
+            "let x = secret(5) (if (x > 3) then (-x) else false)"
+        );
+        let json = r#"{"while": {"binop": "<", "left": {"var": "n"}, "right": 100},
+            "with": "n", "init": 1, "do": {"binop": "*", "left": {"var": "n"}, "right": 2}}"#;
+        let expr = Expr::from_json(json).unwrap();
+        assert_eq!(expr.to_string(), "while (n < 100) with n = 1 do (n * 2)");
+        let json = r#"{"let": ["q", "r"], "value": {"tuple": [7, {"secret": 2}]},
+            "body": {"var": "q"}}"#;
+        let expr = Expr::from_json(json).unwrap();
+        assert_eq!(expr.to_string(), "let (q, r) = (7, secret(2)) q");
+        let json = r#"{"swap_if": {"var": "b"}, "left": 1, "right": {"secret": 2}}"#;
+        let expr = Expr::from_json(json).unwrap();
+        assert_eq!(expr.to_string(), "swap_if(b, 1, secret(2))");
+        let json = r#"{"clamp": {"var": "x"}, "lo": 0, "hi": {"in_range": 1, "lo": 0, "hi": 2}}"#;
+        let expr = Expr::from_json(json).unwrap();
+        assert_eq!(expr.to_string(), "clamp(x, 0, in_range(1, 0, 2))");
+        let expr = Expr::from_json(r#"{"unary": "to_int", "expr": true}"#).unwrap();
+        assert_eq!(expr.to_string(), "to_int(true)");
+        let expr = Expr::from_json(r#"{"cast": {"var": "x"}, "type": "u8"}"#).unwrap();
+        assert_eq!(expr.to_string(), "x as u8");
+        assert!(Expr::from_json(r#"{"cast": 1, "type": "bool"}"#).is_err());
+        let expr = Expr::from_json(r#"{"pow": {"secret": 3}, "exp": 4}"#).unwrap();
+        assert_eq!(expr.to_string(), "pow(secret(3), 4)");
+        let json = r#"{"fold": {"map": {"var": "a"}, "with": {"var": "f"}}, "init": 0,
+            "with": {"lambda": ["s", "x"], "body": {"var": "x"}}}"#;
+        let expr = Expr::from_json(json).unwrap();
+        assert_eq!(expr.to_string(), "fold(map(a, f), 0, fn(s, x) => x)");
+        let json = r#"{"unwrap_or": {"if": true, "then": {"some": 1}, "else": {"none": null}},
+            "default": 0}"#;
+        let expr = Expr::from_json(json).unwrap();
+        assert_eq!(expr.to_string(), "unwrap_or(if true then some(1) else none, 0)");
+        let json = r#"{"match": {"var": "x"}, "arms": [[1, 10], [-1, 20]], "default": 0}"#;
+        let expr = Expr::from_json(json).unwrap();
+        assert_eq!(expr.to_string(), "match x with 1 => 10, -1 => 20, _ => 0");
+        let json = r#"{"enum": "C", "variants": ["A", "B"], "body": {"match": {"var": "c"},
+            "arms": [[{"var": "A"}, 1], [{"var": "B"}, 2]]}}"#;
+        let expr = Expr::from_json(json).unwrap();
+        assert_eq!(expr.to_string(), "enum C = A | B (match c with A => 1, B => 2)");
+        assert!(Expr::from_json(r#"{"match": 1, "arms": [[1, 2]]}"#).is_err());
+        let json = r#"{"let": "k", "type": "secret int", "value": 1, "body": {"var": "k"}}"#;
+        let expr = Expr::from_json(json).unwrap();
+        assert_eq!(expr.to_string(), "let k: secret int = 1 k");
+        let json = r#"{"let": "c", "mut": true, "value": 0, "body": {"assign": "c",
+            "if": {"var": "b"}, "value": 1, "body": {"var": "c"}}}"#;
+        let expr = Expr::from_json(json).unwrap();
+        assert_eq!(expr.to_string(), "let mut c = 0 (if b then c = 1 c)");
+        let json = r#"{"let": "b", "type": "u8", "value": {"int": 200, "type": "u8"},
+            "body": {"var": "b"}}"#;
+        let expr = Expr::from_json(json).unwrap();
+        assert_eq!(expr.to_string(), "let b: u8 = 200u8 b");
+        let json = r#"{"let": "f", "value": {"lambda": ["x"], "body": {"var": "x"}},
+            "body": {"call": "f", "args": [1]}}"#;
+        let expr = Expr::from_json(json).unwrap();
+        assert_eq!(expr.to_string(), "let f = (fn(x) => x) f(1)");
+        let json = r#"{"const": "n", "value": 4, "body": {"var": "n"}}"#;
+        assert_eq!(Expr::from_json(json).unwrap().to_string(), "const n = 4 n");
+        let json = r#"{"fn": "f", "max_depth": 2, "params": [], "body": 1,
+            "rest": {"call": "f", "args": []}}"#;
+        assert_eq!(Expr::from_json(json).unwrap().to_string(), "#[max_depth(2)] fn f() = 1 f()");
+        let json = r#"{"input": "k", "type": "secret u8", "body": {"var": "k"}}"#;
+        assert_eq!(Expr::from_json(json).unwrap().to_string(), "input secret k: u8 k");
+        let json = r#"{"assert": {"var": "b"}, "body": 1}"#;
+        assert_eq!(Expr::from_json(json).unwrap().to_string(), "assert(b) 1");
+    }
+
+    #[test]
+    fn test_errors_name_the_path() {
+        let err = |json| Program::from_json(json).unwrap_err().to_string();
+        assert_eq!(
+            err(r#"{"version": 1, "body": {"binop": "**", "left": 1, "right": 2}}"#),
+            "at $.body.binop: expected a binary operator"
+        );
+        assert_eq!(
+            err(r#"{"version": 1, "body": {"let": "k", "type": "pub", "value": 1, "body": 2}}"#),
+            "at $.body.type: expected a type such as \"secret int\""
+        );
+        assert_eq!(
+            err(r#"{"version": 1, "body": {"secret": 1, "extra": 2}}"#),
+            "at $.body: unexpected key `extra`"
+        );
+        assert_eq!(
+            err(r#"{"version": 1, "body": {"int": 256, "type": "u8"}}"#),
+            "at $.body.int: expected an integer that fits in a u8"
+        );
+        assert_eq!(
+            err(r#"{"version": 1, "body": {"let": "if", "value": 1, "body": 2}}"#),
+            "at $.body.let: expected an identifier"
+        );
+        assert_eq!(
+            err(r#"{"version": 1, "body": {"if": true, "then": 1}}"#),
+            "at $.body: missing `else`"
+        );
+        assert_eq!(
+            err(r#"{"version": 1, "body": {"index": {"array": [1, true]}, "at": {"x": 0}}}"#),
+            "at $.body.at: expected an expression"
+        );
+        assert_eq!(
+            err(r#"{"version": 1, "body": {"array": []}}"#),
+            "at $.body.array: expected a non-empty array"
+        );
+        assert_eq!(
+            err(r#"{"version": 1, "body": {"let": ["x"], "value": 1, "body": 2}}"#),
+            "at $.body.let: expected two or more names"
+        );
+        assert_eq!(
+            err(r#"{"version": 1, "body": {"bytes": [104, 256]}}"#),
+            "at $.body.bytes: expected a non-empty array of bytes"
+        );
+        assert_eq!(
+            err(r#"{"version": 1, "body": {"lookup": 1, "entries": [[1]], "default": 0}}"#),
+            "at $.body.entries[0]: expected a [key, value] pair"
+        );
+        let arms = r#"{"version": 1,
+            "body": {"match": 1, "arms": [[{"secret": 1}, 1]], "default": 0}}"#;
+        let message = "at $.body.arms[0][0]: expected an integer, boolean or variant";
+        assert_eq!(err(arms), message);
+        let arms = r#"{"version": 1, "body": {"match": 1, "arms": [[1, 2]]}}"#;
+        assert_eq!(err(arms), "at $.body: missing `default`");
+        let none = err(r#"{"version": 1, "body": {"none": 0}}"#);
+        assert_eq!(none, "at $.body.none: expected null");
+        let version = err(r#"{"version": 2, "body": 1}"#);
+        assert_eq!(version, "unsupported schema version 2 (expected 1)");
+    }
+
+    #[test]
+    fn test_program_transpiles_like_source() {
+        let json = r#"{"version": 1, "body": {"binop": "*", "left": {"secret": 6}, "right": 7}}"#;
+        let program = Program::from_json(json).unwrap();
+        let config = crate::Config::default();
+        let source = program.to_string();
+        assert_eq!(
+            program.transpile(&config).unwrap(),
+            crate::transpile_with(&source, &config).unwrap()
+        );
+    }
+}
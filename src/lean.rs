@@ -0,0 +1,438 @@
+// SPDX-License-Identifier: MIT OR AGPL-3.0-or-later
+// SPDX-FileCopyrightText: 2024-2025 hyperpolymath
+
+//! Export of a program's semantics as Lean 4 definitions.
+//!
+//! [`to_lean`] writes two Lean functions for one program: `source`, the
+//! meaning of its AST [desugared](crate::desugar), and
+//! `oblivious`, the meaning of its oblivious IR (before optimisation
+//! passes), followed by the statement that they are equal, left as `sorry`
+//! to be proved. Each input becomes a parameter. Programs with named
+//! `outputs` or sized integers are not exported.
+//!
+//! Values follow the reference interpreter: integers wrap around like
+//! `i64` (the saturating operators clamp instead), division truncates,
+//! and a division by zero is `none`. Secrecy is erased. The difference
+//! between the two is in the branches: `if` in `source` evaluates one
+//! branch, while `ctSelect` in `oblivious` evaluates both, so the theorem
+//! fails for programs that divide by zero in the arm a secret condition
+//! does not take. `while` loops are `partial`, so they need not terminate.
+
+use crate::desugar::desugar;
+use crate::ast::Expr;
+use crate::diagnostic::Diagnostic;
+use crate::interp::{Inputs, Value};
+use crate::ir::{ObliBinOp, ObliExpr, ObliUnaryOp};
+use crate::run::{prepare_ast, RunError};
+use crate::span::NodeSpans;
+use crate::transform::to_oblivious_checked;
+use crate::typeck::{typecheck, Type, TypeError};
+
+/// Lean definitions of the values and operations both semantics share.
+const PRELUDE: &str = r#"namespace Obli
+
+/-- Two's complement wrap-around to 64 bits. -/
+def wrap (x : Int) : Int := Int.bmod x (2 ^ 64)
+
+/-- Division truncating towards zero, like Rust's `/`. -/
+def tdiv (a b : Int) : Int :=
+  let q : Int := ↑(a.natAbs / b.natAbs)
+  if decide (a < 0) == decide (b < 0) then q else -q
+
+def add (a b : Int) : Option Int := some (wrap (a + b))
+def sub (a b : Int) : Option Int := some (wrap (a - b))
+def mul (a b : Int) : Option Int := some (wrap (a * b))
+
+/-- Clamping to the 64-bit range, for the saturating operators. -/
+def clamp (x : Int) : Int := max (-(2 ^ 63)) (min (2 ^ 63 - 1) x)
+
+def addSat (a b : Int) : Option Int := some (clamp (a + b))
+def subSat (a b : Int) : Option Int := some (clamp (a - b))
+def mulSat (a b : Int) : Option Int := some (clamp (a * b))
+def div (a b : Int) : Option Int := if b == 0 then none else some (wrap (tdiv a b))
+def mod (a b : Int) : Option Int := if b == 0 then none else some (wrap (a - b * tdiv a b))
+
+/-- The 64-bit word of `x`, for the bitwise operators. Shifts take the
+amount modulo 64, and `>>` is arithmetic. -/
+def word (x : Int) : BitVec 64 := BitVec.ofInt 64 x
+
+def bitAnd (a b : Int) : Option Int := some (word a &&& word b).toInt
+def bitOr (a b : Int) : Option Int := some (word a ||| word b).toInt
+def bitXor (a b : Int) : Option Int := some (word a ^^^ word b).toInt
+def shl (a b : Int) : Option Int := some (word a <<< (b % 64).toNat).toInt
+def shr (a b : Int) : Option Int := some ((word a).sshiftRight (b % 64).toNat).toInt
+def eq {α : Type} [BEq α] (a b : α) : Option Bool := some (a == b)
+def ne {α : Type} [BEq α] (a b : α) : Option Bool := some (a != b)
+def lt (a b : Int) : Option Bool := some (decide (a < b))
+def le (a b : Int) : Option Bool := some (decide (a ≤ b))
+def gt (a b : Int) : Option Bool := some (decide (a > b))
+def ge (a b : Int) : Option Bool := some (decide (a ≥ b))
+def band (a b : Bool) : Option Bool := some (a && b)
+def bor (a b : Bool) : Option Bool := some (a || b)
+def neg (a : Int) : Option Int := some (wrap (-a))
+def bnot (a : Bool) : Option Bool := some (!a)
+def toInt (a : Bool) : Option Int := some (if a then 1 else 0)
+def toBool (a : Int) : Option Bool := some (a != 0)
+
+/-- Constant-time select: both arms are already evaluated. -/
+def ctSelect {α : Type} (c : Bool) (t e : α) : Option α := some (if c then t else e)
+
+def lift2 {α β γ : Type} (f : α → β → Option γ)
+    (a : Option α) (b : Option β) : Option γ :=
+  a.bind fun x => b.bind fun y => f x y
+
+def lift3 {α β γ δ : Type} (f : α → β → γ → Option δ)
+    (a : Option α) (b : Option β) (c : Option γ) : Option δ :=
+  a.bind fun x => b.bind fun y => c.bind fun z => f x y z
+
+/-- A `while` loop, which need not terminate. -/
+partial def whileLoop {α : Type} (cond : α → Option Bool) (body : α → Option α)
+    (acc : α) : Option α :=
+  (cond acc).bind fun c => if c then (body acc).bind (whileLoop cond body) else some acc
+
+end Obli
+"#;
+
+/// Lean 4 source for `source` with its free variables bound to `inputs`.
+/// Input values only fix each parameter's type and secrecy.
+pub fn to_lean(source: &str, inputs: &Inputs) -> Result<String, RunError> {
+    let (ast, spans) = prepare_ast(source, inputs)?;
+    if has_input(&ast) {
+        let message = "input declarations in Lean export".to_string();
+        return Err(RunError::Unsupported(message));
+    }
+    if has_assert(&ast) {
+        let message = "assertions in Lean export".to_string();
+        return Err(RunError::Unsupported(message));
+    }
+    if let Ok(Type::Outputs) = typecheck(&ast) {
+        // The program's root comes before the lets binding inputs
+        let span = spans.get(spans.len() - 1 - inputs.iter().count()).unwrap_or_default();
+        let error = TypeError::MisplacedOutputs { span };
+        return Err(RunError::Diagnostics(vec![error.into()]));
+    }
+    if has_sized(&ast) {
+        let message = "sized integers in Lean export".to_string();
+        return Err(RunError::Unsupported(message));
+    }
+    let ast = desugar(&ast);
+    let (ir, _, _) = to_oblivious_checked(&ast, &NodeSpans::new()).map_err(|errors| {
+        RunError::Diagnostics(errors.into_iter().map(Diagnostic::from).collect())
+    })?;
+
+    // Inputs are the leading lets, in name order: make them parameters
+    let (mut params, mut args) = (String::new(), String::new());
+    let (mut ast_body, mut ir_body) = (&ast, &ir);
+    for _ in 0..inputs.iter().count() {
+        match (ast_body, ir_body) {
+            (
+                Expr::Let {
+                    name, value, body, ..
+                },
+                ObliExpr::Let { body: ir, .. },
+            ) => {
+                let ty = match input_value(value) {
+                    Value::Int(_) => "Int",
+                    Value::Bool(_) => "Bool",
+                    Value::Sized(..) => unreachable!("rejected before export"),
+                };
+                params.push_str(&format!(" ({} : {})", ident(name), ty));
+                args.push_str(&format!(" {}", ident(name)));
+                ast_body = body;
+                ir_body = ir;
+            }
+            _ => unreachable!("prepare_ast binds every input with a let"),
+        }
+    }
+    let ty = match typecheck(ast_body) {
+        Ok(Type::Bool) => "Bool",
+        _ => "Int",
+    };
+
+    let mut out = String::from("-- Generated by obli-transpiler\n\n");
+    out.push_str(PRELUDE);
+    out.push_str("\nnamespace Program\n\n");
+    out.push_str("/-- The source program: `if` evaluates only the branch it takes. -/\n");
+    let source = ast_term(ast_body);
+    out.push_str(&format!("def source{} : Option {} :=\n  {}\n\n", params, ty, source));
+    out.push_str("/-- The oblivious IR: secret conditions select between both arms. -/\n");
+    let oblivious = ir_term(ir_body);
+    out.push_str(&format!("def oblivious{} : Option {} :=\n  {}\n\n", params, ty, oblivious));
+    out.push_str("/-- The transform preserves the program's meaning. -/\n");
+    out.push_str(&format!(
+        "theorem oblivious_eq_source{} :\n    oblivious{} = source{} := by\n  sorry\n\n",
+        params, args, args
+    ));
+    out.push_str("end Program\n");
+    Ok(out)
+}
+
+fn input_value(expr: &Expr) -> Value {
+    match expr {
+        Expr::Int(n) => Value::Int(*n),
+        Expr::Bool(b) => Value::Bool(*b),
+        Expr::Secret(inner) => input_value(inner),
+        _ => unreachable!("inputs are bound to literals"),
+    }
+}
+
+/// A MiniObli variable as a Lean identifier that cannot clash with Lean
+/// keywords or the binders introduced here.
+fn ident(name: &str) -> String {
+    format!("v_{}", name)
+}
+
+fn int(n: i64) -> String {
+    if n < 0 {
+        format!("(some ({}))", n)
+    } else {
+        format!("(some {})", n)
+    }
+}
+
+fn operation(op: &ObliBinOp) -> &'static str {
+    match op {
+        ObliBinOp::CtAdd => "Obli.add",
+        ObliBinOp::CtSub => "Obli.sub",
+        ObliBinOp::CtMul => "Obli.mul",
+        ObliBinOp::CtAddSat => "Obli.addSat",
+        ObliBinOp::CtSubSat => "Obli.subSat",
+        ObliBinOp::CtMulSat => "Obli.mulSat",
+        ObliBinOp::CtDiv => "Obli.div",
+        ObliBinOp::CtMod => "Obli.mod",
+        ObliBinOp::CtBitAnd => "Obli.bitAnd",
+        ObliBinOp::CtBitOr => "Obli.bitOr",
+        ObliBinOp::CtBitXor => "Obli.bitXor",
+        ObliBinOp::CtShl => "Obli.shl",
+        ObliBinOp::CtShr => "Obli.shr",
+        ObliBinOp::CtEq => "Obli.eq",
+        ObliBinOp::CtNe => "Obli.ne",
+        ObliBinOp::CtLt => "Obli.lt",
+        ObliBinOp::CtLe => "Obli.le",
+        ObliBinOp::CtGt => "Obli.gt",
+        ObliBinOp::CtGe => "Obli.ge",
+        ObliBinOp::CtAnd => "Obli.band",
+        ObliBinOp::CtOr => "Obli.bor",
+    }
+}
+
+fn unary_operation(op: &ObliUnaryOp) -> &'static str {
+    match op {
+        ObliUnaryOp::CtNeg => "Obli.neg",
+        ObliUnaryOp::CtNot => "Obli.bnot",
+        ObliUnaryOp::CtToInt => "Obli.toInt",
+        ObliUnaryOp::CtToBool => "Obli.toBool",
+    }
+}
+
+fn let_term(name: &str, value: String, body: String) -> String {
+    format!("({}.bind fun {} => {})", value, ident(name), body)
+}
+
+fn if_term(cond: String, then_term: String, else_term: String) -> String {
+    format!("({}.bind fun c => if c then {} else {})", cond, then_term, else_term)
+}
+
+fn swap_term(cond: String, left: String, right: String, names: [&str; 2], body: String) -> String {
+    let [first, second] = names.map(ident);
+    format!(
+        "({}.bind fun c => {}.bind fun l => {}.bind fun r => \
+         let ({}, {}) := if c then (r, l) else (l, r); {})",
+        cond, left, right, first, second, body
+    )
+}
+
+fn while_term(cond: String, acc: &str, init: String, body: String) -> String {
+    let acc = ident(acc);
+    format!(
+        "({}.bind (Obli.whileLoop (fun {} => {}) (fun {} => {})))",
+        init, acc, cond, acc, body
+    )
+}
+
+/// Whether `expr` has a sized integer literal or a cast, which the
+/// prelude's 64-bit operations do not model.
+fn has_sized(expr: &Expr) -> bool {
+    matches!(expr, Expr::Sized(..) | Expr::Cast { .. })
+        || expr.children().into_iter().any(has_sized)
+}
+
+/// Whether `expr` declares an input, which Lean export does not read.
+fn has_input(expr: &Expr) -> bool {
+    matches!(expr, Expr::Input { .. }) || expr.children().into_iter().any(has_input)
+}
+
+/// Whether `expr` has an assertion, whose failure Lean export does not
+/// model.
+fn has_assert(expr: &Expr) -> bool {
+    matches!(expr, Expr::Assert { .. }) || expr.children().into_iter().any(has_assert)
+}
+
+fn ast_term(expr: &Expr) -> String {
+    match expr {
+        Expr::Int(n) => int(*n),
+        Expr::Bool(b) => format!("(some {})", b),
+        Expr::Var(name) => format!("(some {})", ident(name)),
+        Expr::Secret(inner) | Expr::Declassify(inner) => ast_term(inner),
+        Expr::BinOp { op, left, right } => format!(
+            "(Obli.lift2 {} {} {})",
+            operation(&ObliBinOp::from(op)),
+            ast_term(left),
+            ast_term(right)
+        ),
+        Expr::UnaryOp { op, expr } => {
+            format!("({}.bind {})", ast_term(expr), unary_operation(&ObliUnaryOp::from(op)))
+        }
+        Expr::If {
+            cond,
+            then_branch,
+            else_branch,
+        } => if_term(ast_term(cond), ast_term(then_branch), ast_term(else_branch)),
+        Expr::Let {
+            name, value, body, ..
+        } => let_term(name, ast_term(value), ast_term(body)),
+        Expr::While {
+            cond,
+            acc,
+            init,
+            body,
+        } => while_term(ast_term(cond), acc, ast_term(init), ast_term(body)),
+        Expr::LetTuple { names, value, body } => match (&**value, &names[..]) {
+            (Expr::SwapIf { cond, left, right }, [first, second]) => swap_term(
+                ast_term(cond),
+                ast_term(left),
+                ast_term(right),
+                [first, second],
+                ast_term(body),
+            ),
+            _ => unreachable!("desugared before export"),
+        },
+        Expr::Array(_)
+        | Expr::Tuple(_)
+        | Expr::SwapIf { .. }
+        | Expr::Clamp { .. }
+        | Expr::InRange { .. }
+        | Expr::Pow { .. }
+        | Expr::Map { .. }
+        | Expr::Fold { .. }
+        | Expr::Some(_)
+        | Expr::None
+        | Expr::UnwrapOr { .. }
+        | Expr::Bytes(_)
+        | Expr::Sort(_)
+        | Expr::Index { .. }
+        | Expr::Lookup { .. }
+        | Expr::Match { .. }
+        | Expr::Fn { .. }
+        | Expr::Call { .. }
+        | Expr::Lambda { .. }
+        | Expr::Const { .. }
+        | Expr::Enum { .. }
+        | Expr::For { .. }
+        | Expr::Assign { .. } => {
+            unreachable!("desugared before export")
+        }
+        Expr::Outputs(_)
+        | Expr::Sized(..)
+        | Expr::Cast { .. }
+        | Expr::Input { .. }
+        | Expr::Assert { .. } => unreachable!("rejected before export"),
+    }
+}
+
+fn ir_term(expr: &ObliExpr) -> String {
+    match expr {
+        ObliExpr::PubInt(n) | ObliExpr::SecretInt(n) => int(*n),
+        ObliExpr::PubBool(b) | ObliExpr::SecretBool(b) => format!("(some {})", b),
+        ObliExpr::Var { name, .. } => format!("(some {})", ident(name)),
+        ObliExpr::BinOp { op, left, right, .. } => {
+            format!("(Obli.lift2 {} {} {})", operation(op), ir_term(left), ir_term(right))
+        }
+        ObliExpr::UnaryOp { op, expr, .. } => {
+            format!("({}.bind {})", ir_term(expr), unary_operation(op))
+        }
+        ObliExpr::CtSelect {
+            cond,
+            then_val,
+            else_val,
+        } => format!(
+            "(Obli.lift3 Obli.ctSelect {} {} {})",
+            ir_term(cond),
+            ir_term(then_val),
+            ir_term(else_val)
+        ),
+        ObliExpr::PubIf {
+            cond,
+            then_branch,
+            else_branch,
+        } => if_term(ir_term(cond), ir_term(then_branch), ir_term(else_branch)),
+        ObliExpr::Let {
+            name, value, body, ..
+        } => let_term(name, ir_term(value), ir_term(body)),
+        ObliExpr::Declassify(inner) => ir_term(inner),
+        ObliExpr::Outputs(_)
+        | ObliExpr::Sized { .. }
+        | ObliExpr::CtCast { .. }
+        | ObliExpr::Input { .. }
+        | ObliExpr::Assert { .. } => unreachable!("rejected before export"),
+        ObliExpr::Table { values, index } => ir_term(&ObliExpr::table_scan(values, index)),
+        ObliExpr::While {
+            cond,
+            acc,
+            init,
+            body,
+            ..
+        } => while_term(ir_term(cond), acc, ir_term(init), ir_term(body)),
+        ObliExpr::CtSwap {
+            cond,
+            left,
+            right,
+            names: [first, second],
+            body,
+            ..
+        } => swap_term(
+            ir_term(cond),
+            ir_term(left),
+            ir_term(right),
+            [first, second],
+            ir_term(body),
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_secret_if_becomes_select() {
+        let inputs = Inputs::new()
+            .secret("k", Value::Int(0))
+            .public("n", Value::Int(0));
+        let lean = to_lean("if k > n then k / 2 else 0 - k", &inputs).unwrap();
+        assert!(lean.contains(
+            "def source (v_k : Int) (v_n : Int) : Option Int :=\n  \
+             ((Obli.lift2 Obli.gt (some v_k) (some v_n)).bind fun c => \
+             if c then (Obli.lift2 Obli.div (some v_k) (some 2)) \
+             else (Obli.lift2 Obli.sub (some 0) (some v_k)))\n"
+        ));
+        assert!(lean.contains(
+            "def oblivious (v_k : Int) (v_n : Int) : Option Int :=\n  \
+             (Obli.lift3 Obli.ctSelect (Obli.lift2 Obli.gt (some v_k) (some v_n)) \
+             (Obli.lift2 Obli.div (some v_k) (some 2)) (Obli.lift2 Obli.sub (some 0) (some v_k)))\n"
+        ));
+        assert!(lean.contains(
+            "theorem oblivious_eq_source (v_k : Int) (v_n : Int) :\n    \
+             oblivious v_k v_n = source v_k v_n := by\n  sorry\n"
+        ));
+    }
+
+    #[test]
+    fn test_closed_program() {
+        let lean = to_lean("let b = secret(true) !b", &Inputs::new()).unwrap();
+        assert!(lean.contains("def source : Option Bool :=\n  ((some true).bind fun v_b =>"));
+        assert!(lean.contains("theorem oblivious_eq_source :\n    oblivious = source := by"));
+        assert!(to_lean("assert(true) 1", &Inputs::new()).is_err());
+        assert!(to_lean("(1 as u8) as int", &Inputs::new()).is_err());
+    }
+}
@@ -2,27 +2,78 @@
 // SPDX-FileCopyrightText: 2024-2025 hyperpolymath
 
 //! Lexer for MiniObli language.
+//!
+//! The lexer walks the input by byte offset and reads identifiers and
+//! numbers as slices of it. Identifiers are interned once per distinct
+//! name per lexer; repeats are looked up by the borrowed slice.
 
+use crate::codes;
+use crate::span::Span;
+use crate::symbol::Symbol;
+use crate::typeck::Width;
+use std::collections::HashMap;
 use thiserror::Error;
 
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub enum Token {
     // Literals
     Int(i64),
+    /// An integer with a width suffix, such as `200u8`; a `u64` is held as
+    /// its bit pattern
+    Sized(i64, Width),
     Bool(bool),
-    Ident(String),
+    Ident(Symbol),
+    /// `b"..."`, never empty
+    Bytes(Vec<u8>),
 
     // Keywords
     Let,
+    Const,
+    Enum,
     If,
     Then,
     Else,
+    Elif,
     Secret,
+    Declassify,
+    Lookup,
+    Sort,
+    SwapIf,
+    Clamp,
+    InRange,
+    ToInt,
+    ToBool,
+    Pow,
+    Map,
+    Fold,
+    Some,
+    None,
+    UnwrapOr,
+    Assert,
+    Saturating,
+    Outputs,
+    Fn,
+    For,
+    While,
+    Match,
+    In,
+    With,
+    Do,
+    /// `as`, before the type of a cast
+    As,
 
     // Operators
     Plus,
     Minus,
     Star,
+    /// `+|`, saturating addition
+    SatPlus,
+    /// `-|`
+    SatMinus,
+    /// `*|`
+    SatStar,
     Slash,
     Percent,
     Eq,
@@ -34,107 +85,281 @@ pub enum Token {
     And,
     Or,
     Not,
+    /// `&`, bitwise and
+    Amp,
+    /// `|`, bitwise or
+    Pipe,
+    /// `^`, bitwise xor
+    Caret,
+    /// `<<`
+    Shl,
+    /// `>>`, arithmetic
+    Shr,
 
     // Delimiters
     LParen,
     RParen,
+    LBracket,
+    RBracket,
+    Comma,
     Assign,
+    /// `..`, in `for` ranges
+    DotDot,
+    /// `=>`, in `match` arms
+    FatArrow,
+    /// `:`, before a `let` binding's type
+    Colon,
+    /// `;`, optionally ending a statement
+    Semicolon,
+    /// `#` directly before `[`, opening an attribute such as
+    /// `#[max_depth(8)]`; any other `#` starts a comment
+    Hash,
 
     // End
     Eof,
 }
 
+/// Words the lexer never reads as identifiers.
+pub const KEYWORDS: &[&str] = &[
+    "let", "const", "enum", "if", "then", "else", "elif", "secret", "declassify", "lookup",
+    "sort", "swap_if", "clamp", "in_range", "to_int", "to_bool", "pow", "map", "fold", "some",
+    "none", "unwrap_or", "assert", "saturating", "outputs", "fn", "for", "while", "match", "in",
+    "with", "do", "as", "true", "false", "and", "or", "not",
+];
+
 #[derive(Error, Debug)]
 pub enum LexError {
     #[error("unexpected character: '{0}' at position {1}")]
     UnexpectedChar(char, usize),
     #[error("invalid number at position {0}")]
     InvalidNumber(usize),
+    #[error("expected digits after `{0}` at position {1}")]
+    MissingDigits(&'static str, usize),
+    #[error("invalid suffix `{0}` on a number at position {1}")]
+    InvalidSuffix(String, usize),
+    #[error("invalid byte string at position {0}")]
+    InvalidByteString(usize),
+}
+
+impl LexError {
+    /// Catalog code of this error.
+    pub fn code(&self) -> &'static str {
+        match self {
+            LexError::UnexpectedChar(..) => codes::UNEXPECTED_CHAR,
+            LexError::InvalidNumber(_)
+            | LexError::MissingDigits(..)
+            | LexError::InvalidSuffix(..) => codes::INVALID_NUMBER,
+            LexError::InvalidByteString(_) => codes::INVALID_BYTE_STRING,
+        }
+    }
+
+    /// Source span of the offending input.
+    pub fn span(&self) -> Span {
+        match self {
+            LexError::UnexpectedChar(c, pos) => Span::new(*pos, pos + c.len_utf8()),
+            LexError::InvalidNumber(pos) | LexError::InvalidByteString(pos) => {
+                Span::new(*pos, pos + 1)
+            }
+            LexError::MissingDigits(prefix, pos) => Span::new(*pos, pos + prefix.len()),
+            LexError::InvalidSuffix(suffix, pos) => Span::new(*pos, pos + suffix.len()),
+        }
+    }
 }
 
+/// Tokenizer over borrowed input: identifiers and numbers are read as
+/// slices of the input, never copied.
 pub struct Lexer<'a> {
     input: &'a str,
-    chars: std::iter::Peekable<std::str::CharIndices<'a>>,
+    /// Byte offset of the next unconsumed character.
     pos: usize,
+    /// Symbols of the identifiers seen so far, keyed by their text in the
+    /// input, so each distinct name reaches the global interner once.
+    idents: HashMap<&'a str, Symbol>,
 }
 
 impl<'a> Lexer<'a> {
     pub fn new(input: &'a str) -> Self {
         Self {
             input,
-            chars: input.char_indices().peekable(),
             pos: 0,
+            idents: HashMap::new(),
         }
     }
 
-    fn advance(&mut self) -> Option<(usize, char)> {
-        let result = self.chars.next();
-        if let Some((pos, _)) = result {
-            self.pos = pos;
+    /// Tokenize the whole input, returning tokens alongside their spans.
+    pub fn tokenize(mut self) -> Result<(Vec<Token>, Vec<Span>), LexError> {
+        let _span = tracing::debug_span!("lex", bytes = self.input.len()).entered();
+        let mut tokens = Vec::new();
+        let mut spans = Vec::new();
+        loop {
+            self.skip_whitespace();
+            let start = self.pos;
+            match self.next_token()? {
+                Token::Eof => break,
+                token => {
+                    tokens.push(token);
+                    spans.push(Span::new(start, self.pos));
+                }
+            }
         }
-        result
+        Ok((tokens, spans))
     }
 
-    fn peek(&mut self) -> Option<char> {
-        self.chars.peek().map(|(_, c)| *c)
+    /// The unconsumed input.
+    fn rest(&self) -> &'a str {
+        &self.input[self.pos..]
+    }
+
+    fn advance(&mut self) -> Option<(usize, char)> {
+        let c = self.peek()?;
+        let pos = self.pos;
+        self.pos += c.len_utf8();
+        Some((pos, c))
+    }
+
+    fn peek(&self) -> Option<char> {
+        match self.input.as_bytes().get(self.pos) {
+            Some(b) if b.is_ascii() => Some(*b as char),
+            Some(_) => self.rest().chars().next(),
+            None => None,
+        }
     }
 
     fn skip_whitespace(&mut self) {
         while let Some(c) = self.peek() {
             if c.is_whitespace() {
                 self.advance();
-            } else if c == '#' {
+            } else if c == '#' && !self.rest().starts_with("#[") {
                 // Skip comments
-                while let Some(c) = self.peek() {
-                    if c == '\n' {
-                        break;
-                    }
-                    self.advance();
-                }
+                self.pos += self.rest().find('\n').unwrap_or(self.rest().len());
             } else {
                 break;
             }
         }
     }
 
+    /// Consume the longest prefix of the rest of the input whose characters
+    /// satisfy `f`, returning the token text from `start`.
+    fn take_while(&mut self, start: usize, f: impl Fn(char) -> bool) -> &'a str {
+        let rest = self.rest();
+        self.pos += rest.find(|c| !f(c)).unwrap_or(rest.len());
+        &self.input[start..self.pos]
+    }
+
+    /// Read an integer, decimal or after a `0x` or `0b` prefix, with `_`
+    /// allowed between its digits, and its optional type suffix: `i64`
+    /// keeps it an `int`, and a [`Width`] name makes it sized.
     fn read_number(&mut self, start: usize) -> Result<Token, LexError> {
-        let mut end = start;
-        while let Some(c) = self.peek() {
-            if c.is_ascii_digit() {
-                end = self.advance().unwrap().0;
-            } else {
-                break;
-            }
+        let invalid = || LexError::InvalidNumber(start);
+        let (radix, prefix) = match self.input[start..].get(..2) {
+            Some("0x") => (16, "0x"),
+            Some("0b") => (2, "0b"),
+            _ => (10, ""),
+        };
+        let digits_start = start + prefix.len();
+        self.pos = self.pos.max(digits_start);
+        let digits = self.take_while(digits_start, |c| c.is_digit(radix) || c == '_');
+        let digits: String = digits.chars().filter(|&c| c != '_').collect();
+        if digits.is_empty() {
+            return Err(LexError::MissingDigits(prefix, start));
         }
-        let num_str = &self.input[start..=end];
-        num_str
-            .parse::<i64>()
-            .map(Token::Int)
-            .map_err(|_| LexError::InvalidNumber(start))
+        // Any digits left over do not fit in a `u128`
+        let n = u128::from_str_radix(&digits, radix).map_err(|_| invalid())?;
+        let suffix_start = self.pos;
+        let suffix = self.take_while(suffix_start, |c| c.is_ascii_alphanumeric() || c == '_');
+        let width = match suffix {
+            "" | "i64" => None,
+            _ => Some(
+                Width::from_name(suffix)
+                    .ok_or_else(|| LexError::InvalidSuffix(suffix.to_string(), suffix_start))?,
+            ),
+        };
+        let max = width.map_or(i64::MAX.into(), |width| width.range().1);
+        if n > max as u128 {
+            return Err(invalid());
+        }
+        Ok(match width {
+            None => Token::Int(n as i64),
+            Some(width) => Token::Sized(width.wrap(n as i128), width),
+        })
     }
 
-    fn read_ident(&mut self, start: usize) -> Token {
-        let mut end = start;
-        while let Some(c) = self.peek() {
-            if c.is_alphanumeric() || c == '_' {
-                end = self.advance().unwrap().0;
-            } else {
-                break;
-            }
+    /// Read a byte string after its `b`: printable ASCII, with `\\`,
+    /// `\"`, `\n`, `\t`, `\0` and `\xHH` escapes.
+    fn read_bytes(&mut self, start: usize) -> Result<Token, LexError> {
+        let invalid = || LexError::InvalidByteString(start);
+        self.advance();
+        let mut bytes = Vec::new();
+        loop {
+            let byte = match self.advance().ok_or_else(invalid)?.1 {
+                '"' if bytes.is_empty() => return Err(invalid()),
+                '"' => return Ok(Token::Bytes(bytes)),
+                '\\' => match self.advance().ok_or_else(invalid)?.1 {
+                    '\\' => b'\\',
+                    '"' => b'"',
+                    'n' => b'\n',
+                    't' => b'\t',
+                    '0' => 0,
+                    'x' => {
+                        let digits = self.rest().get(..2).ok_or_else(invalid)?;
+                        if !digits.bytes().all(|b| b.is_ascii_hexdigit()) {
+                            return Err(invalid());
+                        }
+                        let byte = u8::from_str_radix(digits, 16).map_err(|_| invalid())?;
+                        self.pos += 2;
+                        byte
+                    }
+                    _ => return Err(invalid()),
+                },
+                c if c == ' ' || c.is_ascii_graphic() => c as u8,
+                _ => return Err(invalid()),
+            };
+            bytes.push(byte);
         }
-        let ident = &self.input[start..=end];
+    }
+
+    fn read_ident(&mut self, start: usize) -> Token {
+        let ident = self.take_while(start, |c| c.is_alphanumeric() || c == '_');
         match ident {
             "let" => Token::Let,
+            "const" => Token::Const,
+            "enum" => Token::Enum,
             "if" => Token::If,
             "then" => Token::Then,
             "else" => Token::Else,
+            "elif" => Token::Elif,
             "secret" => Token::Secret,
+            "declassify" => Token::Declassify,
+            "lookup" => Token::Lookup,
+            "sort" => Token::Sort,
+            "swap_if" => Token::SwapIf,
+            "clamp" => Token::Clamp,
+            "in_range" => Token::InRange,
+            "to_int" => Token::ToInt,
+            "to_bool" => Token::ToBool,
+            "pow" => Token::Pow,
+            "map" => Token::Map,
+            "fold" => Token::Fold,
+            "some" => Token::Some,
+            "none" => Token::None,
+            "unwrap_or" => Token::UnwrapOr,
+            "assert" => Token::Assert,
+            "saturating" => Token::Saturating,
+            "outputs" => Token::Outputs,
+            "fn" => Token::Fn,
+            "for" => Token::For,
+            "while" => Token::While,
+            "match" => Token::Match,
+            "in" => Token::In,
+            "with" => Token::With,
+            "do" => Token::Do,
+            "as" => Token::As,
             "true" => Token::Bool(true),
             "false" => Token::Bool(false),
             "and" => Token::And,
             "or" => Token::Or,
             "not" => Token::Not,
-            _ => Token::Ident(ident.to_string()),
+            _ => Token::Ident(*self.idents.entry(ident).or_insert_with(|| Symbol::intern(ident))),
         }
     }
 
@@ -147,17 +372,39 @@ fn next_token(&mut self) -> Result<Token, LexError> {
         };
 
         match c {
+            '+' | '-' | '*' if self.peek() == Some('|') => {
+                self.advance();
+                Ok(match c {
+                    '+' => Token::SatPlus,
+                    '-' => Token::SatMinus,
+                    _ => Token::SatStar,
+                })
+            }
             '+' => Ok(Token::Plus),
             '-' => Ok(Token::Minus),
             '*' => Ok(Token::Star),
             '/' => Ok(Token::Slash),
             '%' => Ok(Token::Percent),
+            '^' => Ok(Token::Caret),
             '(' => Ok(Token::LParen),
             ')' => Ok(Token::RParen),
+            '[' => Ok(Token::LBracket),
+            ']' => Ok(Token::RBracket),
+            ',' => Ok(Token::Comma),
+            ':' => Ok(Token::Colon),
+            ';' => Ok(Token::Semicolon),
+            '#' => Ok(Token::Hash),
+            '.' if self.peek() == Some('.') => {
+                self.advance();
+                Ok(Token::DotDot)
+            }
             '=' => {
                 if self.peek() == Some('=') {
                     self.advance();
                     Ok(Token::Eq)
+                } else if self.peek() == Some('>') {
+                    self.advance();
+                    Ok(Token::FatArrow)
                 } else {
                     Ok(Token::Assign)
                 }
@@ -174,6 +421,9 @@ fn next_token(&mut self) -> Result<Token, LexError> {
                 if self.peek() == Some('=') {
                     self.advance();
                     Ok(Token::Le)
+                } else if self.peek() == Some('<') {
+                    self.advance();
+                    Ok(Token::Shl)
                 } else {
                     Ok(Token::Lt)
                 }
@@ -182,6 +432,9 @@ fn next_token(&mut self) -> Result<Token, LexError> {
                 if self.peek() == Some('=') {
                     self.advance();
                     Ok(Token::Ge)
+                } else if self.peek() == Some('>') {
+                    self.advance();
+                    Ok(Token::Shr)
                 } else {
                     Ok(Token::Gt)
                 }
@@ -191,7 +444,7 @@ fn next_token(&mut self) -> Result<Token, LexError> {
                     self.advance();
                     Ok(Token::And)
                 } else {
-                    Err(LexError::UnexpectedChar(c, pos))
+                    Ok(Token::Amp)
                 }
             }
             '|' => {
@@ -199,10 +452,11 @@ fn next_token(&mut self) -> Result<Token, LexError> {
                     self.advance();
                     Ok(Token::Or)
                 } else {
-                    Err(LexError::UnexpectedChar(c, pos))
+                    Ok(Token::Pipe)
                 }
             }
             _ if c.is_ascii_digit() => self.read_number(pos),
+            'b' if self.peek() == Some('"') => self.read_bytes(pos),
             _ if c.is_alphabetic() || c == '_' => Ok(self.read_ident(pos)),
             _ => Err(LexError::UnexpectedChar(c, pos)),
         }
@@ -233,7 +487,7 @@ fn test_basic_tokens() {
             tokens.unwrap(),
             vec![
                 Token::Let,
-                Token::Ident("x".to_string()),
+                Token::Ident("x".into()),
                 Token::Assign,
                 Token::Int(42),
             ]
@@ -257,6 +511,33 @@ fn test_operators() {
                 Token::Int(7),
             ]
         );
+        let tokens: Vec<_> = Lexer::new("x => y = 1").collect::<Result<_, _>>().unwrap();
+        assert_eq!(tokens[1..4], [Token::FatArrow, Token::Ident("y".into()), Token::Assign]);
+        let tokens: Result<Vec<_>, _> = Lexer::new("& && | || +| ^ << <= >> >=").collect();
+        assert_eq!(
+            tokens.unwrap(),
+            [
+                Token::Amp,
+                Token::And,
+                Token::Pipe,
+                Token::Or,
+                Token::SatPlus,
+                Token::Caret,
+                Token::Shl,
+                Token::Le,
+                Token::Shr,
+                Token::Ge,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_tokenize_spans() {
+        let (tokens, spans) = Lexer::new("let xy = 42 # c\n>=").tokenize().unwrap();
+        assert_eq!(tokens.len(), 5);
+        assert_eq!(spans[1], Span::new(4, 6));
+        assert_eq!(spans[3], Span::new(9, 11));
+        assert_eq!(spans[4], Span::new(16, 18));
     }
 
     #[test]
@@ -268,14 +549,84 @@ fn test_if_then_else() {
             tokens.unwrap(),
             vec![
                 Token::If,
-                Token::Ident("x".to_string()),
+                Token::Ident("x".into()),
                 Token::Gt,
                 Token::Int(0),
                 Token::Then,
-                Token::Ident("x".to_string()),
+                Token::Ident("x".into()),
                 Token::Else,
                 Token::Int(0),
             ]
         );
     }
+
+    #[test]
+    fn test_non_ascii_identifiers() {
+        let (tokens, spans) = Lexer::new("let café = 1 café + naïve # é").tokenize().unwrap();
+        assert_eq!(tokens[1], Token::Ident("café".into()));
+        assert_eq!(tokens[4], tokens[1]);
+        assert_eq!(tokens[6], Token::Ident("naïve".into()));
+        assert_eq!(spans[1], Span::new(4, 9));
+        assert_eq!(spans[6], Span::new(22, 28));
+        assert_eq!(tokens.len(), 7);
+    }
+
+    #[test]
+    fn test_byte_strings() {
+        let (tokens, spans) = Lexer::new(r#"b"a\"\x00" == b"#).tokenize().unwrap();
+        assert_eq!(tokens[0], Token::Bytes(vec![b'a', b'"', 0]));
+        assert_eq!(spans[0], Span::new(0, 10));
+        assert_eq!(tokens[2], Token::Ident("b".into()));
+        for invalid in [r#"b"""#, r#"b"abc"#, r#"b"\x+1""#, "b\"é\""] {
+            let err = Lexer::new(invalid).tokenize().unwrap_err();
+            assert!(matches!(err, LexError::InvalidByteString(0)), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_sized_literals() {
+        let (tokens, _) = Lexer::new("200u8 5i64 4294967295u32").tokenize().unwrap();
+        assert_eq!(
+            tokens,
+            [
+                Token::Sized(200, Width::U8),
+                Token::Int(5),
+                Token::Sized(4294967295, Width::U32),
+            ]
+        );
+        let err = Lexer::new("256u8").tokenize().unwrap_err();
+        assert!(matches!(err, LexError::InvalidNumber(0)));
+        for (invalid, suffix) in [("3u9", "u9"), ("1x", "x")] {
+            let err = Lexer::new(invalid).tokenize().unwrap_err();
+            assert!(matches!(&err, LexError::InvalidSuffix(s, 1) if s == suffix), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_hex_binary_and_underscores() {
+        let (tokens, _) = Lexer::new("0xFF 0b1010 1_000_000 0xffff_u16 0x7FFF_FFFF_FFFF_FFFF")
+            .tokenize()
+            .unwrap();
+        assert_eq!(
+            tokens,
+            [
+                Token::Int(255),
+                Token::Int(10),
+                Token::Int(1_000_000),
+                Token::Sized(0xffff, Width::U16),
+                Token::Int(i64::MAX),
+            ]
+        );
+        for invalid in ["0x8000_0000_0000_0000", "0x100u8"] {
+            let err = Lexer::new(invalid).tokenize().unwrap_err();
+            assert!(matches!(err, LexError::InvalidNumber(0)), "{}", invalid);
+        }
+        for (invalid, prefix) in [("0x", "0x"), ("0xG", "0x"), ("0b_", "0b")] {
+            let err = Lexer::new(invalid).tokenize().unwrap_err();
+            assert!(matches!(err, LexError::MissingDigits(p, 0) if p == prefix), "{}", invalid);
+            assert_eq!(err.span(), Span::new(0, 2));
+        }
+        let err = Lexer::new("0b102").tokenize().unwrap_err();
+        assert!(matches!(&err, LexError::InvalidSuffix(s, 4) if s == "2"));
+    }
 }
@@ -5,30 +5,240 @@
 //!
 //! A toy transpiler demonstrating oblivious program transformation.
 
+pub mod analysis;
 pub mod ast;
+pub mod bench;
+pub mod build;
+pub mod check;
+#[cfg(feature = "serde")]
+pub mod checkpoint;
+pub mod codes;
+pub mod config;
+pub mod conformance;
+pub mod cst;
+pub mod debugger;
+pub mod desugar;
+pub mod diagnostic;
+pub mod differential;
+pub mod division;
+pub mod embed;
 pub mod emit;
+pub mod error;
+pub mod fix;
+pub mod format;
+pub mod formatter;
+pub mod frontend;
+pub mod fuzz;
+pub mod graph;
+pub mod hygiene;
+pub mod include;
+pub mod interp;
 pub mod ir;
+pub mod json;
+pub mod lean;
 pub mod lexer;
+pub mod limits;
+pub mod lint;
+pub mod lsp;
+pub mod node;
 pub mod parser;
+pub mod passes;
+pub mod policy;
+pub mod provenance;
+pub mod repl;
+pub mod report;
+pub mod run;
+pub mod schema;
+pub mod span;
+mod stack;
+pub mod stats;
+pub mod symbol;
+pub mod symbolic;
+pub mod testing;
+pub mod timings;
 pub mod transform;
+pub mod typeck;
+pub mod verify;
+pub mod wasm;
+pub mod watch;
 
 pub use ast::Expr;
+pub use check::check;
+pub use config::Config;
+pub use diagnostic::{Diagnostic, Severity};
+pub use error::TranspileError;
+pub use format::FORMAT_VERSION;
 pub use ir::ObliExpr;
 pub use lexer::Lexer;
 pub use parser::Parser;
+pub use span::Span;
+pub use symbol::Symbol;
+pub use timings::Timings;
 pub use transform::to_oblivious;
 
+use limits::Capped;
+use report::Declassification;
+use span::NodeSpans;
+use std::io;
+use transform::to_oblivious_checked;
+use typeck::typecheck_with_spans;
+use verify::verify_with_spans;
+
 /// Transpile MiniObli source code to oblivious Rust code.
-pub fn transpile(source: &str) -> Result<String, String> {
-    let lexer = Lexer::new(source);
-    let tokens: Result<Vec<_>, _> = lexer.collect();
-    let tokens = tokens.map_err(|e| e.to_string())?;
+pub fn transpile(source: &str) -> Result<String, TranspileError> {
+    transpile_with(source, &Config::default())
+}
+
+/// Transpile with the passes and emitter options of a project configuration.
+pub fn transpile_with(source: &str, config: &Config) -> Result<String, TranspileError> {
+    transpile_timed(source, config, &mut Timings::new())
+}
+
+/// Like [`transpile_with`], recording how long each stage takes.
+pub fn transpile_timed(
+    source: &str,
+    config: &Config,
+    timings: &mut Timings,
+) -> Result<String, TranspileError> {
+    compile(source, config, timings).map(|compiled| {
+        stack::drop_tree(compiled.ir);
+        compiled.code
+    })
+}
+
+/// Like [`transpile_timed`], writing into `out` as the code is generated
+/// instead of building it in memory first; see [`emit::write_rust`].
+pub fn transpile_to<W: io::Write>(
+    source: &str,
+    config: &Config,
+    timings: &mut Timings,
+    out: W,
+) -> Result<(), TranspileError> {
+    let (ir, ..) = lower(source, config, timings)?;
+    let mut out = Capped::new(out, config.limits.max_output_bytes);
+    let written = timings.time("emit", || emit::write_rust(&ir, &config.emit, &mut out));
+    stack::drop_tree(ir);
+    match out.exceeded() {
+        Some(e) => Err(e.into()),
+        None => Ok(written?),
+    }
+}
+
+/// Everything a successful compilation produced.
+pub(crate) struct Compiled {
+    /// The IR after passes, as emitted.
+    pub ir: ObliExpr,
+    /// Span of the whole program.
+    pub span: Span,
+    pub code: String,
+    /// Every `declassify(...)` of a secret, in post-order.
+    pub declassifications: Vec<Declassification>,
+}
+
+pub(crate) fn compile(
+    source: &str,
+    config: &Config,
+    timings: &mut Timings,
+) -> Result<Compiled, TranspileError> {
+    let (ir, span, declassifications) = lower(source, config, timings)?;
+    emit_compiled(ir, span, declassifications, config, timings)
+}
+
+/// The stages of [`compile`] before emission: the IR after passes, the
+/// span of the whole program and its `declassify(...)` points.
+fn lower(
+    source: &str,
+    config: &Config,
+    timings: &mut Timings,
+) -> Result<(ObliExpr, Span, Vec<Declassification>), TranspileError> {
+    let (tokens, token_spans) = timings.time("lex", || Lexer::new(source).tokenize())?;
+    let (program, ast_spans) = timings.time("parse", || {
+        Parser::with_spans(&tokens, &token_spans)
+            .with_limits(config.limits)
+            .parse_program_with_spans()
+    })?;
+    let ast = program.into_expr();
+    let lowered = lower_ast(&ast, &ast_spans, config, timings);
+    stack::drop_tree(ast);
+    lowered
+}
+
+/// The stages of [`lower`] after parsing.
+fn lower_ast(
+    ast: &Expr,
+    ast_spans: &NodeSpans,
+    config: &Config,
+    timings: &mut Timings,
+) -> Result<(ObliExpr, Span, Vec<Declassification>), TranspileError> {
+    timings
+        .time("typecheck", || typecheck_with_spans(ast, ast_spans))
+        .map_err(TranspileError::Type)?;
+    let (obli_ir, ir_spans, declassifications) = timings
+        .time("transform", || to_oblivious_checked(ast, ast_spans))
+        .map_err(TranspileError::Secrecy)?;
+    let errors = timings.time("verify", || verify_with_spans(&obli_ir, &ir_spans));
+    if !errors.is_empty() {
+        return Err(TranspileError::Transform(errors));
+    }
+    let errors = emit::check_emittable(&obli_ir, &ir_spans);
+    if !errors.is_empty() {
+        return Err(TranspileError::Emit(errors));
+    }
+    let span = ir_spans.iter().last().copied().unwrap_or_default();
+    let mut obli_ir = config
+        .division_by_zero
+        .apply(obli_ir, &ir_spans)
+        .map_err(TranspileError::Division)?;
+    config.limits.check_ir(&obli_ir, span)?;
+    for pass in config.pipeline().passes() {
+        obli_ir = timings.time(&format!("pass {}", pass.name()), || {
+            let _span = tracing::debug_span!("pass", name = pass.name()).entered();
+            pass.run(obli_ir)
+        });
+        config.limits.check_ir(&obli_ir, span)?;
+    }
+    Ok((hygiene::mangle_reserved(obli_ir), span, declassifications))
+}
+
+fn emit_compiled(
+    ir: ObliExpr,
+    span: Span,
+    declassifications: Vec<Declassification>,
+    config: &Config,
+    timings: &mut Timings,
+) -> Result<Compiled, TranspileError> {
+    let mut code = Capped::new(String::new(), config.limits.max_output_bytes);
+    let written = timings.time("emit", || emit::emit_rust_to(&ir, &config.emit, &mut code));
+    if let Some(e) = code.exceeded() {
+        stack::drop_tree(ir);
+        return Err(e.into());
+    }
+    written.expect("writing to a String cannot fail");
+    Ok(Compiled {
+        ir,
+        span,
+        code: code.into_inner(),
+        declassifications,
+    })
+}
+
+/// Transpile an AST built without the text syntax, such as one read by
+/// [`Expr::from_json`]. Errors carry empty spans.
+pub fn transpile_ast(ast: &Expr, config: &Config) -> Result<String, TranspileError> {
+    let mut timings = Timings::new();
+    let (ir, span, declassifications) = lower_ast(ast, &NodeSpans::new(), config, &mut timings)?;
+    Ok(emit_compiled(ir, span, declassifications, config, &mut timings)?.code)
+}
 
-    let mut parser = Parser::new(&tokens);
-    let ast = parser.parse().map_err(|e| e.to_string())?;
+/// Lex, parse, and transform MiniObli source code into oblivious IR.
+///
+/// Unlike [`transpile`], this does not typecheck: free variables are
+/// allowed, as inputs to be supplied when the IR is evaluated.
+pub fn transpile_to_ir(source: &str) -> Result<ObliExpr, TranspileError> {
+    let (tokens, spans) = Lexer::new(source).tokenize()?;
 
-    let obli_ir = to_oblivious(&ast);
-    let rust_code = emit::emit_rust(&obli_ir);
+    let mut parser = Parser::with_spans(&tokens, &spans);
+    let ast = parser.parse()?;
 
-    Ok(rust_code)
+    Ok(to_oblivious(&ast))
 }
@@ -0,0 +1,216 @@
+// SPDX-License-Identifier: MIT OR AGPL-3.0-or-later
+// SPDX-FileCopyrightText: 2024-2025 hyperpolymath
+
+//! Resource limits for untrusted input.
+//!
+//! [`Limits`] bounds how deeply a program nests, how many nodes its AST
+//! and IR may have, and how much code may be emitted. The
+//! [`Parser`](crate::parser::Parser) checks the first two as it builds
+//! nodes, the pipeline checks the IR after the transform and after every
+//! pass, and [`Capped`] cuts the emitted output off, so a hostile program
+//! fails with a [`LimitError`] instead of exhausting memory. Every limit
+//! is off by default.
+
+use crate::codes;
+use crate::ir::ObliExpr;
+use crate::span::Span;
+use serde::Deserialize;
+use std::{fmt, io};
+use thiserror::Error;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Limits {
+    /// Deepest nesting of expressions.
+    pub max_depth: Option<usize>,
+    /// Most nodes in the AST, and in the IR after each stage.
+    pub max_nodes: Option<usize>,
+    /// Most bytes of generated code.
+    pub max_output_bytes: Option<usize>,
+}
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum LimitError {
+    #[error("program nests deeper than the limit of {limit}")]
+    TooDeep { limit: usize, span: Span },
+    #[error("program has more than the limit of {limit} nodes")]
+    TooManyNodes { limit: usize, span: Span },
+    #[error("generated code is larger than the limit of {limit} bytes")]
+    OutputTooLarge { limit: usize },
+}
+
+impl LimitError {
+    /// Catalog code of this error.
+    pub fn code(&self) -> &'static str {
+        codes::LIMIT_EXCEEDED
+    }
+
+    /// Where the limit was crossed (empty for output and IR limits
+    /// without spans).
+    pub fn span(&self) -> Span {
+        match self {
+            LimitError::TooDeep { span, .. } | LimitError::TooManyNodes { span, .. } => *span,
+            LimitError::OutputTooLarge { .. } => Span::default(),
+        }
+    }
+}
+
+impl Limits {
+    /// No limits.
+    pub fn none() -> Self {
+        Self::default()
+    }
+
+    pub(crate) fn check_depth(&self, depth: usize, span: Span) -> Result<(), LimitError> {
+        match self.max_depth {
+            Some(limit) if depth > limit => Err(LimitError::TooDeep { limit, span }),
+            _ => Ok(()),
+        }
+    }
+
+    pub(crate) fn check_nodes(&self, nodes: usize, span: Span) -> Result<(), LimitError> {
+        match self.max_nodes {
+            Some(limit) if nodes > limit => Err(LimitError::TooManyNodes { limit, span }),
+            _ => Ok(()),
+        }
+    }
+
+    /// Check the depth and size of an IR tree, reporting violations at
+    /// `span`. The walk is iterative and stops at the first violation.
+    pub fn check_ir(&self, expr: &ObliExpr, span: Span) -> Result<(), LimitError> {
+        if self.max_depth.is_none() && self.max_nodes.is_none() {
+            return Ok(());
+        }
+        let mut nodes = 0;
+        let mut pending = vec![(expr, 1)];
+        while let Some((expr, depth)) = pending.pop() {
+            nodes += 1;
+            self.check_depth(depth, span)?;
+            self.check_nodes(nodes, span)?;
+            pending.extend(expr.children().into_iter().map(|child| (child, depth + 1)));
+        }
+        Ok(())
+    }
+}
+
+/// A writer that fails once more than a given number of bytes go through
+/// it, for capping emitted code; [`Capped::exceeded`] tells that failure
+/// apart from the inner writer's own.
+#[derive(Debug)]
+pub struct Capped<W> {
+    inner: W,
+    limit: Option<usize>,
+    written: usize,
+    exceeded: bool,
+}
+
+impl<W> Capped<W> {
+    /// Cap `inner` at `limit` bytes, or not at all.
+    pub fn new(inner: W, limit: Option<usize>) -> Self {
+        Self {
+            inner,
+            limit,
+            written: 0,
+            exceeded: false,
+        }
+    }
+
+    /// The error for the write that crossed the limit, if one did.
+    pub fn exceeded(&self) -> Option<LimitError> {
+        match self.limit {
+            Some(limit) if self.exceeded => Some(LimitError::OutputTooLarge { limit }),
+            _ => None,
+        }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+
+    /// Account for `len` more bytes, failing if they cross the limit.
+    fn take(&mut self, len: usize) -> bool {
+        self.written += len;
+        self.exceeded = self.limit.is_some_and(|limit| self.written > limit);
+        !self.exceeded
+    }
+}
+
+impl<W: fmt::Write> fmt::Write for Capped<W> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        if !self.take(s.len()) {
+            return Err(fmt::Error);
+        }
+        self.inner.write_str(s)
+    }
+}
+
+impl<W: io::Write> io::Write for Capped<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if !self.take(buf.len()) {
+            return Err(io::Error::new(io::ErrorKind::FileTooLarge, "output limit exceeded"));
+        }
+        self.inner.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::fixtures::{unrolled_loop, wide_mux};
+    use crate::parser::ParseError;
+    use crate::{transpile_with, Config, TranspileError};
+
+    fn limited(limits: Limits) -> Config {
+        Config {
+            limits,
+            ..Config::default()
+        }
+    }
+
+    fn limit_error(source: &str, limits: Limits) -> LimitError {
+        match transpile_with(source, &limited(limits)) {
+            Err(TranspileError::Parse(ParseError::Limit(e)) | TranspileError::Limit(e)) => e,
+            other => panic!("expected a limit error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_limits_fail_gracefully() {
+        let deep = Limits {
+            max_depth: Some(50),
+            ..Limits::none()
+        };
+        assert!(matches!(limit_error(&unrolled_loop(100), deep), LimitError::TooDeep { .. }));
+        // A flat chain of additions nests only in the tree
+        assert!(matches!(limit_error(&wide_mux(100), deep), LimitError::TooDeep { .. }));
+        let nodes = Limits {
+            max_nodes: Some(100),
+            ..Limits::none()
+        };
+        let err = limit_error(&wide_mux(100), nodes);
+        assert_eq!(err, LimitError::TooManyNodes { limit: 100, span: err.span() });
+        assert!(!err.span().is_empty());
+        let output = Limits {
+            max_output_bytes: Some(1000),
+            ..Limits::none()
+        };
+        let err = limit_error(&wide_mux(100), output);
+        assert_eq!(err, LimitError::OutputTooLarge { limit: 1000 });
+    }
+
+    #[test]
+    fn test_programs_within_limits_transpile() {
+        let source = wide_mux(10);
+        let limits = Limits {
+            max_depth: Some(20),
+            max_nodes: Some(200),
+            max_output_bytes: Some(100_000),
+        };
+        let code = transpile_with(&source, &limited(limits)).unwrap();
+        assert_eq!(code, crate::transpile(&source).unwrap());
+    }
+}
@@ -0,0 +1,711 @@
+// SPDX-License-Identifier: MIT OR AGPL-3.0-or-later
+// SPDX-FileCopyrightText: 2024-2025 hyperpolymath
+
+//! Configurable lints for oblivious-programming hazards.
+//!
+//! Lints are warnings about programs that are well-typed and transform
+//! correctly but probably do not mean what their author intended. Like
+//! rustc lints, each one can be set to `allow`, `warn` or `deny`
+//! independently; denied lints are reported as errors.
+//!
+//! `dead_declassify` sits next to `unused_secret`: a secret revealed by
+//! `declassify(...)` whose value is never used publicly leaks for
+//! nothing. `redundant_declassify` is its counterpart for a
+//! `declassify(...)` of a value that is public already.
+
+use crate::codes;
+use crate::diagnostic::{Diagnostic, Severity};
+use crate::ir::{ObliBinOp, ObliExpr};
+use crate::span::{NodeSpans, Span, SpanCursor};
+use crate::stack;
+use crate::symbol::Scope;
+use crate::typeck::Type;
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+use thiserror::Error;
+
+/// How a lint is reported.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Allow,
+    Warn,
+    Deny,
+}
+
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Level::Allow => write!(f, "allow"),
+            Level::Warn => write!(f, "warn"),
+            Level::Deny => write!(f, "deny"),
+        }
+    }
+}
+
+impl FromStr for Level {
+    type Err = LintError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "allow" => Ok(Level::Allow),
+            "warn" => Ok(Level::Warn),
+            "deny" => Ok(Level::Deny),
+            _ => Err(LintError::UnknownLevel(s.to_string())),
+        }
+    }
+}
+
+#[derive(Error, Debug, Clone, PartialEq)]
+pub enum LintError {
+    #[error("unknown lint `{0}`")]
+    UnknownLint(String),
+    #[error("unknown lint level `{0}` (expected allow, warn or deny)")]
+    UnknownLevel(String),
+}
+
+/// A lint and its default level.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Lint {
+    pub name: &'static str,
+    pub code: &'static str,
+    pub default: Level,
+    pub description: &'static str,
+}
+
+pub const UNUSED_VARIABLE: Lint = Lint {
+    name: "unused_variable",
+    code: codes::UNUSED_VARIABLE,
+    default: Level::Warn,
+    description: "a `let`-bound variable is never used (prefix it with `_` to silence)",
+};
+
+pub const UNUSED_SECRET: Lint = Lint {
+    name: "unused_secret",
+    code: codes::UNUSED_SECRET,
+    default: Level::Warn,
+    description: "a secret variable is used only by bindings that never reach the result",
+};
+
+pub const SHADOWED_SECRET: Lint = Lint {
+    name: "shadowed_secret",
+    code: codes::SHADOWED_SECRET,
+    default: Level::Warn,
+    description: "a public `let` shadows a secret variable of the same name",
+};
+
+pub const SHADOWED_VARIABLE: Lint = Lint {
+    name: "shadowed_variable",
+    code: codes::SHADOWED_VARIABLE,
+    default: Level::Allow,
+    description: "a `let` shadows a variable of the same name",
+};
+
+pub const SECRET_COMPARED_TO_CONSTANT: Lint = Lint {
+    name: "secret_compared_to_constant",
+    code: codes::SECRET_COMPARED_TO_CONSTANT,
+    default: Level::Allow,
+    description: "a secret is compared with a literal; revealing the result discloses \
+                  whether the secret has that value",
+};
+
+pub const EARLY_EXIT_COMPARISON: Lint = Lint {
+    name: "early_exit_comparison",
+    code: codes::EARLY_EXIT_COMPARISON,
+    default: Level::Warn,
+    description: "secrets are compared pair by pair in `if`s that stop at the first mismatch; \
+                  compare whole byte strings with `==` instead",
+};
+
+pub const DEAD_DECLASSIFY: Lint = Lint {
+    name: "dead_declassify",
+    code: codes::DEAD_DECLASSIFY,
+    default: Level::Warn,
+    description: "a declassified secret is never used publicly, so revealing it gains nothing",
+};
+
+pub const REDUNDANT_DECLASSIFY: Lint = Lint {
+    name: "redundant_declassify",
+    code: codes::REDUNDANT_DECLASSIFY,
+    default: Level::Warn,
+    description: "`declassify` is applied to a value that is already public",
+};
+
+pub const NARROWING_SECRET_CAST: Lint = Lint {
+    name: "narrowing_secret_cast",
+    code: codes::NARROWING_SECRET_CAST,
+    default: Level::Warn,
+    description: "a secret is cast to a type that cannot hold all of its values, \
+                  truncating the ones that do not fit",
+};
+
+/// Every lint, in the order they are documented.
+pub const LINTS: &[&Lint] = &[
+    &UNUSED_VARIABLE,
+    &UNUSED_SECRET,
+    &DEAD_DECLASSIFY,
+    &REDUNDANT_DECLASSIFY,
+    &SHADOWED_SECRET,
+    &SHADOWED_VARIABLE,
+    &SECRET_COMPARED_TO_CONSTANT,
+    &EARLY_EXIT_COMPARISON,
+    &NARROWING_SECRET_CAST,
+];
+
+/// Look up a lint by name.
+pub fn find(name: &str) -> Option<&'static Lint> {
+    LINTS.iter().copied().find(|lint| lint.name == name)
+}
+
+/// Per-lint level overrides.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LintConfig {
+    levels: HashMap<&'static str, Level>,
+}
+
+impl LintConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the level of the lint called `name`.
+    pub fn set(&mut self, name: &str, level: Level) -> Result<(), LintError> {
+        let lint = find(name).ok_or_else(|| LintError::UnknownLint(name.to_string()))?;
+        self.levels.insert(lint.name, level);
+        Ok(())
+    }
+
+    /// Effective level of `lint`.
+    pub fn level(&self, lint: &Lint) -> Level {
+        self.levels.get(lint.name).copied().unwrap_or(lint.default)
+    }
+}
+
+/// Run every lint over an IR tree, reporting at the spans recorded by
+/// [`to_oblivious_with_spans`](crate::transform::to_oblivious_with_spans).
+pub fn lint(expr: &ObliExpr, spans: &NodeSpans, config: &LintConfig) -> Vec<Diagnostic> {
+    let mut linter = Linter {
+        config,
+        spans: SpanCursor::new(Some(spans)),
+        scope: Scope::new(),
+        in_chain: false,
+        diagnostics: Vec::new(),
+    };
+    linter.visit(expr);
+    linter.diagnostics
+}
+
+struct Linter<'a> {
+    config: &'a LintConfig,
+    spans: SpanCursor<'a>,
+    /// Bound variables and whether they are secret
+    scope: Scope<bool>,
+    /// Inside a comparison chain already reported
+    in_chain: bool,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl<'a> Linter<'a> {
+    fn report(&mut self, lint: &Lint, span: Option<Span>, message: String, label: &str) {
+        let level = self.config.level(lint);
+        let severity = match level {
+            Level::Allow => return,
+            Level::Warn => Severity::Warning,
+            Level::Deny => Severity::Error,
+        };
+        let mut diagnostic = Diagnostic::new(severity, message)
+            .with_code(lint.code)
+            .with_note(format!("lint `{}` is set to {}", lint.name, level));
+        if let Some(span) = span {
+            diagnostic = diagnostic.with_span(span).with_label(span, label);
+        }
+        self.diagnostics.push(diagnostic);
+    }
+
+    fn visit(&mut self, expr: &ObliExpr) {
+        stack::guard(|| self.visit_node(expr))
+    }
+
+    fn visit_node(&mut self, expr: &ObliExpr) {
+        match expr {
+            ObliExpr::Let {
+                name, value, body, ..
+            } => {
+                self.visit(value);
+                let shadowed = self.scope.get(*name).copied();
+                let shadows_secret = !value.is_secret() && shadowed == Some(true);
+                self.scope.push(*name, value.is_secret());
+                self.visit(body);
+                self.scope.pop();
+                let span = self.spans.next_span();
+
+                if shadows_secret {
+                    self.report(
+                        &SHADOWED_SECRET,
+                        span,
+                        format!("public `{}` shadows a secret variable", name),
+                        "public binding",
+                    );
+                } else if shadowed.is_some() {
+                    self.report(
+                        &SHADOWED_VARIABLE,
+                        span,
+                        format!("`{}` shadows a variable of the same name", name),
+                        "shadowing binding",
+                    );
+                }
+                let silenced = name.starts_with('_');
+                if !silenced && !uses(body, name) {
+                    self.report(
+                        &UNUSED_VARIABLE,
+                        span,
+                        format!("unused variable `{}`", name),
+                        "bound here but never used",
+                    );
+                } else if !silenced && value.is_secret() && !influences(body, name) {
+                    self.report(
+                        &UNUSED_SECRET,
+                        span,
+                        format!("secret `{}` never influences the result", name),
+                        "only used by unused bindings",
+                    );
+                } else if !silenced && reveals_secret(value) && !used_publicly(body, name) {
+                    self.report(
+                        &DEAD_DECLASSIFY,
+                        span,
+                        format!("declassified `{}` is never used publicly", name),
+                        "revealed here, but only used by secret computations",
+                    );
+                }
+            }
+            ObliExpr::BinOp {
+                op, left, right, ..
+            } => {
+                self.visit(left);
+                self.visit(right);
+                let span = self.spans.next_span();
+
+                let comparison = matches!(
+                    op,
+                    ObliBinOp::CtEq
+                        | ObliBinOp::CtNe
+                        | ObliBinOp::CtLt
+                        | ObliBinOp::CtLe
+                        | ObliBinOp::CtGt
+                        | ObliBinOp::CtGe
+                );
+                let constant = match (&**left, &**right) {
+                    (other, ObliExpr::PubInt(n)) | (ObliExpr::PubInt(n), other)
+                        if other.is_secret() =>
+                    {
+                        Some(n.to_string())
+                    }
+                    (other, ObliExpr::PubBool(b)) | (ObliExpr::PubBool(b), other)
+                        if other.is_secret() =>
+                    {
+                        Some(b.to_string())
+                    }
+                    (other, sized @ ObliExpr::Sized { is_secret: false, .. })
+                    | (sized @ ObliExpr::Sized { is_secret: false, .. }, other)
+                        if other.is_secret() =>
+                    {
+                        Some(sized.to_string())
+                    }
+                    _ => None,
+                };
+                if let (true, Some(constant)) = (comparison, constant) {
+                    self.report(
+                        &SECRET_COMPARED_TO_CONSTANT,
+                        span,
+                        format!("secret value compared to constant `{}`", constant),
+                        "secret comparison",
+                    );
+                }
+            }
+            ObliExpr::CtSelect { .. } if !self.in_chain && comparison_chain(expr) >= 2 => {
+                self.in_chain = true;
+                for child in expr.children() {
+                    self.visit(child);
+                }
+                self.in_chain = false;
+                let span = self.spans.next_span();
+                self.report(
+                    &EARLY_EXIT_COMPARISON,
+                    span,
+                    format!(
+                        "{} secret comparisons chained in `if`s that stop at the first mismatch",
+                        comparison_chain(expr)
+                    ),
+                    "early-exit comparison",
+                );
+            }
+            ObliExpr::CtCast {
+                expr: inner,
+                from,
+                to,
+                is_secret,
+            } => {
+                self.visit(inner);
+                let span = self.spans.next_span();
+                if *is_secret && !from.widens_to(*to) {
+                    self.report(
+                        &NARROWING_SECRET_CAST,
+                        span,
+                        format!(
+                            "secret `{}` may not fit in `{}`",
+                            Type::from(*from),
+                            Type::from(*to)
+                        ),
+                        "narrowing cast",
+                    );
+                }
+            }
+            ObliExpr::Declassify(inner) => {
+                self.visit(inner);
+                let span = self.spans.next_span();
+                if !inner.is_secret() {
+                    self.report(
+                        &REDUNDANT_DECLASSIFY,
+                        span,
+                        "`declassify` of a public value".to_string(),
+                        "already public",
+                    );
+                }
+            }
+            ObliExpr::While {
+                cond,
+                acc,
+                init,
+                body,
+                is_secret,
+            } => {
+                self.scope.push(*acc, *is_secret);
+                self.visit(cond);
+                self.scope.pop();
+                self.visit(init);
+                self.scope.push(*acc, *is_secret);
+                self.visit(body);
+                self.scope.pop();
+                self.spans.next_span();
+            }
+            ObliExpr::CtSwap {
+                cond,
+                left,
+                right,
+                names,
+                body,
+                is_secret,
+            } => {
+                self.visit(cond);
+                self.visit(left);
+                self.visit(right);
+                names.iter().for_each(|name| self.scope.push(*name, *is_secret));
+                self.visit(body);
+                names.iter().for_each(|_| self.scope.pop());
+                self.spans.next_span();
+            }
+            _ => {
+                for child in expr.children() {
+                    self.visit(child);
+                }
+                self.spans.next_span();
+            }
+        }
+    }
+}
+
+/// How many comparisons a chain of the shape `if a == b then REST else
+/// false` (or `if a != b then false else REST`) makes, counting a final
+/// `==` in `REST`; 0 for anything else.
+fn comparison_chain(expr: &ObliExpr) -> usize {
+    let is_false =
+        |e: &ObliExpr| matches!(e, ObliExpr::PubBool(false) | ObliExpr::SecretBool(false));
+    let mut count = 0;
+    let mut expr = expr;
+    loop {
+        expr = match expr {
+            ObliExpr::CtSelect {
+                cond,
+                then_val,
+                else_val,
+            } => match &**cond {
+                ObliExpr::BinOp { op: ObliBinOp::CtEq, .. } if is_false(else_val) => then_val,
+                ObliExpr::BinOp { op: ObliBinOp::CtNe, .. } if is_false(then_val) => else_val,
+                _ => return count,
+            },
+            ObliExpr::BinOp { op: ObliBinOp::CtEq, .. } => return count + 1,
+            _ => return count,
+        };
+        count += 1;
+    }
+}
+
+/// Whether `name` occurs free in `expr`.
+fn uses(expr: &ObliExpr, name: &str) -> bool {
+    stack::guard(|| uses_node(expr, name))
+}
+
+/// Whether `name` occurs free in `expr` where it can affect its value:
+/// not in the value of a `let` whose variable does not.
+fn influences(expr: &ObliExpr, name: &str) -> bool {
+    stack::guard(|| match expr {
+        ObliExpr::Var { name: n, .. } => n == name,
+        ObliExpr::Let {
+            name: bound,
+            value,
+            body,
+            ..
+        } => {
+            (influences(value, name) && influences(body, bound))
+                || (bound != name && influences(body, name))
+        }
+        ObliExpr::While {
+            cond,
+            acc,
+            init,
+            body,
+            ..
+        } => {
+            influences(init, name)
+                || (acc != name && (influences(cond, name) || influences(body, name)))
+        }
+        ObliExpr::CtSwap { names, body, .. } => {
+            let operands = &expr.children()[..3];
+            (operands.iter().any(|e| influences(e, name))
+                && names.iter().any(|bound| influences(body, bound)))
+                || (!names.iter().any(|bound| bound == name) && influences(body, name))
+        }
+        _ => expr.children().into_iter().any(|child| influences(child, name)),
+    })
+}
+
+/// Whether `value` is a `declassify(...)` of a secret.
+fn reveals_secret(value: &ObliExpr) -> bool {
+    matches!(value, ObliExpr::Declassify(inner) if inner.is_secret())
+}
+
+/// Whether `name` occurs free in `expr` where its value reaches the
+/// result through public operations only, or decides a public branch,
+/// loop or assertion.
+fn used_publicly(expr: &ObliExpr, name: &str) -> bool {
+    stack::guard(|| match expr {
+        ObliExpr::Var { name: n, .. } => n == name,
+        ObliExpr::Let {
+            name: bound,
+            value,
+            body,
+            ..
+        } => {
+            (used_publicly(value, name) && used_publicly(body, bound))
+                || (bound != name && used_publicly(body, name))
+        }
+        ObliExpr::While {
+            cond,
+            acc,
+            init,
+            body,
+            ..
+        } => {
+            used_publicly(init, name)
+                || (acc != name && (used_publicly(cond, name) || used_publicly(body, name)))
+        }
+        ObliExpr::CtSwap { names, body, .. } => {
+            !names.iter().any(|bound| bound == name) && used_publicly(body, name)
+        }
+        // The condition of a public `if` or assertion is used publicly
+        // whatever the result, and each output is revealed by its own label
+        ObliExpr::PubIf { .. }
+        | ObliExpr::Assert { .. }
+        | ObliExpr::Outputs(_)
+        | ObliExpr::Declassify(_) => {
+            expr.children().into_iter().any(|child| used_publicly(child, name))
+        }
+        _ => {
+            !expr.is_secret()
+                && expr.children().into_iter().any(|child| used_publicly(child, name))
+        }
+    })
+}
+
+fn uses_node(expr: &ObliExpr, name: &str) -> bool {
+    match expr {
+        ObliExpr::Var { name: n, .. } => n == name,
+        ObliExpr::Let {
+            name: bound,
+            value,
+            body,
+            ..
+        } => uses(value, name) || (bound != name && uses(body, name)),
+        ObliExpr::While {
+            cond,
+            acc,
+            init,
+            body,
+            ..
+        } => uses(init, name) || (acc != name && (uses(cond, name) || uses(body, name))),
+        ObliExpr::CtSwap { names, body, .. } => {
+            expr.children()[..3].iter().any(|e| uses(e, name))
+                || (!names.iter().any(|bound| bound == name) && uses(body, name))
+        }
+        _ => expr.children().into_iter().any(|child| uses(child, name)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+    use crate::transform::to_oblivious_with_spans;
+
+    fn run(source: &str, config: &LintConfig) -> Vec<Diagnostic> {
+        let (tokens, spans) = Lexer::new(source).tokenize().unwrap();
+        let (ast, ast_spans) = Parser::with_spans(&tokens, &spans)
+            .parse_with_spans()
+            .unwrap();
+        let (ir, ir_spans) = to_oblivious_with_spans(&ast, &ast_spans);
+        lint(&ir, &ir_spans, config)
+    }
+
+    #[test]
+    fn test_unused_and_shadowed() {
+        let diagnostics = run("let k = secret(1) let k = 2 let _x = 3 k", &LintConfig::new());
+        let messages: Vec<_> = diagnostics.iter().map(|d| d.message.as_str()).collect();
+        assert_eq!(
+            messages,
+            vec!["public `k` shadows a secret variable", "unused variable `k`"]
+        );
+        assert!(diagnostics.iter().all(|d| d.severity == Severity::Warning));
+    }
+
+    #[test]
+    fn test_unused_secret() {
+        let source = "let pin = secret(1234) let check = pin == 1111 let ok = secret(true) \
+                      let n = pin + 1 if ok then n else 0";
+        let messages: Vec<_> = run(source, &LintConfig::new())
+            .into_iter()
+            .map(|d| d.message)
+            .collect();
+        assert_eq!(messages, ["unused variable `check`"]);
+
+        let source = "let pin = secret(1234) let check = pin == 1111 let ok = secret(true) ok";
+        let messages: Vec<_> = run(source, &LintConfig::new())
+            .into_iter()
+            .map(|d| d.message)
+            .collect();
+        assert_eq!(
+            messages,
+            ["unused variable `check`", "secret `pin` never influences the result"]
+        );
+    }
+
+    #[test]
+    fn test_dead_declassify() {
+        let messages = |source| -> Vec<_> {
+            run(source, &LintConfig::new()).into_iter().map(|d| d.message).collect()
+        };
+        let source = "let k = secret(5) let big = declassify(k > 3) if big then 1 else 2";
+        assert!(messages(source).is_empty());
+        let source = "let k = secret(5) let shown = declassify(k) outputs(a = shown + 1, b = k)";
+        assert!(messages(source).is_empty());
+        // Only mixed back into secrets, or only into a binding never used
+        let source = "let k = secret(5) let shown = declassify(k) shown * k";
+        assert_eq!(messages(source), ["declassified `shown` is never used publicly"]);
+        let source = "let k = secret(5) let shown = declassify(k) let j = shown + 1 \
+                      let ok = secret(true) if ok then 1 else 0";
+        assert_eq!(
+            messages(source),
+            [
+                "unused variable `j`",
+                "declassified `shown` is never used publicly",
+                "secret `k` never influences the result"
+            ]
+        );
+        // Declassifying a public value reveals nothing, which has a lint of its own
+        let source = "let k = secret(5) let n = declassify(2) n * k";
+        assert_eq!(messages(source), ["`declassify` of a public value"]);
+    }
+
+    #[test]
+    fn test_redundant_declassify() {
+        let messages = |source| -> Vec<_> {
+            run(source, &LintConfig::new()).into_iter().map(|d| d.message).collect()
+        };
+        let diagnostics = run("declassify(5)", &LintConfig::new());
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].code, Some(codes::REDUNDANT_DECLASSIFY));
+        assert_eq!(diagnostics[0].span, Some(Span::new(0, 13)));
+        let source = "let k = secret(5) let n = 3 if declassify(k > n) then declassify(n) else 0";
+        assert_eq!(messages(source), ["`declassify` of a public value"]);
+        assert!(messages("let k = secret(5) declassify(k)").is_empty());
+    }
+
+    #[test]
+    fn test_shadowed_variable() {
+        let source = "let k = secret(1) let k = k + 1 let j = k let k = 2 k + j";
+        assert_eq!(run(source, &LintConfig::new()).len(), 1);
+        let mut config = LintConfig::new();
+        config.set("shadowed_variable", Level::Warn).unwrap();
+        let messages: Vec<_> = run(source, &config).into_iter().map(|d| d.message).collect();
+        assert_eq!(
+            messages,
+            [
+                "public `k` shadows a secret variable",
+                "`k` shadows a variable of the same name",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_levels() {
+        let source = "let k = secret(1) k == 7";
+        assert_eq!(run(source, &LintConfig::new()), vec![]);
+
+        let mut config = LintConfig::new();
+        config.set("secret_compared_to_constant", Level::Deny).unwrap();
+        let diagnostics = run(source, &config);
+        assert_eq!(diagnostics.len(), 1);
+        assert!(diagnostics[0].is_error());
+        assert_eq!(
+            config.set("no_such_lint", Level::Allow),
+            Err(LintError::UnknownLint("no_such_lint".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_early_exit_comparison() {
+        let source = "let k = secret(b\"pin\") \
+                      if k[0] == 112 then (if k[1] == 105 then k[2] == 110 else false) else false";
+        let diagnostics = run(source, &LintConfig::new());
+        let messages: Vec<_> = diagnostics.iter().map(|d| d.message.as_str()).collect();
+        assert_eq!(
+            messages,
+            ["3 secret comparisons chained in `if`s that stop at the first mismatch"]
+        );
+        let span = diagnostics[0].span.unwrap();
+        assert!(source[span.start..span.end].starts_with("if k[0] == 112"));
+        assert_eq!(run("let k = secret(b\"pin\") k == b\"pin\"", &LintConfig::new()), vec![]);
+    }
+
+    #[test]
+    fn test_narrowing_secret_cast() {
+        let source = "let k = secret(1000) let b = 7u8 (k as u8 as int) + (b as u32 as int)";
+        let diagnostics = run(source, &LintConfig::new());
+        let messages: Vec<_> = diagnostics.iter().map(|d| d.message.as_str()).collect();
+        assert_eq!(messages, ["secret `int` may not fit in `u8`"]);
+        let span = diagnostics[0].span.unwrap();
+        assert_eq!(&source[span.start..span.end], "k as u8");
+        let source = "let k = secret(5u8) let n = secret(-1i32) (k as int) + (n as u32 as int)";
+        let messages: Vec<_> = run(source, &LintConfig::new())
+            .into_iter()
+            .map(|d| d.message)
+            .collect();
+        assert_eq!(messages, ["secret `i32` may not fit in `u32`"]);
+    }
+
+    #[test]
+    fn test_level_from_str() {
+        assert_eq!("warn".parse(), Ok(Level::Warn));
+        assert!("loud".parse::<Level>().is_err());
+    }
+}
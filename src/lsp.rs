@@ -0,0 +1,359 @@
+// SPDX-License-Identifier: MIT OR AGPL-3.0-or-later
+// SPDX-FileCopyrightText: 2024-2025 hyperpolymath
+
+//! Editor queries behind the `obli-lsp` language server.
+//!
+//! A [`Document`] analyses one source file and answers the questions an
+//! editor asks about it: diagnostics, hover (type and secrecy of the
+//! expression under the cursor), and go-to-definition for let-bound
+//! variables. Everything here works on byte offsets and [`Span`]s; the
+//! server binary converts to and from protocol positions.
+//!
+//! A document read from a file has its `include`s expanded and is analysed
+//! as the whole [`Unit`]; offsets and spans stay those of the file itself,
+//! and what lies in an included file is reported at its `include` line.
+
+use crate::ast::Expr;
+use crate::check::check;
+use crate::cst::{Cst, SyntaxKind};
+use crate::diagnostic::Diagnostic;
+use crate::include::{FileSystem, Loader, Unit};
+use crate::ir::ObliExpr;
+use crate::lexer::Token;
+use crate::span::{NodeSpans, Span};
+use crate::transform::to_oblivious_with_spans;
+use crate::typeck::{node_types, typecheck_with_spans, Type};
+use std::fmt;
+use std::path::Path;
+
+/// Result of a hover query.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Hover {
+    /// Span of the expression being described.
+    pub span: Span,
+    /// Variable name, when hovering a variable or its binding.
+    pub name: Option<String>,
+    /// Inferred type (`None` if the expression is ill-typed).
+    pub ty: Option<Type>,
+    pub secret: bool,
+}
+
+impl fmt::Display for Hover {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(name) = &self.name {
+            write!(f, "{}: ", name)?;
+        }
+        let label = if self.secret { "secret" } else { "public" };
+        match &self.ty {
+            Some(ty) => write!(f, "{} {}", label, ty),
+            None => write!(f, "{} <unknown>", label),
+        }
+    }
+}
+
+/// An analysed source file.
+#[derive(Debug, Clone)]
+pub struct Document {
+    diagnostics: Vec<Diagnostic>,
+    parsed: Option<Parsed>,
+    /// The unit analysed, when the file includes others.
+    unit: Option<Unit>,
+}
+
+#[derive(Debug, Clone)]
+struct Parsed {
+    cst: Cst,
+    types: Vec<Option<Type>>,
+    /// The IR and its spans, for a well-typed program only: the type
+    /// checker bounds how far loops and recursion unroll, so an ill-typed
+    /// one might not fit in memory.
+    ir: Option<(ObliExpr, NodeSpans)>,
+}
+
+impl Document {
+    pub fn new(source: &str) -> Self {
+        let parsed = Cst::parse(source).ok().map(|cst| {
+            let types = node_types(cst.ast());
+            let ir = typecheck_with_spans(cst.ast(), cst.node_spans())
+                .is_ok()
+                .then(|| to_oblivious_with_spans(cst.ast(), cst.node_spans()));
+            Parsed { cst, types, ir }
+        });
+        Self {
+            diagnostics: check(source),
+            parsed,
+            unit: None,
+        }
+    }
+
+    /// Analyse `source`, the contents of the file `path`, with its includes
+    /// expanded.
+    pub fn from_file(path: impl AsRef<Path>, source: &str) -> Self {
+        Self::from_file_with(path, source, &FileSystem)
+    }
+
+    /// Like [`Document::from_file`], with included files from `loader`.
+    pub fn from_file_with(path: impl AsRef<Path>, source: &str, loader: &dyn Loader) -> Self {
+        match Unit::from_source_with(path, source, loader) {
+            Ok(unit) if unit.has_includes() => {
+                let mut document = Self::new(&unit.source);
+                document.diagnostics =
+                    document.diagnostics.iter().map(|d| unit.relocate_to_root(d)).collect();
+                document.unit = Some(unit);
+                document
+            }
+            Ok(_) => Self::new(source),
+            Err(e) => Self {
+                diagnostics: vec![e.into()],
+                parsed: None,
+                unit: None,
+            },
+        }
+    }
+
+    /// Diagnostics for the whole file (parse, type and secrecy errors).
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        &self.diagnostics
+    }
+
+    /// Describe the innermost expression at `offset`.
+    pub fn hover(&self, offset: usize) -> Option<Hover> {
+        let mut hover = self.hover_expanded(self.expanded_offset(offset)?)?;
+        hover.span = self.root_span(hover.span);
+        Some(hover)
+    }
+
+    /// Span of the `let` name that binds the variable at `offset`.
+    pub fn definition(&self, offset: usize) -> Option<Span> {
+        let span = self.definition_expanded(self.expanded_offset(offset)?)?;
+        Some(self.root_span(span))
+    }
+
+    /// Offset in the analysed text of `offset` in the file.
+    fn expanded_offset(&self, offset: usize) -> Option<usize> {
+        match &self.unit {
+            Some(unit) => unit.expanded_offset(offset),
+            None => Some(offset),
+        }
+    }
+
+    /// Span in the file of `span` of the analysed text.
+    fn root_span(&self, span: Span) -> Span {
+        match &self.unit {
+            Some(unit) => unit.locate_in_root(span),
+            None => span,
+        }
+    }
+
+    fn hover_expanded(&self, offset: usize) -> Option<Hover> {
+        let parsed = self.parsed.as_ref()?;
+        let nodes = post_order(parsed.cst.ast());
+        let index = innermost(parsed.cst.node_spans(), offset)?;
+        let ir_node = parsed.ir.as_ref().and_then(|(ir, ir_spans)| {
+            innermost(ir_spans, offset).map(|i| post_order_ir(ir)[i])
+        });
+
+        match nodes[index] {
+            Expr::Let { name, value, .. } => {
+                // Only the bound name itself is described; the rest of the
+                // `let` is covered by its value and body.
+                let span = parsed.name_span(parsed.cst.node_spans().get(index)?)?;
+                if !span.contains(offset) {
+                    return None;
+                }
+                let value_index = nodes.iter().position(|n| std::ptr::eq(*n, &**value))?;
+                let secret = match ir_node {
+                    Some(ObliExpr::Let { value, .. }) => value.is_secret(),
+                    _ => false,
+                };
+                Some(Hover {
+                    span,
+                    name: Some(name.to_string()),
+                    ty: parsed.types[value_index].clone(),
+                    secret,
+                })
+            }
+            node => Some(Hover {
+                span: parsed.cst.node_spans().get(index)?,
+                name: match node {
+                    Expr::Var(name) => Some(name.to_string()),
+                    _ => None,
+                },
+                ty: parsed.types[index].clone(),
+                secret: ir_node.is_some_and(|n| n.is_secret()),
+            }),
+        }
+    }
+
+    fn definition_expanded(&self, offset: usize) -> Option<Span> {
+        let parsed = self.parsed.as_ref()?;
+        let ast = parsed.cst.ast();
+        let nodes = post_order(ast);
+        let index = innermost(parsed.cst.node_spans(), offset)?;
+        let target = nodes[index];
+        let binding = match target {
+            Expr::Var(_) => binding_of(ast, target, &mut Vec::new())??,
+            Expr::Let { .. } => target,
+            _ => return None,
+        };
+        let let_span = parsed.cst.span_map().get(&(binding as *const Expr)).copied()?;
+        let name_span = parsed.name_span(let_span)?;
+        match target {
+            Expr::Let { .. } if !name_span.contains(offset) => None,
+            _ => Some(name_span),
+        }
+    }
+}
+
+impl Parsed {
+    /// Span of the name in the `let` spanning `let_span`.
+    fn name_span(&self, let_span: Span) -> Option<Span> {
+        self.cst
+            .tokens()
+            .iter()
+            .filter(|t| t.span.start >= let_span.start)
+            .find(|t| matches!(t.kind, SyntaxKind::Token(Token::Ident(_))))
+            .map(|t| t.span)
+    }
+}
+
+/// Post-order index of the innermost node containing `offset`.
+///
+/// Descendants precede their ancestors in post-order and siblings are
+/// disjoint, so the first match is the innermost one.
+fn innermost(spans: &NodeSpans, offset: usize) -> Option<usize> {
+    spans.iter().position(|span| span.contains(offset))
+}
+
+fn post_order(expr: &Expr) -> Vec<&Expr> {
+    fn walk<'a>(expr: &'a Expr, out: &mut Vec<&'a Expr>) {
+        for child in expr.children() {
+            walk(child, out);
+        }
+        out.push(expr);
+    }
+    let mut out = Vec::new();
+    walk(expr, &mut out);
+    out
+}
+
+fn post_order_ir(expr: &ObliExpr) -> Vec<&ObliExpr> {
+    fn walk<'a>(expr: &'a ObliExpr, out: &mut Vec<&'a ObliExpr>) {
+        for child in expr.children() {
+            walk(child, out);
+        }
+        out.push(expr);
+    }
+    let mut out = Vec::new();
+    walk(expr, &mut out);
+    out
+}
+
+/// Find the `let` binding the variable node `target`.
+///
+/// Returns `None` if `target` is not inside `expr`, and `Some(None)` if it
+/// is a free variable or bound by a tuple `let`, whose names have no span.
+fn binding_of<'a>(
+    expr: &'a Expr,
+    target: &Expr,
+    scope: &mut Vec<&'a Expr>,
+) -> Option<Option<&'a Expr>> {
+    if std::ptr::eq(expr, target) {
+        let Expr::Var(name) = expr else {
+            return Some(None);
+        };
+        let binding = scope.iter().rev().find(|e| match e {
+            Expr::Let { name: n, .. } => n == name,
+            Expr::LetTuple { names, .. } => names.contains(name),
+            _ => false,
+        });
+        return Some(binding.copied().filter(|e| matches!(e, Expr::Let { .. })));
+    }
+    match expr {
+        Expr::Let { value, body, .. } | Expr::LetTuple { value, body, .. } => {
+            if let Some(found) = binding_of(value, target, scope) {
+                return Some(found);
+            }
+            scope.push(expr);
+            let found = binding_of(body, target, scope);
+            scope.pop();
+            found
+        }
+        _ => expr
+            .children()
+            .into_iter()
+            .find_map(|child| binding_of(child, target, scope)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::include::MemoryFiles;
+
+    const SRC: &str = "let k = secret(7)\nlet m = 3\nif k > m then k + 1 else m";
+
+    fn offset(needle: &str, nth: usize) -> usize {
+        SRC.match_indices(needle).nth(nth).unwrap().0
+    }
+
+    #[test]
+    fn test_hover_variable_and_expression() {
+        let doc = Document::new(SRC);
+        let hover = doc.hover(offset("k", 1)).unwrap();
+        assert_eq!(hover.to_string(), "k: secret int");
+        assert_eq!(doc.hover(offset("m", 1)).unwrap().to_string(), "m: public int");
+        assert_eq!(doc.hover(offset(">", 0)).unwrap().to_string(), "secret bool");
+        assert_eq!(doc.hover(offset("k", 0)).unwrap().to_string(), "k: secret int");
+    }
+
+    #[test]
+    fn test_definition() {
+        let doc = Document::new(SRC);
+        let def = Span::new(offset("k", 0), offset("k", 0) + 1);
+        assert_eq!(doc.definition(offset("k", 2)), Some(def));
+        assert_eq!(doc.definition(offset("m", 2)).map(|s| s.start), Some(offset("m", 0)));
+        assert_eq!(doc.definition(offset("7", 0)), None);
+    }
+
+    #[test]
+    fn test_shadowing_and_free_variables() {
+        let doc = Document::new("let x = 1 let x = x + 1 x + y");
+        assert_eq!(doc.definition(18), Some(Span::new(4, 5)));
+        assert_eq!(doc.definition(24), Some(Span::new(14, 15)));
+        assert_eq!(doc.definition(28), None);
+        assert!(doc.diagnostics()[0].is_error());
+    }
+
+    #[test]
+    fn test_includes_expanded() {
+        let mut files = MemoryFiles::new();
+        files.insert("lib/limit.mobli", "let limit = 100\n");
+        files.insert("lib/bad.mobli", "let limit = 1 + true\n");
+        let source = "include \"lib/bad.mobli\"\nlimit";
+        let doc = Document::from_file_with("main.mobli", source, &files);
+        let diagnostic = &doc.diagnostics()[0];
+        assert_eq!(diagnostic.span, Some(Span::new(0, 23)));
+        assert!(diagnostic.message.starts_with("lib/bad.mobli: "), "{}", diagnostic.message);
+
+        let source = "include \"lib/limit.mobli\"\nlet k = secret(7)\nk + limit";
+        let doc = Document::from_file_with("main.mobli", source, &files);
+        assert!(doc.diagnostics().is_empty());
+        let k = source.rfind('k').unwrap();
+        assert_eq!(doc.hover(k).unwrap().to_string(), "k: secret int");
+        assert_eq!(doc.definition(k), Some(Span::new(30, 31)));
+        // `limit` is bound in the included file, so by its `include` line
+        let limit = source.rfind("limit").unwrap();
+        assert_eq!(doc.definition(limit), Some(Span::new(0, 25)));
+        assert_eq!(doc.hover(3), None);
+    }
+
+    #[test]
+    fn test_runaway_loops_not_lowered() {
+        let doc = Document::new("for i in 0..1000000000 with a = 0 do a + i");
+        assert_eq!(doc.diagnostics()[0].code, Some(crate::codes::TOO_MANY_ITERATIONS));
+        assert_eq!(doc.hover(0).unwrap().to_string(), "public int");
+        let source = "#[max_depth(40)] fn f(n) = if n == 0 then 0 else f(n - 1) + f(n - 1) f(3)";
+        assert!(Document::new(source).diagnostics()[0].is_error());
+    }
+}
@@ -0,0 +1,802 @@
+// SPDX-License-Identifier: MIT OR AGPL-3.0-or-later
+// SPDX-FileCopyrightText: 2024-2025 hyperpolymath
+
+//! AST nodes generic over their children.
+//!
+//! A [`Node`] is the top of an [`Expr`] with children of any type, and a
+//! [`Build`] makes an expression from one. The
+//! [`Parser`](crate::parser::Parser) builds every node through its
+//! builder, by default [`Boxed`], and the desugarer rebuilds the nodes it
+//! does not rewrite with [`Node::of`] and [`Node::map`].
+//!
+//! [`Boxed`] is the only builder: the parser and the transform still
+//! allocate one `Box` per node. Parsing into an index arena would be a
+//! [`Build`] whose expressions are indices, but the transform, the
+//! desugarer and the type checker all walk owned [`Expr`]s, so the AST and
+//! IR are not arena-allocated.
+
+use crate::ast::{Annotation, BinOp, Expr, UnaryOp};
+use crate::symbol::Symbol;
+use crate::typeck::{Scalar, Width};
+
+/// An AST node whose children are `E`s.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Node<E> {
+    Int(i64),
+    Sized(i64, Width),
+    Bool(bool),
+    Var(Symbol),
+    Input {
+        name: Symbol,
+        secret: bool,
+        ty: Scalar,
+    },
+    Secret(E),
+    Declassify(E),
+    BinOp {
+        op: BinOp,
+        left: E,
+        right: E,
+    },
+    UnaryOp {
+        op: UnaryOp,
+        expr: E,
+    },
+    Cast {
+        expr: E,
+        ty: Scalar,
+    },
+    If {
+        cond: E,
+        then_branch: E,
+        else_branch: E,
+    },
+    Let {
+        name: Symbol,
+        ty: Option<Annotation>,
+        mutable: bool,
+        value: E,
+        body: E,
+    },
+    Const {
+        name: Symbol,
+        value: E,
+        body: E,
+    },
+    Enum {
+        name: Symbol,
+        variants: Vec<Symbol>,
+        body: E,
+    },
+    Assert {
+        cond: E,
+        body: E,
+    },
+    Assign {
+        name: Symbol,
+        cond: Option<E>,
+        value: E,
+        body: E,
+    },
+    Array(Vec<E>),
+    Tuple(Vec<E>),
+    LetTuple {
+        names: Vec<Symbol>,
+        value: E,
+        body: E,
+    },
+    Bytes(Vec<u8>),
+    Sort(E),
+    SwapIf {
+        cond: E,
+        left: E,
+        right: E,
+    },
+    Clamp {
+        value: E,
+        lo: E,
+        hi: E,
+    },
+    InRange {
+        value: E,
+        lo: E,
+        hi: E,
+    },
+    Pow {
+        base: E,
+        exp: E,
+    },
+    Map {
+        array: E,
+        f: E,
+    },
+    Fold {
+        array: E,
+        init: E,
+        f: E,
+    },
+    Some(E),
+    None,
+    UnwrapOr {
+        option: E,
+        default: E,
+    },
+    Index {
+        array: E,
+        index: E,
+    },
+    Lookup {
+        key: E,
+        entries: Vec<(E, E)>,
+        default: E,
+    },
+    Match {
+        scrutinee: E,
+        arms: Vec<(E, E)>,
+        default: Option<E>,
+    },
+    Outputs(Vec<(Symbol, E)>),
+    Fn {
+        name: Symbol,
+        max_depth: Option<u32>,
+        params: Vec<Symbol>,
+        body: E,
+        rest: E,
+    },
+    Call {
+        name: Symbol,
+        args: Vec<E>,
+    },
+    Lambda {
+        params: Vec<Symbol>,
+        body: E,
+    },
+    For {
+        var: Symbol,
+        start: E,
+        end: E,
+        acc: Symbol,
+        init: E,
+        body: E,
+    },
+    While {
+        cond: E,
+        acc: Symbol,
+        init: E,
+        body: E,
+    },
+}
+
+impl<E> Node<E> {
+    /// The same node with `f` applied to each child, in order.
+    pub fn map<F>(self, mut f: impl FnMut(E) -> F) -> Node<F> {
+        match self {
+            Node::Int(n) => Node::Int(n),
+            Node::Sized(n, width) => Node::Sized(n, width),
+            Node::Bool(b) => Node::Bool(b),
+            Node::Var(name) => Node::Var(name),
+            Node::Input { name, secret, ty } => Node::Input { name, secret, ty },
+            Node::Secret(inner) => Node::Secret(f(inner)),
+            Node::Declassify(inner) => Node::Declassify(f(inner)),
+            Node::Assert { cond, body } => {
+                let cond = f(cond);
+                Node::Assert {
+                    cond,
+                    body: f(body),
+                }
+            }
+            Node::BinOp { op, left, right } => {
+                let left = f(left);
+                Node::BinOp {
+                    op,
+                    left,
+                    right: f(right),
+                }
+            }
+            Node::UnaryOp { op, expr } => Node::UnaryOp { op, expr: f(expr) },
+            Node::Cast { expr, ty } => Node::Cast { expr: f(expr), ty },
+            Node::If {
+                cond,
+                then_branch,
+                else_branch,
+            } => {
+                let cond = f(cond);
+                let then_branch = f(then_branch);
+                Node::If {
+                    cond,
+                    then_branch,
+                    else_branch: f(else_branch),
+                }
+            }
+            Node::Let {
+                name,
+                ty,
+                mutable,
+                value,
+                body,
+            } => {
+                let value = f(value);
+                Node::Let {
+                    name,
+                    ty,
+                    mutable,
+                    value,
+                    body: f(body),
+                }
+            }
+            Node::Assign {
+                name,
+                cond,
+                value,
+                body,
+            } => {
+                let cond = cond.map(&mut f);
+                let value = f(value);
+                Node::Assign {
+                    name,
+                    cond,
+                    value,
+                    body: f(body),
+                }
+            }
+            Node::Array(elements) => Node::Array(elements.into_iter().map(f).collect()),
+            Node::Tuple(elements) => Node::Tuple(elements.into_iter().map(f).collect()),
+            Node::LetTuple { names, value, body } => {
+                let value = f(value);
+                Node::LetTuple {
+                    names,
+                    value,
+                    body: f(body),
+                }
+            }
+            Node::Bytes(bytes) => Node::Bytes(bytes),
+            Node::Sort(inner) => Node::Sort(f(inner)),
+            Node::SwapIf { cond, left, right } => {
+                let cond = f(cond);
+                let left = f(left);
+                Node::SwapIf {
+                    cond,
+                    left,
+                    right: f(right),
+                }
+            }
+            Node::Clamp { value, lo, hi } => {
+                let value = f(value);
+                let lo = f(lo);
+                Node::Clamp { value, lo, hi: f(hi) }
+            }
+            Node::InRange { value, lo, hi } => {
+                let value = f(value);
+                let lo = f(lo);
+                Node::InRange { value, lo, hi: f(hi) }
+            }
+            Node::Pow { base, exp } => {
+                let base = f(base);
+                Node::Pow { base, exp: f(exp) }
+            }
+            Node::Map { array, f: g } => {
+                let array = f(array);
+                Node::Map { array, f: f(g) }
+            }
+            Node::Fold { array, init, f: g } => {
+                let array = f(array);
+                let init = f(init);
+                Node::Fold { array, init, f: f(g) }
+            }
+            Node::Some(inner) => Node::Some(f(inner)),
+            Node::None => Node::None,
+            Node::UnwrapOr { option, default } => {
+                let option = f(option);
+                Node::UnwrapOr {
+                    option,
+                    default: f(default),
+                }
+            }
+            Node::Index { array, index } => {
+                let array = f(array);
+                Node::Index {
+                    array,
+                    index: f(index),
+                }
+            }
+            Node::Lookup {
+                key,
+                entries,
+                default,
+            } => {
+                let key = f(key);
+                let entries = entries.into_iter().map(|(k, v)| (f(k), f(v))).collect();
+                Node::Lookup {
+                    key,
+                    entries,
+                    default: f(default),
+                }
+            }
+            Node::Match {
+                scrutinee,
+                arms,
+                default,
+            } => {
+                let scrutinee = f(scrutinee);
+                let arms = arms.into_iter().map(|(p, e)| (f(p), f(e))).collect();
+                Node::Match {
+                    scrutinee,
+                    arms,
+                    default: default.map(f),
+                }
+            }
+            Node::Outputs(outputs) => {
+                Node::Outputs(outputs.into_iter().map(|(name, e)| (name, f(e))).collect())
+            }
+            Node::Fn {
+                name,
+                max_depth,
+                params,
+                body,
+                rest,
+            } => {
+                let body = f(body);
+                Node::Fn {
+                    name,
+                    max_depth,
+                    params,
+                    body,
+                    rest: f(rest),
+                }
+            }
+            Node::Call { name, args } => Node::Call {
+                name,
+                args: args.into_iter().map(f).collect(),
+            },
+            Node::Lambda { params, body } => Node::Lambda { params, body: f(body) },
+            Node::Const { name, value, body } => {
+                let value = f(value);
+                Node::Const {
+                    name,
+                    value,
+                    body: f(body),
+                }
+            }
+            Node::Enum {
+                name,
+                variants,
+                body,
+            } => Node::Enum {
+                name,
+                variants,
+                body: f(body),
+            },
+            Node::For {
+                var,
+                start,
+                end,
+                acc,
+                init,
+                body,
+            } => {
+                let start = f(start);
+                let end = f(end);
+                let init = f(init);
+                Node::For {
+                    var,
+                    start,
+                    end,
+                    acc,
+                    init,
+                    body: f(body),
+                }
+            }
+            Node::While {
+                cond,
+                acc,
+                init,
+                body,
+            } => {
+                let cond = f(cond);
+                let init = f(init);
+                Node::While {
+                    cond,
+                    acc,
+                    init,
+                    body: f(body),
+                }
+            }
+        }
+    }
+}
+
+impl<'a> Node<&'a Expr> {
+    /// The top node of `expr`, borrowing its children.
+    pub fn of(expr: &'a Expr) -> Self {
+        match expr {
+            Expr::Int(n) => Node::Int(*n),
+            Expr::Sized(n, width) => Node::Sized(*n, *width),
+            Expr::Bool(b) => Node::Bool(*b),
+            Expr::Var(name) => Node::Var(*name),
+            &Expr::Input { name, secret, ty } => Node::Input { name, secret, ty },
+            Expr::Secret(inner) => Node::Secret(inner),
+            Expr::Declassify(inner) => Node::Declassify(inner),
+            Expr::Assert { cond, body } => Node::Assert { cond, body },
+            Expr::BinOp { op, left, right } => Node::BinOp {
+                op: op.clone(),
+                left,
+                right,
+            },
+            Expr::UnaryOp { op, expr } => Node::UnaryOp {
+                op: op.clone(),
+                expr,
+            },
+            Expr::Cast { expr, ty } => Node::Cast { expr, ty: *ty },
+            Expr::If {
+                cond,
+                then_branch,
+                else_branch,
+            } => Node::If {
+                cond,
+                then_branch,
+                else_branch,
+            },
+            Expr::Let {
+                name,
+                ty,
+                mutable,
+                value,
+                body,
+            } => Node::Let {
+                name: *name,
+                ty: *ty,
+                mutable: *mutable,
+                value,
+                body,
+            },
+            Expr::Assign {
+                name,
+                cond,
+                value,
+                body,
+            } => Node::Assign {
+                name: *name,
+                cond: cond.as_deref(),
+                value,
+                body,
+            },
+            Expr::Array(elements) => Node::Array(elements.iter().collect()),
+            Expr::Tuple(elements) => Node::Tuple(elements.iter().collect()),
+            Expr::LetTuple { names, value, body } => Node::LetTuple {
+                names: names.clone(),
+                value,
+                body,
+            },
+            Expr::Bytes(bytes) => Node::Bytes(bytes.clone()),
+            Expr::Sort(inner) => Node::Sort(inner),
+            Expr::SwapIf { cond, left, right } => Node::SwapIf { cond, left, right },
+            Expr::Clamp { value, lo, hi } => Node::Clamp { value, lo, hi },
+            Expr::InRange { value, lo, hi } => Node::InRange { value, lo, hi },
+            Expr::Pow { base, exp } => Node::Pow { base, exp },
+            Expr::Map { array, f } => Node::Map { array, f },
+            Expr::Fold { array, init, f } => Node::Fold { array, init, f },
+            Expr::Some(inner) => Node::Some(inner),
+            Expr::None => Node::None,
+            Expr::UnwrapOr { option, default } => Node::UnwrapOr { option, default },
+            Expr::Index { array, index } => Node::Index { array, index },
+            Expr::Lookup {
+                key,
+                entries,
+                default,
+            } => Node::Lookup {
+                key,
+                entries: entries.iter().map(|(k, v)| (k, v)).collect(),
+                default,
+            },
+            Expr::Match {
+                scrutinee,
+                arms,
+                default,
+            } => Node::Match {
+                scrutinee,
+                arms: arms.iter().map(|(p, e)| (p, e)).collect(),
+                default: default.as_deref(),
+            },
+            Expr::Outputs(outputs) => {
+                Node::Outputs(outputs.iter().map(|(name, e)| (*name, e)).collect())
+            }
+            Expr::Fn {
+                name,
+                max_depth,
+                params,
+                body,
+                rest,
+            } => Node::Fn {
+                name: *name,
+                max_depth: *max_depth,
+                params: params.clone(),
+                body,
+                rest,
+            },
+            Expr::Call { name, args } => Node::Call {
+                name: *name,
+                args: args.iter().collect(),
+            },
+            Expr::Lambda { params, body } => Node::Lambda {
+                params: params.clone(),
+                body,
+            },
+            Expr::Const { name, value, body } => Node::Const {
+                name: *name,
+                value,
+                body,
+            },
+            Expr::Enum {
+                name,
+                variants,
+                body,
+            } => Node::Enum {
+                name: *name,
+                variants: variants.clone(),
+                body,
+            },
+            Expr::For {
+                var,
+                start,
+                end,
+                acc,
+                init,
+                body,
+            } => Node::For {
+                var: *var,
+                start,
+                end,
+                acc: *acc,
+                init,
+                body,
+            },
+            Expr::While {
+                cond,
+                acc,
+                init,
+                body,
+            } => Node::While {
+                cond,
+                acc: *acc,
+                init,
+                body,
+            },
+        }
+    }
+}
+
+/// How the [`Parser`](crate::parser::Parser) makes a node from its
+/// already built children.
+pub trait Build {
+    type Expr;
+
+    fn build(&mut self, node: Node<Self::Expr>) -> Self::Expr;
+}
+
+/// Builds owned [`Expr`]s, one `Box` per child.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Boxed;
+
+impl Build for Boxed {
+    type Expr = Expr;
+
+    fn build(&mut self, node: Node<Expr>) -> Expr {
+        match node {
+            Node::Int(n) => Expr::Int(n),
+            Node::Sized(n, width) => Expr::Sized(n, width),
+            Node::Bool(b) => Expr::Bool(b),
+            Node::Var(name) => Expr::Var(name),
+            Node::Input { name, secret, ty } => Expr::Input { name, secret, ty },
+            Node::Secret(inner) => Expr::Secret(Box::new(inner)),
+            Node::Declassify(inner) => Expr::Declassify(Box::new(inner)),
+            Node::Assert { cond, body } => Expr::Assert {
+                cond: Box::new(cond),
+                body: Box::new(body),
+            },
+            Node::BinOp { op, left, right } => Expr::BinOp {
+                op,
+                left: Box::new(left),
+                right: Box::new(right),
+            },
+            Node::UnaryOp { op, expr } => Expr::UnaryOp {
+                op,
+                expr: Box::new(expr),
+            },
+            Node::Cast { expr, ty } => Expr::Cast {
+                expr: Box::new(expr),
+                ty,
+            },
+            Node::If {
+                cond,
+                then_branch,
+                else_branch,
+            } => Expr::If {
+                cond: Box::new(cond),
+                then_branch: Box::new(then_branch),
+                else_branch: Box::new(else_branch),
+            },
+            Node::Let {
+                name,
+                ty,
+                mutable,
+                value,
+                body,
+            } => Expr::Let {
+                name,
+                ty,
+                mutable,
+                value: Box::new(value),
+                body: Box::new(body),
+            },
+            Node::Assign {
+                name,
+                cond,
+                value,
+                body,
+            } => Expr::Assign {
+                name,
+                cond: cond.map(Box::new),
+                value: Box::new(value),
+                body: Box::new(body),
+            },
+            Node::Array(elements) => Expr::Array(elements),
+            Node::Tuple(elements) => Expr::Tuple(elements),
+            Node::LetTuple { names, value, body } => Expr::LetTuple {
+                names,
+                value: Box::new(value),
+                body: Box::new(body),
+            },
+            Node::Bytes(bytes) => Expr::Bytes(bytes),
+            Node::Sort(inner) => Expr::Sort(Box::new(inner)),
+            Node::SwapIf { cond, left, right } => Expr::SwapIf {
+                cond: Box::new(cond),
+                left: Box::new(left),
+                right: Box::new(right),
+            },
+            Node::Clamp { value, lo, hi } => Expr::Clamp {
+                value: Box::new(value),
+                lo: Box::new(lo),
+                hi: Box::new(hi),
+            },
+            Node::InRange { value, lo, hi } => Expr::InRange {
+                value: Box::new(value),
+                lo: Box::new(lo),
+                hi: Box::new(hi),
+            },
+            Node::Pow { base, exp } => Expr::Pow {
+                base: Box::new(base),
+                exp: Box::new(exp),
+            },
+            Node::Map { array, f } => Expr::Map {
+                array: Box::new(array),
+                f: Box::new(f),
+            },
+            Node::Fold { array, init, f } => Expr::Fold {
+                array: Box::new(array),
+                init: Box::new(init),
+                f: Box::new(f),
+            },
+            Node::Some(inner) => Expr::Some(Box::new(inner)),
+            Node::None => Expr::None,
+            Node::UnwrapOr { option, default } => Expr::UnwrapOr {
+                option: Box::new(option),
+                default: Box::new(default),
+            },
+            Node::Index { array, index } => Expr::Index {
+                array: Box::new(array),
+                index: Box::new(index),
+            },
+            Node::Lookup {
+                key,
+                entries,
+                default,
+            } => Expr::Lookup {
+                key: Box::new(key),
+                entries,
+                default: Box::new(default),
+            },
+            Node::Match {
+                scrutinee,
+                arms,
+                default,
+            } => Expr::Match {
+                scrutinee: Box::new(scrutinee),
+                arms,
+                default: default.map(Box::new),
+            },
+            Node::Outputs(outputs) => Expr::Outputs(outputs),
+            Node::Fn {
+                name,
+                max_depth,
+                params,
+                body,
+                rest,
+            } => Expr::Fn {
+                name,
+                max_depth,
+                params,
+                body: Box::new(body),
+                rest: Box::new(rest),
+            },
+            Node::Call { name, args } => Expr::Call { name, args },
+            Node::Lambda { params, body } => Expr::Lambda {
+                params,
+                body: Box::new(body),
+            },
+            Node::Const { name, value, body } => Expr::Const {
+                name,
+                value: Box::new(value),
+                body: Box::new(body),
+            },
+            Node::Enum {
+                name,
+                variants,
+                body,
+            } => Expr::Enum {
+                name,
+                variants,
+                body: Box::new(body),
+            },
+            Node::For {
+                var,
+                start,
+                end,
+                acc,
+                init,
+                body,
+            } => Expr::For {
+                var,
+                start: Box::new(start),
+                end: Box::new(end),
+                acc,
+                init: Box::new(init),
+                body: Box::new(body),
+            },
+            Node::While {
+                cond,
+                acc,
+                init,
+                body,
+            } => Expr::While {
+                cond: Box::new(cond),
+                acc,
+                init: Box::new(init),
+                body: Box::new(body),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+
+    /// Counts the nodes it builds.
+    struct Counting(usize);
+
+    impl Build for &mut Counting {
+        type Expr = Expr;
+
+        fn build(&mut self, node: Node<Expr>) -> Expr {
+            self.0 += 1;
+            Boxed.build(node)
+        }
+    }
+
+    #[test]
+    fn test_parser_builds_through_the_builder() {
+        let source = "let x = secret(1) (x + 2)";
+        let (tokens, spans) = Lexer::new(source).tokenize().unwrap();
+        let mut counting = Counting(0);
+        let (expr, node_spans) = Parser::with_spans(&tokens, &spans)
+            .build_with(&mut counting)
+            .parse_with_spans()
+            .unwrap();
+        assert_eq!(counting.0, node_spans.len());
+        assert_eq!(expr, Parser::new(&tokens).parse().unwrap());
+        let rebuilt = Boxed.build(Node::of(&expr).map(Clone::clone));
+        assert_eq!(rebuilt, expr);
+    }
+}
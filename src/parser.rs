@@ -5,38 +5,313 @@
 //!
 //! Grammar (simplified):
 //! ```text
-//! expr     → let_expr | if_expr | or_expr
-//! let_expr → "let" IDENT "=" expr expr
-//! if_expr  → "if" expr "then" expr "else" expr
+//! program  → expr ";"?
+//! expr     → let_expr | const_expr | enum_expr | assert_expr | assign_expr | fn_expr
+//!          | for_expr | while_expr | if_expr | match_expr | input_decl | output_decl+
+//!          | or_expr
+//! let_expr → "let" ("mut"? IDENT (":" annotation)? | "(" IDENT ("," IDENT)+ ")") "=" expr
+//!            ";"? expr
+//! const_expr → "const" IDENT "=" expr ";"? expr
+//! enum_expr → "enum" IDENT "=" IDENT ("|" IDENT)* ";"? expr
+//! assert_expr → "assert" "(" expr ")" ";"? expr
+//! input_decl → "input" ("secret" | "pub")? IDENT ":" type ";"? expr
+//! output_decl → "output" IDENT ";"?
+//! assign_expr → IDENT "=" expr ";"? expr
+//! annotation → ("secret" | "pub")? type
+//! type     → "int" | "bool" | "u8" | "u16" | "u32" | "u64" | "i32" | "i64"
+//! fn_expr  → ("#" "[" "max_depth" "(" INT ")" "]")? "fn" IDENT "(" params ")" "=" expr
+//!            ";"? expr
+//!          | "fn" "(" params ")" "=>" expr
+//! params   → (IDENT ("," IDENT)*)?
+//! for_expr → "for" IDENT "in" or_expr ".." or_expr "with" IDENT "=" expr "do" expr
+//! while_expr → "while" expr "with" IDENT "=" expr "do" expr
+//! if_expr  → "if" expr "then" (expr ("elif" expr "then" expr)* "else" expr | assign_expr)
+//! match_expr → "match" expr "with" (pattern "=>" expr ",")+ "_" "=>" expr
+//!          | "match" expr "with" IDENT "=>" expr ("," IDENT "=>" expr)*
+//! pattern  → "-"? (INT | SIZED) | BOOL | IDENT
 //! or_expr  → and_expr ("or" and_expr)*
 //! and_expr → cmp_expr ("and" cmp_expr)*
-//! cmp_expr → add_expr (("==" | "!=" | "<" | "<=" | ">" | ">=") add_expr)?
-//! add_expr → mul_expr (("+" | "-") mul_expr)*
-//! mul_expr → unary (("*" | "/" | "%") unary)*
-//! unary    → ("not" | "-") unary | primary
-//! primary  → INT | BOOL | IDENT | "secret" "(" expr ")" | "(" expr ")"
+//! cmp_expr → bor_expr (("==" | "!=" | "<" | "<=" | ">" | ">=") bor_expr)?
+//! bor_expr → xor_expr ("|" xor_expr)*
+//! xor_expr → band_expr ("^" band_expr)*
+//! band_expr → shift_expr ("&" shift_expr)*
+//! shift_expr → add_expr (("<<" | ">>") add_expr)*
+//! add_expr → mul_expr (("+" | "-" | "+|" | "-|") mul_expr)*
+//! mul_expr → cast_expr (("*" | "*|" | "/" | "%") cast_expr)*
+//! cast_expr → unary ("as" type)*
+//! unary    → ("not" | "-") unary | postfix
+//! postfix  → primary ("[" expr "]")*
+//! primary  → INT | SIZED | BOOL | BYTES | IDENT | call | "secret" "(" expr ")"
+//!          | "declassify" "(" expr ")"
+//!          | "(" expr ("," expr)* ")"
+//!          | "sort" "(" expr ")" | "saturating" "(" expr ")"
+//!          | "swap_if" "(" expr "," expr "," expr ")"
+//!          | ("clamp" | "in_range") "(" expr "," expr "," expr ")"
+//!          | ("to_int" | "to_bool") "(" expr ")"
+//!          | "pow" "(" expr "," expr ")"
+//!          | "map" "(" expr "," expr ")" | "fold" "(" expr "," expr "," expr ")"
+//!          | "some" "(" expr ")" | "none" | "unwrap_or" "(" expr "," expr ")"
+//!          | "[" expr ("," expr)* "]"
+//!          | "lookup" "(" expr "," "[" entry ("," entry)* "]" "," expr ")"
+//! entry    → "(" expr "," expr ")"
+//! call     → IDENT "(" (expr ("," expr)*)? ")"
 //! ```
+//!
+//! A program is a sequence of statements, `let`, `const`, `enum` and `fn`
+//! bindings in scope for the rest, ending with the result. A `;` may end
+//! each statement, and is needed where the next one would continue the
+//! bound value, as in `let x = 1; -x`. [`Parser::parse_program`] splits
+//! the sequence into a [`Program`].
+//!
+//! A `SIZED` literal is an `INT` with a width suffix, `200u8`; type names
+//! are words only in annotations and casts, `x as u8`. `i64` is `int`.
+//!
+//! `input` and `output` are words only where they start a declaration.
+//! `input secret x: int` binds `x` to a program input for the rest, read
+//! when the program runs; `output x output y` ends the program with the
+//! result `outputs(x = x, y = y)`.
+//!
+//! `if a then x elif b then y else z` is `if a then x else if b then y
+//! else z`.
+//!
+//! `mut` is a word only after `let`. An `assign_expr` rebinds a `let mut`
+//! variable for the rest; after `if c then` it is a conditional
+//! assignment, with no `else`.
+//!
+//! A `call` names a function defined by an enclosing `fn_expr`, a
+//! variable bound to an anonymous `fn (...) =>` by its `let`, or a
+//! parameter of the function around it, which may be passed one; any
+//! other identifier followed by `(` is a variable, so `let y = x (x + 1)`
+//! still binds `y` to `x`. A function is callable in its own body only
+//! with a `#[max_depth(n)]` bound (`n` > 0); without one, its name
+//! followed by `(` there is an [`ParseError::UnboundedRecursion`].
+//!
+//! `saturating(e)` has no node of its own: inside `e`, `+`, `-`, `*` and
+//! unary `-` are read as the saturating `+|`, `-|`, `*|` and `0 -| x`.
 
-use crate::ast::{BinOp, Expr, UnaryOp};
+use crate::codes;
+use crate::ast::{Annotation, BinOp, Program, UnaryOp};
 use crate::lexer::Token;
+use crate::limits::{LimitError, Limits};
+use crate::node::{Boxed, Build, Node};
+use crate::span::{NodeSpans, Span};
+use crate::stack;
+use crate::symbol::Symbol;
+use crate::typeck::{Scalar, Width};
 use thiserror::Error;
 
 #[derive(Error, Debug)]
 pub enum ParseError {
     #[error("unexpected token: {0:?}, expected {1}")]
-    UnexpectedToken(Token, &'static str),
+    UnexpectedToken(Token, &'static str, Span),
     #[error("unexpected end of input")]
-    UnexpectedEof,
+    UnexpectedEof(Span),
+    #[error("`{0}` calls itself without a bound; declare it with `#[max_depth(n)]`")]
+    UnboundedRecursion(Symbol, Span),
+    #[error(transparent)]
+    Limit(#[from] LimitError),
 }
 
-pub struct Parser<'a> {
+impl ParseError {
+    /// Catalog code of this error.
+    pub fn code(&self) -> &'static str {
+        match self {
+            ParseError::UnexpectedToken(..) => codes::UNEXPECTED_TOKEN,
+            ParseError::UnexpectedEof(_) => codes::UNEXPECTED_EOF,
+            ParseError::UnboundedRecursion(..) => codes::UNBOUNDED_RECURSION,
+            ParseError::Limit(e) => e.code(),
+        }
+    }
+
+    /// Source span of the offending token (empty if spans were not supplied).
+    pub fn span(&self) -> Span {
+        match self {
+            ParseError::UnexpectedToken(_, _, span)
+            | ParseError::UnexpectedEof(span)
+            | ParseError::UnboundedRecursion(_, span) => *span,
+            ParseError::Limit(e) => e.span(),
+        }
+    }
+}
+
+/// Parses tokens into nodes made by `B`: owned [`Expr`](crate::ast::Expr)s
+/// by default, or those of another [`Build`] with [`Parser::build_with`].
+pub struct Parser<'a, B: Build = Boxed> {
     tokens: &'a [Token],
+    spans: Option<&'a [Span]>,
     pos: usize,
+    node_spans: NodeSpans,
+    builder: B,
+    limits: Limits,
+    /// Current nesting of `parse_expr` and `parse_unary`.
+    nesting: usize,
+    /// Depth of each built subtree not yet used as a child, in post-order.
+    depths: Vec<usize>,
+    nodes: usize,
+    /// Inside `saturating(...)`.
+    saturating: bool,
+    /// Functions defined around the current position, innermost last.
+    functions: Vec<Symbol>,
+    /// Functions without a depth bound whose bodies are around the current
+    /// position.
+    unbounded: Vec<Symbol>,
 }
 
 impl<'a> Parser<'a> {
     pub fn new(tokens: &'a [Token]) -> Self {
-        Self { tokens, pos: 0 }
+        Self {
+            tokens,
+            spans: None,
+            pos: 0,
+            node_spans: NodeSpans::new(),
+            builder: Boxed,
+            limits: Limits::none(),
+            nesting: 0,
+            depths: Vec::new(),
+            nodes: 0,
+            saturating: false,
+            functions: Vec::new(),
+            unbounded: Vec::new(),
+        }
+    }
+
+    /// Create a parser that knows the source span of every token, as
+    /// produced by [`Lexer::tokenize`](crate::lexer::Lexer::tokenize).
+    pub fn with_spans(tokens: &'a [Token], spans: &'a [Span]) -> Self {
+        Self {
+            spans: Some(spans),
+            ..Self::new(tokens)
+        }
+    }
+
+    /// Parse a whole program into its statements and result.
+    pub fn parse_program(&mut self) -> Result<Program, ParseError> {
+        self.parse().map(Program::from_expr)
+    }
+
+    /// Like [`Parser::parse_program`], also returning the span of every
+    /// node of [`Program::into_expr`] in post-order.
+    pub fn parse_program_with_spans(&mut self) -> Result<(Program, NodeSpans), ParseError> {
+        let (expr, spans) = self.parse_with_spans()?;
+        Ok((Program::from_expr(expr), spans))
+    }
+}
+
+impl<'a, B: Build> Parser<'a, B> {
+    /// Build nodes with `builder` instead.
+    pub fn build_with<C: Build>(self, builder: C) -> Parser<'a, C> {
+        Parser {
+            tokens: self.tokens,
+            spans: self.spans,
+            pos: self.pos,
+            node_spans: self.node_spans,
+            builder,
+            limits: self.limits,
+            nesting: self.nesting,
+            depths: self.depths,
+            nodes: self.nodes,
+            saturating: self.saturating,
+            functions: self.functions,
+            unbounded: self.unbounded,
+        }
+    }
+
+    /// Fail with [`ParseError::Limit`] on programs nesting deeper than
+    /// `limits.max_depth` or with more than `limits.max_nodes` nodes.
+    pub fn with_limits(self, limits: Limits) -> Self {
+        Self { limits, ..self }
+    }
+
+    /// Parse, also returning the span of every AST node in post-order.
+    pub fn parse_with_spans(&mut self) -> Result<(B::Expr, NodeSpans), ParseError> {
+        self.node_spans = NodeSpans::new();
+        let expr = self.parse()?;
+        Ok((expr, std::mem::take(&mut self.node_spans)))
+    }
+
+    fn token_span(&self, idx: usize) -> Span {
+        let Some(spans) = self.spans else {
+            return Span::default();
+        };
+        match spans.get(idx) {
+            Some(span) => *span,
+            // Past the end: point just after the last token
+            None => spans
+                .last()
+                .map(|s| Span::new(s.end, s.end))
+                .unwrap_or_default(),
+        }
+    }
+
+    /// Build a node from tokens `start..self.pos`, recording its span and
+    /// checking it against the limits.
+    fn node(&mut self, start: usize, node: Node<B::Expr>) -> Result<B::Expr, ParseError> {
+        let end = self.pos.saturating_sub(1).max(start);
+        let span = self.token_span(start).merge(self.token_span(end));
+        let children = match &node {
+            Node::Int(_)
+            | Node::Sized(..)
+            | Node::Bool(_)
+            | Node::Var(_)
+            | Node::Input { .. }
+            | Node::Bytes(_)
+            | Node::None => 0,
+            Node::Secret(_)
+            | Node::Declassify(_)
+            | Node::Sort(_)
+            | Node::Some(_)
+            | Node::UnaryOp { .. }
+            | Node::Cast { .. }
+            | Node::Enum { .. } => 1,
+            Node::BinOp { .. } | Node::Let { .. } | Node::Const { .. } | Node::Assert { .. } => 2,
+            Node::Assign { cond, .. } => 2 + usize::from(cond.is_some()),
+            Node::If { .. }
+            | Node::SwapIf { .. }
+            | Node::Clamp { .. }
+            | Node::InRange { .. }
+            | Node::Fold { .. } => 3,
+            Node::Index { .. } | Node::Pow { .. } | Node::Map { .. } | Node::UnwrapOr { .. } => 2,
+            Node::Array(elements) | Node::Tuple(elements) => elements.len(),
+            Node::LetTuple { .. } => 2,
+            Node::Lookup { entries, .. } => 2 + 2 * entries.len(),
+            Node::Match { arms, default, .. } => {
+                1 + 2 * arms.len() + usize::from(default.is_some())
+            }
+            Node::Outputs(outputs) => outputs.len(),
+            Node::Fn { .. } => 2,
+            Node::Call { args, .. } => args.len(),
+            Node::Lambda { .. } => 1,
+            Node::For { .. } => 4,
+            Node::While { .. } => 3,
+        };
+        let rest = self.depths.len() - children;
+        let depth = self.depths.drain(rest..).max().unwrap_or(0) + 1;
+        self.depths.push(depth);
+        self.nodes += 1;
+        self.limits.check_depth(depth, span)?;
+        self.limits.check_nodes(self.nodes, span)?;
+        self.node_spans.push(span);
+        Ok(self.builder.build(node))
+    }
+
+    /// Run `parse` one level deeper, failing once nesting passes the limit.
+    fn nested<T>(
+        &mut self,
+        parse: impl FnOnce(&mut Self) -> Result<T, ParseError>,
+    ) -> Result<T, ParseError> {
+        self.nesting += 1;
+        self.limits.check_depth(self.nesting, self.token_span(self.pos))?;
+        let result = stack::guard(|| parse(self));
+        self.nesting -= 1;
+        result
+    }
+
+    fn unexpected(&self, token: &Token, expected: &'static str) -> ParseError {
+        ParseError::UnexpectedToken(token.clone(), expected, self.token_span(self.pos))
+    }
+
+    fn eof(&self) -> ParseError {
+        ParseError::UnexpectedEof(self.token_span(self.tokens.len()))
     }
 
     fn peek(&self) -> Option<&Token> {
@@ -55,95 +330,570 @@ fn expect(&mut self, expected: &Token) -> Result<(), ParseError> {
                 self.advance();
                 Ok(())
             }
-            Some(t) => Err(ParseError::UnexpectedToken(
-                t.clone(),
-                "specific token",
-            )),
-            None => Err(ParseError::UnexpectedEof),
+            Some(t) => Err(self.unexpected(t, "specific token")),
+            None => Err(self.eof()),
         }
     }
 
-    pub fn parse(&mut self) -> Result<Expr, ParseError> {
-        self.parse_expr()
+    /// Parse a whole program as one expression, in which each statement
+    /// binds the rest; see [`Parser::parse_program`].
+    pub fn parse(&mut self) -> Result<B::Expr, ParseError> {
+        let _span = tracing::debug_span!("parse", tokens = self.tokens.len()).entered();
+        self.nesting = 0;
+        self.depths.clear();
+        self.nodes = 0;
+        self.saturating = false;
+        self.functions.clear();
+        self.unbounded.clear();
+        let expr = self.parse_expr()?;
+        self.separator();
+        match self.peek() {
+            None | Some(Token::Eof) => Ok(expr),
+            Some(t) => Err(self.unexpected(t, "end of input")),
+        }
     }
 
-    fn parse_expr(&mut self) -> Result<Expr, ParseError> {
-        match self.peek() {
-            Some(Token::Let) => self.parse_let(),
-            Some(Token::If) => self.parse_if(),
-            _ => self.parse_or(),
+    /// Skip the `;` that may end a statement.
+    fn separator(&mut self) {
+        if matches!(self.peek(), Some(Token::Semicolon)) {
+            self.advance();
         }
     }
 
-    fn parse_let(&mut self) -> Result<Expr, ParseError> {
+    fn parse_expr(&mut self) -> Result<B::Expr, ParseError> {
+        self.nested(|parser| match parser.peek() {
+            Some(Token::Let) => parser.parse_let(),
+            Some(Token::Const) => parser.parse_const(),
+            Some(Token::Enum) => parser.parse_enum(),
+            Some(Token::Assert) => parser.parse_assert(),
+            Some(Token::Ident(word)) if word.as_str() == "input" && parser.at_declaration() => {
+                parser.parse_input()
+            }
+            Some(Token::Ident(word)) if word.as_str() == "output" && parser.at_declaration() => {
+                parser.parse_output_decls()
+            }
+            Some(Token::Ident(_)) if parser.at_assignment() => {
+                let start = parser.pos;
+                parser.parse_assign(start, None)
+            }
+            Some(Token::Fn | Token::Hash) => parser.parse_fn(),
+            Some(Token::For) => parser.parse_for(),
+            Some(Token::While) => parser.parse_while(),
+            Some(Token::If) => parser.parse_if(),
+            Some(Token::Match) => parser.parse_match(),
+            _ => parser.parse_or(),
+        })
+    }
+
+    fn parse_let(&mut self) -> Result<B::Expr, ParseError> {
+        let start = self.pos;
         self.expect(&Token::Let)?;
 
-        let name = match self.advance() {
-            Some(Token::Ident(n)) => n.clone(),
-            Some(t) => return Err(ParseError::UnexpectedToken(t.clone(), "identifier")),
-            None => return Err(ParseError::UnexpectedEof),
+        if matches!(self.peek(), Some(Token::LParen)) {
+            self.advance();
+            let mut names = vec![self.parse_ident()?];
+            // A tuple has at least two components
+            self.expect(&Token::Comma)?;
+            names.push(self.parse_ident()?);
+            while matches!(self.peek(), Some(Token::Comma)) {
+                self.advance();
+                names.push(self.parse_ident()?);
+            }
+            self.expect(&Token::RParen)?;
+            self.expect(&Token::Assign)?;
+            let value = self.parse_expr()?;
+            self.separator();
+            let body = self.parse_expr()?;
+            return self.node(start, Node::LetTuple { names, value, body });
+        }
+
+        let mutable = matches!(self.peek(), Some(Token::Ident(word)) if word.as_str() == "mut")
+            && matches!(self.tokens.get(self.pos + 1), Some(Token::Ident(_)));
+        if mutable {
+            self.advance();
+        }
+        let name = match self.peek() {
+            Some(Token::Ident(n)) => *n,
+            Some(t) => return Err(self.unexpected(t, "identifier")),
+            None => return Err(self.eof()),
         };
+        self.advance();
+
+        let ty = if matches!(self.peek(), Some(Token::Colon)) {
+            self.advance();
+            Some(self.parse_annotation()?)
+        } else {
+            None
+        };
+        self.expect(&Token::Assign)?;
+        let lambda = matches!(self.peek(), Some(Token::Fn))
+            && matches!(self.tokens.get(self.pos + 1), Some(Token::LParen));
+        let value = self.parse_expr()?;
+        self.separator();
+        let body = self.callable_in(lambda.then_some(name).as_slice(), Self::parse_expr)?;
 
+        self.node(
+            start,
+            Node::Let {
+                name,
+                ty,
+                mutable,
+                value,
+                body,
+            },
+        )
+    }
+
+    fn parse_const(&mut self) -> Result<B::Expr, ParseError> {
+        let start = self.pos;
+        self.expect(&Token::Const)?;
+        let name = self.parse_ident()?;
         self.expect(&Token::Assign)?;
         let value = self.parse_expr()?;
+        self.separator();
+        let body = self.parse_expr()?;
+        self.node(start, Node::Const { name, value, body })
+    }
+
+    fn parse_enum(&mut self) -> Result<B::Expr, ParseError> {
+        let start = self.pos;
+        self.expect(&Token::Enum)?;
+        let name = self.parse_ident()?;
+        self.expect(&Token::Assign)?;
+        let mut variants = vec![self.parse_ident()?];
+        while matches!(self.peek(), Some(Token::Pipe)) {
+            self.advance();
+            variants.push(self.parse_ident()?);
+        }
+        self.separator();
+        let body = self.parse_expr()?;
+        self.node(
+            start,
+            Node::Enum {
+                name,
+                variants,
+                body,
+            },
+        )
+    }
+
+    fn parse_assert(&mut self) -> Result<B::Expr, ParseError> {
+        let start = self.pos;
+        self.expect(&Token::Assert)?;
+        self.expect(&Token::LParen)?;
+        let cond = self.parse_expr()?;
+        self.expect(&Token::RParen)?;
+        self.separator();
         let body = self.parse_expr()?;
+        self.node(start, Node::Assert { cond, body })
+    }
 
-        Ok(Expr::Let {
+    /// `input (secret | pub)? name: type ;? body`, a `let` of the input.
+    fn parse_input(&mut self) -> Result<B::Expr, ParseError> {
+        let start = self.pos;
+        self.advance();
+        let secret = match self.peek() {
+            Some(Token::Secret) => Some(true),
+            // `pub` is the name in `input pub: int`
+            Some(Token::Ident(word)) if word.as_str() == "pub" && !self.at(1, &Token::Colon) => {
+                Some(false)
+            }
+            _ => None,
+        };
+        if secret.is_some() {
+            self.advance();
+        }
+        let secret = secret.unwrap_or(false);
+        let name = self.parse_ident()?;
+        self.expect(&Token::Colon)?;
+        let ty = self.parse_scalar()?;
+        let value = self.node(start, Node::Input { name, secret, ty })?;
+        self.separator();
+        let body = self.parse_expr()?;
+        let node = Node::Let {
             name,
-            value: Box::new(value),
-            body: Box::new(body),
-        })
+            ty: None,
+            mutable: false,
+            value,
+            body,
+        };
+        self.node(start, node)
     }
 
-    fn parse_if(&mut self) -> Result<Expr, ParseError> {
+    /// `output a ;? output b ;? ...`, the program's outputs `a`, `b`, ...
+    fn parse_output_decls(&mut self) -> Result<B::Expr, ParseError> {
+        let start = self.pos;
+        let mut outputs = Vec::new();
+        while matches!(self.peek(), Some(Token::Ident(word)) if word.as_str() == "output")
+            && self.at_declaration()
+        {
+            self.advance();
+            let name_pos = self.pos;
+            let name = self.parse_ident()?;
+            let value = self.node(name_pos, Node::Var(name))?;
+            outputs.push((name, value));
+            self.separator();
+        }
+        self.node(start, Node::Outputs(outputs))
+    }
+
+    /// Whether the next word starts a declaration: a name, or for `input`
+    /// `secret`, follows it.
+    fn at_declaration(&self) -> bool {
+        match self.tokens.get(self.pos + 1) {
+            Some(Token::Ident(_)) => true,
+            Some(Token::Secret) => self.at(0, &Token::Ident(Symbol::intern("input"))),
+            _ => false,
+        }
+    }
+
+    /// Whether the token `offset` past the next one is `token`.
+    fn at(&self, offset: usize, token: &Token) -> bool {
+        self.tokens.get(self.pos + offset) == Some(token)
+    }
+
+    /// Whether the next tokens are `IDENT =`.
+    fn at_assignment(&self) -> bool {
+        matches!(self.peek(), Some(Token::Ident(_)))
+            && matches!(self.tokens.get(self.pos + 1), Some(Token::Assign))
+    }
+
+    /// `name = value ;? body`, under `cond` if it follows `if cond then`.
+    fn parse_assign(
+        &mut self,
+        start: usize,
+        cond: Option<B::Expr>,
+    ) -> Result<B::Expr, ParseError> {
+        let name = self.parse_ident()?;
+        self.expect(&Token::Assign)?;
+        let value = self.parse_expr()?;
+        self.separator();
+        let body = self.parse_expr()?;
+        let node = Node::Assign {
+            name,
+            cond,
+            value,
+            body,
+        };
+        self.node(start, node)
+    }
+
+    /// `secret int`, `pub bool`, `int`, ...: `pub`, `int` and `bool` are
+    /// identifiers everywhere else.
+    fn parse_annotation(&mut self) -> Result<Annotation, ParseError> {
+        let secret = match self.peek() {
+            Some(Token::Secret) => Some(true),
+            Some(Token::Ident(word)) if word.as_str() == "pub" => Some(false),
+            _ => None,
+        };
+        if secret.is_some() {
+            self.advance();
+        }
+        let ty = self.parse_scalar()?;
+        Ok(Annotation { secret, ty })
+    }
+
+    /// `int`, `bool`, `u8`, ...
+    fn parse_scalar(&mut self) -> Result<Scalar, ParseError> {
+        let ty = match self.peek() {
+            Some(Token::Ident(word)) if matches!(word.as_str(), "int" | "i64") => Scalar::Int,
+            Some(Token::Ident(word)) if word.as_str() == "bool" => Scalar::Bool,
+            Some(Token::Ident(word)) if Width::from_name(word.as_str()).is_some() => {
+                Scalar::Sized(Width::from_name(word.as_str()).unwrap())
+            }
+            Some(t) => return Err(self.unexpected(t, "a type")),
+            None => return Err(self.eof()),
+        };
+        self.advance();
+        Ok(ty)
+    }
+
+    /// Parse with `names` callable, as functions, for the duration.
+    fn callable_in(
+        &mut self,
+        names: &[Symbol],
+        parse: fn(&mut Self) -> Result<B::Expr, ParseError>,
+    ) -> Result<B::Expr, ParseError> {
+        self.functions.extend_from_slice(names);
+        let parsed = parse(self);
+        self.functions.truncate(self.functions.len() - names.len());
+        parsed
+    }
+
+    /// Parse the `#[max_depth(n)]` that may precede a `fn`.
+    fn parse_max_depth(&mut self) -> Result<Option<u32>, ParseError> {
+        if !matches!(self.peek(), Some(Token::Hash)) {
+            return Ok(None);
+        }
+        self.advance();
+        self.expect(&Token::LBracket)?;
+        match self.peek() {
+            Some(Token::Ident(word)) if word.as_str() == "max_depth" => {}
+            Some(t) => return Err(self.unexpected(t, "`max_depth`")),
+            None => return Err(self.eof()),
+        }
+        self.advance();
+        self.expect(&Token::LParen)?;
+        let depth = match self.peek() {
+            Some(Token::Int(n)) if *n > 0 && u32::try_from(*n).is_ok() => *n as u32,
+            Some(t) => return Err(self.unexpected(t, "a positive depth")),
+            None => return Err(self.eof()),
+        };
+        self.advance();
+        self.expect(&Token::RParen)?;
+        self.expect(&Token::RBracket)?;
+        Ok(Some(depth))
+    }
+
+    fn parse_fn(&mut self) -> Result<B::Expr, ParseError> {
+        let start = self.pos;
+        let max_depth = self.parse_max_depth()?;
+        self.expect(&Token::Fn)?;
+        let name = match self.peek() {
+            Some(Token::LParen) if max_depth.is_none() => None,
+            _ => Some(self.parse_ident()?),
+        };
+        self.expect(&Token::LParen)?;
+        let mut params = Vec::new();
+        if !matches!(self.peek(), Some(Token::RParen)) {
+            params.push(self.parse_ident()?);
+            while matches!(self.peek(), Some(Token::Comma)) {
+                self.advance();
+                params.push(self.parse_ident()?);
+            }
+        }
+        self.expect(&Token::RParen)?;
+        let Some(name) = name else {
+            self.expect(&Token::FatArrow)?;
+            let body = self.callable_in(&params, Self::parse_expr)?;
+            return self.node(start, Node::Lambda { params, body });
+        };
+        self.expect(&Token::Assign)?;
+        // Callable after its body, and in it only if bounded
+        let body = match max_depth {
+            Some(_) => {
+                let callable: Vec<_> = params.iter().copied().chain([name]).collect();
+                self.callable_in(&callable, Self::parse_expr)?
+            }
+            None => {
+                self.unbounded.push(name);
+                let body = self.callable_in(&params, Self::parse_expr);
+                self.unbounded.pop();
+                body?
+            }
+        };
+        self.separator();
+        let rest = self.callable_in(&[name], Self::parse_expr)?;
+
+        self.node(
+            start,
+            Node::Fn {
+                name,
+                max_depth,
+                params,
+                body,
+                rest,
+            },
+        )
+    }
+
+    fn parse_for(&mut self) -> Result<B::Expr, ParseError> {
+        let start = self.pos;
+        self.expect(&Token::For)?;
+        let var = self.parse_ident()?;
+        self.expect(&Token::In)?;
+        let from = self.parse_or()?;
+        self.expect(&Token::DotDot)?;
+        let to = self.parse_or()?;
+        self.expect(&Token::With)?;
+        let acc = self.parse_ident()?;
+        self.expect(&Token::Assign)?;
+        let init = self.parse_expr()?;
+        self.expect(&Token::Do)?;
+        let body = self.parse_expr()?;
+
+        self.node(
+            start,
+            Node::For {
+                var,
+                start: from,
+                end: to,
+                acc,
+                init,
+                body,
+            },
+        )
+    }
+
+    fn parse_while(&mut self) -> Result<B::Expr, ParseError> {
+        let start = self.pos;
+        self.expect(&Token::While)?;
+        let cond = self.parse_expr()?;
+        self.expect(&Token::With)?;
+        let acc = self.parse_ident()?;
+        self.expect(&Token::Assign)?;
+        let init = self.parse_expr()?;
+        self.expect(&Token::Do)?;
+        let body = self.parse_expr()?;
+
+        self.node(
+            start,
+            Node::While {
+                cond,
+                acc,
+                init,
+                body,
+            },
+        )
+    }
+
+    fn parse_ident(&mut self) -> Result<Symbol, ParseError> {
+        match self.peek() {
+            Some(Token::Ident(n)) => {
+                let name = *n;
+                self.advance();
+                Ok(name)
+            }
+            Some(t) => Err(self.unexpected(t, "identifier")),
+            None => Err(self.eof()),
+        }
+    }
+
+    fn parse_if(&mut self) -> Result<B::Expr, ParseError> {
+        let start = self.pos;
         self.expect(&Token::If)?;
         let cond = self.parse_expr()?;
         self.expect(&Token::Then)?;
+        if self.at_assignment() {
+            return self.parse_assign(start, Some(cond));
+        }
+        self.parse_if_branches(start, cond)
+    }
+
+    /// The branches of an `if` after its `then`. An `elif` is an `if` in
+    /// the else branch, spanning from the `elif`.
+    fn parse_if_branches(&mut self, start: usize, cond: B::Expr) -> Result<B::Expr, ParseError> {
         let then_branch = self.parse_expr()?;
-        self.expect(&Token::Else)?;
-        let else_branch = self.parse_expr()?;
+        let else_branch = if matches!(self.peek(), Some(Token::Elif)) {
+            let elif_start = self.pos;
+            self.advance();
+            let cond = self.parse_expr()?;
+            self.expect(&Token::Then)?;
+            self.parse_if_branches(elif_start, cond)?
+        } else {
+            self.expect(&Token::Else)?;
+            self.parse_expr()?
+        };
 
-        Ok(Expr::If {
-            cond: Box::new(cond),
-            then_branch: Box::new(then_branch),
-            else_branch: Box::new(else_branch),
-        })
+        self.node(
+            start,
+            Node::If {
+                cond,
+                then_branch,
+                else_branch,
+            },
+        )
+    }
+
+    fn parse_match(&mut self) -> Result<B::Expr, ParseError> {
+        let start = self.pos;
+        self.expect(&Token::Match)?;
+        let scrutinee = self.parse_expr()?;
+        self.expect(&Token::With)?;
+        let mut arms = Vec::new();
+        let mut variants = true;
+        // At least one pattern, then the wildcard arm last, which only a
+        // match over enum variants may leave out
+        let default = loop {
+            variants &= matches!(self.peek(), Some(Token::Ident(_)));
+            let pattern = self.parse_pattern()?;
+            self.expect(&Token::FatArrow)?;
+            arms.push((pattern, self.parse_expr()?));
+            if variants && !matches!(self.peek(), Some(Token::Comma)) {
+                break None;
+            }
+            self.expect(&Token::Comma)?;
+            if matches!(self.peek(), Some(Token::Ident(name)) if name.as_str() == "_") {
+                self.advance();
+                self.expect(&Token::FatArrow)?;
+                break Some(self.parse_expr()?);
+            }
+        };
+        let node = Node::Match {
+            scrutinee,
+            arms,
+            default,
+        };
+        self.node(start, node)
+    }
+
+    /// A literal or enum variant pattern of a `match` arm.
+    fn parse_pattern(&mut self) -> Result<B::Expr, ParseError> {
+        let start = self.pos;
+        let node = match (self.peek(), self.tokens.get(self.pos + 1)) {
+            (Some(Token::Int(n)), _) => Node::Int(*n),
+            (Some(Token::Sized(n, width)), _) => Node::Sized(*n, *width),
+            (Some(Token::Bool(b)), _) => Node::Bool(*b),
+            (Some(Token::Ident(name)), _) if name.as_str() != "_" => Node::Var(*name),
+            (Some(Token::Minus), Some(Token::Int(n))) => {
+                let n = -*n;
+                self.advance();
+                Node::Int(n)
+            }
+            (Some(Token::Minus), Some(&Token::Sized(n, width))) if width.is_signed() => {
+                self.advance();
+                Node::Sized(width.wrap(-width.value(n)), width)
+            }
+            (Some(t), _) => return Err(self.unexpected(t, "literal or variant pattern")),
+            (None, _) => return Err(self.eof()),
+        };
+        self.advance();
+        self.node(start, node)
     }
 
-    fn parse_or(&mut self) -> Result<Expr, ParseError> {
+    fn parse_or(&mut self) -> Result<B::Expr, ParseError> {
+        let start = self.pos;
         let mut left = self.parse_and()?;
 
         while matches!(self.peek(), Some(Token::Or)) {
             self.advance();
             let right = self.parse_and()?;
-            left = Expr::BinOp {
-                op: BinOp::Or,
-                left: Box::new(left),
-                right: Box::new(right),
-            };
+            left = self.node(
+                start,
+                Node::BinOp {
+                    op: BinOp::Or,
+                    left,
+                    right,
+                },
+            )?;
         }
 
         Ok(left)
     }
 
-    fn parse_and(&mut self) -> Result<Expr, ParseError> {
+    fn parse_and(&mut self) -> Result<B::Expr, ParseError> {
+        let start = self.pos;
         let mut left = self.parse_cmp()?;
 
         while matches!(self.peek(), Some(Token::And)) {
             self.advance();
             let right = self.parse_cmp()?;
-            left = Expr::BinOp {
-                op: BinOp::And,
-                left: Box::new(left),
-                right: Box::new(right),
-            };
+            left = self.node(
+                start,
+                Node::BinOp {
+                    op: BinOp::And,
+                    left,
+                    right,
+                },
+            )?;
         }
 
         Ok(left)
     }
 
-    fn parse_cmp(&mut self) -> Result<Expr, ParseError> {
-        let left = self.parse_add()?;
+    fn parse_cmp(&mut self) -> Result<B::Expr, ParseError> {
+        let start = self.pos;
+        let left = self.parse_bits(0)?;
 
         let op = match self.peek() {
             Some(Token::Eq) => BinOp::Eq,
@@ -156,105 +906,380 @@ fn parse_cmp(&mut self) -> Result<Expr, ParseError> {
         };
 
         self.advance();
-        let right = self.parse_add()?;
+        let right = self.parse_bits(0)?;
 
-        Ok(Expr::BinOp {
-            op,
-            left: Box::new(left),
-            right: Box::new(right),
-        })
+        self.node(
+            start,
+            Node::BinOp { op, left, right },
+        )
     }
 
-    fn parse_add(&mut self) -> Result<Expr, ParseError> {
+    /// The bitwise operators, `|` at `level` 0, then `^`, `&` and the
+    /// shifts at 3, as in Rust.
+    fn parse_bits(&mut self, level: u8) -> Result<B::Expr, ParseError> {
+        let operand = |parser: &mut Self| match level {
+            3 => parser.parse_add(),
+            _ => parser.parse_bits(level + 1),
+        };
+        let start = self.pos;
+        let mut left = operand(self)?;
+
+        loop {
+            let op = match (level, self.peek()) {
+                (0, Some(Token::Pipe)) => BinOp::BitOr,
+                (1, Some(Token::Caret)) => BinOp::BitXor,
+                (2, Some(Token::Amp)) => BinOp::BitAnd,
+                (3, Some(Token::Shl)) => BinOp::Shl,
+                (3, Some(Token::Shr)) => BinOp::Shr,
+                _ => break,
+            };
+            self.advance();
+            let right = operand(self)?;
+            left = self.node(start, Node::BinOp { op, left, right })?;
+        }
+
+        Ok(left)
+    }
+
+    fn parse_add(&mut self) -> Result<B::Expr, ParseError> {
+        let start = self.pos;
         let mut left = self.parse_mul()?;
 
         loop {
             let op = match self.peek() {
+                Some(Token::Plus) if self.saturating => BinOp::SatAdd,
+                Some(Token::Minus) if self.saturating => BinOp::SatSub,
                 Some(Token::Plus) => BinOp::Add,
                 Some(Token::Minus) => BinOp::Sub,
+                Some(Token::SatPlus) => BinOp::SatAdd,
+                Some(Token::SatMinus) => BinOp::SatSub,
                 _ => break,
             };
             self.advance();
             let right = self.parse_mul()?;
-            left = Expr::BinOp {
-                op,
-                left: Box::new(left),
-                right: Box::new(right),
-            };
+            left = self.node(
+                start,
+                Node::BinOp { op, left, right },
+            )?;
         }
 
         Ok(left)
     }
 
-    fn parse_mul(&mut self) -> Result<Expr, ParseError> {
-        let mut left = self.parse_unary()?;
+    fn parse_mul(&mut self) -> Result<B::Expr, ParseError> {
+        let start = self.pos;
+        let mut left = self.parse_cast()?;
 
         loop {
             let op = match self.peek() {
+                Some(Token::Star) if self.saturating => BinOp::SatMul,
                 Some(Token::Star) => BinOp::Mul,
+                Some(Token::SatStar) => BinOp::SatMul,
                 Some(Token::Slash) => BinOp::Div,
                 Some(Token::Percent) => BinOp::Mod,
                 _ => break,
             };
             self.advance();
-            let right = self.parse_unary()?;
-            left = Expr::BinOp {
-                op,
-                left: Box::new(left),
-                right: Box::new(right),
-            };
+            let right = self.parse_cast()?;
+            left = self.node(
+                start,
+                Node::BinOp { op, left, right },
+            )?;
         }
 
         Ok(left)
     }
 
-    fn parse_unary(&mut self) -> Result<Expr, ParseError> {
-        match self.peek() {
-            Some(Token::Minus) => {
-                self.advance();
-                let expr = self.parse_unary()?;
-                Ok(Expr::UnaryOp {
-                    op: UnaryOp::Neg,
-                    expr: Box::new(expr),
-                })
-            }
-            Some(Token::Not) => {
+    /// `-x as u8` casts `-x`, and `x as u8 as int` casts twice.
+    fn parse_cast(&mut self) -> Result<B::Expr, ParseError> {
+        let start = self.pos;
+        let mut expr = self.parse_unary()?;
+        while matches!(self.peek(), Some(Token::As)) {
+            self.advance();
+            let ty = match self.peek() {
+                Some(t @ Token::Ident(word)) if word.as_str() == "bool" => {
+                    return Err(self.unexpected(t, "an integer type"))
+                }
+                _ => self.parse_scalar()?,
+            };
+            expr = self.node(start, Node::Cast { expr, ty })?;
+        }
+        Ok(expr)
+    }
+
+    fn parse_unary(&mut self) -> Result<B::Expr, ParseError> {
+        let start = self.pos;
+        let op = match self.peek() {
+            Some(Token::Minus) if self.saturating => {
+                // `-x` is `0 -| x`, the zero spanning the minus sign
                 self.advance();
-                let expr = self.parse_unary()?;
-                Ok(Expr::UnaryOp {
-                    op: UnaryOp::Not,
-                    expr: Box::new(expr),
-                })
+                let left = self.node(start, Node::Int(0))?;
+                let right = self.nested(Self::parse_unary)?;
+                let op = BinOp::SatSub;
+                return self.node(start, Node::BinOp { op, left, right });
             }
-            _ => self.parse_primary(),
+            Some(Token::Minus) => UnaryOp::Neg,
+            Some(Token::Not) => UnaryOp::Not,
+            _ => return self.parse_postfix(),
+        };
+        self.advance();
+        let expr = self.nested(Self::parse_unary)?;
+        self.node(
+            start,
+            Node::UnaryOp { op, expr },
+        )
+    }
+
+    /// A primary expression followed by any number of `[index]`s.
+    fn parse_postfix(&mut self) -> Result<B::Expr, ParseError> {
+        let start = self.pos;
+        let mut array = self.parse_primary()?;
+        while matches!(self.peek(), Some(Token::LBracket)) {
+            self.advance();
+            let index = self.parse_expr()?;
+            self.expect(&Token::RBracket)?;
+            array = self.node(start, Node::Index { array, index })?;
         }
+        Ok(array)
     }
 
-    fn parse_primary(&mut self) -> Result<Expr, ParseError> {
-        match self.advance() {
-            Some(Token::Int(n)) => Ok(Expr::Int(*n)),
-            Some(Token::Bool(b)) => Ok(Expr::Bool(*b)),
-            Some(Token::Ident(name)) => Ok(Expr::Var(name.clone())),
+    fn parse_primary(&mut self) -> Result<B::Expr, ParseError> {
+        let start = self.pos;
+        let expr = match self.peek() {
+            Some(Token::Ident(name))
+                if self.functions.contains(name)
+                    && matches!(self.tokens.get(self.pos + 1), Some(Token::LParen)) =>
+            {
+                let name = *name;
+                self.pos += 2;
+                let mut args = Vec::new();
+                if !matches!(self.peek(), Some(Token::RParen)) {
+                    args.push(self.parse_expr()?);
+                    while matches!(self.peek(), Some(Token::Comma)) {
+                        self.advance();
+                        args.push(self.parse_expr()?);
+                    }
+                }
+                self.expect(&Token::RParen)?;
+                return self.node(start, Node::Call { name, args });
+            }
+            Some(Token::Ident(name))
+                if self.unbounded.contains(name)
+                    && matches!(self.tokens.get(self.pos + 1), Some(Token::LParen)) =>
+            {
+                let span = self.token_span(self.pos);
+                return Err(ParseError::UnboundedRecursion(*name, span));
+            }
+            Some(Token::Int(n)) => Node::Int(*n),
+            Some(Token::Sized(n, width)) => Node::Sized(*n, *width),
+            Some(Token::Bool(b)) => Node::Bool(*b),
+            Some(Token::Ident(name)) => Node::Var(*name),
+            Some(Token::Bytes(bytes)) => Node::Bytes(bytes.clone()),
+            Some(Token::None) => Node::None,
             Some(Token::Secret) => {
+                self.advance();
                 self.expect(&Token::LParen)?;
                 let expr = self.parse_expr()?;
                 self.expect(&Token::RParen)?;
-                Ok(Expr::Secret(Box::new(expr)))
+                return self.node(start, Node::Secret(expr));
+            }
+            Some(Token::Declassify) => {
+                self.advance();
+                self.expect(&Token::LParen)?;
+                let expr = self.parse_expr()?;
+                self.expect(&Token::RParen)?;
+                return self.node(start, Node::Declassify(expr));
+            }
+            Some(Token::Sort) => {
+                self.advance();
+                self.expect(&Token::LParen)?;
+                let expr = self.parse_expr()?;
+                self.expect(&Token::RParen)?;
+                return self.node(start, Node::Sort(expr));
+            }
+            Some(Token::SwapIf) => {
+                self.advance();
+                self.expect(&Token::LParen)?;
+                let cond = self.parse_expr()?;
+                self.expect(&Token::Comma)?;
+                let left = self.parse_expr()?;
+                self.expect(&Token::Comma)?;
+                let right = self.parse_expr()?;
+                self.expect(&Token::RParen)?;
+                return self.node(start, Node::SwapIf { cond, left, right });
+            }
+            Some(token @ (Token::ToInt | Token::ToBool)) => {
+                let op = if *token == Token::ToInt { UnaryOp::ToInt } else { UnaryOp::ToBool };
+                self.advance();
+                self.expect(&Token::LParen)?;
+                let expr = self.parse_expr()?;
+                self.expect(&Token::RParen)?;
+                return self.node(start, Node::UnaryOp { op, expr });
+            }
+            Some(Token::Pow) => {
+                self.advance();
+                self.expect(&Token::LParen)?;
+                let base = self.parse_expr()?;
+                self.expect(&Token::Comma)?;
+                let exp = self.parse_expr()?;
+                self.expect(&Token::RParen)?;
+                return self.node(start, Node::Pow { base, exp });
+            }
+            Some(Token::Map) => {
+                self.advance();
+                self.expect(&Token::LParen)?;
+                let array = self.parse_expr()?;
+                self.expect(&Token::Comma)?;
+                let f = self.parse_expr()?;
+                self.expect(&Token::RParen)?;
+                return self.node(start, Node::Map { array, f });
+            }
+            Some(Token::Fold) => {
+                self.advance();
+                self.expect(&Token::LParen)?;
+                let array = self.parse_expr()?;
+                self.expect(&Token::Comma)?;
+                let init = self.parse_expr()?;
+                self.expect(&Token::Comma)?;
+                let f = self.parse_expr()?;
+                self.expect(&Token::RParen)?;
+                return self.node(start, Node::Fold { array, init, f });
+            }
+            Some(Token::Some) => {
+                self.advance();
+                self.expect(&Token::LParen)?;
+                let expr = self.parse_expr()?;
+                self.expect(&Token::RParen)?;
+                return self.node(start, Node::Some(expr));
+            }
+            Some(Token::UnwrapOr) => {
+                self.advance();
+                self.expect(&Token::LParen)?;
+                let option = self.parse_expr()?;
+                self.expect(&Token::Comma)?;
+                let default = self.parse_expr()?;
+                self.expect(&Token::RParen)?;
+                return self.node(start, Node::UnwrapOr { option, default });
+            }
+            Some(token @ (Token::Clamp | Token::InRange)) => {
+                let clamp = *token == Token::Clamp;
+                self.advance();
+                self.expect(&Token::LParen)?;
+                let value = self.parse_expr()?;
+                self.expect(&Token::Comma)?;
+                let lo = self.parse_expr()?;
+                self.expect(&Token::Comma)?;
+                let hi = self.parse_expr()?;
+                self.expect(&Token::RParen)?;
+                let node = if clamp {
+                    Node::Clamp { value, lo, hi }
+                } else {
+                    Node::InRange { value, lo, hi }
+                };
+                return self.node(start, node);
+            }
+            Some(Token::Saturating) => {
+                self.advance();
+                self.expect(&Token::LParen)?;
+                let outer = std::mem::replace(&mut self.saturating, true);
+                let expr = self.parse_expr();
+                self.saturating = outer;
+                let expr = expr?;
+                self.expect(&Token::RParen)?;
+                return Ok(expr);
             }
             Some(Token::LParen) => {
+                self.advance();
                 let expr = self.parse_expr()?;
+                if !matches!(self.peek(), Some(Token::Comma)) {
+                    self.expect(&Token::RParen)?;
+                    return Ok(expr);
+                }
+                let mut elements = vec![expr];
+                while matches!(self.peek(), Some(Token::Comma)) {
+                    self.advance();
+                    elements.push(self.parse_expr()?);
+                }
                 self.expect(&Token::RParen)?;
-                Ok(expr)
+                return self.node(start, Node::Tuple(elements));
             }
-            Some(t) => Err(ParseError::UnexpectedToken(t.clone(), "expression")),
-            None => Err(ParseError::UnexpectedEof),
-        }
+            Some(Token::LBracket) => {
+                self.advance();
+                let mut elements = vec![self.parse_expr()?];
+                while matches!(self.peek(), Some(Token::Comma)) {
+                    self.advance();
+                    elements.push(self.parse_expr()?);
+                }
+                self.expect(&Token::RBracket)?;
+                return self.node(start, Node::Array(elements));
+            }
+            Some(Token::Lookup) => {
+                self.advance();
+                self.expect(&Token::LParen)?;
+                let key = self.parse_expr()?;
+                self.expect(&Token::Comma)?;
+                self.expect(&Token::LBracket)?;
+                let mut entries = vec![self.parse_entry()?];
+                while matches!(self.peek(), Some(Token::Comma)) {
+                    self.advance();
+                    entries.push(self.parse_entry()?);
+                }
+                self.expect(&Token::RBracket)?;
+                self.expect(&Token::Comma)?;
+                let default = self.parse_expr()?;
+                self.expect(&Token::RParen)?;
+                let node = Node::Lookup {
+                    key,
+                    entries,
+                    default,
+                };
+                return self.node(start, node);
+            }
+            Some(Token::Outputs) => {
+                self.advance();
+                self.expect(&Token::LParen)?;
+                let mut outputs = vec![self.parse_output()?];
+                while matches!(self.peek(), Some(Token::Comma)) {
+                    self.advance();
+                    outputs.push(self.parse_output()?);
+                }
+                self.expect(&Token::RParen)?;
+                return self.node(start, Node::Outputs(outputs));
+            }
+            Some(t) => return Err(self.unexpected(t, "expression")),
+            None => return Err(self.eof()),
+        };
+        self.advance();
+        self.node(start, expr)
+    }
+
+    /// A `(key, value)` entry of a `lookup` table.
+    fn parse_entry(&mut self) -> Result<(B::Expr, B::Expr), ParseError> {
+        self.expect(&Token::LParen)?;
+        let key = self.parse_expr()?;
+        self.expect(&Token::Comma)?;
+        let value = self.parse_expr()?;
+        self.expect(&Token::RParen)?;
+        Ok((key, value))
+    }
+
+    /// A `name = value` field of `outputs`.
+    fn parse_output(&mut self) -> Result<(Symbol, B::Expr), ParseError> {
+        let name = match self.peek() {
+            Some(Token::Ident(n)) => *n,
+            Some(t) => return Err(self.unexpected(t, "identifier")),
+            None => return Err(self.eof()),
+        };
+        self.advance();
+        self.expect(&Token::Assign)?;
+        Ok((name, self.parse_expr()?))
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::ast::Expr;
     use crate::lexer::Lexer;
 
     fn parse(input: &str) -> Result<Expr, ParseError> {
@@ -264,6 +1289,23 @@ fn parse(input: &str) -> Result<Expr, ParseError> {
         parser.parse()
     }
 
+    #[test]
+    fn test_program_statements() {
+        let src = "let x = 1; let (a, b) = (x, 2)\nfn f(y) = y * a; -f(b);";
+        let tokens: Vec<_> = Lexer::new(src).filter_map(Result::ok).collect();
+        let program = Parser::new(&tokens).parse_program().unwrap();
+        assert_eq!(program.statements.len(), 3);
+        assert_eq!(program.result.to_string(), "-f(b)");
+        let printed = "let x = 1;\nlet (a, b) = (x, 2);\nfn f(y) = y * a;\n-f(b)";
+        assert_eq!(program.to_string(), printed);
+        // Without the `;`, the result continues the bound value
+        assert!(parse("let x = 1 -x").is_err());
+        assert!(matches!(
+            parse("1 2"),
+            Err(ParseError::UnexpectedToken(Token::Int(2), "end of input", _))
+        ));
+    }
+
     #[test]
     fn test_simple_expr() {
         let expr = parse("1 + 2").unwrap();
@@ -282,9 +1324,258 @@ fn test_if_then_else() {
         assert!(matches!(expr, Expr::If { .. }));
     }
 
+    #[test]
+    fn test_elif_chains() {
+        let expr = parse("if a then 1 elif b then 2 elif c then 3 else 4").unwrap();
+        assert_eq!(expr, parse("if a then 1 else if b then 2 else if c then 3 else 4").unwrap());
+        assert!(parse("if a then 1 elif b then 2").is_err());
+        let src = "if a then 1 elif b then 2 else 3";
+        let (tokens, spans) = Lexer::new(src).tokenize().unwrap();
+        let (_, node_spans) = Parser::with_spans(&tokens, &spans).parse_with_spans().unwrap();
+        let elif = node_spans.iter().nth(5).unwrap();
+        assert_eq!(&src[elif.start..elif.end], "elif b then 2 else 3");
+    }
+
     #[test]
     fn test_let() {
         let expr = parse("let x = 1 x + 1").unwrap();
-        assert!(matches!(expr, Expr::Let { .. }));
+        assert!(matches!(expr, Expr::Let { ty: None, .. }));
+        let expr = parse("let k: secret int = 1 let p: pub bool = true let n: int = k n").unwrap();
+        assert_eq!(
+            expr.to_string(),
+            "let k: secret int = 1 (let p: pub bool = true (let n: int = k n))"
+        );
+        assert!(parse("let x: secret = 1 x").is_err());
+        assert!(parse("let x: [int; 2] = [1, 2] x").is_err());
+    }
+
+    #[test]
+    fn test_assignments() {
+        let expr = parse("let mut x = 1; x = x + 1; if x > 1 then x = 0; x").unwrap();
+        assert_eq!(
+            expr.to_string(),
+            "let mut x = 1 (x = (x + 1) (if (x > 1) then x = 0 x))"
+        );
+        // `mut` is a word only before the name
+        let expr = parse("let mut = 1 mut").unwrap();
+        assert!(matches!(expr, Expr::Let { mutable: false, .. }));
+        assert!(parse("if b then x = 1 else x = 2; x").is_err());
+    }
+
+    #[test]
+    fn test_functions() {
+        let expr = parse("fn add(x, y) = x + y add(1, add(2, 3)) * 2").unwrap();
+        assert!(matches!(&expr, Expr::Fn { params, .. } if params.len() == 2));
+        assert_eq!(expr.to_string(), "fn add(x, y) = (x + y) (add(1, add(2, 3)) * 2)");
+        assert!(parse("fn zero() = 0 zero()").is_ok());
+        // Only defined functions are called
+        let expr = parse("let y = x (x + 1)").unwrap();
+        assert!(matches!(expr, Expr::Let { value, .. } if *value == Expr::Var("x".into())));
+        let expr = parse("fn f(x) = x let y = f (1) y").unwrap();
+        assert_eq!(expr.to_string(), "fn f(x) = x (let y = f(1) y)");
+    }
+
+    #[test]
+    fn test_lambdas() {
+        let expr = parse("let f = fn(x) => x + 1 f(2)").unwrap();
+        assert_eq!(expr.to_string(), "let f = (fn(x) => (x + 1)) f(2)");
+        // Parameters are callable inside the body
+        let expr = parse("fn apply(g, x) = g(x) apply(fn(y) => y * 2, 3)").unwrap();
+        assert_eq!(expr.to_string(), "fn apply(g, x) = g(x) apply(fn(y) => (y * 2), 3)");
+        // A lambda bound by `let` is not callable in its own body
+        let expr = parse("let f = fn(x) => f (x)").unwrap();
+        assert_eq!(expr.to_string(), "let f = (fn(x) => f) x");
+        assert!(parse("fn(x) x").is_err());
+    }
+
+    #[test]
+    fn test_constants() {
+        let expr = parse("const n = 2 * 2; for i in 0..n with s = 0 do s + i").unwrap();
+        assert_eq!(expr.to_string(), "const n = (2 * 2) (for i in 0..n with s = 0 do (s + i))");
+        assert!(parse("const = 1 1").is_err());
+        assert!(parse("let const = 1 1").is_err());
+    }
+
+    #[test]
+    fn test_recursive_functions() {
+        let expr = parse("#[max_depth(3)] fn f(n) = if n == 0 then 0 else f(n - 1) f(5)").unwrap();
+        assert!(matches!(&expr, Expr::Fn { max_depth: Some(3), .. }));
+        assert_eq!(
+            expr.to_string(),
+            "#[max_depth(3)] fn f(n) = (if (n == 0) then 0 else f(n - 1)) f(5)"
+        );
+        let err = parse("fn f(n) = f(n - 1) f(5)").unwrap_err();
+        assert!(matches!(err, ParseError::UnboundedRecursion(name, _) if name.as_str() == "f"));
+        assert!(parse("#[max_depth(0)] fn f(n) = n f(5)").is_err());
+        assert!(parse("#[max_depth(2)] fn(n) => n").is_err());
+        // A `#` not before `[` still starts a comment
+        assert_eq!(parse("# [note]\n1").unwrap(), Expr::Int(1));
+    }
+
+    #[test]
+    fn test_for_loops() {
+        let expr = parse("for i in 0..n - 1 with acc = 0 do acc + i").unwrap();
+        assert!(matches!(&expr, Expr::For { end, .. } if matches!(**end, Expr::BinOp { .. })));
+        assert_eq!(expr.to_string(), "for i in 0..(n - 1) with acc = 0 do (acc + i)");
+        assert!(parse("for i in 0 with acc = 0 do acc").is_err());
+        let expr = parse("while n < 100 with n = 1 do n * 2").unwrap();
+        assert_eq!(expr.to_string(), "while (n < 100) with n = 1 do (n * 2)");
+    }
+
+    #[test]
+    fn test_tuples() {
+        let expr = parse("let (q, r) = (7 / 2, secret(7) % 2) q + r").unwrap();
+        assert!(matches!(&expr, Expr::LetTuple { names, .. } if names.len() == 2));
+        assert_eq!(expr.to_string(), "let (q, r) = (7 / 2, secret(7) % 2) (q + r)");
+        assert_eq!(parse("(1)").unwrap(), Expr::Int(1));
+        assert!(parse("let (x) = 1 x").is_err());
+    }
+
+    #[test]
+    fn test_swap_if() {
+        let expr = parse("let (lo, hi) = swap_if(a > b, a, b) hi - lo").unwrap();
+        assert_eq!(expr.to_string(), "let (lo, hi) = swap_if(a > b, a, b) (hi - lo)");
+        assert!(parse("swap_if(c, a)").is_err());
+    }
+
+    #[test]
+    fn test_clamp_and_in_range() {
+        let expr = parse("clamp(x + 1, 0, n) + in_range(x, 0, 9)").unwrap();
+        assert_eq!(expr.to_string(), "clamp(x + 1, 0, n) + in_range(x, 0, 9)");
+        assert!(parse("clamp(x, 0)").is_err());
+        assert!(parse("let in_range = 1 in_range").is_err());
+    }
+
+    #[test]
+    fn test_casts() {
+        let expr = parse("-x as u8 * 2 + y as u16 as int").unwrap();
+        assert_eq!(expr.to_string(), "(((-x) as u8) * 2) + ((y as u16) as int)");
+        assert!(parse("x as bool").is_err());
+        assert!(parse("x as").is_err());
+    }
+
+    #[test]
+    fn test_to_int_and_to_bool() {
+        let expr = parse("to_int(a > b) * 2 + to_int(to_bool(n))").unwrap();
+        assert_eq!(expr.to_string(), "(to_int(a > b) * 2) + to_int(to_bool(n))");
+        assert!(parse("to_int a").is_err());
+    }
+
+    #[test]
+    fn test_pow() {
+        let expr = parse("pow(x + 1, n) * pow(2, 3)").unwrap();
+        assert_eq!(expr.to_string(), "pow(x + 1, n) * pow(2, 3)");
+        assert!(parse("pow(x)").is_err());
+        assert!(parse("x ** 2").is_err());
+    }
+
+    #[test]
+    fn test_map_and_fold() {
+        let source = "fn sq(x) = x * x fold(map(a, sq), 0, fn(s, x) => s + x)";
+        let expr = parse(source).unwrap();
+        assert_eq!(expr.to_string(), "fn sq(x) = (x * x) fold(map(a, sq), 0, fn(s, x) => (s + x))");
+        assert!(parse("map(a)").is_err());
+        assert!(parse("fold(a, fn(s, x) => s)").is_err());
+    }
+
+    #[test]
+    fn test_options() {
+        let expr = parse("unwrap_or(if c then some(x + 1) else none, 0) + 1").unwrap();
+        assert_eq!(expr.to_string(), "unwrap_or(if c then some(x + 1) else none, 0) + 1");
+        assert!(parse("some(1, 2)").is_err());
+        assert!(parse("unwrap_or(o)").is_err());
+        assert!(parse("let none = 1 none").is_err());
+    }
+
+    #[test]
+    fn test_assert() {
+        let expr = parse("assert(x > 0); assert(y) x + y").unwrap();
+        assert_eq!(expr.to_string(), "assert(x > 0) (assert(y) (x + y))");
+        assert!(parse("assert x > 0 x").is_err());
+    }
+
+    #[test]
+    fn test_input_and_output_declarations() {
+        let expr = parse("input secret k: int; input n: u8 let m = k * 2 output m output n");
+        assert_eq!(
+            expr.unwrap().to_string(),
+            "input secret k: int (input n: u8 (let m = (k * 2) outputs(m = m, n = n)))"
+        );
+        // Words only where they start a declaration
+        let expr = parse("let input = 1 let output = input + 1 output").unwrap();
+        assert_eq!(expr.to_string(), "let input = 1 (let output = (input + 1) output)");
+        assert_eq!(parse("input pub: int pub").unwrap().to_string(), "input pub: int pub");
+        assert!(parse("input k k").is_err());
+        assert!(parse("input k: secret int k").is_err());
+    }
+
+    #[test]
+    fn test_match() {
+        let expr = parse("match x with 0 => 10, -1 => y + 1, _ => match y with true => 1, _ => 2");
+        assert_eq!(
+            expr.unwrap().to_string(),
+            "match x with 0 => 10, -1 => (y + 1), _ => (match y with true => 1, _ => 2)"
+        );
+        assert!(parse("match x with _ => 1").is_err());
+        assert!(parse("match x with y => 1, 0 => 2").is_err());
+        assert!(parse("match x with 0 => 1").is_err());
+        assert!(parse("match x with A => 1, 0").is_err());
+    }
+
+    #[test]
+    fn test_enum() {
+        let expr = parse("enum Color = Red | Green; match c with Red => 1, Green => 2");
+        assert_eq!(
+            expr.unwrap().to_string(),
+            "enum Color = Red | Green (match c with Red => 1, Green => 2)"
+        );
+        let expr = parse("enum C = A | B match c with A => 1, _ => 2").unwrap();
+        assert_eq!(expr.to_string(), "enum C = A | B (match c with A => 1, _ => 2)");
+        assert!(parse("enum C = A | 1").is_err());
+        assert!(parse("enum C A").is_err());
+    }
+
+    #[test]
+    fn test_node_spans_post_order() {
+        let src = "let x = secret(1) x + 2";
+        let (tokens, spans) = Lexer::new(src).tokenize().unwrap();
+        let mut parser = Parser::with_spans(&tokens, &spans);
+        let (_, node_spans) = parser.parse_with_spans().unwrap();
+        let texts: Vec<_> = node_spans
+            .iter()
+            .map(|s| &src[s.start..s.end])
+            .collect();
+        assert_eq!(
+            texts,
+            vec!["1", "secret(1)", "x", "2", "x + 2", "let x = secret(1) x + 2"]
+        );
+    }
+
+    #[test]
+    fn test_saturating() {
+        let block = parse("saturating(-x * 2 + y) - 1").unwrap();
+        assert_eq!(block, parse("(0 -| x) *| 2 +| y - 1").unwrap());
+        assert_eq!(block.to_string(), "(((0 -| x) *| 2) +| y) - 1");
+
+        let src = "saturating(-x)";
+        let (tokens, spans) = Lexer::new(src).tokenize().unwrap();
+        let (_, node_spans) = Parser::with_spans(&tokens, &spans).parse_with_spans().unwrap();
+        let texts: Vec<_> = node_spans.iter().map(|s| &src[s.start..s.end]).collect();
+        assert_eq!(texts, ["-", "x", "-x"]);
+    }
+
+    #[test]
+    fn test_bitwise_precedence() {
+        let expr = parse("x | y ^ z & 1 << 2 + 3 == x").unwrap();
+        assert_eq!(expr.to_string(), "(x | (y ^ (z & (1 << (2 + 3))))) == x");
+        assert_eq!(parse("a >> 1 >> 2").unwrap().to_string(), "(a >> 1) >> 2");
+    }
+
+    #[test]
+    fn test_error_span() {
+        let src = "let 1 = 2 3";
+        let (tokens, spans) = Lexer::new(src).tokenize().unwrap();
+        let err = Parser::with_spans(&tokens, &spans).parse().unwrap_err();
+        assert_eq!(err.span(), Span::new(4, 5));
     }
 }
@@ -0,0 +1,846 @@
+// SPDX-License-Identifier: MIT OR AGPL-3.0-or-later
+// SPDX-FileCopyrightText: 2024-2025 hyperpolymath
+
+//! IR-to-IR optimization passes.
+//!
+//! Passes run after [`to_oblivious`](crate::transform::to_oblivious) and
+//! must preserve both the program's value and its obliviousness: they may
+//! only simplify public computation, or secret computation in a way that
+//! does not depend on secret data.
+//!
+//! With the `debug-invariants` feature, [`Pipeline::run`] checks the output
+//! of every pass with the [conformance suite](crate::conformance) and
+//! panics with a dump of the IR before and after the pass that broke it.
+
+use crate::interp::{apply_binop, apply_cast, apply_unaryop, Value};
+use crate::ir::{ObliBinOp, ObliExpr};
+use crate::stack;
+use crate::symbol::{Scope, Symbol};
+use crate::transform::mark_as_secret;
+use std::collections::HashMap;
+use std::fmt;
+
+/// An IR rewrite.
+pub trait Pass: Send + Sync {
+    /// Stable name used to enable or disable the pass.
+    fn name(&self) -> &'static str;
+
+    fn run(&self, expr: ObliExpr) -> ObliExpr;
+}
+
+/// Names of the built-in passes, in their standard order.
+pub const BUILTIN_PASSES: &[&str] = &["const-fold", "select-simplify", "const-tables"];
+
+/// Look up a built-in pass by name.
+pub fn builtin(name: &str) -> Option<Box<dyn Pass>> {
+    match name {
+        "const-fold" => Some(Box::new(ConstFold)),
+        "select-simplify" => Some(Box::new(SelectSimplify)),
+        "const-tables" => Some(Box::new(ConstTables)),
+        _ => None,
+    }
+}
+
+/// An ordered list of passes.
+#[derive(Default)]
+pub struct Pipeline {
+    passes: Vec<Box<dyn Pass>>,
+}
+
+impl Pipeline {
+    /// An empty pipeline (the IR is emitted exactly as transformed).
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// All built-in passes, in standard order.
+    pub fn standard() -> Self {
+        let mut pipeline = Self::new();
+        for name in BUILTIN_PASSES {
+            pipeline.passes.extend(builtin(name));
+        }
+        pipeline
+    }
+
+    /// Append a pass.
+    pub fn add(&mut self, pass: Box<dyn Pass>) {
+        self.passes.push(pass);
+    }
+
+    /// Append a pass, builder style.
+    pub fn with(mut self, pass: impl Pass + 'static) -> Self {
+        self.add(Box::new(pass));
+        self
+    }
+
+    /// Remove every pass with the given name, returning whether any was removed.
+    pub fn remove(&mut self, name: &str) -> bool {
+        let before = self.passes.len();
+        self.passes.retain(|p| p.name() != name);
+        self.passes.len() != before
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.passes.iter().any(|p| p.name() == name)
+    }
+
+    /// The passes, in the order they run.
+    pub fn passes(&self) -> impl Iterator<Item = &dyn Pass> {
+        self.passes.iter().map(|p| &**p)
+    }
+
+    pub fn names(&self) -> Vec<&'static str> {
+        self.passes.iter().map(|p| p.name()).collect()
+    }
+
+    /// Run every pass in order.
+    pub fn run(&self, expr: ObliExpr) -> ObliExpr {
+        self.passes.iter().fold(expr, |expr, pass| {
+            let _span = tracing::debug_span!("pass", name = pass.name()).entered();
+            #[cfg(feature = "debug-invariants")]
+            let before = expr.clone();
+            let after = pass.run(expr);
+            #[cfg(feature = "debug-invariants")]
+            check_invariants(pass.name(), &before, &after);
+            after
+        })
+    }
+}
+
+/// Panic if pass `name` turned conformant IR into IR that is not.
+#[cfg(feature = "debug-invariants")]
+fn check_invariants(name: &str, before: &ObliExpr, after: &ObliExpr) {
+    let report = crate::conformance::run_all(after);
+    if report.is_conformant() || !crate::conformance::run_all(before).is_conformant() {
+        return;
+    }
+    panic!(
+        "pass `{}` broke IR invariants:\n{}\nIR before the pass:\n{:#?}\n\n\
+         IR after the pass:\n{:#?}",
+        name, report, before, after
+    );
+}
+
+impl fmt::Debug for Pipeline {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.names()).finish()
+    }
+}
+
+/// Fold public operations on literals, and public `if`s on literal conditions.
+pub struct ConstFold;
+
+impl Pass for ConstFold {
+    fn name(&self) -> &'static str {
+        "const-fold"
+    }
+
+    fn run(&self, expr: ObliExpr) -> ObliExpr {
+        fold(expr)
+    }
+}
+
+fn literal(expr: &ObliExpr) -> Option<Value> {
+    match expr {
+        ObliExpr::PubInt(n) => Some(Value::Int(*n)),
+        ObliExpr::PubBool(b) => Some(Value::Bool(*b)),
+        &ObliExpr::Sized {
+            value,
+            width,
+            is_secret: false,
+        } => Some(Value::Sized(value, width)),
+        _ => None,
+    }
+}
+
+fn from_value(value: Value) -> ObliExpr {
+    match value {
+        Value::Int(n) => ObliExpr::PubInt(n),
+        Value::Sized(value, width) => ObliExpr::Sized {
+            value,
+            width,
+            is_secret: false,
+        },
+        Value::Bool(b) => ObliExpr::PubBool(b),
+    }
+}
+
+fn fold(expr: ObliExpr) -> ObliExpr {
+    stack::guard(|| fold_node(expr))
+}
+
+fn fold_node(expr: ObliExpr) -> ObliExpr {
+    match expr {
+        ObliExpr::BinOp {
+            op,
+            left,
+            right,
+            is_secret,
+        } => {
+            let left = fold(*left);
+            let right = fold(*right);
+            if let (false, Some(l), Some(r)) = (is_secret, literal(&left), literal(&right)) {
+                // Division by zero is left for the runtime to report
+                if let Ok(v) = apply_binop(&op, l, r) {
+                    return from_value(v);
+                }
+            }
+            ObliExpr::BinOp {
+                op,
+                left: Box::new(left),
+                right: Box::new(right),
+                is_secret,
+            }
+        }
+        ObliExpr::UnaryOp {
+            op,
+            expr,
+            is_secret,
+        } => {
+            let inner = fold(*expr);
+            if let (false, Some(v)) = (is_secret, literal(&inner)) {
+                if let Ok(v) = apply_unaryop(&op, v) {
+                    return from_value(v);
+                }
+            }
+            ObliExpr::UnaryOp {
+                op,
+                expr: Box::new(inner),
+                is_secret,
+            }
+        }
+        ObliExpr::CtCast {
+            expr,
+            from,
+            to,
+            is_secret,
+        } => {
+            let inner = fold(*expr);
+            if let (false, Some(v)) = (is_secret, literal(&inner)) {
+                if let Ok(v) = apply_cast(to, v) {
+                    return from_value(v);
+                }
+            }
+            ObliExpr::CtCast {
+                expr: Box::new(inner),
+                from,
+                to,
+                is_secret,
+            }
+        }
+        ObliExpr::PubIf {
+            cond,
+            then_branch,
+            else_branch,
+        } => {
+            let cond = fold(*cond);
+            let then_branch = fold(*then_branch);
+            let else_branch = fold(*else_branch);
+            // The taken branch keeps the `if`'s label, e.g. as a CtSelect condition
+            let secret = then_branch.is_secret() || else_branch.is_secret();
+            let label = |branch: ObliExpr| if secret { mark_as_secret(branch) } else { branch };
+            match literal(&cond) {
+                Some(Value::Bool(true)) => label(then_branch),
+                Some(Value::Bool(false)) => label(else_branch),
+                _ => ObliExpr::PubIf {
+                    cond: Box::new(cond),
+                    then_branch: Box::new(then_branch),
+                    else_branch: Box::new(else_branch),
+                },
+            }
+        }
+        ObliExpr::CtSelect {
+            cond,
+            then_val,
+            else_val,
+        } => ObliExpr::CtSelect {
+            cond: Box::new(fold(*cond)),
+            then_val: Box::new(fold(*then_val)),
+            else_val: Box::new(fold(*else_val)),
+        },
+        ObliExpr::Let {
+            name,
+            value,
+            body,
+            is_secret,
+        } => ObliExpr::Let {
+            name,
+            value: Box::new(fold(*value)),
+            body: Box::new(fold(*body)),
+            is_secret,
+        },
+        ObliExpr::Outputs(outputs) => ObliExpr::Outputs(
+            outputs.into_iter().map(|(name, value)| (name, fold(value))).collect(),
+        ),
+        ObliExpr::Table { values, index } => ObliExpr::Table {
+            values,
+            index: Box::new(fold(*index)),
+        },
+        ObliExpr::While {
+            cond,
+            acc,
+            init,
+            body,
+            is_secret,
+        } => ObliExpr::While {
+            cond: Box::new(fold(*cond)),
+            acc,
+            init: Box::new(fold(*init)),
+            body: Box::new(fold(*body)),
+            is_secret,
+        },
+        ObliExpr::CtSwap {
+            cond,
+            left,
+            right,
+            names,
+            body,
+            is_secret,
+        } => ObliExpr::CtSwap {
+            cond: Box::new(fold(*cond)),
+            left: Box::new(fold(*left)),
+            right: Box::new(fold(*right)),
+            names,
+            body: Box::new(fold(*body)),
+            is_secret,
+        },
+        ObliExpr::Assert { cond, body } => {
+            let cond = fold(*cond);
+            let body = fold(*body);
+            match literal(&cond) {
+                Some(Value::Bool(true)) => body,
+                _ => ObliExpr::Assert {
+                    cond: Box::new(cond),
+                    body: Box::new(body),
+                },
+            }
+        }
+        ObliExpr::Declassify(inner) => ObliExpr::Declassify(Box::new(fold(*inner))),
+        leaf => leaf,
+    }
+}
+
+/// Replace `CtSelect` whose arms are identical by that arm. The result no
+/// longer depends on the secret condition, but stays labeled secret.
+pub struct SelectSimplify;
+
+impl Pass for SelectSimplify {
+    fn name(&self) -> &'static str {
+        "select-simplify"
+    }
+
+    fn run(&self, expr: ObliExpr) -> ObliExpr {
+        simplify_selects(expr)
+    }
+}
+
+fn simplify_selects(expr: ObliExpr) -> ObliExpr {
+    stack::guard(|| simplify_node(expr))
+}
+
+fn simplify_node(expr: ObliExpr) -> ObliExpr {
+    match expr {
+        ObliExpr::CtSelect {
+            cond,
+            then_val,
+            else_val,
+        } => {
+            let cond = simplify_selects(*cond);
+            let then_val = simplify_selects(*then_val);
+            let else_val = simplify_selects(*else_val);
+            if then_val == else_val {
+                match then_val {
+                    ObliExpr::PubInt(n) => return ObliExpr::SecretInt(n),
+                    ObliExpr::PubBool(b) => return ObliExpr::SecretBool(b),
+                    ObliExpr::Sized { .. } => return mark_as_secret(then_val),
+                    ref arm if arm.is_secret() => return then_val,
+                    _ => {}
+                }
+            }
+            ObliExpr::CtSelect {
+                cond: Box::new(cond),
+                then_val: Box::new(then_val),
+                else_val: Box::new(else_val),
+            }
+        }
+        ObliExpr::BinOp {
+            op,
+            left,
+            right,
+            is_secret,
+        } => ObliExpr::BinOp {
+            op,
+            left: Box::new(simplify_selects(*left)),
+            right: Box::new(simplify_selects(*right)),
+            is_secret,
+        },
+        ObliExpr::UnaryOp {
+            op,
+            expr,
+            is_secret,
+        } => ObliExpr::UnaryOp {
+            op,
+            expr: Box::new(simplify_selects(*expr)),
+            is_secret,
+        },
+        ObliExpr::CtCast {
+            expr,
+            from,
+            to,
+            is_secret,
+        } => ObliExpr::CtCast {
+            expr: Box::new(simplify_selects(*expr)),
+            from,
+            to,
+            is_secret,
+        },
+        ObliExpr::PubIf {
+            cond,
+            then_branch,
+            else_branch,
+        } => ObliExpr::PubIf {
+            cond: Box::new(simplify_selects(*cond)),
+            then_branch: Box::new(simplify_selects(*then_branch)),
+            else_branch: Box::new(simplify_selects(*else_branch)),
+        },
+        ObliExpr::Let {
+            name,
+            value,
+            body,
+            is_secret,
+        } => ObliExpr::Let {
+            name,
+            value: Box::new(simplify_selects(*value)),
+            body: Box::new(simplify_selects(*body)),
+            is_secret,
+        },
+        ObliExpr::Outputs(outputs) => ObliExpr::Outputs(
+            outputs.into_iter().map(|(name, value)| (name, simplify_selects(value))).collect(),
+        ),
+        ObliExpr::Table { values, index } => ObliExpr::Table {
+            values,
+            index: Box::new(simplify_selects(*index)),
+        },
+        ObliExpr::While {
+            cond,
+            acc,
+            init,
+            body,
+            is_secret,
+        } => ObliExpr::While {
+            cond: Box::new(simplify_selects(*cond)),
+            acc,
+            init: Box::new(simplify_selects(*init)),
+            body: Box::new(simplify_selects(*body)),
+            is_secret,
+        },
+        ObliExpr::CtSwap {
+            cond,
+            left,
+            right,
+            names,
+            body,
+            is_secret,
+        } => ObliExpr::CtSwap {
+            cond: Box::new(simplify_selects(*cond)),
+            left: Box::new(simplify_selects(*left)),
+            right: Box::new(simplify_selects(*right)),
+            names,
+            body: Box::new(simplify_selects(*body)),
+            is_secret,
+        },
+        ObliExpr::Assert { cond, body } => ObliExpr::Assert {
+            cond: Box::new(simplify_selects(*cond)),
+            body: Box::new(simplify_selects(*body)),
+        },
+        ObliExpr::Declassify(inner) => ObliExpr::Declassify(Box::new(simplify_selects(*inner))),
+        leaf => leaf,
+    }
+}
+
+/// Read constant integer tables through [`ObliExpr::Table`] instead of a
+/// chain of selects. A chain qualifies if it scans one variable index
+/// against `0, 1, ...` like a desugared array read, every arm is a
+/// variable bound to an integer literal, and the result is secret. The
+/// emitter defines each table once as a `static`; bindings of literals
+/// that are no longer read are dropped.
+pub struct ConstTables;
+
+impl Pass for ConstTables {
+    fn name(&self) -> &'static str {
+        "const-tables"
+    }
+
+    fn run(&self, expr: ObliExpr) -> ObliExpr {
+        let expr = Tables { scope: Scope::new() }.rewrite(expr);
+        let mut reads = HashMap::new();
+        count_reads(&expr, &mut reads);
+        drop_unread(expr, &mut reads)
+    }
+}
+
+struct Tables {
+    /// The integer literal each variable is bound to, if it is.
+    scope: Scope<Option<i64>>,
+}
+
+impl Tables {
+    fn rewrite(&mut self, expr: ObliExpr) -> ObliExpr {
+        stack::guard(|| self.rewrite_node(expr))
+    }
+
+    fn rewrite_node(&mut self, expr: ObliExpr) -> ObliExpr {
+        if let Some((values, index)) = self.table(&expr) {
+            return ObliExpr::Table {
+                values,
+                index: Box::new(index.clone()),
+            };
+        }
+        let mut boxed = |expr: Box<ObliExpr>| Box::new(self.rewrite(*expr));
+        match expr {
+            ObliExpr::BinOp {
+                op,
+                left,
+                right,
+                is_secret,
+            } => ObliExpr::BinOp {
+                op,
+                left: boxed(left),
+                right: boxed(right),
+                is_secret,
+            },
+            ObliExpr::UnaryOp {
+                op,
+                expr,
+                is_secret,
+            } => ObliExpr::UnaryOp {
+                op,
+                expr: boxed(expr),
+                is_secret,
+            },
+            ObliExpr::CtCast {
+                expr,
+                from,
+                to,
+                is_secret,
+            } => ObliExpr::CtCast {
+                expr: boxed(expr),
+                from,
+                to,
+                is_secret,
+            },
+            ObliExpr::CtSelect {
+                cond,
+                then_val,
+                else_val,
+            } => ObliExpr::CtSelect {
+                cond: boxed(cond),
+                then_val: boxed(then_val),
+                else_val: boxed(else_val),
+            },
+            ObliExpr::PubIf {
+                cond,
+                then_branch,
+                else_branch,
+            } => ObliExpr::PubIf {
+                cond: boxed(cond),
+                then_branch: boxed(then_branch),
+                else_branch: boxed(else_branch),
+            },
+            ObliExpr::Let {
+                name,
+                value,
+                body,
+                is_secret,
+            } => {
+                let value = boxed(value);
+                let literal = self.literal(&value);
+                self.scope.push(name, literal);
+                let body = self.rewrite(*body);
+                self.scope.pop();
+                ObliExpr::Let {
+                    name,
+                    value,
+                    body: Box::new(body),
+                    is_secret,
+                }
+            }
+            ObliExpr::Outputs(outputs) => ObliExpr::Outputs(
+                outputs.into_iter().map(|(name, value)| (name, self.rewrite(value))).collect(),
+            ),
+            ObliExpr::Table { values, index } => ObliExpr::Table {
+                values,
+                index: boxed(index),
+            },
+            ObliExpr::While {
+                cond,
+                acc,
+                init,
+                body,
+                is_secret,
+            } => {
+                // `acc` changes every iteration, so it is never a literal
+                self.scope.push(acc, None);
+                let cond = self.rewrite(*cond);
+                self.scope.pop();
+                let init = self.rewrite(*init);
+                self.scope.push(acc, None);
+                let body = self.rewrite(*body);
+                self.scope.pop();
+                ObliExpr::While {
+                    cond: Box::new(cond),
+                    acc,
+                    init: Box::new(init),
+                    body: Box::new(body),
+                    is_secret,
+                }
+            }
+            ObliExpr::CtSwap {
+                cond,
+                left,
+                right,
+                names,
+                body,
+                is_secret,
+            } => {
+                let (cond, left, right) = (boxed(cond), boxed(left), boxed(right));
+                names.iter().for_each(|name| self.scope.push(*name, None));
+                let body = self.rewrite(*body);
+                names.iter().for_each(|_| self.scope.pop());
+                ObliExpr::CtSwap {
+                    cond,
+                    left,
+                    right,
+                    names,
+                    body: Box::new(body),
+                    is_secret,
+                }
+            }
+            ObliExpr::Assert { cond, body } => ObliExpr::Assert {
+                cond: boxed(cond),
+                body: boxed(body),
+            },
+            ObliExpr::Declassify(inner) => ObliExpr::Declassify(boxed(inner)),
+            leaf => leaf,
+        }
+    }
+
+    fn literal(&self, expr: &ObliExpr) -> Option<i64> {
+        match expr {
+            ObliExpr::PubInt(n) | ObliExpr::SecretInt(n) => Some(*n),
+            ObliExpr::Var { name, .. } => self.scope.get(*name).copied().flatten(),
+            _ => None,
+        }
+    }
+
+    /// The entries and index of a select chain that reads a constant table.
+    fn table<'e>(&self, expr: &'e ObliExpr) -> Option<(Vec<i64>, &'e ObliExpr)> {
+        if !expr.is_secret() {
+            return None;
+        }
+        let entry = |arm: &ObliExpr| match arm {
+            ObliExpr::Var { .. } => self.literal(arm),
+            _ => None,
+        };
+        let mut values = Vec::new();
+        let mut index = None;
+        let mut node = expr;
+        loop {
+            let (cond, then_arm, else_arm) = match node {
+                ObliExpr::CtSelect {
+                    cond,
+                    then_val,
+                    else_val,
+                } => (cond, then_val, else_val),
+                ObliExpr::PubIf {
+                    cond,
+                    then_branch,
+                    else_branch,
+                } => (cond, then_branch, else_branch),
+                _ => break,
+            };
+            let ObliExpr::BinOp {
+                op: ObliBinOp::CtEq,
+                left,
+                right,
+                ..
+            } = &**cond
+            else {
+                break;
+            };
+            let ObliExpr::Var { name, .. } = &**left else { break };
+            if **right != ObliExpr::PubInt(values.len() as i64)
+                || index.is_some_and(|(other, _)| other != *name)
+            {
+                break;
+            }
+            index = Some((*name, &**left));
+            values.push(entry(then_arm)?);
+            node = else_arm;
+        }
+        values.push(entry(node)?);
+        Some((values, index?.1))
+    }
+}
+
+/// How often each variable is read, whichever binding it refers to.
+fn count_reads(expr: &ObliExpr, reads: &mut HashMap<Symbol, usize>) {
+    if let ObliExpr::Var { name, .. } = expr {
+        *reads.entry(*name).or_default() += 1;
+    }
+    for child in expr.children() {
+        stack::guard(|| count_reads(child, reads));
+    }
+}
+
+/// Remove `let`s of literals and variables whose name is never read,
+/// innermost first so that a binding read only by dropped ones goes too.
+fn drop_unread(expr: ObliExpr, reads: &mut HashMap<Symbol, usize>) -> ObliExpr {
+    stack::guard(|| match expr {
+        ObliExpr::Let {
+            name,
+            value,
+            body,
+            is_secret,
+        } => {
+            let body = drop_unread(*body, reads);
+            let trivial = matches!(
+                *value,
+                ObliExpr::PubInt(_)
+                    | ObliExpr::SecretInt(_)
+                    | ObliExpr::Sized { .. }
+                    | ObliExpr::Var { .. }
+            );
+            if trivial && reads.get(&name).is_none_or(|n| *n == 0) {
+                if let ObliExpr::Var { name, .. } = *value {
+                    reads.entry(name).and_modify(|n| *n -= 1);
+                }
+                return body;
+            }
+            ObliExpr::Let {
+                name,
+                value,
+                body: Box::new(body),
+                is_secret,
+            }
+        }
+        ObliExpr::CtSwap {
+            cond,
+            left,
+            right,
+            names,
+            body,
+            is_secret,
+        } => ObliExpr::CtSwap {
+            cond,
+            left,
+            right,
+            names,
+            body: Box::new(drop_unread(*body, reads)),
+            is_secret,
+        },
+        ObliExpr::Assert { cond, body } => ObliExpr::Assert {
+            cond,
+            body: Box::new(drop_unread(*body, reads)),
+        },
+        ObliExpr::Outputs(outputs) => ObliExpr::Outputs(
+            outputs.into_iter().map(|(name, value)| (name, drop_unread(value, reads))).collect(),
+        ),
+        // Every `let` of a desugared array is on the spine of `let`, swap
+        // and assertion bodies and output values
+        expr => expr,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transpile_to_ir;
+
+    #[test]
+    fn test_const_fold_public_only() {
+        let ir = ConstFold.run(transpile_to_ir("1 + 2 * 3").unwrap());
+        assert_eq!(ir, ObliExpr::PubInt(7));
+
+        let ir = ConstFold.run(transpile_to_ir("secret(1) + 2 * 3").unwrap());
+        assert!(matches!(ir, ObliExpr::BinOp { ref right, .. } if **right == ObliExpr::PubInt(6)));
+    }
+
+    #[test]
+    fn test_const_fold_drops_assertions_that_hold() {
+        let ir = ConstFold.run(transpile_to_ir("assert(1 < 2) 3").unwrap());
+        assert_eq!(ir, ObliExpr::PubInt(3));
+        let ir = ConstFold.run(transpile_to_ir("assert(2 < 1) 3").unwrap());
+        assert!(matches!(ir, ObliExpr::Assert { .. }));
+    }
+
+    #[test]
+    fn test_const_fold_keeps_division_by_zero() {
+        let ir = ConstFold.run(transpile_to_ir("1 / 0").unwrap());
+        assert!(matches!(ir, ObliExpr::BinOp { .. }));
+    }
+
+    #[test]
+    fn test_const_fold_keeps_if_secrecy() {
+        let source = "let s = secret(true) if (if true then false else s) then 1 else 2";
+        let ir = ConstFold.run(transpile_to_ir(source).unwrap());
+        let report = crate::conformance::run_all(&ir);
+        assert!(report.is_conformant(), "{}", report);
+    }
+
+    #[test]
+    fn test_select_simplify() {
+        let ir = transpile_to_ir("let k = secret(1) if k > 0 then 5 else 5").unwrap();
+        let ir = SelectSimplify.run(ir);
+        assert!(matches!(ir, ObliExpr::Let { ref body, .. } if **body == ObliExpr::SecretInt(5)));
+    }
+
+    #[test]
+    fn test_const_tables() {
+        use crate::interp::{eval_ir, Inputs};
+        let ir = transpile_to_ir("let t = secret([5, 7, 9]) t[k] + t[1]").unwrap();
+        let tables = ConstTables.run(ir.clone());
+        let entries = |node: &&ObliExpr| match node {
+            ObliExpr::Table { values, .. } => Some(values.clone()),
+            _ => None,
+        };
+        let entries: Vec<_> = tables.post_order().iter().filter_map(entries).collect();
+        assert_eq!(entries, [vec![5, 7, 9]]);
+        for k in [-1, 0, 1, 2, 5] {
+            let inputs = Inputs::new().secret("k", Value::Int(k));
+            assert_eq!(eval_ir(&tables, &inputs), eval_ir(&ir, &inputs));
+        }
+        // A public read of public entries stays a scan
+        let ir = transpile_to_ir("let t = [5, 7, 9] let i = 1 t[i]").unwrap();
+        assert_eq!(ConstTables.run(ir.clone()), ir);
+    }
+
+    #[cfg(feature = "debug-invariants")]
+    #[test]
+    #[should_panic(expected = "pass `leak` broke IR invariants")]
+    fn test_invariants_checked_after_each_pass() {
+        struct Leak;
+        impl Pass for Leak {
+            fn name(&self) -> &'static str {
+                "leak"
+            }
+            fn run(&self, _: ObliExpr) -> ObliExpr {
+                ObliExpr::PubIf {
+                    cond: Box::new(ObliExpr::SecretBool(true)),
+                    then_branch: Box::new(ObliExpr::PubInt(1)),
+                    else_branch: Box::new(ObliExpr::PubInt(2)),
+                }
+            }
+        }
+        Pipeline::standard().with(Leak).run(transpile_to_ir("secret(1) + 2").unwrap());
+    }
+
+    #[test]
+    fn test_pipeline_toggle() {
+        let mut pipeline = Pipeline::standard();
+        assert_eq!(pipeline.names(), BUILTIN_PASSES);
+        assert!(pipeline.remove("const-fold"));
+        assert!(!pipeline.contains("const-fold"));
+        assert!(!pipeline.remove("const-fold"));
+    }
+}
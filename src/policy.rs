@@ -0,0 +1,420 @@
+// SPDX-License-Identifier: MIT OR AGPL-3.0-or-later
+// SPDX-FileCopyrightText: 2024-2025 hyperpolymath
+
+//! Declassification policies.
+//!
+//! A policy lists the results a program may reveal, and optionally which
+//! secrets each may be computed from. Results are the named `outputs` of
+//! the program, or `result` for a program without them; a public result
+//! reveals nothing and needs no entry. Each `declassify(...)` of a secret
+//! reveals it too, and is named after what it is part of: the variable of
+//! the innermost `let` whose value contains it, else the output, else
+//! `result`.
+//!
+//! ```toml
+//! [declassify.total]          # `total` may be revealed
+//! from = ["salary", "bonus"]  # only if computed from these secrets alone
+//!
+//! [declassify.result]         # any secret may flow into it
+//! ```
+//!
+//! The same document can be given as JSON (`{"declassify": {"total":
+//! {"from": ["salary"]}}}`). A secret is a `let` whose value contains a
+//! `secret(...)`; a `secret(...)` used directly in a result belongs to no
+//! name and is only allowed without `from`. Conditions count every secret
+//! the result depends on, including through `if` conditions, but not
+//! through a `declassify(...)`, whose own entry covers what it reveals.
+
+use crate::ast::Expr;
+use crate::codes;
+use crate::desugar;
+use crate::ir::ObliExpr;
+use crate::span::{NodeSpans, Span, SpanCursor};
+use crate::stack;
+use crate::symbol::{Scope, Symbol};
+use serde::Deserialize;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+/// Name of the result of a program without `outputs`.
+pub const RESULT: &str = "result";
+
+#[derive(Error, Debug)]
+pub enum PolicyError {
+    #[error("cannot read {path}: {source}")]
+    Io {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+    #[error("invalid policy: {0}")]
+    Toml(#[from] toml::de::Error),
+    #[error("invalid policy: {0}")]
+    Json(#[from] serde_json::Error),
+}
+
+/// Which results may be revealed.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Policy {
+    pub declassify: BTreeMap<String, Rule>,
+}
+
+/// Conditions on revealing one result.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Rule {
+    /// The only secrets the result may depend on; any if absent.
+    pub from: Option<Vec<String>>,
+}
+
+#[derive(Error, Debug, Clone, PartialEq)]
+pub enum PolicyViolation {
+    #[error("`{name}` is revealed but not declassified by the policy")]
+    Undeclared { name: String, span: Span },
+    #[error("`{name}` depends on `{secret}`, which the policy does not allow it to reveal")]
+    Disallowed {
+        name: String,
+        secret: String,
+        span: Span,
+    },
+}
+
+impl PolicyViolation {
+    /// Catalog code of this violation.
+    pub fn code(&self) -> &'static str {
+        match self {
+            PolicyViolation::Undeclared { .. } => codes::UNDECLARED_REVEAL,
+            PolicyViolation::Disallowed { .. } => codes::DISALLOWED_SECRET,
+        }
+    }
+
+    /// Source span of the revealed result.
+    pub fn span(&self) -> Span {
+        match self {
+            PolicyViolation::Undeclared { span, .. } | PolicyViolation::Disallowed { span, .. } => {
+                *span
+            }
+        }
+    }
+}
+
+impl Policy {
+    /// Load a policy file: JSON if its extension is `.json`, TOML otherwise.
+    pub fn from_path(path: impl AsRef<Path>) -> Result<Self, PolicyError> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path).map_err(|source| PolicyError::Io {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        if path.extension().is_some_and(|ext| ext == "json") {
+            Self::parse_json(&text)
+        } else {
+            Self::parse(&text)
+        }
+    }
+
+    /// Parse a TOML policy.
+    pub fn parse(text: &str) -> Result<Self, PolicyError> {
+        Ok(toml::from_str(text)?)
+    }
+
+    /// Parse a JSON policy.
+    pub fn parse_json(text: &str) -> Result<Self, PolicyError> {
+        Ok(serde_json::from_str(text)?)
+    }
+
+    /// Check the reveals of a typechecked program, given as its AST with
+    /// post-order spans and the IR it transforms to.
+    pub fn check(&self, ast: &Expr, spans: &NodeSpans, ir: &ObliExpr) -> Vec<PolicyViolation> {
+        if desugar::needs_expanding(ast) {
+            let (ast, spans) = desugar::expand_with_spans(ast, spans);
+            return self.check(&ast, &spans, ir);
+        }
+        let mut sources = Sources {
+            spans: SpanCursor::new(Some(spans)),
+            scope: Scope::new(),
+            outputs: Vec::new(),
+            owners: Vec::new(),
+            declassified: Vec::new(),
+        };
+        let (deps, span) = sources.visit(ast);
+        let reveals: Vec<_> = match ir.outputs() {
+            Some(secrecy) => sources
+                .outputs
+                .into_iter()
+                .zip(secrecy)
+                .filter(|(_, (_, value))| value.is_secret())
+                .map(|(output, _)| output)
+                .collect(),
+            None if ir.is_secret() => vec![(Symbol::from(RESULT), deps, span)],
+            None => Vec::new(),
+        };
+
+        let mut violations = Vec::new();
+        for (name, deps, span) in sources.declassified.into_iter().chain(reveals) {
+            let name = name.to_string();
+            let Some(rule) = self.declassify.get(&name) else {
+                violations.push(PolicyViolation::Undeclared { name, span });
+                continue;
+            };
+            let Some(from) = &rule.from else { continue };
+            let unnamed = deps.unnamed.then(|| "secret(...)".to_string());
+            let named = deps.named.iter().map(|s| s.to_string());
+            for secret in unnamed.into_iter().chain(named) {
+                if !from.contains(&secret) {
+                    violations.push(PolicyViolation::Disallowed {
+                        name: name.clone(),
+                        secret,
+                        span,
+                    });
+                }
+            }
+        }
+        violations
+    }
+}
+
+/// The secrets a value depends on.
+#[derive(Clone, Default)]
+struct Deps {
+    named: BTreeSet<Symbol>,
+    /// Whether it uses a `secret(...)` directly.
+    unnamed: bool,
+}
+
+impl Deps {
+    fn extend(&mut self, other: Deps) {
+        self.named.extend(other.named);
+        self.unnamed |= other.unnamed;
+    }
+}
+
+struct Sources<'a> {
+    spans: SpanCursor<'a>,
+    scope: Scope<BTreeSet<Symbol>>,
+    outputs: Vec<(Symbol, Deps, Span)>,
+    /// The `let`s and outputs whose values are being visited, innermost
+    /// last.
+    owners: Vec<Symbol>,
+    /// Every `declassify(...)` of a secret, once, with its owner.
+    declassified: Vec<(Symbol, Deps, Span)>,
+}
+
+impl Sources<'_> {
+    fn visit(&mut self, expr: &Expr) -> (Deps, Span) {
+        stack::guard(|| self.visit_node(expr))
+    }
+
+    fn visit_node(&mut self, expr: &Expr) -> (Deps, Span) {
+        // Children first, so spans are consumed in post-order
+        let deps = match expr {
+            Expr::Var(name) => Deps {
+                named: self.scope.get(*name).cloned().unwrap_or_default(),
+                unnamed: false,
+            },
+            Expr::Secret(inner) => {
+                let mut deps = self.visit(inner).0;
+                deps.unnamed = true;
+                deps
+            }
+            // As with `secret(...)`
+            Expr::Input { secret, .. } => Deps {
+                named: BTreeSet::new(),
+                unnamed: *secret,
+            },
+            Expr::Let {
+                name,
+                ty,
+                value,
+                body,
+                ..
+            } => {
+                self.owners.push(*name);
+                let mut bound = self.visit(value).0;
+                self.owners.pop();
+                // As with `secret(...)`
+                bound.unnamed |= ty.is_some_and(|ty| ty.secret == Some(true));
+                if bound.unnamed {
+                    bound.named.insert(*name);
+                }
+                self.scope.push(*name, bound.named);
+                let deps = self.visit(body).0;
+                self.scope.pop();
+                deps
+            }
+            // Each name depends on the whole tuple
+            Expr::LetTuple { names, value, body } => {
+                let bound = self.visit(value).0;
+                for name in names {
+                    let mut named = bound.named.clone();
+                    if bound.unnamed {
+                        named.insert(*name);
+                    }
+                    self.scope.push(*name, named);
+                }
+                let deps = self.visit(body).0;
+                for _ in names {
+                    self.scope.pop();
+                }
+                deps
+            }
+            // What `acc` depends on grows with every iteration, so visit
+            // the loop again until it stops growing
+            Expr::While {
+                cond,
+                acc,
+                init,
+                body,
+            } => {
+                let spans = self.spans.clone();
+                let mut named = BTreeSet::new();
+                loop {
+                    self.scope.push(*acc, named.clone());
+                    let mut deps = self.visit(cond).0;
+                    self.scope.pop();
+                    deps.extend(self.visit(init).0);
+                    self.scope.push(*acc, named.clone());
+                    deps.extend(self.visit(body).0);
+                    self.scope.pop();
+                    let mut next = deps.named.clone();
+                    if deps.unnamed {
+                        next.insert(*acc);
+                    }
+                    if next == named {
+                        break deps;
+                    }
+                    named = next;
+                    self.spans = spans.clone();
+                }
+            }
+            Expr::Outputs(outputs) => {
+                for (name, value) in outputs {
+                    self.owners.push(*name);
+                    let (deps, span) = self.visit(value);
+                    self.owners.pop();
+                    self.outputs.push((*name, deps, span));
+                }
+                Deps::default()
+            }
+            // The value is public from here on; what it reveals is checked
+            // where it is declassified
+            Expr::Declassify(inner) => {
+                let deps = self.visit(inner).0;
+                let span = self.spans.next_span().unwrap_or_default();
+                if deps.unnamed || !deps.named.is_empty() {
+                    let owner = self.owners.last().copied().unwrap_or(Symbol::from(RESULT));
+                    // Loops are visited again, and each call of a function
+                    // is a copy of its body
+                    match self.declassified.iter_mut().find(|(name, _, at)| {
+                        *name == owner && *at == span
+                    }) {
+                        Some((_, known, _)) => known.extend(deps),
+                        None => self.declassified.push((owner, deps, span)),
+                    }
+                }
+                return (Deps::default(), span);
+            }
+            _ => {
+                let mut deps = Deps::default();
+                for child in expr.children() {
+                    deps.extend(self.visit(child).0);
+                }
+                deps
+            }
+        };
+        (deps, self.spans.next_span().unwrap_or_default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+    use crate::transform::to_oblivious_with_spans;
+
+    fn check(policy: &str, source: &str) -> Vec<PolicyViolation> {
+        let (tokens, spans) = Lexer::new(source).tokenize().unwrap();
+        let (ast, spans) = Parser::with_spans(&tokens, &spans).parse_with_spans().unwrap();
+        let (ir, _) = to_oblivious_with_spans(&ast, &spans);
+        Policy::parse(policy).unwrap().check(&ast, &spans, &ir)
+    }
+
+    #[test]
+    fn test_reveals_must_be_declassified() {
+        let source = "let k = secret(7) let n = 3 outputs(total = k * n, count = n + 1)";
+        assert_eq!(check("[declassify.total]", source), vec![]);
+        assert_eq!(
+            check("", source),
+            vec![PolicyViolation::Undeclared {
+                name: "total".to_string(),
+                span: Span::new(44, 49),
+            }]
+        );
+        assert_eq!(check("", "1 + 2"), vec![]);
+        assert_eq!(check("[declassify.result]", "secret(1) + 2"), vec![]);
+    }
+
+    #[test]
+    fn test_from_condition() {
+        let policy = "[declassify.total]\nfrom = [\"a\"]";
+        let source = "let a = secret(1) let b = a + secret(2) let c = secret(3) \
+                      outputs(total = if c > 0 then b else 0)";
+        let disallowed: Vec<_> = check(policy, source)
+            .into_iter()
+            .map(|v| match v {
+                PolicyViolation::Disallowed { secret, .. } => secret,
+                v => panic!("unexpected {:?}", v),
+            })
+            .collect();
+        assert_eq!(disallowed, ["b", "c"]);
+        assert_eq!(check(policy, "let a = secret(1) outputs(total = a + 1)"), vec![]);
+        let unnamed = check(policy, "outputs(total = secret(1))");
+        assert_eq!(unnamed[0].code(), codes::DISALLOWED_SECRET);
+        assert_eq!(
+            unnamed[0].to_string(),
+            "`total` depends on `secret(...)`, which the policy does not allow it to reveal"
+        );
+    }
+
+    #[test]
+    fn test_declassify_points_need_entries() {
+        let source = "let salary = secret(5000) let pin = secret(1234) \
+                      let leaked = declassify(pin) outputs(total = salary + 1, shown = leaked)";
+        assert_eq!(
+            check("[declassify.total]", source),
+            vec![PolicyViolation::Undeclared {
+                name: "leaked".to_string(),
+                span: Span::new(62, 77),
+            }]
+        );
+        let policy = "[declassify.total]\nfrom = [\"salary\"]\n[declassify.leaked]\nfrom = [\"k\"]";
+        let disallowed: Vec<_> = check(policy, source)
+            .into_iter()
+            .map(|v| match v {
+                PolicyViolation::Disallowed { name, secret, .. } => (name, secret),
+                v => panic!("unexpected {:?}", v),
+            })
+            .collect();
+        assert_eq!(disallowed, [("leaked".to_string(), "pin".to_string())]);
+        // Outside a `let`, a point belongs to its output or to the result
+        let source = "let k = secret(5) outputs(big = declassify(k > 3))";
+        assert_eq!(check("[declassify.big]", source), vec![]);
+        let source = "let k = secret(5) if declassify(k > 3) then 1 else 0";
+        assert_eq!(check("[declassify.result]\nfrom = [\"k\"]", source), vec![]);
+        assert_eq!(check("", source).len(), 1);
+        // Declassifying a public value reveals nothing
+        assert_eq!(check("", "let n = declassify(2) n + 1"), vec![]);
+    }
+
+    #[test]
+    fn test_parse_json() {
+        let policy = Policy::parse_json(r#"{"declassify": {"total": {"from": ["k"]}}}"#).unwrap();
+        assert_eq!(policy.declassify["total"].from, Some(vec!["k".to_string()]));
+        assert!(Policy::parse("[declassify.total]\nform = []").is_err());
+    }
+}
@@ -0,0 +1,230 @@
+// SPDX-License-Identifier: MIT OR AGPL-3.0-or-later
+// SPDX-FileCopyrightText: 2024-2025 hyperpolymath
+
+//! "Why is this secret?" queries.
+//!
+//! [`explain_secrecy`] finds the innermost IR node at a source position and
+//! follows its secrecy back to where it came from: through operators,
+//! conditions and variable bindings, to a `secret(...)` in the source.
+
+use crate::error::TranspileError;
+use crate::ir::ObliExpr;
+use crate::lexer::Lexer;
+use crate::parser::Parser;
+use crate::span::{NodeSpans, Span};
+use crate::symbol::Symbol;
+use crate::transform::to_oblivious_with_spans;
+use std::collections::HashSet;
+
+/// One link in a secrecy chain.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Step {
+    pub span: Span,
+    /// Why this node is secret.
+    pub reason: String,
+}
+
+/// The secrecy of the node at a position, and where it comes from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Provenance {
+    /// Span of the node the query resolved to.
+    pub span: Span,
+    pub secret: bool,
+    /// From the queried node back to the origin of its secrecy; empty if
+    /// the node is public.
+    pub chain: Vec<Step>,
+}
+
+/// Explain the secrecy of the innermost expression at byte `offset`, or
+/// `None` if no expression covers it.
+pub fn explain_secrecy(source: &str, offset: usize) -> Result<Option<Provenance>, TranspileError> {
+    let (tokens, token_spans) = Lexer::new(source).tokenize()?;
+    let (ast, ast_spans) = Parser::with_spans(&tokens, &token_spans).parse_with_spans()?;
+    let (ir, ir_spans) = to_oblivious_with_spans(&ast, &ast_spans);
+    Ok(explain_ir(&ir, &ir_spans, offset))
+}
+
+/// Like [`explain_secrecy`], on an IR tree with its post-order spans.
+pub fn explain_ir(expr: &ObliExpr, spans: &NodeSpans, offset: usize) -> Option<Provenance> {
+    let mut nodes = Vec::new();
+    index(expr, spans, &mut Vec::new(), &mut nodes);
+    // Post-order: the first node containing the offset is the innermost
+    let target = nodes.iter().position(|n| n.span.contains(offset))?;
+    Some(Provenance {
+        span: nodes[target].span,
+        secret: nodes[target].expr.is_secret(),
+        chain: chain(&nodes, target),
+    })
+}
+
+struct Node<'a> {
+    expr: &'a ObliExpr,
+    span: Span,
+    children: Vec<usize>,
+    /// For variables: the node of the bound value.
+    binding: Option<usize>,
+}
+
+/// Number nodes in post-order, returning the index of `expr`.
+fn index<'a>(
+    expr: &'a ObliExpr,
+    spans: &NodeSpans,
+    scope: &mut Vec<(Symbol, usize)>,
+    nodes: &mut Vec<Node<'a>>,
+) -> usize {
+    let mut children = Vec::new();
+    let mut binding = None;
+    match expr {
+        ObliExpr::Let {
+            name, value, body, ..
+        } => {
+            let value = index(value, spans, scope, nodes);
+            scope.push((*name, value));
+            let body = index(body, spans, scope, nodes);
+            scope.pop();
+            children = vec![value, body];
+        }
+        ObliExpr::Var { name, .. } => {
+            binding = scope.iter().rev().find(|(n, _)| n == name).map(|(_, i)| *i);
+        }
+        // The accumulator is bound to the loop, numbered after its children
+        ObliExpr::While {
+            cond,
+            acc,
+            init,
+            body,
+            ..
+        } => {
+            let this = nodes.len() + expr.post_order().len() - 1;
+            scope.push((*acc, this));
+            children.push(index(cond, spans, scope, nodes));
+            scope.pop();
+            children.push(index(init, spans, scope, nodes));
+            scope.push((*acc, this));
+            children.push(index(body, spans, scope, nodes));
+            scope.pop();
+        }
+        // Both names are bound to the first secret operand, if any
+        ObliExpr::CtSwap { names, body, .. } => {
+            for operand in &expr.children()[..3] {
+                children.push(index(operand, spans, scope, nodes));
+            }
+            let operand = children.iter().copied().find(|&c| nodes[c].expr.is_secret());
+            let operand = operand.unwrap_or(children[1]);
+            scope.extend(names.map(|name| (name, operand)));
+            children.push(index(body, spans, scope, nodes));
+            scope.truncate(scope.len() - 2);
+        }
+        _ => {
+            for child in expr.children() {
+                children.push(index(child, spans, scope, nodes));
+            }
+        }
+    }
+    nodes.push(Node {
+        expr,
+        span: spans.get(nodes.len()).unwrap_or_default(),
+        children,
+        binding,
+    });
+    nodes.len() - 1
+}
+
+fn chain(nodes: &[Node], start: usize) -> Vec<Step> {
+    let mut steps = Vec::new();
+    let mut current = Some(start);
+    let mut visited = HashSet::new();
+    while let Some(i) = current {
+        let node = &nodes[i];
+        // A loop's body can lead back to the loop
+        if !node.expr.is_secret() || !visited.insert(i) {
+            break;
+        }
+        let secret_child = node.children.iter().copied().find(|&c| nodes[c].expr.is_secret());
+        let (reason, next) = match node.expr {
+            ObliExpr::SecretInt(_) | ObliExpr::SecretBool(_) | ObliExpr::Sized { .. } => {
+                ("declared with `secret(...)`".to_string(), None)
+            }
+            ObliExpr::Var { name, .. } => match node.binding {
+                Some(b) if nodes[b].expr.is_secret() => {
+                    (format!("`{}` is bound to a secret value", name), Some(b))
+                }
+                Some(_) => (format!("`{}` is used inside `secret(...)`", name), None),
+                None => (format!("`{}` is a secret input", name), None),
+            },
+            ObliExpr::Input { name, .. } => (format!("`{}` is declared a secret input", name), None),
+            ObliExpr::CtSelect { .. } => (
+                "its condition is secret, so both branches are blended with `ct_select`"
+                    .to_string(),
+                node.children.first().copied(),
+            ),
+            ObliExpr::PubIf { .. } => ("one of its branches is secret".to_string(), secret_child),
+            ObliExpr::Let { .. } => ("its body is secret".to_string(), secret_child),
+            ObliExpr::CtSwap { .. } | ObliExpr::Assert { .. } => {
+                ("its body is secret".to_string(), node.children.last().copied())
+            }
+            ObliExpr::While { .. } => match secret_child {
+                Some(_) => {
+                    let reason = "its initial value or an iteration is secret";
+                    (reason.to_string(), secret_child)
+                }
+                None => ("it is inside `secret(...)`".to_string(), None),
+            },
+            ObliExpr::Outputs(_) => ("one of its outputs is secret".to_string(), secret_child),
+            ObliExpr::Table { .. } => match secret_child {
+                Some(_) => ("it reads a table at a secret index".to_string(), secret_child),
+                None => ("its table entries are secret".to_string(), None),
+            },
+            ObliExpr::BinOp { .. } | ObliExpr::UnaryOp { .. } | ObliExpr::CtCast { .. } => {
+                match secret_child {
+                    Some(_) => ("it has a secret operand".to_string(), secret_child),
+                    None => ("it is inside `secret(...)`".to_string(), None),
+                }
+            }
+            ObliExpr::PubInt(_) | ObliExpr::PubBool(_) | ObliExpr::Declassify(_) => break,
+        };
+        steps.push(Step {
+            span: node.span,
+            reason,
+        });
+        current = next;
+    }
+    steps
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_chain_through_binding() {
+        let src = "let k = secret(5) let y = k + 1 y * 2";
+        let provenance = explain_secrecy(src, src.rfind('y').unwrap()).unwrap().unwrap();
+        assert!(provenance.secret);
+        let origins: Vec<_> = provenance
+            .chain
+            .iter()
+            .map(|step| &src[step.span.start..step.span.end])
+            .collect();
+        assert_eq!(origins, vec!["y", "k + 1", "k", "secret(5)"]);
+        assert_eq!(provenance.chain[0].reason, "`y` is bound to a secret value");
+        assert_eq!(provenance.chain[3].reason, "declared with `secret(...)`");
+    }
+
+    #[test]
+    fn test_select_follows_condition() {
+        let src = "let k = secret(5) if k > 0 then 1 else 2";
+        let provenance = explain_secrecy(src, src.find("then").unwrap()).unwrap().unwrap();
+        assert_eq!(provenance.span, Span::new(18, src.len()));
+        assert!(provenance.chain[0].reason.contains("condition is secret"));
+        assert_eq!(provenance.chain.last().unwrap().reason, "declared with `secret(...)`");
+    }
+
+    #[test]
+    fn test_public_node() {
+        let provenance = explain_secrecy("1 + 2", 0).unwrap().unwrap();
+        assert!(!provenance.secret);
+        assert!(provenance.chain.is_empty());
+        assert_eq!(explain_secrecy("1 + 2", 99).unwrap(), None);
+    }
+}
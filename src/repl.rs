@@ -0,0 +1,190 @@
+// SPDX-License-Identifier: MIT OR AGPL-3.0-or-later
+// SPDX-FileCopyrightText: 2024-2025 hyperpolymath
+
+//! Interactive read-eval-print session.
+//!
+//! Each input line is parsed, typechecked, transformed, run through the
+//! active pass pipeline, and evaluated by the reference interpreter. Lines
+//! starting with `:` are commands (see [`HELP`]).
+
+use crate::ast::Expr;
+use crate::interp::{eval_ir, Inputs};
+use crate::ir::ObliExpr;
+use crate::lexer::Lexer;
+use crate::parser::Parser;
+use crate::passes::{builtin, Pipeline, BUILTIN_PASSES};
+use crate::span::NodeSpans;
+use crate::transform::to_oblivious_checked;
+use crate::typeck::typecheck;
+
+pub const HELP: &str = "\
+Enter a MiniObli expression to evaluate it.
+Commands:
+  :ast               show the AST of the last expression
+  :ir                show the IR of the last expression (after passes)
+  :passes            list passes and whether they are enabled
+  :enable <pass>     enable a pass
+  :disable <pass>    disable a pass
+  :help              show this help
+  :quit              exit";
+
+/// What the caller should do after a line has been handled.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Reply {
+    /// Print this text (may be empty).
+    Print(String),
+    /// End the session.
+    Quit,
+}
+
+/// REPL state: the pass pipeline and the last evaluated program.
+#[derive(Debug, Default)]
+pub struct Session {
+    pipeline: Pipeline,
+    last: Option<(Expr, ObliExpr)>,
+}
+
+impl Session {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Handle one line of input.
+    pub fn handle(&mut self, line: &str) -> Reply {
+        let line = line.trim();
+        if line.is_empty() {
+            return Reply::Print(String::new());
+        }
+        match line.strip_prefix(':') {
+            Some(command) => self.command(command),
+            None => Reply::Print(self.evaluate(line)),
+        }
+    }
+
+    fn command(&mut self, command: &str) -> Reply {
+        let mut words = command.split_whitespace();
+        let text = match (words.next(), words.next()) {
+            (Some("q" | "quit"), _) => return Reply::Quit,
+            (Some("h" | "help"), _) => HELP.to_string(),
+            (Some("ast"), _) => match &self.last {
+                Some((ast, _)) => format!("{:#?}", ast),
+                None => "no expression evaluated yet".to_string(),
+            },
+            (Some("ir"), _) => match &self.last {
+                Some((_, ir)) => format!("{:#?}", ir),
+                None => "no expression evaluated yet".to_string(),
+            },
+            (Some("passes"), _) => BUILTIN_PASSES
+                .iter()
+                .map(|name| {
+                    let state = if self.pipeline.contains(name) { "on" } else { "off" };
+                    format!("{:<16} {}", name, state)
+                })
+                .collect::<Vec<_>>()
+                .join("\n"),
+            (Some("enable"), Some(name)) => {
+                if self.pipeline.contains(name) {
+                    format!("{} is already enabled", name)
+                } else if let Some(pass) = builtin(name) {
+                    self.pipeline.add(pass);
+                    format!("enabled {}", name)
+                } else {
+                    format!("unknown pass `{}`", name)
+                }
+            }
+            (Some("disable"), Some(name)) => {
+                if self.pipeline.remove(name) {
+                    format!("disabled {}", name)
+                } else {
+                    format!("{} is not enabled", name)
+                }
+            }
+            _ => format!("unknown command `:{}` (try :help)", command),
+        };
+        Reply::Print(text)
+    }
+
+    fn evaluate(&mut self, source: &str) -> String {
+        let tokens = match Lexer::new(source).collect::<Result<Vec<_>, _>>() {
+            Ok(tokens) => tokens,
+            Err(e) => return format!("error: {}", e),
+        };
+        let ast = match Parser::new(&tokens).parse() {
+            Ok(ast) => ast,
+            Err(e) => return format!("error: {}", e),
+        };
+        let ty = match typecheck(&ast) {
+            Ok(ty) => ty,
+            Err(errors) => {
+                return errors
+                    .iter()
+                    .map(|e| format!("error: {}", e))
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            }
+        };
+        let ir = match to_oblivious_checked(&ast, &NodeSpans::new()) {
+            Ok((ir, ..)) => self.pipeline.run(ir),
+            Err(errors) => {
+                return errors
+                    .iter()
+                    .map(|e| format!("error: {}", e))
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            }
+        };
+        let result = eval_ir(&ir, &Inputs::new());
+        self.last = Some((ast, ir));
+
+        match result {
+            Ok(v) => {
+                let label = if v.secret { "secret" } else { "public" };
+                format!("{} : {} {}", v.value, label, ty)
+            }
+            Err(e) => format!("error: {}", e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn print(session: &mut Session, line: &str) -> String {
+        match session.handle(line) {
+            Reply::Print(text) => text,
+            Reply::Quit => panic!("unexpected quit"),
+        }
+    }
+
+    #[test]
+    fn test_evaluates_with_label() {
+        let mut session = Session::new();
+        assert_eq!(print(&mut session, "1 + 2"), "3 : public int");
+        assert_eq!(
+            print(&mut session, "let x = secret(4) x > 3"),
+            "true : secret bool"
+        );
+    }
+
+    #[test]
+    fn test_toggle_passes_changes_ir() {
+        let mut session = Session::new();
+        print(&mut session, "1 + 2");
+        assert!(print(&mut session, ":ir").contains("BinOp"));
+        assert_eq!(print(&mut session, ":enable const-fold"), "enabled const-fold");
+        print(&mut session, "1 + 2");
+        assert_eq!(print(&mut session, ":ir"), "PubInt(\n    3,\n)");
+        assert!(print(&mut session, ":passes").contains("const-fold       on"));
+    }
+
+    #[test]
+    fn test_errors_and_quit() {
+        let mut session = Session::new();
+        assert!(print(&mut session, "1 + true").starts_with("error:"));
+        let secret_loop = "while secret(false) with x = 0 do x + 1";
+        assert!(print(&mut session, secret_loop).starts_with("error:"));
+        assert!(print(&mut session, ":bogus").contains("unknown command"));
+        assert_eq!(session.handle(":quit"), Reply::Quit);
+    }
+}
@@ -0,0 +1,172 @@
+// SPDX-License-Identifier: MIT OR AGPL-3.0-or-later
+// SPDX-FileCopyrightText: 2024-2025 hyperpolymath
+
+//! Compilation reports.
+//!
+//! A [`CompilationReport`] records how a piece of generated code was
+//! produced: hashes of the source and the output, the compiler version,
+//! back-end options, the pass pipeline, every point where a secret is
+//! declassified, and the cost statistics of the emitted IR. Reviewers can
+//! archive it next to the generated code and check one against the other.
+//!
+//! Declassification points are each `declassify(...)` of a secret value,
+//! then the output section when the program's result is secret.
+
+use crate::compile;
+use crate::config::Config;
+use crate::emit::EmitOptions;
+use crate::error::TranspileError;
+use crate::format::FORMAT_VERSION;
+use crate::span::Span;
+use crate::stats::{stats, Stats, COST_MODELS};
+use crate::timings::Timings;
+use serde_json::json;
+use sha2::{Digest, Sha256};
+
+/// Extension of report files, written next to the generated code
+/// (`out/a.rs` gets `out/a.report.json`).
+pub const REPORT_EXTENSION: &str = "report.json";
+
+/// A place where a secret value becomes public.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Declassification {
+    pub span: Span,
+    /// What declassifies it: `"declassify"` for a `declassify(...)`,
+    /// `"output"` for the program result.
+    pub kind: &'static str,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompilationReport {
+    /// SHA-256 of the source text, in hex.
+    pub source_sha256: String,
+    /// SHA-256 of the generated code, in hex.
+    pub output_sha256: String,
+    pub compiler_version: &'static str,
+    pub backends: Vec<String>,
+    pub emit: EmitOptions,
+    /// Passes run on the IR, in order.
+    pub passes: Vec<String>,
+    pub declassifications: Vec<Declassification>,
+    /// Statistics of the emitted IR.
+    pub stats: Stats,
+}
+
+impl CompilationReport {
+    /// Render as pretty-printed JSON; spans also get 1-based line/column
+    /// positions in `source`.
+    pub fn to_json(&self, source: &str) -> String {
+        let declassifications: Vec<_> = self
+            .declassifications
+            .iter()
+            .map(|d| {
+                let (line, column) = d.span.line_col(source);
+                json!({
+                    "kind": d.kind,
+                    "start": d.span.start,
+                    "end": d.span.end,
+                    "line": line,
+                    "column": column,
+                })
+            })
+            .collect();
+        let stats: serde_json::Value =
+            serde_json::from_str(&self.stats.to_json(COST_MODELS)).expect("stats JSON is valid");
+        let report = json!({
+            "format_version": FORMAT_VERSION,
+            "source_sha256": self.source_sha256,
+            "output_sha256": self.output_sha256,
+            "compiler_version": self.compiler_version,
+            "backends": self.backends,
+            "emit": { "prelude": self.emit.prelude },
+            "passes": self.passes,
+            "declassifications": declassifications,
+            "stats": stats,
+        });
+        serde_json::to_string_pretty(&report).expect("report JSON is valid")
+    }
+}
+
+/// Like [`transpile_timed`](crate::transpile_timed), also returning the
+/// report of the compilation.
+pub fn transpile_with_report(
+    source: &str,
+    config: &Config,
+    timings: &mut Timings,
+) -> Result<(String, CompilationReport), TranspileError> {
+    let compiled = compile(source, config, timings)?;
+    let mut declassifications = compiled.declassifications;
+    if compiled.ir.is_secret() {
+        declassifications.push(Declassification {
+            span: compiled.span,
+            kind: "output",
+        });
+    }
+    let report = CompilationReport {
+        source_sha256: sha256(source),
+        output_sha256: sha256(&compiled.code),
+        compiler_version: env!("CARGO_PKG_VERSION"),
+        backends: config.backends.clone(),
+        emit: config.emit.clone(),
+        passes: config.passes.clone(),
+        declassifications,
+        stats: stats(&compiled.ir),
+    };
+    Ok((compiled.code, report))
+}
+
+fn sha256(text: &str) -> String {
+    Sha256::digest(text.as_bytes())
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_report_contents() {
+        let config = Config {
+            passes: vec!["const-fold".to_string()],
+            ..Config::default()
+        };
+        let src = "let k = secret(5) k * (1 + 2)";
+        let (code, report) = transpile_with_report(src, &config, &mut Timings::new()).unwrap();
+        assert_eq!(report.output_sha256, sha256(&code));
+        assert_eq!(report.source_sha256.len(), 64);
+        assert_eq!(report.passes, vec!["const-fold"]);
+        assert_eq!(
+            report.declassifications,
+            vec![Declassification {
+                span: Span::new(0, src.len()),
+                kind: "output",
+            }]
+        );
+
+        let json: serde_json::Value = serde_json::from_str(&report.to_json(src)).unwrap();
+        assert_eq!(json["declassifications"][0]["line"], 1);
+        assert_eq!(json["backends"][0], "rust");
+        assert_eq!(json["stats"]["muxes"], 0);
+    }
+
+    #[test]
+    fn test_declassify_listed_before_output() {
+        let src = "let k = secret(5) let n = declassify(k + 1) n * k";
+        let (_, report) =
+            transpile_with_report(src, &Config::default(), &mut Timings::new()).unwrap();
+        let kinds: Vec<_> = report.declassifications.iter().map(|d| d.kind).collect();
+        assert_eq!(kinds, vec!["declassify", "output"]);
+        let first = report.declassifications[0].span;
+        assert_eq!(&src[first.start..first.end], "declassify(k + 1)");
+    }
+
+    #[test]
+    fn test_public_program_declassifies_nothing() {
+        let (_, report) =
+            transpile_with_report("1 + 2", &Config::default(), &mut Timings::new()).unwrap();
+        assert!(report.declassifications.is_empty());
+        assert_eq!(report.source_sha256, sha256("1 + 2"));
+    }
+}
@@ -0,0 +1,376 @@
+// SPDX-License-Identifier: MIT OR AGPL-3.0-or-later
+// SPDX-FileCopyrightText: 2024-2025 hyperpolymath
+
+//! End-to-end execution for `obli run`.
+//!
+//! Free variables of a program are its inputs. They are bound by wrapping
+//! the program in `let`s (`let k = secret(5) <program>`) before the
+//! transform, so secret inputs get the same treatment as `secret(...)`
+//! literals. The result can then be computed by the reference interpreter
+//! ([`run_interpreted`]) or by compiling the emitted Rust with `rustc` and
+//! executing it ([`run_native`]).
+//!
+//! Inputs the program declares with `input` are not bound: the interpreter
+//! reads them from the supplied values, and the compiled program from its
+//! command-line arguments, in declaration order.
+
+use crate::ast::Expr;
+use crate::config::Config;
+use crate::diagnostic::Diagnostic;
+use crate::emit::emit_rust_with;
+use crate::hygiene::mangle_reserved;
+use crate::interp::{eval_ir, EvalError, Inputs, Labeled, Value};
+use crate::ir::ObliExpr;
+use crate::lexer::Lexer;
+use crate::parser::Parser;
+use crate::span::{NodeSpans, Span};
+use crate::stack;
+use crate::symbol::Symbol;
+use crate::transform::to_oblivious_checked;
+use crate::typeck::{typecheck_with_spans, Width};
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::process::{Command, ExitStatus};
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum RunError {
+    #[error("program has errors")]
+    Diagnostics(Vec<Diagnostic>),
+    #[error(transparent)]
+    Eval(#[from] EvalError),
+    #[error("invalid input: {0}")]
+    Input(String),
+    #[error("{context}: {source}")]
+    Io {
+        context: String,
+        #[source]
+        source: io::Error,
+    },
+    #[error("rustc failed to compile the generated code:\n{0}")]
+    Compile(String),
+    #[error("program exited with {0}")]
+    Exit(ExitStatus),
+    #[error("not supported: {0}")]
+    Unsupported(String),
+}
+
+/// Parse a `NAME=VALUE` input assignment (`k=5`, `flag=true`).
+pub fn parse_assignment(assignment: &str) -> Result<(String, Value), RunError> {
+    let (name, value) = assignment
+        .split_once('=')
+        .ok_or_else(|| RunError::Input(format!("expected NAME=VALUE, found `{}`", assignment)))?;
+    Ok((name.trim().to_string(), parse_value(value.trim())?))
+}
+
+/// A boolean, an integer, or an integer with a width suffix (`200u8`).
+fn parse_value(text: &str) -> Result<Value, RunError> {
+    let invalid = || RunError::Input(format!("`{}` is not an integer or boolean", text));
+    let sized = Width::ALL.into_iter().find_map(|w| Some((text.strip_suffix(w.name())?, w)));
+    match text {
+        "true" => Ok(Value::Bool(true)),
+        "false" => Ok(Value::Bool(false)),
+        _ => match sized {
+            Some((digits, width)) => {
+                let n: i128 = digits.parse().map_err(|_| invalid())?;
+                let (min, max) = width.range();
+                if !(min..=max).contains(&n) {
+                    return Err(invalid());
+                }
+                Ok(Value::Sized(width.wrap(n), width))
+            }
+            None => text.parse().map(Value::Int).map_err(|_| invalid()),
+        },
+    }
+}
+
+/// Parse a JSON witness file: `{"secret": {"k": 5}, "public": {"n": 3}}`.
+pub fn parse_witness(json: &str, mut inputs: Inputs) -> Result<Inputs, RunError> {
+    let invalid = |message: String| RunError::Input(format!("witness: {}", message));
+    let root: serde_json::Value = serde_json::from_str(json).map_err(|e| invalid(e.to_string()))?;
+    let root = root
+        .as_object()
+        .ok_or_else(|| invalid("expected an object".to_string()))?;
+    for (section, values) in root {
+        let secret = match section.as_str() {
+            "secret" => true,
+            "public" => false,
+            _ => return Err(invalid(format!("unknown section `{}`", section))),
+        };
+        let values = values
+            .as_object()
+            .ok_or_else(|| invalid(format!("`{}` must be an object", section)))?;
+        for (name, value) in values {
+            let value = match value {
+                serde_json::Value::Bool(b) => Value::Bool(*b),
+                serde_json::Value::Number(n) => n
+                    .as_i64()
+                    .map(Value::Int)
+                    .ok_or_else(|| invalid(format!("`{}` does not fit in an i64", name)))?,
+                _ => return Err(invalid(format!("`{}` must be an integer or boolean", name))),
+            };
+            inputs = if secret {
+                inputs.secret(name, value)
+            } else {
+                inputs.public(name, value)
+            };
+        }
+    }
+    Ok(inputs)
+}
+
+/// Lex, parse and typecheck `source` with `inputs` bound, then transform,
+/// apply the configured division-by-zero setting, run the passes and
+/// [rename](mangle_reserved) the variables Rust cannot use, so the result
+/// can be emitted.
+pub fn prepare(source: &str, inputs: &Inputs, config: &Config) -> Result<ObliExpr, RunError> {
+    let (ir, spans) = prepare_with_spans(source, inputs)?;
+    let ir = config.division_by_zero.apply(ir, &spans).map_err(|errors| {
+        RunError::Diagnostics(errors.into_iter().map(Diagnostic::from).collect())
+    })?;
+    Ok(mangle_reserved(config.pipeline().run(ir)))
+}
+
+/// Like [`prepare`] without running passes, returning the IR's post-order
+/// spans (empty for the `let`s that bind inputs).
+pub fn prepare_with_spans(
+    source: &str,
+    inputs: &Inputs,
+) -> Result<(ObliExpr, NodeSpans), RunError> {
+    let (ast, spans) = prepare_ast(source, inputs)?;
+    let checked = to_oblivious_checked(&ast, &spans);
+    stack::drop_tree(ast);
+    let (ir, ir_spans, _) = checked.map_err(|errors| {
+        RunError::Diagnostics(errors.into_iter().map(Diagnostic::from).collect())
+    })?;
+    Ok((ir, ir_spans))
+}
+
+/// Lex, parse and typecheck `source` with `inputs` bound, returning the
+/// AST before the transform and its post-order spans.
+pub fn prepare_ast(source: &str, inputs: &Inputs) -> Result<(Expr, NodeSpans), RunError> {
+    let fail = |d: Diagnostic| RunError::Diagnostics(vec![d]);
+    let (tokens, token_spans) = Lexer::new(source).tokenize().map_err(|e| fail(e.into()))?;
+    let mut parser = Parser::with_spans(&tokens, &token_spans);
+    let (ast, ast_spans) = parser.parse_with_spans().map_err(|e| fail(e.into()))?;
+
+    let (ast, spans) = bind_inputs(ast, &ast_spans, inputs);
+    if let Err(errors) = typecheck_with_spans(&ast, &spans) {
+        return Err(RunError::Diagnostics(errors.into_iter().map(Diagnostic::from).collect()));
+    }
+    Ok((ast, spans))
+}
+
+/// Wrap `ast` in a `let` per input it does not declare (in name order),
+/// extending its post-order span table with empty spans for the added nodes.
+fn bind_inputs(ast: Expr, spans: &NodeSpans, inputs: &Inputs) -> (Expr, NodeSpans) {
+    let mut declared = HashSet::new();
+    declared_inputs(&ast, &mut declared);
+    let mut bound: Vec<_> = inputs.iter().filter(|(name, _)| !declared.contains(name)).collect();
+    bound.sort_by_key(|(name, _)| *name);
+
+    let mut wrapped = NodeSpans::new();
+    let mut body = ast;
+    for (_, input) in &bound {
+        // Post-order puts every bound value before the original program
+        wrapped.push(Span::default());
+        if input.secret {
+            wrapped.push(Span::default());
+        }
+    }
+    for span in spans.iter() {
+        wrapped.push(*span);
+    }
+    for (name, input) in bound.into_iter().rev() {
+        let literal = match input.value {
+            Value::Int(n) => Expr::Int(n),
+            Value::Sized(n, width) => Expr::Sized(n, width),
+            Value::Bool(b) => Expr::Bool(b),
+        };
+        let value = if input.secret {
+            Expr::Secret(Box::new(literal))
+        } else {
+            literal
+        };
+        body = Expr::Let {
+            name,
+            ty: None,
+            mutable: false,
+            value: Box::new(value),
+            body: Box::new(body),
+        };
+        wrapped.push(Span::default());
+    }
+    (body, wrapped)
+}
+
+/// Add the names of the inputs `expr` declares to `declared`.
+fn declared_inputs(expr: &Expr, declared: &mut HashSet<Symbol>) {
+    if let Expr::Input { name, .. } = expr {
+        declared.insert(*name);
+    }
+    for child in expr.children() {
+        stack::guard(|| declared_inputs(child, declared));
+    }
+}
+
+/// Run `source` with the reference interpreter.
+pub fn run_interpreted(
+    source: &str,
+    inputs: &Inputs,
+    config: &Config,
+) -> Result<Labeled, RunError> {
+    let ir = prepare(source, inputs, config)?;
+    let result = eval_ir(&ir, inputs);
+    stack::drop_tree(ir);
+    Ok(result?)
+}
+
+/// Emit `source` as Rust, compile it with `rustc` and run it, returning
+/// what the program printed.
+pub fn run_native(source: &str, inputs: &Inputs, config: &Config) -> Result<String, RunError> {
+    let ir = prepare(source, inputs, config)?;
+    let mut args = Vec::new();
+    for (name, ty, _) in ir.inputs() {
+        args.push(inputs.read(name, ty)?.to_string());
+    }
+    run_emitted_with_args(&emit_rust_with(&ir, &config.emit), &args)
+}
+
+/// Compile emitted Rust with `rustc` and run it, returning what it printed.
+pub(crate) fn run_emitted(code: &str) -> Result<String, RunError> {
+    run_emitted_with_args(code, &[])
+}
+
+/// [`run_emitted`], passing `args` to the compiled program.
+pub(crate) fn run_emitted_with_args(code: &str, args: &[String]) -> Result<String, RunError> {
+    let io_error = |context: &str| {
+        let context = context.to_string();
+        move |source| RunError::Io { context, source }
+    };
+    let dir = scratch_dir();
+    fs::create_dir_all(&dir).map_err(io_error("cannot create build directory"))?;
+    let main = dir.join("main.rs");
+    let binary = dir.join("main");
+    fs::write(&main, code).map_err(io_error("cannot write generated code"))?;
+
+    let compiled = Command::new("rustc")
+        .args(["--edition", "2021", "-O", "-A", "warnings", "-o"])
+        .arg(&binary)
+        .arg(&main)
+        .output()
+        .map_err(io_error("cannot run rustc"))?;
+    if !compiled.status.success() {
+        let _ = fs::remove_dir_all(&dir);
+        return Err(RunError::Compile(String::from_utf8_lossy(&compiled.stderr).into_owned()));
+    }
+
+    let ran = Command::new(&binary)
+        .args(args)
+        .output()
+        .map_err(io_error("cannot run compiled program"));
+    let _ = fs::remove_dir_all(&dir);
+    let ran = ran?;
+    if !ran.status.success() {
+        return Err(RunError::Exit(ran.status));
+    }
+    Ok(String::from_utf8_lossy(&ran.stdout).into_owned())
+}
+
+fn scratch_dir() -> PathBuf {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    static NEXT: AtomicUsize = AtomicUsize::new(0);
+    let n = NEXT.fetch_add(1, Ordering::Relaxed);
+    std::env::temp_dir().join(format!("obli-run-{}-{}", std::process::id(), n))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_inputs_are_bound() {
+        let inputs = Inputs::new()
+            .secret("k", Value::Int(5))
+            .public("n", Value::Int(2));
+        let result = run_interpreted("if k > n then k * n else 0", &inputs, &Config::default());
+        assert_eq!(result.unwrap(), Labeled::secret(Value::Int(10)));
+    }
+
+    #[test]
+    fn test_native_runs_rename_reserved_names() {
+        let config = Config::default();
+        let source = "let Pub = secret(3) let w = 4 Pub + w";
+        assert_eq!(run_native(source, &Inputs::new(), &config).unwrap(), "Result: 7\n");
+        let inputs = Inputs::new().secret("k", Value::Int(2));
+        let source = "let type = k + 1 let loop = type * 2 loop";
+        assert_eq!(run_native(source, &inputs, &config).unwrap(), "Result: 6\n");
+    }
+
+    #[test]
+    fn test_declared_inputs_are_read() {
+        let source = "input secret k: int input n: u8 if k > 2 then n else 0u8";
+        let inputs = Inputs::new()
+            .public("k", Value::Int(5))
+            .public("n", Value::Int(200));
+        let result = run_interpreted(source, &inputs, &Config::default());
+        assert_eq!(result.unwrap(), Labeled::secret(Value::Sized(200, Width::U8)));
+        let Err(RunError::Eval(error)) =
+            run_interpreted(source, &Inputs::new(), &Config::default())
+        else {
+            panic!("expected a missing input");
+        };
+        assert_eq!(error, EvalError::MissingInput("k".to_string()));
+    }
+
+    #[test]
+    fn test_errors_keep_spans() {
+        let inputs = Inputs::new().secret("k", Value::Int(5));
+        let Err(RunError::Diagnostics(diagnostics)) =
+            run_interpreted("k + true", &inputs, &Config::default())
+        else {
+            panic!("expected a type error");
+        };
+        assert_eq!(diagnostics[0].span, Some(Span::new(4, 8)));
+    }
+
+    #[test]
+    fn test_secret_loop_condition_is_an_error() {
+        let Err(RunError::Diagnostics(diagnostics)) = run_interpreted(
+            "while secret(true) with x = 0 do x + 1",
+            &Inputs::new(),
+            &Config::default(),
+        ) else {
+            panic!("expected the loop to be rejected");
+        };
+        assert_eq!(diagnostics[0].code, Some(crate::codes::SECRET_LOOP_CONDITION));
+    }
+
+    #[test]
+    fn test_secret_declared_pub_is_an_error() {
+        let Err(RunError::Diagnostics(diagnostics)) = run_interpreted(
+            "let shown: pub int = secret(1); shown",
+            &Inputs::new(),
+            &Config::default(),
+        ) else {
+            panic!("expected the binding to be rejected");
+        };
+        assert_eq!(diagnostics[0].code, Some(crate::codes::DECLARED_PUBLIC));
+    }
+
+    #[test]
+    fn test_parse_witness_and_assignment() {
+        let inputs = parse_witness(r#"{"secret": {"k": 5}, "public": {"f": true}}"#, Inputs::new())
+            .unwrap();
+        assert_eq!(inputs.get("k"), Some(Labeled::secret(Value::Int(5))));
+        assert_eq!(inputs.get("f"), Some(Labeled::public(Value::Bool(true))));
+        assert!(parse_witness(r#"{"hidden": {}}"#, Inputs::new()).is_err());
+        assert_eq!(parse_assignment("x = -3").unwrap(), ("x".to_string(), Value::Int(-3)));
+        let byte = Value::Sized(200, Width::U8);
+        assert_eq!(parse_assignment("b=200u8").unwrap(), ("b".to_string(), byte));
+        assert!(parse_assignment("b=256u8").is_err());
+        assert!(parse_assignment("x").is_err());
+    }
+}
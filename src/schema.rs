@@ -0,0 +1,365 @@
+// SPDX-License-Identifier: MIT OR AGPL-3.0-or-later
+// SPDX-FileCopyrightText: 2024-2025 hyperpolymath
+
+//! Input and output schemas.
+//!
+//! A [`Schema`] describes what a program takes and what it returns, so
+//! tools can wire generated code into a pipeline without reading it. Builds
+//! write one next to every output (`out/a.rs` gets `out/a.schema.json`):
+//!
+//! ```json
+//! {"format_version": 2,
+//!  "inputs": [{"name": "limit", "type": "int", "width": 64, "label": "public"}],
+//!  "outputs": [{"name": "total", "type": "int", "width": 64, "label": "secret"}]}
+//! ```
+//!
+//! Inputs are the program's free variables, which library-mode builds take
+//! as parameters, typed as [`ObliBuild`](crate::build::ObliBuild) types
+//! them; an input is secret if the program wraps it in `secret(...)`. The
+//! inputs it declares with `input` follow, in the order its `main` reads
+//! them.
+//! Outputs are the named `outputs`, or a single `result`; an output is
+//! secret if revealing it declassifies a secret.
+
+use crate::ast::Expr;
+use crate::desugar::expand;
+use crate::diagnostic::Diagnostic;
+use crate::embed::{free_variables, infer_captures};
+use crate::format::{check_version, FormatError, FORMAT_VERSION};
+use crate::policy::RESULT;
+use crate::stack;
+use crate::symbol::{Scope, Symbol};
+use crate::transform::to_oblivious;
+use crate::typeck::{node_types, Scalar, Type, Width};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+/// Extension of schema files, written next to the generated code.
+pub const SCHEMA_EXTENSION: &str = "schema.json";
+
+#[derive(Error, Debug)]
+pub enum SchemaError {
+    #[error("cannot read {path}: {source}")]
+    Io {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+    #[error("invalid schema: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error(transparent)]
+    Format(#[from] FormatError),
+}
+
+/// The inputs and outputs of a program.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Schema {
+    /// [`FORMAT_VERSION`] of the writer.
+    pub format_version: u32,
+    /// In parameter order.
+    pub inputs: Vec<Field>,
+    /// In declaration order.
+    pub outputs: Vec<Field>,
+}
+
+/// One input or output.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Field {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub ty: FieldType,
+    /// Width in bits.
+    pub width: u32,
+    pub label: Label,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FieldType {
+    Int,
+    Bool,
+    U8,
+    U16,
+    U32,
+    U64,
+    I32,
+}
+
+impl FieldType {
+    /// Width in bits of values of this type.
+    pub fn width(self) -> u32 {
+        match self {
+            FieldType::Int => 64,
+            FieldType::Bool => 1,
+            FieldType::U8 => 8,
+            FieldType::U16 => 16,
+            FieldType::U32 | FieldType::I32 => 32,
+            FieldType::U64 => 64,
+        }
+    }
+}
+
+impl From<Width> for FieldType {
+    fn from(width: Width) -> Self {
+        match width {
+            Width::U8 => FieldType::U8,
+            Width::U16 => FieldType::U16,
+            Width::U32 => FieldType::U32,
+            Width::U64 => FieldType::U64,
+            Width::I32 => FieldType::I32,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Label {
+    Public,
+    Secret,
+}
+
+impl Field {
+    fn new(name: impl Into<String>, ty: FieldType, secret: bool) -> Self {
+        Self {
+            name: name.into(),
+            ty,
+            width: ty.width(),
+            label: if secret { Label::Secret } else { Label::Public },
+        }
+    }
+}
+
+impl Schema {
+    /// Schema of a program, reporting why it does not typecheck otherwise.
+    pub fn of(source: &str) -> Result<Self, Vec<Diagnostic>> {
+        let names = free_variables(source);
+        let (ast, _, types) = infer_captures(source, &names)?;
+        let ir = to_oblivious(&ast);
+
+        // Inputs are bound by leading lets in name order; see `embed`
+        let mut program = &ast;
+        for _ in 0..names.len() {
+            program = match program {
+                Expr::Let { body, .. } => body,
+                _ => unreachable!("prepare_ast binds every input with a let"),
+            };
+        }
+        let mut secret_inputs = SecretInputs {
+            scope: Scope::new(),
+            found: BTreeSet::new(),
+        };
+        secret_inputs.visit(&expand(program), false);
+        let mut inputs: Vec<_> = names
+            .iter()
+            .zip(types)
+            .map(|(name, ty)| {
+                let ty = if ty == "bool" { FieldType::Bool } else { FieldType::Int };
+                Field::new(name, ty, secret_inputs.found.contains(&Symbol::from(name)))
+            })
+            .collect();
+        inputs.extend(ir.inputs().into_iter().map(|(name, ty, secret)| {
+            let ty = match ty {
+                Scalar::Int => FieldType::Int,
+                Scalar::Bool => FieldType::Bool,
+                Scalar::Sized(width) => FieldType::from(width),
+            };
+            Field::new(name.as_str(), ty, secret)
+        }));
+
+        let types = node_types(&ast);
+        let mut results = Vec::new();
+        let root = post_order(&ast, &mut 0, &mut results);
+        let field_type = |index: usize| match types.get(index) {
+            Some(Some(Type::Bool)) => FieldType::Bool,
+            Some(Some(Type::Sized(width))) => FieldType::from(*width),
+            _ => FieldType::Int,
+        };
+        let outputs = match ir.outputs() {
+            Some(values) => results
+                .iter()
+                .zip(values)
+                .map(|((name, index), (_, value))| {
+                    Field::new(name.as_str(), field_type(*index), value.is_secret())
+                })
+                .collect(),
+            None => vec![Field::new(RESULT, field_type(root), ir.is_secret())],
+        };
+        Ok(Self {
+            format_version: FORMAT_VERSION,
+            inputs,
+            outputs,
+        })
+    }
+
+    /// Load a schema file.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, SchemaError> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path).map_err(|source| SchemaError::Io {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        Self::from_json(&text)
+    }
+
+    /// Read a schema, rejecting format versions this crate does not read.
+    pub fn from_json(text: &str) -> Result<Self, SchemaError> {
+        let schema: Self = serde_json::from_str(text)?;
+        check_version(schema.format_version)?;
+        Ok(schema)
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("schemas always serialize")
+    }
+
+    /// The input called `name`.
+    pub fn input(&self, name: &str) -> Option<&Field> {
+        self.inputs.iter().find(|field| field.name == name)
+    }
+
+    /// The output called `name`.
+    pub fn output(&self, name: &str) -> Option<&Field> {
+        self.outputs.iter().find(|field| field.name == name)
+    }
+}
+
+/// Number the nodes of `expr` in post-order, like [`node_types`], and
+/// record the number of every output value. Returns the number of `expr`.
+fn post_order(expr: &Expr, next: &mut usize, outputs: &mut Vec<(Symbol, usize)>) -> usize {
+    let children: Vec<_> = expr
+        .children()
+        .into_iter()
+        .map(|child| stack::guard(|| post_order(child, next, outputs)))
+        .collect();
+    if let Expr::Outputs(named) = expr {
+        outputs.extend(named.iter().map(|(name, _)| *name).zip(children));
+    }
+    *next += 1;
+    *next - 1
+}
+
+/// Free variables used inside `secret(...)`.
+struct SecretInputs {
+    /// Names bound by the program, which shadow inputs.
+    scope: Scope<()>,
+    found: BTreeSet<Symbol>,
+}
+
+impl SecretInputs {
+    fn visit(&mut self, expr: &Expr, in_secret: bool) {
+        stack::guard(|| match expr {
+            Expr::Var(name) => {
+                if in_secret && self.scope.get(*name).is_none() {
+                    self.found.insert(*name);
+                }
+            }
+            Expr::Secret(inner) => self.visit(inner, true),
+            Expr::Let {
+                name,
+                ty,
+                value,
+                body,
+                ..
+            } => {
+                let classified = ty.is_some_and(|ty| ty.secret == Some(true));
+                self.visit(value, in_secret || classified);
+                self.scope.push(*name, ());
+                self.visit(body, in_secret);
+                self.scope.pop();
+            }
+            Expr::LetTuple { names, value, body } => {
+                self.visit(value, in_secret);
+                for name in names {
+                    self.scope.push(*name, ());
+                }
+                self.visit(body, in_secret);
+                for _ in names {
+                    self.scope.pop();
+                }
+            }
+            Expr::While {
+                cond,
+                acc,
+                init,
+                body,
+            } => {
+                self.scope.push(*acc, ());
+                self.visit(cond, in_secret);
+                self.visit(body, in_secret);
+                self.scope.pop();
+                self.visit(init, in_secret);
+            }
+            _ => {
+                for child in expr.children() {
+                    self.visit(child, in_secret);
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_inputs_and_outputs() {
+        let schema = Schema::of(
+            "let x = secret(input) let limit = bound + 1 \
+             outputs(capped = if x > limit then limit else x, over = x > limit, bound = limit)",
+        )
+        .unwrap();
+        let field = |name: &str, ty, label| Field {
+            name: name.to_string(),
+            ty,
+            width: ty.width(),
+            label,
+        };
+        assert_eq!(
+            schema.inputs,
+            [
+                field("bound", FieldType::Int, Label::Public),
+                field("input", FieldType::Int, Label::Secret),
+            ]
+        );
+        assert_eq!(
+            schema.outputs,
+            [
+                field("capped", FieldType::Int, Label::Secret),
+                field("over", FieldType::Bool, Label::Secret),
+                field("bound", FieldType::Int, Label::Public),
+            ]
+        );
+        assert_eq!(schema.output("over").unwrap().width, 1);
+
+        let schema = Schema::of("let flag = secret(true) flag && enabled").unwrap();
+        assert_eq!(schema.input("enabled").unwrap().ty, FieldType::Bool);
+        assert_eq!(schema.outputs, [field(RESULT, FieldType::Bool, Label::Secret)]);
+        assert!(Schema::of("1 + true").is_err());
+
+        let schema = Schema::of("outputs(byte = secret(7u8) + 1u8)").unwrap();
+        assert_eq!(schema.outputs, [field("byte", FieldType::U8, Label::Secret)]);
+        assert_eq!(schema.outputs[0].width, 8);
+    }
+
+    #[test]
+    fn test_json_round_trip() {
+        let schema = Schema::of("let input = 2 secret(input) * 3").unwrap();
+        assert!(schema.inputs.is_empty());
+        let json = schema.to_json();
+        assert!(json.contains("\"type\": \"int\""));
+        assert_eq!(Schema::from_json(&json).unwrap(), schema);
+        assert!(Schema::from_json("{\"inputs\": 1}").is_err());
+        let version = format!("\"format_version\": {}", FORMAT_VERSION);
+        assert!(json.contains(&version));
+        let newer = json.replace(&version, "\"format_version\": 99");
+        assert!(matches!(Schema::from_json(&newer), Err(SchemaError::Format(_))));
+        // Sized field types and inputs changed the format
+        let older = json.replace(&version, "\"format_version\": 1");
+        assert!(matches!(Schema::from_json(&older), Err(SchemaError::Format(_))));
+    }
+}
@@ -0,0 +1,169 @@
+// SPDX-License-Identifier: MIT OR AGPL-3.0-or-later
+// SPDX-FileCopyrightText: 2024-2025 hyperpolymath
+
+//! Source spans.
+//!
+//! Spans are byte ranges into the original source text. AST and IR nodes do
+//! not carry spans themselves; instead the parser and the transform record
+//! a [`NodeSpans`] table listing one span per node in post-order (children
+//! before parents), which is exactly the order in which nodes are built.
+
+/// A half-open byte range `start..end` into the source text.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Self {
+        Self { start, end }
+    }
+
+    /// Smallest span covering both `self` and `other`.
+    pub fn merge(self, other: Span) -> Span {
+        Span {
+            start: self.start.min(other.start),
+            end: self.end.max(other.end),
+        }
+    }
+
+    /// Whether `offset` falls inside this span.
+    pub fn contains(&self, offset: usize) -> bool {
+        self.start <= offset && offset < self.end
+    }
+
+    pub fn len(&self) -> usize {
+        self.end.saturating_sub(self.start)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// 1-based line and column of the span start within `source`.
+    pub fn line_col(&self, source: &str) -> (usize, usize) {
+        line_col(source, self.start)
+    }
+}
+
+/// 1-based line and column (in characters) of a byte offset within `source`.
+pub fn line_col(source: &str, offset: usize) -> (usize, usize) {
+    let offset = offset.min(source.len());
+    let before = &source[..offset];
+    let line = before.matches('\n').count() + 1;
+    let line_start = before.rfind('\n').map(|i| i + 1).unwrap_or(0);
+    let col = before[line_start..].chars().count() + 1;
+    (line, col)
+}
+
+/// Byte offset of a 1-based line and column within `source`, if it exists.
+pub fn offset_of(source: &str, line: usize, col: usize) -> Option<usize> {
+    let mut line_start = 0;
+    for _ in 1..line {
+        line_start += source[line_start..].find('\n')? + 1;
+    }
+    let line_text = source[line_start..].split('\n').next().unwrap_or("");
+    let (idx, _) = line_text
+        .char_indices()
+        .chain(std::iter::once((line_text.len(), ' ')))
+        .nth(col.checked_sub(1)?)?;
+    Some(line_start + idx)
+}
+
+/// Spans of tree nodes, in post-order.
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct NodeSpans(Vec<Span>);
+
+impl NodeSpans {
+    pub fn new() -> Self {
+        Self(Vec::new())
+    }
+
+    pub fn push(&mut self, span: Span) {
+        self.0.push(span);
+    }
+
+    /// Span of the node with the given post-order index.
+    pub fn get(&self, index: usize) -> Option<Span> {
+        self.0.get(index).copied()
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Span> {
+        self.0.iter()
+    }
+
+    /// Drop all but the first `len` spans.
+    pub fn truncate(&mut self, len: usize) {
+        self.0.truncate(len);
+    }
+}
+
+/// Cursor handing out spans in post-order while a tree is being walked.
+///
+/// Walks that do not have spans available use [`SpanCursor::none`], which
+/// yields `None` for every node.
+#[derive(Clone)]
+pub(crate) struct SpanCursor<'a> {
+    spans: Option<&'a NodeSpans>,
+    next: usize,
+}
+
+impl<'a> SpanCursor<'a> {
+    pub(crate) fn new(spans: Option<&'a NodeSpans>) -> Self {
+        Self { spans, next: 0 }
+    }
+
+    pub(crate) fn none() -> Self {
+        Self::new(None)
+    }
+
+    /// Span of the next node in post-order.
+    pub(crate) fn next_span(&mut self) -> Option<Span> {
+        let span = self.spans.and_then(|s| s.get(self.next));
+        self.next += 1;
+        span
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_line_col() {
+        let src = "let x = 1\nx + y";
+        assert_eq!(line_col(src, 0), (1, 1));
+        assert_eq!(line_col(src, 4), (1, 5));
+        assert_eq!(line_col(src, 10), (2, 1));
+        assert_eq!(line_col(src, 14), (2, 5));
+    }
+
+    #[test]
+    fn test_offset_of_roundtrip() {
+        let src = "let x = 1\nx + y";
+        for offset in 0..src.len() {
+            let (line, col) = line_col(src, offset);
+            assert_eq!(offset_of(src, line, col), Some(offset));
+        }
+        assert_eq!(offset_of(src, 3, 1), None);
+    }
+
+    #[test]
+    fn test_merge() {
+        let span = Span::new(4, 6).merge(Span::new(1, 3));
+        assert_eq!(span, Span::new(1, 6));
+        assert!(span.contains(5));
+        assert!(!span.contains(6));
+    }
+}
@@ -0,0 +1,75 @@
+// SPDX-License-Identifier: MIT OR AGPL-3.0-or-later
+// SPDX-FileCopyrightText: 2024-2025 hyperpolymath
+
+//! Stack growth for the recursive tree walks.
+//!
+//! Generated programs nest deeply, often 10k and more chained `let`s,
+//! which is further than a recursive walk can go on a thread's stack.
+//! Each recursive step of the parser, type checker, transform, verifier,
+//! passes, emitter, interpreter and formatter runs under [`guard`], which
+//! continues on a new heap-allocated stack segment when the current one
+//! runs low, and the pipeline drops its trees with [`drop_tree`].
+
+/// Space left on the stack below which [`guard`] switches segments.
+const RED_ZONE: usize = 128 * 1024;
+
+/// Size of each new stack segment.
+const SEGMENT: usize = 4 * 1024 * 1024;
+
+/// Run `f`, first moving to a new stack segment if this one is nearly
+/// used up.
+#[inline]
+pub(crate) fn guard<R>(f: impl FnOnce() -> R) -> R {
+    stacker::maybe_grow(RED_ZONE, SEGMENT, f)
+}
+
+/// Stack reserved for dropping a whole tree.
+const DROP_SEGMENT: usize = 64 * SEGMENT;
+
+/// Drop a tree on a fresh stack segment. Drop glue recurses through the
+/// boxes and cannot be guarded node by node; the segment is only mapped,
+/// so unused space costs nothing.
+pub(crate) fn drop_tree<T>(tree: T) {
+    stacker::grow(DROP_SEGMENT, || drop(tree))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::check::check;
+    use crate::config::Config;
+    use crate::formatter::format_source;
+    use crate::interp::{Inputs, Value};
+    use crate::run::run_interpreted;
+    use crate::testing::fixtures::unrolled_loop;
+    use std::thread;
+
+    #[test]
+    fn test_deep_programs_on_a_small_stack() {
+        let source = unrolled_loop(10_000);
+        let worker = thread::Builder::new().stack_size(256 * 1024).spawn(move || {
+            let code = crate::transpile(&source).unwrap();
+            assert!(check(&source).iter().all(|d| !d.is_error()));
+            code
+        });
+        let code = worker.unwrap().join().unwrap();
+        assert!(code.contains("let x9999 = "));
+    }
+
+    #[test]
+    fn test_deep_programs_run_and_format_on_a_small_stack() {
+        let lets = unrolled_loop(20_000);
+        let sum = vec!["1"; 20_000].join(" + ");
+        let worker = thread::Builder::new().stack_size(256 * 1024).spawn(move || {
+            let inputs = Inputs::new();
+            let config = Config::default();
+            let ran = run_interpreted(&lets, &inputs, &config).unwrap();
+            assert_eq!(ran.value, Value::Int(20_000));
+            let ran = run_interpreted(&sum, &inputs, &config).unwrap();
+            assert_eq!(ran.value, Value::Int(20_000));
+            let formatted = format_source(&lets).unwrap();
+            assert!(formatted.ends_with("x19999\n"));
+            assert_eq!(format_source(&sum).unwrap().matches('+').count(), 19_999);
+        });
+        worker.unwrap().join().unwrap();
+    }
+}
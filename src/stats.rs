@@ -0,0 +1,407 @@
+// SPDX-License-Identifier: MIT OR AGPL-3.0-or-later
+// SPDX-FileCopyrightText: 2024-2025 hyperpolymath
+
+//! IR statistics and cost projections.
+//!
+//! [`stats`] counts what an oblivious program will actually execute: every
+//! `CtSelect` is a mux over both branches, and secret operations cannot be
+//! skipped. A `while` loop runs a number of times only known when it
+//! runs, so its body is counted as one iteration. [`CostModel`]s turn
+//! those counts into rough projected costs for different kinds of target,
+//! from native constant-time code to boolean and arithmetic circuits (as
+//! used by garbled-circuit and MPC/FHE back ends).
+//! The figures are estimates for comparing programs, not benchmarks.
+
+use crate::ir::{ObliBinOp, ObliExpr, ObliUnaryOp};
+use crate::symbol::Symbol;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fmt::Write as _;
+
+/// Counts and depth estimates for an IR tree.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Stats {
+    pub nodes: usize,
+    pub secret_nodes: usize,
+    /// `CtSelect` nodes, and two for each `CtSwap` on a secret condition.
+    pub muxes: usize,
+    /// `PubIf` nodes and `CtSwap`s on a public condition.
+    pub public_branches: usize,
+    /// Secret operations by operator name (`add`, `mul`, `lt`, …).
+    #[cfg_attr(feature = "serde", serde(with = "crate::checkpoint::op_counts"))]
+    pub secret_ops: BTreeMap<&'static str, usize>,
+    /// Public operations by operator name.
+    #[cfg_attr(feature = "serde", serde(with = "crate::checkpoint::op_counts"))]
+    pub public_ops: BTreeMap<&'static str, usize>,
+    /// Longest chain of dependent secret operations (including muxes).
+    pub depth: usize,
+    /// Longest chain of dependent non-linear secret operations
+    /// (multiplication, division, `and`/`or`, muxes).
+    pub multiplicative_depth: usize,
+}
+
+/// Every operator name [`Stats`] counts.
+#[cfg(feature = "serde")]
+pub(crate) const OP_NAMES: &[&str] = &[
+    "add", "sub", "mul", "add_sat", "sub_sat", "mul_sat", "div", "mod", "bit_and", "bit_or",
+    "bit_xor", "shl", "shr", "eq", "ne", "lt", "le", "gt", "ge", "and", "or", "neg", "not",
+];
+
+fn binop_name(op: &ObliBinOp) -> &'static str {
+    match op {
+        ObliBinOp::CtAdd => "add",
+        ObliBinOp::CtSub => "sub",
+        ObliBinOp::CtMul => "mul",
+        ObliBinOp::CtAddSat => "add_sat",
+        ObliBinOp::CtSubSat => "sub_sat",
+        ObliBinOp::CtMulSat => "mul_sat",
+        ObliBinOp::CtDiv => "div",
+        ObliBinOp::CtMod => "mod",
+        ObliBinOp::CtBitAnd => "bit_and",
+        ObliBinOp::CtBitOr => "bit_or",
+        ObliBinOp::CtBitXor => "bit_xor",
+        ObliBinOp::CtShl => "shl",
+        ObliBinOp::CtShr => "shr",
+        ObliBinOp::CtEq => "eq",
+        ObliBinOp::CtNe => "ne",
+        ObliBinOp::CtLt => "lt",
+        ObliBinOp::CtLe => "le",
+        ObliBinOp::CtGt => "gt",
+        ObliBinOp::CtGe => "ge",
+        ObliBinOp::CtAnd => "and",
+        ObliBinOp::CtOr => "or",
+    }
+}
+
+fn unaryop_name(op: &ObliUnaryOp) -> &'static str {
+    match op {
+        ObliUnaryOp::CtNeg => "neg",
+        ObliUnaryOp::CtNot => "not",
+        ObliUnaryOp::CtToInt => "to_int",
+        ObliUnaryOp::CtToBool => "to_bool",
+    }
+}
+
+fn is_nonlinear(name: &str) -> bool {
+    matches!(name, "mul" | "mul_sat" | "div" | "mod" | "bit_and" | "bit_or" | "and" | "or")
+}
+
+/// Compute statistics for an IR tree.
+pub fn stats(expr: &ObliExpr) -> Stats {
+    let mut stats = Stats::default();
+    let mut scope = Vec::new();
+    let (depth, mult_depth) = visit(expr, &mut stats, &mut scope);
+    stats.depth = depth;
+    stats.multiplicative_depth = mult_depth;
+    stats
+}
+
+/// Count `expr`, returning its (depth, multiplicative depth).
+fn visit(
+    expr: &ObliExpr,
+    stats: &mut Stats,
+    scope: &mut Vec<(Symbol, (usize, usize))>,
+) -> (usize, usize) {
+    stats.nodes += 1;
+    if expr.is_secret() {
+        stats.secret_nodes += 1;
+    }
+
+    let op = match expr {
+        ObliExpr::BinOp { op, .. } => Some(binop_name(op)),
+        ObliExpr::UnaryOp { op, .. } => Some(unaryop_name(op)),
+        ObliExpr::CtCast { .. } => Some("cast"),
+        _ => None,
+    };
+    if let Some(op) = op {
+        let counts = if expr.is_secret() {
+            &mut stats.secret_ops
+        } else {
+            &mut stats.public_ops
+        };
+        *counts.entry(op).or_insert(0) += 1;
+    }
+    match expr {
+        ObliExpr::CtSelect { .. } => stats.muxes += 1,
+        ObliExpr::PubIf { .. } => stats.public_branches += 1,
+        ObliExpr::CtSwap { cond, .. } if cond.is_secret() => stats.muxes += 2,
+        ObliExpr::CtSwap { .. } => stats.public_branches += 1,
+        _ => {}
+    }
+
+    let (depth, mult_depth) = match expr {
+        ObliExpr::Var { name, .. } => {
+            return scope
+                .iter()
+                .rev()
+                .find(|(n, _)| n == name)
+                .map_or((0, 0), |(_, d)| *d)
+        }
+        ObliExpr::Let {
+            name, value, body, ..
+        } => {
+            let bound = visit(value, stats, scope);
+            scope.push((*name, bound));
+            let result = visit(body, stats, scope);
+            scope.pop();
+            return result;
+        }
+        ObliExpr::While {
+            cond,
+            acc,
+            init,
+            body,
+            ..
+        } => {
+            scope.push((*acc, (0, 0)));
+            visit(cond, stats, scope);
+            scope.pop();
+            let bound = visit(init, stats, scope);
+            scope.push((*acc, bound));
+            let (d, m) = visit(body, stats, scope);
+            scope.pop();
+            return (d.max(bound.0), m.max(bound.1));
+        }
+        ObliExpr::CtSwap {
+            cond, names, body, ..
+        } => {
+            let operands = expr.children()[..3].iter().map(|e| visit(e, stats, scope)).fold(
+                (0, 0),
+                |(d, m), (cd, cm)| (d.max(cd), m.max(cm)),
+            );
+            let bound = match (cond.is_secret(), operands) {
+                (true, (d, m)) => (d + 1, m + 1),
+                (false, operands) => operands,
+            };
+            scope.extend(names.map(|name| (name, bound)));
+            let result = visit(body, stats, scope);
+            scope.truncate(scope.len() - 2);
+            return result;
+        }
+        _ => expr
+            .children()
+            .into_iter()
+            .map(|child| visit(child, stats, scope))
+            .fold((0, 0), |(d, m), (cd, cm)| (d.max(cd), m.max(cm))),
+    };
+
+    if !expr.is_secret() {
+        return (depth, mult_depth);
+    }
+    match (expr, op) {
+        (ObliExpr::CtSelect { .. }, _) => (depth + 1, mult_depth + 1),
+        (_, Some(op)) if is_nonlinear(op) => (depth + 1, mult_depth + 1),
+        (_, Some(_)) => (depth + 1, mult_depth),
+        _ => (depth, mult_depth),
+    }
+}
+
+/// Per-operation costs of a kind of target.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CostModel {
+    pub name: &'static str,
+    /// What a unit of cost is.
+    pub unit: &'static str,
+    /// Whether public operations cost anything (they are free on targets
+    /// that compute them in the clear, outside the protected computation).
+    pub public_ops_cost: bool,
+    pub add: u64,
+    pub mul: u64,
+    pub div: u64,
+    pub eq: u64,
+    pub cmp: u64,
+    pub logic: u64,
+    /// A bitwise operator or shift on a whole word.
+    pub bitwise: u64,
+    pub neg: u64,
+    pub not: u64,
+    pub mux: u64,
+}
+
+/// Native code from the Rust emitter (rough cycle counts for 64-bit
+/// constant-time operations).
+pub const RUST_CT: CostModel = CostModel {
+    name: "rust-ct",
+    unit: "cycles",
+    public_ops_cost: true,
+    add: 1,
+    mul: 3,
+    div: 40,
+    eq: 3,
+    cmp: 3,
+    logic: 1,
+    bitwise: 1,
+    neg: 1,
+    not: 1,
+    mux: 4,
+};
+
+/// Boolean circuits over 64-bit words, counting AND gates (XOR is free),
+/// as in garbled circuits and GMW.
+pub const BOOLEAN_CIRCUIT: CostModel = CostModel {
+    name: "boolean-circuit",
+    unit: "AND gates",
+    public_ops_cost: false,
+    add: 63,
+    mul: 4032,
+    div: 8192,
+    eq: 63,
+    cmp: 64,
+    logic: 1,
+    bitwise: 64,
+    neg: 63,
+    not: 0,
+    mux: 64,
+};
+
+/// Arithmetic circuits over a 64-bit ring, counting multiplications
+/// (additions are free), as in secret-sharing MPC and arithmetic FHE.
+pub const ARITHMETIC_CIRCUIT: CostModel = CostModel {
+    name: "arithmetic-circuit",
+    unit: "multiplications",
+    public_ops_cost: false,
+    add: 0,
+    mul: 1,
+    div: 256,
+    eq: 64,
+    cmp: 64,
+    logic: 1,
+    bitwise: 64,
+    neg: 0,
+    not: 0,
+    mux: 1,
+};
+
+/// Built-in cost models.
+pub const COST_MODELS: &[CostModel] = &[RUST_CT, BOOLEAN_CIRCUIT, ARITHMETIC_CIRCUIT];
+
+impl CostModel {
+    fn op_cost(&self, op: &str) -> u64 {
+        match op {
+            "add" | "sub" => self.add,
+            "mul" => self.mul,
+            "div" | "mod" => self.div,
+            "eq" | "ne" => self.eq,
+            "lt" | "le" | "gt" | "ge" => self.cmp,
+            "and" | "or" => self.logic,
+            "bit_and" | "bit_or" | "bit_xor" | "shl" | "shr" => self.bitwise,
+            "neg" => self.neg,
+            "not" => self.not,
+            _ => 0,
+        }
+    }
+
+    /// Projected cost of a program with the given statistics.
+    pub fn cost(&self, stats: &Stats) -> u64 {
+        let mut total = stats.muxes as u64 * self.mux;
+        for (op, count) in &stats.secret_ops {
+            total += *count as u64 * self.op_cost(op);
+        }
+        if self.public_ops_cost {
+            for (op, count) in &stats.public_ops {
+                total += *count as u64 * self.op_cost(op);
+            }
+        }
+        total
+    }
+}
+
+impl Stats {
+    /// Render as a JSON object, with projected costs under `costs`.
+    pub fn to_json(&self, models: &[CostModel]) -> String {
+        let counts = |map: &BTreeMap<&str, usize>| {
+            let fields: Vec<_> = map.iter().map(|(k, v)| format!("\"{}\":{}", k, v)).collect();
+            format!("{{{}}}", fields.join(","))
+        };
+        let costs: Vec<_> = models
+            .iter()
+            .map(|m| {
+                let cost = m.cost(self);
+                format!("\"{}\":{{\"cost\":{},\"unit\":\"{}\"}}", m.name, cost, m.unit)
+            })
+            .collect();
+        format!(
+            "{{\"nodes\":{},\"secret_nodes\":{},\"muxes\":{},\"public_branches\":{},\
+             \"secret_ops\":{},\"public_ops\":{},\"depth\":{},\"multiplicative_depth\":{},\
+             \"costs\":{{{}}}}}",
+            self.nodes,
+            self.secret_nodes,
+            self.muxes,
+            self.public_branches,
+            counts(&self.secret_ops),
+            counts(&self.public_ops),
+            self.depth,
+            self.multiplicative_depth,
+            costs.join(","),
+        )
+    }
+
+    /// Render as a human-readable report, with projected costs.
+    pub fn report(&self, models: &[CostModel]) -> String {
+        let mut out = self.to_string();
+        out.push_str("projected cost:\n");
+        for model in models {
+            let _ = writeln!(out, "  {:<20} {:>8} {}", model.name, model.cost(self), model.unit);
+        }
+        out
+    }
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "nodes:                {:>8} ({} secret)", self.nodes, self.secret_nodes)?;
+        writeln!(f, "muxes (CtSelect):     {:>8}", self.muxes)?;
+        writeln!(f, "public branches:      {:>8}", self.public_branches)?;
+        for (title, ops) in [("secret ops", &self.secret_ops), ("public ops", &self.public_ops)] {
+            if ops.is_empty() {
+                continue;
+            }
+            writeln!(f, "{}:", title)?;
+            for (op, count) in ops {
+                writeln!(f, "  {:<20}{:>8}", op, count)?;
+            }
+        }
+        writeln!(f, "depth:                {:>8}", self.depth)?;
+        writeln!(f, "multiplicative depth: {:>8}", self.multiplicative_depth)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transpile_to_ir;
+
+    #[test]
+    fn test_counts_and_depth() {
+        let ir = transpile_to_ir("let x = secret(3) let y = x * x + 1 if y > 5 then y else 0")
+            .unwrap();
+        let stats = stats(&ir);
+        assert_eq!(stats.muxes, 1);
+        assert_eq!(stats.public_branches, 0);
+        let ops: Vec<_> = stats.secret_ops.iter().map(|(k, v)| (*k, *v)).collect();
+        assert_eq!(ops, vec![("add", 1), ("gt", 1), ("mul", 1)]);
+        // mul -> add -> gt -> mux
+        assert_eq!(stats.depth, 4);
+        assert_eq!(stats.multiplicative_depth, 2);
+    }
+
+    #[test]
+    fn test_public_program_costs_nothing_in_circuits() {
+        let stats = stats(&transpile_to_ir("1 + 2 * 3").unwrap());
+        assert_eq!(stats.depth, 0);
+        assert_eq!(BOOLEAN_CIRCUIT.cost(&stats), 0);
+        assert_eq!(RUST_CT.cost(&stats), 4);
+    }
+
+    #[test]
+    fn test_json() {
+        let stats = stats(&transpile_to_ir("secret(1) + 2").unwrap());
+        let json = stats.to_json(&[ARITHMETIC_CIRCUIT]);
+        assert!(json.starts_with("{\"nodes\":3,\"secret_nodes\":2,\"muxes\":0,"));
+        assert!(json.contains("\"secret_ops\":{\"add\":1}"));
+        assert!(json.ends_with(
+            "\"costs\":{\"arithmetic-circuit\":{\"cost\":0,\"unit\":\"multiplications\"}}}"
+        ));
+    }
+}
@@ -0,0 +1,288 @@
+// SPDX-License-Identifier: MIT OR AGPL-3.0-or-later
+// SPDX-FileCopyrightText: 2024-2025 hyperpolymath
+
+//! Interned identifiers.
+//!
+//! Variable names in tokens, the AST and the IR are [`Symbol`]s: a `u32`
+//! index into a process-wide table of names. Copying one is free, and
+//! equality and hashing look at the index only, so environments keyed by
+//! symbol hash a `u32`. Ordering compares the names, so maps and sets of
+//! symbols iterate in the same order as they would for the strings.
+//!
+//! Interned names live for the rest of the process.
+//!
+//! [`Scope`] is the environment the pipeline stages keep while walking a
+//! tree: lookups hash the symbol instead of scanning the enclosing `let`s.
+//! [`NameSupply`] hands out the temporaries that stages introduce.
+
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::ops::Deref;
+use std::sync::{OnceLock, RwLock};
+
+/// An interned identifier.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Symbol(u32);
+
+#[derive(Default)]
+struct Interner {
+    names: Vec<&'static str>,
+    ids: HashMap<&'static str, Symbol>,
+}
+
+fn interner() -> &'static RwLock<Interner> {
+    static INTERNER: OnceLock<RwLock<Interner>> = OnceLock::new();
+    INTERNER.get_or_init(Default::default)
+}
+
+impl Symbol {
+    /// The symbol for `name`, adding it to the table if it is new.
+    pub fn intern(name: &str) -> Symbol {
+        if let Some(&symbol) = interner().read().unwrap().ids.get(name) {
+            return symbol;
+        }
+        let mut interner = interner().write().unwrap();
+        if let Some(&symbol) = interner.ids.get(name) {
+            return symbol;
+        }
+        let symbol = Symbol(interner.names.len() as u32);
+        let name: &'static str = Box::leak(name.into());
+        interner.names.push(name);
+        interner.ids.insert(name, symbol);
+        symbol
+    }
+
+    pub fn as_str(self) -> &'static str {
+        interner().read().unwrap().names[self.0 as usize]
+    }
+
+    /// Index of the symbol in the table.
+    pub fn as_u32(self) -> u32 {
+        self.0
+    }
+}
+
+impl Deref for Symbol {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl AsRef<str> for Symbol {
+    fn as_ref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl PartialOrd for Symbol {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Symbol {
+    fn cmp(&self, other: &Self) -> Ordering {
+        if self == other {
+            return Ordering::Equal;
+        }
+        self.as_str().cmp(other.as_str())
+    }
+}
+
+impl From<&str> for Symbol {
+    fn from(name: &str) -> Self {
+        Symbol::intern(name)
+    }
+}
+
+impl From<&String> for Symbol {
+    fn from(name: &String) -> Self {
+        Symbol::intern(name)
+    }
+}
+
+impl From<String> for Symbol {
+    fn from(name: String) -> Self {
+        Symbol::intern(&name)
+    }
+}
+
+impl From<Symbol> for String {
+    fn from(symbol: Symbol) -> Self {
+        symbol.as_str().to_string()
+    }
+}
+
+impl PartialEq<str> for Symbol {
+    fn eq(&self, other: &str) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl PartialEq<&str> for Symbol {
+    fn eq(&self, other: &&str) -> bool {
+        self.as_str() == *other
+    }
+}
+
+impl PartialEq<String> for Symbol {
+    fn eq(&self, other: &String) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl fmt::Debug for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self.as_str(), f)
+    }
+}
+
+impl fmt::Display for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Lexically scoped bindings of symbols, innermost shadowing outer ones.
+#[derive(Debug, Clone)]
+pub(crate) struct Scope<T> {
+    bindings: HashMap<Symbol, Vec<T>>,
+    /// Bound names, innermost last, to know what [`Scope::pop`] removes.
+    order: Vec<Symbol>,
+}
+
+impl<T> Default for Scope<T> {
+    fn default() -> Self {
+        Self {
+            bindings: HashMap::new(),
+            order: Vec::new(),
+        }
+    }
+}
+
+impl<T> Scope<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, name: Symbol, value: T) {
+        self.bindings.entry(name).or_default().push(value);
+        self.order.push(name);
+    }
+
+    /// Remove the innermost binding.
+    pub fn pop(&mut self) {
+        if let Some(name) = self.order.pop() {
+            if let Some(values) = self.bindings.get_mut(&name) {
+                values.pop();
+            }
+        }
+    }
+
+    /// The innermost binding of `name`.
+    pub fn get(&self, name: Symbol) -> Option<&T> {
+        self.bindings.get(&name).and_then(|values| values.last())
+    }
+}
+
+/// Prefix of the temporaries a [`NameSupply`] generates.
+pub const TEMP_PREFIX: &str = "__obli_t";
+
+/// Fresh names for temporaries: `__obli_t0`, `__obli_t1`, … skipping every
+/// name already taken. Numbering depends only on the order of requests, so
+/// the same program always gets the same names. A stage keeps one supply
+/// per program and seeds it with every name the program uses.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct NameSupply {
+    used: HashSet<Symbol>,
+    next: usize,
+}
+
+impl NameSupply {
+    pub fn new(used: HashSet<Symbol>) -> Self {
+        Self { used, next: 0 }
+    }
+
+    /// A name not used before, by the program or this supply.
+    pub fn fresh(&mut self) -> Symbol {
+        loop {
+            let name = Symbol::from(format!("{}{}", TEMP_PREFIX, self.next));
+            self.next += 1;
+            if self.used.insert(name) {
+                return name;
+            }
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for Symbol {
+    fn serialize<S: serde::Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        s.serialize_str(self.as_str())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Symbol {
+    fn deserialize<D: serde::Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+        String::deserialize(d).map(Symbol::from)
+    }
+}
+
+#[cfg(feature = "arbitrary")]
+impl<'a> arbitrary::Arbitrary<'a> for Symbol {
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        <&str>::arbitrary(u).map(Symbol::intern)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeSet;
+
+    #[test]
+    fn test_interning_is_stable() {
+        let x = Symbol::intern("interned_x");
+        assert_eq!(x, Symbol::from("interned_x".to_string()));
+        assert_ne!(x, Symbol::intern("interned_y"));
+        assert_eq!(x.as_str(), "interned_x");
+        assert_eq!(x, "interned_x");
+        assert_eq!(format!("{} {:?}", x, x), "interned_x \"interned_x\"");
+    }
+
+    #[test]
+    fn test_symbols_order_by_name() {
+        // Interned in the reverse of their name order
+        let names = ["sym_c", "sym_b", "sym_a"];
+        let symbols: BTreeSet<Symbol> = names.iter().map(|n| Symbol::intern(n)).collect();
+        let sorted: Vec<_> = symbols.iter().map(|s| s.as_str()).collect();
+        assert_eq!(sorted, ["sym_a", "sym_b", "sym_c"]);
+    }
+
+    #[test]
+    fn test_scope_shadows() {
+        let (x, y) = (Symbol::intern("x"), Symbol::intern("y"));
+        let mut scope = Scope::new();
+        scope.push(x, 1);
+        scope.push(y, 2);
+        scope.push(x, 3);
+        assert_eq!((scope.get(x), scope.get(y)), (Some(&3), Some(&2)));
+        scope.pop();
+        assert_eq!(scope.get(x), Some(&1));
+        scope.pop();
+        scope.pop();
+        assert_eq!(scope.get(x), None);
+    }
+
+    #[test]
+    fn test_name_supply_skips_used_names() {
+        let used = ["__obli_t1", "x"].iter().map(|n| Symbol::intern(n)).collect();
+        let mut names = NameSupply::new(used);
+        let fresh: Vec<_> = (0..3).map(|_| names.fresh().as_str()).collect();
+        assert_eq!(fresh, ["__obli_t0", "__obli_t2", "__obli_t3"]);
+    }
+}
@@ -0,0 +1,673 @@
+// SPDX-License-Identifier: MIT OR AGPL-3.0-or-later
+// SPDX-FileCopyrightText: 2024-2025 hyperpolymath
+
+//! Symbolic execution of oblivious IR.
+//!
+//! [`SymbolicProgram::new`] runs a program with chosen free variables left
+//! as symbols. Operations on symbols build [`Term`]s, a `CtSelect` on a
+//! symbolic condition becomes a select term, and a `PubIf` on one forks
+//! execution, giving one [`Path`] per feasible combination of branch
+//! decisions with its path condition.
+//!
+//! There is no solver: [`SymbolicProgram::prove`] and
+//! [`SymbolicProgram::prove_independent`] check a property for every
+//! assignment of the symbols over finite domains, and return the first
+//! counterexample. That is a proof for bounded domains such as booleans,
+//! bytes, or single key bits.
+//!
+//! A `while` loop is unrolled as it runs, forking like a `PubIf` on every
+//! symbolic condition. Without a solver nothing stops a loop on a symbol,
+//! so execution fails once a loop has forked [`MAX_LOOP_FORKS`] times.
+
+use crate::interp::{apply_binop, apply_cast, apply_unaryop, EvalError, Inputs, Value};
+use crate::ir::{ObliBinOp, ObliExpr, ObliUnaryOp};
+use crate::symbol::Symbol;
+use crate::typeck::{Scalar, Type};
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// Times one loop may fork on symbolic conditions.
+pub const MAX_LOOP_FORKS: usize = 256;
+
+/// Values for every symbol.
+pub type Assignment = BTreeMap<String, Value>;
+
+/// A value in terms of the symbols.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Term {
+    Const(Value),
+    Symbol(String),
+    BinOp {
+        op: ObliBinOp,
+        left: Box<Term>,
+        right: Box<Term>,
+    },
+    UnaryOp {
+        op: ObliUnaryOp,
+        expr: Box<Term>,
+    },
+    Cast {
+        to: Scalar,
+        expr: Box<Term>,
+    },
+    Select {
+        cond: Box<Term>,
+        then_val: Box<Term>,
+        else_val: Box<Term>,
+    },
+}
+
+impl Term {
+    /// Evaluate under an assignment of every symbol.
+    pub fn eval(&self, assignment: &Assignment) -> Result<Value, EvalError> {
+        match self {
+            Term::Const(value) => Ok(*value),
+            Term::Symbol(name) => assignment
+                .get(name)
+                .copied()
+                .ok_or_else(|| EvalError::UnboundVariable(name.clone())),
+            Term::BinOp { op, left, right } => {
+                apply_binop(op, left.eval(assignment)?, right.eval(assignment)?)
+            }
+            Term::UnaryOp { op, expr } => apply_unaryop(op, expr.eval(assignment)?),
+            Term::Cast { to, expr } => apply_cast(*to, expr.eval(assignment)?),
+            Term::Select {
+                cond,
+                then_val,
+                else_val,
+            } => {
+                // Like ct_select, both arms are evaluated
+                let c = cond.eval(assignment)?;
+                let t = then_val.eval(assignment)?;
+                let e = else_val.eval(assignment)?;
+                Ok(if c == Value::Bool(true) { t } else { e })
+            }
+        }
+    }
+
+    fn binop(op: &ObliBinOp, left: Term, right: Term) -> Result<Term, EvalError> {
+        if let (Term::Const(l), Term::Const(r)) = (&left, &right) {
+            return Ok(Term::Const(apply_binop(op, *l, *r)?));
+        }
+        Ok(Term::BinOp {
+            op: op.clone(),
+            left: Box::new(left),
+            right: Box::new(right),
+        })
+    }
+}
+
+impl fmt::Display for Term {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Term::Const(value) => write!(f, "{}", value),
+            Term::Symbol(name) => write!(f, "{}", name),
+            Term::BinOp { op, left, right } => write!(f, "({} {} {})", left, symbol(op), right),
+            Term::UnaryOp {
+                op: ObliUnaryOp::CtNeg,
+                expr,
+            } => write!(f, "-{}", expr),
+            Term::UnaryOp {
+                op: ObliUnaryOp::CtNot,
+                expr,
+            } => write!(f, "!{}", expr),
+            Term::UnaryOp {
+                op: ObliUnaryOp::CtToInt,
+                expr,
+            } => write!(f, "to_int({})", expr),
+            Term::UnaryOp {
+                op: ObliUnaryOp::CtToBool,
+                expr,
+            } => write!(f, "to_bool({})", expr),
+            Term::Cast { to, expr } => write!(f, "({} as {})", expr, Type::from(*to)),
+            Term::Select {
+                cond,
+                then_val,
+                else_val,
+            } => write!(f, "select({}, {}, {})", cond, then_val, else_val),
+        }
+    }
+}
+
+fn symbol(op: &ObliBinOp) -> &'static str {
+    match op {
+        ObliBinOp::CtAdd => "+",
+        ObliBinOp::CtSub => "-",
+        ObliBinOp::CtMul => "*",
+        ObliBinOp::CtAddSat => "+|",
+        ObliBinOp::CtSubSat => "-|",
+        ObliBinOp::CtMulSat => "*|",
+        ObliBinOp::CtDiv => "/",
+        ObliBinOp::CtMod => "%",
+        ObliBinOp::CtBitAnd => "&",
+        ObliBinOp::CtBitOr => "|",
+        ObliBinOp::CtBitXor => "^",
+        ObliBinOp::CtShl => "<<",
+        ObliBinOp::CtShr => ">>",
+        ObliBinOp::CtEq => "==",
+        ObliBinOp::CtNe => "!=",
+        ObliBinOp::CtLt => "<",
+        ObliBinOp::CtLe => "<=",
+        ObliBinOp::CtGt => ">",
+        ObliBinOp::CtGe => ">=",
+        ObliBinOp::CtAnd => "&&",
+        ObliBinOp::CtOr => "||",
+    }
+}
+
+/// One execution path.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Path {
+    /// Branch decisions taken: each term evaluated to the given boolean.
+    pub conditions: Vec<(Term, bool)>,
+    /// Conditions of the assertions checked: an assignment for which one
+    /// is false fails.
+    pub assertions: Vec<Term>,
+    pub result: Term,
+}
+
+impl Path {
+    /// Whether `assignment` follows this path.
+    pub fn feasible(&self, assignment: &Assignment) -> Result<bool, EvalError> {
+        for (cond, expected) in &self.conditions {
+            if cond.eval(assignment)? != Value::Bool(*expected) {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    /// Whether every assertion on this path holds for `assignment`.
+    pub fn holds(&self, assignment: &Assignment) -> Result<bool, EvalError> {
+        for cond in &self.assertions {
+            if cond.eval(assignment)? != Value::Bool(true) {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+}
+
+/// Result of a bounded proof.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Verdict {
+    /// The property held for every assignment.
+    Holds { cases: usize },
+    Counterexample {
+        assignment: Assignment,
+        /// What the program computed (or how it failed) for `assignment`.
+        output: Result<Value, EvalError>,
+    },
+}
+
+/// A program executed with some of its free variables as symbols.
+#[derive(Debug, Clone)]
+pub struct SymbolicProgram {
+    pub paths: Vec<Path>,
+}
+
+impl SymbolicProgram {
+    /// Execute `expr` symbolically: free variables named in `symbols` stay
+    /// symbolic, others take their value from `inputs`. Fails on errors
+    /// that happen whatever the symbols are, such as `1 / 0`.
+    pub fn new(expr: &ObliExpr, symbols: &[&str], inputs: &Inputs) -> Result<Self, EvalError> {
+        let mut executor = Executor {
+            symbols,
+            inputs,
+            scope: Vec::new(),
+        };
+        let paths = executor.run(expr, Vec::new(), Vec::new())?;
+        Ok(Self { paths })
+    }
+
+    /// The program's output for a full assignment of the symbols.
+    pub fn eval(&self, assignment: &Assignment) -> Result<Value, EvalError> {
+        for path in &self.paths {
+            if path.feasible(assignment)? {
+                if !path.holds(assignment)? {
+                    return Err(EvalError::AssertionFailed);
+                }
+                return path.result.eval(assignment);
+            }
+        }
+        unreachable!("the path conditions of a program cover every assignment")
+    }
+
+    /// Check `property` of the output for every assignment of the symbols
+    /// drawn from `domains`.
+    pub fn prove(
+        &self,
+        domains: &[(&str, &[Value])],
+        property: impl Fn(&Assignment, Value) -> bool,
+    ) -> Verdict {
+        let assignments = assignments(domains);
+        let cases = assignments.len();
+        for assignment in assignments {
+            match self.eval(&assignment) {
+                Ok(output) if property(&assignment, output) => {}
+                output => return Verdict::Counterexample { assignment, output },
+            }
+        }
+        Verdict::Holds { cases }
+    }
+
+    /// Check that `observe(output)` does not change when `symbol` is
+    /// replaced by `vary(symbol)`, for every assignment from `domains`; e.g.
+    /// that the output's parity is independent of bit 0 of a key.
+    pub fn prove_independent(
+        &self,
+        domains: &[(&str, &[Value])],
+        symbol: &str,
+        vary: impl Fn(Value) -> Value,
+        observe: impl Fn(Value) -> Value,
+    ) -> Verdict {
+        self.prove(domains, |assignment, output| {
+            let mut varied = assignment.clone();
+            if let Some(value) = varied.get_mut(symbol) {
+                *value = vary(*value);
+            }
+            self.eval(&varied)
+                .is_ok_and(|other| observe(other) == observe(output))
+        })
+    }
+}
+
+/// `assertion` made in an arm of a select on `cond`, the `then` arm if
+/// `then_arm`: it only counts where the arm is chosen.
+fn guard(cond: &Term, then_arm: bool, assertion: Term) -> Option<Term> {
+    if let Term::Const(Value::Bool(chosen)) = cond {
+        return (*chosen == then_arm).then_some(assertion);
+    }
+    // The arm is not chosen, or the assertion holds
+    let unchosen = match then_arm {
+        true => Term::UnaryOp {
+            op: ObliUnaryOp::CtNot,
+            expr: Box::new(cond.clone()),
+        },
+        false => cond.clone(),
+    };
+    Some(Term::BinOp {
+        op: ObliBinOp::CtOr,
+        left: Box::new(unchosen),
+        right: Box::new(assertion),
+    })
+}
+
+/// Every combination of values from `domains`.
+fn assignments(domains: &[(&str, &[Value])]) -> Vec<Assignment> {
+    let mut all = vec![Assignment::new()];
+    for (name, values) in domains {
+        all = all
+            .into_iter()
+            .flat_map(|partial| {
+                values.iter().map(move |value| {
+                    let mut assignment = partial.clone();
+                    assignment.insert(name.to_string(), *value);
+                    assignment
+                })
+            })
+            .collect();
+    }
+    all
+}
+
+struct Executor<'a> {
+    symbols: &'a [&'a str],
+    inputs: &'a Inputs,
+    scope: Vec<(Symbol, Term)>,
+}
+
+impl Executor<'_> {
+    /// Execute `expr` under `conditions` and after `assertions`,
+    /// returning every path from there.
+    fn run(
+        &mut self,
+        expr: &ObliExpr,
+        conditions: Vec<(Term, bool)>,
+        assertions: Vec<Term>,
+    ) -> Result<Vec<Path>, EvalError> {
+        let value = |result: Term| {
+            Ok(vec![Path {
+                conditions: conditions.clone(),
+                assertions: assertions.clone(),
+                result,
+            }])
+        };
+        match expr {
+            ObliExpr::PubInt(n) | ObliExpr::SecretInt(n) => value(Term::Const(Value::Int(*n))),
+            ObliExpr::PubBool(b) | ObliExpr::SecretBool(b) => value(Term::Const(Value::Bool(*b))),
+            &ObliExpr::Sized { value: n, width, .. } => value(Term::Const(Value::Sized(n, width))),
+            ObliExpr::Var { name, .. } => {
+                let bound = self.scope.iter().rev().find(|(n, _)| n == name);
+                let term = match bound {
+                    Some((_, term)) => term.clone(),
+                    None if self.symbols.contains(&name.as_str()) => Term::Symbol(name.to_string()),
+                    None => match self.inputs.get(*name) {
+                        Some(input) => Term::Const(input.value),
+                        None => return Err(EvalError::UnboundVariable(name.to_string())),
+                    },
+                };
+                value(term)
+            }
+            &ObliExpr::Input { name, ty, .. } => match self.symbols.contains(&name.as_str()) {
+                true => value(Term::Symbol(name.to_string())),
+                false => value(Term::Const(self.inputs.read(name, ty)?)),
+            },
+            ObliExpr::BinOp { op, left, right, .. } => {
+                let mut paths = Vec::new();
+                for l in self.run(left, conditions, assertions)? {
+                    for r in self.run(right, l.conditions, l.assertions)? {
+                        paths.push(Path {
+                            conditions: r.conditions,
+                            assertions: r.assertions,
+                            result: Term::binop(op, l.result.clone(), r.result)?,
+                        });
+                    }
+                }
+                Ok(paths)
+            }
+            ObliExpr::UnaryOp { op, expr, .. } => {
+                let mut paths = self.run(expr, conditions, assertions)?;
+                for path in &mut paths {
+                    let inner = std::mem::replace(&mut path.result, Term::Const(Value::Int(0)));
+                    path.result = match inner {
+                        Term::Const(v) => Term::Const(apply_unaryop(op, v)?),
+                        inner => Term::UnaryOp {
+                            op: op.clone(),
+                            expr: Box::new(inner),
+                        },
+                    };
+                }
+                Ok(paths)
+            }
+            ObliExpr::CtCast { expr, to, .. } => {
+                let mut paths = self.run(expr, conditions, assertions)?;
+                for path in &mut paths {
+                    let inner = std::mem::replace(&mut path.result, Term::Const(Value::Int(0)));
+                    path.result = match inner {
+                        Term::Const(v) => Term::Const(apply_cast(*to, v)?),
+                        inner => Term::Cast {
+                            to: *to,
+                            expr: Box::new(inner),
+                        },
+                    };
+                }
+                Ok(paths)
+            }
+            ObliExpr::CtSelect {
+                cond,
+                then_val,
+                else_val,
+            } => {
+                let mut paths = Vec::new();
+                for c in self.run(cond, conditions, assertions)? {
+                    let before = c.assertions.len();
+                    for t in self.run(then_val, c.conditions, c.assertions.clone())? {
+                        let after = t.assertions.len();
+                        for e in self.run(else_val, t.conditions, t.assertions.clone())? {
+                            let result = match &c.result {
+                                Term::Const(Value::Bool(true)) => t.result.clone(),
+                                Term::Const(Value::Bool(false)) => e.result,
+                                cond => Term::Select {
+                                    cond: Box::new(cond.clone()),
+                                    then_val: Box::new(t.result.clone()),
+                                    else_val: Box::new(e.result),
+                                },
+                            };
+                            // Only the assertions of the arm chosen count
+                            let mut assertions = e.assertions;
+                            let arms = assertions.split_off(before);
+                            for (i, assertion) in arms.into_iter().enumerate() {
+                                let arm = i < after - before;
+                                assertions.extend(guard(&c.result, arm, assertion));
+                            }
+                            paths.push(Path {
+                                conditions: e.conditions,
+                                assertions,
+                                result,
+                            });
+                        }
+                    }
+                }
+                Ok(paths)
+            }
+            ObliExpr::PubIf {
+                cond,
+                then_branch,
+                else_branch,
+            } => {
+                let mut paths = Vec::new();
+                for c in self.run(cond, conditions, assertions)? {
+                    match c.result {
+                        Term::Const(Value::Bool(taken)) => {
+                            let branch = if taken { then_branch } else { else_branch };
+                            paths.extend(self.run(branch, c.conditions, c.assertions)?);
+                        }
+                        Term::Const(Value::Int(_) | Value::Sized(..)) => {
+                            return Err(EvalError::TypeMismatch("bool"))
+                        }
+                        cond => {
+                            // A decision on a symbol: follow both ways
+                            for (branch, taken) in [(then_branch, true), (else_branch, false)] {
+                                let mut conditions = c.conditions.clone();
+                                conditions.push((cond.clone(), taken));
+                                paths.extend(self.run(branch, conditions, c.assertions.clone())?);
+                            }
+                        }
+                    }
+                }
+                Ok(paths)
+            }
+            ObliExpr::Let {
+                name, value, body, ..
+            } => {
+                let mut paths = Vec::new();
+                for v in self.run(value, conditions, assertions)? {
+                    self.scope.push((*name, v.result));
+                    let body_paths = self.run(body, v.conditions, v.assertions);
+                    self.scope.pop();
+                    paths.extend(body_paths?);
+                }
+                Ok(paths)
+            }
+            ObliExpr::Assert { cond, body } => {
+                let mut paths = Vec::new();
+                for c in self.run(cond, conditions, assertions)? {
+                    let mut assertions = c.assertions;
+                    match c.result {
+                        Term::Const(Value::Bool(true)) => {}
+                        Term::Const(Value::Int(_) | Value::Sized(..)) => {
+                            return Err(EvalError::TypeMismatch("bool"))
+                        }
+                        cond => assertions.push(cond),
+                    }
+                    paths.extend(self.run(body, c.conditions, assertions)?);
+                }
+                Ok(paths)
+            }
+            ObliExpr::Declassify(inner) => self.run(inner, conditions, assertions),
+            ObliExpr::Outputs(_) => Err(EvalError::TypeMismatch("scalar")),
+            ObliExpr::Table { values, index } => {
+                self.run(&ObliExpr::table_scan(values, index), conditions, assertions)
+            }
+            ObliExpr::While {
+                cond,
+                acc,
+                init,
+                body,
+                ..
+            } => {
+                let mut paths = Vec::new();
+                let mut pending = self.run(init, conditions, assertions)?;
+                let mut forks = 0;
+                while let Some(path) = pending.pop() {
+                    self.scope.push((*acc, path.result.clone()));
+                    let iterations = self.iterate(cond, body, &path, &mut forks);
+                    self.scope.pop();
+                    let (done, next) = iterations?;
+                    paths.extend(done);
+                    pending.extend(next);
+                }
+                Ok(paths)
+            }
+            // Whatever the secrecy of the condition, a select per name
+            ObliExpr::CtSwap {
+                cond,
+                left,
+                right,
+                names,
+                body,
+                ..
+            } => {
+                let mut paths = Vec::new();
+                for c in self.run(cond, conditions, assertions)? {
+                    for l in self.run(left, c.conditions, c.assertions.clone())? {
+                        for r in self.run(right, l.conditions, l.assertions.clone())? {
+                            let select = |then_val: &Term, else_val: &Term| match &c.result {
+                                Term::Const(Value::Bool(true)) => then_val.clone(),
+                                Term::Const(Value::Bool(false)) => else_val.clone(),
+                                cond => Term::Select {
+                                    cond: Box::new(cond.clone()),
+                                    then_val: Box::new(then_val.clone()),
+                                    else_val: Box::new(else_val.clone()),
+                                },
+                            };
+                            let first = select(&r.result, &l.result);
+                            let second = select(&l.result, &r.result);
+                            self.scope.extend(names.iter().copied().zip([first, second]));
+                            let body_paths = self.run(body, r.conditions, r.assertions);
+                            self.scope.truncate(self.scope.len() - 2);
+                            paths.extend(body_paths?);
+                        }
+                    }
+                }
+                Ok(paths)
+            }
+        }
+    }
+
+    /// One iteration of a loop from `path`, with the accumulator in scope:
+    /// the paths on which it stops, and those on which it goes on.
+    fn iterate(
+        &mut self,
+        cond: &ObliExpr,
+        body: &ObliExpr,
+        path: &Path,
+        forks: &mut usize,
+    ) -> Result<(Vec<Path>, Vec<Path>), EvalError> {
+        let (mut done, mut next) = (Vec::new(), Vec::new());
+        for c in self.run(cond, path.conditions.clone(), path.assertions.clone())? {
+            let stop = |conditions, assertions| Path {
+                conditions,
+                assertions,
+                result: path.result.clone(),
+            };
+            match c.result {
+                Term::Const(Value::Bool(true)) => {
+                    next.extend(self.run(body, c.conditions, c.assertions)?)
+                }
+                Term::Const(Value::Bool(false)) => done.push(stop(c.conditions, c.assertions)),
+                Term::Const(Value::Int(_) | Value::Sized(..)) => {
+                    return Err(EvalError::TypeMismatch("bool"))
+                }
+                cond => {
+                    *forks += 1;
+                    if *forks > MAX_LOOP_FORKS {
+                        return Err(EvalError::UnboundedLoop(MAX_LOOP_FORKS));
+                    }
+                    let mut conditions = c.conditions.clone();
+                    conditions.push((cond.clone(), true));
+                    next.extend(self.run(body, conditions, c.assertions.clone())?);
+                    let mut conditions = c.conditions;
+                    conditions.push((cond, false));
+                    done.push(stop(conditions, c.assertions));
+                }
+            }
+        }
+        Ok((done, next))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transpile_to_ir;
+
+    fn ints(range: std::ops::RangeInclusive<i64>) -> Vec<Value> {
+        range.map(Value::Int).collect()
+    }
+
+    #[test]
+    fn test_public_if_on_symbol_forks() {
+        let ir = transpile_to_ir("if k > n then k - n else 0").unwrap();
+        let inputs = Inputs::new().public("n", Value::Int(3));
+        let program = SymbolicProgram::new(&ir, &["k"], &inputs).unwrap();
+        assert_eq!(program.paths.len(), 2);
+        assert_eq!(program.paths[0].conditions[0].0.to_string(), "(k > 3)");
+        assert_eq!(program.paths[0].result.to_string(), "(k - 3)");
+        assert_eq!(program.paths[1].result, Term::Const(Value::Int(0)));
+    }
+
+    #[test]
+    fn test_parity_independent_of_key_bit() {
+        let flip = |v: Value| match v {
+            Value::Int(n) => Value::Int(n ^ 1),
+            other => other,
+        };
+        let parity = |v: Value| match v {
+            Value::Int(n) => Value::Int(n & 1),
+            other => other,
+        };
+        let keys = ints(0..=255);
+        let domains: &[(&str, &[Value])] = &[("k", &keys)];
+        let inputs = Inputs::new().public("m", Value::Int(7));
+
+        let ir = transpile_to_ir("let k = secret(k) k * 2 + m").unwrap();
+        let program = SymbolicProgram::new(&ir, &["k"], &inputs).unwrap();
+        assert_eq!(
+            program.prove_independent(domains, "k", flip, parity),
+            Verdict::Holds { cases: 256 }
+        );
+
+        let ir = transpile_to_ir("let k = secret(k) k + m").unwrap();
+        let program = SymbolicProgram::new(&ir, &["k"], &inputs).unwrap();
+        let Verdict::Counterexample { assignment, .. } =
+            program.prove_independent(domains, "k", flip, parity)
+        else {
+            panic!("parity of k + m depends on bit 0 of k");
+        };
+        assert_eq!(assignment["k"], Value::Int(0));
+    }
+
+    #[test]
+    fn test_assertion_counterexample() {
+        let ir = transpile_to_ir("let k = secret(k) if k > 3 then k / (k - 6) else 0").unwrap();
+        let program = SymbolicProgram::new(&ir, &["k"], &Inputs::new()).unwrap();
+        let keys = ints(-8..=8);
+        let verdict = program.prove(&[("k", &keys)], |_, output| output != Value::Int(5));
+        // Both arms of the select are evaluated, so k = 6 divides by zero
+        assert_eq!(
+            verdict,
+            Verdict::Counterexample {
+                assignment: Assignment::from([("k".to_string(), Value::Int(6))]),
+                output: Err(EvalError::DivisionByZero),
+            }
+        );
+    }
+
+    #[test]
+    fn test_assertions_of_the_chosen_arm() {
+        let source = "let k = secret(k) if k > 0 then (assert(k != 3) k) else 0";
+        let program = SymbolicProgram::new(&transpile_to_ir(source).unwrap(), &["k"], &Inputs::new());
+        let program = program.unwrap();
+        assert_eq!(program.paths[0].assertions[0].to_string(), "(!(k > 0) || (k != 3))");
+        let keys = ints(-4..=4);
+        assert_eq!(
+            program.prove(&[("k", &keys)], |_, _| true),
+            Verdict::Counterexample {
+                assignment: Assignment::from([("k".to_string(), Value::Int(3))]),
+                output: Err(EvalError::AssertionFailed),
+            }
+        );
+    }
+}
@@ -0,0 +1,530 @@
+// SPDX-License-Identifier: MIT OR AGPL-3.0-or-later
+// SPDX-FileCopyrightText: 2024-2025 hyperpolymath
+
+//! Coverage-guided program generation.
+//!
+//! [`features`] lists what a program exercises once compiled: every IR
+//! node kind (with operator and secrecy), interactions such as a secret
+//! branch under a public `if` or a `CtSelect` nested in a `CtSelect`
+//! condition, and which built-in passes change it, alone and in pairs.
+//! [`CoverageGenerator`] grows a corpus from random programs and
+//! mutations of earlier ones, keeping only those that add a feature, so a
+//! small corpus covers what thousands of random programs would. It seeds
+//! fuzzing and regression suites via [`CoverageGenerator::write_corpus`].
+
+use crate::ast::Expr;
+use crate::ir::ObliExpr;
+use crate::passes::{builtin, BUILTIN_PASSES};
+use crate::testing::property::{to_source, Generator};
+use crate::transform::to_oblivious;
+use crate::typeck::{typecheck, Type};
+use std::collections::BTreeSet;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Programs larger than this are not mutated further.
+const MAX_NODES: usize = 200;
+
+/// The features `ast` covers once transformed. Empty for ill-typed programs.
+pub fn features(ast: &Expr) -> BTreeSet<String> {
+    let mut features = BTreeSet::new();
+    if typecheck(ast).is_err() {
+        return features;
+    }
+    let ir = to_oblivious(ast);
+    ir_features(&ir, &mut features);
+
+    for first in BUILTIN_PASSES {
+        let once = run_pass(first, &ir);
+        if once == ir {
+            continue;
+        }
+        features.insert(format!("pass:{}", first));
+        for second in BUILTIN_PASSES.iter().filter(|p| *p != first) {
+            if run_pass(second, &once) != once {
+                features.insert(format!("passes:{}+{}", first, second));
+            }
+        }
+    }
+    features
+}
+
+fn run_pass(name: &str, ir: &ObliExpr) -> ObliExpr {
+    builtin(name).expect("built-in pass").run(ir.clone())
+}
+
+fn ir_features(expr: &ObliExpr, features: &mut BTreeSet<String>) {
+    let secrecy = |secret: bool| if secret { "secret" } else { "public" };
+    let feature = match expr {
+        ObliExpr::PubInt(_) => "PubInt".to_string(),
+        ObliExpr::PubBool(_) => "PubBool".to_string(),
+        ObliExpr::SecretInt(_) => "SecretInt".to_string(),
+        ObliExpr::SecretBool(_) => "SecretBool".to_string(),
+        ObliExpr::Sized { width, is_secret, .. } => {
+            format!("Sized:{}:{}", width, secrecy(*is_secret))
+        }
+        ObliExpr::Var { is_secret, .. } => format!("Var:{}", secrecy(*is_secret)),
+        ObliExpr::Input { is_secret, .. } => format!("Input:{}", secrecy(*is_secret)),
+        ObliExpr::BinOp { op, is_secret, .. } => format!("{:?}:{}", op, secrecy(*is_secret)),
+        ObliExpr::UnaryOp { op, is_secret, .. } => format!("{:?}:{}", op, secrecy(*is_secret)),
+        ObliExpr::CtCast { to, is_secret, .. } => {
+            format!("CtCast:{}:{}", Type::from(*to), secrecy(*is_secret))
+        }
+        ObliExpr::CtSelect {
+            cond,
+            then_val,
+            else_val,
+        } => {
+            if contains_select(cond) {
+                features.insert("select-in-select-condition".to_string());
+            }
+            if contains_pub_if(then_val) || contains_pub_if(else_val) {
+                features.insert("public-if-in-select".to_string());
+            }
+            "CtSelect".to_string()
+        }
+        ObliExpr::PubIf {
+            then_branch,
+            else_branch,
+            ..
+        } => {
+            if then_branch.is_secret() || else_branch.is_secret() {
+                features.insert("secret-in-public-if".to_string());
+            }
+            "PubIf".to_string()
+        }
+        ObliExpr::Let { is_secret, .. } => format!("Let:{}", secrecy(*is_secret)),
+        ObliExpr::Outputs(_) => "Outputs".to_string(),
+        ObliExpr::Table { index, .. } => format!("Table:{}", secrecy(index.is_secret())),
+        ObliExpr::While { is_secret, .. } => format!("While:{}", secrecy(*is_secret)),
+        ObliExpr::CtSwap { cond, .. } => format!("CtSwap:{}", secrecy(cond.is_secret())),
+        ObliExpr::Assert { cond, .. } => format!("Assert:{}", secrecy(cond.is_secret())),
+        ObliExpr::Declassify(inner) => format!("Declassify:{}", secrecy(inner.is_secret())),
+    };
+    features.insert(format!("node:{}", feature));
+    for child in expr.children() {
+        ir_features(child, features);
+    }
+}
+
+fn contains_select(expr: &ObliExpr) -> bool {
+    expr.post_order().iter().any(|e| matches!(e, ObliExpr::CtSelect { .. }))
+}
+
+fn contains_pub_if(expr: &ObliExpr) -> bool {
+    expr.post_order().iter().any(|e| matches!(e, ObliExpr::PubIf { .. }))
+}
+
+/// Grows a corpus of programs that each add coverage.
+#[derive(Debug, Clone)]
+pub struct CoverageGenerator {
+    generator: Generator,
+    corpus: Vec<Expr>,
+    covered: BTreeSet<String>,
+}
+
+impl CoverageGenerator {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            generator: Generator::new(seed),
+            corpus: Vec::new(),
+            covered: BTreeSet::new(),
+        }
+    }
+
+    /// Try `attempts` candidates, returning how many joined the corpus.
+    pub fn grow(&mut self, attempts: usize) -> usize {
+        let before = self.corpus.len();
+        for _ in 0..attempts {
+            let candidate = match self.corpus.len() {
+                0 => self.generator.program(),
+                _ if self.generator.below(3) == 0 => self.generator.program(),
+                n => {
+                    let parent = self.corpus[self.generator.below(n)].clone();
+                    self.mutate(parent)
+                }
+            };
+            let features = features(&candidate);
+            if !features.is_subset(&self.covered) {
+                self.covered.extend(features);
+                self.corpus.push(candidate);
+            }
+        }
+        self.corpus.len() - before
+    }
+
+    pub fn corpus(&self) -> &[Expr] {
+        &self.corpus
+    }
+
+    /// Every feature some program in the corpus covers.
+    pub fn covered(&self) -> &BTreeSet<String> {
+        &self.covered
+    }
+
+    /// Write the corpus to `dir` as `cov-NNNN.mobli` files.
+    pub fn write_corpus(&self, dir: &Path) -> io::Result<Vec<PathBuf>> {
+        fs::create_dir_all(dir)?;
+        let mut paths = Vec::new();
+        for (i, program) in self.corpus.iter().enumerate() {
+            let path = dir.join(format!("cov-{:04}.mobli", i));
+            fs::write(&path, to_source(program) + "\n")?;
+            paths.push(path);
+        }
+        Ok(paths)
+    }
+
+    /// Rewrite one node of `program`, keeping it well-typed: make it
+    /// secret, put it under an `if` (both branches the node itself), or
+    /// nest an `if` in its condition.
+    fn mutate(&mut self, program: Expr) -> Expr {
+        let size = count_nodes(&program);
+        if size > MAX_NODES {
+            return self.generator.program();
+        }
+        let mut target = self.generator.below(size);
+        let kind = self.generator.below(3);
+        let cond = self.condition();
+        rewrite(program, &mut target, &mut |node| match (kind, node) {
+            (0, node) => Expr::Secret(Box::new(node)),
+            (
+                1,
+                Expr::If {
+                    cond: inner,
+                    then_branch,
+                    else_branch,
+                },
+            ) => Expr::If {
+                cond: Box::new(Expr::If {
+                    cond: Box::new(cond.clone()),
+                    then_branch: inner.clone(),
+                    else_branch: inner,
+                }),
+                then_branch,
+                else_branch,
+            },
+            (_, node) => Expr::If {
+                cond: Box::new(cond.clone()),
+                then_branch: Box::new(node.clone()),
+                else_branch: Box::new(node),
+            },
+        })
+    }
+
+    /// A random closed boolean: public, secret, or a secret selection.
+    fn condition(&mut self) -> Expr {
+        let literal = |b: bool| Expr::Bool(b);
+        let b = self.generator.below(2) == 0;
+        match self.generator.below(3) {
+            0 => literal(b),
+            1 => Expr::Secret(Box::new(literal(b))),
+            _ => Expr::If {
+                cond: Box::new(Expr::Secret(Box::new(literal(b)))),
+                then_branch: Box::new(literal(!b)),
+                else_branch: Box::new(literal(b)),
+            },
+        }
+    }
+}
+
+fn count_nodes(expr: &Expr) -> usize {
+    1 + expr.children().into_iter().map(count_nodes).sum::<usize>()
+}
+
+/// Apply `f` to the `target`-th node in pre-order.
+fn rewrite(expr: Expr, target: &mut usize, f: &mut dyn FnMut(Expr) -> Expr) -> Expr {
+    if *target == 0 {
+        *target = usize::MAX;
+        return f(expr);
+    }
+    *target = target.saturating_sub(1);
+    let mut sub = |e: Box<Expr>| Box::new(rewrite(*e, target, f));
+    match expr {
+        Expr::Int(_)
+        | Expr::Sized(..)
+        | Expr::Bool(_)
+        | Expr::Var(_)
+        | Expr::Input { .. }
+        | Expr::Bytes(_)
+        | Expr::None => expr,
+        Expr::Secret(inner) => Expr::Secret(sub(inner)),
+        Expr::Some(inner) => Expr::Some(sub(inner)),
+        Expr::Sort(inner) => Expr::Sort(sub(inner)),
+        Expr::Declassify(inner) => Expr::Declassify(sub(inner)),
+        Expr::BinOp { op, left, right } => {
+            let left = sub(left);
+            Expr::BinOp {
+                op,
+                left,
+                right: sub(right),
+            }
+        }
+        Expr::UnaryOp { op, expr } => Expr::UnaryOp { op, expr: sub(expr) },
+        Expr::Cast { expr, ty } => Expr::Cast { expr: sub(expr), ty },
+        Expr::SwapIf { cond, left, right } => {
+            let cond = sub(cond);
+            let left = sub(left);
+            Expr::SwapIf {
+                cond,
+                left,
+                right: sub(right),
+            }
+        }
+        Expr::Clamp { value, lo, hi } => {
+            let value = sub(value);
+            let lo = sub(lo);
+            Expr::Clamp { value, lo, hi: sub(hi) }
+        }
+        Expr::InRange { value, lo, hi } => {
+            let value = sub(value);
+            let lo = sub(lo);
+            Expr::InRange { value, lo, hi: sub(hi) }
+        }
+        Expr::Pow { base, exp } => {
+            let base = sub(base);
+            Expr::Pow { base, exp: sub(exp) }
+        }
+        Expr::Map { array, f } => {
+            let array = sub(array);
+            Expr::Map { array, f: sub(f) }
+        }
+        Expr::Fold { array, init, f } => {
+            let (array, init) = (sub(array), sub(init));
+            Expr::Fold { array, init, f: sub(f) }
+        }
+        Expr::UnwrapOr { option, default } => {
+            let option = sub(option);
+            Expr::UnwrapOr {
+                option,
+                default: sub(default),
+            }
+        }
+        Expr::If {
+            cond,
+            then_branch,
+            else_branch,
+        } => {
+            let cond = sub(cond);
+            let then_branch = sub(then_branch);
+            Expr::If {
+                cond,
+                then_branch,
+                else_branch: sub(else_branch),
+            }
+        }
+        Expr::Let {
+            name,
+            ty,
+            mutable,
+            value,
+            body,
+        } => {
+            let value = sub(value);
+            Expr::Let {
+                name,
+                ty,
+                mutable,
+                value,
+                body: sub(body),
+            }
+        }
+        Expr::Assign {
+            name,
+            cond,
+            value,
+            body,
+        } => {
+            let cond = cond.map(&mut sub);
+            let value = sub(value);
+            Expr::Assign {
+                name,
+                cond,
+                value,
+                body: sub(body),
+            }
+        }
+        Expr::Array(elements) => {
+            Expr::Array(elements.into_iter().map(|e| *sub(Box::new(e))).collect())
+        }
+        Expr::Tuple(elements) => {
+            Expr::Tuple(elements.into_iter().map(|e| *sub(Box::new(e))).collect())
+        }
+        Expr::LetTuple { names, value, body } => {
+            let value = sub(value);
+            Expr::LetTuple {
+                names,
+                value,
+                body: sub(body),
+            }
+        }
+        Expr::Index { array, index } => {
+            let array = sub(array);
+            Expr::Index {
+                array,
+                index: sub(index),
+            }
+        }
+        Expr::Match {
+            scrutinee,
+            arms,
+            default,
+        } => {
+            let scrutinee = sub(scrutinee);
+            let arms = arms
+                .into_iter()
+                .map(|(p, e)| {
+                    let p = *sub(Box::new(p));
+                    (p, *sub(Box::new(e)))
+                })
+                .collect();
+            Expr::Match {
+                scrutinee,
+                arms,
+                default: default.map(sub),
+            }
+        }
+        Expr::Lookup {
+            key,
+            entries,
+            default,
+        } => {
+            let key = sub(key);
+            let entries = entries
+                .into_iter()
+                .map(|(k, v)| {
+                    let k = *sub(Box::new(k));
+                    (k, *sub(Box::new(v)))
+                })
+                .collect();
+            Expr::Lookup {
+                key,
+                entries,
+                default: sub(default),
+            }
+        }
+        Expr::Outputs(outputs) => Expr::Outputs(
+            outputs.into_iter().map(|(name, e)| (name, *sub(Box::new(e)))).collect(),
+        ),
+        Expr::Fn {
+            name,
+            max_depth,
+            params,
+            body,
+            rest,
+        } => {
+            let body = sub(body);
+            Expr::Fn {
+                name,
+                max_depth,
+                params,
+                body,
+                rest: sub(rest),
+            }
+        }
+        Expr::Call { name, args } => Expr::Call {
+            name,
+            args: args.into_iter().map(|e| *sub(Box::new(e))).collect(),
+        },
+        Expr::Lambda { params, body } => Expr::Lambda {
+            params,
+            body: sub(body),
+        },
+        Expr::Const { name, value, body } => {
+            let value = sub(value);
+            Expr::Const {
+                name,
+                value,
+                body: sub(body),
+            }
+        }
+        Expr::Enum {
+            name,
+            variants,
+            body,
+        } => Expr::Enum {
+            name,
+            variants,
+            body: sub(body),
+        },
+        Expr::Assert { cond, body } => {
+            let cond = sub(cond);
+            Expr::Assert {
+                cond,
+                body: sub(body),
+            }
+        }
+        Expr::For {
+            var,
+            start,
+            end,
+            acc,
+            init,
+            body,
+        } => {
+            let start = sub(start);
+            let end = sub(end);
+            let init = sub(init);
+            Expr::For {
+                var,
+                start,
+                end,
+                acc,
+                init,
+                body: sub(body),
+            }
+        }
+        Expr::While {
+            cond,
+            acc,
+            init,
+            body,
+        } => {
+            let cond = sub(cond);
+            let init = sub(init);
+            Expr::While {
+                cond,
+                acc,
+                init,
+                body: sub(body),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+
+    fn parse(source: &str) -> Expr {
+        let tokens = Lexer::new(source).tokenize().unwrap().0;
+        Parser::new(&tokens).parse().unwrap()
+    }
+
+    #[test]
+    fn test_interaction_features() {
+        let program = parse("if (if secret(true) then false else true) then 1 else 2");
+        let found = features(&program);
+        assert!(found.contains("select-in-select-condition"));
+        assert!(features(&parse("secret(1) + (2 * 3)")).contains("pass:const-fold"));
+
+        let found = features(&parse("if true then secret(1) else 2"));
+        assert!(found.contains("secret-in-public-if"));
+        assert!(features(&parse("1 + true")).is_empty());
+    }
+
+    #[test]
+    fn test_growth_adds_coverage() {
+        let mut generator = CoverageGenerator::new(3);
+        let added = generator.grow(400);
+        assert_eq!(added, generator.corpus().len());
+        for feature in ["secret-in-public-if", "select-in-select-condition", "node:CtSelect"] {
+            assert!(generator.covered().contains(feature), "{} not covered", feature);
+        }
+        assert!(generator.corpus().iter().all(|p| typecheck(p).is_ok()));
+        // A corpus is much smaller than the attempts that built it
+        assert!(added < 200);
+    }
+}
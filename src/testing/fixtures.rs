@@ -0,0 +1,96 @@
+// SPDX-License-Identifier: MIT OR AGPL-3.0-or-later
+// SPDX-FileCopyrightText: 2024-2025 hyperpolymath
+
+//! Large programs for performance measurements.
+//!
+//! Each generator returns MiniObli source whose size grows linearly with
+//! its argument, in the shapes that stress the pipeline: long `let`
+//! chains as left by unrolling a loop, deeply nested secret `if`s, and
+//! wide sums of independent muxes. The `benches/` suite times every stage
+//! on [`standard`]; the same sources serve as regression fixtures for
+//! tools built on the crate.
+
+/// A named benchmark program.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Fixture {
+    pub name: &'static str,
+    pub source: String,
+}
+
+/// An unrolled loop of `n` iterations, each a secret mux on the previous
+/// one: `let x0 = secret(1) let x1 = if x0 > 0 then x0 + 1 else 0 ...
+/// x{n-1}`.
+pub fn unrolled_loop(n: usize) -> String {
+    let mut source = String::from("let x0 = secret(1)\n");
+    for i in 1..n {
+        let prev = i - 1;
+        source.push_str(&format!("let x{} = if x{} > 0 then x{} + 1 else 0\n", i, prev, prev));
+    }
+    source + &format!("x{}", n.max(1) - 1)
+}
+
+/// `depth` secret `if`s, each nested in the `then` branch of the previous.
+pub fn deep_nesting(depth: usize) -> String {
+    let mut source = String::from("let s = secret(7)\n");
+    for i in 0..depth {
+        source.push_str(&format!("if s > {} then ", i));
+    }
+    source.push('s');
+    for i in (0..depth).rev() {
+        source.push_str(&format!(" else {}", i));
+    }
+    source
+}
+
+/// A sum of `width` independent secret muxes.
+pub fn wide_mux(width: usize) -> String {
+    let mut source = String::from("let s = secret(3)\n");
+    let muxes: Vec<_> = (0..width.max(1))
+        .map(|i| format!("(if s > {} then {} else {})", i, i, i + 1))
+        .collect();
+    source.push_str(&muxes.join("\n+ "));
+    source
+}
+
+/// Every fixture shape at `size`.
+pub fn standard(size: usize) -> Vec<Fixture> {
+    vec![
+        Fixture {
+            name: "unrolled_loop",
+            source: unrolled_loop(size),
+        },
+        Fixture {
+            name: "deep_nesting",
+            source: deep_nesting(size),
+        },
+        Fixture {
+            name: "wide_mux",
+            source: wide_mux(size),
+        },
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interp::{eval_ast, Inputs, Value};
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+
+    fn run(source: &str) -> Value {
+        let (tokens, spans) = Lexer::new(source).tokenize().unwrap();
+        let ast = Parser::with_spans(&tokens, &spans).parse().unwrap();
+        crate::transpile(source).unwrap();
+        eval_ast(&ast, &Inputs::default()).unwrap().value
+    }
+
+    #[test]
+    fn test_fixtures_transpile_and_evaluate() {
+        assert_eq!(run(&unrolled_loop(50)), Value::Int(50));
+        assert_eq!(run(&deep_nesting(5)), Value::Int(7));
+        assert_eq!(run(&deep_nesting(10)), Value::Int(7));
+        // 0..=2 are taken, 3..=5 are not: 0 + 1 + 2 + 4 + 5 + 6
+        assert_eq!(run(&wide_mux(6)), Value::Int(18));
+        assert!(standard(20).iter().all(|f| crate::transpile(&f.source).is_ok()));
+    }
+}
@@ -0,0 +1,250 @@
+// SPDX-License-Identifier: MIT OR AGPL-3.0-or-later
+// SPDX-FileCopyrightText: 2024-2025 hyperpolymath
+
+//! Golden (snapshot) tests for emitters.
+//!
+//! A corpus is a directory of `.mobli` programs. For each back end, the
+//! expected output of `dir/name.mobli` lives in `dir/<backend>/name.<ext>`.
+//! [`check_corpus`] emits every program and compares; what happens on a
+//! difference depends on the [`Mode`], normally taken from the
+//! `OBLI_GOLDEN` environment variable:
+//!
+//! - `check` (default): report it;
+//! - `review`: also write the new output to `name.<ext>.new` next to the
+//!   golden file, to be diffed and accepted by renaming it;
+//! - `update`: overwrite the golden file.
+//!
+//! ```sh
+//! OBLI_GOLDEN=update cargo test --test golden
+//! ```
+
+use crate::build::find_sources;
+use crate::config::Config;
+use crate::emit::BACKENDS;
+use crate::formatter::diff_labeled;
+use crate::transpile_with;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+/// Environment variable selecting the [`Mode`].
+pub const MODE_VAR: &str = "OBLI_GOLDEN";
+
+/// What to do with output that differs from its golden file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    Check,
+    Review,
+    Update,
+}
+
+impl Mode {
+    /// The mode named by `OBLI_GOLDEN`, [`Mode::Check`] if it is unset.
+    pub fn from_env() -> Result<Self, GoldenError> {
+        match std::env::var(MODE_VAR).ok().as_deref() {
+            None | Some("") | Some("check") => Ok(Mode::Check),
+            Some("review") => Ok(Mode::Review),
+            Some("update") => Ok(Mode::Update),
+            Some(other) => Err(GoldenError::UnknownMode(other.to_string())),
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum GoldenError {
+    #[error("unknown {MODE_VAR} mode `{0}` (expected check, review or update)")]
+    UnknownMode(String),
+    #[error("unknown backend `{0}` (available: {backends})", backends = BACKENDS.join(", "))]
+    UnknownBackend(String),
+    #[error("{}: {source}", path.display())]
+    Io {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+}
+
+/// How one program compared with its golden file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Outcome {
+    Matched,
+    /// The golden file was written (`update` mode).
+    Updated,
+    /// The output differs; `diff` goes from the golden file to the output.
+    Mismatch { diff: String },
+    /// There is no golden file yet.
+    Missing,
+    /// The program failed to compile.
+    Failed(String),
+}
+
+/// Results for a whole corpus, one entry per program in path order.
+#[derive(Debug, Default)]
+pub struct GoldenReport {
+    pub backend: String,
+    pub results: Vec<(PathBuf, Outcome)>,
+}
+
+impl GoldenReport {
+    /// Programs whose output is not (yet) as recorded.
+    pub fn failures(&self) -> impl Iterator<Item = &(PathBuf, Outcome)> {
+        self.results
+            .iter()
+            .filter(|(_, outcome)| !matches!(outcome, Outcome::Matched | Outcome::Updated))
+    }
+
+    pub fn is_ok(&self) -> bool {
+        self.failures().next().is_none()
+    }
+}
+
+impl fmt::Display for GoldenReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (path, outcome) in self.failures() {
+            match outcome {
+                Outcome::Mismatch { diff } => {
+                    writeln!(f, "{}: output differs ({})", path.display(), self.backend)?;
+                    write!(f, "{}", diff)?;
+                }
+                Outcome::Missing => writeln!(
+                    f,
+                    "{}: no golden file; run with {}=update",
+                    path.display(),
+                    MODE_VAR
+                )?,
+                Outcome::Failed(error) => writeln!(f, "{}: {}", path.display(), error)?,
+                Outcome::Matched | Outcome::Updated => {}
+            }
+        }
+        let failed = self.failures().count();
+        write!(f, "{} of {} golden tests failed", failed, self.results.len())
+    }
+}
+
+/// File extension of a back end's output.
+pub fn extension(backend: &str) -> Option<&'static str> {
+    match backend {
+        "rust" => Some("rs"),
+        _ => None,
+    }
+}
+
+/// Compare the output of every program in `dir` for `backend` with its
+/// golden file.
+pub fn check_corpus(dir: &Path, backend: &str, mode: Mode) -> Result<GoldenReport, GoldenError> {
+    let ext = extension(backend).ok_or_else(|| GoldenError::UnknownBackend(backend.to_string()))?;
+    let io_error = |path: &Path| {
+        let path = path.to_path_buf();
+        move |source| GoldenError::Io { path, source }
+    };
+    let sources = find_sources(dir).map_err(|e| GoldenError::Io {
+        path: e.path,
+        source: e.source,
+    })?;
+    let config = Config {
+        backends: vec![backend.to_string()],
+        ..Config::default()
+    };
+
+    let mut report = GoldenReport {
+        backend: backend.to_string(),
+        results: Vec::new(),
+    };
+    for source_path in sources {
+        let relative = source_path.strip_prefix(dir).unwrap_or(&source_path);
+        let golden = dir.join(backend).join(relative).with_extension(ext);
+        let source = fs::read_to_string(&source_path).map_err(io_error(&source_path))?;
+        let output = match transpile_with(&source, &config) {
+            Ok(output) => output,
+            Err(e) => {
+                report.results.push((source_path, Outcome::Failed(e.to_string())));
+                continue;
+            }
+        };
+
+        let expected = match fs::read_to_string(&golden) {
+            Ok(expected) => Some(expected),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+            Err(e) => return Err(io_error(&golden)(e)),
+        };
+        let outcome = match (&expected, mode) {
+            (Some(expected), _) if *expected == output => Outcome::Matched,
+            (_, Mode::Update) => {
+                if let Some(parent) = golden.parent() {
+                    fs::create_dir_all(parent).map_err(io_error(parent))?;
+                }
+                fs::write(&golden, &output).map_err(io_error(&golden))?;
+                Outcome::Updated
+            }
+            (expected, mode) => {
+                if mode == Mode::Review {
+                    let new = golden.with_extension(format!("{}.new", ext));
+                    if let Some(parent) = new.parent() {
+                        fs::create_dir_all(parent).map_err(io_error(parent))?;
+                    }
+                    fs::write(&new, &output).map_err(io_error(&new))?;
+                }
+                match expected {
+                    Some(expected) => Outcome::Mismatch {
+                        diff: diff_labeled(expected, &output, ("golden", "output")),
+                    },
+                    None => Outcome::Missing,
+                }
+            }
+        };
+        report.results.push((source_path, outcome));
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn corpus(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("obli-golden-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("add.mobli"), "secret(1) + 2").unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_update_then_check() {
+        let dir = corpus("update");
+        let report = check_corpus(&dir, "rust", Mode::Check).unwrap();
+        assert_eq!(report.results[0].1, Outcome::Missing);
+        assert!(!report.is_ok());
+
+        let report = check_corpus(&dir, "rust", Mode::Update).unwrap();
+        assert_eq!(report.results[0].1, Outcome::Updated);
+        assert!(dir.join("rust/add.rs").is_file());
+        assert!(check_corpus(&dir, "rust", Mode::Check).unwrap().is_ok());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_review_writes_new_output() {
+        let dir = corpus("review");
+        fs::create_dir_all(dir.join("rust")).unwrap();
+        fs::write(dir.join("rust/add.rs"), "stale\n").unwrap();
+
+        let report = check_corpus(&dir, "rust", Mode::Review).unwrap();
+        let Outcome::Mismatch { diff } = &report.results[0].1 else {
+            panic!("expected a mismatch");
+        };
+        assert!(diff.starts_with("--- golden\n+++ output\n-stale\n"));
+        assert!(dir.join("rust/add.rs.new").is_file());
+        assert_eq!(fs::read_to_string(dir.join("rust/add.rs")).unwrap(), "stale\n");
+        assert!(report.to_string().ends_with("1 of 1 golden tests failed"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_unknown_backend() {
+        let err = check_corpus(Path::new("."), "c", Mode::Check).unwrap_err();
+        assert_eq!(err.to_string(), "unknown backend `c` (available: rust)");
+    }
+}
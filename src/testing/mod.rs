@@ -0,0 +1,12 @@
+// SPDX-License-Identifier: MIT OR AGPL-3.0-or-later
+// SPDX-FileCopyrightText: 2024-2025 hyperpolymath
+
+//! Test support for this crate and for code built on it.
+
+pub mod coverage;
+pub mod fixtures;
+pub mod golden;
+pub mod mutation;
+pub mod property;
+
+pub use mutation::self_check;
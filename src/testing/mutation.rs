@@ -0,0 +1,445 @@
+// SPDX-License-Identifier: MIT OR AGPL-3.0-or-later
+// SPDX-FileCopyrightText: 2024-2025 hyperpolymath
+
+//! Mutation-style self-check of the Rust back end's tests.
+//!
+//! [`self_check`] perturbs one IR node of a program at a time (an
+//! operator, a literal, or the arms of a branch), re-emits it, and runs
+//! the same differential comparison as
+//! [`check_native`](crate::differential::check_native) on every test case.
+//! Nodes inside `while` loops are left alone, so no mutant runs forever.
+//! A mutant is killed when some case notices the change. Survivors show
+//! what the cases cannot tell apart, so the score measures how strong a
+//! set of backend tests is.
+
+use crate::config::Config;
+use crate::emit::emit_rust_with;
+use crate::interp::{eval_ir, Inputs, Labeled};
+use crate::ir::{ObliBinOp, ObliExpr, ObliUnaryOp};
+use crate::run::{prepare, run_emitted, RunError};
+use std::fmt;
+
+/// One perturbation of the program.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mutant {
+    /// Pre-order index of the changed node in the program (input bindings
+    /// excluded).
+    pub node: usize,
+    pub description: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SelfCheckReport {
+    pub mutants: usize,
+    pub killed: usize,
+    /// Mutants no case detected.
+    pub survivors: Vec<Mutant>,
+}
+
+impl SelfCheckReport {
+    /// Fraction of mutants killed (1.0 when there are none).
+    pub fn score(&self) -> f64 {
+        if self.mutants == 0 {
+            return 1.0;
+        }
+        self.killed as f64 / self.mutants as f64
+    }
+}
+
+impl fmt::Display for SelfCheckReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "killed {} of {} mutants ({:.0}%)",
+            self.killed,
+            self.mutants,
+            self.score() * 100.0
+        )?;
+        for mutant in &self.survivors {
+            writeln!(f, "  survived: node {}: {}", mutant.node, mutant.description)?;
+        }
+        Ok(())
+    }
+}
+
+/// Mutate `source` node by node and check each mutant against `cases`,
+/// inputs binding its free variables. Every case compiles and runs the
+/// emitted Rust with `rustc`, so this is slow for large programs.
+pub fn self_check(source: &str, cases: &[Inputs]) -> Result<SelfCheckReport, RunError> {
+    let config = Config::default();
+    let programs = cases
+        .iter()
+        .map(|inputs| {
+            let ir = prepare(source, inputs, &config)?;
+            let expected = eval_ir(&ir, &Inputs::new());
+            // Each input is bound by a leading `let` and its value
+            Ok((ir, expected, 2 * inputs.iter().count()))
+        })
+        .collect::<Result<Vec<_>, RunError>>()?;
+
+    let mut report = SelfCheckReport::default();
+    let Some((first, _, offset)) = programs.first() else {
+        return Ok(report);
+    };
+    let size = first.post_order().len() - offset;
+    for node in 0..size {
+        let Some(description) = mutate(first, node + offset).map(|(d, _)| d) else {
+            continue;
+        };
+        report.mutants += 1;
+        let mut killed = false;
+        for (ir, expected, offset) in &programs {
+            let (_, mutant) = mutate(ir, node + offset).expect("cases share the program");
+            let output = run_emitted(&emit_rust_with(&mutant, &config.emit));
+            if !agrees(expected, output)? {
+                killed = true;
+                break;
+            }
+        }
+        if killed {
+            report.killed += 1;
+        } else {
+            report.survivors.push(Mutant { node, description });
+        }
+    }
+    Ok(report)
+}
+
+/// Whether native `output` matches the interpreter's result.
+fn agrees(
+    expected: &Result<Labeled, crate::interp::EvalError>,
+    output: Result<String, RunError>,
+) -> Result<bool, RunError> {
+    match (expected, output) {
+        (Ok(value), Ok(output)) => Ok(output.trim_end() == format!("Result: {}", value.value)),
+        (Err(_), Err(RunError::Exit(_))) => Ok(true),
+        (_, Ok(_)) | (_, Err(RunError::Exit(_))) => Ok(false),
+        (_, Err(e)) => Err(e),
+    }
+}
+
+/// The tree with its `target`-th node (pre-order) perturbed, if that
+/// node has a mutation.
+fn mutate(expr: &ObliExpr, target: usize) -> Option<(String, ObliExpr)> {
+    let mut index = 0;
+    let mut description = None;
+    let mutant = rebuild(expr, target, &mut index, &mut description);
+    description.map(|d| (d, mutant))
+}
+
+fn rebuild(
+    expr: &ObliExpr,
+    target: usize,
+    index: &mut usize,
+    description: &mut Option<String>,
+) -> ObliExpr {
+    let here = *index == target;
+    *index += 1;
+    if here {
+        if let Some((d, mutant)) = perturb(expr) {
+            *description = Some(d);
+            return mutant;
+        }
+    }
+    let mut sub = |e: &ObliExpr| Box::new(rebuild(e, target, index, description));
+    match expr {
+        ObliExpr::BinOp {
+            op,
+            left,
+            right,
+            is_secret,
+        } => {
+            let left = sub(left);
+            ObliExpr::BinOp {
+                op: op.clone(),
+                left,
+                right: sub(right),
+                is_secret: *is_secret,
+            }
+        }
+        ObliExpr::UnaryOp {
+            op,
+            expr,
+            is_secret,
+        } => ObliExpr::UnaryOp {
+            op: op.clone(),
+            expr: sub(expr),
+            is_secret: *is_secret,
+        },
+        ObliExpr::CtCast {
+            expr,
+            from,
+            to,
+            is_secret,
+        } => ObliExpr::CtCast {
+            expr: sub(expr),
+            from: *from,
+            to: *to,
+            is_secret: *is_secret,
+        },
+        ObliExpr::CtSelect {
+            cond,
+            then_val,
+            else_val,
+        } => {
+            let (cond, then_val) = (sub(cond), sub(then_val));
+            ObliExpr::CtSelect {
+                cond,
+                then_val,
+                else_val: sub(else_val),
+            }
+        }
+        ObliExpr::PubIf {
+            cond,
+            then_branch,
+            else_branch,
+        } => {
+            let (cond, then_branch) = (sub(cond), sub(then_branch));
+            ObliExpr::PubIf {
+                cond,
+                then_branch,
+                else_branch: sub(else_branch),
+            }
+        }
+        ObliExpr::Let {
+            name,
+            value,
+            body,
+            is_secret,
+        } => {
+            let value = sub(value);
+            ObliExpr::Let {
+                name: *name,
+                value,
+                body: sub(body),
+                is_secret: *is_secret,
+            }
+        }
+        ObliExpr::Outputs(outputs) => ObliExpr::Outputs(
+            outputs.iter().map(|(name, value)| (*name, *sub(value))).collect(),
+        ),
+        ObliExpr::Table { values, index } => ObliExpr::Table {
+            values: values.clone(),
+            index: sub(index),
+        },
+        ObliExpr::Declassify(inner) => ObliExpr::Declassify(sub(inner)),
+        ObliExpr::Assert { cond, body } => {
+            let cond = sub(cond);
+            ObliExpr::Assert {
+                cond,
+                body: sub(body),
+            }
+        }
+        ObliExpr::CtSwap {
+            cond,
+            left,
+            right,
+            names,
+            body,
+            is_secret,
+        } => {
+            let (cond, left, right) = (sub(cond), sub(left), sub(right));
+            ObliExpr::CtSwap {
+                cond,
+                left,
+                right,
+                names: *names,
+                body: sub(body),
+                is_secret: *is_secret,
+            }
+        }
+        // A mutant of a loop could run forever, so nothing in one changes
+        ObliExpr::While { .. } => expr.clone(),
+        leaf => leaf.clone(),
+    }
+}
+
+/// A type-preserving change to one node, with a description.
+fn perturb(expr: &ObliExpr) -> Option<(String, ObliExpr)> {
+    use ObliBinOp::*;
+    let mutant = match expr {
+        ObliExpr::PubInt(n) => ObliExpr::PubInt(n.wrapping_add(1)),
+        ObliExpr::SecretInt(n) => ObliExpr::SecretInt(n.wrapping_add(1)),
+        ObliExpr::PubBool(b) => ObliExpr::PubBool(!b),
+        ObliExpr::SecretBool(b) => ObliExpr::SecretBool(!b),
+        &ObliExpr::Sized {
+            value,
+            width,
+            is_secret,
+        } => ObliExpr::Sized {
+            value: width.wrap(width.value(value) + 1),
+            width,
+            is_secret,
+        },
+        ObliExpr::BinOp {
+            op,
+            left,
+            right,
+            is_secret,
+        } => {
+            let swapped = match op {
+                CtAdd => CtSub,
+                CtSub => CtAdd,
+                CtMul => CtAdd,
+                CtAddSat => CtSubSat,
+                CtSubSat => CtAddSat,
+                CtMulSat => CtAddSat,
+                CtDiv => CtMul,
+                CtMod => CtDiv,
+                CtBitAnd => CtBitOr,
+                CtBitOr => CtBitXor,
+                CtBitXor => CtBitAnd,
+                CtShl => CtShr,
+                CtShr => CtShl,
+                CtEq => CtNe,
+                CtNe => CtEq,
+                CtLt => CtLe,
+                CtLe => CtLt,
+                CtGt => CtGe,
+                CtGe => CtGt,
+                CtAnd => CtOr,
+                CtOr => CtAnd,
+            };
+            ObliExpr::BinOp {
+                op: swapped,
+                left: left.clone(),
+                right: right.clone(),
+                is_secret: *is_secret,
+            }
+        }
+        // Negation and `!` keep their operand's type, so drop them
+        ObliExpr::UnaryOp {
+            op: ObliUnaryOp::CtNeg | ObliUnaryOp::CtNot,
+            expr: inner,
+            ..
+        } => (**inner).clone(),
+        // Conversions change the type, so negate their boolean side
+        ObliExpr::UnaryOp {
+            op: ObliUnaryOp::CtToInt,
+            expr: inner,
+            is_secret,
+        } => ObliExpr::UnaryOp {
+            op: ObliUnaryOp::CtToInt,
+            expr: Box::new(ObliExpr::UnaryOp {
+                op: ObliUnaryOp::CtNot,
+                expr: inner.clone(),
+                is_secret: *is_secret,
+            }),
+            is_secret: *is_secret,
+        },
+        &ObliExpr::UnaryOp {
+            op: ObliUnaryOp::CtToBool,
+            is_secret,
+            ..
+        } => ObliExpr::UnaryOp {
+            op: ObliUnaryOp::CtNot,
+            expr: Box::new(expr.clone()),
+            is_secret,
+        },
+        // A cast changes the type, so negate its operand
+        ObliExpr::CtCast {
+            expr: inner,
+            from,
+            to,
+            is_secret,
+        } => ObliExpr::CtCast {
+            expr: Box::new(ObliExpr::UnaryOp {
+                op: ObliUnaryOp::CtNeg,
+                expr: inner.clone(),
+                is_secret: *is_secret,
+            }),
+            from: *from,
+            to: *to,
+            is_secret: *is_secret,
+        },
+        ObliExpr::CtSelect {
+            cond,
+            then_val,
+            else_val,
+        } => ObliExpr::CtSelect {
+            cond: cond.clone(),
+            then_val: else_val.clone(),
+            else_val: then_val.clone(),
+        },
+        ObliExpr::PubIf {
+            cond,
+            then_branch,
+            else_branch,
+        } => ObliExpr::PubIf {
+            cond: cond.clone(),
+            then_branch: else_branch.clone(),
+            else_branch: then_branch.clone(),
+        },
+        // Both names have one type, so bind them the other way round
+        ObliExpr::CtSwap {
+            cond,
+            left,
+            right,
+            names: [first, second],
+            body,
+            is_secret,
+        } => ObliExpr::CtSwap {
+            cond: cond.clone(),
+            left: left.clone(),
+            right: right.clone(),
+            names: [*second, *first],
+            body: body.clone(),
+            is_secret: *is_secret,
+        },
+        // Skip the assertion
+        ObliExpr::Assert { body, .. } => (**body).clone(),
+        ObliExpr::Table { values, index } if values.len() > 1 => {
+            let mut values = values.clone();
+            values.rotate_left(1);
+            ObliExpr::Table {
+                values,
+                index: index.clone(),
+            }
+        }
+        ObliExpr::Var { .. }
+        | ObliExpr::Input { .. }
+        | ObliExpr::Let { .. }
+        | ObliExpr::Outputs(_)
+        | ObliExpr::Table { .. }
+        | ObliExpr::Declassify(_)
+        | ObliExpr::While { .. } => return None,
+    };
+    Some((format!("{} -> {}", expr, mutant), mutant))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interp::Value;
+
+    #[test]
+    fn test_mutants_are_type_preserving() {
+        let ir = crate::transpile_to_ir("if secret(1) < 2 then !true else false").unwrap();
+        let count = ir.post_order().len();
+        let mutants: Vec<_> = (0..count).filter_map(|i| mutate(&ir, i)).collect();
+        assert_eq!(mutants.len(), 7);
+        assert_eq!(mutants[0].0, "(ct_select (ct_lt:s 1:s 2) (ct_not true) false) -> \
+                                  (ct_select (ct_lt:s 1:s 2) false (ct_not true))");
+        for (_, mutant) in mutants {
+            assert!(crate::conformance::run_all(&mutant).is_conformant());
+        }
+    }
+
+    #[test]
+    fn test_weak_cases_leave_survivors() {
+        let source = "if k > 3 then k * 2 else 0";
+        let strong = [
+            Inputs::new().secret("k", Value::Int(4)),
+            Inputs::new().secret("k", Value::Int(3)),
+            Inputs::new().secret("k", Value::Int(1)),
+        ];
+        let report = self_check(source, &strong).unwrap();
+        assert_eq!(report.mutants, 6);
+        assert_eq!(report.killed, 6, "{}", report);
+
+        // With k = 4 alone, `0 -> 1` in the untaken arm goes unnoticed
+        let report = self_check(source, &strong[..1]).unwrap();
+        assert!(report.killed < report.mutants);
+        assert!(report.to_string().contains("survived: node 7: 0 -> 1"), "{}", report);
+    }
+}
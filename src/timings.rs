@@ -0,0 +1,101 @@
+// SPDX-License-Identifier: MIT OR AGPL-3.0-or-later
+// SPDX-FileCopyrightText: 2024-2025 hyperpolymath
+
+//! Per-stage compile timings for `--timings`.
+//!
+//! Each pipeline stage also opens a `tracing` span (`lex`, `parse`,
+//! `transform`, `pass`, `emit`), so the same breakdown is available from any
+//! tracing subscriber, e.g. `OBLI_LOG=debug` with the CLI.
+
+use std::fmt;
+use std::time::{Duration, Instant};
+
+/// Wall-clock time spent in each stage, in first-run order.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Timings {
+    stages: Vec<(String, Duration)>,
+}
+
+impl Timings {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Run `f` as the stage `name`, adding its duration to that stage.
+    pub fn time<T>(&mut self, name: &str, f: impl FnOnce() -> T) -> T {
+        let start = Instant::now();
+        let result = f();
+        self.add(name, start.elapsed());
+        result
+    }
+
+    /// Add `duration` to the stage `name`.
+    pub fn add(&mut self, name: &str, duration: Duration) {
+        match self.stages.iter_mut().find(|(n, _)| n == name) {
+            Some((_, total)) => *total += duration,
+            None => self.stages.push((name.to_string(), duration)),
+        }
+    }
+
+    /// Add every stage of `other` (e.g. to total the timings of many files).
+    pub fn merge(&mut self, other: &Timings) {
+        for (name, duration) in &other.stages {
+            self.add(name, *duration);
+        }
+    }
+
+    pub fn stages(&self) -> impl Iterator<Item = (&str, Duration)> {
+        self.stages.iter().map(|(n, d)| (n.as_str(), *d))
+    }
+
+    pub fn total(&self) -> Duration {
+        self.stages.iter().map(|(_, d)| *d).sum()
+    }
+}
+
+impl fmt::Display for Timings {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let total = self.total();
+        let ms = |d: Duration| d.as_secs_f64() * 1000.0;
+        for (name, duration) in self.stages() {
+            let percent = if total.is_zero() {
+                0.0
+            } else {
+                100.0 * duration.as_secs_f64() / total.as_secs_f64()
+            };
+            writeln!(f, "  {:<24} {:>10.3} ms {:>5.1}%", name, ms(duration), percent)?;
+        }
+        writeln!(f, "  {:<24} {:>10.3} ms", "total", ms(total))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stages_accumulate_in_order() {
+        let mut timings = Timings::new();
+        timings.add("lex", Duration::from_millis(2));
+        timings.add("parse", Duration::from_millis(3));
+        let mut other = Timings::new();
+        other.add("lex", Duration::from_millis(5));
+        timings.merge(&other);
+
+        let stages: Vec<_> = timings.stages().collect();
+        assert_eq!(
+            stages,
+            vec![("lex", Duration::from_millis(7)), ("parse", Duration::from_millis(3))]
+        );
+        assert_eq!(timings.total(), Duration::from_millis(10));
+    }
+
+    #[test]
+    fn test_report() {
+        let mut timings = Timings::new();
+        assert_eq!(timings.time("emit", || 42), 42);
+        let report = timings.to_string();
+        assert!(report.starts_with("  emit "));
+        assert!(report.contains("  total "));
+    }
+}
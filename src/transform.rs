@@ -5,83 +5,303 @@
 //!
 //! This module transforms regular AST expressions into oblivious IR.
 //! The key transformation is replacing `if-then-else` on secret conditions
-//! with constant-time selection (`ct_select`).
+//! with constant-time selection (`ct_select`). Constructs the IR has no
+//! nodes for, such as arrays and `lookup`, are [desugared](crate::desugar)
+//! first.
+//!
+//! A `while` loop runs as many times as its condition says, so it can only
+//! be oblivious if that condition is public. A loop on a secret condition,
+//! like a secret value bound to a variable declared `pub`, is a
+//! [`SecrecyError`], which [`to_oblivious_checked`] reports; the other
+//! entry points keep the IR as it is, and [verification](crate::verify)
+//! rejects it.
+//!
+//! `declassify(e)` is kept as an explicit [`ObliExpr::Declassify`] node,
+//! public whatever `e` is, and [`to_oblivious_with_audit`] lists every one
+//! that reveals a secret.
+//!
+//! A cast `e as u8` becomes an [`ObliExpr::CtCast`], as secret as `e`,
+//! which records the type of `e` as the type checker infers it. So does
+//! every implicit integer conversion, which [`desugar::promote`] first
+//! makes a cast.
 
-use crate::ast::{Expr, UnaryOp};
+use crate::codes;
+use crate::desugar::{self, any_node};
+use crate::ast::Expr;
 use crate::ir::{ObliBinOp, ObliExpr, ObliUnaryOp};
-use std::collections::HashSet;
+use crate::report::Declassification;
+use crate::span::{NodeSpans, Span, SpanCursor};
+use crate::stack;
+use crate::symbol::{Scope, Symbol};
+use crate::typeck::{self, Scalar};
+use std::collections::HashMap;
+use thiserror::Error;
+
+/// A program the transform cannot make oblivious.
+#[derive(Error, Debug, Clone, PartialEq)]
+pub enum SecrecyError {
+    #[error("while loop condition depends on secret data")]
+    SecretLoopCondition { span: Span },
+    #[error("variable `{name}` is declared `pub` but bound to a secret value")]
+    DeclaredPublic { name: String, span: Span },
+}
+
+impl SecrecyError {
+    /// Catalog code of this error.
+    pub fn code(&self) -> &'static str {
+        match self {
+            SecrecyError::SecretLoopCondition { .. } => codes::SECRET_LOOP_CONDITION,
+            SecrecyError::DeclaredPublic { .. } => codes::DECLARED_PUBLIC,
+        }
+    }
+
+    /// Source span of the loop or `let` (empty if spans were not supplied).
+    pub fn span(&self) -> Span {
+        match self {
+            SecrecyError::SecretLoopCondition { span }
+            | SecrecyError::DeclaredPublic { span, .. } => *span,
+        }
+    }
+}
 
 /// Context for tracking which variables are secret.
-struct TransformCtx {
-    secret_vars: HashSet<String>,
+struct TransformCtx<'a> {
+    /// Whether each bound variable is secret; free variables are public.
+    scope: Scope<bool>,
+    ast_spans: SpanCursor<'a>,
+    ir_spans: NodeSpans,
+    /// Whether the node being transformed is the value of a `secret(...)`,
+    /// which [`mark_as_secret`] cannot relabel without adding nodes.
+    marked: bool,
+    /// Every `declassify(...)` of a secret value, in post-order.
+    audit: Vec<Declassification>,
+    /// Operand type of every cast, by the cast.
+    casts: HashMap<*const Expr, Scalar>,
+    /// Every loop and binding that cannot be made oblivious, in post-order.
+    rejected: Vec<SecrecyError>,
 }
 
-impl TransformCtx {
-    fn new() -> Self {
+impl<'a> TransformCtx<'a> {
+    fn new(expr: &Expr, ast_spans: SpanCursor<'a>) -> Self {
+        let has_casts = any_node(expr, |expr| matches!(expr, Expr::Cast { .. }));
         Self {
-            secret_vars: HashSet::new(),
+            scope: Scope::new(),
+            ast_spans,
+            ir_spans: NodeSpans::new(),
+            marked: false,
+            audit: Vec::new(),
+            casts: if has_casts { typeck::cast_sources(expr) } else { HashMap::new() },
+            rejected: Vec::new(),
         }
     }
 
-    fn mark_secret(&mut self, name: &str) {
-        self.secret_vars.insert(name.to_string());
+    /// Consume the span of the AST node just transformed.
+    fn ast_span(&mut self) -> Span {
+        self.ast_spans.next_span().unwrap_or_default()
+    }
+
+    /// Record the span of a newly built IR node.
+    fn ir_node(&mut self, span: Span, expr: ObliExpr) -> ObliExpr {
+        self.ir_spans.push(span);
+        expr
     }
 
-    fn is_secret(&self, name: &str) -> bool {
-        self.secret_vars.contains(name)
+    fn is_secret(&self, name: Symbol) -> bool {
+        self.scope.get(name).is_some_and(|secret| *secret)
     }
 }
 
 /// Transform an AST expression into oblivious IR.
 pub fn to_oblivious(expr: &Expr) -> ObliExpr {
-    let mut ctx = TransformCtx::new();
+    if desugar::needs_desugaring(expr) {
+        return to_oblivious(&desugar::desugar(expr));
+    }
+    if desugar::needs_promoting(expr) {
+        return to_oblivious(&desugar::promote(expr));
+    }
+    let _span = tracing::debug_span!("transform").entered();
+    let mut ctx = TransformCtx::new(expr, SpanCursor::none());
     transform_expr(expr, &mut ctx)
 }
 
+/// Transform an AST expression into oblivious IR, mapping the AST node spans
+/// produced by [`Parser::parse_with_spans`](crate::parser::Parser::parse_with_spans)
+/// onto the IR nodes (again in post-order).
+pub fn to_oblivious_with_spans(expr: &Expr, spans: &NodeSpans) -> (ObliExpr, NodeSpans) {
+    let (ir, ir_spans, _) = to_oblivious_with_audit(expr, spans);
+    (ir, ir_spans)
+}
+
+/// Like [`to_oblivious_with_spans`], also returning the audit list: every
+/// `declassify(...)` that reveals a secret, once per inlined call, at the
+/// span of the `declassify`.
+pub fn to_oblivious_with_audit(
+    expr: &Expr,
+    spans: &NodeSpans,
+) -> (ObliExpr, NodeSpans, Vec<Declassification>) {
+    let (ir, ir_spans, audit, _) = transform_program(expr, spans);
+    (ir, ir_spans, audit)
+}
+
+/// Like [`to_oblivious_with_audit`], but reject a program with a `while`
+/// loop on a secret condition or a secret value declared `pub`, which no
+/// transform makes oblivious.
+pub fn to_oblivious_checked(
+    expr: &Expr,
+    spans: &NodeSpans,
+) -> Result<(ObliExpr, NodeSpans, Vec<Declassification>), Vec<SecrecyError>> {
+    let (ir, ir_spans, audit, rejected) = transform_program(expr, spans);
+    if !rejected.is_empty() {
+        stack::drop_tree(ir);
+        return Err(rejected);
+    }
+    Ok((ir, ir_spans, audit))
+}
+
+/// The IR of `expr`, its spans, its audit list and the secrecy errors.
+fn transform_program(
+    expr: &Expr,
+    spans: &NodeSpans,
+) -> (ObliExpr, NodeSpans, Vec<Declassification>, Vec<SecrecyError>) {
+    if desugar::needs_desugaring(expr) {
+        let (expr, spans) = desugar::desugar_with_spans(expr, spans);
+        return transform_program(&expr, &spans);
+    }
+    if desugar::needs_promoting(expr) {
+        let (expr, spans) = desugar::promote_with_spans(expr, spans);
+        return transform_program(&expr, &spans);
+    }
+    let _span = tracing::debug_span!("transform").entered();
+    let mut ctx = TransformCtx::new(expr, SpanCursor::new(Some(spans)));
+    let ir = transform_expr(expr, &mut ctx);
+    (ir, ctx.ir_spans, ctx.audit, ctx.rejected)
+}
+
 fn transform_expr(expr: &Expr, ctx: &mut TransformCtx) -> ObliExpr {
+    stack::guard(|| transform_node(expr, ctx))
+}
+
+fn transform_node(expr: &Expr, ctx: &mut TransformCtx) -> ObliExpr {
+    let marked = std::mem::take(&mut ctx.marked);
     match expr {
-        Expr::Int(n) => ObliExpr::PubInt(*n),
-        Expr::Bool(b) => ObliExpr::PubBool(*b),
-        Expr::Var(name) => ObliExpr::Var {
-            name: name.clone(),
-            is_secret: ctx.is_secret(name),
-        },
+        Expr::Int(n) => {
+            let span = ctx.ast_span();
+            ctx.ir_node(span, ObliExpr::PubInt(*n))
+        }
+        Expr::Sized(value, width) => {
+            let span = ctx.ast_span();
+            let sized = ObliExpr::Sized {
+                value: *value,
+                width: *width,
+                is_secret: false,
+            };
+            ctx.ir_node(span, sized)
+        }
+        Expr::Bool(b) => {
+            let span = ctx.ast_span();
+            ctx.ir_node(span, ObliExpr::PubBool(*b))
+        }
+        Expr::Var(name) => {
+            let span = ctx.ast_span();
+            let var = ObliExpr::Var {
+                name: *name,
+                is_secret: ctx.is_secret(*name),
+            };
+            ctx.ir_node(span, var)
+        }
+        &Expr::Input { name, secret, ty } => {
+            let span = ctx.ast_span();
+            let input = ObliExpr::Input {
+                name,
+                ty,
+                is_secret: secret,
+            };
+            ctx.ir_node(span, input)
+        }
         Expr::Secret(inner) => {
             // Mark inner value as secret
             match inner.as_ref() {
-                Expr::Int(n) => ObliExpr::SecretInt(*n),
-                Expr::Bool(b) => ObliExpr::SecretBool(*b),
+                Expr::Int(n) => {
+                    ctx.ast_span();
+                    let span = ctx.ast_span();
+                    ctx.ir_node(span, ObliExpr::SecretInt(*n))
+                }
+                Expr::Bool(b) => {
+                    ctx.ast_span();
+                    let span = ctx.ast_span();
+                    ctx.ir_node(span, ObliExpr::SecretBool(*b))
+                }
                 _ => {
-                    // For complex expressions, transform and mark as secret
+                    // For complex expressions, transform and mark as secret;
+                    // the `secret(...)` wrapper itself has no IR node.
+                    ctx.marked = true;
                     let transformed = transform_expr(inner, ctx);
+                    ctx.ast_span();
                     mark_as_secret(transformed)
                 }
             }
         }
+        Expr::Declassify(inner) => {
+            let inner_obli = transform_expr(inner, ctx);
+            let span = ctx.ast_span();
+            // Reclassified at once by the enclosing `secret(...)`, so
+            // nothing is revealed
+            if marked {
+                return inner_obli;
+            }
+            if inner_obli.is_secret() {
+                ctx.audit.push(Declassification {
+                    span,
+                    kind: "declassify",
+                });
+            }
+            ctx.ir_node(span, ObliExpr::Declassify(Box::new(inner_obli)))
+        }
         Expr::BinOp { op, left, right } => {
             let left_obli = transform_expr(left, ctx);
             let right_obli = transform_expr(right, ctx);
             let is_secret = left_obli.is_secret() || right_obli.is_secret();
 
-            ObliExpr::BinOp {
-                op: ObliBinOp::from(op),
-                left: Box::new(left_obli),
-                right: Box::new(right_obli),
-                is_secret,
-            }
+            let span = ctx.ast_span();
+            ctx.ir_node(
+                span,
+                ObliExpr::BinOp {
+                    op: ObliBinOp::from(op),
+                    left: Box::new(left_obli),
+                    right: Box::new(right_obli),
+                    is_secret,
+                },
+            )
         }
         Expr::UnaryOp { op, expr: inner } => {
             let inner_obli = transform_expr(inner, ctx);
             let is_secret = inner_obli.is_secret();
 
-            ObliExpr::UnaryOp {
-                op: match op {
-                    UnaryOp::Neg => ObliUnaryOp::CtNeg,
-                    UnaryOp::Not => ObliUnaryOp::CtNot,
+            let span = ctx.ast_span();
+            ctx.ir_node(
+                span,
+                ObliExpr::UnaryOp {
+                    op: ObliUnaryOp::from(op),
+                    expr: Box::new(inner_obli),
+                    is_secret,
                 },
-                expr: Box::new(inner_obli),
-                is_secret,
-            }
+            )
+        }
+        Expr::Cast { expr: inner, ty } => {
+            let inner_obli = transform_expr(inner, ctx);
+            let is_secret = inner_obli.is_secret();
+            let from = ctx.casts.get(&(expr as *const Expr)).copied();
+
+            let span = ctx.ast_span();
+            ctx.ir_node(
+                span,
+                ObliExpr::CtCast {
+                    expr: Box::new(inner_obli),
+                    from: from.unwrap_or(Scalar::Int),
+                    to: *ty,
+                    is_secret,
+                },
+            )
         }
         Expr::If {
             cond,
@@ -89,11 +309,13 @@ fn transform_expr(expr: &Expr, ctx: &mut TransformCtx) -> ObliExpr {
             else_branch,
         } => {
             let cond_obli = transform_expr(cond, ctx);
+            ctx.marked = marked;
             let then_obli = transform_expr(then_branch, ctx);
+            ctx.marked = marked;
             let else_obli = transform_expr(else_branch, ctx);
 
             // KEY TRANSFORMATION: If condition is secret, use ct_select
-            if cond_obli.is_secret() {
+            let node = if cond_obli.is_secret() {
                 ObliExpr::CtSelect {
                     cond: Box::new(cond_obli),
                     then_val: Box::new(then_obli),
@@ -106,38 +328,203 @@ fn transform_expr(expr: &Expr, ctx: &mut TransformCtx) -> ObliExpr {
                     then_branch: Box::new(then_obli),
                     else_branch: Box::new(else_obli),
                 }
-            }
+            };
+            let span = ctx.ast_span();
+            ctx.ir_node(span, node)
         }
-        Expr::Let { name, value, body } => {
-            let value_obli = transform_expr(value, ctx);
-            let is_secret = value_obli.is_secret();
+        Expr::Let {
+            name,
+            ty,
+            value,
+            body,
+            ..
+        } => {
+            // A `secret` annotation classifies the value as `secret(...)`
+            // does. A `pub` one labels the node public whatever its value,
+            // and a secret value is rejected at the binding
+            let secrecy = ty.and_then(|ty| ty.secret);
+            let value_obli = if secrecy == Some(true) {
+                ctx.marked = true;
+                mark_as_secret(transform_expr(value, ctx))
+            } else {
+                transform_expr(value, ctx)
+            };
+            let is_secret = secrecy.unwrap_or(value_obli.is_secret());
+
+            // The binding takes its value's label until the end of its
+            // body, whatever the label of a variable it shadows
+            ctx.scope.push(*name, value_obli.is_secret());
+            ctx.marked = marked;
+            let body_obli = transform_expr(body, ctx);
+            ctx.scope.pop();
 
-            // Track if this variable is secret
-            if is_secret {
-                ctx.mark_secret(name);
+            let span = ctx.ast_span();
+            if secrecy == Some(false) && value_obli.is_secret() {
+                ctx.rejected.push(SecrecyError::DeclaredPublic {
+                    name: name.to_string(),
+                    span,
+                });
             }
+            ctx.ir_node(
+                span,
+                ObliExpr::Let {
+                    name: *name,
+                    value: Box::new(value_obli),
+                    body: Box::new(body_obli),
+                    is_secret,
+                },
+            )
+        }
+        Expr::While {
+            cond,
+            acc,
+            init,
+            body,
+        } => {
+            // `acc` is secret if its initial value or any iteration's is;
+            // transform again with it secret if the first guess was wrong
+            let ast_spans = ctx.ast_spans.clone();
+            let ir_spans = ctx.ir_spans.len();
+            let rejected = ctx.rejected.len();
+            let mut is_secret = false;
+            let (cond_obli, init_obli, body_obli) = loop {
+                ctx.scope.push(*acc, is_secret);
+                let cond_obli = transform_expr(cond, ctx);
+                ctx.scope.pop();
+                let init_obli = transform_expr(init, ctx);
+                ctx.scope.push(*acc, is_secret);
+                let body_obli = transform_expr(body, ctx);
+                ctx.scope.pop();
+                if is_secret || !(init_obli.is_secret() || body_obli.is_secret()) {
+                    break (cond_obli, init_obli, body_obli);
+                }
+                ctx.ast_spans = ast_spans.clone();
+                ctx.ir_spans.truncate(ir_spans);
+                ctx.rejected.truncate(rejected);
+                is_secret = true;
+            };
+            let span = ctx.ast_span();
+            if cond_obli.is_secret() {
+                ctx.rejected.push(SecrecyError::SecretLoopCondition { span });
+            }
+            let node = ctx.ir_node(
+                span,
+                ObliExpr::While {
+                    cond: Box::new(cond_obli),
+                    acc: *acc,
+                    init: Box::new(init_obli),
+                    body: Box::new(body_obli),
+                    is_secret,
+                },
+            );
+            if !marked || is_secret {
+                return node;
+            }
+            // As `mark_as_secret` does it, with the loop's span
+            let node = mark_as_secret(node);
+            ctx.ir_spans.push(span);
+            ctx.ir_node(span, node)
+        }
+        Expr::LetTuple { names, value, body } => {
+            let (Expr::SwapIf { cond, left, right }, &[first, second]) = (&**value, &names[..])
+            else {
+                unreachable!("desugared before the transform")
+            };
+            let cond_obli = transform_expr(cond, ctx);
+            let left_obli = transform_expr(left, ctx);
+            let right_obli = transform_expr(right, ctx);
+            // The `swap_if` itself has no IR node of its own
+            ctx.ast_span();
+            let is_secret = [&cond_obli, &left_obli, &right_obli].iter().any(|e| e.is_secret());
 
+            ctx.scope.push(first, is_secret);
+            ctx.scope.push(second, is_secret);
+            ctx.marked = marked;
             let body_obli = transform_expr(body, ctx);
+            ctx.scope.pop();
+            ctx.scope.pop();
 
-            ObliExpr::Let {
-                name: name.clone(),
-                value: Box::new(value_obli),
-                body: Box::new(body_obli),
-                is_secret,
-            }
+            let span = ctx.ast_span();
+            ctx.ir_node(
+                span,
+                ObliExpr::CtSwap {
+                    cond: Box::new(cond_obli),
+                    left: Box::new(left_obli),
+                    right: Box::new(right_obli),
+                    names: [first, second],
+                    body: Box::new(body_obli),
+                    is_secret,
+                },
+            )
+        }
+        Expr::Assert { cond, body } => {
+            let cond_obli = transform_expr(cond, ctx);
+            ctx.marked = marked;
+            let body_obli = transform_expr(body, ctx);
+            let span = ctx.ast_span();
+            ctx.ir_node(
+                span,
+                ObliExpr::Assert {
+                    cond: Box::new(cond_obli),
+                    body: Box::new(body_obli),
+                },
+            )
+        }
+        Expr::Outputs(outputs) => {
+            let outputs = outputs
+                .iter()
+                .map(|(name, value)| (*name, transform_expr(value, ctx)))
+                .collect();
+            let span = ctx.ast_span();
+            ctx.ir_node(span, ObliExpr::Outputs(outputs))
+        }
+        Expr::Array(_)
+        | Expr::Tuple(_)
+        | Expr::SwapIf { .. }
+        | Expr::Clamp { .. }
+        | Expr::InRange { .. }
+        | Expr::Pow { .. }
+        | Expr::Map { .. }
+        | Expr::Fold { .. }
+        | Expr::Some(_)
+        | Expr::None
+        | Expr::UnwrapOr { .. }
+        | Expr::Bytes(_)
+        | Expr::Sort(_)
+        | Expr::Index { .. }
+        | Expr::Lookup { .. }
+        | Expr::Match { .. }
+        | Expr::Fn { .. }
+        | Expr::Call { .. }
+        | Expr::Lambda { .. }
+        | Expr::Const { .. }
+        | Expr::Enum { .. }
+        | Expr::For { .. }
+        | Expr::Assign { .. } => {
+            unreachable!("desugared before the transform")
         }
     }
 }
 
 /// Mark an expression as secret (propagate secrecy).
-fn mark_as_secret(expr: ObliExpr) -> ObliExpr {
+pub(crate) fn mark_as_secret(expr: ObliExpr) -> ObliExpr {
     match expr {
         ObliExpr::PubInt(n) => ObliExpr::SecretInt(n),
         ObliExpr::PubBool(b) => ObliExpr::SecretBool(b),
+        ObliExpr::Sized { value, width, .. } => ObliExpr::Sized {
+            value,
+            width,
+            is_secret: true,
+        },
         ObliExpr::Var { name, .. } => ObliExpr::Var {
             name,
             is_secret: true,
         },
+        ObliExpr::Input { name, ty, .. } => ObliExpr::Input {
+            name,
+            ty,
+            is_secret: true,
+        },
         ObliExpr::BinOp {
             op, left, right, ..
         } => ObliExpr::BinOp {
@@ -151,7 +538,81 @@ fn mark_as_secret(expr: ObliExpr) -> ObliExpr {
             expr,
             is_secret: true,
         },
-        other => other,
+        ObliExpr::CtCast { expr, from, to, .. } => ObliExpr::CtCast {
+            expr,
+            from,
+            to,
+            is_secret: true,
+        },
+        // Whichever branch is taken, its value is secret
+        ObliExpr::PubIf {
+            cond,
+            then_branch,
+            else_branch,
+        } => ObliExpr::PubIf {
+            cond,
+            then_branch: Box::new(stack::guard(|| mark_as_secret(*then_branch))),
+            else_branch: Box::new(stack::guard(|| mark_as_secret(*else_branch))),
+        },
+        ObliExpr::Let {
+            name,
+            value,
+            body,
+            is_secret,
+        } => ObliExpr::Let {
+            name,
+            value,
+            body: Box::new(stack::guard(|| mark_as_secret(*body))),
+            is_secret,
+        },
+        ObliExpr::CtSwap {
+            cond,
+            left,
+            right,
+            names,
+            body,
+            is_secret,
+        } => ObliExpr::CtSwap {
+            cond,
+            left,
+            right,
+            names,
+            body: Box::new(stack::guard(|| mark_as_secret(*body))),
+            is_secret,
+        },
+        ObliExpr::Assert { cond, body } => ObliExpr::Assert {
+            cond,
+            body: Box::new(stack::guard(|| mark_as_secret(*body))),
+        },
+        ObliExpr::Outputs(outputs) => ObliExpr::Outputs(
+            outputs
+                .into_iter()
+                .map(|(name, value)| (name, stack::guard(|| mark_as_secret(value))))
+                .collect(),
+        ),
+        // The loop's variables stay public; its result is bound and read
+        // back as a secret. The transform records spans for the new nodes
+        ObliExpr::While {
+            acc,
+            is_secret: false,
+            ..
+        } => ObliExpr::Let {
+            name: acc,
+            value: Box::new(expr),
+            body: Box::new(ObliExpr::Var {
+                name: acc,
+                is_secret: true,
+            }),
+            is_secret: false,
+        },
+        // Revealed and reclassified at once, so the reveal can go
+        ObliExpr::Declassify(expr) => stack::guard(|| mark_as_secret(*expr)),
+        // Always secret
+        ObliExpr::CtSelect { .. }
+        | ObliExpr::Table { .. }
+        | ObliExpr::While { .. }
+        | ObliExpr::SecretInt(_)
+        | ObliExpr::SecretBool(_) => expr,
     }
 }
 
@@ -181,6 +642,118 @@ fn test_secret_value() {
         assert!(obli.is_secret());
     }
 
+    #[test]
+    fn test_secret_wraps_let_and_pub_if() {
+        assert!(parse_and_transform("secret(let x = 1 x)").is_secret());
+        assert!(parse_and_transform("secret(if true then 1 else 2)").is_secret());
+    }
+
+    #[test]
+    fn test_shadowing_takes_the_new_label() {
+        assert!(!parse_and_transform("let k = secret(1) let k = 2 k").is_secret());
+        assert!(parse_and_transform("let k = 2 let k = secret(k) k").is_secret());
+        assert!(parse_and_transform("let k = secret(1) (let k = 2 k) + k").is_secret());
+        // The read itself is public, not only the program's result
+        let obli = parse_and_transform("let x = secret(1) let x = 2 x");
+        let reads: Vec<_> = obli
+            .post_order()
+            .into_iter()
+            .filter_map(|node| match node {
+                ObliExpr::Var { is_secret, .. } => Some(*is_secret),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(reads, [false]);
+        assert!(crate::verify::verify(&obli).is_empty());
+    }
+
+    #[test]
+    fn test_integer_conversions_become_casts() {
+        use crate::typeck::{Scalar, Width};
+        let obli = parse_and_transform("let x: u16 = secret(1u8) x + 2");
+        let ObliExpr::Let { value, body, .. } = &obli else {
+            panic!("expected a let, got {:?}", obli);
+        };
+        assert!(matches!(
+            **value,
+            ObliExpr::CtCast {
+                from: Scalar::Sized(Width::U8),
+                to: Scalar::Sized(Width::U16),
+                is_secret: true,
+                ..
+            }
+        ));
+        let ObliExpr::BinOp { right, .. } = &**body else {
+            panic!("expected an addition, got {:?}", body);
+        };
+        assert!(matches!(**right, ObliExpr::Sized { value: 2, width: Width::U16, .. }));
+
+        // The cast takes the span of the value it converts
+        let src = "1u8 + 2u16";
+        let (tokens, spans) = Lexer::new(src).tokenize().unwrap();
+        let (ast, ast_spans) = Parser::with_spans(&tokens, &spans)
+            .parse_with_spans()
+            .unwrap();
+        let (_, ir_spans) = to_oblivious_with_spans(&ast, &ast_spans);
+        let texts: Vec<_> = ir_spans.iter().map(|s| &src[s.start..s.end]).collect();
+        assert_eq!(texts, vec!["1u8", "1u8", "2u16", src]);
+    }
+
+    #[test]
+    fn test_annotation_sets_the_label() {
+        assert!(parse_and_transform("let k: secret int = 1 k + 2").is_secret());
+        assert!(!parse_and_transform("let k: pub int = 1 k + 2").is_secret());
+        let obli = parse_and_transform("let k: pub int = secret(1) k");
+        assert!(matches!(obli, ObliExpr::Let { is_secret: false, .. }));
+        let errors = crate::verify::verify(&obli);
+        assert!(matches!(&errors[..], [crate::verify::VerifyError::DeclaredPublic { .. }]));
+
+        let tokens: Vec<_> = Lexer::new("let k: pub int = secret(1) k")
+            .filter_map(Result::ok)
+            .collect();
+        let ast = Parser::new(&tokens).parse().unwrap();
+        let Err(errors) = to_oblivious_checked(&ast, &NodeSpans::new()) else {
+            panic!("expected the binding to be rejected");
+        };
+        assert!(matches!(&errors[..], [SecrecyError::DeclaredPublic { name, .. }] if name == "k"));
+    }
+
+    #[test]
+    fn test_secret_loop_condition_is_rejected() {
+        let parse = |input: &str| {
+            let tokens: Vec<_> = Lexer::new(input).filter_map(Result::ok).collect();
+            Parser::new(&tokens).parse().unwrap()
+        };
+        let ast = parse("while secret(true) with x = 0 do x + 1");
+        let Err(errors) = to_oblivious_checked(&ast, &NodeSpans::new()) else {
+            panic!("expected the loop to be rejected");
+        };
+        assert!(matches!(&errors[..], [SecrecyError::SecretLoopCondition { .. }]));
+        let ast = parse("while x < 5 with x = secret(0) do x + 1");
+        assert!(to_oblivious_checked(&ast, &NodeSpans::new()).is_err());
+        let ast = parse("while n < 3 with n = 0 do n + 1");
+        assert!(to_oblivious_checked(&ast, &NodeSpans::new()).is_ok());
+    }
+
+    #[test]
+    fn test_secrecy_inferred_per_call() {
+        let obli = parse_and_transform(
+            "fn double(x) = x * 2 outputs(hidden = double(secret(3)), shown = double(4))",
+        );
+        let outputs = obli.outputs().unwrap();
+        assert!(outputs[0].1.is_secret());
+        assert!(!outputs[1].1.is_secret());
+    }
+
+    #[test]
+    fn test_secret_captures_make_lambdas_secret() {
+        let source = "let k = secret(2) let f = fn(x) => x * k let g = fn(x) => x + 1 \
+                      outputs(hidden = f(3), shown = g(3))";
+        let outputs = parse_and_transform(source).outputs().unwrap().to_vec();
+        assert!(outputs[0].1.is_secret());
+        assert!(!outputs[1].1.is_secret());
+    }
+
     #[test]
     fn test_secret_if_becomes_ct_select() {
         let obli = parse_and_transform("let x = secret(1) if x > 0 then 1 else 0");
@@ -193,6 +766,50 @@ fn test_secret_if_becomes_ct_select() {
         }
     }
 
+    #[test]
+    fn test_elif_chain_nests_selects() {
+        let src = "let x = 1 if x < 0 then 0 elif secret(x) < 9 then 1 else 2";
+        let obli = parse_and_transform(src);
+        let ObliExpr::Let { body, .. } = obli else {
+            panic!("Expected Let");
+        };
+        let ObliExpr::PubIf { else_branch, .. } = *body else {
+            panic!("Expected PubIf");
+        };
+        assert!(matches!(*else_branch, ObliExpr::CtSelect { .. }));
+    }
+
+    #[test]
+    fn test_ir_spans_follow_ast() {
+        let src = "let x = secret(1) x + 2";
+        let (tokens, spans) = Lexer::new(src).tokenize().unwrap();
+        let (ast, ast_spans) = Parser::with_spans(&tokens, &spans)
+            .parse_with_spans()
+            .unwrap();
+        let (_, ir_spans) = to_oblivious_with_spans(&ast, &ast_spans);
+        let texts: Vec<_> = ir_spans.iter().map(|s| &src[s.start..s.end]).collect();
+        assert_eq!(texts, vec!["secret(1)", "x", "2", "x + 2", src]);
+    }
+
+    #[test]
+    fn test_declassify_is_audited() {
+        let audit = |src: &str| {
+            let (tokens, spans) = Lexer::new(src).tokenize().unwrap();
+            let (ast, ast_spans) = Parser::with_spans(&tokens, &spans)
+                .parse_with_spans()
+                .unwrap();
+            let (ir, _, audit) = to_oblivious_with_audit(&ast, &ast_spans);
+            let texts = audit.iter().map(|d| src[d.span.start..d.span.end].to_string());
+            (ir.is_secret(), texts.collect::<Vec<_>>())
+        };
+        // A public value reveals nothing
+        let (secret, texts) = audit("let k = secret(5) declassify(k > 3) && declassify(true)");
+        assert!(!secret);
+        assert_eq!(texts, vec!["declassify(k > 3)"]);
+        // Nor does one reclassified at once
+        assert_eq!(audit("let k = secret(5) secret(declassify(k))"), (true, vec![]));
+    }
+
     #[test]
     fn test_public_if_stays_pub_if() {
         let obli = parse_and_transform("let x = 1 if x > 0 then 1 else 0");
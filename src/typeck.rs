@@ -0,0 +1,2292 @@
+// SPDX-License-Identifier: MIT OR AGPL-3.0-or-later
+// SPDX-FileCopyrightText: 2024-2025 hyperpolymath
+
+//! Type checker for MiniObli.
+//!
+//! MiniObli has two base types, `int` and `bool`, fixed-size arrays of
+//! either (`[int; 4]`), tuples of them (`(int, bool)`) and options of them
+//! (`option<int>`), a `none` being an option of every type. `int` is `i64`,
+//! besides the sized integers `u8`, `u16`, `u32`, `u64` and `i32`. An
+//! operand, `if` branch, annotated `let` value or assigned value of another
+//! integer type converts only where no value is lost: an unsuffixed literal
+//! to a type it fits, and otherwise a type to one holding all of its
+//! values, so `1u8 + 2` is a `u8` and `1u8 + 2u16` a `u16`. Anything else
+//! narrows and takes a cast, `e as u32`, from any integer type to any
+//! other. Secrecy is
+//! orthogonal and tracked by the transform; this pass only rejects
+//! ill-typed programs (mismatched operands, non-boolean conditions,
+//! mismatched branches, unbound variables, arrays or tuples used as
+//! scalars, tuples of the wrong length and constant out-of-bounds
+//! indices) before they reach
+//! the emitter. A `lookup` needs keys of the key's type and values of one
+//! scalar type, and so does a `match` with its patterns and arms. The
+//! variants of an `enum` are `int` constants; a `match` whose patterns are
+//! variants names those of one enum, and without a `_` arm all of them. An
+//! annotated `let` needs a value of the declared type; its declared
+//! secrecy is checked by the transform. A `let mut` binds a scalar, and an
+//! assignment needs one of its type, in the block that declares it.
+
+use crate::codes;
+use crate::ast::{BinOp, Expr, UnaryOp};
+use crate::desugar::{any_node, truncated_calls};
+use crate::span::{NodeSpans, Span, SpanCursor};
+use crate::stack;
+use crate::symbol::{Scope, Symbol};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::rc::Rc;
+use thiserror::Error;
+
+/// Types of expressions.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Type {
+    Int,
+    Bool,
+    /// An integer of a width other than `int`'s.
+    Sized(Width),
+    /// A fixed-size array; arrays do not nest.
+    Array(Scalar, usize),
+    /// A tuple of two or more scalars.
+    Tuple(Vec<Scalar>),
+    /// An option of a scalar, or of any scalar (`None`) where only `none`
+    /// builds it.
+    Option(Option<Scalar>),
+    /// Named results of a program; see [`Expr::Outputs`].
+    Outputs,
+}
+
+/// Element types of arrays.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub enum Scalar {
+    Int,
+    Bool,
+    Sized(Width),
+}
+
+/// Integer types other than `int` (`i64`), each emitted as the Rust type
+/// of its name. Values are held in an `i64`: a `u64` as its bit pattern.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub enum Width {
+    U8,
+    U16,
+    U32,
+    U64,
+    I32,
+}
+
+impl Width {
+    pub const ALL: [Width; 5] = [Width::U8, Width::U16, Width::U32, Width::U64, Width::I32];
+
+    /// The width named by a literal suffix or annotation, such as `u8`.
+    pub fn from_name(name: &str) -> Option<Width> {
+        Width::ALL.into_iter().find(|width| width.name() == name)
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Width::U8 => "u8",
+            Width::U16 => "u16",
+            Width::U32 => "u32",
+            Width::U64 => "u64",
+            Width::I32 => "i32",
+        }
+    }
+
+    pub fn bits(self) -> u32 {
+        match self {
+            Width::U8 => 8,
+            Width::U16 => 16,
+            Width::U32 | Width::I32 => 32,
+            Width::U64 => 64,
+        }
+    }
+
+    pub fn is_signed(self) -> bool {
+        self == Width::I32
+    }
+
+    /// The smallest and largest values of the type.
+    pub fn range(self) -> (i128, i128) {
+        let bits = self.bits();
+        if self.is_signed() {
+            (-(1 << (bits - 1)), (1 << (bits - 1)) - 1)
+        } else {
+            (0, (1 << bits) - 1)
+        }
+    }
+
+    /// The value a held `i64` stands for.
+    pub fn value(self, held: i64) -> i128 {
+        match self {
+            Width::U64 => i128::from(held as u64),
+            _ => i128::from(held),
+        }
+    }
+
+    /// Reduce `n` modulo 2^bits into the type's range, as its wrapping
+    /// arithmetic does, and hold it.
+    pub fn wrap(self, n: i128) -> i64 {
+        let bits = self.bits();
+        let low = n & ((1 << bits) - 1);
+        let n = if self.is_signed() && low >> (bits - 1) == 1 { low - (1 << bits) } else { low };
+        n as i64
+    }
+
+    /// Clamp `n` into the type's range, as its saturating arithmetic
+    /// does, and hold it.
+    pub fn saturate(self, n: i128) -> i64 {
+        let (min, max) = self.range();
+        self.wrap(n.clamp(min, max))
+    }
+}
+
+impl fmt::Display for Width {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl Type {
+    /// The scalar this type is, if it is not an array or tuple.
+    pub fn scalar(&self) -> Option<Scalar> {
+        match self {
+            Type::Int => Some(Scalar::Int),
+            Type::Bool => Some(Scalar::Bool),
+            Type::Sized(width) => Some(Scalar::Sized(*width)),
+            Type::Array(..) | Type::Tuple(_) | Type::Option(_) | Type::Outputs => None,
+        }
+    }
+
+    /// The type a value of both `self` and `other` has, if any: an option
+    /// of any scalar is an option of `other`'s.
+    pub fn unify(&self, other: &Type) -> Option<Type> {
+        match (self, other) {
+            (Type::Option(None), Type::Option(_)) => Some(other.clone()),
+            (Type::Option(_), Type::Option(None)) => Some(self.clone()),
+            _ => (self == other).then(|| self.clone()),
+        }
+    }
+}
+
+impl Scalar {
+    /// Whether every value of the integer type `self` is also a value of
+    /// `to`, so converting it loses nothing.
+    pub fn widens_to(self, to: Scalar) -> bool {
+        let range = |ty| match ty {
+            Scalar::Sized(width) => width.range(),
+            _ => (i64::MIN.into(), i64::MAX.into()),
+        };
+        let ((from_min, from_max), (to_min, to_max)) = (range(self), range(to));
+        to_min <= from_min && from_max <= to_max
+    }
+}
+
+impl From<Scalar> for Type {
+    fn from(scalar: Scalar) -> Self {
+        match scalar {
+            Scalar::Int => Type::Int,
+            Scalar::Bool => Type::Bool,
+            Scalar::Sized(width) => Type::Sized(width),
+        }
+    }
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Type::Int => write!(f, "int"),
+            Type::Bool => write!(f, "bool"),
+            Type::Sized(width) => write!(f, "{}", width),
+            Type::Array(elem, len) => write!(f, "[{}; {}]", Type::from(*elem), len),
+            Type::Tuple(components) => {
+                let components: Vec<_> =
+                    components.iter().map(|c| Type::from(*c).to_string()).collect();
+                write!(f, "({})", components.join(", "))
+            }
+            Type::Option(Some(value)) => write!(f, "option<{}>", Type::from(*value)),
+            Type::Option(None) => write!(f, "option"),
+            Type::Outputs => write!(f, "outputs"),
+        }
+    }
+}
+
+#[derive(Error, Debug, Clone, PartialEq)]
+pub enum TypeError {
+    #[error("mismatched types: expected {expected}, found {found}")]
+    Mismatch {
+        expected: Type,
+        found: Type,
+        span: Span,
+    },
+    #[error("if branches have different types: {then_ty} and {else_ty}")]
+    BranchMismatch {
+        then_ty: Type,
+        else_ty: Type,
+        span: Span,
+    },
+    #[error("unbound variable `{name}`")]
+    Unbound { name: String, span: Span },
+    #[error("expected a scalar, found {found}")]
+    NotScalar { found: Type, span: Span },
+    #[error("index {index} is out of bounds for an array of length {len}")]
+    IndexOutOfBounds { index: i64, len: usize, span: Span },
+    #[error("cannot index into a value of type {found}")]
+    NotAnArray { found: Type, span: Span },
+    #[error("expected a tuple of {len} components, found {found}")]
+    NotATuple { found: Type, len: usize, span: Span },
+    #[error("named outputs are only allowed as a program's result")]
+    MisplacedOutputs { span: Span },
+    #[error("output `{name}` is named twice")]
+    DuplicateOutput { name: String, span: Span },
+    #[error("undefined function `{name}`")]
+    UndefinedFunction { name: String, span: Span },
+    #[error("function `{name}` takes {expected} arguments, found {found}")]
+    ArityMismatch {
+        name: String,
+        expected: usize,
+        found: usize,
+        span: Span,
+    },
+    #[error("loop bounds must be constant")]
+    NonConstantBound { span: Span },
+    #[error("body would be unrolled {iterations} times, more than {MAX_ITERATIONS}")]
+    TooManyIterations { iterations: u64, span: Span },
+    #[error("cannot assign to `{name}`, which is not a `let mut` variable here")]
+    NotMutable { name: String, span: Span },
+    #[error("a function can only be called, passed to a call or bound by `let`")]
+    FunctionValue { span: Span },
+    #[error("the value of a `const` must be a constant integer")]
+    NonConstantValue { span: Span },
+    #[error("the value of a `const` cannot be secret")]
+    SecretConstant { span: Span },
+    #[error("constant `{name}` cannot be bound again in its scope")]
+    ConstantRebound { name: String, span: Span },
+    #[error("every call of `{name}` calls it again")]
+    NoBaseCase { name: String, span: Span },
+    #[error("inputs can only be declared by the program's statements")]
+    MisplacedInput { span: Span },
+    #[error("input `{name}` is declared twice")]
+    DuplicateInput { name: String, span: Span },
+    #[error("`{name}` is not a variant of {expected}")]
+    NotAVariant {
+        name: String,
+        expected: String,
+        span: Span,
+    },
+    #[error("match has no arm for `{missing}` and no `_` arm")]
+    NonExhaustiveMatch { missing: String, span: Span },
+    #[error("the exponent of `pow` must be a non-negative constant")]
+    NonConstantExponent { span: Span },
+    #[error("`{builtin}` takes a function of {arity} arguments")]
+    NotAFunction {
+        builtin: String,
+        arity: usize,
+        span: Span,
+    },
+    #[error("`{from}` does not fit in `{to}`; convert it with `as {to}`")]
+    Narrowing { from: Type, to: Type, span: Span },
+    #[error("this call of `{name}` is nested deeper than its `#[max_depth({max_depth})]`")]
+    TruncatedCall {
+        name: String,
+        max_depth: u32,
+        span: Span,
+    },
+}
+
+impl TypeError {
+    /// Catalog code of this error.
+    pub fn code(&self) -> &'static str {
+        match self {
+            TypeError::Mismatch { .. } => codes::TYPE_MISMATCH,
+            TypeError::BranchMismatch { .. } => codes::BRANCH_MISMATCH,
+            TypeError::Unbound { .. } => codes::UNBOUND_VARIABLE,
+            TypeError::NotScalar { .. } => codes::NOT_SCALAR,
+            TypeError::IndexOutOfBounds { .. } => codes::INDEX_OUT_OF_BOUNDS,
+            TypeError::NotAnArray { .. } => codes::NOT_AN_ARRAY,
+            TypeError::NotATuple { .. } => codes::NOT_A_TUPLE,
+            TypeError::MisplacedOutputs { .. } => codes::MISPLACED_OUTPUTS,
+            TypeError::DuplicateOutput { .. } => codes::DUPLICATE_OUTPUT,
+            TypeError::UndefinedFunction { .. } => codes::UNDEFINED_FUNCTION,
+            TypeError::ArityMismatch { .. } => codes::ARITY_MISMATCH,
+            TypeError::NonConstantBound { .. } => codes::NON_CONSTANT_BOUND,
+            TypeError::TooManyIterations { .. } => codes::TOO_MANY_ITERATIONS,
+            TypeError::NotMutable { .. } => codes::NOT_MUTABLE,
+            TypeError::FunctionValue { .. } => codes::FUNCTION_VALUE,
+            TypeError::NonConstantValue { .. } => codes::NON_CONSTANT_VALUE,
+            TypeError::SecretConstant { .. } => codes::SECRET_CONSTANT,
+            TypeError::ConstantRebound { .. } => codes::CONSTANT_REBOUND,
+            TypeError::NoBaseCase { .. } => codes::NO_BASE_CASE,
+            TypeError::MisplacedInput { .. } => codes::MISPLACED_INPUT,
+            TypeError::DuplicateInput { .. } => codes::DUPLICATE_INPUT,
+            TypeError::NotAVariant { .. } => codes::NOT_A_VARIANT,
+            TypeError::NonExhaustiveMatch { .. } => codes::NON_EXHAUSTIVE_MATCH,
+            TypeError::NonConstantExponent { .. } => codes::NON_CONSTANT_EXPONENT,
+            TypeError::NotAFunction { .. } => codes::NOT_A_FUNCTION,
+            TypeError::Narrowing { .. } => codes::NARROWING,
+            TypeError::TruncatedCall { .. } => codes::TRUNCATED_CALL,
+        }
+    }
+
+    /// Source span of the offending expression (empty if spans were not supplied).
+    pub fn span(&self) -> Span {
+        match self {
+            TypeError::Mismatch { span, .. }
+            | TypeError::BranchMismatch { span, .. }
+            | TypeError::Unbound { span, .. }
+            | TypeError::NotScalar { span, .. }
+            | TypeError::IndexOutOfBounds { span, .. }
+            | TypeError::NotAnArray { span, .. }
+            | TypeError::NotATuple { span, .. }
+            | TypeError::MisplacedOutputs { span }
+            | TypeError::DuplicateOutput { span, .. }
+            | TypeError::UndefinedFunction { span, .. }
+            | TypeError::ArityMismatch { span, .. }
+            | TypeError::NonConstantBound { span }
+            | TypeError::TooManyIterations { span, .. }
+            | TypeError::NotMutable { span, .. }
+            | TypeError::FunctionValue { span }
+            | TypeError::NonConstantValue { span }
+            | TypeError::SecretConstant { span }
+            | TypeError::ConstantRebound { span, .. }
+            | TypeError::NoBaseCase { span, .. }
+            | TypeError::MisplacedInput { span }
+            | TypeError::DuplicateInput { span, .. }
+            | TypeError::NotAVariant { span, .. }
+            | TypeError::NonExhaustiveMatch { span, .. }
+            | TypeError::NonConstantExponent { span }
+            | TypeError::NotAFunction { span, .. }
+            | TypeError::Narrowing { span, .. }
+            | TypeError::TruncatedCall { span, .. } => *span,
+        }
+    }
+}
+
+/// Most copies of a loop body unrolling may make, counting the loops
+/// around it, and of a recursive function's body.
+pub const MAX_ITERATIONS: u64 = 65536;
+
+/// Type-check an expression, returning its type or every error found.
+pub fn typecheck(expr: &Expr) -> Result<Type, Vec<TypeError>> {
+    let ty = Checker::new(SpanCursor::none()).run(expr)?;
+    reject_truncated(expr, None)?;
+    Ok(ty)
+}
+
+/// Type of every node of `expr`, in post-order (`None` where ill-typed).
+pub fn node_types(expr: &Expr) -> Vec<Option<Type>> {
+    let mut checker = Checker::new(SpanCursor::none());
+    checker.infer(expr);
+    checker.types
+}
+
+/// Result type of every recursive function of `expr` that is called, by
+/// the address of its body.
+pub(crate) fn recursive_results(expr: &Expr) -> HashMap<*const Expr, Type> {
+    let mut checker = Checker::new(SpanCursor::none());
+    checker.infer(expr);
+    checker.results
+}
+
+/// Operand type of every cast in `expr`, by the address of the cast;
+/// `int` where the operand is ill-typed.
+pub(crate) fn cast_sources(expr: &Expr) -> HashMap<*const Expr, Scalar> {
+    let mut checker = Checker::new(SpanCursor::none());
+    checker.infer(expr);
+    checker.casts
+}
+
+/// Type every integer of `expr` converted implicitly is converted to, by
+/// the address of the converted node.
+pub(crate) fn promotions(expr: &Expr) -> HashMap<*const Expr, Scalar> {
+    let mut checker = Checker::new(SpanCursor::none());
+    checker.infer(expr);
+    checker.promotions
+}
+
+/// Length of the array every `map` and `fold` of `expr` runs over, by
+/// the address of the node; missing where the array is ill-typed.
+pub(crate) fn mapped_lengths(expr: &Expr) -> HashMap<*const Expr, usize> {
+    let mut checker = Checker::new(SpanCursor::none());
+    checker.infer(expr);
+    let lengths = checker.mapped.into_iter().filter_map(|(node, ty)| match ty {
+        Type::Array(_, len) => Some((node, len)),
+        _ => None,
+    });
+    lengths.collect()
+}
+
+/// An AST with the type of every node, as computed by [`node_types`].
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TypedAst {
+    pub ast: Expr,
+    /// Type of every node, in post-order (`None` where ill-typed).
+    pub types: Vec<Option<Type>>,
+}
+
+impl TypedAst {
+    pub fn new(ast: Expr) -> Self {
+        let types = node_types(&ast);
+        Self { ast, types }
+    }
+}
+
+/// Type-check an expression, reporting errors at the spans recorded by
+/// [`Parser::parse_with_spans`](crate::parser::Parser::parse_with_spans).
+pub fn typecheck_with_spans(expr: &Expr, spans: &NodeSpans) -> Result<Type, Vec<TypeError>> {
+    let ty = Checker::new(SpanCursor::new(Some(spans))).run(expr)?;
+    reject_truncated(expr, Some(spans))?;
+    Ok(ty)
+}
+
+/// Reject a well-typed program with a call of a `#[max_depth]` function
+/// nested deeper than its bound that runs whatever the inputs, which
+/// would silently be the zero of the result type.
+fn reject_truncated(expr: &Expr, spans: Option<&NodeSpans>) -> Result<(), Vec<TypeError>> {
+    if !any_node(expr, |expr| matches!(expr, Expr::Fn { max_depth: Some(_), .. })) {
+        return Ok(());
+    }
+    let truncated = truncated_calls(expr, spans).into_iter();
+    let errors: Vec<_> = truncated
+        .map(|(name, max_depth, span)| TypeError::TruncatedCall {
+            name: name.to_string(),
+            max_depth,
+            span,
+        })
+        .collect();
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+/// The integer type two operands `(expr, type)` convert to: the type of
+/// the other where one is an unsuffixed literal that fits it, and
+/// otherwise the type the other [converts](converts) to. `None` where
+/// either is not an integer or neither converts.
+fn common((left, l): (&Expr, &Type), (right, r): (&Expr, &Type)) -> Option<Type> {
+    if !is_int(l) || !is_int(r) {
+        return None;
+    }
+    if l == r || *r == Type::Int && fits(right, l) {
+        Some(l.clone())
+    } else if *l == Type::Int && fits(left, r) || converts(left, l, r) {
+        Some(r.clone())
+    } else {
+        converts(right, r, l).then(|| l.clone())
+    }
+}
+
+/// Whether `expr`, of type `from`, converts to `to` implicitly: both are
+/// integer types and either every value of `from` is one of `to` or
+/// `expr` is an unsuffixed literal that fits `to`.
+fn converts(expr: &Expr, from: &Type, to: &Type) -> bool {
+    match (from.scalar(), to.scalar()) {
+        (Some(f), Some(t)) if is_int(from) && is_int(to) => {
+            f.widens_to(t) || *from == Type::Int && fits(expr, to)
+        }
+        _ => false,
+    }
+}
+
+fn is_int(ty: &Type) -> bool {
+    matches!(ty, Type::Int | Type::Sized(_))
+}
+
+/// Whether `expr` is an unsuffixed literal, possibly negated or marked
+/// secret, whose value is one of the integer type `to`.
+fn fits(expr: &Expr, to: &Type) -> bool {
+    fn literal(expr: &Expr) -> Option<i128> {
+        match expr {
+            Expr::Int(n) => Some((*n).into()),
+            Expr::Secret(inner) => literal(inner),
+            Expr::UnaryOp {
+                op: UnaryOp::Neg,
+                expr,
+            } => literal(expr).map(|n| -n),
+            _ => None,
+        }
+    }
+    let (min, max) = match to {
+        Type::Sized(width) => width.range(),
+        _ => (i64::MIN.into(), i64::MAX.into()),
+    };
+    literal(expr).is_some_and(|n| min <= n && n <= max)
+}
+
+struct Checker<'a> {
+    scope: Scope<Option<Type>>,
+    /// Whether each variable of `scope` is assignable here.
+    mutable: Scope<bool>,
+    /// Functions callable here.
+    functions: Scope<Rc<Function<'a>>>,
+    /// Values of the constants in scope, which variables cannot shadow.
+    consts: Scope<i64>,
+    /// The enum declaring each variant in scope.
+    variants: Scope<Enum>,
+    spans: SpanCursor<'a>,
+    errors: Vec<TypeError>,
+    types: Vec<Option<Type>>,
+    /// Whether the node being inferred is the program's result.
+    tail: bool,
+    /// Whether the node being inferred is the value of one of the
+    /// program's statements, where an input can be declared.
+    statement: bool,
+    /// Inputs declared so far.
+    inputs: HashSet<Symbol>,
+    /// Whether the node being inferred may be a function.
+    callable: bool,
+    /// Iterations of the loops around the node being inferred, multiplied.
+    unrolled: u64,
+    /// Recursive functions whose bodies are being checked, with the types
+    /// of their arguments (any if `None`) and the type assumed for the
+    /// calls back into them.
+    recursing: Vec<Recursion<'a>>,
+    /// Result types of the recursive functions called, by body.
+    results: HashMap<*const Expr, Type>,
+    /// Operand type of every cast, by the cast.
+    casts: HashMap<*const Expr, Scalar>,
+    /// Type every integer converted implicitly is converted to, by the
+    /// converted node.
+    promotions: HashMap<*const Expr, Scalar>,
+    /// Type of the array every `map` and `fold` runs over, by the node.
+    mapped: HashMap<*const Expr, Type>,
+}
+
+type Recursion<'a> = (*const Function<'a>, Option<Vec<Option<Type>>>, Option<Type>);
+
+/// An `enum` declaration: its name and variants, in order.
+type Enum = Rc<(Symbol, Vec<Symbol>)>;
+
+/// A function definition, checked again at every call with the types of
+/// its arguments.
+struct Function<'a> {
+    /// For a `#[max_depth]` function, the name its body calls it by and
+    /// the bound.
+    recursion: Option<(Symbol, u32)>,
+    params: Vec<Symbol>,
+    body: &'a Expr,
+    /// Spans of the body's nodes.
+    spans: SpanCursor<'a>,
+    /// Functions the body can call.
+    functions: Scope<Rc<Function<'a>>>,
+    /// Constants the body sees.
+    consts: Scope<i64>,
+    /// Enums whose variants the body sees.
+    variants: Scope<Enum>,
+    /// Variables the body sees besides its parameters: none for a `fn`,
+    /// those around it for a lambda.
+    captures: Scope<Option<Type>>,
+}
+
+/// An argument of a call: a value of its type, or a function.
+#[derive(Clone)]
+enum Arg<'a> {
+    Value(Option<Type>),
+    Function(Rc<Function<'a>>),
+}
+
+impl<'a> Checker<'a> {
+    fn new(spans: SpanCursor<'a>) -> Self {
+        Self {
+            scope: Scope::new(),
+            mutable: Scope::new(),
+            functions: Scope::new(),
+            consts: Scope::new(),
+            variants: Scope::new(),
+            spans,
+            errors: Vec::new(),
+            types: Vec::new(),
+            tail: true,
+            statement: false,
+            inputs: HashSet::new(),
+            callable: false,
+            unrolled: 1,
+            recursing: Vec::new(),
+            results: HashMap::new(),
+            casts: HashMap::new(),
+            promotions: HashMap::new(),
+            mapped: HashMap::new(),
+        }
+    }
+
+    /// Check the body of `function` with `args` bound to its parameters,
+    /// returning its type. Errors already reported are not repeated.
+    ///
+    /// A call back into a recursive function whose body is being checked
+    /// with the same argument types is assumed to have no type, then the
+    /// type the body had: that of its base cases.
+    fn check_call(
+        &mut self,
+        function: &Rc<Function<'a>>,
+        args: Vec<Arg<'a>>,
+        span: Span,
+    ) -> Option<Type> {
+        let Some((name, max_depth)) = function.recursion else {
+            let (ty, checker) = self.check_body(function, args);
+            self.merge(checker, span);
+            return ty;
+        };
+        let id = Rc::as_ptr(function);
+        let types: Vec<_> = args
+            .iter()
+            .map(|arg| match arg {
+                Arg::Value(ty) => ty.clone(),
+                Arg::Function(_) => None,
+            })
+            .collect();
+        let recursing = self.recursing.iter().rev().find(|(f, expected, _)| {
+            *f == id && expected.as_ref().is_none_or(|expected| *expected == types)
+        });
+        if let Some((.., assumed)) = recursing {
+            return assumed.clone();
+        }
+
+        let outer = self.unrolled;
+        if !self.recursing.iter().any(|(f, ..)| *f == id) {
+            // Every call unrolls its body up to `max_depth` calls deep
+            let calls = calls(function.body, name);
+            let mut copies: u64 = 0;
+            let mut level: u64 = 1;
+            for _ in 0..max_depth {
+                copies = copies.saturating_add(level);
+                level = level.saturating_mul(calls);
+            }
+            self.unrolled = outer.saturating_mul(copies);
+            if self.unrolled > MAX_ITERATIONS {
+                self.errors.push(TypeError::TooManyIterations {
+                    iterations: self.unrolled,
+                    span,
+                });
+                self.unrolled = outer;
+                return None;
+            }
+        }
+        self.recursing.push((id, Some(types), None));
+        let (mut ty, mut checker) = self.check_body(function, args.clone());
+        if ty.is_some() {
+            self.recursing.last_mut().unwrap().2 = ty;
+            (ty, checker) = self.check_body(function, args.clone());
+        }
+        self.recursing.pop();
+        self.unrolled = outer;
+        let typed = args.iter().all(|arg| !matches!(arg, Arg::Value(None)));
+        if ty.is_none() && checker.errors.is_empty() && typed {
+            checker.errors.push(TypeError::NoBaseCase {
+                name: name.to_string(),
+                span,
+            });
+        }
+        if let Some(ty) = &ty {
+            checker.results.insert(function.body, ty.clone());
+        }
+        self.merge(checker, span);
+        ty
+    }
+
+    /// The type of the body of `function` with `args` bound to its
+    /// parameters, and the checker that inferred it.
+    fn check_body(
+        &self,
+        function: &Rc<Function<'a>>,
+        args: Vec<Arg<'a>>,
+    ) -> (Option<Type>, Checker<'a>) {
+        let mut checker = Checker {
+            scope: function.captures.clone(),
+            mutable: Scope::new(),
+            functions: function.functions.clone(),
+            consts: function.consts.clone(),
+            variants: function.variants.clone(),
+            spans: function.spans.clone(),
+            errors: Vec::new(),
+            types: Vec::new(),
+            tail: false,
+            statement: false,
+            inputs: HashSet::new(),
+            callable: false,
+            unrolled: self.unrolled,
+            recursing: self.recursing.clone(),
+            results: HashMap::new(),
+            casts: HashMap::new(),
+            promotions: HashMap::new(),
+            mapped: HashMap::new(),
+        };
+        if let Some((name, _)) = function.recursion {
+            checker.functions.push(name, function.clone());
+        }
+        for (param, arg) in function.params.iter().zip(args) {
+            match arg {
+                Arg::Value(ty) => checker.scope.push(*param, ty),
+                Arg::Function(function) => checker.functions.push(*param, function),
+            }
+        }
+        let (ty, _) = checker.infer(function.body);
+        (ty, checker)
+    }
+
+    /// Take over the errors, results, casts, conversions and mapped arrays
+    /// found by `checker` for a call at `span`. Errors already reported are
+    /// not repeated, a recursive function must have one result type, and a
+    /// `map` or `fold` in a function body one length of array.
+    fn merge(&mut self, checker: Checker<'a>, span: Span) {
+        self.casts.extend(checker.casts);
+        self.promotions.extend(checker.promotions);
+        for error in checker.errors {
+            if !self.errors.contains(&error) {
+                self.errors.push(error);
+            }
+        }
+        for (node, ty) in checker.mapped {
+            match self.mapped.get(&node) {
+                Some(expected) if *expected != ty => {
+                    let expected = expected.clone();
+                    self.errors.push(TypeError::Mismatch {
+                        expected,
+                        found: ty,
+                        span,
+                    });
+                }
+                _ => {
+                    self.mapped.insert(node, ty);
+                }
+            }
+        }
+        for (body, ty) in checker.results {
+            match self.results.get(&body) {
+                Some(expected) if *expected != ty => {
+                    let expected = expected.clone();
+                    self.errors.push(TypeError::Mismatch {
+                        expected,
+                        found: ty,
+                        span,
+                    });
+                }
+                _ => {
+                    self.results.insert(body, ty);
+                }
+            }
+        }
+    }
+
+    fn run(mut self, expr: &'a Expr) -> Result<Type, Vec<TypeError>> {
+        let (ty, span) = self.infer(expr);
+        // A program's result is printed or returned, which arrays and
+        // tuples cannot be
+        self.expect_scalar(&(ty.clone(), span));
+        match ty {
+            Some(ty) if self.errors.is_empty() => Ok(ty),
+            _ => Err(self.errors),
+        }
+    }
+
+    /// Infer a call argument or `let` value, which may also be a lambda or
+    /// the name of a function, and return the function it is.
+    fn infer_callable(&mut self, expr: &'a Expr) -> (Option<Type>, Option<Rc<Function<'a>>>) {
+        let spans = self.spans.clone();
+        self.callable = true;
+        let (ty, _) = self.infer(expr);
+        let function = match expr {
+            Expr::Lambda { params, body } => Some(Rc::new(Function {
+                recursion: None,
+                params: params.clone(),
+                body,
+                spans,
+                functions: self.functions.clone(),
+                consts: self.consts.clone(),
+                variants: self.variants.clone(),
+                captures: self.scope.clone(),
+            })),
+            Expr::Var(name) if self.scope.get(*name).is_none() => {
+                self.functions.get(*name).cloned()
+            }
+            _ => None,
+        };
+        (ty, function)
+    }
+
+    /// Require `found` to be `expected`; unknown types (from earlier errors)
+    /// are accepted silently to avoid cascading reports.
+    fn expect(&mut self, expected: Type, found: &(Option<Type>, Span)) {
+        if let (Some(found), span) = found {
+            if found.unify(&expected).is_none() {
+                self.errors.push(TypeError::Mismatch {
+                    expected,
+                    found: found.clone(),
+                    span: *span,
+                });
+            }
+        }
+    }
+
+    /// Require two integer operands of one type, after converting one to
+    /// the other's as [`common`] does, `int` unless either is sized, and
+    /// return it.
+    fn expect_ints(
+        &mut self,
+        (left, left_ty): (&Expr, &(Option<Type>, Span)),
+        (right, right_ty): (&Expr, &(Option<Type>, Span)),
+    ) -> Type {
+        if let (Some(l), Some(r)) = (&left_ty.0, &right_ty.0) {
+            if let Some(ty) = common((left, l), (right, r)) {
+                self.promote(left, l, &ty);
+                self.promote(right, r, &ty);
+                return ty;
+            }
+        }
+        let ty = match (&left_ty.0, &right_ty.0) {
+            (Some(ty @ Type::Sized(_)), _) | (_, Some(ty @ Type::Sized(_))) => ty.clone(),
+            _ => Type::Int,
+        };
+        self.expect(ty.clone(), left_ty);
+        self.expect(ty.clone(), right_ty);
+        ty
+    }
+
+    /// Require `found`, the type of `expr`, to be `expected` or an integer
+    /// type that [converts](converts) to it; any other integer type is
+    /// only narrowed by a cast.
+    fn expect_converted(&mut self, expected: Type, expr: &Expr, found: &(Option<Type>, Span)) {
+        match found {
+            (Some(from), _) if converts(expr, from, &expected) => {
+                self.promote(expr, from, &expected);
+            }
+            (Some(from), span) if is_int(from) && is_int(&expected) => {
+                self.errors.push(TypeError::Narrowing {
+                    from: from.clone(),
+                    to: expected,
+                    span: *span,
+                });
+            }
+            _ => self.expect(expected, found),
+        }
+    }
+
+    /// Record that `expr`, of type `from`, is converted to `to`.
+    fn promote(&mut self, expr: &Expr, from: &Type, to: &Type) {
+        if let (true, Some(to)) = (from != to, to.scalar()) {
+            self.promotions.insert(expr, to);
+        }
+    }
+
+    /// Require `found` not to be an array or tuple.
+    fn expect_scalar(&mut self, found: &(Option<Type>, Span)) {
+        if let (Some(found @ (Type::Array(..) | Type::Tuple(_) | Type::Option(_))), span) = found {
+            self.errors.push(TypeError::NotScalar {
+                found: found.clone(),
+                span: *span,
+            });
+        }
+    }
+
+    /// Require `found` to be a scalar of type `same`, which the first
+    /// well-typed scalar sets.
+    fn expect_same(&mut self, same: &mut Option<Type>, found: &(Option<Type>, Span)) {
+        self.expect_scalar(found);
+        match same {
+            Some(expected) => self.expect(expected.clone(), found),
+            None => *same = found.0.clone().filter(|ty| ty.scalar().is_some()),
+        }
+    }
+
+    /// Require the pattern `name` to be a variant of the enum `declared`,
+    /// which the first variant sets.
+    fn expect_variant(&mut self, name: Symbol, declared: &mut Option<Enum>, span: Span) {
+        let variant = self.variants.get(name).cloned();
+        match (variant, declared.as_ref()) {
+            (Some(variant), Some(expected)) if Rc::ptr_eq(&variant, expected) => {}
+            (Some(variant), None) => *declared = Some(variant),
+            // An unbound name is reported already
+            (None, _) if self.scope.get(name).is_none() && self.consts.get(name).is_none() => {}
+            (_, expected) => self.errors.push(TypeError::NotAVariant {
+                name: name.to_string(),
+                expected: expected.map_or("an enum".to_string(), |e| format!("`{}`", e.0)),
+                span,
+            }),
+        }
+    }
+
+    /// Infer the type of `expr` (`None` if it is ill-typed) and its span.
+    fn infer(&mut self, expr: &'a Expr) -> (Option<Type>, Span) {
+        let inferred = stack::guard(|| self.infer_node(expr));
+        self.types.push(inferred.0.clone());
+        inferred
+    }
+
+    fn infer_node(&mut self, expr: &'a Expr) -> (Option<Type>, Span) {
+        // Only a `let` body is in the position of the whole expression
+        let tail = std::mem::replace(&mut self.tail, false);
+        let statement = std::mem::replace(&mut self.statement, false);
+        let callable = std::mem::replace(&mut self.callable, false);
+        let ty = match expr {
+            Expr::Int(_) => Some(Type::Int),
+            Expr::Sized(_, width) => Some(Type::Sized(*width)),
+            Expr::Bool(_) => Some(Type::Bool),
+            Expr::Bytes(bytes) => Some(Type::Array(Scalar::Int, bytes.len())),
+            Expr::Var(name) if self.consts.get(*name).is_some() => Some(Type::Int),
+            Expr::Var(name) => {
+                match self.scope.get(*name).cloned() {
+                    Some(ty) => ty,
+                    None if callable && self.functions.get(*name).is_some() => None,
+                    None => {
+                        let span = self.spans.next_span().unwrap_or_default();
+                        let error = match self.functions.get(*name) {
+                            Some(_) => TypeError::FunctionValue { span },
+                            None => TypeError::Unbound {
+                                name: name.to_string(),
+                                span,
+                            },
+                        };
+                        self.errors.push(error);
+                        return (None, span);
+                    }
+                }
+            }
+            Expr::Input { name, ty, .. } => {
+                let span = self.spans.next_span().unwrap_or_default();
+                if !statement {
+                    self.errors.push(TypeError::MisplacedInput { span });
+                } else if !self.inputs.insert(*name) {
+                    self.errors.push(TypeError::DuplicateInput {
+                        name: name.to_string(),
+                        span,
+                    });
+                }
+                return (Some((*ty).into()), span);
+            }
+            Expr::Secret(inner) => self.infer(inner).0,
+            Expr::Declassify(inner) => {
+                let found = self.infer(inner);
+                self.expect_scalar(&found);
+                found.0.filter(|ty| ty.scalar().is_some() || *ty == Type::Outputs)
+            }
+            Expr::Sort(inner) => {
+                let found = self.infer(inner);
+                match found {
+                    (Some(Type::Array(elem, len)), _) => {
+                        // Only integers are ordered
+                        let elem = match elem {
+                            Scalar::Sized(width) => Scalar::Sized(width),
+                            Scalar::Int | Scalar::Bool => Scalar::Int,
+                        };
+                        let sorted = Type::Array(elem, len);
+                        self.expect(sorted.clone(), &found);
+                        Some(sorted)
+                    }
+                    (Some(found), span) => {
+                        self.errors.push(TypeError::NotAnArray { found, span });
+                        None
+                    }
+                    (None, _) => None,
+                }
+            }
+            Expr::BinOp { op, left, right } => {
+                let (left, right) = ((&**left, self.infer(left)), (&**right, self.infer(right)));
+                let (left, right) = ((left.0, &left.1), (right.0, &right.1));
+                match op {
+                    BinOp::Add
+                    | BinOp::Sub
+                    | BinOp::Mul
+                    | BinOp::SatAdd
+                    | BinOp::SatSub
+                    | BinOp::SatMul
+                    | BinOp::Div
+                    | BinOp::Mod
+                    | BinOp::BitAnd
+                    | BinOp::BitOr
+                    | BinOp::BitXor
+                    | BinOp::Shl
+                    | BinOp::Shr => Some(self.expect_ints(left, right)),
+                    BinOp::Lt | BinOp::Le | BinOp::Gt | BinOp::Ge => {
+                        self.expect_ints(left, right);
+                        Some(Type::Bool)
+                    }
+                    BinOp::Eq | BinOp::Ne => {
+                        // Arrays compare whole, so both need the same length;
+                        // options do not, as `none` holds an arbitrary value
+                        match &left.1 .0 {
+                            Some(Type::Option(_)) => self.expect_scalar(left.1),
+                            Some(ty) if is_int(ty) && right.1 .0.as_ref().is_some_and(is_int) => {
+                                self.expect_ints(left, right);
+                            }
+                            Some(expected) => self.expect(expected.clone(), right.1),
+                            None => {}
+                        }
+                        Some(Type::Bool)
+                    }
+                    BinOp::And | BinOp::Or => {
+                        self.expect(Type::Bool, left.1);
+                        self.expect(Type::Bool, right.1);
+                        Some(Type::Bool)
+                    }
+                }
+            }
+            Expr::UnaryOp { op, expr: inner } => {
+                let inner = self.infer(inner);
+                let (operand, ty) = match (op, &inner.0) {
+                    (UnaryOp::Neg, Some(ty @ Type::Sized(_))) => (ty.clone(), ty.clone()),
+                    (UnaryOp::Neg, _) => (Type::Int, Type::Int),
+                    (UnaryOp::Not, _) => (Type::Bool, Type::Bool),
+                    (UnaryOp::ToInt, _) => (Type::Bool, Type::Int),
+                    (UnaryOp::ToBool, Some(ty @ Type::Sized(_))) => (ty.clone(), Type::Bool),
+                    (UnaryOp::ToBool, _) => (Type::Int, Type::Bool),
+                };
+                self.expect(operand, &inner);
+                Some(ty)
+            }
+            Expr::Cast { expr: inner, ty } => {
+                let inner = self.infer(inner);
+                let from = match inner.0 {
+                    Some(Type::Sized(width)) => Scalar::Sized(width),
+                    _ => Scalar::Int,
+                };
+                self.expect(from.into(), &inner);
+                self.casts.insert(expr, from);
+                if *ty == Scalar::Bool {
+                    let span = self.spans.next_span().unwrap_or_default();
+                    self.errors.push(TypeError::Mismatch {
+                        expected: Type::Int,
+                        found: Type::Bool,
+                        span,
+                    });
+                    return (None, span);
+                }
+                Some((*ty).into())
+            }
+            Expr::If {
+                cond,
+                then_branch,
+                else_branch,
+            } => {
+                let cond = self.infer(cond);
+                self.expect(Type::Bool, &cond);
+                let (then_ty, _) = self.infer(then_branch);
+                let (else_ty, _) = self.infer(else_branch);
+                match (then_ty, else_ty) {
+                    (Some(then_ty), Some(else_ty)) => match then_ty
+                        .unify(&else_ty)
+                        .or_else(|| common((then_branch, &then_ty), (else_branch, &else_ty)))
+                    {
+                        Some(ty) => {
+                            self.promote(then_branch, &then_ty, &ty);
+                            self.promote(else_branch, &else_ty, &ty);
+                            Some(ty)
+                        }
+                        None => {
+                            let span = self.spans.next_span().unwrap_or_default();
+                            self.errors.push(TypeError::BranchMismatch {
+                                then_ty,
+                                else_ty,
+                                span,
+                            });
+                            return (None, span);
+                        }
+                    },
+                    (Some(ty), _) | (_, Some(ty)) => Some(ty),
+                    (None, None) => None,
+                }
+            }
+            Expr::Some(inner) => {
+                let found = self.infer(inner);
+                self.expect_scalar(&found);
+                found.0.as_ref().and_then(Type::scalar).map(|value| Type::Option(Some(value)))
+            }
+            Expr::None => Some(Type::Option(None)),
+            Expr::UnwrapOr { option, default } => {
+                let option = self.infer(option);
+                let default = self.infer(default);
+                match option.0 {
+                    Some(Type::Option(Some(value))) => {
+                        self.expect(value.into(), &default);
+                        Some(value.into())
+                    }
+                    Some(Type::Option(None)) | None => {
+                        self.expect_scalar(&default);
+                        default.0.filter(|ty| ty.scalar().is_some())
+                    }
+                    Some(found) => {
+                        self.errors.push(TypeError::Mismatch {
+                            expected: Type::Option(None),
+                            found,
+                            span: option.1,
+                        });
+                        default.0.filter(|ty| ty.scalar().is_some())
+                    }
+                }
+            }
+            Expr::Let {
+                name,
+                ty: None,
+                mutable: false,
+                value,
+                body,
+            } if matches!(**value, Expr::Lambda { .. }) => {
+                let (_, function) = self.infer_callable(value);
+                self.functions.push(*name, function.expect("a lambda is a function"));
+                self.tail = tail;
+                let (body_ty, _) = self.infer(body);
+                self.functions.pop();
+                body_ty
+            }
+            Expr::Let {
+                name,
+                ty,
+                mutable,
+                value,
+                body,
+            } => {
+                self.statement = tail;
+                let found = self.infer(value);
+                if *mutable {
+                    self.expect_scalar(&found);
+                }
+                // The body sees the declared type, even if the value has another
+                let value_ty = match ty {
+                    Some(ty) => {
+                        self.expect_converted(ty.ty.into(), value, &found);
+                        Some(ty.ty.into())
+                    }
+                    None => found.0,
+                };
+                self.scope.push(*name, value_ty);
+                self.mutable.push(*name, *mutable);
+                self.tail = tail;
+                let (body_ty, _) = self.infer(body);
+                self.mutable.pop();
+                self.scope.pop();
+                body_ty
+            }
+            Expr::Const { name, value, body } => {
+                let found = self.infer(value);
+                self.expect(Type::Int, &found);
+                let constant = value.const_int_with(&|name| self.consts.get(name).copied());
+                if value.contains_secret() {
+                    self.errors.push(TypeError::SecretConstant { span: found.1 });
+                } else if found.0 == Some(Type::Int) && constant.is_none() {
+                    self.errors.push(TypeError::NonConstantValue { span: found.1 });
+                }
+                self.consts.push(*name, constant.unwrap_or(0));
+                self.tail = tail;
+                let (body_ty, _) = self.infer(body);
+                self.consts.pop();
+                let span = self.spans.next_span().unwrap_or_default();
+                if binds(body, *name) {
+                    self.errors.push(TypeError::ConstantRebound {
+                        name: name.to_string(),
+                        span,
+                    });
+                }
+                return (body_ty, span);
+            }
+            Expr::Enum {
+                name,
+                variants,
+                body,
+            } => {
+                let declared = Rc::new((*name, variants.clone()));
+                for (index, variant) in variants.iter().enumerate() {
+                    self.consts.push(*variant, index as i64);
+                    self.variants.push(*variant, declared.clone());
+                }
+                self.tail = tail;
+                let (body_ty, _) = self.infer(body);
+                for _ in variants {
+                    self.consts.pop();
+                    self.variants.pop();
+                }
+                let span = self.spans.next_span().unwrap_or_default();
+                let rebound = variants.iter().enumerate().find(|&(i, variant)| {
+                    variants[..i].contains(variant) || binds(body, *variant)
+                });
+                if let Some((_, variant)) = rebound {
+                    self.errors.push(TypeError::ConstantRebound {
+                        name: variant.to_string(),
+                        span,
+                    });
+                }
+                return (body_ty, span);
+            }
+            Expr::Assert { cond, body } => {
+                let found = self.infer(cond);
+                self.expect(Type::Bool, &found);
+                self.tail = tail;
+                self.infer(body).0
+            }
+            Expr::Assign {
+                name,
+                cond,
+                value,
+                body,
+            } => {
+                if let Some(cond) = cond {
+                    let found = self.infer(cond);
+                    self.expect(Type::Bool, &found);
+                }
+                let found = self.infer(value);
+                if self.mutable.get(*name) == Some(&true) {
+                    if let Some(Some(ty)) = self.scope.get(*name).cloned() {
+                        self.expect_converted(ty, value, &found);
+                    }
+                } else {
+                    self.errors.push(TypeError::NotMutable {
+                        name: name.to_string(),
+                        span: found.1,
+                    });
+                }
+                self.tail = tail;
+                self.infer(body).0
+            }
+            Expr::Array(elements) => {
+                let mut elem = None;
+                for element in elements {
+                    let found = self.infer(element);
+                    self.expect_same(&mut elem, &found);
+                }
+                elem.as_ref().and_then(Type::scalar).map(|elem| Type::Array(elem, elements.len()))
+            }
+            Expr::Tuple(components) => {
+                let mut scalars = Vec::new();
+                for component in components {
+                    let found = self.infer(component);
+                    self.expect_scalar(&found);
+                    scalars.push(found.0.as_ref().and_then(Type::scalar));
+                }
+                scalars.into_iter().collect::<Option<_>>().map(Type::Tuple)
+            }
+            Expr::SwapIf { cond, left, right } => {
+                let cond = self.infer(cond);
+                self.expect(Type::Bool, &cond);
+                let left = self.infer(left);
+                self.expect_scalar(&left);
+                let right = self.infer(right);
+                let scalar = left.0.as_ref().and_then(Type::scalar);
+                match scalar {
+                    Some(scalar) => self.expect(scalar.into(), &right),
+                    None => self.expect_scalar(&right),
+                }
+                let scalar = scalar.or_else(|| right.0.as_ref().and_then(Type::scalar));
+                scalar.map(|scalar| Type::Tuple(vec![scalar, scalar]))
+            }
+            Expr::Clamp { value, lo, hi } | Expr::InRange { value, lo, hi } => {
+                let operands = [value, lo, hi].map(|e| self.infer(e));
+                // One integer type for all three, `int` unless any is sized
+                let sized = operands.iter().find_map(|o| match o.0 {
+                    Some(Type::Sized(width)) => Some(Type::Sized(width)),
+                    _ => None,
+                });
+                let ty = sized.unwrap_or(Type::Int);
+                for operand in &operands {
+                    self.expect(ty.clone(), operand);
+                }
+                match expr {
+                    Expr::Clamp { .. } => Some(ty),
+                    _ => Some(Type::Bool),
+                }
+            }
+            Expr::Pow { base, exp } => {
+                let base = self.infer(base);
+                let ty = match base.0 {
+                    Some(Type::Sized(width)) => Type::Sized(width),
+                    _ => Type::Int,
+                };
+                self.expect(ty.clone(), &base);
+                let found = self.infer(exp);
+                self.expect(Type::Int, &found);
+                let consts = &self.consts;
+                let n = exp.const_int_with(&|name| consts.get(name).copied());
+                if found.0 == Some(Type::Int) && n.is_none_or(|n| n < 0) {
+                    self.errors.push(TypeError::NonConstantExponent { span: found.1 });
+                }
+                Some(ty)
+            }
+            Expr::Map { array, f } | Expr::Fold { array, f, .. } => {
+                let found = self.infer(array);
+                let init = match expr {
+                    Expr::Fold { init, .. } => Some(self.infer(init)),
+                    _ => None,
+                };
+                let (_, function) = self.infer_callable(f);
+                let span = self.spans.next_span().unwrap_or_default();
+                let (builtin, arity) = if init.is_some() { ("fold", 2) } else { ("map", 1) };
+                let function = match function {
+                    Some(function) if function.params.len() == arity => function,
+                    // Unbound, reported already, or a parameter, untyped
+                    // until a call, which may be passed a function
+                    None if matches!(**f, Expr::Var(name)
+                        if self.scope.get(name).is_none_or(Option::is_none)
+                            && self.consts.get(name).is_none()) =>
+                    {
+                        return (None, span)
+                    }
+                    _ => {
+                        self.errors.push(TypeError::NotAFunction {
+                            builtin: builtin.to_string(),
+                            arity,
+                            span,
+                        });
+                        return (None, span);
+                    }
+                };
+                let (element, len) = match found {
+                    (Some(Type::Array(element, len)), _) => (element, len),
+                    (Some(ty), span) => {
+                        self.errors.push(TypeError::NotAnArray { found: ty, span });
+                        return (None, span);
+                    }
+                    (None, _) => return (None, span),
+                };
+                self.mapped.insert(expr, Type::Array(element, len));
+                let element = Arg::Value(Some(element.into()));
+                let ty = match init {
+                    None => {
+                        let result = self.check_call(&function, vec![element], span);
+                        self.expect_scalar(&(result.clone(), span));
+                        result.and_then(|ty| ty.scalar()).map(|s| Type::Array(s, len))
+                    }
+                    Some(init) => {
+                        self.expect_scalar(&init);
+                        let acc = Arg::Value(init.0.clone());
+                        let result = self.check_call(&function, vec![acc, element], span);
+                        match init.0 {
+                            Some(ty) => {
+                                self.expect(ty.clone(), &(result, span));
+                                Some(ty)
+                            }
+                            None => result,
+                        }
+                    }
+                };
+                return (ty, span);
+            }
+            Expr::LetTuple { names, value, body } => {
+                let found = self.infer(value);
+                let components = match &found {
+                    (Some(Type::Tuple(components)), _) if components.len() == names.len() => {
+                        components.iter().map(|&c| Some(c.into())).collect()
+                    }
+                    (Some(ty), span) => {
+                        self.errors.push(TypeError::NotATuple {
+                            found: ty.clone(),
+                            len: names.len(),
+                            span: *span,
+                        });
+                        vec![None; names.len()]
+                    }
+                    (None, _) => vec![None; names.len()],
+                };
+                for (name, ty) in names.iter().zip(components) {
+                    self.scope.push(*name, ty);
+                    self.mutable.push(*name, false);
+                }
+                self.tail = tail;
+                let (body_ty, _) = self.infer(body);
+                for _ in names {
+                    self.mutable.pop();
+                    self.scope.pop();
+                }
+                body_ty
+            }
+            Expr::Index { array, index } => {
+                let array = self.infer(array);
+                let index_ty = self.infer(index);
+                self.expect(Type::Int, &index_ty);
+                match array {
+                    (Some(Type::Array(elem, len)), _) => {
+                        if let Expr::Int(k) = index.as_ref() {
+                            if usize::try_from(*k).map_or(true, |k| k >= len) {
+                                self.errors.push(TypeError::IndexOutOfBounds {
+                                    index: *k,
+                                    len,
+                                    span: index_ty.1,
+                                });
+                            }
+                        }
+                        Some(elem.into())
+                    }
+                    (Some(found), span) => {
+                        self.errors.push(TypeError::NotAnArray { found, span });
+                        None
+                    }
+                    (None, _) => None,
+                }
+            }
+            Expr::Lookup {
+                key,
+                entries,
+                default,
+            } => {
+                let mut key_ty = None;
+                let mut value_ty = None;
+                let found = self.infer(key);
+                self.expect_same(&mut key_ty, &found);
+                for (k, v) in entries {
+                    let found = self.infer(k);
+                    self.expect_same(&mut key_ty, &found);
+                    let found = self.infer(v);
+                    self.expect_same(&mut value_ty, &found);
+                }
+                let found = self.infer(default);
+                self.expect_same(&mut value_ty, &found);
+                value_ty
+            }
+            Expr::Match {
+                scrutinee,
+                arms,
+                default,
+            } => {
+                let mut key_ty = None;
+                let mut value_ty = None;
+                let found = self.infer(scrutinee);
+                self.expect_same(&mut key_ty, &found);
+                // The enum of the first variant pattern
+                let mut declared: Option<Enum> = None;
+                for (pattern, arm) in arms {
+                    let found = self.infer(pattern);
+                    self.expect_same(&mut key_ty, &found);
+                    if let Expr::Var(name) = pattern {
+                        self.expect_variant(*name, &mut declared, found.1);
+                    }
+                    let found = self.infer(arm);
+                    self.expect_same(&mut value_ty, &found);
+                }
+                if let Some(default) = default {
+                    let found = self.infer(default);
+                    self.expect_same(&mut value_ty, &found);
+                    return (value_ty, self.spans.next_span().unwrap_or_default());
+                }
+                let span = self.spans.next_span().unwrap_or_default();
+                let covered = |variant: &Symbol| {
+                    let pattern = |(pattern, _): &(Expr, Expr)| *pattern == Expr::Var(*variant);
+                    arms.iter().any(pattern)
+                };
+                let missing = declared.and_then(|d| d.1.iter().find(|v| !covered(v)).copied());
+                if let Some(missing) = missing {
+                    self.errors.push(TypeError::NonExhaustiveMatch {
+                        missing: missing.to_string(),
+                        span,
+                    });
+                }
+                return (value_ty, span);
+            }
+            Expr::Outputs(outputs) => {
+                for (_, value) in outputs {
+                    let found = self.infer(value);
+                    self.expect_scalar(&found);
+                }
+                let span = self.spans.next_span().unwrap_or_default();
+                if !tail {
+                    self.errors.push(TypeError::MisplacedOutputs { span });
+                    return (None, span);
+                }
+                let mut names = HashSet::new();
+                if let Some((name, _)) = outputs.iter().find(|(name, _)| !names.insert(*name)) {
+                    self.errors.push(TypeError::DuplicateOutput {
+                        name: name.to_string(),
+                        span,
+                    });
+                }
+                return (Some(Type::Outputs), span);
+            }
+            Expr::Fn {
+                name,
+                max_depth,
+                params,
+                body,
+                rest,
+            } => {
+                // The body sees only its parameters, untyped until a call,
+                // and the function itself if recursive
+                let function = Rc::new(Function {
+                    recursion: max_depth.map(|depth| (*name, depth)),
+                    params: params.clone(),
+                    body,
+                    spans: self.spans.clone(),
+                    functions: self.functions.clone(),
+                    consts: self.consts.clone(),
+                    variants: self.variants.clone(),
+                    captures: Scope::new(),
+                });
+                let mut outer = std::mem::take(&mut self.scope);
+                let outer_mutable = std::mem::take(&mut self.mutable);
+                for param in params {
+                    self.scope.push(*param, None);
+                }
+                if max_depth.is_some() {
+                    self.functions.push(*name, function.clone());
+                    self.recursing.push((Rc::as_ptr(&function), None, None));
+                    self.infer(body);
+                    self.recursing.pop();
+                    self.functions.pop();
+                } else {
+                    self.infer(body);
+                }
+                std::mem::swap(&mut self.scope, &mut outer);
+                self.mutable = outer_mutable;
+                self.functions.push(*name, function);
+                self.tail = tail;
+                let (rest_ty, _) = self.infer(rest);
+                self.functions.pop();
+                rest_ty
+            }
+            Expr::Call { name, args } => {
+                let args: Vec<_> = args
+                    .iter()
+                    .map(|arg| match self.infer_callable(arg) {
+                        (_, Some(function)) => Arg::Function(function),
+                        (ty, None) => Arg::Value(ty),
+                    })
+                    .collect();
+                let span = self.spans.next_span().unwrap_or_default();
+                let Some(function) = self.functions.get(*name).cloned() else {
+                    // A parameter, untyped until a call, may be passed a function
+                    if let Some(None) = self.scope.get(*name) {
+                        return (None, span);
+                    }
+                    self.errors.push(TypeError::UndefinedFunction {
+                        name: name.to_string(),
+                        span,
+                    });
+                    return (None, span);
+                };
+                if args.len() != function.params.len() {
+                    self.errors.push(TypeError::ArityMismatch {
+                        name: name.to_string(),
+                        expected: function.params.len(),
+                        found: args.len(),
+                        span,
+                    });
+                    return (None, span);
+                }
+                return (self.check_call(&function, args, span), span);
+            }
+            Expr::Lambda { params, body } => {
+                // The body sees the variables around it too, and its
+                // parameters untyped until a call
+                let outer_mutable = std::mem::take(&mut self.mutable);
+                for param in params {
+                    self.scope.push(*param, None);
+                }
+                self.infer(body);
+                for _ in params {
+                    self.scope.pop();
+                }
+                self.mutable = outer_mutable;
+                if !callable {
+                    let span = self.spans.next_span().unwrap_or_default();
+                    self.errors.push(TypeError::FunctionValue { span });
+                    return (None, span);
+                }
+                None
+            }
+            Expr::For {
+                var,
+                start,
+                end,
+                acc,
+                init,
+                body,
+            } => {
+                let mut constant = true;
+                let consts = self.consts.clone();
+                let value = |name| consts.get(name).copied();
+                let const_int = |bound: &Expr| bound.const_int_with(&value);
+                for bound in [start, end] {
+                    let found = self.infer(bound);
+                    self.expect(Type::Int, &found);
+                    if found.0 == Some(Type::Int) && const_int(bound).is_none() {
+                        self.errors.push(TypeError::NonConstantBound { span: found.1 });
+                        constant = false;
+                    }
+                }
+                let iterations = match (const_int(start), const_int(end)) {
+                    (Some(start), Some(end)) => end.saturating_sub(start).max(0) as u64,
+                    _ => 0,
+                };
+                let (init_ty, _) = self.infer(init);
+                let outer = self.unrolled;
+                self.unrolled = outer.saturating_mul(iterations.max(1));
+                // Each iteration is a block of its own
+                let outer_mutable = std::mem::take(&mut self.mutable);
+                self.scope.push(*var, Some(Type::Int));
+                self.scope.push(*acc, init_ty.clone());
+                let found = self.infer(body);
+                self.scope.pop();
+                self.scope.pop();
+                self.mutable = outer_mutable;
+                let unrolled = std::mem::replace(&mut self.unrolled, outer);
+                if let Some(init_ty) = &init_ty {
+                    self.expect(init_ty.clone(), &found);
+                }
+                let span = self.spans.next_span().unwrap_or_default();
+                if constant && unrolled > MAX_ITERATIONS {
+                    self.errors.push(TypeError::TooManyIterations {
+                        iterations: unrolled,
+                        span,
+                    });
+                }
+                return (init_ty, span);
+            }
+            Expr::While {
+                cond,
+                acc,
+                init,
+                body,
+            } => {
+                // The condition comes first but reads `acc`, typed by `init`
+                let acc_ty = self.peek(init).filter(|ty| ty.scalar().is_some());
+                self.scope.push(*acc, acc_ty.clone());
+                let found = self.infer(cond);
+                self.scope.pop();
+                self.expect(Type::Bool, &found);
+                let found = self.infer(init);
+                self.expect_scalar(&found);
+                let outer_mutable = std::mem::take(&mut self.mutable);
+                self.scope.push(*acc, acc_ty.clone());
+                let found = self.infer(body);
+                self.scope.pop();
+                self.mutable = outer_mutable;
+                if let Some(acc_ty) = &acc_ty {
+                    self.expect(acc_ty.clone(), &found);
+                }
+                acc_ty
+            }
+        };
+        (ty, self.spans.next_span().unwrap_or_default())
+    }
+
+    /// Type of `expr` in the current scope, without reporting errors.
+    fn peek(&self, expr: &'a Expr) -> Option<Type> {
+        let mut checker = Checker {
+            scope: self.scope.clone(),
+            mutable: self.mutable.clone(),
+            functions: self.functions.clone(),
+            consts: self.consts.clone(),
+            variants: self.variants.clone(),
+            spans: SpanCursor::none(),
+            errors: Vec::new(),
+            types: Vec::new(),
+            tail: false,
+            statement: false,
+            inputs: HashSet::new(),
+            callable: false,
+            unrolled: self.unrolled,
+            recursing: self.recursing.clone(),
+            results: HashMap::new(),
+            casts: HashMap::new(),
+            promotions: HashMap::new(),
+            mapped: HashMap::new(),
+        };
+        checker.infer(expr).0
+    }
+}
+
+/// Number of calls of `name` in `expr`.
+fn calls(expr: &Expr, name: Symbol) -> u64 {
+    let here = matches!(expr, Expr::Call { name: called, .. } if *called == name);
+    let below: u64 = expr.children().into_iter().map(|child| calls(child, name)).sum();
+    below + u64::from(here)
+}
+
+/// Whether `expr` binds `name` as a variable anywhere.
+fn binds(expr: &Expr, name: Symbol) -> bool {
+    any_node(expr, |expr| match expr {
+        Expr::Let { name: bound, .. } | Expr::Const { name: bound, .. } => *bound == name,
+        Expr::LetTuple { names: params, .. }
+        | Expr::Fn { params, .. }
+        | Expr::Lambda { params, .. } => params.contains(&name),
+        Expr::For { var, acc, .. } => *var == name || *acc == name,
+        Expr::While { acc, .. } => *acc == name,
+        Expr::Enum { variants, .. } => variants.contains(&name),
+        _ => false,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+
+    fn check(input: &str) -> Result<Type, Vec<TypeError>> {
+        let (tokens, spans) = Lexer::new(input).tokenize().unwrap();
+        let (ast, node_spans) = Parser::with_spans(&tokens, &spans)
+            .parse_with_spans()
+            .unwrap();
+        typecheck_with_spans(&ast, &node_spans)
+    }
+
+    #[test]
+    fn test_well_typed() {
+        assert_eq!(check("let x = secret(1) x + 2"), Ok(Type::Int));
+        assert_eq!(check("if secret(1) > 0 then true else false"), Ok(Type::Bool));
+    }
+
+    #[test]
+    fn test_operand_mismatch_span() {
+        let errors = check("1 + true").unwrap_err();
+        assert_eq!(
+            errors,
+            vec![TypeError::Mismatch {
+                expected: Type::Int,
+                found: Type::Bool,
+                span: Span::new(4, 8),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_annotated_let() {
+        assert_eq!(check("let b: secret bool = true b"), Ok(Type::Bool));
+        let errors = check("let b: bool = 1 b and true").unwrap_err();
+        assert_eq!(
+            errors,
+            vec![TypeError::Mismatch {
+                expected: Type::Bool,
+                found: Type::Int,
+                span: Span::new(14, 15),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_branch_mismatch() {
+        let errors = check("if true then 1 else false").unwrap_err();
+        assert!(matches!(errors[0], TypeError::BranchMismatch { .. }));
+    }
+
+    #[test]
+    fn test_node_types_post_order() {
+        let tokens: Vec<_> = Lexer::new("let x = 1 x < 2").collect::<Result<_, _>>().unwrap();
+        let ast = Parser::new(&tokens).parse().unwrap();
+        let types = node_types(&ast);
+        let expected = [Type::Int, Type::Int, Type::Int, Type::Bool, Type::Bool];
+        assert_eq!(types, expected.map(Some));
+    }
+
+    #[test]
+    fn test_arrays() {
+        assert_eq!(check("let a = [1, 2, 3] a[secret(0)] + a[2]"), Ok(Type::Int));
+        let array = Type::Array(Scalar::Bool, 2);
+        assert_eq!(array.to_string(), "[bool; 2]");
+        let errors = check("let a = [true, false] lookup(a, [(a, 1)], 2)").unwrap_err();
+        let spans: Vec<_> = errors.iter().map(TypeError::span).collect();
+        assert_eq!(spans, [Span::new(29, 30), Span::new(34, 35)]);
+        assert_eq!(errors[0], TypeError::NotScalar { found: array, span: spans[0] });
+        let errors = check("[1, 2][2]").unwrap_err();
+        assert_eq!(
+            errors,
+            vec![TypeError::IndexOutOfBounds {
+                index: 2,
+                len: 2,
+                span: Span::new(7, 8),
+            }]
+        );
+        assert!(matches!(check("let x = 5 x[0]").unwrap_err()[0], TypeError::NotAnArray { .. }));
+        assert!(matches!(check("[1, true][0]").unwrap_err()[0], TypeError::Mismatch { .. }));
+        assert!(matches!(check("[1, 2]").unwrap_err()[0], TypeError::NotScalar { .. }));
+    }
+
+    #[test]
+    fn test_tuples() {
+        assert_eq!(check("let (x, b) = (1, secret(true)) if b then x else 0"), Ok(Type::Int));
+        let pair = Type::Tuple(vec![Scalar::Int, Scalar::Bool]);
+        assert_eq!(pair.to_string(), "(int, bool)");
+        assert_eq!(
+            check("let (x, y) = (1, true, 2) x").unwrap_err(),
+            vec![TypeError::NotATuple {
+                found: Type::Tuple(vec![Scalar::Int, Scalar::Bool, Scalar::Int]),
+                len: 2,
+                span: Span::new(13, 25),
+            }]
+        );
+        assert!(matches!(check("let (x, y) = 5 x").unwrap_err()[0], TypeError::NotATuple { .. }));
+        assert!(matches!(check("(1, true)").unwrap_err()[0], TypeError::NotScalar { .. }));
+    }
+
+    #[test]
+    fn test_match() {
+        assert_eq!(check("match secret(2) with 1 => 10, 2 => 20, _ => 0"), Ok(Type::Int));
+        let errors = check("match 1 with true => 1, _ => 2").unwrap_err();
+        assert!(matches!(errors[..], [TypeError::Mismatch { .. }]));
+        let errors = check("match 1 with 1 => [1, 2], _ => [3, 4]").unwrap_err();
+        assert!(matches!(errors[0], TypeError::NotScalar { .. }));
+    }
+
+    #[test]
+    fn test_sort() {
+        assert_eq!(check("sort(secret([3, 1, 2]))[0]"), Ok(Type::Int));
+        assert_eq!(check("sort(b\"ba\") == b\"ab\""), Ok(Type::Bool));
+        let errors = check("sort([true, false])[0]").unwrap_err();
+        assert_eq!(
+            errors,
+            vec![TypeError::Mismatch {
+                expected: Type::Array(Scalar::Int, 2),
+                found: Type::Array(Scalar::Bool, 2),
+                span: Span::new(5, 18),
+            }]
+        );
+        assert!(matches!(check("sort(1)").unwrap_err()[0], TypeError::NotAnArray { .. }));
+    }
+
+    #[test]
+    fn test_swap_if() {
+        let source = "let (x, y) = swap_if(secret(true), 1u8, 2u8) y";
+        assert_eq!(check(source), Ok(Type::Sized(Width::U8)));
+        assert_eq!(check("let (x, y) = swap_if(1 > 2, true, false) x"), Ok(Type::Bool));
+        let errors = check("let (x, y) = swap_if(1, 2, true) x").unwrap_err();
+        assert_eq!(
+            errors,
+            vec![
+                TypeError::Mismatch {
+                    expected: Type::Bool,
+                    found: Type::Int,
+                    span: Span::new(21, 22),
+                },
+                TypeError::Mismatch {
+                    expected: Type::Int,
+                    found: Type::Bool,
+                    span: Span::new(27, 31),
+                },
+            ]
+        );
+        let errors = check("swap_if(true, [1], [2])").unwrap_err();
+        assert!(matches!(errors[0], TypeError::NotScalar { .. }));
+    }
+
+    #[test]
+    fn test_clamp_and_in_range() {
+        assert_eq!(check("clamp(secret(300), 0, 255)"), Ok(Type::Int));
+        assert_eq!(check("clamp(5u8, 0u8, 9u8)"), Ok(Type::Sized(Width::U8)));
+        assert_eq!(check("in_range(secret(5u16), 1u16, 9u16)"), Ok(Type::Bool));
+        assert_eq!(
+            check("in_range(1, true, 2)"),
+            Err(vec![TypeError::Mismatch {
+                expected: Type::Int,
+                found: Type::Bool,
+                span: Span::new(12, 16),
+            }])
+        );
+    }
+
+    #[test]
+    fn test_casts() {
+        assert_eq!(check("(secret(300) as u8) + 1u8"), Ok(Type::Sized(Width::U8)));
+        assert_eq!(check("(7u8 as int) + 1"), Ok(Type::Int));
+        assert_eq!(
+            check("true as u8"),
+            Err(vec![TypeError::Mismatch {
+                expected: Type::Int,
+                found: Type::Bool,
+                span: Span::new(0, 4),
+            }])
+        );
+    }
+
+    #[test]
+    fn test_to_int_and_to_bool() {
+        assert_eq!(check("to_int(secret(1) > 0) + 1"), Ok(Type::Int));
+        assert_eq!(check("to_bool(3u8) and true"), Ok(Type::Bool));
+        assert_eq!(
+            check("to_int(1) + to_bool(true)"),
+            Err(vec![
+                TypeError::Mismatch {
+                    expected: Type::Bool,
+                    found: Type::Int,
+                    span: Span::new(7, 8),
+                },
+                TypeError::Mismatch {
+                    expected: Type::Int,
+                    found: Type::Bool,
+                    span: Span::new(20, 24),
+                },
+                TypeError::Mismatch {
+                    expected: Type::Int,
+                    found: Type::Bool,
+                    span: Span::new(12, 25),
+                },
+            ])
+        );
+    }
+
+    #[test]
+    fn test_assert() {
+        assert_eq!(check("let k = secret(1u8) assert(k > 0u8) k"), Ok(Type::Sized(Width::U8)));
+        assert_eq!(
+            check("assert(1) true"),
+            Err(vec![TypeError::Mismatch {
+                expected: Type::Bool,
+                found: Type::Int,
+                span: Span::new(7, 8),
+            }])
+        );
+    }
+
+    #[test]
+    fn test_byte_string_equality() {
+        assert_eq!(check("let a = [true, false] a == a"), Ok(Type::Bool));
+        assert_eq!(check(r#"secret(b"abc") != [97, 98, 99]"#), Ok(Type::Bool));
+        let errors = check(r#"b"abc" == b"abcd""#).unwrap_err();
+        assert_eq!(
+            errors,
+            vec![TypeError::Mismatch {
+                expected: Type::Array(Scalar::Int, 3),
+                found: Type::Array(Scalar::Int, 4),
+                span: Span::new(10, 17),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_lookup() {
+        assert_eq!(check("lookup(secret(2), [(1, true), (2, false)], true)"), Ok(Type::Bool));
+        let errors = check("lookup(1, [(true, 10), (2, false)], 0)").unwrap_err();
+        assert_eq!(
+            errors,
+            vec![
+                TypeError::Mismatch {
+                    expected: Type::Int,
+                    found: Type::Bool,
+                    span: Span::new(12, 16),
+                },
+                TypeError::Mismatch {
+                    expected: Type::Int,
+                    found: Type::Bool,
+                    span: Span::new(27, 32),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_outputs() {
+        assert_eq!(check("let k = secret(1) outputs(a = k, b = k > 0)"), Ok(Type::Outputs));
+        assert_eq!(
+            check("if true then outputs(a = 1) else 2").unwrap_err(),
+            vec![TypeError::MisplacedOutputs { span: Span::new(13, 27) }]
+        );
+        assert_eq!(
+            check("outputs(a = 1, a = 2)").unwrap_err(),
+            vec![TypeError::DuplicateOutput {
+                name: "a".to_string(),
+                span: Span::new(0, 21),
+            }]
+        );
+        assert!(matches!(check("outputs(a = [1])").unwrap_err()[0], TypeError::NotScalar { .. }));
+    }
+
+    #[test]
+    fn test_inputs() {
+        let source = "input secret k: int input n: u8 if k > 0 then n else 0u8";
+        assert_eq!(check(source), Ok(Type::Sized(Width::U8)));
+        assert_eq!(
+            check("let x = 1 + (input k: int k) x").unwrap_err(),
+            vec![TypeError::MisplacedInput { span: Span::new(13, 25) }]
+        );
+        assert_eq!(
+            check("input k: int input k: bool k").unwrap_err(),
+            vec![TypeError::DuplicateInput {
+                name: "k".to_string(),
+                span: Span::new(13, 26),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_unbound_variable() {
+        let errors = check("let x = 1 y").unwrap_err();
+        assert_eq!(
+            errors,
+            vec![TypeError::Unbound {
+                name: "y".to_string(),
+                span: Span::new(10, 11),
+            }]
+        );
+        // A binding is not in scope in its own value, nor after its body
+        let unbound = |source| match &check(source).unwrap_err()[..] {
+            [TypeError::Unbound { span, .. }] => *span,
+            errors => panic!("{:?}", errors),
+        };
+        assert_eq!(unbound("let x = x + 1 x"), Span::new(8, 9));
+        assert_eq!(unbound("(let x = 1 x) + x"), Span::new(16, 17));
+    }
+
+    #[test]
+    fn test_shadowing() {
+        // The inner binding wins in its body and takes its own value's type
+        assert_eq!(check("let x = 1 let x = x > 0 x"), Ok(Type::Bool));
+        assert_eq!(check("let x = true (let x = 1 x) + 1"), Ok(Type::Int));
+    }
+
+    #[test]
+    fn test_functions_checked_per_call() {
+        assert_eq!(check("fn id(x) = x if id(true) then id(1) else id(2)"), Ok(Type::Int));
+        // Found by both calls, reported once
+        let errors = check("fn inc(x) = x + 1 inc(true) + inc(false)").unwrap_err();
+        assert_eq!(
+            errors,
+            vec![TypeError::Mismatch {
+                expected: Type::Int,
+                found: Type::Bool,
+                span: Span::new(12, 13),
+            }]
+        );
+        let errors = check("fn add(x, y) = x + y add(1)").unwrap_err();
+        assert!(matches!(errors[0], TypeError::ArityMismatch { expected: 2, found: 1, .. }));
+        // Bodies see only their parameters
+        let errors = check("let k = 1 fn f(x) = x + k f(2)").unwrap_err();
+        assert!(matches!(&errors[0], TypeError::Unbound { name, .. } if name == "k"));
+        let call = Expr::Call {
+            name: "g".into(),
+            args: vec![],
+        };
+        assert!(matches!(typecheck(&call).unwrap_err()[0], TypeError::UndefinedFunction { .. }));
+    }
+
+    #[test]
+    fn test_lambdas() {
+        let source = "fn apply(f, x) = f(x) let k = true apply(fn(y) => y > 0 and k, 3)";
+        assert_eq!(check(source), Ok(Type::Bool));
+        let errors = check("let k = 1 let f = fn(x) => x + k f(true)").unwrap_err();
+        assert!(matches!(errors[0], TypeError::Mismatch { found: Type::Bool, .. }));
+        let errors = check("let f = fn(x) => x + 1 f").unwrap_err();
+        assert_eq!(errors, vec![TypeError::FunctionValue { span: Span::new(23, 24) }]);
+        let errors = check("fn g(x) = x + 1 fn(y) => g(y)").unwrap_err();
+        assert!(matches!(errors[0], TypeError::FunctionValue { .. }));
+    }
+
+    #[test]
+    fn test_constants() {
+        let source = "const n = 2 fn f(a) = a[n + 1] const m = n * 2 for i in 0..m with s = 0 do s";
+        assert_eq!(check(source), Ok(Type::Int));
+        let errors = check("let k = 2 const n = k + 1 n").unwrap_err();
+        assert_eq!(errors, vec![TypeError::NonConstantValue { span: Span::new(20, 25) }]);
+        let errors = check("const n = secret(1) n").unwrap_err();
+        assert!(matches!(errors[..], [TypeError::SecretConstant { .. }]));
+        let errors = check("const n = true n").unwrap_err();
+        assert!(matches!(errors[..], [TypeError::Mismatch { found: Type::Bool, .. }]));
+        let errors = check("const n = 1 fn f(n) = n f(2)").unwrap_err();
+        assert!(matches!(&errors[..], [TypeError::ConstantRebound { name, .. }] if name == "n"));
+    }
+
+    #[test]
+    fn test_recursion() {
+        let source = "#[max_depth(4)] fn f(n) = if n == 0 then 0u8 else f(n - 1) f(secret(3))";
+        assert_eq!(check(source), Ok(Type::Sized(Width::U8)));
+        let errors = check("#[max_depth(4)] fn f(n) = f(n - 1) f(3)").unwrap_err();
+        assert_eq!(
+            errors,
+            vec![TypeError::NoBaseCase {
+                name: "f".to_string(),
+                span: Span::new(35, 39),
+            }]
+        );
+        // Two calls in the body double the copies at every level
+        let source = "#[max_depth(20)] fn f(n) = if n == 0 then 0 else f(n - 1) + f(n - 2) f(3)";
+        let errors = check(source).unwrap_err();
+        assert!(matches!(errors[..], [TypeError::TooManyIterations { iterations: 1048575, .. }]));
+    }
+
+    #[test]
+    fn test_truncated_calls() {
+        let source = "#[max_depth(2)] fn f(n) = if n == 0 then 0 else n + f(n - 1) f(5)";
+        assert_eq!(
+            check(source).unwrap_err(),
+            vec![TypeError::TruncatedCall {
+                name: "f".to_string(),
+                max_depth: 2,
+                span: Span::new(52, 60),
+            }]
+        );
+        // Deep enough, or only reached for some secret argument
+        let source = "#[max_depth(6)] fn f(n) = if n == 0 then 0 else n + f(n - 1) f(5)";
+        assert_eq!(check(source), Ok(Type::Int));
+        let source = "#[max_depth(2)] fn f(n) = if n == 0 then 0 else n + f(n - 1) f(secret(5))";
+        assert_eq!(check(source), Ok(Type::Int));
+        // Constants flow through `let`s and into the branch that runs
+        let source = "#[max_depth(2)] fn f(n) = if n > 1 then f(n - 1) else n \
+                      let k = 3 if k < 2 then f(9) else f(k)";
+        assert!(matches!(check(source).unwrap_err()[..], [TypeError::TruncatedCall { .. }]));
+        let source = "#[max_depth(2)] fn f(n) = if n > 1 then f(n - 1) else n \
+                      let k = 3 if k < 2 then f(9) else f(k - 1)";
+        assert_eq!(check(source), Ok(Type::Int));
+    }
+
+    #[test]
+    fn test_for_loops() {
+        assert_eq!(check("let a = [1, 2] for i in 0..2 with s = 0 do s + a[i]"), Ok(Type::Int));
+        let errors = check("let n = 2 for i in 0..n with s = 0 do s + i").unwrap_err();
+        assert_eq!(errors, vec![TypeError::NonConstantBound { span: Span::new(22, 23) }]);
+        let errors = check("for i in 0..2 with s = 0 do s > i").unwrap_err();
+        assert!(matches!(errors[0], TypeError::Mismatch { expected: Type::Int, .. }));
+        // Nested loops count together
+        let source = "for i in 0..300 with a = 0 do for j in 1..301 with b = a do b + j";
+        let errors = check(source).unwrap_err();
+        assert!(matches!(errors[..], [TypeError::TooManyIterations { iterations: 90000, .. }]));
+    }
+
+    #[test]
+    fn test_while_loops() {
+        assert_eq!(check("while n < 100 with n = 1 do n * 2"), Ok(Type::Int));
+        assert_eq!(check("while not b with b = false do true"), Ok(Type::Bool));
+        let errors = check("while n with n = 1 do n").unwrap_err();
+        assert_eq!(
+            errors,
+            vec![TypeError::Mismatch {
+                expected: Type::Bool,
+                found: Type::Int,
+                span: Span::new(6, 7),
+            }]
+        );
+        let errors = check("while true with a = [1] do a").unwrap_err();
+        assert!(matches!(errors[0], TypeError::NotScalar { .. }));
+    }
+
+    #[test]
+    fn test_assignments() {
+        assert_eq!(check("let mut c = 0; if secret(1) > 0 then c = c + 1; c"), Ok(Type::Int));
+        let errors = check("let c = 0; c = 1; c").unwrap_err();
+        assert_eq!(
+            errors,
+            vec![TypeError::NotMutable {
+                name: "c".to_string(),
+                span: Span::new(15, 16),
+            }]
+        );
+        let errors = check("let mut c = 0; c = true; c").unwrap_err();
+        assert!(matches!(errors[..], [TypeError::Mismatch { expected: Type::Int, .. }]));
+        let errors = check("let mut a = [1]; 0").unwrap_err();
+        assert!(matches!(errors[..], [TypeError::NotScalar { .. }]));
+        // Shadowed by an immutable binding, or out of reach of a loop body
+        for source in [
+            "let mut c = 0; let c = 1; c = 2; c",
+            "let mut c = 0; for i in 0..2 with s = 0 do (c = i; s + c)",
+        ] {
+            let errors = check(source).unwrap_err();
+            assert!(matches!(errors[..], [TypeError::NotMutable { .. }]), "{}", source);
+        }
+    }
+
+    #[test]
+    fn test_sized_integers() {
+        assert_eq!(check("let x: u8 = 3u8; -(x + 1u8)"), Ok(Type::Sized(Width::U8)));
+        assert_eq!(check("secret(3u32) < 4u32"), Ok(Type::Bool));
+        let errors = check("let x = 3u8 x + true").unwrap_err();
+        assert_eq!(
+            errors,
+            vec![TypeError::Mismatch {
+                expected: Type::Sized(Width::U8),
+                found: Type::Bool,
+                span: Span::new(16, 20),
+            }]
+        );
+        assert!(check("1u32 + 1i32").is_err());
+    }
+
+    #[test]
+    fn test_integer_promotion() {
+        // An unsuffixed literal takes the other operand's type if it fits
+        assert_eq!(check("1u8 + 2"), Ok(Type::Sized(Width::U8)));
+        assert_eq!(check("2 * secret(3u16) == 6"), Ok(Type::Bool));
+        assert_eq!(check("1u8 + 300"), Ok(Type::Int));
+        // Otherwise the narrower operand widens, if no value is lost
+        assert_eq!(check("1u8 + 2u16"), Ok(Type::Sized(Width::U16)));
+        assert_eq!(check("let n = 5 n + 3u32"), Ok(Type::Int));
+        assert_eq!(check("if true then 1u8 else 2i32"), Ok(Type::Sized(Width::I32)));
+        assert_eq!(check("let x: u16 = 1u8 x"), Ok(Type::Sized(Width::U16)));
+        assert_eq!(check("let mut c: u64 = 0; c = 7u32; c"), Ok(Type::Sized(Width::U64)));
+        assert!(check("let n = 1 1u64 + n").is_err());
+        // Narrowing takes a cast
+        let errors = check("let x: u8 = 1u16 x").unwrap_err();
+        assert_eq!(
+            errors,
+            vec![TypeError::Narrowing {
+                from: Type::Sized(Width::U16),
+                to: Type::Sized(Width::U8),
+                span: Span::new(12, 16),
+            }]
+        );
+        assert_eq!(errors[0].code(), codes::NARROWING);
+        let errors = check("let mut c: u8 = 0; c = 256; c").unwrap_err();
+        assert!(matches!(errors[..], [TypeError::Narrowing { from: Type::Int, .. }]));
+        assert_eq!(check("let x: u8 = 1u16 as u8 x"), Ok(Type::Sized(Width::U8)));
+    }
+
+    #[test]
+    fn test_pow() {
+        assert_eq!(check("pow(secret(3), 4)"), Ok(Type::Int));
+        assert_eq!(check("const N = 2 pow(7u8, N + 1)"), Ok(Type::Sized(Width::U8)));
+        assert_eq!(check("pow(2, 0)"), Ok(Type::Int));
+        for source in ["let n = 3 pow(2, n)", "pow(2, secret(3))", "pow(2, -1)"] {
+            let errors = check(source).unwrap_err();
+            assert!(matches!(errors[..], [TypeError::NonConstantExponent { .. }]), "{}", source);
+        }
+        let errors = check("pow(true, 2)").unwrap_err();
+        assert!(matches!(errors[..], [TypeError::Mismatch { found: Type::Bool, .. }]));
+    }
+
+    #[test]
+    fn test_map_and_fold() {
+        assert_eq!(check("let a = [1, 2, 3] map(a, fn(x) => x > 1)[2]"), Ok(Type::Bool));
+        let source = "fn add(s, x) = s + x fold(secret([1u8, 2u8]), 0u8, add)";
+        assert_eq!(check(source), Ok(Type::Sized(Width::U8)));
+        let source = "fn apply(g) = fold(map([1, 2], g), 0, fn(s, x) => s + x) apply(fn(x) => x)";
+        assert_eq!(check(source), Ok(Type::Int));
+        for source in ["map([1], 2)[0]", "map([1], fn(x, y) => x)[0]", "fold([1], 0, fn(s) => s)"] {
+            let errors = check(source).unwrap_err();
+            assert!(matches!(errors[..], [TypeError::NotAFunction { .. }]), "{}", source);
+        }
+        let errors = check("map(1, fn(x) => x)[0]").unwrap_err();
+        assert!(matches!(errors[..], [TypeError::NotAnArray { found: Type::Int, .. }]));
+        let errors = check("fold([1], true, fn(s, x) => s + x)").unwrap_err();
+        assert!(matches!(errors[..], [TypeError::Mismatch { .. }, ..]));
+        let errors = check("map([1], fn(x) => [x])[0]").unwrap_err();
+        assert!(matches!(errors[..], [TypeError::NotScalar { .. }, ..]));
+        // Each `map` is unrolled for one length of array
+        let source = "fn total(a) = fold(a, 0, fn(s, x) => s + x) total([1, 2]) + total([1, 2, 3])";
+        let errors = check(source).unwrap_err();
+        assert!(matches!(errors[..], [TypeError::Mismatch { .. }]));
+    }
+
+    #[test]
+    fn test_options() {
+        let source = "let o = if secret(true) then some(2u8) else none unwrap_or(o, 1u8)";
+        assert_eq!(check(source), Ok(Type::Sized(Width::U8)));
+        assert_eq!(check("unwrap_or(if true then none else none, false)"), Ok(Type::Bool));
+        let errors = check("unwrap_or(some(1), true)").unwrap_err();
+        assert!(matches!(errors[..], [TypeError::Mismatch { found: Type::Bool, .. }]));
+        let errors = check("unwrap_or(1, 2)").unwrap_err();
+        let expected = Type::Option(None);
+        assert!(matches!(&errors[..], [TypeError::Mismatch { expected: e, .. }] if *e == expected));
+        let errors = check("if true then some(1) else some(false)").unwrap_err();
+        assert!(matches!(errors[..], [TypeError::BranchMismatch { .. }]));
+        assert!(check("some(1) == some(1)").is_err());
+        assert!(check("some([1, 2])").is_err());
+        assert!(check("some(1)").is_err());
+    }
+
+    #[test]
+    fn test_enums() {
+        let source = "enum C = A | B | D let c = secret(B) match c with A => 1, B => 2, D => 3";
+        assert_eq!(check(source), Ok(Type::Int));
+        assert_eq!(check("enum C = A | B match 1 with A => true, _ => false"), Ok(Type::Bool));
+        let errors = check("enum C = A | B | D match A with A => 1, D => 2").unwrap_err();
+        assert!(matches!(&errors[..],
+            [TypeError::NonExhaustiveMatch { missing, .. }] if missing == "B"));
+        let source = "enum C = A | B enum S = X | Y match A with A => 1, B => 2, X => 3";
+        let errors = check(source).unwrap_err();
+        assert!(matches!(&errors[..],
+            [TypeError::NotAVariant { name, expected, .. }] if name == "X" && expected == "`C`"));
+        let errors = check("enum C = A | B let y = 1 match A with y => 1, _ => 2").unwrap_err();
+        assert!(matches!(&errors[..], [TypeError::NotAVariant { name, .. }] if name == "y"));
+        let errors = check("enum C = A | A A").unwrap_err();
+        assert!(matches!(&errors[..], [TypeError::ConstantRebound { name, .. }] if name == "A"));
+        let errors = check("enum C = A | B let A = 1 A").unwrap_err();
+        assert!(matches!(&errors[..], [TypeError::ConstantRebound { name, .. }] if name == "A"));
+    }
+
+}
@@ -0,0 +1,343 @@
+// SPDX-License-Identifier: MIT OR AGPL-3.0-or-later
+// SPDX-FileCopyrightText: 2024-2025 hyperpolymath
+
+//! IR verifier.
+//!
+//! Checks the verification conditions of `docs/IR_SPEC.adoc` on a finished
+//! IR tree. Secrecy flags may over-approximate (a public value may be
+//! upgraded to secret, per the lattice `Public ⊑ Secret`), but a flag must
+//! never claim a value is public when it depends on secret data.
+
+use crate::codes;
+use crate::ir::ObliExpr;
+use crate::span::{NodeSpans, Span, SpanCursor};
+use crate::stack;
+use crate::symbol::Scope;
+use thiserror::Error;
+
+pub mod harnesses;
+
+#[derive(Error, Debug, Clone, PartialEq)]
+pub enum VerifyError {
+    /// VC-2: `PubIf` must only branch on public conditions.
+    #[error("PubIf branches on a secret condition")]
+    SecretPubIf { span: Span },
+    /// VC-1: `CtSelect` must only be used for secret conditions.
+    #[error("CtSelect used with a public condition")]
+    PublicCtSelect { span: Span },
+    /// VC-3/VC-5: a node is flagged public but depends on secret data.
+    #[error("{node} is marked public but depends on secret data")]
+    SecrecyDowngrade { node: &'static str, span: Span },
+    /// VC-4: a variable reference is public but its binding is secret.
+    #[error("variable `{name}` is marked public but bound to a secret value")]
+    VarDowngrade { name: String, span: Span },
+    /// VC-6: a `While` loop's condition depends on secret data. Unlike the
+    /// others, this is a program the transform cannot make oblivious.
+    #[error("while loop condition depends on secret data")]
+    SecretLoopCondition { span: Span },
+    /// VC-7: a `Let` is public but binds secret data. The transform
+    /// produces one only for a variable declared `pub`.
+    #[error("variable `{name}` is declared `pub` but bound to a secret value")]
+    DeclaredPublic { name: String, span: Span },
+}
+
+impl VerifyError {
+    /// Catalog code of this error.
+    pub fn code(&self) -> &'static str {
+        match self {
+            VerifyError::SecretPubIf { .. } => codes::SECRET_PUB_IF,
+            VerifyError::PublicCtSelect { .. } => codes::PUBLIC_CT_SELECT,
+            VerifyError::SecrecyDowngrade { .. } => codes::SECRECY_DOWNGRADE,
+            VerifyError::VarDowngrade { .. } => codes::VAR_DOWNGRADE,
+            VerifyError::SecretLoopCondition { .. } => codes::SECRET_LOOP_CONDITION,
+            VerifyError::DeclaredPublic { .. } => codes::DECLARED_PUBLIC,
+        }
+    }
+
+    /// Source span of the offending node (empty if spans were not supplied).
+    pub fn span(&self) -> Span {
+        match self {
+            VerifyError::SecretPubIf { span }
+            | VerifyError::PublicCtSelect { span }
+            | VerifyError::SecrecyDowngrade { span, .. }
+            | VerifyError::VarDowngrade { span, .. }
+            | VerifyError::SecretLoopCondition { span }
+            | VerifyError::DeclaredPublic { span, .. } => *span,
+        }
+    }
+}
+
+/// Verify an IR tree, returning every violated condition.
+pub fn verify(expr: &ObliExpr) -> Vec<VerifyError> {
+    Verifier::new(SpanCursor::none()).run(expr)
+}
+
+/// Verify an IR tree, reporting violations at the spans recorded by
+/// [`to_oblivious_with_spans`](crate::transform::to_oblivious_with_spans).
+pub fn verify_with_spans(expr: &ObliExpr, spans: &NodeSpans) -> Vec<VerifyError> {
+    Verifier::new(SpanCursor::new(Some(spans))).run(expr)
+}
+
+struct Verifier<'a> {
+    scope: Scope<bool>,
+    spans: SpanCursor<'a>,
+    errors: Vec<VerifyError>,
+}
+
+impl<'a> Verifier<'a> {
+    fn new(spans: SpanCursor<'a>) -> Self {
+        Self {
+            scope: Scope::new(),
+            spans,
+            errors: Vec::new(),
+        }
+    }
+
+    fn run(mut self, expr: &ObliExpr) -> Vec<VerifyError> {
+        self.visit(expr);
+        self.errors
+    }
+
+    fn visit(&mut self, expr: &ObliExpr) {
+        stack::guard(|| self.visit_node(expr))
+    }
+
+    fn visit_node(&mut self, expr: &ObliExpr) {
+        // Children first, so spans are consumed in post-order
+        match expr {
+            ObliExpr::PubInt(_)
+            | ObliExpr::PubBool(_)
+            | ObliExpr::SecretInt(_)
+            | ObliExpr::SecretBool(_)
+            | ObliExpr::Sized { .. }
+            | ObliExpr::Var { .. }
+            | ObliExpr::Input { .. } => {}
+            ObliExpr::BinOp { left, right, .. } => {
+                self.visit(left);
+                self.visit(right);
+            }
+            ObliExpr::UnaryOp { expr, .. } | ObliExpr::CtCast { expr, .. } => self.visit(expr),
+            // A secret condition is only checked at the end, so nothing leaks
+            ObliExpr::Assert { cond, body } => {
+                self.visit(cond);
+                self.visit(body);
+            }
+            ObliExpr::CtSelect {
+                cond,
+                then_val,
+                else_val,
+            } => {
+                self.visit(cond);
+                self.visit(then_val);
+                self.visit(else_val);
+            }
+            ObliExpr::PubIf {
+                cond,
+                then_branch,
+                else_branch,
+            } => {
+                self.visit(cond);
+                self.visit(then_branch);
+                self.visit(else_branch);
+            }
+            ObliExpr::Let {
+                name, value, body, ..
+            } => {
+                self.visit(value);
+                self.scope.push(*name, value.is_secret());
+                self.visit(body);
+                self.scope.pop();
+            }
+            ObliExpr::Outputs(outputs) => {
+                for (_, value) in outputs {
+                    self.visit(value);
+                }
+            }
+            ObliExpr::Table { index, .. } => self.visit(index),
+            // A deliberate reveal, so no downgrade to report
+            ObliExpr::Declassify(inner) => self.visit(inner),
+            ObliExpr::While {
+                cond,
+                acc,
+                init,
+                body,
+                is_secret,
+            } => {
+                self.scope.push(*acc, *is_secret);
+                self.visit(cond);
+                self.scope.pop();
+                self.visit(init);
+                self.scope.push(*acc, *is_secret);
+                self.visit(body);
+                self.scope.pop();
+            }
+            ObliExpr::CtSwap {
+                cond,
+                left,
+                right,
+                names,
+                body,
+                is_secret,
+            } => {
+                self.visit(cond);
+                self.visit(left);
+                self.visit(right);
+                names.iter().for_each(|name| self.scope.push(*name, *is_secret));
+                self.visit(body);
+                names.iter().for_each(|_| self.scope.pop());
+            }
+        }
+
+        let span = self.spans.next_span().unwrap_or_default();
+        match expr {
+            ObliExpr::Var { name, is_secret } => {
+                let bound_secret = self.scope.get(*name).is_some_and(|secret| *secret);
+                if bound_secret && !is_secret {
+                    self.errors.push(VerifyError::VarDowngrade {
+                        name: name.to_string(),
+                        span,
+                    });
+                }
+            }
+            ObliExpr::BinOp {
+                left,
+                right,
+                is_secret: false,
+                ..
+            } if left.is_secret() || right.is_secret() => {
+                self.errors.push(VerifyError::SecrecyDowngrade {
+                    node: "BinOp",
+                    span,
+                });
+            }
+            ObliExpr::UnaryOp {
+                expr,
+                is_secret: false,
+                ..
+            } if expr.is_secret() => {
+                self.errors.push(VerifyError::SecrecyDowngrade {
+                    node: "UnaryOp",
+                    span,
+                });
+            }
+            ObliExpr::CtCast {
+                expr,
+                is_secret: false,
+                ..
+            } if expr.is_secret() => {
+                self.errors.push(VerifyError::SecrecyDowngrade {
+                    node: "CtCast",
+                    span,
+                });
+            }
+            ObliExpr::CtSelect { cond, .. } if !cond.is_secret() => {
+                self.errors.push(VerifyError::PublicCtSelect { span });
+            }
+            ObliExpr::PubIf { cond, .. } if cond.is_secret() => {
+                self.errors.push(VerifyError::SecretPubIf { span });
+            }
+            ObliExpr::Let {
+                name,
+                value,
+                is_secret: false,
+                ..
+            } if value.is_secret() => {
+                self.errors.push(VerifyError::DeclaredPublic {
+                    name: name.to_string(),
+                    span,
+                });
+            }
+            ObliExpr::While { cond, .. } if cond.is_secret() => {
+                self.errors.push(VerifyError::SecretLoopCondition { span });
+            }
+            ObliExpr::While {
+                init,
+                body,
+                is_secret: false,
+                ..
+            } if init.is_secret() || body.is_secret() => {
+                self.errors.push(VerifyError::SecrecyDowngrade {
+                    node: "While",
+                    span,
+                });
+            }
+            ObliExpr::CtSwap {
+                cond,
+                left,
+                right,
+                is_secret: false,
+                ..
+            } if [cond, left, right].iter().any(|e| e.is_secret()) => {
+                self.errors.push(VerifyError::SecrecyDowngrade {
+                    node: "CtSwap",
+                    span,
+                });
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+    use crate::transform::to_oblivious;
+
+    fn transform(input: &str) -> ObliExpr {
+        let tokens: Vec<_> = Lexer::new(input).filter_map(Result::ok).collect();
+        to_oblivious(&Parser::new(&tokens).parse().unwrap())
+    }
+
+    #[test]
+    fn test_transform_output_verifies() {
+        for src in [
+            "let x = secret(1) if x > 0 then secret(1) else secret(0)",
+            "let x = 1 let y = secret(x) x + y",
+            "let x = secret(1) let x = 2 if x > 0 then 1 else 0",
+        ] {
+            assert_eq!(verify(&transform(src)), vec![], "{}", src);
+        }
+    }
+
+    #[test]
+    fn test_secret_pub_if_rejected() {
+        let ir = ObliExpr::PubIf {
+            cond: Box::new(ObliExpr::SecretBool(true)),
+            then_branch: Box::new(ObliExpr::PubInt(1)),
+            else_branch: Box::new(ObliExpr::PubInt(0)),
+        };
+        assert!(matches!(verify(&ir)[..], [VerifyError::SecretPubIf { .. }]));
+    }
+
+    #[test]
+    fn test_var_downgrade_rejected() {
+        let ir = ObliExpr::Let {
+            name: "k".into(),
+            value: Box::new(ObliExpr::SecretInt(7)),
+            body: Box::new(ObliExpr::Var {
+                name: "k".into(),
+                is_secret: false,
+            }),
+            is_secret: true,
+        };
+        assert!(matches!(verify(&ir)[..], [VerifyError::VarDowngrade { .. }]));
+    }
+
+    #[test]
+    fn test_loops_verify_unless_condition_secret() {
+        for src in [
+            "while n < 100 with n = 1 do n * 2",
+            "let k = secret(3) while n < 10 with n = 0 do n + 1",
+            "while false with n = secret(1) do n + 1",
+            "secret(while n < 10 with n = 0 do n + 1)",
+        ] {
+            assert_eq!(verify(&transform(src)), vec![], "{}", src);
+        }
+        let errors = verify(&transform("let k = secret(3) while n < k with n = 0 do n + 1"));
+        assert!(matches!(errors[..], [VerifyError::SecretLoopCondition { .. }]));
+        let errors = verify(&transform("while n < 10 with n = 0 do n + secret(1)"));
+        assert!(matches!(errors[..], [VerifyError::SecretLoopCondition { .. }]));
+    }
+}
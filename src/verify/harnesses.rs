@@ -0,0 +1,201 @@
+// SPDX-License-Identifier: MIT OR AGPL-3.0-or-later
+// SPDX-FileCopyrightText: 2024-2025 hyperpolymath
+
+//! Kani proof harnesses for the emitted runtime prelude.
+//!
+//! [`generate`] writes a standalone Rust file holding the prelude exactly
+//! as the Rust back end emits it, one `check_*` function per property in
+//! [`HARNESSES`], and a `#[cfg(kani)]` module with a `#[kani::proof]`
+//! harness per check that draws every argument from `kani::any()`. Kani
+//! then proves each property over the full input domain:
+//!
+//! ```no_run
+//! std::fs::write("prelude_proofs.rs", obli_transpiler::verify::harnesses::generate())?;
+//! // then: kani prelude_proofs.rs
+//! # Ok::<(), std::io::Error>(())
+//! ```
+//!
+//! The checks are ordinary functions, so the same file also builds with
+//! `rustc` for spot tests. Kani checks overflow and memory safety as well
+//! as the assertions, so it also reports `ct_neg` of `i64::MIN` and
+//! `ct_select` reading past a value narrower than 64 bits.
+
+use crate::emit::RUNTIME_PRELUDE;
+
+/// One property of the prelude.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Harness {
+    pub name: &'static str,
+    /// Arguments of the check as `(name, type)`.
+    pub params: &'static [(&'static str, &'static str)],
+    /// Precondition passed to `kani::assume`, if any.
+    pub assume: Option<&'static str>,
+    /// Body of the check.
+    pub check: &'static str,
+}
+
+const SELECT_I64: &[(&str, &str)] = &[("cond", "bool"), ("a", "i64"), ("b", "i64")];
+const SELECT_BOOL: &[(&str, &str)] = &[("cond", "bool"), ("a", "bool"), ("b", "bool")];
+const I64_PAIR: &[(&str, &str)] = &[("a", "i64"), ("b", "i64")];
+
+/// Every property [`generate`] writes a harness for.
+pub const HARNESSES: &[Harness] = &[
+    Harness {
+        name: "ct_select_i64",
+        params: SELECT_I64,
+        assume: None,
+        check: "assert_eq!(ct_select(&Secret(cond), &Secret(a), &Secret(b)).0, \
+                if cond { a } else { b });",
+    },
+    Harness {
+        name: "ct_select_bool",
+        params: SELECT_BOOL,
+        assume: None,
+        check: "assert_eq!(ct_select(&Secret(cond), &Secret(a), &Secret(b)).0, \
+                if cond { a } else { b });",
+    },
+    Harness {
+        name: "ct_eq",
+        params: I64_PAIR,
+        assume: None,
+        check: "assert_eq!(Secret(a).ct_eq(&Secret(b)).0, a == b);\n\
+                assert_eq!(Secret(a).ct_ne(&Secret(b)).0, a != b);",
+    },
+    Harness {
+        name: "ct_add",
+        params: I64_PAIR,
+        assume: None,
+        check: "assert_eq!(Secret(a).ct_add(&Secret(b)).0, a.wrapping_add(b));",
+    },
+    Harness {
+        name: "ct_sub",
+        params: I64_PAIR,
+        assume: None,
+        check: "assert_eq!(Secret(a).ct_sub(&Secret(b)).0, a.wrapping_sub(b));",
+    },
+    Harness {
+        name: "ct_mul",
+        params: I64_PAIR,
+        assume: None,
+        check: "assert_eq!(Secret(a).ct_mul(&Secret(b)).0, a.wrapping_mul(b));",
+    },
+    Harness {
+        name: "ct_saturating",
+        params: I64_PAIR,
+        assume: None,
+        check: "assert_eq!(Secret(a).ct_add_sat(&Secret(b)).0, a.saturating_add(b));\n\
+                assert_eq!(Secret(a).ct_sub_sat(&Secret(b)).0, a.saturating_sub(b));\n\
+                assert_eq!(Secret(a).ct_mul_sat(&Secret(b)).0, a.saturating_mul(b));",
+    },
+    Harness {
+        name: "ct_div_mod",
+        params: I64_PAIR,
+        assume: Some("b != 0"),
+        check: "let q = Secret(a).ct_div(&Secret(b)).0;\n\
+                let r = Secret(a).ct_mod(&Secret(b)).0;\n\
+                assert_eq!(q.wrapping_mul(b).wrapping_add(r), a);",
+    },
+    Harness {
+        name: "ct_bitwise",
+        params: I64_PAIR,
+        assume: None,
+        check: "assert_eq!(Secret(a).ct_bit_and(&Secret(b)).0, a & b);\n\
+                assert_eq!(Secret(a).ct_bit_or(&Secret(b)).0, a | b);\n\
+                assert_eq!(Secret(a).ct_bit_xor(&Secret(b)).0, a ^ b);",
+    },
+    Harness {
+        name: "ct_shift",
+        params: I64_PAIR,
+        assume: None,
+        check: "assert_eq!(Secret(a).ct_shl(&Secret(b)).0, a << (b & 63));\n\
+                assert_eq!(Secret(a).ct_shr(&Secret(b)).0, a >> (b & 63));",
+    },
+    Harness {
+        name: "ct_neg",
+        params: &[("a", "i64")],
+        assume: None,
+        check: "assert_eq!(Secret(a).ct_neg().0, a.wrapping_neg());",
+    },
+];
+
+/// The prelude with a check function and Kani harness per [`HARNESSES`]
+/// entry.
+pub fn generate() -> String {
+    let mut out = String::from("#![allow(dead_code)]\n\n");
+    out.push_str(RUNTIME_PRELUDE);
+
+    for harness in HARNESSES {
+        let params: Vec<_> = harness
+            .params
+            .iter()
+            .map(|(name, ty)| format!("{}: {}", name, ty))
+            .collect();
+        out.push_str(&format!("\nfn check_{}({}) {{\n", harness.name, params.join(", ")));
+        for line in harness.check.lines() {
+            out.push_str(&format!("    {}\n", line.trim()));
+        }
+        out.push_str("}\n");
+    }
+
+    out.push_str("\n#[cfg(kani)]\nmod proofs {\n    use super::*;\n");
+    for harness in HARNESSES {
+        out.push_str(&format!("\n    #[kani::proof]\n    fn {}() {{\n", harness.name));
+        for (name, ty) in harness.params {
+            out.push_str(&format!("        let {}: {} = kani::any();\n", name, ty));
+        }
+        if let Some(assume) = harness.assume {
+            out.push_str(&format!("        kani::assume({});\n", assume));
+        }
+        let args: Vec<_> = harness.params.iter().map(|(name, _)| *name).collect();
+        out.push_str(&format!("        check_{}({});\n    }}\n", harness.name, args.join(", ")));
+    }
+    out.push_str("}\n");
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process::Command;
+
+    #[test]
+    fn test_one_proof_per_harness() {
+        let code = generate();
+        assert_eq!(code.matches("#[kani::proof]").count(), HARNESSES.len());
+        assert!(code.contains(
+            "    fn ct_div_mod() {\n        let a: i64 = kani::any();\n        \
+             let b: i64 = kani::any();\n        kani::assume(b != 0);\n        \
+             check_ct_div_mod(a, b);\n    }\n"
+        ));
+    }
+
+    #[test]
+    fn test_checks_hold_on_edge_values() {
+        let dir = std::env::temp_dir().join(format!("obli-harnesses-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let main = dir.join("main.rs");
+        let samples = "[i64::MIN, -7, -1, 0, 1, 3, i64::MAX]";
+        let driver = format!(
+            "fn main() {{\n    for a in {s} {{\n        for b in {s} {{\n            \
+             for cond in [true, false] {{ check_ct_select_i64(cond, a, b); }}\n            \
+             check_ct_eq(a, b);\n            check_ct_add(a, b);\n            \
+             check_ct_sub(a, b);\n            check_ct_mul(a, b);\n            \
+             check_ct_saturating(a, b);\n            \
+             if b != 0 {{ check_ct_div_mod(a, b); }}\n        }}\n    }}\n}}\n",
+            s = samples
+        );
+        std::fs::write(&main, generate() + &driver).unwrap();
+
+        let binary = dir.join("main");
+        let compiled = Command::new("rustc")
+            .args(["--edition", "2021", "-A", "warnings", "-o"])
+            .arg(&binary)
+            .arg(&main)
+            .output()
+            .unwrap();
+        assert!(compiled.status.success(), "{}", String::from_utf8_lossy(&compiled.stderr));
+        let ran = Command::new(&binary).status().unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert!(ran.success());
+    }
+}
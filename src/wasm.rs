@@ -0,0 +1,113 @@
+// SPDX-License-Identifier: MIT OR AGPL-3.0-or-later
+// SPDX-FileCopyrightText: 2024-2025 hyperpolymath
+
+//! JSON entry point for in-browser transpilation.
+//!
+//! [`transpile_json`] takes the source and options as strings and returns
+//! a JSON result, so a web playground can call it without a server. The
+//! `obli-wasm` crate exports it to JavaScript as `transpile`:
+//!
+//! ```sh
+//! wasm-pack build obli-wasm --target web
+//! ```
+//!
+//! ```js
+//! const result = JSON.parse(transpile(source, '{"passes": {"pipeline": ["const-fold"]}}'));
+//! ```
+//!
+//! Options have the keys of `obli.toml` (see [`crate::config`]); `"{}"`
+//! or an empty string selects the defaults. The result is
+//! `{"ok": bool, "code": string | null, "diagnostics": [...]}`, with the
+//! lint warnings of a successful compilation as its diagnostics.
+
+use crate::check::check_with;
+use crate::config::Config;
+use crate::diagnostic::{has_errors, Diagnostic};
+use crate::transpile_with;
+use serde_json::{json, Value};
+
+/// Transpile `source` with the options in `options_json`, returning the
+/// result as JSON.
+pub fn transpile_json(source: &str, options_json: &str) -> String {
+    let config = match options_json.trim() {
+        "" => Ok(Config::default()),
+        text => Config::parse_json(text),
+    };
+    let (code, diagnostics) = match config {
+        Ok(config) => compile(source, &config),
+        Err(e) => (None, vec![Diagnostic::error(e.to_string())]),
+    };
+    let diagnostics: Vec<_> = diagnostics.iter().map(|d| diagnostic_json(d, source)).collect();
+    let result = json!({
+        "ok": code.is_some(),
+        "code": code,
+        "diagnostics": diagnostics,
+    });
+    result.to_string()
+}
+
+fn compile(source: &str, config: &Config) -> (Option<String>, Vec<Diagnostic>) {
+    let mut diagnostics = check_with(source, &config.lints);
+    if has_errors(&diagnostics) {
+        return (None, diagnostics);
+    }
+    match transpile_with(source, config) {
+        Ok(code) => (Some(code), diagnostics),
+        Err(e) => {
+            diagnostics.extend(e.into_diagnostics());
+            (None, diagnostics)
+        }
+    }
+}
+
+fn diagnostic_json(diagnostic: &Diagnostic, source: &str) -> Value {
+    let span = |span: crate::span::Span| {
+        let (line, column) = span.line_col(source);
+        json!({ "start": span.start, "end": span.end, "line": line, "column": column })
+    };
+    let labels: Vec<_> = diagnostic
+        .labels
+        .iter()
+        .map(|label| json!({ "span": span(label.span), "message": label.message }))
+        .collect();
+    json!({
+        "severity": diagnostic.severity.to_string(),
+        "code": diagnostic.code,
+        "message": diagnostic.message,
+        "span": diagnostic.span.map(span),
+        "labels": labels,
+        "notes": diagnostic.notes,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(json: &str) -> Value {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn test_success_returns_code() {
+        let result = parse(&transpile_json("secret(1) + 2", r#"{"emit": {"prelude": false}}"#));
+        assert_eq!(result["ok"], true);
+        let code = result["code"].as_str().unwrap();
+        assert!(code.contains("ct_add") && !code.contains("struct Secret"));
+        assert_eq!(parse(&transpile_json("1", ""))["ok"], true);
+    }
+
+    #[test]
+    fn test_errors_are_structured() {
+        let result = parse(&transpile_json("1 +\ntrue", "{}"));
+        assert_eq!(result["ok"], false);
+        assert!(result["code"].is_null());
+        let error = &result["diagnostics"][0];
+        assert_eq!(error["severity"], "error");
+        assert_eq!(error["code"], "OB0005");
+        assert_eq!(error["span"]["line"], 2);
+
+        let result = parse(&transpile_json("1", r#"{"passes": {"pipeline": ["dce"]}}"#));
+        assert_eq!(result["diagnostics"][0]["message"], "unknown pass `dce`");
+    }
+}
@@ -0,0 +1,146 @@
+// SPDX-License-Identifier: MIT OR AGPL-3.0-or-later
+// SPDX-FileCopyrightText: 2024-2025 hyperpolymath
+
+//! Polling file watcher for `obli build --watch`.
+//!
+//! Files are polled by modification time, and a file only counts as changed
+//! when its contents differ from the last version seen, so touching a file
+//! or saving it unmodified does not re-run the pipeline.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+#[derive(Debug)]
+struct Watched {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+    contents: Option<String>,
+}
+
+/// Watches a set of source files for content changes.
+#[derive(Debug)]
+pub struct Watcher {
+    files: Vec<Watched>,
+}
+
+impl Watcher {
+    /// Watch `paths`. Every file is reported as changed on the first poll.
+    pub fn new<P: AsRef<Path>>(paths: impl IntoIterator<Item = P>) -> Self {
+        let files = paths
+            .into_iter()
+            .map(|p| Watched {
+                path: p.as_ref().to_path_buf(),
+                modified: None,
+                contents: None,
+            })
+            .collect();
+        Self { files }
+    }
+
+    /// Watch exactly `paths` from now on, as when the files a program
+    /// includes change. Files already watched keep their state, and new
+    /// ones are read now, so they are only reported once they change.
+    pub fn set_paths<P: AsRef<Path>>(&mut self, paths: impl IntoIterator<Item = P>) {
+        let mut old = std::mem::take(&mut self.files);
+        for path in paths {
+            let path = path.as_ref();
+            if self.files.iter().any(|f| f.path == path) {
+                continue;
+            }
+            let file = match old.iter().position(|f| f.path == path) {
+                Some(i) => old.swap_remove(i),
+                None => Watched {
+                    path: path.to_path_buf(),
+                    modified: fs::metadata(path).and_then(|m| m.modified()).ok(),
+                    contents: fs::read_to_string(path).ok(),
+                },
+            };
+            self.files.push(file);
+        }
+    }
+
+    /// Files whose contents changed since the last poll, with their new contents.
+    ///
+    /// Files that cannot be read (for example while an editor is replacing
+    /// them) are skipped and retried on the next poll.
+    pub fn poll(&mut self) -> Vec<(PathBuf, String)> {
+        let mut changed = Vec::new();
+        for file in &mut self.files {
+            let modified = match fs::metadata(&file.path).and_then(|m| m.modified()) {
+                Ok(modified) => Some(modified),
+                Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+                Err(_) => None,
+            };
+            if modified.is_some() && modified == file.modified {
+                continue;
+            }
+            let Ok(contents) = fs::read_to_string(&file.path) else {
+                continue;
+            };
+            file.modified = modified;
+            if file.contents.as_ref() != Some(&contents) {
+                file.contents = Some(contents.clone());
+                changed.push((file.path.clone(), contents));
+            }
+        }
+        changed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_file(name: &str, contents: &str) -> PathBuf {
+        let name = format!("obli-watch-{}-{}", std::process::id(), name);
+        let path = std::env::temp_dir().join(name);
+        fs::write(&path, contents).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_reports_initial_and_changed_contents() {
+        let path = temp_file("a.mobli", "1 + 2");
+        let mut watcher = Watcher::new([&path]);
+        assert_eq!(watcher.poll(), vec![(path.clone(), "1 + 2".to_string())]);
+        assert_eq!(watcher.poll(), vec![]);
+
+        fs::write(&path, "1 + 3").unwrap();
+        // Force a different mtime on filesystems with coarse timestamps
+        watcher.files[0].modified = None;
+        assert_eq!(watcher.poll(), vec![(path.clone(), "1 + 3".to_string())]);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_added_files_are_watched_from_their_current_contents() {
+        let main = temp_file("c.mobli", "include \"d.mobli\"");
+        let included = temp_file("d.mobli", "1");
+        let mut watcher = Watcher::new([&main]);
+        watcher.poll();
+        watcher.set_paths([&main, &included]);
+        assert_eq!(watcher.poll(), vec![]);
+
+        fs::write(&included, "2").unwrap();
+        watcher.files[1].modified = None;
+        assert_eq!(watcher.poll(), vec![(included.clone(), "2".to_string())]);
+
+        watcher.set_paths([&main]);
+        assert_eq!(watcher.files.len(), 1);
+        fs::remove_file(main).unwrap();
+        fs::remove_file(included).unwrap();
+    }
+
+    #[test]
+    fn test_unchanged_contents_are_ignored() {
+        let path = temp_file("b.mobli", "true");
+        let mut watcher = Watcher::new([&path]);
+        watcher.poll();
+        fs::write(&path, "true").unwrap();
+        watcher.files[0].modified = None;
+        assert_eq!(watcher.poll(), vec![]);
+        fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,102 @@
+// SPDX-License-Identifier: MIT OR AGPL-3.0-or-later
+// SPDX-FileCopyrightText: 2024-2025 hyperpolymath
+
+//! CLI Tests
+//!
+//! `obli run`, `check`, `fmt` and `build --watch` on the examples and on
+//! programs of other frontends, through the built binary.
+
+use std::process::{Command, Output, Stdio};
+use std::time::{Duration, Instant};
+
+fn obli(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_obli"))
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn run_reads_declared_inputs() {
+    let output = obli(&[
+        "run",
+        "--secret",
+        "pin=1234",
+        "--public",
+        "attempts=2",
+        "examples/parameterized.mobli",
+    ]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "ok: true\n");
+
+    let source = "input secret pin: int input attempts: int pin - attempts";
+    let args = ["run", "--differential", "--secret", "pin=7", "--public", "attempts=2"];
+    let output = obli(&[&args[..], &["-e", source]].concat());
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "Result: 5\n");
+}
+
+#[test]
+fn differential_run_compares_outputs() {
+    let output = obli(&["run", "--differential", "-e", "outputs(a = 1, b = secret(2))"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "a: 1\nb: 2\n");
+}
+
+#[test]
+fn run_reads_other_frontends() {
+    let dir = std::env::temp_dir().join(format!("obli-cli-frontend-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("kernel.json");
+    let json = r#"{"version": 1, "body": {"binop": "*", "left": {"secret": 6}, "right": 7}}"#;
+    std::fs::write(&path, json).unwrap();
+    let output = obli(&["run", path.to_str().unwrap()]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "Result: 42\n");
+    let output = obli(&["check", path.to_str().unwrap()]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn fmt_keeps_includes() {
+    let dir = std::env::temp_dir().join(format!("obli-cli-fmt-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("limit.mobli"), "let limit = 100\n").unwrap();
+    let path = dir.join("main.mobli");
+    std::fs::write(&path, "include \"limit.mobli\"\nlet x = secret(1)\nx+limit").unwrap();
+    let output = obli(&["fmt", path.to_str().unwrap()]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let formatted = std::fs::read_to_string(&path).unwrap();
+    assert_eq!(formatted, "include \"limit.mobli\"\nlet x = secret(1)\nx + limit\n");
+    let output = obli(&["fmt", "--check", path.to_str().unwrap()]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stdout));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn watch_reads_other_frontends() {
+    let dir = std::env::temp_dir().join(format!("obli-cli-watch-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("kernel.json");
+    let json = r#"{"version": 1, "body": {"binop": "*", "left": {"secret": 6}, "right": 7}}"#;
+    std::fs::write(&path, json).unwrap();
+    let out = dir.join("kernel.rs");
+    let mut child = Command::new(env!("CARGO_BIN_EXE_obli"))
+        .args(["build", "--watch", path.to_str().unwrap(), "-o", out.to_str().unwrap()])
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+    // The first poll builds it; a JSON program read as MiniObli would not build
+    let start = Instant::now();
+    let mut rust_code = String::new();
+    while !rust_code.contains("fn main") && start.elapsed() < Duration::from_secs(30) {
+        std::thread::sleep(Duration::from_millis(50));
+        rust_code = std::fs::read_to_string(&out).unwrap_or_default();
+    }
+    child.kill().unwrap();
+    child.wait().unwrap();
+    assert!(rust_code.contains("fn main"), "no output built");
+    std::fs::remove_dir_all(&dir).unwrap();
+}
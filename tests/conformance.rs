@@ -415,6 +415,9 @@ fn arithmetic_ops_transform() {
             ("1 + 2", ObliBinOp::CtAdd),
             ("1 - 2", ObliBinOp::CtSub),
             ("1 * 2", ObliBinOp::CtMul),
+            ("1 +| 2", ObliBinOp::CtAddSat),
+            ("1 -| 2", ObliBinOp::CtSubSat),
+            ("saturating(1 * 2)", ObliBinOp::CtMulSat),
             ("1 / 2", ObliBinOp::CtDiv),
             ("1 % 2", ObliBinOp::CtMod),
         ];
@@ -486,6 +489,181 @@ fn unary_ops_transform() {
     }
 }
 
+// ============================================================================
+// Property 7: Semantic Preservation
+// ============================================================================
+
+mod property_semantics {
+    use obli_transpiler::differential::{check, check_ast};
+    use obli_transpiler::interp::{Inputs, Value};
+    use obli_transpiler::testing::coverage::CoverageGenerator;
+    use obli_transpiler::testing::property::{check_preservation, to_source};
+
+    const PROGRAMS: &[&str] = &[
+        "if s > p then s - p else p - s",
+        "let m = if s == p then 1 else 0 m * s + (if p > 0 then p else -p)",
+        "if p != 0 then (if s > 0 && p > 0 then s / p else s % 3) else s",
+        "let t = s * s if !(t < 10) || p == 0 then t else if p > s then p else s",
+        "saturating(s * 4611686018427387904 - p * -s)",
+    ];
+
+    #[test]
+    fn transform_and_passes_preserve_results() {
+        for program in PROGRAMS {
+            for s in [-4, 0, 3, 9] {
+                for p in [-1, 0, 2, 9] {
+                    let inputs = Inputs::new()
+                        .secret("s", Value::Int(s))
+                        .public("p", Value::Int(p));
+                    if let Err(e) = check(program, &inputs) {
+                        panic!("`{}` with s={}, p={}: {}", program, s, p, e);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn random_programs_preserve_results() {
+        for seed in 0..4 {
+            if let Err(counterexample) = check_preservation(seed, 250) {
+                panic!("seed {}: {}", seed, counterexample);
+            }
+        }
+    }
+
+    #[test]
+    fn coverage_corpus_preserves_results() {
+        let mut generator = CoverageGenerator::new(0x0b11);
+        generator.grow(500);
+        for program in generator.corpus() {
+            if let Err(e) = check_ast(program) {
+                panic!("{}: {}", to_source(program), e);
+            }
+        }
+    }
+}
+
+// ============================================================================
+// Property 8: Oblivious Array and Table Access
+// ============================================================================
+
+mod property_array_access {
+    use super::*;
+    use obli_transpiler::differential::check;
+    use obli_transpiler::emit::EmitOptions;
+    use obli_transpiler::interp::{eval_monitored, Inputs, Value};
+    use obli_transpiler::{transpile_with, Config};
+
+    const PROGRAMS: &[&str] = &[
+        "let a = [3, 1, 4, 1, 5] a[s]",
+        "let a = [s, p, s + p] a[s] * a[p]",
+        "let t = [true, false] if t[s] then p else (if t[p] then 1 else 2)",
+        "let a = if s > p then [1, 2, 3] else secret([4, 5, 6]) a[s] + a[p]",
+        "lookup(s, [(0, 7), (p, 8), (4, s)], -1)",
+        "lookup(p, [(s, true), (2, false)], s > 0)",
+        "b\"ab\" == [s + 96, p + 96]",
+        "let k = secret(b\"pin\") k != [112, 105, 108 + s]",
+        "let a = sort([s, p, 3, s * p, -s]) a[0] + a[2] * 10 + a[4] * 100",
+    ];
+
+    #[test]
+    fn secret_index_reads_every_element() {
+        let ir = transform("let a = [10, 20, 30] a[secret(2)]");
+        assert!(!contains_secret_pub_if(&ir), "{}", ir);
+        assert!(contains_ct_select(&ir), "{}", ir);
+        assert!(ir.is_secret());
+    }
+
+    #[test]
+    fn secret_key_lookup_scans_every_entry() {
+        let ir = transform("lookup(secret(3), [(1, 100), (2, 200), (3, 300)], 0)");
+        assert!(!contains_secret_pub_if(&ir), "{}", ir);
+        assert!(contains_ct_select(&ir), "{}", ir);
+        assert_eq!(ir.to_string().matches("ct_eq").count(), 3, "{}", ir);
+    }
+
+    #[test]
+    fn byte_string_equality_compares_every_byte() {
+        let ir = transform("secret(b\"secret\") == b\"sesame\"");
+        let text = ir.to_string();
+        assert_eq!(text.matches("ct_eq").count(), 6, "{}", text);
+        assert_eq!(text.matches("ct_and").count(), 5, "{}", text);
+        assert!(!contains_ct_select(&ir) && !contains_secret_pub_if(&ir), "{}", text);
+        assert!(ir.is_secret());
+    }
+
+    #[test]
+    fn sorting_a_secret_array_runs_the_whole_network() {
+        use obli_transpiler::desugar::sorting_network;
+        let ir = transform("let a = sort(secret([4, 1, 3, 1, 5, 9])) a[0] + a[5]");
+        let text = ir.to_string();
+        let comparators = sorting_network(6).len();
+        assert_eq!(text.matches("ct_gt").count(), comparators, "{}", text);
+        assert_eq!(text.matches("ct_swap").count(), comparators, "{}", text);
+        assert!(!contains_secret_pub_if(&ir), "{}", text);
+    }
+
+    #[test]
+    fn no_secret_is_used_as_a_memory_index() {
+        let config = Config {
+            emit: EmitOptions { prelude: false },
+            ..Config::default()
+        };
+        for program in PROGRAMS {
+            let source = format!("let s = secret(1) let p = 2 {}", program);
+            let code = transpile_with(&source, &config).unwrap();
+            // The only brackets are array literals passed to `ct_scan`
+            let indexes = code.match_indices('[').any(|(at, _)| !code[..at].ends_with("ct_scan(&"));
+            assert!(!indexes, "`{}` indexes memory:\n{}", program, code);
+
+            let ir = transform(&source);
+            let mut observations = Vec::new();
+            eval_monitored(&ir, &Inputs::new(), &mut observations).unwrap();
+            assert!(observations.iter().all(|o| !o.secret), "{}", program);
+        }
+    }
+
+    #[test]
+    fn array_reads_preserve_results() {
+        for program in PROGRAMS {
+            // Including indices past either end, which read the last element
+            for s in [-1, 0, 1, 2, 4, 7] {
+                for p in [0, 2, 9] {
+                    let inputs = Inputs::new()
+                        .secret("s", Value::Int(s))
+                        .public("p", Value::Int(p));
+                    if let Err(e) = check(program, &inputs) {
+                        panic!("`{}` with s={}, p={}: {}", program, s, p, e);
+                    }
+                }
+            }
+        }
+    }
+}
+
+// ============================================================================
+// Library Suite
+// ============================================================================
+
+mod library_suite {
+    use super::*;
+    use obli_transpiler::conformance::run_all;
+
+    #[test]
+    fn transform_output_passes_every_check() {
+        for src in [
+            "let x = secret(1) if x > 0 then secret(1) else secret(0)",
+            "let p = 3 if p > 0 then secret(1) else 2",
+            "let s = secret(true) let n = if s then 1 else 2 if n == 1 then !s else s",
+            "let a = secret(4) 0 - a * (a % 3) / 2",
+        ] {
+            let report = run_all(&transform(src));
+            assert!(report.is_conformant(), "{}\n{}", src, report);
+        }
+    }
+}
+
 // ============================================================================
 // Regression Tests
 // ============================================================================
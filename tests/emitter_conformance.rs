@@ -395,6 +395,37 @@ fn secret_has_reveal_method() {
             "Secret should have reveal method"
         );
     }
+
+    #[test]
+    fn secret_reveal_requires_token() {
+        let code = emit("1");
+        assert!(
+            contains_pattern(&code, "fn reveal(&self, _token: &RevealToken)"),
+            "Secret::reveal should require a RevealToken"
+        );
+    }
+
+    #[test]
+    fn reveal_token_only_in_output_section() {
+        let code = emit("let x = secret(1) if x > 0 then secret(1) else secret(0)");
+        assert_eq!(
+            code.matches("RevealToken::output_section()").count(),
+            1,
+            "RevealToken should be constructed exactly once, in main's output section"
+        );
+        let main_start = code.find("fn main()").unwrap();
+        let token_pos = code.find("let token = RevealToken::output_section();").unwrap();
+        assert!(token_pos > main_start, "Token must be constructed inside main");
+    }
+
+    #[test]
+    fn public_result_does_not_construct_token() {
+        let code = emit("1 + 2");
+        assert!(
+            !contains_pattern(&code, "let token = RevealToken"),
+            "Public results need no RevealToken"
+        );
+    }
 }
 
 // ============================================================================
@@ -0,0 +1,23 @@
+// SPDX-License-Identifier: MIT OR AGPL-3.0-or-later
+// SPDX-FileCopyrightText: 2024-2025 hyperpolymath
+
+//! Golden Tests
+//!
+//! Emitted code for the programs in tests/golden, per backend. After an
+//! intended change to the output, regenerate with
+//! `OBLI_GOLDEN=update cargo test --test golden`, or use
+//! `OBLI_GOLDEN=review` to write `.new` files next to the golden ones.
+
+use obli_transpiler::emit::BACKENDS;
+use obli_transpiler::testing::golden::{check_corpus, Mode};
+use std::path::Path;
+
+#[test]
+fn emitted_code_matches_golden_files() {
+    let mode = Mode::from_env().unwrap();
+    let corpus = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden");
+    for backend in BACKENDS {
+        let report = check_corpus(&corpus, backend, mode).unwrap();
+        assert!(report.is_ok(), "{}", report);
+    }
+}
@@ -0,0 +1,241 @@
+// SPDX-License-Identifier: MIT OR AGPL-3.0-or-later
+// Generated by obli-transpiler
+// obli-runtime-version: 2
+
+/// Public value wrapper
+#[derive(Debug, Clone, Copy)]
+struct Pub<T>(T);
+
+/// Secret value wrapper - operations are constant-time
+#[derive(Debug, Clone, Copy)]
+struct Secret<T>(T);
+
+impl<T: Copy> Pub<T> {
+    fn new(v: T) -> Self { Pub(v) }
+    fn reveal(&self) -> &T { &self.0 }
+    /// Treat a public value as secret (always allowed: `Public ⊑ Secret`).
+    #[allow(dead_code)]
+    fn classify(&self) -> Secret<T> { Secret(self.0) }
+}
+
+/// Capability required to declassify a `Secret`.
+/// Only the generated output section of `main` and each `declassify(...)`
+/// of the source construct one, so intermediate code cannot casually
+/// reveal secrets.
+struct RevealToken {
+    _sealed: (),
+}
+
+impl RevealToken {
+    #[allow(dead_code)]
+    fn output_section() -> Self { RevealToken { _sealed: () } }
+    /// One per `declassify(...)`, each listed in the compilation report.
+    #[allow(dead_code)]
+    fn declassify() -> Self { RevealToken { _sealed: () } }
+}
+
+impl<T> Secret<T> {
+    fn new(v: T) -> Self { Secret(v) }
+    #[allow(dead_code)]
+    fn reveal(&self, _token: &RevealToken) -> &T { &self.0 }
+}
+
+/// Replace an overflowed result with `max` (`high`) or `min` through
+/// masks, so saturating is as constant-time as wrapping.
+#[allow(dead_code)]
+fn ct_clamp<T: Blend>((value, overflow): (T, bool), high: bool, min: T, max: T) -> T {
+    let bound = max.blend(min, -(high as i64));
+    bound.blend(value, -(overflow as i64))
+}
+
+// Constant-time operations for `$wrapper<$t>`. An overflowing saturated
+// result clamps up when the exact result is positive, which the operand
+// signs tell: widened to `i128`, so unsigned operands are never negative.
+macro_rules! ct_int_ops {
+    ($wrapper:ident, $t:ty) => {
+        impl $wrapper<$t> {
+            fn ct_add(&self, other: &Self) -> Self { $wrapper(self.0.wrapping_add(other.0)) }
+            fn ct_sub(&self, other: &Self) -> Self { $wrapper(self.0.wrapping_sub(other.0)) }
+            fn ct_mul(&self, other: &Self) -> Self { $wrapper(self.0.wrapping_mul(other.0)) }
+            fn ct_add_sat(&self, other: &Self) -> Self {
+                let high = (self.0 as i128) >= 0;
+                $wrapper(ct_clamp(self.0.overflowing_add(other.0), high, <$t>::MIN, <$t>::MAX))
+            }
+            fn ct_sub_sat(&self, other: &Self) -> Self {
+                let high = (other.0 as i128) < 0;
+                $wrapper(ct_clamp(self.0.overflowing_sub(other.0), high, <$t>::MIN, <$t>::MAX))
+            }
+            fn ct_mul_sat(&self, other: &Self) -> Self {
+                let high = ((self.0 ^ other.0) as i128) >= 0;
+                $wrapper(ct_clamp(self.0.overflowing_mul(other.0), high, <$t>::MIN, <$t>::MAX))
+            }
+            fn ct_div(&self, other: &Self) -> Self { $wrapper(self.0.wrapping_div(other.0)) }
+            fn ct_mod(&self, other: &Self) -> Self { $wrapper(self.0.wrapping_rem(other.0)) }
+            fn ct_bit_and(&self, other: &Self) -> Self { $wrapper(self.0 & other.0) }
+            fn ct_bit_or(&self, other: &Self) -> Self { $wrapper(self.0 | other.0) }
+            fn ct_bit_xor(&self, other: &Self) -> Self { $wrapper(self.0 ^ other.0) }
+            // Shifts mask the amount to the width and compile to one
+            // shift instruction, which takes the same time for every amount
+            fn ct_shl(&self, other: &Self) -> Self { $wrapper(self.0.wrapping_shl(other.0 as u32)) }
+            fn ct_shr(&self, other: &Self) -> Self { $wrapper(self.0.wrapping_shr(other.0 as u32)) }
+            fn ct_eq(&self, other: &Self) -> $wrapper<bool> { $wrapper(self.0 == other.0) }
+            fn ct_ne(&self, other: &Self) -> $wrapper<bool> { $wrapper(self.0 != other.0) }
+            fn ct_lt(&self, other: &Self) -> $wrapper<bool> { $wrapper(self.0 < other.0) }
+            fn ct_le(&self, other: &Self) -> $wrapper<bool> { $wrapper(self.0 <= other.0) }
+            fn ct_gt(&self, other: &Self) -> $wrapper<bool> { $wrapper(self.0 > other.0) }
+            fn ct_ge(&self, other: &Self) -> $wrapper<bool> { $wrapper(self.0 >= other.0) }
+            fn ct_neg(&self) -> Self { $wrapper(self.0.wrapping_neg()) }
+            fn ct_to_bool(&self) -> $wrapper<bool> { self.ct_ne(&$wrapper(0)) }
+            fn ct_cast<U: CastFrom>(&self) -> $wrapper<U> {
+                $wrapper(U::cast_from(self.0 as i128))
+            }
+        }
+    };
+}
+
+// Integers, their blends through a mask, and their operations
+macro_rules! ct_ints {
+    ($($t:ty),*) => {$(
+        impl Blend for $t {
+            fn blend(self, other: Self, mask: i64) -> Self {
+                let mask = mask as $t;
+                (self & mask) | (other & !mask)
+            }
+            fn swap(self, other: Self, mask: i64) -> (Self, Self) {
+                let t = (self ^ other) & mask as $t;
+                (self ^ t, other ^ t)
+            }
+        }
+        impl CastFrom for $t {
+            fn cast_from(n: i128) -> Self { n as $t }
+        }
+        ct_int_ops!(Pub, $t);
+        ct_int_ops!(Secret, $t);
+    )*};
+}
+
+/// Integers a cast converts to. Widened to `i128` and cut back with `as`,
+/// a value is extended or truncated by one instruction whatever it is.
+#[allow(dead_code)]
+trait CastFrom: Copy {
+    fn cast_from(n: i128) -> Self;
+}
+
+ct_ints!(i64, u8, u16, u32, u64, i32);
+
+impl Pub<bool> {
+    fn ct_and(&self, other: &Self) -> Pub<bool> { Pub(self.0 & other.0) }
+    fn ct_or(&self, other: &Self) -> Pub<bool> { Pub(self.0 | other.0) }
+    fn ct_not(&self) -> Pub<bool> { Pub(!self.0) }
+    fn ct_to_int(&self) -> Pub<i64> { Pub(1i64.blend(0, -(self.0 as i64))) }
+}
+
+impl Secret<bool> {
+    fn ct_and(&self, other: &Self) -> Secret<bool> { Secret(self.0 & other.0) }
+    fn ct_or(&self, other: &Self) -> Secret<bool> { Secret(self.0 | other.0) }
+    fn ct_not(&self) -> Secret<bool> { Secret(!self.0) }
+    // `1` or `0` through the mask of the condition, as `ct_select` blends
+    fn ct_to_int(&self) -> Secret<i64> { Secret(1i64.blend(0, -(self.0 as i64))) }
+}
+
+/// Read `table[index]` by blending every entry through a mask, so the
+/// index is never used as an address. An index outside `0..N - 1` reads
+/// the last entry.
+#[allow(dead_code)]
+fn ct_table<const N: usize>(table: &[i64; N], index: &Secret<i64>) -> Secret<i64> {
+    let mut acc = table[N - 1];
+    for (k, &value) in table[..N - 1].iter().enumerate() {
+        let mask = -((index.0 == k as i64) as i64);
+        acc = (value & mask) | (acc & !mask);
+    }
+    Secret(acc)
+}
+
+/// Values `ct_select` and `ct_scan` blend, and `ct_swap` swaps, through
+/// an all-ones or all-zeros mask.
+#[allow(dead_code)]
+trait Blend: Copy {
+    fn blend(self, other: Self, mask: i64) -> Self;
+    fn swap(self, other: Self, mask: i64) -> (Self, Self);
+}
+
+impl Blend for bool {
+    fn blend(self, other: Self, mask: i64) -> Self {
+        ((self as i64 & mask) | (other as i64 & !mask)) != 0
+    }
+    fn swap(self, other: Self, mask: i64) -> (Self, Self) {
+        let t = (self ^ other) & (mask != 0);
+        (self ^ t, other ^ t)
+    }
+}
+
+/// Read `entries[index]` like `ct_table`, for entries computed at run
+/// time: one pass over every entry, each blended in through a mask.
+#[allow(dead_code)]
+fn ct_scan<T: Blend, const N: usize>(entries: &[Secret<T>; N], index: &Secret<i64>) -> Secret<T> {
+    let mut acc = entries[N - 1].0;
+    for (k, entry) in entries[..N - 1].iter().enumerate() {
+        let mask = -((index.0 == k as i64) as i64);
+        acc = entry.0.blend(acc, mask);
+    }
+    Secret(acc)
+}
+
+/// Constant-time select: returns `then_val` if `cond` is true, else `else_val`.
+/// CRITICAL: Both branches are always evaluated to prevent timing leaks.
+fn ct_select<T: Blend>(cond: &Secret<bool>, then_val: &Secret<T>, else_val: &Secret<T>) -> Secret<T> {
+    Secret(then_val.0.blend(else_val.0, -(cond.0 as i64)))
+}
+
+/// Constant-time swap: returns `(b, a)` if `cond` is true, else `(a, b)`,
+/// exchanging the bits that differ under a mask.
+#[allow(dead_code)]
+fn ct_swap<T: Blend>(
+    cond: &Secret<bool>,
+    a: &Secret<T>,
+    b: &Secret<T>,
+) -> (Secret<T>, Secret<T>) {
+    let (a, b) = a.0.swap(b.0, -(cond.0 as i64));
+    (Secret(a), Secret(b))
+}
+
+/// The words of the program's inputs, in declaration order: the
+/// command-line arguments, or the whitespace-separated words of stdin if
+/// there are none.
+#[allow(dead_code)]
+fn read_inputs(names: &[&str]) -> Vec<String> {
+    let mut words: Vec<String> = std::env::args().skip(1).collect();
+    if words.is_empty() {
+        let mut text = String::new();
+        std::io::Read::read_to_string(&mut std::io::stdin(), &mut text).expect("cannot read stdin");
+        words = text.split_whitespace().map(String::from).collect();
+    }
+    if words.len() != names.len() {
+        eprintln!("expected {} inputs ({}), found {}", names.len(), names.join(", "), words.len());
+        std::process::exit(2);
+    }
+    words
+}
+
+/// Input `name` read from `word`, exiting if it is not a `T`.
+#[allow(dead_code)]
+fn parse_input<T: std::str::FromStr>(name: &str, word: &str) -> T {
+    word.parse().unwrap_or_else(|_| {
+        eprintln!("invalid value for input `{}`: `{}`", name, word);
+        std::process::exit(2)
+    })
+}
+
+struct Outputs<T0, T1, T2> {
+    total: T0,
+    big: T1,
+    count: T2,
+}
+
+fn main() {
+    let result = { let k = Secret::new(7i64); { let n = Pub::new(3i64); Outputs { total: k.ct_mul(&n.classify()), big: k.ct_gt(&Pub::new(5i64).classify()), count: n.ct_add(&Pub::new(1i64)) } } };
+    let token = RevealToken::output_section();
+    println!("total: {:?}", result.total.reveal(&token));
+    println!("big: {:?}", result.big.reveal(&token));
+    println!("count: {:?}", result.count.reveal());
+}